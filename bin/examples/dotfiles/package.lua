@@ -0,0 +1,5 @@
+name 'dotfiles'
+
+dep '../shell'
+dep '../git'
+dep '../editor'
@@ -0,0 +1 @@
+vim.g.mapleader = ' '
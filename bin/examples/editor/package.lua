@@ -0,0 +1,3 @@
+name 'editor'
+
+tree {'nvim', '.config/nvim'}
@@ -0,0 +1,4 @@
+name 'git'
+
+hbs {'gitconfig.hbs', '.gitconfig', vars = {name = 'Example User', email = 'user@example.com'}}
+file {'gitignore', '.config/git/ignore'}
@@ -0,0 +1,5 @@
+name 'shell'
+
+file {'bashrc', '.bashrc'}
+file {'profile', '.profile', type = 'copy', mode = '0644'}
+mkdir {'.local/state/bash', parents = true}
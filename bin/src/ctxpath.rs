@@ -111,6 +111,15 @@ impl CtxPath {
         }
     }
 
+    /// Resolve symlinks in the absolute path, keeping the relative path as it was given.
+    #[inline]
+    pub fn canonicalize(&self) -> io::Result<Self> {
+        Ok(Self {
+            rel: self.rel.clone(),
+            abs: self.abs.canonicalize()?,
+        })
+    }
+
     #[inline]
     pub fn cleaned(self) -> Self {
         Self {
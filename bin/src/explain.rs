@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use shelflib::{
+    action::{tree, Action, Resolve},
+    graph::{select, DestFilter, PathResolver, Selector},
+};
+
+use crate::load::Loaded;
+
+/// Print the destination `target`, how it was normalized from `given`, its existing state, and
+/// the directives that manage it. Returns selections of those directives, keyed by package path.
+#[inline]
+pub fn explain(
+    loaded: &Loaded,
+    paths: &PathResolver,
+    target: &Path,
+    given: &str,
+) -> Result<HashMap<PathBuf, Vec<Selector>>, ()> {
+    let dest = paths.home();
+    output::explaining(dest, target, given);
+    output::existing(target);
+
+    let order = match loaded.graph.order() {
+        Ok(order) => order,
+        Err(err) => {
+            output::error_circular(err);
+            return Err(());
+        }
+    };
+
+    let only = DestFilter::exact(target);
+    let mut selections = HashMap::new();
+    for pd in order {
+        // SAFETY: Path guaranteed to be in it by `load`.
+        let path = loaded.paths.get(&pd.path).unwrap();
+        for (i, drct) in pd.spec.directives.iter().enumerate() {
+            let selector = Selector::Index(i + 1);
+            let owns = pd
+                .action_iter(paths)
+                .select(vec![selector.clone()])
+                .only(only.clone())
+                .any(|action| owns(&action));
+
+            if owns {
+                output::owner(path.rel(), i + 1, select::directive_kinds(drct));
+                selections
+                    .entry(pd.path.clone())
+                    .or_insert_with(Vec::new)
+                    .push(selector);
+            }
+        }
+    }
+
+    if selections.is_empty() {
+        output::no_owner(dest, target);
+        return Err(());
+    }
+
+    Ok(selections)
+}
+
+/// Return true if `action`, already restricted to the explained destination, still manages it.
+#[inline]
+fn owns(action: &Action<'_>) -> bool {
+    match action {
+        // Trees are only narrowed to the destination on resolution.
+        Action::Tree(action) => match action.resolve() {
+            Ok(tree::Res::Normal(reses)) => !reses.is_empty(),
+            Ok(tree::Res::Skip(_)) | Err(_) => false,
+        },
+        _ => true,
+    }
+}
+
+mod output {
+    use std::fs;
+    use std::path::Path;
+
+    use shelflib::graph::CircularDependencyError;
+
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{pretty, sjoin2, sjoin3, sjoin4},
+        spath, Pretty, Section, Step,
+    };
+
+    #[inline]
+    fn sdest_relative(target: &Path, dest: &Path) -> Pretty {
+        // SAFETY: `dest` is absolute.
+        spath(CtxPath::new(target, dest).unwrap().rel())
+    }
+
+    #[inline]
+    pub fn explaining(dest: &Path, target: &Path, given: &str) {
+        Section::message("explaining", sdest_relative(target, dest));
+        Step::message(sjoin4(
+            "given as",
+            spath(given),
+            "normalized to",
+            spath(target),
+        ));
+    }
+
+    #[inline]
+    pub fn existing(target: &Path) {
+        let state = match fs::symlink_metadata(target) {
+            Ok(meta) if meta.file_type().is_symlink() => match fs::read_link(target) {
+                Ok(link) => sjoin2("a symlink to", spath(link)),
+                Err(_) => pretty("an unreadable symlink"),
+            },
+            Ok(meta) if meta.is_dir() => pretty("a directory"),
+            Ok(meta) => sjoin3("a file of", meta.len(), "bytes"),
+            Err(_) => pretty("missing"),
+        };
+        Step::message(sjoin2("currently", state));
+    }
+
+    #[inline]
+    pub fn owner(package: &Path, index: usize, kinds: &[&str]) {
+        Step::message(sjoin4(
+            "managed by directive",
+            format!("#{}", index),
+            format!("({})", kinds.join(", ")),
+            sjoin2("of package", spath(package)),
+        ));
+    }
+
+    #[inline]
+    pub fn no_owner(dest: &Path, target: &Path) {
+        Section::error().message(sjoin2("no directive manages", sdest_relative(target, dest)));
+    }
+
+    #[inline]
+    pub fn error_circular(err: CircularDependencyError) {
+        Section::error().message("circular dependency detected");
+        Section::error().context(err.path().display());
+    }
+}
@@ -0,0 +1,79 @@
+use shelflib::state::StateStore;
+
+use crate::load::Loaded;
+
+/// List the loaded packages, along with their last applied metadata.
+#[inline]
+pub fn list(loaded: &Loaded, store: Option<&StateStore>) -> Result<(), ()> {
+    let order = match loaded.graph.order() {
+        Ok(order) => order,
+        Err(err) => {
+            output::error_circular(err);
+            return Err(());
+        }
+    };
+
+    for pd in order {
+        // SAFETY: Path guaranteed to be in it by `load`.
+        let path = loaded.paths.get(&pd.path).unwrap();
+        match store.map(|store| store.get(&pd.path)) {
+            Some(Ok(state)) => output::package(path, state.as_ref()),
+            Some(Err(_)) => {
+                output::state_read_error(path);
+                output::package(path, None);
+            }
+            None => output::package(path, None),
+        }
+    }
+
+    Ok(())
+}
+
+mod output {
+    use shelflib::{
+        graph::CircularDependencyError,
+        state::{ApplyResult, PackageState},
+    };
+
+    use crate::ctxpath::CtxPath;
+    use crate::output::{ago, render, spath, Prettify, Section};
+
+    #[inline]
+    pub fn package(path: &CtxPath, state: Option<&PackageState>) {
+        let path = spath(path.rel());
+        match state {
+            Some(state) => {
+                let result = match (state.result, state.partial) {
+                    (ApplyResult::Success, false) => "ok".green(),
+                    (ApplyResult::Success, true) => "partial".yellow(),
+                    (ApplyResult::Failure, _) => "failed".red(),
+                };
+                let mut line = format!(
+                    "{}  {}  {}  {}",
+                    path,
+                    ago(state.applied_at),
+                    state.version.as_str().dim(),
+                    result
+                );
+                if let Some(message) = &state.message {
+                    line.push_str(&format!("  {}", message.as_str().italic()));
+                }
+                println!("{}", render(line));
+            }
+            None => println!("{}", render(format!("{}  {}", path, "never applied".dim()))),
+        }
+    }
+
+    #[inline]
+    pub fn state_read_error(path: &CtxPath) {
+        Section::warning()
+            .message("couldn't read last applied state")
+            .context(path.abs().display());
+    }
+
+    #[inline]
+    pub fn error_circular(err: CircularDependencyError) {
+        Section::error().message("circular dependency detected");
+        Section::error().context(err.path().display());
+    }
+}
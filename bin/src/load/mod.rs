@@ -1,11 +1,13 @@
 mod output;
 
-use std::collections::{HashMap, VecDeque};
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 
 use shelflib::{
     graph::PackageGraph,
-    load::{LoadError, SpecLoader},
+    load::{base, options, version, BaseFetcher, LoadError, SpecCache, SpecLoader},
+    spec::{Object, Scope, Spec},
+    state::StateStore,
 };
 
 use crate::ctxpath::CtxPath;
@@ -16,15 +18,162 @@ pub struct Loaded {
     pub paths: HashMap<PathBuf, CtxPath>,
 }
 
+impl Loaded {
+    /// Remove the `excluded` packages, even if they are dependencies of other packages. Errors if
+    /// an excluded package is required by a package that isn't excluded, unless `force` is set,
+    /// in which case it only warns. If `unique_deps` is set, dependencies that are no longer
+    /// required by any of the `roots` are removed as well.
+    #[inline]
+    pub fn exclude(
+        &mut self,
+        roots: &[PathBuf],
+        excluded: &[PathBuf],
+        unique_deps: bool,
+        force: bool,
+    ) -> Result<(), ()> {
+        let mut paths = HashSet::new();
+        for path in excluded {
+            let path = CtxPath::from_cwd(path);
+            match path.canonicalize() {
+                Ok(canonical) if self.graph.contains(canonical.abs()) => {
+                    paths.insert(canonical.abs().to_path_buf());
+                }
+                _ => output::exclude_not_loaded(&path),
+            }
+        }
+
+        let mut required = false;
+        for path in &paths {
+            let dependents: Vec<&Path> = self
+                .graph
+                .dependents(path)
+                .into_iter()
+                .filter(|dependent| !paths.contains(*dependent))
+                .collect();
+            if !dependents.is_empty() {
+                required = true;
+                output::exclude_required(&self.paths[path], &dependents, &self.paths, force);
+            }
+        }
+        if required && !force {
+            return Err(());
+        }
+
+        for path in &paths {
+            self.remove(path);
+        }
+
+        if unique_deps {
+            let roots: Vec<_> = roots
+                .iter()
+                .filter_map(|path| CtxPath::from_cwd(path).canonicalize().ok())
+                .map(|path| path.abs().to_path_buf())
+                .filter(|path| !paths.contains(path))
+                .collect();
+            let keep = self.graph.reachable(&roots);
+
+            let unique: Vec<_> = self
+                .paths
+                .keys()
+                .filter(|path| !keep.contains(*path))
+                .cloned()
+                .collect();
+            for path in &unique {
+                output::excluding_dep(&self.paths[path]);
+                self.remove(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the `roots` that aren't dependencies of other loaded packages, so that only their
+    /// dependencies are applied.
+    #[inline]
+    pub fn only_deps(&mut self, roots: &[PathBuf]) {
+        let named: Vec<_> = canonical_loaded(&self.graph, roots)
+            .into_iter()
+            .filter(|path| self.graph.dependents(path).is_empty())
+            .collect();
+        for path in &named {
+            output::skipping_named(&self.paths[path]);
+            self.remove(path);
+        }
+    }
+
+    /// Remove every package other than the `roots`, assuming that their dependencies have already
+    /// been applied.
+    #[inline]
+    pub fn no_deps(&mut self, roots: &[PathBuf]) {
+        let named: HashSet<_> = canonical_loaded(&self.graph, roots).into_iter().collect();
+        let deps: Vec<_> = self
+            .paths
+            .keys()
+            .filter(|path| !named.contains(*path))
+            .cloned()
+            .collect();
+        for path in &deps {
+            output::skipping_dep(&self.paths[path]);
+            self.remove(path);
+        }
+    }
+
+    /// Move the packages of `scope` into a separate set of loaded packages. Dependency relations
+    /// between packages of different scopes are dropped.
+    #[inline]
+    pub fn split_scope(&mut self, scope: Scope) -> Loaded {
+        let graph = self.graph.split_off(|pd| pd.spec.scope == scope);
+        let paths = graph
+            .iter()
+            .filter_map(|pd| self.paths.remove_entry(&pd.path))
+            .collect();
+        Loaded { graph, paths }
+    }
+
+    /// Return true if no packages are loaded.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.graph.package_count() == 0
+    }
+
+    #[inline]
+    fn remove(&mut self, path: &Path) {
+        self.graph.remove_package(path);
+        self.paths.remove(path);
+    }
+}
+
+/// Return the canonical paths of the `paths` that are loaded in `graph`.
+#[inline]
+fn canonical_loaded(graph: &PackageGraph, paths: &[PathBuf]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .filter_map(|path| CtxPath::from_cwd(path).canonicalize().ok())
+        .map(|path| path.abs().to_path_buf())
+        .filter(|path| graph.contains(path))
+        .collect()
+}
+
 #[derive(Debug)]
 pub struct Loader {
     packages: VecDeque<(CtxPath, Option<CtxPath>)>,
     graph: PackageGraph,
     paths: HashMap<PathBuf, CtxPath>,
+    /// First reference to each package, keyed by canonical path.
+    refs: HashMap<PathBuf, CtxPath>,
+
+    cache: Option<SpecCache>,
+    bases: BaseFetcher,
+    /// Store of the last applied state, from which previous template variables are read.
+    state: Option<StateStore>,
+    /// Only warn about packages that require a newer version of shelf.
+    ignore_version: bool,
+    /// Template variables given for this run, which override those of every package.
+    vars: Object,
 }
 
 impl Loader {
-    pub fn new(packages: Vec<PathBuf>) -> Self {
+    pub fn new(packages: Vec<PathBuf>, cache: Option<SpecCache>, bases: BaseFetcher) -> Self {
         let packages = packages
             .into_iter()
             .map(|path| (CtxPath::from_cwd(path), None))
@@ -33,13 +182,51 @@ impl Loader {
             packages,
             graph: PackageGraph::new(),
             paths: HashMap::new(),
+            refs: HashMap::new(),
+            cache,
+            bases,
+            state: None,
+            ignore_version: false,
+            vars: Object::new(),
         }
     }
 
+    /// Only warn, instead of erroring, when a package requires a newer version of shelf.
     #[inline]
-    pub fn load(mut self) -> Result<Loaded, ()> {
+    pub fn ignore_version(mut self, ignore_version: bool) -> Self {
+        self.ignore_version = ignore_version;
+        self
+    }
+
+    /// Read the template variables of the last application of each package from `state`.
+    #[inline]
+    pub fn state(mut self, state: Option<StateStore>) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Override the template variables of every package with `vars`, which are checked against the
+    /// options the packages declare. See [`Spec::override_vars`].
+    #[inline]
+    pub fn vars(mut self, vars: Object) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    #[inline]
+    pub fn load(self) -> Result<Loaded, ()> {
+        crate::output::set_category(crate::output::Category::Load);
+        let res = self.load_all();
+        crate::output::set_category(crate::output::Category::General);
+        res
+    }
+
+    #[inline]
+    fn load_all(mut self) -> Result<Loaded, ()> {
         let mut errors = Vec::new();
         while let Some((path, parent)) = self.packages.pop_front() {
+            let path = self.canonicalize(path);
+            crate::output::set_annotation_file(Some(path.rel().join("package.lua")));
             match self.load_one(&path, parent.as_ref()) {
                 Err(err) => {
                     errors.push((path, err));
@@ -51,6 +238,7 @@ impl Loader {
                 }
             };
         }
+        crate::output::set_annotation_file(None);
 
         if !errors.is_empty() {
             output::error_loading(errors);
@@ -64,6 +252,30 @@ impl Loader {
         }
     }
 
+    /// Resolve symlinks in `path`, so that a package referenced under different paths is only
+    /// loaded once. Warns if the package was previously referenced under a different path.
+    #[inline]
+    fn canonicalize(&mut self, path: CtxPath) -> CtxPath {
+        // Nonexistent packages fail to load later anyway.
+        let canonical = match path.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(_) => return path,
+        };
+
+        match self.refs.get(canonical.abs()) {
+            Some(first) if first.abs() != path.abs() => {
+                output::duplicate_reference(&path, first, canonical.abs())
+            }
+            Some(_) => {}
+            None => {
+                self.refs
+                    .insert(canonical.abs().to_path_buf(), path.clone());
+            }
+        }
+
+        canonical
+    }
+
     #[inline]
     fn load_one(
         &mut self,
@@ -75,14 +287,64 @@ impl Loader {
             output::skip(path);
             vec![]
         } else {
-            let loader = SpecLoader::new(&path.abs())?;
+            // Unreadable state is reported when the package is processed.
+            let previous_vars = self
+                .state
+                .as_ref()
+                .and_then(|state| state.get(path.abs()).ok().flatten())
+                .and_then(|state| state.vars)
+                .unwrap_or_default();
+            let loader = SpecLoader::new(&path.abs())?.previous_vars(&previous_vars)?;
 
             output::reading();
             let loader = loader.read()?;
 
-            output::evaling();
-            let loader = loader.eval()?;
-            let data = loader.finish()?;
+            let key = self.cache.as_ref().map(|cache| {
+                cache.key(loader.path(), loader.contents(), &previous_vars, &self.vars)
+            });
+            let cached = match (&self.cache, key) {
+                (Some(cache), Some(key)) => cache.get(key).unwrap_or_else(|_| {
+                    output::cache_read_error(path);
+                    None
+                }),
+                _ => None,
+            };
+
+            let mut data = match cached {
+                Some(spec) => {
+                    output::cached();
+                    loader.with_spec(spec)
+                }
+                None => {
+                    output::evaling();
+                    let loader = loader.eval()?;
+                    let env = loader.env_reads();
+                    let data = loader.finish()?;
+
+                    if let (Some(cache), Some(key)) = (&self.cache, key) {
+                        if cache.insert(key, &data.spec, &env).is_err() {
+                            output::cache_write_error(path);
+                        }
+                    }
+
+                    data
+                }
+            };
+
+            self.check_version(&data.spec)?;
+
+            // Layer over the base after caching, so that changes to the base are picked up.
+            if let Some(base) = data.spec.base.clone() {
+                output::fetching_base(&base.source);
+                let base_path = self.bases.fetch(&base)?;
+                let base_data = SpecLoader::load(&base_path)?;
+                self.check_version(&base_data.spec)?;
+                base::layer(&mut data.spec, &base_data.path, base_data.spec)?;
+            }
+            // Likewise after caching, since the variables only hold for this run.
+            options::apply(&mut data.spec, &self.vars)?;
+            data.spec.override_vars(&self.vars);
+            data.spec.set_previous_vars(&previous_vars);
 
             let deps = data
                 .dep_paths()
@@ -104,4 +366,22 @@ impl Loader {
 
         Ok(deps)
     }
+
+    /// Check that this version of shelf is supported by the package, unless ignored.
+    #[inline]
+    fn check_version(&self, spec: &Spec) -> Result<(), LoadError> {
+        let required = match &spec.requires {
+            Some(required) => required,
+            None => return Ok(()),
+        };
+
+        match version::check(required) {
+            Ok(()) => Ok(()),
+            Err(err) if self.ignore_version => {
+                output::ignoring_version(&err);
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
 }
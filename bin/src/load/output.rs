@@ -1,9 +1,10 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use shelflib::load::LoadError;
+use shelflib::load::{LoadError, VersionError};
 
 use crate::ctxpath::CtxPath;
-use crate::output::{comb, spath, Section, Step};
+use crate::output::{self, comb, spath, Section, Step};
 
 #[inline]
 pub fn loading(path: &CtxPath) {
@@ -25,6 +26,44 @@ pub fn evaling() {
     Step::message("evaluating lua");
 }
 
+#[inline]
+pub fn cached() {
+    Step::message("using cached evaluation");
+}
+
+#[inline]
+pub fn cache_read_error(path: &CtxPath) {
+    Step::warning().message("couldn't read the spec cache; evaluating lua");
+    Step::warning().context(spath(path.abs()));
+}
+
+#[inline]
+pub fn cache_write_error(path: &CtxPath) {
+    Step::warning().message("couldn't write to the spec cache");
+    Step::warning().context(spath(path.abs()));
+}
+
+#[inline]
+pub fn ignoring_version(err: &VersionError) {
+    Step::warning().message(comb::pretty(format!("{}; continuing anyway", err)));
+}
+
+#[inline]
+pub fn fetching_base(source: &str) {
+    Step::message(comb::sjoin2("fetching base", spath(source)));
+}
+
+#[inline]
+pub fn duplicate_reference(path: &CtxPath, first: &CtxPath, canonical: &Path) {
+    Section::warning().message(comb::sjoin4(
+        "package",
+        spath(path.rel()),
+        "is the same as",
+        spath(first.rel()),
+    ));
+    Section::warning().context(spath(canonical));
+}
+
 #[inline]
 pub fn queueing_dep(dep: &CtxPath, parent: &Path) {
     let dep_rel = CtxPath::new(dep.abs(), &parent).unwrap();
@@ -38,10 +77,12 @@ pub fn error_loading(errors: Vec<(CtxPath, LoadError)>) {
     for (path, err) in errors.into_iter() {
         error_loading_path(path, err);
     }
+    output::set_annotation_file(None);
 }
 
 #[inline]
 pub fn error_loading_path(path: CtxPath, err: LoadError) {
+    output::set_annotation_file(Some(path.rel().join("package.lua")));
     Step::error().context(spath(path.abs()));
 
     let message = match err {
@@ -52,7 +93,72 @@ pub fn error_loading_path(path: CtxPath, err: LoadError) {
             "exists?",
         ),
         LoadError::Lua(err) => comb::sjoin2("couldn't evaluate Lua:", err),
+        LoadError::Base(err) => comb::sjoin2("couldn't layer the base package:", err),
+        LoadError::Option(err) => comb::sjoin2("invalid --set:", err),
+        LoadError::Version(err) => {
+            comb::pretty(format!("{}; upgrade shelf or pass --ignore-version", err))
+        }
     };
 
     Step::error().message(message);
 }
+
+#[inline]
+pub fn exclude_not_loaded(path: &CtxPath) {
+    Section::warning().message(comb::sjoin3(
+        "excluded package",
+        spath(path.rel()),
+        "isn't loaded; ignoring",
+    ));
+}
+
+#[inline]
+pub fn exclude_required(
+    path: &CtxPath,
+    dependents: &[&Path],
+    paths: &HashMap<PathBuf, CtxPath>,
+    force: bool,
+) {
+    if force {
+        Section::warning().message(comb::sjoin3(
+            "excluded package",
+            spath(path.rel()),
+            "is required by other packages",
+        ));
+    } else {
+        Section::error().message(comb::sjoin4(
+            "excluded package",
+            spath(path.rel()),
+            "is required by other packages;",
+            "exclude them too or use --force-exclude",
+        ));
+    }
+
+    for dependent in dependents {
+        let rel = paths.get(*dependent).map(CtxPath::rel).unwrap_or(dependent);
+        let message = comb::sjoin2("required by", spath(rel));
+        if force {
+            Step::warning().message(message);
+        } else {
+            Step::error().message(message);
+        }
+    }
+}
+
+#[inline]
+pub fn skipping_named(path: &CtxPath) {
+    Section::message("skipping", path.rel().display());
+    Step::message("only its dependencies are applied (--only-deps)");
+}
+
+#[inline]
+pub fn skipping_dep(path: &CtxPath) {
+    Section::message("skipping", path.rel().display());
+    Step::message("dependency assumed to be applied already (--no-deps)");
+}
+
+#[inline]
+pub fn excluding_dep(path: &CtxPath) {
+    Section::message("excluding", path.rel().display());
+    Step::message("only required by excluded packages");
+}
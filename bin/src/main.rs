@@ -3,30 +3,67 @@
 mod ctxpath;
 mod output;
 
+mod explain;
+mod list;
 mod load;
+#[cfg(feature = "notify")]
+mod notify;
 mod process;
+mod registry;
+mod repair;
+mod restore;
+mod status;
+mod strays;
+mod unlink;
+mod vars;
+mod verbosity;
 
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
-use clap::{ArgGroup, Parser};
+use clap::{ArgEnum, ArgGroup, Args, Parser, Subcommand};
 use directories_next::BaseDirs;
-use once_cell::unsync::Lazy;
-use shelflib::op::{
-    ctx::{FileSafe, FinishCtx},
-    journal::OpJournal,
+use log::LevelFilter;
+use serde::Serialize;
+use shelflib::{
+    action::{conflict::ConflictPolicy, tree, write},
+    fse::expand::UnknownVars,
+    graph::{select, DestFilter, PathResolver, Selector},
+    journal::{RotatePolicy, RotatingFile},
+    load::{BaseFetcher, SpecCache},
+    op::{
+        ctx::{FileSafe, FinishCtx, RetryPolicy},
+        journal::{JournalFileError, OpJournal},
+        reconcile::Reconciliation,
+        sink::{OpSink, SyslogSink, WebhookSink},
+    },
+    originals::OriginalStore,
+    spec::Scope,
+    state::{Checkpoint, StateStore},
 };
-use stderrlog::ColorChoice;
 
-use crate::load::Loader;
-use crate::output::{Prettify, Section};
-use crate::process::{Processor, ProcessorOptions};
+use crate::ctxpath::CtxPath;
+use crate::load::{Loaded, Loader};
+use crate::output::{comb, spath, Prettify, Section, Step};
+use crate::process::{
+    EscapePolicy, Estimate, PermsPolicy, Processor, ProcessorOptions, RunReport, Summary, Warning,
+};
+use crate::registry::Registry;
+use crate::strays::Handling;
+use crate::verbosity::{Logger, Verbosity};
 
 fn main() {
     let opts = Options::parse();
 
-    if cli(opts).is_err() {
-        std::process::exit(1);
+    let outcome = cli(opts);
+    // Exiting skips destructors, so buffered output must be written first.
+    log::logger().flush();
+    if outcome != Outcome::Ok {
+        std::process::exit(outcome.code());
     }
 }
 
@@ -38,61 +75,1366 @@ fn main() {
 )]
 #[clap(group(
     ArgGroup::new("vers")
-        .args(&["verbosity", "quiet"]),
+        .args(&["verbose", "quiet"]),
 ))]
 pub struct Options {
     #[clap(short, long, parse(from_occurrences), help = "Message verbosity")]
-    pub verbosity: usize,
+    pub verbose: usize,
+    #[clap(
+        long,
+        value_name = "LEVELS",
+        conflicts_with = "quiet",
+        help = "Message levels by category (general, load, or process), e.g. \
+                load=debug,process=info; a bare level sets that of the rest"
+    )]
+    pub verbosity: Option<Verbosity>,
     #[clap(short, long, help = "Silence all output")]
     pub quiet: bool,
 
-    #[clap(short, long, help = "Pretend to process")]
+    #[clap(
+        long,
+        value_name = "DIR",
+        env = "SHELF_ROOT",
+        help = "Directory of packages, by whose names its subdirectories can be given instead of \
+                their paths"
+    )]
+    pub root: Option<String>,
+    #[clap(
+        long,
+        value_name = "MS",
+        default_value = "100",
+        help = "Minimum time between flushes of buffered output, in milliseconds; errors are \
+                flushed at once, and 0 writes each message as it comes"
+    )]
+    pub flush_interval: u64,
+    #[clap(
+        long,
+        value_name = "KEY=VALUE",
+        help = "Set a template variable of every package for this run, e.g. --set theme=light",
+        long_help = "Set a template variable of every package for this run, e.g. --set \
+                     theme=light. Keys are dotted paths into nested objects, e.g. colors.bg. \
+                     Values are parsed as JSON where possible, so that true, 12, and [1, 2] are \
+                     typed; anything else is a string. May be repeated; later values take \
+                     precedence."
+    )]
+    pub set: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Always evaluate package configs, glob trees, and read written destinations, \
+                ignoring the caches"
+    )]
+    pub no_cache: bool,
+    #[clap(long, help = "Fetch base packages again, even if fetched before")]
+    pub refresh_bases: bool,
+    #[clap(
+        long,
+        help = "Refuse to fetch base packages, and to apply directives that run hooks or \
+                external programs or read outside of their package and the destination"
+    )]
+    pub hermetic: bool,
+    #[clap(
+        long,
+        help = "Warn instead of erroring for packages that require a newer version of shelf"
+    )]
+    pub ignore_version: bool,
+
+    #[clap(
+        long,
+        help = "Run non-interactively with plain output and distinct exit codes \
+                (0 ok, 1 errors, 2 conflicts, 3 drift detected)"
+    )]
+    pub ci: bool,
+    #[clap(long, help = "Treat warnings as errors")]
+    pub deny_warnings: bool,
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "text",
+        help = "Output format; github also emits warnings and errors as workflow annotations, \
+                and json prints a summary with collected warnings"
+    )]
+    pub output: OutputFormat,
+
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    #[clap(about = "Apply packages to the destination", visible_alias = "link")]
+    Apply(Box<ApplyOptions>),
+    #[clap(about = "List packages and when they were last applied")]
+    List(ListOptions),
+    #[clap(about = "Explain how a single destination would be applied, without applying it")]
+    Explain(ExplainOptions),
+    #[clap(about = "Remove or re-point broken symlinks into package sources")]
+    Repair(RepairOptions),
+    #[clap(about = "Restore what was at a destination before shelf first took it over")]
+    RestoreOriginal(RestoreOriginalOptions),
+    #[clap(about = "Report which destinations of packages are in place or have drifted")]
+    Status(StatusOptions),
+    #[clap(about = "Report files in the destinations of trees that no directive produces")]
+    Strays(StraysOptions),
+    #[clap(about = "Undo what shelf did for packages, restoring the files that it replaced")]
+    Unlink(UnlinkOptions),
+    #[clap(about = "Resume the last failed apply from where it failed")]
+    Resume,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ApplyOptions {
+    #[clap(
+        short,
+        long,
+        help = "Pretend to process, and estimate the work to be done"
+    )]
     pub noop: bool,
 
+    #[clap(flatten)]
+    pub paths: PathOptions,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "warn",
+        help = "Handling of permissive modes in ~/.ssh and ~/.gnupg"
+    )]
+    pub sensitive_perms: SensitivePerms,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "warn",
+        help = "Handling of destinations that resolve outside of the destination through \
+                symlinked parent directories"
+    )]
+    pub symlinked_parents: SymlinkedParents,
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Additional root that destinations may resolve into through symlinked parents"
+    )]
+    pub allow_root: Vec<String>,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "POLICY",
+        default_value = "replace",
+        help = "Handling of existing destinations that differ, for directives that don't set \
+                on_conflict"
+    )]
+    pub on_conflict: OnConflict,
+    #[clap(
+        long,
+        help = "Replace existing destinations that differ, regardless of on_conflict"
+    )]
+    pub force: bool,
+    #[clap(
+        short,
+        long,
+        conflicts_with = "noop",
+        help = "Describe each change and ask whether to make it before doing so"
+    )]
+    pub interactive: bool,
+    #[clap(
+        short,
+        long,
+        help = "Message describing why the packages are applied, recorded in the journal and \
+                shown by list"
+    )]
+    pub message: Option<String>,
+
+    #[clap(
+        long,
+        default_value = "0",
+        help = "Number of times to retry ops that fail with transient errors"
+    )]
+    pub retries: u32,
+    #[clap(
+        long,
+        default_value = "100",
+        help = "Delay between retries, in milliseconds"
+    )]
+    pub retry_delay: u64,
+
+    #[clap(
+        long,
+        value_name = "BYTES",
+        help = "Compress backed-up files that are at least this large"
+    )]
+    pub compress_backups: Option<u64>,
+
+    #[clap(
+        long,
+        value_name = "GLOB",
+        help = "Only apply directives whose destinations match, relative to the home directory"
+    )]
+    pub only: Vec<String>,
+    #[clap(
+        long,
+        value_name = "NAME",
+        use_value_delimiter = true,
+        help = "Also apply directives in these profiles, e.g. --profile work,laptop"
+    )]
+    pub profile: Vec<String>,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "all",
+        help = "Scope of packages to apply; when applying all without elevated privileges, \
+                system-scope packages are deferred"
+    )]
+    pub scope: ApplyScope,
+    #[clap(
+        long,
+        value_name = "PATH",
+        default_value = "/",
+        help = "Root of the destinations of system-scope packages"
+    )]
+    pub system_root: String,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "FORMAT",
+        help = "Write a report of the run to the data directory",
+        long_help = "Write a report of the run to the data directory. If SOURCE_DATE_EPOCH is set, \
+                     its time is used instead of the current time and durations are zeroed, so that \
+                     runs from the same inputs produce identical reports."
+    )]
+    pub report: Option<ReportFormat>,
+
+    #[clap(
+        long,
+        value_name = "PACKAGE",
+        help = "Skip a package, even if it is a dependency of another package"
+    )]
+    pub exclude: Vec<String>,
+    #[clap(
+        long,
+        requires = "exclude",
+        help = "Also skip dependencies that are only required by excluded packages"
+    )]
+    pub exclude_deps: bool,
+    #[clap(
+        long,
+        requires = "exclude",
+        help = "Only warn, instead of erroring, when excluding a package required by another"
+    )]
+    pub force_exclude: bool,
+
+    #[clap(
+        long,
+        conflicts_with = "no-deps",
+        help = "Only apply the dependencies of the named packages, e.g. to prepare base packages \
+                first"
+    )]
+    pub only_deps: bool,
+    #[clap(
+        long,
+        help = "Skip the dependencies of the named packages, assuming that they are already \
+                applied"
+    )]
+    pub no_deps: bool,
+
+    #[clap(
+        long,
+        help = "Default shell for command hooks (sh, or cmd.exe on Windows, if not given)"
+    )]
+    pub shell: Option<String>,
+    #[clap(long, help = "Skip hooks, applying only files")]
+    pub no_hooks: bool,
+
+    #[clap(
+        long,
+        help = "Log a record of each change to the system log, for auditing"
+    )]
+    pub audit_syslog: bool,
+    #[clap(
+        long,
+        value_name = "URL",
+        env = "SHELF_AUDIT_WEBHOOK",
+        help = "POST a JSON record of each change to this URL, for auditing"
+    )]
+    pub audit_webhook: Option<String>,
+
+    #[cfg(feature = "notify")]
+    #[clap(
+        long,
+        value_name = "SECS",
+        help = "Send a desktop notification when applying completes or fails after taking at \
+                least this long"
+    )]
+    pub notify_after: Option<u64>,
+
+    #[clap(
+        required = true,
+        help = "Packages to apply, by path or by name under --root; select directives with \
+                PATH:KIND (e.g. nvim:tree) or PATH#INDEX (1-based, e.g. nvim#3)"
+    )]
+    pub packages: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct PathOptions {
     #[clap(long, help = "Set linking destination")]
     pub home: Option<String>,
 
+    #[clap(
+        long,
+        value_name = "PATH",
+        env = "SHELF_XDG_CONFIG_HOME",
+        help = "Link destinations in ~/.config into this directory instead"
+    )]
+    pub xdg_config_home: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        env = "SHELF_XDG_DATA_HOME",
+        help = "Link destinations in ~/.local/share into this directory instead"
+    )]
+    pub xdg_data_home: Option<String>,
+
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "BEHAVIOR",
+        default_value = "error",
+        help = "Handling of unset environment variables in destinations"
+    )]
+    pub unknown_env: UnknownEnv,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListOptions {
+    #[clap(required = true)]
+    pub packages: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExplainOptions {
+    #[clap(flatten)]
+    pub paths: PathOptions,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Destination to explain, relative to the home directory if not absolute"
+    )]
+    pub dest: String,
+
+    #[clap(help = "Package that manages the destination, or one of its dependents")]
+    pub package: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RepairOptions {
+    #[clap(
+        short,
+        long,
+        help = "Only report the broken symlinks, without changing them"
+    )]
+    pub noop: bool,
+
+    #[clap(flatten)]
+    pub paths: PathOptions,
+
+    #[clap(
+        long,
+        default_value = "8",
+        help = "Maximum depth of directories under the destination to scan"
+    )]
+    pub max_depth: usize,
+
     #[clap(required = true)]
     pub packages: Vec<String>,
 }
 
+#[derive(Args, Debug, Clone)]
+pub struct RestoreOriginalOptions {
+    #[clap(short, long, help = "Only report what would be restored")]
+    pub noop: bool,
+
+    #[clap(flatten)]
+    pub paths: PathOptions,
+
+    #[clap(help = "Destination to restore, relative to the home directory if not absolute")]
+    pub dest: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct StatusOptions {
+    #[clap(flatten)]
+    pub paths: PathOptions,
+
+    #[clap(required = true)]
+    pub packages: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct StraysOptions {
+    #[clap(short, long, help = "Only report what would be adopted or deleted")]
+    pub noop: bool,
+
+    #[clap(flatten)]
+    pub paths: PathOptions,
+
+    #[clap(
+        long,
+        conflicts_with = "delete",
+        help = "Move stray files into the source of their tree, and place them back from there"
+    )]
+    pub adopt: bool,
+    #[clap(long, help = "Remove stray files, keeping them in the file safe")]
+    pub delete: bool,
+
+    #[clap(required = true)]
+    pub packages: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct UnlinkOptions {
+    #[clap(short, long, help = "Only report what would be undone")]
+    pub noop: bool,
+
+    #[clap(flatten)]
+    pub paths: PathOptions,
+
+    #[clap(required = true)]
+    pub packages: Vec<String>,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy)]
+pub enum SensitivePerms {
+    Ignore,
+    Warn,
+    Fix,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy)]
+pub enum OnConflict {
+    Replace,
+    Skip,
+    Backup,
+    Fail,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownEnv {
+    Error,
+    Empty,
+}
+
+impl Default for UnknownEnv {
+    #[inline]
+    fn default() -> Self {
+        Self::Error
+    }
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Github,
+    Json,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyScope {
+    All,
+    User,
+    System,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Html,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy)]
+pub enum SymlinkedParents {
+    Ignore,
+    Warn,
+    Error,
+}
+
+/// Class of the result of a run. In CI mode, each class maps to a distinct exit code; otherwise,
+/// only errors are distinguished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Errors,
+    Conflicts,
+    Drift,
+}
+
+impl Outcome {
+    #[inline]
+    pub fn code(self) -> i32 {
+        match self {
+            Self::Ok => 0,
+            Self::Errors => 1,
+            Self::Conflicts => 2,
+            Self::Drift => 3,
+        }
+    }
+
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Errors => "errors",
+            Self::Conflicts => "conflicts",
+            Self::Drift => "drift",
+        }
+    }
+}
+
 #[inline]
-pub fn cli(opts: Options) -> Result<(), ()> {
-    stderrlog::new()
-        .quiet(opts.quiet)
-        .verbosity(opts.verbosity + 2)
-        .show_level(false)
-        .color(ColorChoice::Never)
+pub fn cli(opts: Options) -> Outcome {
+    // Explaining, repairing, and pretending are only useful with the details of each step. This
+    // only raises the level of shelf's own messages; those of dependencies stay at warnings.
+    let verbose = match opts.command {
+        Command::Apply(ref apply) if apply.noop => opts.verbose.max(1),
+        Command::Explain(_)
+        | Command::Repair(_)
+        | Command::RestoreOriginal(_)
+        | Command::Status(_)
+        | Command::Strays(_)
+        | Command::Unlink(_) => opts.verbose.max(1),
+        _ => opts.verbose,
+    };
+    let level = match verbose {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let verbosity = opts.verbosity.clone().unwrap_or_default();
+    let interval = Duration::from_millis(opts.flush_interval);
+    Logger::new(verbosity, level, opts.quiet, interval)
         .init()
         .unwrap();
 
-    run(opts)
-        .map_err(|_| Section::fatal().message("errors were encountered; see above"))
-        .map_err(|_| ())
+    output::set_plain(opts.ci);
+    output::set_github(opts.output == OutputFormat::Github);
+
+    #[cfg(feature = "notify")]
+    let start = std::time::Instant::now();
+    let res = run(&opts);
+    if res.is_err() {
+        Section::fatal().message("errors were encountered; see above");
+    }
+
+    let failed = res.is_err();
+    let summary = res.unwrap_or_default();
+    let warnings = output::warning_count();
+    let outcome = if failed || (opts.deny_warnings && warnings > 0) {
+        Outcome::Errors
+    } else if !opts.ci {
+        // Only errors are distinguished outside of CI mode.
+        Outcome::Ok
+    } else if summary.conflicts > 0 {
+        Outcome::Conflicts
+    } else if summary.drift > 0 {
+        Outcome::Drift
+    } else {
+        Outcome::Ok
+    };
+
+    #[cfg(feature = "notify")]
+    if let Command::Apply(ref apply) = opts.command {
+        if let Some(after) = apply.notify_after {
+            notify::apply_finished(start.elapsed(), Duration::from_secs(after), outcome);
+        }
+    }
+
+    if opts.output == OutputFormat::Json {
+        let report = Report {
+            result: outcome.name(),
+            conflicts: summary.conflicts,
+            drift: summary.drift,
+            degraded: summary.degraded,
+            warnings: &summary.warnings,
+            estimate: summary.estimate.as_ref(),
+        };
+        // SAFETY: The report contains no maps with non-string keys.
+        println!("{}", serde_json::to_string(&report).unwrap());
+        return outcome;
+    }
+
+    if let Some(estimate) = &summary.estimate {
+        print_estimate(estimate);
+    }
+
+    if !summary.warnings.is_empty() {
+        Section::message("", "");
+        Section::message(
+            "summary:".yellow().bold(),
+            format!("{} warning(s)", summary.warnings.len()),
+        );
+        if summary.degraded > 0 {
+            Section::message(
+                "",
+                format!(
+                    "{} link(s) copied instead, since their destinations can't hold symlinks",
+                    summary.degraded
+                ),
+            );
+        }
+        // Already counted and annotated when first emitted.
+        for warning in &summary.warnings {
+            Section::message(
+                format!("{:>8}", warning.kind.name()),
+                describe_warning(warning),
+            );
+        }
+    }
+
+    if opts.ci {
+        // Machine-readable summary for pipelines.
+        println!(
+            "result={} conflicts={} drift={} warnings={}",
+            outcome.name(),
+            summary.conflicts,
+            summary.drift,
+            warnings
+        );
+    }
+
+    outcome
 }
 
+/// Machine-readable summary of a run, for `--output json`.
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    result: &'static str,
+    conflicts: usize,
+    drift: usize,
+    degraded: usize,
+    warnings: &'a [Warning],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimate: Option<&'a Estimate>,
+}
+
+/// Print the estimate of the work that applying would do.
 #[inline]
-fn run(opts: Options) -> Result<(), ()> {
-    let packages: Vec<_> = opts.packages.iter().map(PathBuf::from).collect();
-    let loaded = Loader::new(packages).load()?;
+fn print_estimate(estimate: &Estimate) {
+    Section::message("", "");
+    Section::message(
+        "estimate:".bold(),
+        format!(
+            "{} op(s), {} byte(s) to copy, {} hook(s)",
+            estimate.ops,
+            estimate.bytes,
+            estimate.hooks.len()
+        ),
+    );
+    for hook in &estimate.hooks {
+        let timeout = match hook.timeout {
+            Some(timeout) => format!("timeout {}s", timeout),
+            None => "no timeout".to_string(),
+        };
+        Section::message(
+            format!("{:>8}", "hook"),
+            format!(
+                "{}: {} ({})",
+                CtxPath::from_cwd(&hook.package).rel().display(),
+                hook.hook,
+                timeout
+            ),
+        );
+    }
+    if !estimate.hooks.is_empty() {
+        let bound = match estimate.hook_time() {
+            Some(time) => format!("at most {}s", time.as_secs()),
+            None => "unbounded, since some hooks have no timeout".to_string(),
+        };
+        Section::message(format!("{:>8}", "hooks"), format!("run for {}", bound));
+    }
+}
 
-    // TODO: Load journal from filesystem.
-    let mut journal = OpJournal::new();
+#[inline]
+fn describe_warning(warning: &Warning) -> String {
+    let mut parts = Vec::new();
+    if let Some(package) = &warning.package {
+        parts.push(CtxPath::from_cwd(package).rel().display().to_string());
+    }
+    if let Some(path) = &warning.path {
+        parts.push(path.display().to_string());
+    }
+    parts.push(warning.message.clone());
+    parts.join(": ")
+}
 
-    let mut processor = Processor::new(process_opts(opts)?, &mut journal);
-    processor.process(&loaded.graph, &loaded.paths)?;
+#[inline]
+fn run(opts: &Options) -> Result<Summary, ()> {
+    match &opts.command {
+        Command::Apply(apply) => run_apply(opts, *apply.clone(), None),
+        Command::List(list) => run_list(opts, list).map(|_| Summary::default()),
+        Command::Explain(explain) => run_explain(opts, explain),
+        Command::Repair(repair) => run_repair(opts, repair).map(|_| Summary::default()),
+        Command::RestoreOriginal(restore) => {
+            run_restore_original(restore).map(|_| Summary::default())
+        }
+        Command::Status(status) => run_status(opts, status),
+        Command::Strays(strays) => run_strays(opts, strays).map(|_| Summary::default()),
+        Command::Unlink(unlink) => run_unlink(opts, unlink).map(|_| Summary::default()),
+        Command::Resume => run_resume(),
+    }
+}
+
+#[inline]
+fn run_apply(
+    opts: &Options,
+    apply: ApplyOptions,
+    resume: Option<Checkpoint>,
+) -> Result<Summary, ()> {
+    if apply.interactive && opts.ci {
+        Section::error().message("--interactive can't be used with --ci");
+        return Err(());
+    }
+
+    let registry = registry(opts);
+    let targets: Vec<_> = apply
+        .packages
+        .iter()
+        .map(|target| {
+            let (path, selector) = select::parse_target(target);
+            (registry.resolve(path), selector)
+        })
+        .collect();
+
+    let packages = targets.iter().map(|(path, _)| path.clone()).collect();
+    let mut loaded = load(opts, packages)?;
+
+    if !apply.exclude.is_empty() {
+        let roots: Vec<_> = targets.iter().map(|(path, _)| path.clone()).collect();
+        let excluded: Vec<_> = apply.exclude.iter().map(|p| registry.resolve(p)).collect();
+        loaded.exclude(&roots, &excluded, apply.exclude_deps, apply.force_exclude)?;
+    }
+
+    let roots: Vec<_> = targets.iter().map(|(path, _)| path.clone()).collect();
+    if apply.only_deps {
+        loaded.only_deps(&roots);
+    } else if apply.no_deps {
+        loaded.no_deps(&roots);
+    }
+
+    let system = loaded.split_scope(Scope::System);
+    let mut passes = Vec::new();
+    if !system.is_empty() && apply.scope != ApplyScope::User {
+        if apply.scope == ApplyScope::All && !elevated() {
+            defer_system(&system);
+        } else {
+            passes.push((Scope::System, system));
+        }
+    }
+    if apply.scope != ApplyScope::System {
+        passes.push((Scope::User, loaded));
+    }
+
+    let report = apply.report;
+    let system_root = CtxPath::from_cwd(&apply.system_root).abs().to_path_buf();
+    let only = apply.only.clone();
+    let mut popts = process_opts(apply, targets)?;
+    popts.hermetic = opts.hermetic;
+    // Resuming records the arguments of the original apply again.
+    popts.args = Some(match &resume {
+        Some(checkpoint) => checkpoint.args.clone(),
+        None => env::args().collect(),
+    });
+
+    // Scopes before the one that failed were applied successfully.
+    let resume_at = resume.as_ref().and_then(|checkpoint| {
+        passes
+            .iter()
+            .position(|(_, loaded)| loaded.graph.contains(&checkpoint.package))
+    });
+
+    let mut summary = Summary::default();
+    let mut record: Option<RunReport> = None;
+    let mut res = Ok(());
+    for (i, (scope, loaded)) in passes.iter().enumerate() {
+        let mut popts = popts.clone();
+        popts.resume = match resume_at {
+            Some(at) if i < at => continue,
+            Some(at) if i > at => None,
+            _ => resume.clone(),
+        };
+        if *scope == Scope::System {
+            popts.only = if only.is_empty() {
+                None
+            } else {
+                // SAFETY: The patterns were already checked against the home directory.
+                Some(DestFilter::new(&system_root, &only).unwrap())
+            };
+            // Overrides of the XDG directories are only for users.
+            popts.paths =
+                PathResolver::new(&system_root).with_unknown_vars(popts.paths.unknown_vars());
+        }
+
+        // Each scope keeps a separate journal, so that one can be rolled back without the
+        // other.
+        let mut journal = match open_journal(*scope, popts.noop) {
+            Ok(journal) => journal,
+            Err(()) => {
+                res = Err(());
+                break;
+            }
+        };
+        journal.set_message(popts.message.clone());
+        let mut processor = Processor::new(popts, &mut journal);
+        let pass = processor.process(&loaded.graph, &loaded.paths);
+        if let Some(other) = processor.into_report() {
+            match &mut record {
+                Some(record) => record.merge(other),
+                None => record = Some(other),
+            }
+        }
+
+        match pass {
+            Ok(pass) => summary.merge(pass),
+            Err(()) => {
+                res = Err(());
+                break;
+            }
+        }
+    }
+
+    save_caches(opts);
+    if let (Some(format), Some(record)) = (report, &record) {
+        write_report(format, record);
+    }
+    res?;
 
     Section::message("", "");
     Section::message("done:".green().bold(), "no issues encountered");
 
+    Ok(summary)
+}
+
+/// Return true if running with the privileges needed to apply system-scope packages.
+#[cfg(unix)]
+#[inline]
+fn elevated() -> bool {
+    // SAFETY: geteuid is always successful.
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+#[inline]
+fn elevated() -> bool {
+    true
+}
+
+/// Warn that the system-scope packages in `system` are not applied, and how to apply them.
+#[inline]
+fn defer_system(system: &Loaded) {
+    let mut args: Vec<String> = Vec::new();
+    let mut iter = env::args();
+    while let Some(arg) = iter.next() {
+        if arg == "--scope" {
+            iter.next();
+        } else if !arg.starts_with("--scope=") {
+            args.push(arg);
+        }
+    }
+    args.push("--scope".to_string());
+    args.push("system".to_string());
+
+    Section::warning().message(format!(
+        "deferring {} system-scope package(s), which require elevated privileges",
+        system.graph.package_count()
+    ));
+    for path in system.paths.values() {
+        Step::warning().message(comb::sjoin2("package", spath(path.rel())));
+    }
+    Step::warning().message(comb::sjoin2(
+        "apply them with",
+        format!("sudo {}", args.join(" ")),
+    ));
+}
+
+#[inline]
+fn run_resume() -> Result<Summary, ()> {
+    let checkpoint = match state_store().map(|store| store.checkpoint()) {
+        Some(Ok(Some(checkpoint))) => checkpoint,
+        Some(Ok(None)) => {
+            Section::error().message("there is no failed apply to resume");
+            return Err(());
+        }
+        Some(Err(_)) | None => {
+            Section::error().message("couldn't read the checkpoint of the failed apply");
+            return Err(());
+        }
+    };
+
+    // Run the failed apply again with its original arguments.
+    let opts = match Options::try_parse_from(&checkpoint.args) {
+        Ok(opts) => opts,
+        Err(err) => {
+            Section::error()
+                .message("couldn't parse the arguments of the failed apply")
+                .reason(err.kind());
+            return Err(());
+        }
+    };
+    match opts.command.clone() {
+        Command::Apply(apply) => run_apply(&opts, *apply, Some(checkpoint)),
+        _ => {
+            Section::error().message("the checkpoint was not recorded by apply");
+            Err(())
+        }
+    }
+}
+
+#[inline]
+fn run_list(opts: &Options, list: &ListOptions) -> Result<(), ()> {
+    let packages = packages(opts, &list.packages);
+    let loaded = load(opts, packages)?;
+    list::list(&loaded, state_store().as_ref())
+}
+
+#[inline]
+fn run_explain(opts: &Options, explain: &ExplainOptions) -> Result<Summary, ()> {
+    let loaded = load(opts, vec![registry(opts).resolve(&explain.package)])?;
+
+    // Resolve as usual, but only pretend to run the ops.
+    let apply = ApplyOptions {
+        noop: true,
+        paths: explain.paths.clone(),
+        sensitive_perms: SensitivePerms::Ignore,
+        symlinked_parents: SymlinkedParents::Warn,
+        allow_root: vec![],
+        on_conflict: OnConflict::Replace,
+        force: false,
+        interactive: false,
+        message: None,
+        retries: 0,
+        retry_delay: 0,
+        compress_backups: None,
+        scope: ApplyScope::All,
+        system_root: "/".to_string(),
+        report: None,
+        only: vec![],
+        profile: vec![],
+        exclude: vec![],
+        exclude_deps: false,
+        force_exclude: false,
+        only_deps: false,
+        no_deps: false,
+        shell: None,
+        no_hooks: false,
+        audit_syslog: false,
+        audit_webhook: None,
+        #[cfg(feature = "notify")]
+        notify_after: None,
+        packages: vec![],
+    };
+    let mut popts = process_opts(apply, vec![])?;
+
+    let target = popts.paths.join(&explain.dest);
+    popts.selections = explain::explain(&loaded, &popts.paths, &target, &explain.dest)?;
+    popts.only = Some(DestFilter::exact(&target));
+    popts.state = None;
+    popts.originals = None;
+
+    let mut journal = OpJournal::new();
+    let mut processor = Processor::new(popts, &mut journal);
+    let res = processor.process(&loaded.graph, &loaded.paths);
+    save_caches(opts);
+    res
+}
+
+#[inline]
+fn run_repair(opts: &Options, repair: &RepairOptions) -> Result<(), ()> {
+    let packages = packages(opts, &repair.packages);
+    let loaded = load(opts, packages)?;
+
+    let paths = path_resolver(&repair.paths)?;
+    let ctx = FinishCtx::new(FileSafe::new(file_safe_path()?));
+    let res = repair::repair(&loaded, &paths, repair.max_depth, repair.noop, &ctx);
+    save_caches(opts);
+    res
+}
+
+#[inline]
+fn run_restore_original(restore: &RestoreOriginalOptions) -> Result<(), ()> {
+    let store = match original_store() {
+        Some(store) => store,
+        None => {
+            Section::error().message("couldn't determine a suitable location for auxiliary data");
+            return Err(());
+        }
+    };
+
+    let paths = path_resolver(&restore.paths)?;
+    let dest = paths.join(&restore.dest);
+    let ctx = FinishCtx::new(FileSafe::new(file_safe_path()?));
+    restore::restore_original(&store, &dest, paths.home(), restore.noop, &ctx)
+}
+
+#[inline]
+fn run_status(opts: &Options, status: &StatusOptions) -> Result<Summary, ()> {
+    let packages = packages(opts, &status.packages);
+    let mut loaded = load(opts, packages)?;
+    let system = loaded.split_scope(Scope::System);
+
+    // Each scope keeps a separate journal.
+    let paths = path_resolver(&status.paths)?;
+    let passes = [
+        (
+            Scope::System,
+            system,
+            PathResolver::new("/").with_unknown_vars(paths.unknown_vars()),
+        ),
+        (Scope::User, loaded, paths),
+    ];
+    let mut summary = Summary::default();
+    for (scope, loaded, paths) in &passes {
+        if loaded.is_empty() {
+            continue;
+        }
+
+        let rec = reconcile_journal(*scope)?;
+        summary.merge(status::status(loaded, paths, &rec)?);
+    }
+
+    save_caches(opts);
+    Ok(summary)
+}
+
+#[inline]
+fn run_strays(opts: &Options, strays: &StraysOptions) -> Result<(), ()> {
+    let packages = packages(opts, &strays.packages);
+    let mut loaded = load(opts, packages)?;
+    let system = loaded.split_scope(Scope::System);
+
+    let handling = if strays.adopt {
+        Handling::Adopt
+    } else if strays.delete {
+        Handling::Delete
+    } else {
+        Handling::Report
+    };
+    // Only reporting needs nothing but the journal, so it doesn't wait for other runs.
+    let readonly = strays.noop || handling == Handling::Report;
+
+    let paths = path_resolver(&strays.paths)?;
+    let ctx = FinishCtx::new(FileSafe::new(file_safe_path()?));
+    let passes = [
+        (
+            Scope::System,
+            system,
+            PathResolver::new("/").with_unknown_vars(paths.unknown_vars()),
+        ),
+        (Scope::User, loaded, paths),
+    ];
+    for (scope, loaded, paths) in &passes {
+        if loaded.is_empty() {
+            continue;
+        }
+
+        let mut journal = if readonly {
+            read_journal(*scope)?
+        } else {
+            open_journal(*scope, false)?
+        };
+        let res = strays::strays(loaded, paths, &mut journal, handling, strays.noop, &ctx);
+        if let Err(err) = journal.sync() {
+            Section::error()
+                .message("couldn't write the journal")
+                .reason(err);
+            return Err(());
+        }
+        res?;
+    }
+
+    save_caches(opts);
+    Ok(())
+}
+
+#[inline]
+fn run_unlink(opts: &Options, unlink: &UnlinkOptions) -> Result<(), ()> {
+    let roots = packages(opts, &unlink.packages);
+    let mut loaded = load(opts, roots.clone())?;
+    // Dependencies may be shared with other packages, so they are left alone.
+    loaded.no_deps(&roots);
+    let system = loaded.split_scope(Scope::System);
+
+    let paths = path_resolver(&unlink.paths)?;
+    let ctx = FinishCtx::new(FileSafe::new(file_safe_path()?));
+    let passes = [
+        (
+            Scope::System,
+            system,
+            PathResolver::new("/").with_unknown_vars(paths.unknown_vars()),
+        ),
+        (Scope::User, loaded, paths),
+    ];
+    for (scope, loaded, paths) in &passes {
+        if loaded.is_empty() {
+            continue;
+        }
+
+        // Pretending only needs to read the journal, so it doesn't wait for other runs.
+        let mut journal = if unlink.noop {
+            read_journal(*scope)?
+        } else {
+            open_journal(*scope, false)?
+        };
+        let res = unlink::unlink(loaded, paths, &mut journal, unlink.noop, &ctx);
+        if let Err(err) = journal.sync() {
+            Section::error()
+                .message("couldn't write the journal")
+                .reason(err);
+            return Err(());
+        }
+        res?;
+    }
+
+    save_caches(opts);
     Ok(())
 }
 
+/// Read the persisted journal of `scope` without locking it.
+#[inline]
+fn read_journal(scope: Scope) -> Result<OpJournal, ()> {
+    match journal_file(scope).map(|file| OpJournal::load_rotated(&file)) {
+        Some(Ok(journal)) => Ok(journal),
+        Some(Err(err)) => {
+            Section::error()
+                .message("couldn't read the journal")
+                .reason(err);
+            Err(())
+        }
+        None => {
+            Section::error().message("couldn't determine a suitable location for auxiliary data");
+            Err(())
+        }
+    }
+}
+
+/// Compare the persisted journal of `scope` against the filesystem without locking it, using its
+/// index. See [`OpJournal::reconcile_rotated`].
+#[inline]
+fn reconcile_journal(scope: Scope) -> Result<Reconciliation, ()> {
+    match journal_file(scope).map(|file| OpJournal::reconcile_rotated(&file)) {
+        Some(Ok(rec)) => Ok(rec),
+        Some(Err(err)) => {
+            Section::error()
+                .message("couldn't read the journal")
+                .reason(err);
+            Err(())
+        }
+        None => {
+            Section::error().message("couldn't determine a suitable location for auxiliary data");
+            Err(())
+        }
+    }
+}
+
+/// Open the persisted journal of `scope`, or an in-memory journal if `noop` is set, since
+/// pretending records nothing.
+#[inline]
+fn open_journal(scope: Scope, noop: bool) -> Result<OpJournal, ()> {
+    if noop {
+        return Ok(OpJournal::new());
+    }
+
+    match journal_file(scope).map(OpJournal::open) {
+        Some(Ok(journal)) => Ok(journal),
+        Some(Err(JournalFileError::Locked)) => {
+            Section::error()
+                .message("the journal is in use by another run of shelf")
+                .reason("wait for it to finish, then try again");
+            Err(())
+        }
+        Some(Err(err)) => {
+            Section::error()
+                .message("couldn't open the journal")
+                .reason(err);
+            Err(())
+        }
+        None => {
+            Section::error().message("couldn't determine a suitable location for auxiliary data");
+            Err(())
+        }
+    }
+}
+
+/// Return the registry of packages under the root directory, if one is given.
+#[inline]
+fn registry(opts: &Options) -> Registry {
+    let root = opts
+        .root
+        .as_ref()
+        .map(|root| CtxPath::from_cwd(root).abs().to_path_buf());
+    Registry::new(root)
+}
+
+/// Resolve the names or paths of `packages` to their paths.
 #[inline]
-fn process_opts(opts: Options) -> Result<ProcessorOptions, ()> {
-    let bd = Lazy::new(BaseDirs::new);
+fn packages(opts: &Options, packages: &[String]) -> Vec<PathBuf> {
+    let registry = registry(opts);
+    packages.iter().map(|p| registry.resolve(p)).collect()
+}
+
+#[inline]
+fn load(opts: &Options, packages: Vec<PathBuf>) -> Result<Loaded, ()> {
+    let cache = if opts.no_cache {
+        None
+    } else {
+        data_dir().map(|dir| SpecCache::new(dir.join("cache")))
+    };
+    let bases = data_dir()
+        .map(|dir| dir.join("bases"))
+        .unwrap_or_else(|| env::temp_dir().join(concat!(env!("CARGO_PKG_NAME"), "-bases")));
+    let bases = BaseFetcher::new(bases)
+        .refresh(opts.refresh_bases)
+        .hermetic(opts.hermetic);
+
+    // Trees are globbed while processing; reuse the files globbed by previous runs.
+    if let Some(path) = glob_cache_path(opts) {
+        if tree::cache::load(&path).is_err() {
+            tree::cache::clear();
+        }
+    }
+    // Likewise, reuse what previous runs found written destinations to hold.
+    if let Some(path) = contents_cache_path(opts) {
+        if write::cache::load(&path).is_err() {
+            write::cache::clear();
+        }
+    }
+
+    let vars = match vars::parse(&opts.set) {
+        Ok(vars) => vars,
+        Err(err) => {
+            Section::error().message("invalid --set").reason(err);
+            return Err(());
+        }
+    };
+
+    Loader::new(packages, cache, bases)
+        .state(state_store())
+        .vars(vars)
+        .ignore_version(opts.ignore_version)
+        .load()
+}
+
+#[inline]
+fn process_opts(
+    opts: ApplyOptions,
+    targets: Vec<(PathBuf, Option<Selector>)>,
+) -> Result<ProcessorOptions, ()> {
+    let paths = path_resolver(&opts.paths)?;
+    let allowed_roots = opts
+        .allow_root
+        .iter()
+        .map(|root| CtxPath::from_cwd(root).abs().to_path_buf())
+        .collect();
+
+    debug_assert!(paths.home().is_absolute());
+
+    // Collect directive selections; naming a package without a selector applies it in full.
+    let mut selections: HashMap<PathBuf, Vec<Selector>> = HashMap::new();
+    let mut full = HashSet::new();
+    for (path, selector) in targets {
+        // Package paths are canonical after loading.
+        let path = CtxPath::from_cwd(path);
+        let path = path.canonicalize().map_or_else(
+            |_| path.abs().to_path_buf(),
+            |path| path.abs().to_path_buf(),
+        );
+        match selector {
+            Some(selector) => selections.entry(path).or_default().push(selector),
+            None => {
+                full.insert(path);
+            }
+        }
+    }
+    selections.retain(|path, _| !full.contains(path));
 
-    let dest = match opts.home.map(PathBuf::from) {
+    let only = if opts.only.is_empty() {
+        None
+    } else {
+        match DestFilter::new(&paths, &opts.only) {
+            Ok(only) => Some(only),
+            Err(err) => {
+                Section::error()
+                    .message("invalid --only pattern")
+                    .reason(err.msg);
+                return Err(());
+            }
+        }
+    };
+
+    let file_safe_path = file_safe_path()?;
+    debug_assert!(file_safe_path.is_absolute());
+
+    let retry = RetryPolicy::new(opts.retries, Duration::from_millis(opts.retry_delay));
+    let mut file_safe = FileSafe::new(file_safe_path);
+    if let Some(threshold) = opts.compress_backups {
+        file_safe = file_safe.with_compression(threshold);
+    }
+    let ctx = FinishCtx::new(file_safe).with_retry(retry);
+
+    let mut sinks: Vec<Arc<dyn OpSink>> = Vec::new();
+    if opts.audit_syslog {
+        sinks.push(Arc::new(SyslogSink));
+    }
+    if let Some(url) = &opts.audit_webhook {
+        sinks.push(Arc::new(WebhookSink { url: url.clone() }));
+    }
+
+    Ok(ProcessorOptions {
+        noop: opts.noop,
+        paths,
+        perms: match opts.sensitive_perms {
+            SensitivePerms::Ignore => PermsPolicy::Ignore,
+            SensitivePerms::Warn => PermsPolicy::Warn,
+            SensitivePerms::Fix => PermsPolicy::Fix,
+        },
+        escapes: match opts.symlinked_parents {
+            SymlinkedParents::Ignore => EscapePolicy::Ignore,
+            SymlinkedParents::Warn => EscapePolicy::Warn,
+            SymlinkedParents::Error => EscapePolicy::Error,
+        },
+        conflict: match opts.on_conflict {
+            OnConflict::Replace => ConflictPolicy::Replace,
+            OnConflict::Skip => ConflictPolicy::Skip,
+            OnConflict::Backup => ConflictPolicy::Backup,
+            OnConflict::Fail => ConflictPolicy::Fail,
+        },
+        force: opts.force,
+        profiles: opts.profile.clone(),
+        interactive: opts.interactive,
+        message: opts.message.clone(),
+        allowed_roots,
+        state: state_store(),
+        originals: original_store(),
+        selections,
+        only,
+        shell: opts.shell.clone(),
+        no_hooks: opts.no_hooks,
+        hermetic: false,
+        args: None,
+        resume: None,
+        sinks,
+        ctx,
+    })
+}
+
+/// Return the resolver of destinations, which are relative to the home directory unless given
+/// with `--home`.
+#[inline]
+fn path_resolver(opts: &PathOptions) -> Result<PathResolver, ()> {
+    let home = match &opts.home {
         Some(home) => {
             // Ensure home directory is absolute.
             let cwd = match env::current_dir() {
@@ -104,7 +1446,7 @@ fn process_opts(opts: Options) -> Result<ProcessorOptions, ()> {
             };
             cwd.join(home)
         }
-        None => match &*bd {
+        None => match BaseDirs::new() {
             Some(bd) => bd.home_dir().to_path_buf(),
             None => {
                 Section::error().message("couldn't determine home directory; try --home");
@@ -113,31 +1455,127 @@ fn process_opts(opts: Options) -> Result<ProcessorOptions, ()> {
         },
     };
 
-    debug_assert!(dest.is_absolute());
+    let abs = |path: &String| CtxPath::from_cwd(path).abs().to_path_buf();
+    Ok(PathResolver::new(home)
+        .with_config_home(opts.xdg_config_home.as_ref().map(abs))
+        .with_data_home(opts.xdg_data_home.as_ref().map(abs))
+        .with_unknown_vars(match opts.unknown_env {
+            UnknownEnv::Error => UnknownVars::Error,
+            UnknownEnv::Empty => UnknownVars::Empty,
+        }))
+}
 
+/// Return a new directory in which files removed by ops are backed up.
+#[inline]
+fn file_safe_path() -> Result<PathBuf, ()> {
     // TODO: No journal option.
-    let file_safe_path = match &*bd {
-        Some(bd) => {
+    match data_dir() {
+        Some(data_dir) => {
             // TODO: Extract this logic.
             let timestamp = chrono::offset::Local::now()
                 .format("%Y-%m-%d-%H-%M-%S")
                 .to_string();
-            bd.data_local_dir()
-                .join(env!("CARGO_PKG_NAME"))
-                .join(timestamp)
+            Ok(data_dir.join(timestamp))
         }
         None => {
             Section::error().message("couldn't determine a suitable location for auxiliary data");
-            return Err(());
+            Err(())
         }
+    }
+}
+
+/// Return the on-disk journal of the ops applied to packages of `scope`, if a data directory can
+/// be determined.
+#[inline]
+fn journal_file(scope: Scope) -> Option<RotatingFile> {
+    let name = match scope {
+        Scope::User => "journal.jsonl",
+        Scope::System => "journal-system.jsonl",
     };
-    debug_assert!(file_safe_path.is_absolute());
+    data_dir().map(|dir| RotatingFile::new(dir.join(name), RotatePolicy::default()))
+}
+
+/// Return the store of the originals of destinations, if a data directory can be determined.
+#[inline]
+fn original_store() -> Option<OriginalStore> {
+    data_dir().map(|dir| OriginalStore::new(dir.join("originals")))
+}
 
-    let ctx = FinishCtx::new(FileSafe::new(file_safe_path));
+/// Return the store of per-package state, if a data directory can be determined.
+#[inline]
+fn state_store() -> Option<StateStore> {
+    data_dir().map(|dir| StateStore::new(dir.join("state")))
+}
 
-    Ok(ProcessorOptions {
-        noop: opts.noop,
-        dest,
-        ctx,
-    })
+/// Write the report of a run under the data directory, and print its path.
+#[inline]
+fn write_report(format: ReportFormat, report: &RunReport) {
+    let dir = match data_dir() {
+        Some(data_dir) => data_dir.join("reports"),
+        None => {
+            Section::warning().message("couldn't determine a location for the report");
+            return;
+        }
+    };
+
+    let timestamp = process::run_time().format("%Y-%m-%d-%H-%M-%S").to_string();
+    let (path, contents) = match format {
+        // SAFETY: The report contains no maps with non-string keys.
+        ReportFormat::Json => (
+            dir.join(timestamp).with_extension("json"),
+            serde_json::to_string_pretty(report).unwrap(),
+        ),
+        ReportFormat::Html => (dir.join(timestamp).with_extension("html"), report.to_html()),
+    };
+
+    match fs::create_dir_all(&dir).and_then(|_| fs::write(&path, contents)) {
+        Ok(()) => {
+            Section::message("", "");
+            Section::message("report:".bold(), spath(&path));
+        }
+        Err(err) => {
+            Section::warning()
+                .message(comb::sjoin2("couldn't write the report to", spath(&path)))
+                .reason(err);
+        }
+    }
+}
+
+/// Return the path of the cache of globbed tree files, unless caching is disabled.
+#[inline]
+fn glob_cache_path(opts: &Options) -> Option<PathBuf> {
+    if opts.no_cache {
+        None
+    } else {
+        data_dir().map(|dir| dir.join("cache").join("globs.json"))
+    }
+}
+
+/// Return the path of the cache of the contents of written destinations, unless caching is
+/// disabled.
+#[inline]
+fn contents_cache_path(opts: &Options) -> Option<PathBuf> {
+    if opts.no_cache {
+        None
+    } else {
+        data_dir().map(|dir| dir.join("cache").join("contents.json"))
+    }
+}
+
+/// Save the files globbed and the destination contents found while processing for later runs.
+/// Failing to is harmless, since trees are globbed and destinations are read again.
+#[inline]
+fn save_caches(opts: &Options) {
+    if let Some(path) = glob_cache_path(opts) {
+        let _ = tree::cache::save(path);
+    }
+    if let Some(path) = contents_cache_path(opts) {
+        let _ = write::cache::save(path);
+    }
+}
+
+/// Return the directory for auxiliary data (file safe, caches, etc.), if one can be determined.
+#[inline]
+fn data_dir() -> Option<PathBuf> {
+    BaseDirs::new().map(|bd| bd.data_local_dir().join(env!("CARGO_PKG_NAME")))
 }
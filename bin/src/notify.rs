@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use notify_rust::Notification;
+
+use crate::output::Section;
+use crate::Outcome;
+
+/// Send a desktop notification that an apply has completed with `outcome`, if it took at least
+/// `after`.
+#[inline]
+pub fn apply_finished(elapsed: Duration, after: Duration, outcome: Outcome) {
+    if elapsed < after {
+        return;
+    }
+
+    let body = match outcome {
+        Outcome::Ok => format!("Apply completed in {}s", elapsed.as_secs()),
+        _ => format!(
+            "Apply finished with {} after {}s",
+            outcome.name(),
+            elapsed.as_secs()
+        ),
+    };
+
+    let res = Notification::new()
+        .appname("shelf")
+        .summary("shelf")
+        .body(&body)
+        .show();
+    if let Err(err) = res {
+        Section::warning()
+            .message("couldn't send desktop notification")
+            .reason(err);
+    }
+}
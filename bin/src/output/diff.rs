@@ -0,0 +1,54 @@
+use std::fmt::Display;
+
+use similar::{ChangeTag, TextDiff};
+
+use super::comb::{indent, pretty, Prettify, Pretty};
+use super::{render, target};
+
+/// Number of unchanged lines shown around each change.
+const CONTEXT: usize = 3;
+
+/// Render the line diff from `old` to `new` as colored lines: removed lines in red, added lines
+/// in green, and the unchanged lines around them dimmed. Returns `None` if either isn't UTF-8,
+/// and no lines if they're the same.
+#[inline]
+pub fn lines(old: &[u8], new: &[u8]) -> Option<Vec<Pretty>> {
+    let (old, new) = (
+        std::str::from_utf8(old).ok()?,
+        std::str::from_utf8(new).ok()?,
+    );
+    let diff = TextDiff::from_lines(old, new);
+
+    let mut lines = Vec::new();
+    for (i, group) in diff.grouped_ops(CONTEXT).iter().enumerate() {
+        if i > 0 {
+            lines.push(pretty("...").dim());
+        }
+        for op in group {
+            for change in diff.iter_changes(op) {
+                let value = change.value().trim_end_matches(&['\r', '\n'][..]);
+                let line = match change.tag() {
+                    ChangeTag::Delete => pretty(format!("- {}", value)).red(),
+                    ChangeTag::Insert => pretty(format!("+ {}", value)).green(),
+                    ChangeTag::Equal => pretty(format!("  {}", value)).dim(),
+                };
+                lines.push(line);
+            }
+        }
+    }
+
+    Some(lines)
+}
+
+/// Emit the diff from `old` to `new` under the current step, or `binary` if either isn't UTF-8.
+#[inline]
+pub fn print(old: &[u8], new: &[u8], binary: impl Display) {
+    match lines(old, new) {
+        Some(lines) => {
+            for line in lines {
+                log::debug!(target: target(), "{}", render(indent(6, line)));
+            }
+        }
+        None => log::debug!(target: target(), "{}", render(indent(6, pretty(binary).dim()))),
+    }
+}
@@ -1,16 +1,164 @@
 // TODO: Efficiency of this stuff is probably awful.
 use std::fmt::Display;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+pub mod diff;
+pub mod prompt;
 
 pub use self::comb::{Prettify, Pretty};
 
+static PLAIN: AtomicBool = AtomicBool::new(false);
+static GITHUB: AtomicBool = AtomicBool::new(false);
+static ANNOTATION_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+static CATEGORY: AtomicU8 = AtomicU8::new(Category::General as u8);
+
+/// Category of messages, by which their levels can be set separately. Messages are logged with
+/// the name of their category as the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    /// Messages outside of loading and processing.
+    General,
+    /// Messages about loading packages.
+    Load,
+    /// Messages about processing directives.
+    Process,
+}
+
+impl Category {
+    pub const ALL: [Category; 3] = [Self::General, Self::Load, Self::Process];
+
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::General => "general",
+            Self::Load => "load",
+            Self::Process => "process",
+        }
+    }
+
+    /// Return the category with the given name, if any.
+    #[inline]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|category| category.name() == name)
+    }
+
+    #[inline]
+    fn current() -> Self {
+        match CATEGORY.load(Ordering::Relaxed) {
+            1 => Self::Load,
+            2 => Self::Process,
+            _ => Self::General,
+        }
+    }
+}
+
+/// Disable styling of all output, so that it can be consumed by other programs.
+#[inline]
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+}
+
+/// Additionally emit warnings and errors as GitHub Actions workflow commands on stdout, so that
+/// they show up as annotations.
+#[inline]
+pub fn set_github(github: bool) {
+    GITHUB.store(github, Ordering::Relaxed);
+}
+
+/// Set the file that subsequent annotations refer to, or none.
+#[inline]
+pub fn set_annotation_file(file: Option<PathBuf>) {
+    *ANNOTATION_FILE.lock().unwrap() = file;
+}
+
+/// Set the category of subsequent messages.
+#[inline]
+pub fn set_category(category: Category) {
+    CATEGORY.store(category as u8, Ordering::Relaxed);
+}
+
+/// Return the log target of subsequent messages, i.e. the name of their category.
+#[inline]
+pub fn target() -> &'static str {
+    Category::current().name()
+}
+
+/// Return the number of warnings emitted so far.
+#[inline]
+pub fn warning_count() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
+/// Render `message`, stripping escape sequences if plain output is enabled.
+#[inline]
+pub fn render(message: impl Display) -> String {
+    let message = message.to_string();
+    if !PLAIN.load(Ordering::Relaxed) {
+        return message;
+    }
+
+    strip(&message)
+}
+
+/// Strip escape sequences from `message`.
+#[inline]
+fn strip(message: &str) -> String {
+    let mut plain = String::with_capacity(message.len());
+    let mut chars = message.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip the control sequence up to and including its final byte.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// Emit a GitHub Actions annotation of `level` for `message`, if enabled.
+#[inline]
+fn annotate(level: &str, message: &str) {
+    if !GITHUB.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // See https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions.
+    let escape = |s: &str| {
+        s.replace('%', "%25")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A")
+    };
+    let message = escape(strip(message).trim());
+    match &*ANNOTATION_FILE.lock().unwrap() {
+        Some(file) => {
+            let file = escape(&file.display().to_string())
+                .replace(':', "%3A")
+                .replace(',', "%2C");
+            println!("::{} file={}::{}", level, file, message);
+        }
+        None => println!("::{}::{}", level, message),
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Section;
 
 impl Section {
     #[inline]
     pub fn message(word: impl Display, rest: impl Display) {
-        log::info!("{} {}", comb::pretty(word).dim(), rest);
+        log::info!(target: target(), "{}", render(comb::sjoin2(comb::pretty(word).dim(), rest)));
     }
 }
 
@@ -21,14 +169,15 @@ impl Step {
     #[inline]
     pub fn message(message: impl Display) {
         let prefix = comb::indent(2, "->").dim();
-        log::debug!("{} {}", prefix, message);
+        log::debug!(target: target(), "{}", render(comb::sjoin2(prefix, message)));
     }
 }
 
 macro_rules! Prefixes {
     (
         $Name:ident, $name:ident, $prefix:expr,
-        $context_prefix:expr, $reason_prefix:expr, $log:path
+        $context_prefix:expr, $reason_prefix:expr, $log:path, $counted:expr,
+        $annotation:expr
     ) => {
         #[allow(dead_code)]
         impl Section {
@@ -53,6 +202,15 @@ macro_rules! Prefixes {
         impl<const I: usize> $Name<I> {
             #[inline]
             pub fn message(&self, message: impl Display) -> Self {
+                if $counted {
+                    WARNINGS.fetch_add(1, Ordering::Relaxed);
+                }
+
+                let message = message.to_string();
+                if let Some(level) = $annotation {
+                    annotate(level, &message);
+                }
+
                 let message = comb::sjoin2(Self::prefix(), message);
                 let message = comb::indent(I, message);
                 self.print(message);
@@ -77,7 +235,7 @@ macro_rules! Prefixes {
 
             #[inline]
             fn print(&self, message: impl Display) {
-                $log!("{}", message)
+                $log!(target: target(), "{}", render(message))
             }
 
             #[inline]
@@ -104,7 +262,9 @@ Prefixes!(
     comb::pretty("fatal: ").red().bold(),
     comb::pretty(" when  ").dim(),
     comb::pretty("   as  ").dim(),
-    log::error
+    log::error,
+    false,
+    Some("error")
 );
 
 Prefixes!(
@@ -113,7 +273,9 @@ Prefixes!(
     comb::pretty("error: ").red().bold(),
     comb::pretty(" when  ").dim(),
     comb::pretty("   as  ").dim(),
-    log::error
+    log::error,
+    false,
+    Some("error")
 );
 
 Prefixes!(
@@ -122,7 +284,9 @@ Prefixes!(
     comb::pretty(" warn: ").yellow().bold(),
     comb::pretty(" when  ").dim(),
     comb::pretty("   as  ").dim(),
-    log::warn
+    log::warn,
+    true,
+    Some("warning")
 );
 
 Prefixes!(
@@ -131,7 +295,9 @@ Prefixes!(
     comb::pretty(" note: ").dim().bold(),
     comb::pretty(" when  ").dim(),
     comb::pretty("   as  ").dim(),
-    log::warn
+    log::warn,
+    false,
+    None::<&str>
 );
 
 Prefixes!(
@@ -140,7 +306,9 @@ Prefixes!(
     comb::pretty(" skip: ").blue().bold(),
     comb::pretty(" when  ").dim(),
     comb::pretty("   as  ").dim(),
-    log::warn
+    log::warn,
+    false,
+    None::<&str>
 );
 
 // TODO: Separate colors for source and destination paths?
@@ -149,6 +317,28 @@ pub fn spath(path: impl AsRef<Path>) -> Pretty {
     comb::pretty(path.as_ref().display()).green()
 }
 
+/// Describe how long ago `time` was, e.g. `3 days ago`.
+#[inline]
+pub fn ago(time: SystemTime) -> Pretty {
+    let secs = match crate::process::now().duration_since(time) {
+        Ok(elapsed) => elapsed.as_secs(),
+        // Clock moved backwards; treat as just now.
+        Err(_) => 0,
+    };
+
+    let (n, unit) = match secs {
+        0..=59 => return comb::pretty("just now"),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        86400..=2591999 => (secs / 86400, "day"),
+        2592000..=31535999 => (secs / 2592000, "month"),
+        _ => (secs / 31536000, "year"),
+    };
+    let plural = if n == 1 { "" } else { "s" };
+
+    comb::pretty(format!("{} {}{} ago", n, unit, plural))
+}
+
 #[allow(dead_code)]
 pub mod comb {
     use std::fmt::Display;
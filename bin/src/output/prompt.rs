@@ -0,0 +1,65 @@
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+
+use super::comb::{pretty, sjoin2, Prettify};
+use super::render;
+
+/// Answer to a confirmation prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Answer {
+    Yes,
+    No,
+    /// Yes, and to all further prompts.
+    All,
+    /// No, and to all further prompts.
+    Quit,
+}
+
+/// Ask on stderr whether to go ahead with `question`, and read the answer from stdin. Input that
+/// isn't an answer is asked about again; an empty answer is no, and the end of input is quitting.
+#[inline]
+pub fn confirm(question: impl Display) -> Answer {
+    let question = render(sjoin2(question, pretty("[y/N/a/q]").dim()));
+    // The question must come after the buffered messages that led up to it.
+    log::logger().flush();
+    let stdin = io::stdin();
+    loop {
+        eprint!("{} ", question);
+        let _ = io::stderr().flush();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return Answer::Quit,
+            Ok(_) => {}
+        }
+        if let Some(answer) = parse(&line) {
+            return answer;
+        }
+    }
+}
+
+#[inline]
+fn parse(line: &str) -> Option<Answer> {
+    match line.trim().to_lowercase().as_str() {
+        "y" | "yes" => Some(Answer::Yes),
+        "" | "n" | "no" => Some(Answer::No),
+        "a" | "all" => Some(Answer::All),
+        "q" | "quit" => Some(Answer::Quit),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Answer};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("y\n"), Some(Answer::Yes));
+        assert_eq!(parse(" YES "), Some(Answer::Yes));
+        assert_eq!(parse("\n"), Some(Answer::No));
+        assert_eq!(parse("a"), Some(Answer::All));
+        assert_eq!(parse("q"), Some(Answer::Quit));
+        assert_eq!(parse("maybe"), None);
+    }
+}
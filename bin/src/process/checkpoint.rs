@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+use shelflib::{graph::PackageData, state::Checkpoint};
+
+use super::{GraphProcessor, WarningKind};
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    /// Return the index in `plan` of the package to resume from, if resuming. Fails if the plan
+    /// has changed since the checkpoint was recorded.
+    #[inline]
+    pub fn resume_index(&self, plan: &[PathBuf]) -> Result<Option<usize>, ()> {
+        let checkpoint = match &self.opts.resume {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(None),
+        };
+
+        match plan.iter().position(|path| *path == checkpoint.package) {
+            Some(index) if plan == checkpoint.plan.as_slice() => {
+                output::resuming(index, checkpoint);
+                Ok(Some(index))
+            }
+            _ => {
+                output::plan_changed();
+                Err(())
+            }
+        }
+    }
+
+    /// Record a checkpoint for the failure of the package `pd`, unless pretending or checkpoints
+    /// are disabled.
+    #[inline]
+    pub fn record_checkpoint(&self, plan: Vec<PathBuf>, pd: &PackageData) {
+        let (store, args) = match (&self.opts.state, &self.opts.args) {
+            (Some(store), Some(args)) if !self.opts.noop => (store, args),
+            _ => return,
+        };
+
+        let checkpoint = Checkpoint {
+            failed_at: super::report::now(),
+            args: args.clone(),
+            plan,
+            package: pd.path.clone(),
+            actions: self.progress.actions,
+            ops: self.progress.ops,
+        };
+        match store.set_checkpoint(&checkpoint) {
+            Ok(()) => output::checkpoint_recorded(),
+            Err(_) => {
+                output::checkpoint_write_error();
+                self.warn(
+                    WarningKind::State,
+                    Some(&pd.path),
+                    None,
+                    "couldn't record checkpoint",
+                );
+            }
+        }
+    }
+
+    /// Remove the checkpoint once the apply that it was recorded for has succeeded.
+    #[inline]
+    pub fn clear_checkpoint(&self) {
+        let (store, args) = match (&self.opts.state, &self.opts.args) {
+            (Some(store), Some(args)) if !self.opts.noop => (store, args),
+            _ => return,
+        };
+
+        let res = store.checkpoint().and_then(|checkpoint| match checkpoint {
+            Some(checkpoint) if checkpoint.args == *args => store.clear_checkpoint(),
+            _ => Ok(()),
+        });
+        if res.is_err() {
+            self.warn(WarningKind::State, None, None, "couldn't remove checkpoint");
+        }
+    }
+}
+
+mod output {
+    use shelflib::state::Checkpoint;
+
+    use crate::output::Section;
+
+    #[inline]
+    pub fn resuming(index: usize, checkpoint: &Checkpoint) {
+        Section::message(
+            "resuming",
+            format!(
+                "skipping {} package(s) and {} directive(s) applied before the failure",
+                index, checkpoint.actions
+            ),
+        );
+    }
+
+    #[inline]
+    pub fn plan_changed() {
+        Section::error()
+            .message("the packages to apply have changed since the failure")
+            .reason("run apply again instead of resuming");
+    }
+
+    #[inline]
+    pub fn checkpoint_recorded() {
+        Section::message(
+            "checkpoint",
+            "recorded; fix the problem and run 'shelf resume' to continue",
+        );
+    }
+
+    #[inline]
+    pub fn checkpoint_write_error() {
+        Section::warning().message("couldn't record checkpoint");
+    }
+}
@@ -13,7 +13,7 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
     #[inline]
     pub fn resolve_command(
         &self,
-        action: CommandAction,
+        action: &CommandAction,
         _path: &CtxPath,
     ) -> Result<Vec<Op<'static>>, ()> {
         let res = match action.resolve() {
@@ -0,0 +1,150 @@
+use shelflib::graph::Conflict;
+
+use super::{GraphProcessor, WarningKind};
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    /// Report conflicts between destination paths managed by different packages before anything
+    /// is processed. Returns the number of conflicts.
+    #[inline]
+    pub fn report_conflicts(&self) -> usize {
+        let conflicts = self.graph.conflicts(&self.opts.paths);
+        if conflicts.is_empty() {
+            return 0;
+        }
+
+        output::conflicts_found(conflicts.len());
+        for conflict in &conflicts {
+            output::conflict(conflict, self.paths, self.opts.paths.home());
+
+            let (claim, message) = match conflict {
+                Conflict::SameDest { second, .. } => (
+                    second,
+                    "destination is managed by multiple packages".to_string(),
+                ),
+                Conflict::Case { first, second } => (
+                    second,
+                    format!(
+                        "destination differs only in case from {}",
+                        first.dest.display()
+                    ),
+                ),
+                Conflict::Nested { dir, inner } => (
+                    inner,
+                    format!(
+                        "destination is inside directory {}, which is managed as a whole",
+                        dir.dest.display()
+                    ),
+                ),
+            };
+            self.warn(
+                WarningKind::Conflict,
+                Some(&claim.package),
+                Some(&claim.dest),
+                message,
+            );
+        }
+        crate::output::set_annotation_file(None);
+
+        conflicts.len()
+    }
+}
+
+mod output {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    use shelflib::graph::{Claim, ClaimKind, Conflict};
+
+    use super::super::describe;
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        self,
+        comb::{sjoin2, sjoin3, sjoin4},
+        spath, Section, Step,
+    };
+
+    #[inline]
+    pub fn conflicts_found(count: usize) {
+        Section::warning().message(sjoin3(
+            "found",
+            count,
+            "conflict(s) between packages; see below",
+        ));
+    }
+
+    #[inline]
+    pub fn conflict(conflict: &Conflict, paths: &HashMap<PathBuf, CtxPath>, dest: &Path) {
+        let package = |claim: &Claim| match paths.get(&claim.package) {
+            Some(path) => spath(path.rel()),
+            None => spath(&claim.package),
+        };
+        let claim_dest = |claim: &Claim| describe::sdest_relative(dest, &claim.dest);
+        // Annotate the config of the package whose claim runs into the other one.
+        let annotate = |claim: &Claim| {
+            let path = match paths.get(&claim.package) {
+                Some(path) => path.rel(),
+                None => &claim.package,
+            };
+            output::set_annotation_file(Some(path.join("package.lua")));
+        };
+
+        match conflict {
+            Conflict::SameDest { first, second } => {
+                annotate(second);
+                Step::warning()
+                    .message(sjoin2("multiple packages manage", claim_dest(first)))
+                    .context(sjoin2("package", package(first)))
+                    .context(sjoin2("package", package(second)))
+                    .reason("the later package will overwrite the earlier one; keep only one");
+            }
+            Conflict::Case { first, second } => {
+                annotate(second);
+                Step::warning()
+                    .message(sjoin4(
+                        claim_dest(first),
+                        "and",
+                        claim_dest(second),
+                        "differ only in case",
+                    ))
+                    .context(sjoin2("package", package(first)))
+                    .context(sjoin2("package", package(second)))
+                    .reason("these collide on case-insensitive filesystems; rename one");
+            }
+            Conflict::Nested { dir, inner } => {
+                annotate(inner);
+                Step::warning()
+                    .message(sjoin4(
+                        claim_dest(inner),
+                        "is inside directory",
+                        claim_dest(dir),
+                        nested_how(dir.kind),
+                    ))
+                    .context(sjoin2("directory from package", package(dir)))
+                    .context(sjoin2("file from package", package(inner)))
+                    .reason(nested_suggestion(dir.kind));
+            }
+        }
+    }
+
+    #[inline]
+    fn nested_how(kind: ClaimKind) -> &'static str {
+        match kind {
+            ClaimKind::DirLink => "(linked as a whole)",
+            ClaimKind::DirCopy | ClaimKind::File => "(copied as a whole)",
+        }
+    }
+
+    #[inline]
+    fn nested_suggestion(kind: ClaimKind) -> &'static str {
+        match kind {
+            ClaimKind::DirLink => {
+                "the file would be written into the other package's source tree; link the \
+                 directory with tree instead, so that its files are linked individually"
+            }
+            ClaimKind::DirCopy | ClaimKind::File => {
+                "the directory copy would overwrite the file; mark it volatile in the copy_dir \
+                 directive, or move the file into that package and depend on it"
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use shelflib::{
+    action::{
+        copydir::{self, Error, Res},
+        CopyDirAction, Resolve,
+    },
+    op::Op,
+};
+
+use super::GraphProcessor;
+use crate::ctxpath::CtxPath;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    #[inline]
+    pub fn resolve_copy_dir(
+        &self,
+        action: &CopyDirAction,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        output::processing_copy_dir(action, path, self.opts.paths.home());
+
+        let res = match action.resolve() {
+            Ok(res) => res,
+            Err(err) => {
+                match err {
+                    Error::SrcMissing => output::src_missing(action, path, self.opts.paths.home()),
+                    Error::SrcNotDir => output::src_not_dir(action, path, self.opts.paths.home()),
+                    Error::Pattern(err) => {
+                        output::volatile_pattern_error(err, action, path, self.opts.paths.home())
+                    }
+                }
+
+                return Err(());
+            }
+        };
+
+        match res {
+            Res::Normal(ops) => Ok(map_ops(ops)),
+            Res::Overwrite(ops) => {
+                self.drifted(path, &action.dest);
+                output::overwriting(action, path, self.opts.paths.home());
+                Ok(map_ops(ops))
+            }
+            Res::Skip(skip) => {
+                output::skipping(&skip, action, path, self.opts.paths.home());
+                Ok(vec![])
+            }
+        }
+    }
+}
+
+#[inline]
+fn map_ops(ops: Vec<copydir::Op>) -> Vec<Op<'static>> {
+    ops.into_iter()
+        .map(|op| match op {
+            copydir::Op::Rm(op) => Op::Rm(op),
+            copydir::Op::CopyDir(op) => Op::CopyDir(op),
+            copydir::Op::Mkdir(op) => Op::Mkdir(op),
+        })
+        .collect()
+}
+
+mod output {
+    use std::fmt::Display;
+    use std::path::Path;
+
+    use shelflib::action::{copydir::Skip, CopyDirAction};
+
+    use super::super::{describe, Describe, DescribeMode};
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        Pretty, Step,
+    };
+
+    impl Describe for CopyDirAction {
+        #[inline]
+        fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+            let src = describe::path_relative(&self.src, path);
+            let dest = describe::dest_relative(&self.dest, dest);
+            sjoin4(
+                "copying directory",
+                describe::mode_spath(src, mode),
+                "to",
+                describe::mode_spath(dest, mode),
+            )
+        }
+    }
+
+    #[inline]
+    pub fn processing_copy_dir(action: &CopyDirAction, path: &CtxPath, dest: &Path) {
+        Step::message(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn src_missing(action: &CopyDirAction, path: &CtxPath, dest: &Path) {
+        Step::error().message(sjoin2(
+            "missing source",
+            describe::spath_relative(&action.src, path),
+        ));
+        Step::error().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn src_not_dir(action: &CopyDirAction, path: &CtxPath, dest: &Path) {
+        Step::error().message(sjoin2(
+            "source is not a directory",
+            describe::spath_relative(&action.src, path),
+        ));
+        Step::error().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn volatile_pattern_error(
+        err: impl Display,
+        action: &CopyDirAction,
+        path: &CtxPath,
+        dest: &Path,
+    ) {
+        Step::error()
+            .message("invalid volatile pattern")
+            .reason(err)
+            .context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn overwriting(action: &CopyDirAction, path: &CtxPath, dest: &Path) {
+        Step::warning().message(sjoin2(
+            "overwriting existing",
+            describe::sdest_relative(&action.dest, dest),
+        ));
+        Step::warning().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn skipping(skip: &Skip, action: &CopyDirAction, path: &CtxPath, dest: &Path) {
+        let message = match skip {
+            Skip::SameSrcDest => sjoin2(
+                "same source and destination",
+                describe::spath_relative(&action.src, path),
+            ),
+            Skip::OptMissing => sjoin2(
+                "missing optional source",
+                describe::spath_relative(&action.src, path),
+            ),
+            Skip::DestExists => sjoin2(
+                "existing destination",
+                describe::sdest_relative(&action.dest, dest),
+            ),
+        };
+
+        Step::skipping().message(message);
+        Step::skipping().context(action.describe_info(path, dest));
+    }
+}
@@ -0,0 +1,87 @@
+use shelflib::{
+    action::{
+        defaults::{self, Res},
+        DefaultsAction, Resolve,
+    },
+    op::Op,
+};
+
+use super::GraphProcessor;
+use crate::ctxpath::CtxPath;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    #[inline]
+    pub fn resolve_defaults(
+        &self,
+        action: &DefaultsAction,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        match action.resolve() {
+            Ok(Res::Normal(op)) => Ok(vec![Op::Defaults(op)]),
+            Ok(Res::Skip(skip)) => {
+                output::skipping(&skip, action, path, self.opts.paths.home());
+                Ok(vec![])
+            }
+            Err(defaults::Error::Read(err)) => {
+                output::read_error(err, action, path, self.opts.paths.home());
+                Err(())
+            }
+        }
+    }
+}
+
+mod output {
+    use std::path::Path;
+
+    use shelflib::{
+        action::{defaults::Skip, DefaultsAction},
+        op::error::DefaultsError,
+    };
+
+    use super::super::{Describe, DescribeMode};
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        Pretty, Step,
+    };
+
+    impl Describe for DefaultsAction {
+        #[inline]
+        fn describe(&self, _path: &CtxPath, _dest: &Path, _mode: DescribeMode) -> Pretty {
+            sjoin4(
+                "setting default",
+                format!("{} {}", self.domain, self.key),
+                "to",
+                &self.value,
+            )
+        }
+    }
+
+    #[inline]
+    pub fn skipping(skip: &Skip, action: &DefaultsAction, path: &CtxPath, dest: &Path) {
+        let message = match skip {
+            Skip::Unsupported => sjoin2(
+                "not on macOS, so not setting",
+                format!("{} {}", action.domain, action.key),
+            ),
+            Skip::UpToDate => sjoin2(
+                "default already set",
+                format!("{} {}", action.domain, action.key),
+            ),
+        };
+
+        Step::skipping().message(message);
+        Step::skipping().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn read_error(err: DefaultsError, action: &DefaultsAction, path: &CtxPath, dest: &Path) {
+        Step::error()
+            .message(sjoin2(
+                "couldn't read current value of",
+                format!("{} {}", action.domain, action.key),
+            ))
+            .reason(err.inner)
+            .context(action.describe_info(path, dest));
+    }
+}
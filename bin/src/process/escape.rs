@@ -0,0 +1,80 @@
+use super::{EscapePolicy, GraphProcessor, WarningKind};
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    /// Check for destinations that would be written outside of the destination roots through
+    /// symlinked parent directories before anything is processed.
+    #[inline]
+    pub fn check_escapes(&self) -> Result<(), ()> {
+        let deny = match self.opts.escapes {
+            EscapePolicy::Ignore => return Ok(()),
+            EscapePolicy::Warn => false,
+            EscapePolicy::Error => true,
+        };
+
+        let escapes = self
+            .graph
+            .escapes(&self.opts.paths, &self.opts.allowed_roots);
+        for escape in &escapes {
+            output::escape(escape, self.paths, self.opts.paths.home(), deny);
+            if !deny {
+                self.warn(
+                    WarningKind::Escape,
+                    Some(&escape.package),
+                    Some(&escape.dest),
+                    format!(
+                        "destination resolves outside of the destination to {}",
+                        escape.real.display()
+                    ),
+                );
+            }
+        }
+
+        if deny && !escapes.is_empty() {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+mod output {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    use shelflib::graph::Escape;
+
+    use super::super::describe;
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{pretty, sjoin2},
+        spath, Step,
+    };
+
+    #[inline]
+    pub fn escape(escape: &Escape, paths: &HashMap<PathBuf, CtxPath>, dest: &Path, deny: bool) {
+        let message = sjoin2(
+            describe::sdest_relative(dest, &escape.dest),
+            "resolves outside of the destination",
+        );
+        let package = match paths.get(&escape.package) {
+            Some(path) => spath(path.rel()),
+            None => spath(&escape.package),
+        };
+        let reason = pretty(format!(
+            "a parent directory is a symlink to {}; pass --allow-root if this is intended",
+            spath(&escape.real)
+        ));
+
+        if deny {
+            Step::error()
+                .message(message)
+                .context(sjoin2("package", package))
+                .reason(reason);
+        } else {
+            Step::warning()
+                .message(message)
+                .context(sjoin2("package", package))
+                .reason(reason);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+use shelflib::op::Op;
+
+use super::GraphProcessor;
+
+/// Estimate of the work that applying would do, collected while pretending.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Estimate {
+    /// Number of filesystem ops.
+    pub ops: usize,
+    /// Total size in bytes of the files to copy.
+    pub bytes: u64,
+    /// Hooks to run, in order.
+    pub hooks: Vec<HookEstimate>,
+}
+
+/// A hook that applying would run.
+#[derive(Debug, Clone, Serialize)]
+pub struct HookEstimate {
+    /// Absolute path of the package.
+    pub package: PathBuf,
+    /// Command, script path, or `"function"`.
+    pub hook: String,
+    /// Declared timeout in seconds, if any.
+    pub timeout: Option<u64>,
+}
+
+impl Estimate {
+    /// Add the work of `other`.
+    #[inline]
+    pub fn merge(&mut self, other: Estimate) {
+        self.ops += other.ops;
+        self.bytes += other.bytes;
+        self.hooks.extend(other.hooks);
+    }
+
+    /// Return the sum of the declared hook timeouts, or `None` if a hook has no timeout, in which
+    /// case there is no upper bound.
+    #[inline]
+    pub fn hook_time(&self) -> Option<Duration> {
+        self.hooks
+            .iter()
+            .map(|hook| hook.timeout)
+            .sum::<Option<u64>>()
+            .map(Duration::from_secs)
+    }
+}
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    /// Add the ops that would be run for the package at `package` to the estimate.
+    #[inline]
+    pub fn estimate(&mut self, ops: &[Op<'_>], package: &Path) {
+        for op in ops {
+            let (hook, timeout) = match op {
+                Op::Command(op) => (op.command.clone(), op.timeout),
+                Op::Script(op) => (op.path.display().to_string(), op.timeout),
+                Op::Function(_) => ("function".to_string(), None),
+                op => {
+                    self.estimate.ops += 1;
+                    self.estimate.bytes += copy_size(op);
+                    continue;
+                }
+            };
+
+            self.estimate.hooks.push(HookEstimate {
+                package: package.to_path_buf(),
+                hook,
+                timeout: timeout.map(|timeout| timeout.as_secs()),
+            });
+        }
+    }
+}
+
+/// Return the number of bytes copied by `op`, as far as can be told before running it.
+#[inline]
+fn copy_size(op: &Op<'_>) -> u64 {
+    match op {
+        Op::Copy(op) => size(&op.src),
+        Op::CopyDir(op) => size(&op.src),
+        _ => 0,
+    }
+}
+
+/// Return the total size of the file or directory at `path`, not following symlinks.
+#[inline]
+fn size(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| size(&entry.path()))
+                    .sum()
+            })
+            .unwrap_or(0),
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
+}
@@ -0,0 +1,92 @@
+use shelflib::{
+    action::{ExpectAction, Resolve},
+    op::Op,
+};
+
+use super::GraphProcessor;
+use crate::ctxpath::CtxPath;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    #[inline]
+    pub fn resolve_expect(
+        &self,
+        action: &ExpectAction,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        // The actions before this one weren't applied, so there is nothing to check.
+        if self.opts.noop {
+            output::skipping(action, path, self.opts.paths.home());
+            return Ok(vec![]);
+        }
+
+        match action.resolve() {
+            Ok(()) => {
+                output::met(action, path, self.opts.paths.home());
+                Ok(vec![])
+            }
+            Err(err) => {
+                output::unmet(err, action, path, self.opts.paths.home());
+                Err(())
+            }
+        }
+    }
+}
+
+mod output {
+    use std::path::Path;
+
+    use shelflib::action::{
+        expect::{Error, Expectation},
+        ExpectAction,
+    };
+
+    use super::super::{describe, Describe, DescribeMode};
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        Pretty, Step,
+    };
+
+    impl Describe for ExpectAction {
+        #[inline]
+        fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+            let this = describe::mode_spath(describe::dest_relative(&self.dest, dest), mode);
+            match &self.expectation {
+                Expectation::Symlink(target) => {
+                    let target = describe::path_relative(target, path);
+                    sjoin4(
+                        "expecting",
+                        this,
+                        "to link to",
+                        describe::mode_spath(target, mode),
+                    )
+                }
+                Expectation::File {
+                    contains: Some(contains),
+                } => sjoin4("expecting", this, "to contain", format!("'{}'", contains)),
+                Expectation::File { contains: None } => {
+                    sjoin2(sjoin2("expecting", this), "to be a file")
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub fn skipping(action: &ExpectAction, path: &CtxPath, dest: &Path) {
+        Step::skipping().message("pretending, so expectations aren't checked");
+        Step::skipping().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn met(action: &ExpectAction, path: &CtxPath, dest: &Path) {
+        Step::message(sjoin2("met:", action.describe_info(path, dest)));
+    }
+
+    #[inline]
+    pub fn unmet(err: Error, action: &ExpectAction, path: &CtxPath, dest: &Path) {
+        Step::error()
+            .message("expectation not met")
+            .reason(err)
+            .context(action.describe_error(path, dest));
+    }
+}
@@ -0,0 +1,92 @@
+use shelflib::{
+    action::{write::Res, FragmentAction, Resolve},
+    op::Op,
+};
+
+use super::write::map_ops;
+use super::GraphProcessor;
+use crate::ctxpath::CtxPath;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    #[inline]
+    pub fn resolve_fragment(
+        &self,
+        action: &FragmentAction,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        let res = match action.resolve() {
+            Ok(res) => res,
+            Err(err) => {
+                output::resolve_error(err, action, path, self.opts.paths.home());
+                return Err(());
+            }
+        };
+
+        let mut ops: Vec<_> = res
+            .prune
+            .into_iter()
+            .map(|op| {
+                output::pruning(&op.path, self.opts.paths.home());
+                Op::Rm(op)
+            })
+            .collect();
+
+        match res.write {
+            Res::Normal(write) => ops.extend(map_ops(write)),
+            Res::OverwriteContents(write) | Res::OverwriteFile(write) => {
+                self.drifted(path, &action.dest);
+                ops.extend(map_ops(write));
+            }
+            // Fragments own their blocks, and always replace them.
+            Res::Skip(_) | Res::Conflict => {}
+        }
+
+        Ok(ops)
+    }
+}
+
+mod output {
+    use std::path::Path;
+
+    use shelflib::action::{fragment, FragmentAction};
+
+    use super::super::{describe, Describe, DescribeMode};
+    use crate::ctxpath::CtxPath;
+    use crate::output::{comb::sjoin2, Pretty, Step};
+
+    impl Describe for FragmentAction {
+        #[inline]
+        fn describe(&self, _path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+            let dest = describe::dest_relative(&self.dest, dest);
+            sjoin2("writing fragment", describe::mode_spath(dest, mode))
+        }
+    }
+
+    #[inline]
+    pub fn resolve_error(
+        err: fragment::Error,
+        action: &FragmentAction,
+        path: &CtxPath,
+        dest: &Path,
+    ) {
+        match err {
+            fragment::Error::ReadDir(err) => {
+                Step::error()
+                    .message(sjoin2(
+                        "couldn't read fragment directory",
+                        describe::sdest_relative(&action.dir, dest),
+                    ))
+                    .reason(err)
+                    .context(action.describe_info(path, dest));
+            }
+        }
+    }
+
+    #[inline]
+    pub fn pruning(fragment: &Path, dest: &Path) {
+        Step::message(sjoin2(
+            "removing stale fragment",
+            describe::sdest_relative(fragment, dest),
+        ));
+    }
+}
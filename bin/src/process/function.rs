@@ -1,9 +1,11 @@
+use std::path::Path;
+
 use shelflib::{
     action::{
         function::{self, Res},
-        FunctionAction, Resolve,
+        Action, FunctionAction, Resolve,
     },
-    op::Op,
+    op::{function::FunctionStep, FunctionOp, Op},
 };
 
 use super::GraphProcessor;
@@ -13,7 +15,7 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
     #[inline]
     pub fn resolve_function<'lua>(
         &self,
-        action: FunctionAction<'lua>,
+        action: &FunctionAction<'lua>,
         _path: &CtxPath,
     ) -> Result<Vec<Op<'lua>>, ()> {
         let res = match action.resolve() {
@@ -31,6 +33,38 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
             }
         }
     }
+
+    /// Run the function of `iop`, processing each change it requests as an action of its own
+    /// and passing back whether it succeeded.
+    #[inline]
+    pub fn process_function_op(
+        &mut self,
+        action: &Action<'_>,
+        iop: &FunctionOp<'_>,
+        path: &CtxPath,
+        dest: &Path,
+    ) -> Result<(), ()> {
+        let action = match action {
+            Action::Function(action) => action,
+            _ => unreachable!(),
+        };
+
+        let mut step = iop.start();
+        loop {
+            match step {
+                Ok(FunctionStep::Yield(req)) => {
+                    let req = action.request(req);
+                    let ok = self.process_action(req, path, dest).is_ok();
+                    step = iop.resume(ok);
+                }
+                Ok(FunctionStep::Return(_ret)) => return Ok(()),
+                Err(err) => {
+                    output::function_error(err);
+                    return Err(());
+                }
+            }
+        }
+    }
 }
 
 #[inline]
@@ -45,11 +79,11 @@ fn map_ops(ops: Vec<function::Op<'_>>) -> Vec<Op<'_>> {
 mod output {
     use std::path::Path;
 
-    use shelflib::action::FunctionAction;
+    use shelflib::{action::FunctionAction, op::function::FunctionOpError};
 
     use super::super::{Describe, DescribeMode};
     use crate::ctxpath::CtxPath;
-    use crate::output::{comb::pretty, Pretty};
+    use crate::output::{comb::pretty, Pretty, Step};
 
     impl<'lua> Describe for FunctionAction<'lua> {
         #[inline]
@@ -57,4 +91,9 @@ mod output {
             pretty("calling lua function")
         }
     }
+
+    #[inline]
+    pub fn function_error(err: FunctionOpError) {
+        Step::error().message("lua function failed").reason(err);
+    }
 }
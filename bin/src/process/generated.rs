@@ -1,6 +1,8 @@
+use std::path::Path;
+
 use shelflib::{
     action::{
-        generated::{self, Res},
+        generated::{self, json, toml, yaml, Res},
         JsonAction, Resolve, TomlAction, YamlAction,
     },
     op::Op,
@@ -13,73 +15,110 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
     #[inline]
     pub fn resolve_yaml(
         &self,
-        action: YamlAction,
-        _path: &CtxPath,
+        action: &YamlAction,
+        path: &CtxPath,
     ) -> Result<Vec<Op<'static>>, ()> {
         let res = match action.resolve() {
             Ok(res) => res,
-            Err(_err) => {
-                // TODO: Output
+            Err(err) => {
+                match err {
+                    yaml::Error::Serde(err) => {
+                        output::serialize_error(err, action, path, self.opts.paths.home())
+                    }
+                    yaml::Error::Schema(err) => {
+                        output::schema_error(err, action, path, self.opts.paths.home())
+                    }
+                }
+
                 return Err(());
             }
         };
 
-        handle_res(res)
+        self.handle_generated_res(res, path, &action.dest)
     }
 
     #[inline]
     pub fn resolve_toml(
         &self,
-        action: TomlAction,
-        _path: &CtxPath,
+        action: &TomlAction,
+        path: &CtxPath,
     ) -> Result<Vec<Op<'static>>, ()> {
         let res = match action.resolve() {
             Ok(res) => res,
-            Err(_err) => {
-                // TODO: Output
+            Err(err) => {
+                match err {
+                    toml::Error::Serde(err) => {
+                        output::serialize_error(err, action, path, self.opts.paths.home())
+                    }
+                    toml::Error::Schema(err) => {
+                        output::schema_error(err, action, path, self.opts.paths.home())
+                    }
+                }
+
                 return Err(());
             }
         };
 
-        handle_res(res)
+        self.handle_generated_res(res, path, &action.dest)
     }
 
     #[inline]
     pub fn resolve_json(
         &self,
-        action: JsonAction,
-        _path: &CtxPath,
+        action: &JsonAction,
+        path: &CtxPath,
     ) -> Result<Vec<Op<'static>>, ()> {
         let res = match action.resolve() {
             Ok(res) => res,
-            Err(_err) => {
-                // TODO: Output
+            Err(err) => {
+                match err {
+                    json::Error::Serde(err) => {
+                        output::serialize_error(err, action, path, self.opts.paths.home())
+                    }
+                    json::Error::Schema(err) => {
+                        output::schema_error(err, action, path, self.opts.paths.home())
+                    }
+                }
+
                 return Err(());
             }
         };
 
-        handle_res(res)
+        self.handle_generated_res(res, path, &action.dest)
     }
-}
 
-#[inline]
-fn handle_res(res: Res) -> Result<Vec<Op<'static>>, ()> {
-    match res {
-        Res::Normal(ops) => {
-            // TODO: Output
-            Ok(map_ops(ops))
-        }
-        Res::OverwriteContents(ops) => {
-            // TODO: Output
-            Ok(map_ops(ops))
-        }
-        Res::OverwriteFile(ops) => {
-            // TODO: Output
-            Ok(map_ops(ops))
-        }
-        Res::Skip(_skip) => {
-            // TODO: Output
-            Ok(vec![])
+    #[inline]
+    fn handle_generated_res(
+        &self,
+        res: Res,
+        path: &CtxPath,
+        dest: &Path,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        match res {
+            Res::Normal(ops) => {
+                // TODO: Output
+                Ok(map_ops(ops))
+            }
+            Res::OverwriteContents(ops) | Res::OverwriteFile(ops) => {
+                self.drifted(path, dest);
+                if let Some(backup) = super::write::backup(&ops, dest) {
+                    super::write::backing_up(dest, backup, self.opts.paths.home());
+                }
+                // TODO: Output
+                Ok(map_ops(ops))
+            }
+            Res::Skip(generated::Skip::Conflict) => {
+                super::write::skipping_conflict(dest, self.opts.paths.home());
+                Ok(vec![])
+            }
+            Res::Skip(_skip) => {
+                // TODO: Output
+                Ok(vec![])
+            }
+            Res::Conflict => {
+                super::write::conflict(dest, self.opts.paths.home());
+                Err(())
+            }
         }
     }
 }
@@ -89,21 +128,29 @@ fn map_ops(ops: Vec<generated::Op>) -> Vec<Op<'static>> {
     ops.into_iter()
         .map(|op| match op {
             generated::Op::Rm(op) => Op::Rm(op),
+            generated::Op::Copy(op) => Op::Copy(op),
             generated::Op::Create(op) => Op::Create(op),
             generated::Op::Write(op) => Op::Write(op),
             generated::Op::Mkdir(op) => Op::Mkdir(op),
+            generated::Op::Chmod(op) => Op::Chmod(op),
+            #[cfg(unix)]
+            generated::Op::Chown(op) => Op::Chown(op),
         })
         .collect()
 }
 
 mod output {
+    use std::fmt::Display;
     use std::path::Path;
 
-    use shelflib::action::{JsonAction, TomlAction, YamlAction};
+    use shelflib::action::{generated::schema, JsonAction, TomlAction, YamlAction};
 
     use super::super::{describe, Describe, DescribeMode};
     use crate::ctxpath::CtxPath;
-    use crate::output::{comb::sjoin4, Pretty};
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        spath, Pretty, Step,
+    };
 
     impl Describe for YamlAction {
         #[inline]
@@ -115,7 +162,7 @@ mod output {
     impl Describe for TomlAction {
         #[inline]
         fn describe(&self, _path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
-            common_describe("json", &self.dest, dest, mode)
+            common_describe("toml", &self.dest, dest, mode)
         }
     }
 
@@ -141,4 +188,51 @@ mod output {
             describe::mode_spath(action_dest, mode),
         )
     }
+
+    #[inline]
+    pub fn serialize_error<A>(err: impl Display, action: &A, path: &CtxPath, dest: &Path)
+    where
+        A: Describe,
+    {
+        Step::error()
+            .message("couldn't serialize values")
+            .reason(err)
+            .context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn schema_error<A>(err: schema::Error, action: &A, path: &CtxPath, dest: &Path)
+    where
+        A: Describe,
+    {
+        match err {
+            schema::Error::Io(err) => {
+                Step::error().message("couldn't read schema").reason(err);
+            }
+            schema::Error::Parse(err) => {
+                Step::error().message("couldn't parse schema").reason(err);
+            }
+            schema::Error::Serde(err) => {
+                Step::error()
+                    .message("couldn't serialize values")
+                    .reason(err);
+            }
+            schema::Error::Compile(err) => {
+                Step::error().message("invalid schema").reason(err);
+            }
+            schema::Error::Invalid(failures) => {
+                Step::error().message("values don't satisfy the schema");
+                for schema::Failure { pointer, message } in failures {
+                    let pointer = if pointer.is_empty() {
+                        "/".to_string()
+                    } else {
+                        pointer
+                    };
+                    Step::error().reason(sjoin2(spath(pointer), message));
+                }
+            }
+        }
+
+        Step::error().context(action.describe_info(path, dest));
+    }
 }
@@ -0,0 +1,68 @@
+use super::GraphProcessor;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    /// If hermetic, check for directives that would run hooks or external programs, or read
+    /// outside of their packages and the destination roots, before anything is processed, and
+    /// report all of them at once.
+    #[inline]
+    pub fn check_hermetic(&self) -> Result<(), ()> {
+        if !self.opts.hermetic {
+            return Ok(());
+        }
+
+        let unhermetic = self.graph.unhermetic(&self.opts.paths);
+        if unhermetic.is_empty() {
+            return Ok(());
+        }
+
+        output::unhermetic_found(unhermetic.len());
+        for uh in &unhermetic {
+            output::unhermetic(uh, self.paths);
+        }
+
+        Err(())
+    }
+}
+
+mod output {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use shelflib::graph::{Unhermetic, UnhermeticKind};
+
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{pretty, sjoin2, sjoin3},
+        spath, Section, Step,
+    };
+
+    #[inline]
+    pub fn unhermetic_found(count: usize) {
+        Section::error().message(sjoin3(
+            "found",
+            count,
+            "directive(s) that can't be applied hermetically; nothing was changed",
+        ));
+    }
+
+    #[inline]
+    pub fn unhermetic(uh: &Unhermetic, paths: &HashMap<PathBuf, CtxPath>) {
+        let package = match paths.get(&uh.package) {
+            Some(path) => spath(path.rel()),
+            None => spath(&uh.package),
+        };
+        let message = match &uh.kind {
+            UnhermeticKind::Hook => pretty("runs a hook"),
+            UnhermeticKind::Program(program) => sjoin2("runs", format!("'{}'", program)),
+            UnhermeticKind::Read(src) => sjoin3(
+                "reads",
+                spath(src),
+                "outside of the package and the destination",
+            ),
+        };
+
+        Step::error()
+            .message(message)
+            .context(sjoin2("package", package));
+    }
+}
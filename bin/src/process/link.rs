@@ -1,24 +1,62 @@
+use std::path::Path;
+
 use shelflib::{
     action::{
+        conflict::ConflictPolicy,
         link::{self, Error, Res},
         LinkAction, Resolve,
     },
-    op::Op,
+    op::{self, layout, Op},
 };
 
-use super::GraphProcessor;
+use super::{GraphProcessor, WarningKind};
 use crate::ctxpath::CtxPath;
 
 impl<'p, 'g> GraphProcessor<'p, 'g> {
     #[inline]
-    pub fn resolve_link(&self, action: LinkAction, path: &CtxPath) -> Result<Vec<Op<'static>>, ()> {
-        output::processing_link(&action, path, &self.opts.dest);
+    pub fn resolve_link(
+        &self,
+        action: &LinkAction,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        let parent = action.dest.parent().unwrap_or(&action.dest);
+        let copied;
+        let action = if !action.copy && !action.hardlink && self.degrade(&action.dest, parent, path)
+        {
+            copied = LinkAction {
+                copy: true,
+                ..action.clone()
+            };
+            &copied
+        } else {
+            action
+        };
+
+        // A directory of links from older runs is shelf's own, so it is converted below regardless
+        // of the conflict policy.
+        let replacing;
+        let action =
+            if action.on_conflict != ConflictPolicy::Replace && self.unlink_dir(action).is_some() {
+                replacing = LinkAction {
+                    on_conflict: ConflictPolicy::Replace,
+                    ..action.clone()
+                };
+                &replacing
+            } else {
+                action
+            };
+
+        output::processing_link(action, path, self.opts.paths.home());
 
         let res = match action.resolve() {
             Ok(res) => res,
             Err(err) => {
                 match err {
-                    Error::SrcMissing => output::src_missing(&action, path, &self.opts.dest),
+                    Error::SrcMissing => output::src_missing(action, path, self.opts.paths.home()),
+                    Error::Conflict => super::write::conflict(&action.dest, self.opts.paths.home()),
+                    Error::ParentMissing => {
+                        super::write::parent_missing(&action.dest, self.opts.paths.home())
+                    }
                 }
 
                 return Err(());
@@ -28,15 +66,83 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
         match res {
             Res::Normal(ops) => Ok(map_ops(ops)),
             Res::Overwrite(ops) => {
-                output::overwriting(&action, path, &self.opts.dest);
+                // A directory of links from older runs is converted, rather than overwritten.
+                if let Some(mut unlink) = self.unlink_dir(action) {
+                    output::converting(action, path);
+                    let ops = ops
+                        .into_iter()
+                        .filter(|op| !matches!(op, link::Op::Rm(_)))
+                        .collect();
+                    unlink.extend(map_ops(ops));
+                    return Ok(unlink);
+                }
+
+                self.drifted(path, &action.dest);
+                if let Some(backup) = backup(&ops, &action.dest) {
+                    super::write::backing_up(&action.dest, backup, self.opts.paths.home());
+                }
+                output::overwriting(action, path, self.opts.paths.home());
                 Ok(map_ops(ops))
             }
             Res::Skip(skip) => {
-                output::skipping(&skip, &action, path, &self.opts.dest);
+                output::skipping(&skip, action, path, self.opts.paths.home());
                 Ok(vec![])
             }
         }
     }
+
+    /// Return true if links to `dest` of the package at `path`, made in the directory `parent`,
+    /// should be copies instead, because the filesystem can't hold symlinks, e.g. FAT or some SMB
+    /// mounts. Each such degradation is warned about and counted for the final summary.
+    ///
+    /// Support is probed in the nearest existing ancestor of `parent`, once per directory.
+    #[inline]
+    pub fn degrade(&self, dest: &Path, parent: &Path, path: &CtxPath) -> bool {
+        let dir = match parent.ancestors().find(|dir| dir.is_dir()) {
+            Some(dir) => dir,
+            None => return false,
+        };
+
+        let supported = *self
+            .symlink_support
+            .borrow_mut()
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| op::link::symlinks_supported(dir));
+        if supported != Some(false) {
+            return false;
+        }
+
+        self.degraded.set(self.degraded.get() + 1);
+        self.warn(
+            WarningKind::Degraded,
+            Some(path.abs()),
+            Some(dest),
+            "destination can't hold symlinks, and is copied instead",
+        );
+        output::degrading(dest, self.opts.paths.home());
+        true
+    }
+
+    /// Return the ops that remove the destination of `action`, if it is a directory holding only
+    /// links that shelf made, so that it can be replaced by a single link.
+    #[inline]
+    fn unlink_dir(&self, action: &LinkAction) -> Option<Vec<Op<'static>>> {
+        if action.copy || action.hardlink {
+            return None;
+        }
+
+        let links = self.journal.links_in_place();
+        layout::unlink_dir(&action.dest, &links)
+    }
+}
+
+/// Return the path that `ops`, which overwrite `dest`, first back it up to, if any.
+#[inline]
+pub fn backup<'a>(ops: &'a [link::Op], dest: &Path) -> Option<&'a Path> {
+    match ops.first() {
+        Some(link::Op::Copy(op)) if op.src == dest => Some(&op.dest),
+        _ => None,
+    }
 }
 
 #[inline]
@@ -46,7 +152,11 @@ pub fn map_ops(ops: Vec<link::Op>) -> Vec<Op<'static>> {
             link::Op::Rm(op) => Op::Rm(op),
             link::Op::Link(op) => Op::Link(op),
             link::Op::Copy(op) => Op::Copy(op),
+            link::Op::Hardlink(op) => Op::Hardlink(op),
             link::Op::Mkdir(op) => Op::Mkdir(op),
+            link::Op::Chmod(op) => Op::Chmod(op),
+            #[cfg(unix)]
+            link::Op::Chown(op) => Op::Chown(op),
         })
         .collect()
 }
@@ -66,7 +176,13 @@ mod output {
     impl Describe for LinkAction {
         #[inline]
         fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
-            let verb = if self.copy { "copying"} else {"linking"};
+            let verb = if self.copy {
+                "copying"
+            } else if self.hardlink {
+                "hardlinking"
+            } else {
+                "linking"
+            };
             let src = describe::path_relative(&self.src, path);
             let dest = describe::dest_relative(&self.dest, dest);
             sjoin4(
@@ -101,6 +217,22 @@ mod output {
         Step::warning().context(action.describe_info(path, dest));
     }
 
+    #[inline]
+    pub fn degrading(link: &Path, dest: &Path) {
+        Step::warning().message(sjoin2(
+            "filesystem can't hold symlinks; copying instead to",
+            describe::sdest_relative(link, dest),
+        ));
+    }
+
+    #[inline]
+    pub fn converting(action: &LinkAction, path: &CtxPath) {
+        Step::message(sjoin2(
+            "replacing links from an earlier run in",
+            describe::spath_relative(&action.dest, path),
+        ));
+    }
+
     #[inline]
     pub fn skipping(skip: &Skip, action: &LinkAction, path: &CtxPath, dest: &Path) {
         let message = match skip {
@@ -116,6 +248,14 @@ mod output {
                 "existing destination",
                 describe::sdest_relative(&action.dest, dest),
             ),
+            Skip::Conflict => sjoin2(
+                "differing destination",
+                describe::sdest_relative(&action.dest, dest),
+            ),
+            Skip::ParentMissing => sjoin2(
+                "missing parent of",
+                describe::sdest_relative(&action.dest, dest),
+            ),
         };
 
         Step::skipping().message(message);
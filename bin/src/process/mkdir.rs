@@ -13,8 +13,8 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
     #[inline]
     pub fn resolve_mkdir(
         &self,
-        action: MkdirAction,
-        _path: &CtxPath,
+        action: &MkdirAction,
+        path: &CtxPath,
     ) -> Result<Vec<Op<'static>>, ()> {
         let res = action.resolve();
         match res {
@@ -23,6 +23,7 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
                 Ok(map_ops(ops))
             }
             Res::Overwrite(ops) => {
+                self.drifted(path, &action.path);
                 // TODO: Output
                 Ok(map_ops(ops))
             }
@@ -40,6 +41,9 @@ fn map_ops(ops: Vec<mkdir::Op>) -> Vec<Op<'static>> {
         .map(|op| match op {
             mkdir::Op::Rm(op) => Op::Rm(op),
             mkdir::Op::Mkdir(op) => Op::Mkdir(op),
+            mkdir::Op::Chmod(op) => Op::Chmod(op),
+            #[cfg(unix)]
+            mkdir::Op::Chown(op) => Op::Chown(op),
         })
         .collect()
 }
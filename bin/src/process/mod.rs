@@ -1,8 +1,26 @@
+mod checkpoint;
 mod command;
+mod conflict;
+mod copydir;
+mod defaults;
+mod escape;
+mod estimate;
+mod expect;
+mod fragment;
 mod function;
 mod generated;
+mod hermetic;
 mod link;
 mod mkdir;
+mod original;
+mod perms;
+mod plugin;
+mod readonly;
+mod registry;
+mod report;
+mod script;
+mod sourceline;
+mod systemd;
 mod template;
 mod tree;
 mod write;
@@ -12,32 +30,187 @@ mod op;
 mod describe;
 mod output;
 
+use std::cell::{Cell, RefCell};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
 use std::{collections::HashMap, path::Path};
 
+use serde::Serialize;
 use shelflib::{
-    action::Action,
-    graph::{PackageData, PackageGraph},
-    op::{ctx::FinishCtx, journal::OpJournal},
+    action::{conflict::ConflictPolicy, Action},
+    graph::{DestFilter, PackageData, PackageGraph, PathResolver, Selector},
+    op::{ctx::FinishCtx, journal::OpJournal, sink::OpSink},
+    originals::OriginalStore,
+    spec::Object,
+    state::{ApplyResult, Checkpoint, PackageState, StateStore},
 };
 
 use crate::ctxpath::CtxPath;
 use crate::output::Pretty;
 
 pub(self) use self::describe::{Describe, DescribeMode};
+use self::report::{OpStatus, PackageReport};
+
+pub use self::estimate::Estimate;
+pub use self::report::{now, run_time, RunReport};
 
 #[derive(Debug, Clone)]
 pub struct ProcessorOptions {
     pub noop: bool,
-    pub dest: PathBuf,
+    /// Resolver of destinations, relative to the home directory.
+    pub paths: PathResolver,
+    /// Policy for permissive modes on security-sensitive destinations.
+    pub perms: PermsPolicy,
+    /// Policy for destinations that escape the destination roots through symlinked parents.
+    pub escapes: EscapePolicy,
+    /// Policy for existing destinations of directives that don't specify their own.
+    pub conflict: ConflictPolicy,
+    /// If set, existing destinations are replaced regardless of conflict policies.
+    pub force: bool,
+    /// Profiles whose directives are applied along with those that belong to no profile.
+    pub profiles: Vec<String>,
+    /// If set, each op is described and only finished once confirmed on stdin.
+    pub interactive: bool,
+    /// Message describing why the packages are applied, recorded with the commits in the journal
+    /// and the last applied state.
+    pub message: Option<String>,
+    /// Roots, other than those of `paths`, that destinations may resolve into.
+    pub allowed_roots: Vec<PathBuf>,
+    /// Store of last applied metadata; if absent, it is neither displayed nor recorded.
+    pub state: Option<StateStore>,
+    /// Store of the originals of destinations; if absent, none are kept.
+    pub originals: Option<OriginalStore>,
+    /// Directive selections of packages, keyed by package path; packages without an entry are
+    /// applied in full.
+    pub selections: HashMap<PathBuf, Vec<Selector>>,
+    /// If present, only actions with matching destinations are applied.
+    pub only: Option<DestFilter>,
+    /// Shell for command hooks that don't specify one; if absent, the platform default is used.
+    pub shell: Option<String>,
+    /// If set, hooks are skipped.
+    pub no_hooks: bool,
+    /// If set, nothing is processed if a directive would run hooks or external programs, or read
+    /// outside of its package and the destination roots.
+    pub hermetic: bool,
+    /// Command-line arguments of the run, recorded in a checkpoint on failure so that it can be
+    /// resumed; if absent, no checkpoint is recorded.
+    pub args: Option<Vec<String>>,
+    /// Checkpoint of the failed apply to resume from, if resuming.
+    pub resume: Option<Checkpoint>,
+    /// Sinks given a record of each op that is finished, for auditing.
+    pub sinks: Vec<Arc<dyn OpSink>>,
 
     pub ctx: FinishCtx,
 }
 
+/// Policy for handling overly permissive modes on security-sensitive destinations (e.g. `~/.ssh`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermsPolicy {
+    /// Skip the check.
+    Ignore,
+    /// Warn about violations.
+    Warn,
+    /// Warn about and fix violations.
+    Fix,
+}
+
+/// Policy for handling destinations whose parent directories are symlinks leading out of the
+/// destination roots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapePolicy {
+    /// Skip the check.
+    Ignore,
+    /// Warn about escaping destinations.
+    Warn,
+    /// Refuse to process any package if a destination escapes.
+    Error,
+}
+
+/// Summary of the classes of issues encountered while processing, other than errors.
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    /// Number of destination conflicts between packages.
+    pub conflicts: usize,
+    /// Number of existing destinations that differed from what was expected and were overwritten.
+    pub drift: usize,
+    /// Number of links copied instead, because their destinations can't hold symlinks.
+    pub degraded: usize,
+    /// Warnings collected while processing, in order.
+    pub warnings: Vec<Warning>,
+    /// Estimate of the work that applying would do; only present when pretending.
+    pub estimate: Option<Estimate>,
+}
+
+impl Summary {
+    /// Add the issues of `other`, e.g. from processing packages of another scope.
+    #[inline]
+    pub fn merge(&mut self, other: Summary) {
+        self.conflicts += other.conflicts;
+        self.drift += other.drift;
+        self.degraded += other.degraded;
+        self.warnings.extend(other.warnings);
+        self.estimate = match (self.estimate.take(), other.estimate) {
+            (Some(mut estimate), Some(other)) => {
+                estimate.merge(other);
+                Some(estimate)
+            }
+            (estimate, other) => estimate.or(other),
+        };
+    }
+}
+
+/// A warning encountered while processing, collected for the final summary.
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    /// Absolute path of the package concerned, if any.
+    pub package: Option<PathBuf>,
+    /// Path concerned, if any.
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// Destination conflict between packages.
+    Conflict,
+    /// Existing destination that differed from what was expected.
+    Drift,
+    /// Destination that escapes through symlinked parent directories.
+    Escape,
+    /// Permissive mode on a security-sensitive destination.
+    Perms,
+    /// Failure to read or record last applied state.
+    State,
+    /// Failure to record a finished op in an audit sink.
+    Audit,
+    /// Link copied instead, because its destination can't hold symlinks.
+    Degraded,
+}
+
+impl WarningKind {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Conflict => "conflict",
+            Self::Drift => "drift",
+            Self::Escape => "escape",
+            Self::Perms => "perms",
+            Self::State => "state",
+            Self::Audit => "audit",
+            Self::Degraded => "degraded",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Processor<'j> {
     opts: ProcessorOptions,
     journal: &'j mut OpJournal,
+    /// Record of the last processing, for the report file.
+    report: Option<RunReport>,
 }
 
 #[derive(Debug)]
@@ -47,12 +220,46 @@ pub(self) struct GraphProcessor<'p, 'g> {
 
     graph: &'g PackageGraph,
     paths: &'g HashMap<PathBuf, CtxPath>,
+
+    drift: Cell<usize>,
+    degraded: Cell<usize>,
+    warnings: RefCell<Vec<Warning>>,
+    /// Whether directories can hold symlinks, probed once each; `None` if that couldn't be
+    /// determined.
+    symlink_support: RefCell<HashMap<PathBuf, Option<bool>>>,
+    /// Whether an op of the package being processed has changed the filesystem.
+    changed: bool,
+    /// Destinations changed by ops of the package being processed, in order.
+    changed_paths: Vec<PathBuf>,
+    /// Whether all further ops have been confirmed, if confirming interactively.
+    confirmed_all: bool,
+    /// Whether all further ops have been declined, if confirming interactively.
+    quit: bool,
+    /// Work that would be done, collected when pretending.
+    estimate: Estimate,
+    /// Progress through the package being processed, recorded in a checkpoint on failure.
+    progress: Progress,
+    /// Record of the processed packages, for the report file.
+    report: Vec<PackageReport>,
+}
+
+/// Progress through the actions of a package.
+#[derive(Debug, Clone, Copy, Default)]
+struct Progress {
+    /// Number of actions applied successfully.
+    actions: usize,
+    /// Number of ops of the current action applied successfully.
+    ops: usize,
 }
 
 impl<'j> Processor<'j> {
     #[inline]
     pub fn new(opts: ProcessorOptions, journal: &'j mut OpJournal) -> Self {
-        Self { opts, journal }
+        Self {
+            opts,
+            journal,
+            report: None,
+        }
     }
 
     #[inline]
@@ -60,9 +267,35 @@ impl<'j> Processor<'j> {
         &mut self,
         graph: &PackageGraph,
         paths: &HashMap<PathBuf, CtxPath>,
-    ) -> Result<(), ()> {
+    ) -> Result<Summary, ()> {
+        let started_at = report::run_time().to_rfc3339();
+        let start = Instant::now();
+
+        crate::output::set_category(crate::output::Category::Process);
         let mut processor = GraphProcessor::new(&self.opts, self.journal, graph, paths);
-        processor.process()
+        let res = processor.process();
+        crate::output::set_category(crate::output::Category::General);
+
+        let warnings = match &res {
+            Ok(summary) => summary.warnings.clone(),
+            Err(_) => processor.warnings.take(),
+        };
+        self.report = Some(RunReport {
+            started_at,
+            duration_ms: report::duration_millis(start.elapsed()),
+            success: res.is_ok(),
+            noop: self.opts.noop,
+            packages: processor.report,
+            warnings,
+        });
+
+        res
+    }
+
+    /// Return the record of the last processing, if any.
+    #[inline]
+    pub fn into_report(self) -> Option<RunReport> {
+        self.report
     }
 }
 
@@ -79,19 +312,92 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
             journal,
             graph,
             paths,
+            drift: Cell::new(0),
+            degraded: Cell::new(0),
+            warnings: RefCell::new(Vec::new()),
+            symlink_support: RefCell::new(HashMap::new()),
+            changed: false,
+            changed_paths: Vec::new(),
+            confirmed_all: false,
+            quit: false,
+            estimate: Estimate::default(),
+            progress: Progress::default(),
+            report: Vec::new(),
         }
     }
+
+    /// Record that the existing destination `dest` of the package at `path` differed from what was
+    /// expected.
+    #[inline]
+    pub fn drifted(&self, path: &CtxPath, dest: &Path) {
+        self.drift.set(self.drift.get() + 1);
+        self.warn(
+            WarningKind::Drift,
+            Some(path.abs()),
+            Some(dest),
+            "existing destination differed from what was expected, and is overwritten",
+        );
+    }
+
+    /// Collect a warning for the final summary.
+    #[inline]
+    pub fn warn(
+        &self,
+        kind: WarningKind,
+        package: Option<&Path>,
+        path: Option<&Path>,
+        message: impl Into<String>,
+    ) {
+        self.warnings.borrow_mut().push(Warning {
+            kind,
+            package: package.map(Path::to_path_buf),
+            path: path.map(Path::to_path_buf),
+            message: message.into(),
+        });
+    }
 }
 
 impl<'p, 'g> GraphProcessor<'p, 'g> {
     #[inline]
-    pub fn process(&mut self) -> Result<(), ()> {
+    pub fn process(&mut self) -> Result<Summary, ()> {
         match self.graph.order() {
             Ok(order) => {
-                order
-                    .map(|pd| self.process_package(pd))
-                    .collect::<Result<Vec<_>, _>>()?;
-                Ok(())
+                let conflicts = self.report_conflicts();
+                self.check_escapes()?;
+                self.check_read_only()?;
+                self.check_hermetic()?;
+
+                let order: Vec<_> = order.collect();
+                let plan: Vec<_> = order.iter().map(|pd| pd.path.clone()).collect();
+                let resume = self.resume_index(&plan)?;
+
+                for (i, pd) in order.into_iter().enumerate() {
+                    // Skip what was applied before the failure that is being resumed from.
+                    let skip = match (resume, &self.opts.resume) {
+                        (Some(index), _) if i < index => continue,
+                        (Some(index), Some(checkpoint)) if i == index => checkpoint.actions,
+                        _ => 0,
+                    };
+
+                    if self.process_package(pd, skip).is_err() {
+                        self.record_checkpoint(plan, pd);
+                        return Err(());
+                    }
+                }
+                self.process_sensitive_perms()?;
+                self.clear_checkpoint();
+
+                Ok(Summary {
+                    conflicts,
+                    drift: self.drift.get(),
+                    degraded: self.degraded.get(),
+                    warnings: self.warnings.take(),
+                    estimate: if self.opts.noop {
+                        Some(std::mem::take(&mut self.estimate))
+                    } else {
+                        None
+                    },
+                })
             }
             Err(err) => {
                 output::error_circular(err);
@@ -101,18 +407,138 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
     }
 
     #[inline]
-    pub fn process_package(&mut self, pd: &PackageData) -> Result<(), ()> {
+    pub fn process_package(&mut self, pd: &PackageData, skip: usize) -> Result<(), ()> {
         // SAFETY: Path guaranteed to be in it by `load`.
         let path = self.paths.get(&pd.path).unwrap();
 
         output::processing(path);
+        self.report_package(&pd.path);
+        crate::output::set_annotation_file(Some(path.rel().join("package.lua")));
+        self.changed = false;
+        self.changed_paths.clear();
+        let mut previous_vars = None;
+        if let Some(store) = &self.opts.state {
+            match store.get(&pd.path) {
+                Ok(state) => {
+                    output::last_applied(state.as_ref());
+                    previous_vars = state.and_then(|state| state.vars);
+                }
+                Err(_) => {
+                    output::state_read_error(path);
+                    self.warn(
+                        WarningKind::State,
+                        Some(&pd.path),
+                        None,
+                        "couldn't read last applied state",
+                    );
+                }
+            }
+        }
 
-        let aiter = pd.action_iter(&self.opts.dest);
-        aiter
-            .map(|action| self.process_action(action, path, &self.opts.dest))
-            .collect::<Result<Vec<_>, _>>()?;
+        let selectors = self
+            .opts
+            .selections
+            .get(&pd.path)
+            .cloned()
+            .unwrap_or_default();
+        let partial = !selectors.is_empty() || self.opts.only.is_some();
+        if partial {
+            output::partial();
+        }
 
-        Ok(())
+        // Destinations are checked up front, rather than failing partway through the package.
+        if let Err(err) = pd.check_dests(&self.opts.paths) {
+            output::invalid_dest(&err);
+            self.record_state(pd, path, false, partial, previous_vars);
+            self.report_package_done(false);
+            crate::output::set_annotation_file(None);
+            return Err(());
+        }
+
+        let mut aiter = pd.action_iter(&self.opts.paths).select(selectors);
+        if let Some(only) = &self.opts.only {
+            aiter = aiter.only(only.clone());
+        }
+        if let Some(shell) = &self.opts.shell {
+            aiter = aiter.default_shell(shell.clone());
+        }
+        aiter = aiter
+            .on_conflict(self.opts.conflict)
+            .force(self.opts.force)
+            .profiles(self.opts.profiles.clone());
+
+        self.progress = Progress {
+            actions: skip,
+            ops: 0,
+        };
+        // Directives whose conditions don't hold, or whose profiles aren't selected, are still
+        // counted, so that checkpoints refer to the same positions.
+        let res = aiter
+            .checked()
+            .skip(skip)
+            .map(|(action, met)| {
+                self.progress.ops = 0;
+                let res = if met {
+                    self.process_action(action, path, self.opts.paths.home())
+                } else {
+                    output::skipping_condition(&action, path, self.opts.paths.home());
+                    Ok(())
+                };
+                if res.is_ok() {
+                    self.progress.actions += 1;
+                }
+                res
+            })
+            .collect::<Result<Vec<_>, _>>();
+
+        self.record_state(pd, path, res.is_ok(), partial, previous_vars);
+        self.report_package_done(res.is_ok());
+        crate::output::set_annotation_file(None);
+        res.map(|_| ())
+    }
+
+    /// Record the last applied metadata of the package, unless pretending. Template variables are
+    /// only replaced if every directive was applied successfully; otherwise, `previous_vars` are
+    /// kept.
+    #[inline]
+    fn record_state(
+        &self,
+        pd: &PackageData,
+        path: &CtxPath,
+        success: bool,
+        partial: bool,
+        previous_vars: Option<Object>,
+    ) {
+        let store = match &self.opts.state {
+            Some(store) if !self.opts.noop => store,
+            _ => return,
+        };
+
+        let state = PackageState {
+            applied_at: report::now(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            result: if success {
+                ApplyResult::Success
+            } else {
+                ApplyResult::Failure
+            },
+            partial,
+            vars: if success && !partial {
+                Some(pd.spec.template_vars())
+            } else {
+                previous_vars
+            },
+            message: self.opts.message.clone(),
+        };
+        if store.insert(&pd.path, &state).is_err() {
+            output::state_write_error(path);
+            self.warn(
+                WarningKind::State,
+                Some(&pd.path),
+                None,
+                "couldn't record applied state",
+            );
+        }
     }
 
     #[inline]
@@ -122,22 +548,70 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
         path: &CtxPath,
         dest: &Path,
     ) -> Result<(), ()> {
-        let ops = match action.clone() {
+        let hook = matches!(
+            action,
+            Action::Command(_) | Action::Function(_) | Action::Script(_)
+        );
+        if hook && self.opts.no_hooks {
+            output::skipping_hook(&action, path, dest);
+            return Ok(());
+        }
+
+        let only_if_changed = match &action {
+            Action::Command(action) => action.only_if_changed,
+            Action::Function(action) => action.only_if_changed,
+            Action::Script(action) => action.only_if_changed,
+            _ => false,
+        };
+        if only_if_changed && !self.changed {
+            output::skipping_unchanged(&action, path, dest);
+            return Ok(());
+        }
+
+        let ops = match &action {
             Action::Link(action) => self.resolve_link(action, path),
             Action::Write(action) => self.resolve_write(action, path),
+            Action::Fragment(action) => self.resolve_fragment(action, path),
+            Action::SourceLine(action) => self.resolve_source_line(action, path),
+            Action::Defaults(action) => self.resolve_defaults(action, path),
+            Action::RegValue(action) => self.resolve_reg_value(action, path),
+            // Each directive of the plugin is processed in turn, like any other.
+            Action::Plugin(action) => return self.process_plugin(action, path, dest),
+            Action::Expect(action) => self.resolve_expect(action, path),
             Action::Mkdir(action) => self.resolve_mkdir(action, path),
+            Action::SystemdUnit(action) => self.resolve_systemd_unit(action, path),
+            Action::SensitivePerms(action) => self.resolve_sensitive_perms(action, path),
             Action::Tree(action) => self.resolve_tree(action, path),
+            Action::CopyDir(action) => self.resolve_copy_dir(action, path),
             Action::Handlebars(action) => self.resolve_handlebars(action, path),
             Action::Liquid(action) => self.resolve_liquid(action, path),
+            Action::Gotmpl(action) => self.resolve_gotmpl(action, path),
+            Action::Pipe(action) => self.resolve_pipe(action, path),
             Action::Yaml(action) => self.resolve_yaml(action, path),
             Action::Toml(action) => self.resolve_toml(action, path),
             Action::Json(action) => self.resolve_json(action, path),
             Action::Command(action) => self.resolve_command(action, path),
             Action::Function(action) => self.resolve_function(action, path),
+            Action::Script(action) => self.resolve_script(action, path),
         }?;
 
+        if self.opts.noop {
+            for op in &ops {
+                output::would_run(op, path, dest);
+                self.report_op(op, OpStatus::Pretended, None);
+            }
+            self.estimate(&ops, path.abs());
+            return Ok(());
+        }
+
         ops.into_iter()
-            .map(|op| self.process_op(&action, op, path, dest))
+            .map(|op| {
+                let res = self.process_op(&action, op, path, dest);
+                if res.is_ok() {
+                    self.progress.ops += 1;
+                }
+                res
+            })
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(())
@@ -150,15 +624,27 @@ impl<'lua> Describe for Action<'lua> {
         match self {
             Action::Link(action) => action.describe(path, dest, mode),
             Action::Write(action) => action.describe(path, dest, mode),
+            Action::Fragment(action) => action.describe(path, dest, mode),
+            Action::SourceLine(action) => action.describe(path, dest, mode),
+            Action::Defaults(action) => action.describe(path, dest, mode),
+            Action::RegValue(action) => action.describe(path, dest, mode),
+            Action::Plugin(action) => action.describe(path, dest, mode),
+            Action::Expect(action) => action.describe(path, dest, mode),
             Action::Tree(action) => action.describe(path, dest, mode),
+            Action::CopyDir(action) => action.describe(path, dest, mode),
             Action::Handlebars(action) => action.describe(path, dest, mode),
             Action::Liquid(action) => action.describe(path, dest, mode),
+            Action::Gotmpl(action) => action.describe(path, dest, mode),
+            Action::Pipe(action) => action.describe(path, dest, mode),
             Action::Yaml(action) => action.describe(path, dest, mode),
             Action::Toml(action) => action.describe(path, dest, mode),
             Action::Json(action) => action.describe(path, dest, mode),
             Action::Mkdir(action) => action.describe(path, dest, mode),
+            Action::SystemdUnit(action) => action.describe(path, dest, mode),
+            Action::SensitivePerms(action) => action.describe(path, dest, mode),
             Action::Command(action) => action.describe(path, dest, mode),
             Action::Function(action) => action.describe(path, dest, mode),
+            Action::Script(action) => action.describe(path, dest, mode),
         }
     }
 }
@@ -1,28 +1,47 @@
 use std::path::Path;
 
+#[cfg(unix)]
+use shelflib::op::{chown::ChownOpError, error::ChownError, ChownOp, ChownUndoOp};
+#[cfg(all(windows, feature = "registry"))]
+use shelflib::op::{error::RegistryError, registry::RegistryOpError, RegistryOp, RegistryUndoOp};
 use shelflib::{
     action::Action,
+    journal::Record,
     op::{
+        chmod::ChmodOpError,
         copy::{CopyOpError, CopyUndoOpError},
+        copydir::{CopyDirOpError, CopyDirUndoOpError},
         create::{CreateOpError, CreateUndoOpError},
+        ctx::Retried,
+        defaults::DefaultsOpError,
         error::{
-            CopyError, CreateError, MetadataError, MkdirError, MoveError, OpenError, ReadError,
-            ReadLinkError, RemoveError, RenameError, SymlinkError, WriteError,
+            ChmodError, CompressError, CopyError, CreateError, DecompressError, DefaultsError,
+            ExecError, HardlinkError, MetadataError, MkdirError, MoveError, OpenError, ReadError,
+            ReadLinkError, RemoveError, RenameError, SymlinkError, SystemctlError, WriteError,
         },
-        journal::JournalOpFinish,
-        link::{LinkOpError, LinkUndoOpError},
+        hardlink::{HardlinkFinish, HardlinkOpError, HardlinkUndoOpError},
+        journal::{JournalFileError, JournalOpFinish},
+        link::{LinkFinish, LinkOpError, LinkUndoOpError},
         mkdir::{MkdirOpError, MkdirUndoOpError},
         rm::{RmOpError, RmUndoOpError},
+        script::{ScriptFinish, ScriptOpError},
+        sink::{OpRecord, SinkError},
+        sourceline::{SourceLineOpError, SourceLineUndoOpError},
+        systemctl::SystemctlOpError,
         write::{WriteOpError, WriteUndoOpError},
-        CommandOp, CopyOp, CopyUndoOp, CreateOp, CreateUndoOp, Finish, FunctionOp, LinkOp,
-        LinkUndoOp, MkdirOp, MkdirUndoOp, Op, RmOp, RmUndoOp, WriteOp, WriteUndoOp,
+        ChmodOp, ChmodUndoOp, CommandOp, CopyDirOp, CopyDirUndoOp, CopyOp, CopyUndoOp, CreateOp,
+        CreateUndoOp, DefaultsOp, DefaultsUndoOp, Finish, FunctionOp, HardlinkOp, HardlinkUndoOp,
+        LinkOp, LinkUndoOp, MkdirOp, MkdirUndoOp, Op, RmOp, RmUndoOp, ScriptOp, SourceLineOp,
+        SourceLineUndoOp, SystemctlOp, SystemctlUndoOp, WriteOp, WriteUndoOp,
     },
 };
 
-use super::{describe, Describe, DescribeMode, GraphProcessor};
+use super::report::OpStatus;
+use super::{describe, output, Describe, DescribeMode, GraphProcessor, WarningKind};
 use crate::ctxpath::CtxPath;
 use crate::output::{
     comb::{pretty, sjoin2, sjoin3, sjoin4},
+    prompt::{self, Answer},
     spath, Pretty, Step,
 };
 
@@ -31,24 +50,65 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
     pub fn process_op<'lua>(
         &mut self,
         action: &Action<'lua>,
-        op: Op,
+        mut op: Op,
         path: &CtxPath,
         dest: &Path,
     ) -> Result<(), ()> {
-        // TODO: Lots of cloning :(
-        match op.clone() {
-            Op::Link(iop) => self.process_link_op(action, op, iop, path, dest),
-            Op::LinkUndo(iop) => self.process_link_undo_op(action, op, iop, path, dest),
-            Op::Copy(iop) => self.process_copy_op(action, op, iop, path, dest),
-            Op::CopyUndo(iop) => self.process_copy_undo_op(action, op, iop, path, dest),
-            Op::Create(iop) => self.process_create_op(action, op, iop, path, dest),
-            Op::CreateUndo(iop) => self.process_create_undo_op(action, op, iop, path, dest),
-            Op::Write(iop) => self.process_write_op(action, op, iop, path, dest),
-            Op::WriteUndo(iop) => self.process_write_undo_op(action, op, iop, path, dest),
-            Op::Mkdir(iop) => self.process_mkdir_op(action, op, iop, path, dest),
-            Op::MkdirUndo(iop) => self.process_mkdir_undo_op(action, op, iop, path, dest),
-            Op::Rm(iop) => self.process_rm_op(action, op, iop, path, dest),
-            Op::RmUndo(iop) => self.process_rm_undo_op(action, op, iop, path, dest),
+        if !self.confirm(&op, path, dest) {
+            self.report_op(&op, OpStatus::Declined, None);
+            return Ok(());
+        }
+
+        // Hooks may change anything, but are not counted as changes.
+        let hook = matches!(op, Op::Command(_) | Op::Function(_) | Op::Script(_));
+        // Hooks may write to stderr themselves, after the buffered messages.
+        if hook {
+            log::logger().flush();
+        }
+
+        self.keep_original(&op, dest)?;
+
+        let size = self.journal.size();
+
+        if let Op::Function(iop) = &mut op {
+            iop.changed = self.changed_paths.clone();
+        }
+
+        let res = match &op {
+            Op::Link(iop) => self.process_link_op(action, &op, iop, path, dest),
+            Op::LinkUndo(iop) => self.process_link_undo_op(action, &op, iop, path, dest),
+            Op::Copy(iop) => self.process_copy_op(action, &op, iop, path, dest),
+            Op::CopyUndo(iop) => self.process_copy_undo_op(action, &op, iop, path, dest),
+            Op::Hardlink(iop) => self.process_hardlink_op(action, &op, iop, path, dest),
+            Op::HardlinkUndo(iop) => self.process_hardlink_undo_op(action, &op, iop, path, dest),
+            Op::CopyDir(iop) => self.process_copy_dir_op(action, &op, iop, path, dest),
+            Op::CopyDirUndo(iop) => self.process_copy_dir_undo_op(action, &op, iop, path, dest),
+            Op::Create(iop) => self.process_create_op(action, &op, iop, path, dest),
+            Op::CreateUndo(iop) => self.process_create_undo_op(action, &op, iop, path, dest),
+            Op::Write(iop) => self.process_write_op(action, &op, iop, path, dest),
+            Op::WriteUndo(iop) => self.process_write_undo_op(action, &op, iop, path, dest),
+            Op::Mkdir(iop) => self.process_mkdir_op(action, &op, iop, path, dest),
+            Op::MkdirUndo(iop) => self.process_mkdir_undo_op(action, &op, iop, path, dest),
+            Op::Rm(iop) => self.process_rm_op(action, &op, iop, path, dest),
+            Op::RmUndo(iop) => self.process_rm_undo_op(action, &op, iop, path, dest),
+            Op::Systemctl(iop) => self.process_systemctl_op(action, &op, iop, path, dest),
+            Op::SystemctlUndo(iop) => self.process_systemctl_undo_op(action, &op, iop, path, dest),
+            Op::Chmod(iop) => self.process_chmod_op(action, &op, iop, path, dest),
+            Op::ChmodUndo(iop) => self.process_chmod_undo_op(action, &op, iop, path, dest),
+            #[cfg(unix)]
+            Op::Chown(iop) => self.process_chown_op(action, &op, iop, path, dest),
+            #[cfg(unix)]
+            Op::ChownUndo(iop) => self.process_chown_undo_op(action, &op, iop, path, dest),
+            Op::SourceLine(iop) => self.process_source_line_op(action, &op, iop, path, dest),
+            Op::SourceLineUndo(iop) => {
+                self.process_source_line_undo_op(action, &op, iop, path, dest)
+            }
+            Op::Defaults(iop) => self.process_defaults_op(action, &op, iop, path, dest),
+            Op::DefaultsUndo(iop) => self.process_defaults_undo_op(action, &op, iop, path, dest),
+            #[cfg(all(windows, feature = "registry"))]
+            Op::Registry(iop) => self.process_registry_op(action, &op, iop, path, dest),
+            #[cfg(all(windows, feature = "registry"))]
+            Op::RegistryUndo(iop) => self.process_registry_undo_op(action, &op, iop, path, dest),
             Op::Command(iop) => {
                 // TODO: Output
                 match iop.finish(&self.opts.ctx) {
@@ -62,33 +122,177 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
                     }
                 }
             }
-            Op::Function(iop) => {
-                // TODO: Output
-                match iop.finish(&self.opts.ctx) {
-                    Ok(_fin) => {
-                        // TODO: Output
-                        Ok(())
-                    }
-                    Err(_err) => {
-                        // TODO: Output
-                        Err(())
-                    }
+            Op::Function(iop) => self.process_function_op(action, iop, path, dest),
+            Op::Script(iop) => match iop.finish(&self.opts.ctx) {
+                Ok(fin) if fin.output.status.success() => Ok(()),
+                Ok(fin) => {
+                    emit_script_failed(&fin, action, &op, path, dest);
+                    Err(())
+                }
+                Err(ScriptOpError::Exec(err)) => {
+                    emit_exec_error(err, action, &op, path, dest);
+                    Err(())
+                }
+            },
+        };
+
+        // Ops requested by functions are journaled and reported on their own.
+        let size = if matches!(op, Op::Function(_)) {
+            self.journal.size()
+        } else {
+            size
+        };
+
+        // Records are persisted as soon as they are committed.
+        let res = res.and_then(|()| self.sync_journal());
+
+        // Each journaled op is committed in its own transaction.
+        let transaction = if self.journal.size() > size {
+            self.journal
+                .stamp(self.journal.size() - 1)
+                .map(|stamp| stamp.seq)
+        } else {
+            None
+        };
+        if res.is_ok() {
+            self.audit_ops(size, path);
+        }
+
+        let status = if res.is_ok() {
+            OpStatus::Applied
+        } else {
+            OpStatus::Failed
+        };
+        self.report_op(&op, status, transaction);
+
+        if res.is_ok() && !hook {
+            self.changed = true;
+            if let Some(changed) = changed_path(&op) {
+                if !self.changed_paths.iter().any(|p| p == changed) {
+                    self.changed_paths.push(changed.to_path_buf());
                 }
             }
         }
+        res
+    }
+
+    /// Return true if `op` should be finished: always, unless confirming interactively, in which
+    /// case it is described and asked about. Answering all or quit stops the asking, and finishes
+    /// or declines the rest of the ops.
+    #[inline]
+    fn confirm(&mut self, op: &Op, path: &CtxPath, dest: &Path) -> bool {
+        if !self.opts.interactive || self.confirmed_all {
+            return true;
+        }
+
+        let confirmed = !self.quit
+            && match prompt::confirm(op.describe_info(path, dest)) {
+                Answer::Yes => true,
+                Answer::No => false,
+                Answer::All => {
+                    self.confirmed_all = true;
+                    true
+                }
+                Answer::Quit => {
+                    self.quit = true;
+                    false
+                }
+            };
+        if !confirmed {
+            output::declined(op, path, dest);
+        }
+        confirmed
     }
 
+    /// Give the records of the ops finished since the journal had `size` records to the audit
+    /// sinks. Failures are only warned about, since the ops have already been applied.
     #[inline]
-    pub fn op_append_finish<O>(&mut self, op: O) -> Result<(), O::Error>
+    fn audit_ops(&self, size: usize, path: &CtxPath) {
+        if self.opts.sinks.is_empty() {
+            return;
+        }
+
+        for idx in size..self.journal.size() {
+            let (fin, stamp) = match (self.journal.get(idx), self.journal.stamp(idx)) {
+                (Some(Record::Atom(fin)), Some(stamp)) => (fin, stamp),
+                _ => continue,
+            };
+
+            let record = OpRecord::new(fin, stamp.seq, stamp.time, path.abs());
+            for sink in &self.opts.sinks {
+                if let Err(err) = sink.record(&record) {
+                    emit_audit_error(fin, err);
+                    self.warn(
+                        WarningKind::Audit,
+                        Some(path.abs()),
+                        fin.dest(),
+                        "couldn't record op in audit sink",
+                    );
+                }
+            }
+        }
+    }
+
+    /// Write the records appended to the journal since the last sync, if it is persisted.
+    #[inline]
+    fn sync_journal(&mut self) -> Result<(), ()> {
+        self.journal.sync().map_err(emit_journal_error)
+    }
+
+    #[inline]
+    pub fn op_append_finish<O>(&mut self, op: O) -> Result<(), Retried<O::Error>>
     where
         O: Finish,
         O::Output: Into<JournalOpFinish>,
+        O::Error: std::error::Error + 'static,
     {
         let mut t = self.journal.lock();
         t.append_finish(op, &self.opts.ctx).map(|_| ())
     }
 }
 
+/// Return the path changed by `op`, if any.
+#[inline]
+pub(super) fn changed_path<'a>(op: &'a Op<'_>) -> Option<&'a Path> {
+    let path = match op {
+        Op::Link(op) => &op.dest,
+        Op::LinkUndo(op) => &op.dest,
+        Op::Copy(op) => &op.dest,
+        Op::CopyUndo(op) => &op.dest,
+        Op::Hardlink(op) => &op.dest,
+        Op::HardlinkUndo(op) => &op.dest,
+        Op::CopyDir(op) => &op.dest,
+        Op::CopyDirUndo(op) => &op.dest,
+        Op::Create(op) => &op.path,
+        Op::CreateUndo(op) => &op.path,
+        Op::Write(op) => &op.path,
+        Op::WriteUndo(op) => &op.path,
+        Op::Mkdir(op) => &op.path,
+        Op::MkdirUndo(op) => &op.path,
+        Op::Rm(op) => &op.path,
+        Op::RmUndo(op) => &op.path,
+        Op::Chmod(op) => &op.path,
+        Op::ChmodUndo(op) => &op.path,
+        #[cfg(unix)]
+        Op::Chown(op) => &op.path,
+        #[cfg(unix)]
+        Op::ChownUndo(op) => &op.path,
+        Op::SourceLine(op) => &op.path,
+        Op::SourceLineUndo(op) => &op.path,
+        Op::Systemctl(_)
+        | Op::SystemctlUndo(_)
+        | Op::Defaults(_)
+        | Op::DefaultsUndo(_)
+        | Op::Command(_)
+        | Op::Function(_)
+        | Op::Script(_) => return None,
+        #[cfg(all(windows, feature = "registry"))]
+        Op::Registry(_) | Op::RegistryUndo(_) => return None,
+    };
+
+    Some(path)
+}
+
 macro_rules! process_op_impl {
     (
         $name:ident, $op_ty:ty, $action:ident, $op:ident, $iop:ident,
@@ -98,15 +302,21 @@ macro_rules! process_op_impl {
         pub fn $name<'lua>(
             &mut self,
             $action: &Action<'lua>,
-            $op: Op<'lua>,
-            $iop: $op_ty,
+            $op: &Op<'lua>,
+            $iop: &$op_ty,
             $path: &CtxPath,
             $dest: &Path,
         ) -> Result<(), ()> {
             match self.op_append_finish($iop) {
                 Ok(_) => Ok(()),
-                Err($err) => {
+                Err(Retried {
+                    inner: $err,
+                    attempts,
+                }) => {
                     $out;
+                    if attempts > 1 {
+                        emit_retried(attempts);
+                    }
                     Err(())
                 }
             }
@@ -116,11 +326,37 @@ macro_rules! process_op_impl {
 
 #[allow(unreachable_code)]
 impl<'p, 'g> GraphProcessor<'p, 'g> {
-    process_op_impl!(process_link_op, LinkOp,
-        action, op, iop, path, dest, err => match err {
-            LinkOpError::Symlink(err) => emit_symlink_error(err, action, op, path, dest)
+    #[inline]
+    pub fn process_link_op<'lua>(
+        &mut self,
+        action: &Action<'lua>,
+        op: &Op<'lua>,
+        iop: &LinkOp,
+        path: &CtxPath,
+        dest: &Path,
+    ) -> Result<(), ()> {
+        let mut t = self.journal.lock();
+        match t.append_finish(iop, &self.opts.ctx) {
+            Ok(fin) => {
+                if let JournalOpFinish::Link(fin) = fin {
+                    if fin.copied {
+                        emit_link_copied(fin, dest);
+                    }
+                }
+                Ok(())
+            }
+            Err(Retried { inner, attempts }) => {
+                match inner {
+                    LinkOpError::Symlink(err) => emit_symlink_error(err, action, op, path, dest),
+                    LinkOpError::Copy(err) => emit_copy_error(err, action, op, path, dest),
+                }
+                if attempts > 1 {
+                    emit_retried(attempts);
+                }
+                Err(())
+            }
         }
-    );
+    }
 
     process_op_impl!(process_link_undo_op, LinkUndoOp,
         action, op, iop, path, dest, err => match err {
@@ -140,6 +376,61 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
         }
     );
 
+    #[inline]
+    pub fn process_hardlink_op<'lua>(
+        &mut self,
+        action: &Action<'lua>,
+        op: &Op<'lua>,
+        iop: &HardlinkOp,
+        path: &CtxPath,
+        dest: &Path,
+    ) -> Result<(), ()> {
+        let mut t = self.journal.lock();
+        match t.append_finish(iop, &self.opts.ctx) {
+            Ok(fin) => {
+                if let JournalOpFinish::Hardlink(fin) = fin {
+                    emit_hardlinked(fin, dest);
+                }
+                Ok(())
+            }
+            Err(Retried { inner, attempts }) => {
+                match inner {
+                    HardlinkOpError::Hardlink(err) => {
+                        emit_hardlink_error(err, action, op, path, dest)
+                    }
+                    HardlinkOpError::Copy(err) => emit_copy_error(err, action, op, path, dest),
+                }
+                if attempts > 1 {
+                    emit_retried(attempts);
+                }
+                Err(())
+            }
+        }
+    }
+
+    process_op_impl!(process_hardlink_undo_op, HardlinkUndoOp,
+        action, op, iop, path, dest, err => match err {
+            HardlinkUndoOpError::Remove(err) => emit_remove_error(err, action, op, path, dest)
+        }
+    );
+
+    process_op_impl!(process_copy_dir_op, CopyDirOp,
+        action, op, iop, path, dest, err => match err {
+            CopyDirOpError::Copy(err) => emit_copy_error(err, action, op, path, dest),
+            CopyDirOpError::Mkdir(err) => emit_mkdir_error(err, action, op, path, dest),
+            CopyDirOpError::Read(err) => emit_read_error(err, action, op, path, dest),
+            CopyDirOpError::Metadata(err) => emit_metadata_error(err, action, op, path, dest),
+            CopyDirOpError::ReadLink(err) => emit_read_link_error(err, action, op, path, dest),
+            CopyDirOpError::Symlink(err) => emit_symlink_error(err, action, op, path, dest),
+        }
+    );
+
+    process_op_impl!(process_copy_dir_undo_op, CopyDirUndoOp,
+        action, op, iop, path, dest, err => match err {
+            CopyDirUndoOpError::Remove(err) => emit_remove_error(err, action, op, path, dest)
+        }
+    );
+
     process_op_impl!(process_create_op, CreateOp,
         action, op, iop, path, dest, err => match err {
             CreateOpError::Create(err) => emit_create_error(err, action, op, path, dest)
@@ -190,6 +481,7 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
             RmOpError::Move(err) => emit_move_error(err, action, op, path, dest),
             RmOpError::Copy(err) => emit_copy_error(err, action, op, path, dest),
             RmOpError::Mkdir(err) => emit_mkdir_error(err, action, op, path, dest),
+            RmOpError::Compress(err) => emit_compress_error(err, action, op, path, dest),
         }
     );
 
@@ -203,8 +495,133 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
             RmUndoOpError::Move(err) => emit_move_error(err, action, op, path, dest),
             RmUndoOpError::Copy(err) => emit_copy_error(err, action, op, path, dest),
             RmUndoOpError::Mkdir(err) => emit_mkdir_error(err, action, op, path, dest),
+            RmUndoOpError::Decompress(err) => emit_decompress_error(err, action, op, path, dest),
+        }
+    );
+
+    process_op_impl!(process_systemctl_op, SystemctlOp,
+        action, op, iop, path, dest, err => match err {
+            SystemctlOpError::Systemctl(err) => emit_systemctl_error(err, action, op, path, dest)
+        }
+    );
+
+    process_op_impl!(process_systemctl_undo_op, SystemctlUndoOp,
+        action, op, iop, path, dest, err => match err {
+            SystemctlOpError::Systemctl(err) => emit_systemctl_error(err, action, op, path, dest)
+        }
+    );
+
+    process_op_impl!(process_defaults_op, DefaultsOp,
+        action, op, iop, path, dest, err => match err {
+            DefaultsOpError::Defaults(err) => emit_defaults_error(err, action, op, path, dest)
+        }
+    );
+
+    process_op_impl!(process_defaults_undo_op, DefaultsUndoOp,
+        action, op, iop, path, dest, err => match err {
+            DefaultsOpError::Defaults(err) => emit_defaults_error(err, action, op, path, dest)
+        }
+    );
+
+    #[cfg(all(windows, feature = "registry"))]
+    process_op_impl!(process_registry_op, RegistryOp,
+        action, op, iop, path, dest, err => match err {
+            RegistryOpError::Registry(err) => emit_registry_error(err, action, op, path, dest)
+        }
+    );
+
+    #[cfg(all(windows, feature = "registry"))]
+    process_op_impl!(process_registry_undo_op, RegistryUndoOp,
+        action, op, iop, path, dest, err => match err {
+            RegistryOpError::Registry(err) => emit_registry_error(err, action, op, path, dest)
         }
     );
+
+    process_op_impl!(process_chmod_op, ChmodOp,
+        action, op, iop, path, dest, err => match err {
+            ChmodOpError::Metadata(err) => emit_metadata_error(err, action, op, path, dest),
+            ChmodOpError::Chmod(err) => emit_chmod_error(err, action, op, path, dest),
+        }
+    );
+
+    process_op_impl!(process_chmod_undo_op, ChmodUndoOp,
+        action, op, iop, path, dest, err => match err {
+            ChmodOpError::Metadata(err) => emit_metadata_error(err, action, op, path, dest),
+            ChmodOpError::Chmod(err) => emit_chmod_error(err, action, op, path, dest),
+        }
+    );
+
+    #[cfg(unix)]
+    process_op_impl!(process_chown_op, ChownOp,
+        action, op, iop, path, dest, err => match err {
+            ChownOpError::Metadata(err) => emit_metadata_error(err, action, op, path, dest),
+            ChownOpError::Chown(err) => emit_chown_error(err, action, op, path, dest),
+        }
+    );
+
+    #[cfg(unix)]
+    process_op_impl!(process_chown_undo_op, ChownUndoOp,
+        action, op, iop, path, dest, err => match err {
+            ChownOpError::Metadata(err) => emit_metadata_error(err, action, op, path, dest),
+            ChownOpError::Chown(err) => emit_chown_error(err, action, op, path, dest),
+        }
+    );
+
+    process_op_impl!(process_source_line_op, SourceLineOp,
+        action, op, iop, path, dest, err => match err {
+            SourceLineOpError::Read(err) => emit_read_error(err, action, op, path, dest),
+            SourceLineOpError::Write(err) => emit_write_error(err, action, op, path, dest),
+        }
+    );
+
+    process_op_impl!(process_source_line_undo_op, SourceLineUndoOp,
+        action, op, iop, path, dest, err => match err {
+            SourceLineUndoOpError::Read(err) => emit_read_error(err, action, op, path, dest),
+            SourceLineUndoOpError::Write(err) => emit_write_error(err, action, op, path, dest),
+            SourceLineUndoOpError::Remove(err) => emit_remove_error(err, action, op, path, dest),
+        }
+    );
+}
+
+/// Report that the file was copied because symlinks couldn't be created.
+#[inline]
+fn emit_link_copied(fin: &LinkFinish, dest: &Path) {
+    Step::message(sjoin2(
+        "copied (symlinks not permitted)",
+        describe::sdest_relative(&fin.dest, dest),
+    ));
+}
+
+/// Report whether the file was hard linked, or copied because it crossed filesystems.
+#[inline]
+fn emit_hardlinked(fin: &HardlinkFinish, dest: &Path) {
+    let how = if fin.copied {
+        "copied (different filesystem)"
+    } else {
+        "hardlinked"
+    };
+    Step::message(sjoin2(how, describe::sdest_relative(&fin.dest, dest)));
+}
+
+#[inline]
+fn emit_journal_error(err: JournalFileError) {
+    Step::error()
+        .message("couldn't write the journal")
+        .reason(err);
+}
+
+#[inline]
+fn emit_audit_error(fin: &JournalOpFinish, err: SinkError) {
+    let message = match fin.dest() {
+        Some(dest) => sjoin2("couldn't record audit of", spath(dest)),
+        None => pretty(format!("couldn't record audit of {} op", fin.name())),
+    };
+    Step::warning().message(message).reason(err);
+}
+
+#[inline]
+fn emit_retried(attempts: u32) {
+    Step::error().reason(sjoin3("gave up after", attempts, "attempts"));
 }
 
 macro_rules! emit_error_impl {
@@ -213,7 +630,7 @@ macro_rules! emit_error_impl {
         fn $name<'lua>(
             $err: $ty,
             action: &Action<'lua>,
-            op: Op<'lua>,
+            op: &Op<'lua>,
             path: &CtxPath,
             dest: &Path,
         ) {
@@ -230,10 +647,22 @@ emit_error_impl!(emit_symlink_error, SymlinkError:
     err => sjoin2("couldn't symlink to", spath(err.dest))
 );
 
+emit_error_impl!(emit_hardlink_error, HardlinkError:
+    err => sjoin2("couldn't hard link to", spath(err.dest))
+);
+
 emit_error_impl!(emit_copy_error, CopyError:
     err => sjoin2("couldn't copy to", spath(err.dest))
 );
 
+emit_error_impl!(emit_compress_error, CompressError:
+    err => sjoin4("couldn't compress", spath(err.src), "to", spath(err.dest))
+);
+
+emit_error_impl!(emit_decompress_error, DecompressError:
+    err => sjoin4("couldn't decompress", spath(err.src), "to", spath(err.dest))
+);
+
 emit_error_impl!(emit_create_error, CreateError:
     err => sjoin2("couldn't create", spath(err.path))
 );
@@ -274,6 +703,52 @@ emit_error_impl!(emit_write_error, WriteError:
     err => sjoin2("couldn't write to", spath(err.path))
 );
 
+emit_error_impl!(emit_chmod_error, ChmodError:
+    err => sjoin2("couldn't change mode of", spath(err.path))
+);
+
+#[cfg(unix)]
+emit_error_impl!(emit_chown_error, ChownError:
+    err => sjoin2("couldn't change owner of", spath(err.path))
+);
+
+emit_error_impl!(emit_exec_error, ExecError:
+    err => sjoin2("couldn't execute", spath(err.path))
+);
+
+#[inline]
+fn emit_script_failed<'lua>(
+    fin: &ScriptFinish,
+    action: &Action<'lua>,
+    op: &Op<'lua>,
+    path: &CtxPath,
+    dest: &Path,
+) {
+    Step::error()
+        .message(sjoin4(
+            "script",
+            spath(&fin.path),
+            "failed with",
+            fin.output.status,
+        ))
+        .reason(String::from_utf8_lossy(&fin.output.stderr).trim())
+        .context(op.describe(path, dest, DescribeMode::Error))
+        .context(action.describe(path, dest, DescribeMode::Error));
+}
+
+emit_error_impl!(emit_systemctl_error, SystemctlError:
+    err => sjoin3("couldn't run 'systemctl ", err.args.join(" "), "'")
+);
+
+emit_error_impl!(emit_defaults_error, DefaultsError:
+    err => sjoin3("couldn't run 'defaults ", err.args.join(" "), "'")
+);
+
+#[cfg(all(windows, feature = "registry"))]
+emit_error_impl!(emit_registry_error, RegistryError:
+    err => sjoin3("couldn't run 'reg ", err.args.join(" "), "'")
+);
+
 impl<'lua> Describe for Op<'lua> {
     #[inline]
     fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
@@ -282,6 +757,10 @@ impl<'lua> Describe for Op<'lua> {
             Op::LinkUndo(op) => op.describe(path, dest, mode),
             Op::Copy(op) => op.describe(path, dest, mode),
             Op::CopyUndo(op) => op.describe(path, dest, mode),
+            Op::Hardlink(op) => op.describe(path, dest, mode),
+            Op::HardlinkUndo(op) => op.describe(path, dest, mode),
+            Op::CopyDir(op) => op.describe(path, dest, mode),
+            Op::CopyDirUndo(op) => op.describe(path, dest, mode),
             Op::Create(op) => op.describe(path, dest, mode),
             Op::CreateUndo(op) => op.describe(path, dest, mode),
             Op::Write(op) => op.describe(path, dest, mode),
@@ -290,8 +769,25 @@ impl<'lua> Describe for Op<'lua> {
             Op::MkdirUndo(op) => op.describe(path, dest, mode),
             Op::Rm(op) => op.describe(path, dest, mode),
             Op::RmUndo(op) => op.describe(path, dest, mode),
+            Op::Systemctl(op) => op.describe(path, dest, mode),
+            Op::SystemctlUndo(op) => op.describe(path, dest, mode),
+            Op::Defaults(op) => op.describe(path, dest, mode),
+            Op::DefaultsUndo(op) => op.describe(path, dest, mode),
+            #[cfg(all(windows, feature = "registry"))]
+            Op::Registry(op) => op.describe(path, dest, mode),
+            #[cfg(all(windows, feature = "registry"))]
+            Op::RegistryUndo(op) => op.describe(path, dest, mode),
+            Op::Chmod(op) => op.describe(path, dest, mode),
+            Op::ChmodUndo(op) => op.describe(path, dest, mode),
+            #[cfg(unix)]
+            Op::Chown(op) => op.describe(path, dest, mode),
+            #[cfg(unix)]
+            Op::ChownUndo(op) => op.describe(path, dest, mode),
+            Op::SourceLine(op) => op.describe(path, dest, mode),
+            Op::SourceLineUndo(op) => op.describe(path, dest, mode),
             Op::Command(op) => op.describe(path, dest, mode),
             Op::Function(op) => op.describe(path, dest, mode),
+            Op::Script(op) => op.describe(path, dest, mode),
         }
     }
 }
@@ -352,6 +848,62 @@ impl Describe for CopyUndoOp {
     }
 }
 
+impl Describe for HardlinkOp {
+    #[inline]
+    fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+        let src = describe::path_relative(&self.src, path);
+        let dest = describe::dest_relative(&self.dest, dest);
+        sjoin4(
+            "creating hard link from",
+            describe::mode_spath(src, mode),
+            "to",
+            describe::mode_spath(dest, mode),
+        )
+    }
+}
+
+impl Describe for HardlinkUndoOp {
+    #[inline]
+    fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+        let src = describe::path_relative(&self.src, path);
+        let dest = describe::dest_relative(&self.dest, dest);
+        sjoin4(
+            "undoing hard link from",
+            describe::mode_spath(src, mode),
+            "to",
+            describe::mode_spath(dest, mode),
+        )
+    }
+}
+
+impl Describe for CopyDirOp {
+    #[inline]
+    fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+        let src = describe::path_relative(&self.src, path);
+        let dest = describe::dest_relative(&self.dest, dest);
+        sjoin4(
+            "copying directory from",
+            describe::mode_spath(src, mode),
+            "to",
+            describe::mode_spath(dest, mode),
+        )
+    }
+}
+
+impl Describe for CopyDirUndoOp {
+    #[inline]
+    fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+        let src = describe::path_relative(&self.src, path);
+        let dest = describe::dest_relative(&self.dest, dest);
+        sjoin4(
+            "undoing copy directory from",
+            describe::mode_spath(src, mode),
+            "to",
+            describe::mode_spath(dest, mode),
+        )
+    }
+}
+
 impl Describe for CreateOp {
     #[inline]
     fn describe(&self, _path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
@@ -419,6 +971,166 @@ impl Describe for RmUndoOp {
     }
 }
 
+impl Describe for SourceLineOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+        let path = describe::dest_relative(&self.path, dest);
+        sjoin2("adding line to", describe::mode_spath(path, mode))
+    }
+}
+
+impl Describe for SourceLineUndoOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+        let path = describe::dest_relative(&self.path, dest);
+        sjoin2("removing added line from", describe::mode_spath(path, mode))
+    }
+}
+
+impl Describe for SystemctlOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, _dest: &Path, _mode: DescribeMode) -> Pretty {
+        sjoin3("running 'systemctl ", self.command.args().join(" "), "'")
+    }
+}
+
+impl Describe for SystemctlUndoOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, _dest: &Path, _mode: DescribeMode) -> Pretty {
+        sjoin3(
+            "undoing with 'systemctl ",
+            self.command.inverse().args().join(" "),
+            "'",
+        )
+    }
+}
+
+impl Describe for DefaultsOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, _dest: &Path, _mode: DescribeMode) -> Pretty {
+        sjoin4(
+            "writing default",
+            format!("{} {}", self.domain, self.key),
+            "as",
+            &self.value,
+        )
+    }
+}
+
+impl Describe for DefaultsUndoOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, _dest: &Path, _mode: DescribeMode) -> Pretty {
+        let restored = match &self.previous {
+            Some(previous) => sjoin2("restoring previous value", previous),
+            None => pretty("deleting it"),
+        };
+        sjoin4(
+            "undoing default",
+            format!("{} {}", self.domain, self.key),
+            "by",
+            restored,
+        )
+    }
+}
+
+#[cfg(all(windows, feature = "registry"))]
+impl Describe for RegistryOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, _dest: &Path, _mode: DescribeMode) -> Pretty {
+        sjoin4(
+            "writing registry value",
+            format!("{} {}", self.key, self.name),
+            "as",
+            &self.value,
+        )
+    }
+}
+
+#[cfg(all(windows, feature = "registry"))]
+impl Describe for RegistryUndoOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, _dest: &Path, _mode: DescribeMode) -> Pretty {
+        let restored = match &self.previous {
+            Some(previous) => sjoin2("restoring previous value", previous),
+            None => pretty("deleting it"),
+        };
+        sjoin4(
+            "undoing registry value",
+            format!("{} {}", self.key, self.name),
+            "by",
+            restored,
+        )
+    }
+}
+
+impl Describe for ChmodOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+        let path = describe::dest_relative(&self.path, dest);
+        sjoin4(
+            "changing mode of",
+            describe::mode_spath(path, mode),
+            "to",
+            format!("{:04o}", self.mode),
+        )
+    }
+}
+
+impl Describe for ChmodUndoOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+        let path = describe::dest_relative(&self.path, dest);
+        sjoin4(
+            "restoring mode of",
+            describe::mode_spath(path, mode),
+            "to",
+            format!("{:04o}", self.prev_mode),
+        )
+    }
+}
+
+#[cfg(unix)]
+impl Describe for ChownOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+        let path = describe::dest_relative(&self.path, dest);
+        sjoin4(
+            "changing owner of",
+            describe::mode_spath(path, mode),
+            "to",
+            owner(self.uid, self.gid),
+        )
+    }
+}
+
+#[cfg(unix)]
+impl Describe for ChownUndoOp {
+    #[inline]
+    fn describe(&self, _path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+        let path = describe::dest_relative(&self.path, dest);
+        sjoin4(
+            "restoring owner of",
+            describe::mode_spath(path, mode),
+            "to",
+            owner(
+                self.uid.map(|_| self.prev_uid),
+                self.gid.map(|_| self.prev_gid),
+            ),
+        )
+    }
+}
+
+/// Format user and group ids like `chown` does, e.g. `1000:100`, `1000`, or `:100`.
+#[cfg(unix)]
+#[inline]
+fn owner(uid: Option<u32>, gid: Option<u32>) -> String {
+    let uid = uid.map(|uid| uid.to_string()).unwrap_or_default();
+    match gid {
+        Some(gid) => format!("{}:{}", uid, gid),
+        None => uid,
+    }
+}
+
 impl Describe for CommandOp {
     #[inline]
     fn describe(&self, _path: &CtxPath, _dest: &Path, _mode: DescribeMode) -> Pretty {
@@ -432,3 +1144,11 @@ impl<'lua> Describe for FunctionOp<'lua> {
         pretty("running lua function")
     }
 }
+
+impl Describe for ScriptOp {
+    #[inline]
+    fn describe(&self, path: &CtxPath, _dest: &Path, mode: DescribeMode) -> Pretty {
+        let script = describe::path_relative(&self.path, path);
+        sjoin2("executing script", describe::mode_spath(script, mode))
+    }
+}
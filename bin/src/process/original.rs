@@ -0,0 +1,61 @@
+use std::path::Path;
+
+use shelflib::op::Op;
+
+use super::GraphProcessor;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    /// Keep the original of the path that `op` is about to take over, if this is the first time
+    /// shelf does so.
+    #[inline]
+    pub fn keep_original(&self, op: &Op<'_>, dest: &Path) -> Result<(), ()> {
+        let (store, path) = match (&self.opts.originals, taken_path(op)) {
+            (Some(store), Some(path)) => (store, path),
+            _ => return Ok(()),
+        };
+
+        match store.keep(path) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                output::keep_error(path, dest, err);
+                Err(())
+            }
+        }
+    }
+}
+
+/// Return the path that `op` replaces, creates, or modifies, if any. Undo operations and
+/// directory creation don't take over anything.
+#[inline]
+fn taken_path<'a>(op: &'a Op<'_>) -> Option<&'a Path> {
+    match op {
+        Op::Link(op) => Some(&op.dest),
+        Op::Copy(op) => Some(&op.dest),
+        Op::Hardlink(op) => Some(&op.dest),
+        Op::CopyDir(op) => Some(&op.dest),
+        Op::Create(op) => Some(&op.path),
+        Op::Write(op) => Some(&op.path),
+        Op::Rm(op) => Some(&op.path),
+        Op::SourceLine(op) => Some(&op.path),
+        _ => None,
+    }
+}
+
+mod output {
+    use std::path::Path;
+
+    use shelflib::originals::OriginalsError;
+
+    use super::super::describe;
+    use crate::output::{comb::sjoin2, Step};
+
+    #[inline]
+    pub fn keep_error(path: &Path, dest: &Path, err: OriginalsError) {
+        Step::error()
+            .message(sjoin2(
+                "couldn't keep the original of",
+                describe::sdest_relative(dest, path),
+            ))
+            .reason(err);
+    }
+}
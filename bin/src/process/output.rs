@@ -1,13 +1,146 @@
-use shelflib::graph::CircularDependencyError;
+use std::fs;
+use std::path::Path;
 
+use shelflib::{
+    action::Action,
+    fse::expand::ExpandError,
+    graph::CircularDependencyError,
+    op::Op,
+    state::{ApplyResult, PackageState},
+};
+
+use super::{describe, Describe};
 use crate::ctxpath::CtxPath;
-use crate::output::Section;
+use crate::output::{
+    ago,
+    comb::{paren, pretty, sjoin2, sjoin3, sjoin4},
+    diff, spath, Section, Step,
+};
 
 #[inline]
 pub fn processing(path: &CtxPath) {
     Section::message("processing", path.rel().display());
 }
 
+#[inline]
+pub fn last_applied(state: Option<&PackageState>) {
+    let message = match state {
+        Some(state) => {
+            let result = match state.result {
+                ApplyResult::Success => "",
+                ApplyResult::Failure => " (failed)",
+            };
+            let partial = if state.partial { " (partial)" } else { "" };
+            sjoin3(
+                "last applied",
+                ago(state.applied_at),
+                paren(sjoin2(
+                    "by shelf",
+                    format!("{}{}{}", state.version, result, partial),
+                )),
+            )
+        }
+        None => pretty("never applied"),
+    };
+    Step::message(message);
+}
+
+#[inline]
+pub fn skipping_unchanged(action: &Action<'_>, path: &CtxPath, dest: &Path) {
+    Step::skipping().message("nothing changed in the package");
+    Step::skipping().context(action.describe_info(path, dest));
+}
+
+#[inline]
+pub fn skipping_condition(action: &Action<'_>, path: &CtxPath, dest: &Path) {
+    Step::skipping().message("conditions not met or profile not selected");
+    Step::skipping().context(action.describe_info(path, dest));
+}
+
+#[inline]
+pub fn declined(op: &Op<'_>, path: &CtxPath, dest: &Path) {
+    Step::skipping().message("declined");
+    Step::skipping().context(op.describe_info(path, dest));
+}
+
+#[inline]
+pub fn skipping_hook(action: &Action<'_>, path: &CtxPath, dest: &Path) {
+    Step::skipping().message("hooks are disabled");
+    Step::skipping().context(action.describe_info(path, dest));
+}
+
+/// Report the change that `op` would make to the filesystem. The contents written to existing
+/// files are shown as a diff against what is there now.
+#[inline]
+pub fn would_run(op: &Op<'_>, path: &CtxPath, dest: &Path) {
+    let src = |src: &Path| spath(describe::path_relative(src, path).rel());
+    let dst = |this: &Path| spath(describe::dest_relative(this, dest).rel());
+    let message = match op {
+        Op::Link(op) => sjoin4("would create symlink", dst(&op.dest), "->", src(&op.src)),
+        Op::Copy(op) => sjoin4("would copy", src(&op.src), "to", dst(&op.dest)),
+        Op::Hardlink(op) => sjoin4("would hard link", dst(&op.dest), "to", src(&op.src)),
+        Op::CopyDir(op) => sjoin4("would copy directory", src(&op.src), "to", dst(&op.dest)),
+        Op::Mkdir(op) => sjoin2("would mkdir", dst(&op.path)),
+        Op::Rm(op) if op.dir => sjoin2("would remove directory", dst(&op.path)),
+        Op::Rm(op) => sjoin2("would remove", dst(&op.path)),
+        Op::Create(op) => sjoin2("would create file", dst(&op.path)),
+        Op::Write(op) => {
+            let old = existing_contents(&op.path);
+            let verb = if old.is_some() {
+                "would overwrite file"
+            } else {
+                "would write file"
+            };
+            Step::message(sjoin2(verb, dst(&op.path)));
+            diff::print(
+                old.as_deref().unwrap_or_default(),
+                &op.contents,
+                "binary contents differ",
+            );
+            return;
+        }
+        op => sjoin2("pretending:", op.describe_info(path, dest)),
+    };
+    Step::message(message);
+}
+
+/// Return the contents of the regular file at `path`, if there is one.
+#[inline]
+fn existing_contents(path: &Path) -> Option<Vec<u8>> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if metadata.is_file() {
+        fs::read(path).ok()
+    } else {
+        None
+    }
+}
+
+#[inline]
+pub fn partial() {
+    Step::note().message("applying selected directives only");
+}
+
+#[inline]
+pub fn invalid_dest(err: &ExpandError) {
+    Step::error()
+        .message("couldn't expand destination")
+        .reason(err);
+}
+
+#[inline]
+pub fn state_read_error(path: &CtxPath) {
+    Step::warning()
+        .message("couldn't read last applied state")
+        .context(path.abs().display());
+}
+
+#[inline]
+pub fn state_write_error(path: &CtxPath) {
+    Step::warning()
+        .message("couldn't record applied state")
+        .context(path.abs().display());
+}
+
 #[inline]
 pub fn error_circular(err: CircularDependencyError) {
     Section::error().message("circular dependency detected");
@@ -0,0 +1,89 @@
+use shelflib::{
+    action::{perms::Res, Action, Resolve, SensitivePermsAction},
+    op::Op,
+};
+
+use super::{GraphProcessor, PermsPolicy, WarningKind};
+use crate::ctxpath::CtxPath;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    /// Check (and fix, depending on policy) the permissions of security-sensitive destinations
+    /// after all packages have been processed.
+    #[inline]
+    pub fn process_sensitive_perms(&mut self) -> Result<(), ()> {
+        let fix = match self.opts.perms {
+            PermsPolicy::Ignore => return Ok(()),
+            PermsPolicy::Warn => false,
+            PermsPolicy::Fix => true,
+        };
+
+        let dest = self.opts.paths.home().to_path_buf();
+        let path = CtxPath::new(&dest, &dest).unwrap();
+        let action = SensitivePermsAction {
+            dest: dest.clone(),
+            fix,
+        };
+
+        self.process_action(Action::SensitivePerms(action), &path, &dest)
+    }
+
+    #[inline]
+    pub fn resolve_sensitive_perms(
+        &self,
+        action: &SensitivePermsAction,
+        _path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        let Res { violations, ops } = action.resolve();
+        for violation in &violations {
+            output::violation(violation, action.fix, self.opts.paths.home());
+            self.warn(
+                WarningKind::Perms,
+                None,
+                Some(&violation.path),
+                format!(
+                    "permissive mode {:04o}, expected {:04o}",
+                    violation.mode, violation.expected
+                ),
+            );
+        }
+
+        Ok(ops.into_iter().map(Op::Chmod).collect())
+    }
+}
+
+mod output {
+    use std::path::Path;
+
+    use shelflib::action::{perms::Violation, SensitivePermsAction};
+
+    use super::super::{describe, Describe, DescribeMode};
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{pretty, sjoin2, sjoin4},
+        Pretty, Step,
+    };
+
+    impl Describe for SensitivePermsAction {
+        #[inline]
+        fn describe(&self, _path: &CtxPath, _dest: &Path, _mode: DescribeMode) -> Pretty {
+            pretty("checking permissions of sensitive files")
+        }
+    }
+
+    #[inline]
+    pub fn violation(violation: &Violation, fix: bool, dest: &Path) {
+        let message = sjoin4(
+            "permissive mode",
+            format!("{:04o}", violation.mode),
+            "on",
+            describe::sdest_relative(&violation.path, dest),
+        );
+        let reason = if fix {
+            sjoin2("changing mode to", format!("{:04o}", violation.expected))
+        } else {
+            sjoin2("expected mode", format!("{:04o}", violation.expected))
+        };
+
+        Step::warning().message(message).reason(reason);
+    }
+}
@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use shelflib::action::{PluginAction, Resolve};
+
+use super::GraphProcessor;
+use crate::ctxpath::CtxPath;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    #[inline]
+    pub fn process_plugin(
+        &mut self,
+        action: &PluginAction,
+        path: &CtxPath,
+        dest: &Path,
+    ) -> Result<(), ()> {
+        let directives = match action.resolve() {
+            Ok(directives) => directives,
+            Err(err) => {
+                output::error(err, action, path, self.opts.paths.home());
+                return Err(());
+            }
+        };
+
+        directives
+            .into_iter()
+            .try_for_each(|directive| self.process_action(directive.into(), path, dest))
+    }
+}
+
+mod output {
+    use std::path::Path;
+
+    use shelflib::action::{plugin::Error, PluginAction};
+
+    use super::super::{describe, Describe, DescribeMode};
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        Pretty, Step,
+    };
+
+    impl Describe for PluginAction {
+        #[inline]
+        fn describe(&self, path: &CtxPath, _dest: &Path, mode: DescribeMode) -> Pretty {
+            let module = describe::path_relative(&self.module, path);
+            sjoin4(
+                "resolving",
+                format!("'{}'", self.kind),
+                "with plugin",
+                describe::mode_spath(module, mode),
+            )
+        }
+    }
+
+    #[inline]
+    pub fn error(err: Error, action: &PluginAction, path: &CtxPath, dest: &Path) {
+        Step::error()
+            .message(sjoin2("couldn't resolve", format!("'{}'", action.kind)))
+            .reason(err)
+            .context(action.describe_error(path, dest));
+    }
+}
@@ -0,0 +1,62 @@
+use super::GraphProcessor;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    /// Check for destinations on read-only mounts before anything is processed, and report all
+    /// of them at once.
+    #[inline]
+    pub fn check_read_only(&self) -> Result<(), ()> {
+        let read_only = self.graph.read_only(&self.opts.paths);
+        if read_only.is_empty() {
+            return Ok(());
+        }
+
+        output::read_only_found(read_only.len());
+        for ro in &read_only {
+            output::read_only(ro, self.paths, self.opts.paths.home());
+        }
+
+        Err(())
+    }
+}
+
+mod output {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    use shelflib::graph::ReadOnly;
+
+    use super::super::describe;
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{pretty, sjoin2, sjoin3},
+        spath, Section, Step,
+    };
+
+    #[inline]
+    pub fn read_only_found(count: usize) {
+        Section::error().message(sjoin3(
+            "found",
+            count,
+            "destination(s) on read-only filesystems; nothing was changed",
+        ));
+    }
+
+    #[inline]
+    pub fn read_only(ro: &ReadOnly, paths: &HashMap<PathBuf, CtxPath>, dest: &Path) {
+        let package = match paths.get(&ro.package) {
+            Some(path) => spath(path.rel()),
+            None => spath(&ro.package),
+        };
+
+        Step::error()
+            .message(sjoin2(
+                describe::sdest_relative(dest, &ro.dest),
+                "is on a read-only filesystem",
+            ))
+            .context(sjoin2("package", package))
+            .reason(pretty(format!(
+                "{} is mounted read-only; remount it read-write or exclude the destination",
+                spath(&ro.mount)
+            )));
+    }
+}
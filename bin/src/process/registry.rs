@@ -0,0 +1,88 @@
+use shelflib::{
+    action::{
+        registry::{self, Res},
+        RegValueAction, Resolve,
+    },
+    op::Op,
+};
+
+use super::GraphProcessor;
+use crate::ctxpath::CtxPath;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    #[inline]
+    pub fn resolve_reg_value(
+        &self,
+        action: &RegValueAction,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        match action.resolve() {
+            #[cfg(all(windows, feature = "registry"))]
+            Ok(Res::Normal(op)) => Ok(vec![Op::Registry(op)]),
+            Ok(Res::Skip(skip)) => {
+                output::skipping(&skip, action, path, self.opts.paths.home());
+                Ok(vec![])
+            }
+            Err(registry::Error::Read(err)) => {
+                output::read_error(err, action, path, self.opts.paths.home());
+                Err(())
+            }
+        }
+    }
+}
+
+mod output {
+    use std::path::Path;
+
+    use shelflib::{
+        action::{registry::Skip, RegValueAction},
+        op::error::RegistryError,
+    };
+
+    use super::super::{Describe, DescribeMode};
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        Pretty, Step,
+    };
+
+    impl Describe for RegValueAction {
+        #[inline]
+        fn describe(&self, _path: &CtxPath, _dest: &Path, _mode: DescribeMode) -> Pretty {
+            sjoin4(
+                "setting registry value",
+                format!("{} {}", self.key, self.name),
+                "to",
+                &self.value,
+            )
+        }
+    }
+
+    #[inline]
+    pub fn skipping(skip: &Skip, action: &RegValueAction, path: &CtxPath, dest: &Path) {
+        let message = match skip {
+            Skip::Unsupported => sjoin2(
+                "registry support not enabled, so not setting",
+                format!("{} {}", action.key, action.name),
+            ),
+            Skip::UpToDate => sjoin2(
+                "registry value already set",
+                format!("{} {}", action.key, action.name),
+            ),
+        };
+
+        Step::skipping().message(message);
+        Step::skipping().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn read_error(err: RegistryError, action: &RegValueAction, path: &CtxPath, dest: &Path) {
+        Step::error()
+            .message(sjoin2(
+                "couldn't read current value of",
+                format!("{} {}", action.key, action.name),
+            ))
+            .reason(err.inner)
+            .context(action.describe_info(path, dest));
+    }
+}
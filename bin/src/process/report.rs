@@ -0,0 +1,311 @@
+use std::convert::TryInto;
+use std::env;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, FixedOffset, Local, TimeZone, Utc};
+use serde::Serialize;
+use shelflib::op::Op;
+
+use super::op::changed_path;
+use super::{GraphProcessor, Warning};
+
+/// Record of a run, written to a report file for audits.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    /// Local time at which processing started, in RFC 3339 format, or that of `SOURCE_DATE_EPOCH`
+    /// if set.
+    pub started_at: String,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// Whether processing was only pretended.
+    pub noop: bool,
+    /// Processed packages, in order.
+    pub packages: Vec<PackageReport>,
+    /// Warnings collected while processing, in order.
+    pub warnings: Vec<Warning>,
+}
+
+/// Record of the processing of a single package.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageReport {
+    /// Absolute path of the package.
+    pub path: PathBuf,
+    pub success: bool,
+    pub duration_ms: u64,
+    /// Ops run for the package, in order.
+    pub ops: Vec<OpReport>,
+    #[serde(skip)]
+    start: Option<Instant>,
+}
+
+/// Record of a single op.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpReport {
+    /// Kind of op, e.g. `"link"`.
+    pub op: &'static str,
+    /// Path changed by the op, if any.
+    pub path: Option<PathBuf>,
+    pub status: OpStatus,
+    /// Sequence number of the journal record that committed the op, if it was journaled.
+    pub transaction: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpStatus {
+    /// The op was run successfully.
+    Applied,
+    /// The op failed.
+    Failed,
+    /// The op would have been run, but processing was only pretended.
+    Pretended,
+    /// The op was declined when asked to confirm it.
+    Declined,
+}
+
+impl OpStatus {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Applied => "applied",
+            Self::Failed => "failed",
+            Self::Pretended => "pretended",
+            Self::Declined => "declined",
+        }
+    }
+}
+
+impl RunReport {
+    /// Add the record of `other`, e.g. from processing packages of another scope.
+    #[inline]
+    pub fn merge(&mut self, other: RunReport) {
+        self.duration_ms += other.duration_ms;
+        self.success &= other.success;
+        self.packages.extend(other.packages);
+        self.warnings.extend(other.warnings);
+    }
+
+    /// Render the report as an HTML document.
+    #[inline]
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        // Writing to a string never fails.
+        let _ = self.write_html(&mut html);
+        html
+    }
+
+    #[inline]
+    fn write_html(&self, w: &mut String) -> std::fmt::Result {
+        writeln!(w, "<!DOCTYPE html>")?;
+        writeln!(w, "<html><head><meta charset=\"utf-8\">")?;
+        writeln!(
+            w,
+            "<title>shelf report {}</title>",
+            escape(&self.started_at)
+        )?;
+        writeln!(w, "</head><body>")?;
+        writeln!(w, "<h1>shelf report</h1>")?;
+        writeln!(
+            w,
+            "<p>started {}, took {} ms, {}{}</p>",
+            escape(&self.started_at),
+            self.duration_ms,
+            if self.success { "succeeded" } else { "failed" },
+            if self.noop { " (pretended)" } else { "" }
+        )?;
+
+        for package in &self.packages {
+            writeln!(
+                w,
+                "<h2>{}</h2>",
+                escape(&package.path.display().to_string())
+            )?;
+            writeln!(
+                w,
+                "<p>{}, took {} ms</p>",
+                if package.success {
+                    "succeeded"
+                } else {
+                    "failed"
+                },
+                package.duration_ms
+            )?;
+            writeln!(
+                w,
+                "<table><tr><th>op</th><th>path</th><th>status</th><th>transaction</th></tr>"
+            )?;
+            for op in &package.ops {
+                writeln!(
+                    w,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    op.op,
+                    op.path
+                        .as_ref()
+                        .map(|path| escape(&path.display().to_string()))
+                        .unwrap_or_default(),
+                    op.status.name(),
+                    op.transaction.map(|t| t.to_string()).unwrap_or_default()
+                )?;
+            }
+            writeln!(w, "</table>")?;
+        }
+
+        if !self.warnings.is_empty() {
+            writeln!(w, "<h2>warnings</h2>")?;
+            writeln!(w, "<ul>")?;
+            for warning in &self.warnings {
+                let mut parts = Vec::new();
+                if let Some(package) = &warning.package {
+                    parts.push(package.display().to_string());
+                }
+                if let Some(path) = &warning.path {
+                    parts.push(path.display().to_string());
+                }
+                parts.push(warning.message.clone());
+                writeln!(
+                    w,
+                    "<li>{}: {}</li>",
+                    warning.kind.name(),
+                    escape(&parts.join(": "))
+                )?;
+            }
+            writeln!(w, "</ul>")?;
+        }
+
+        writeln!(w, "</body></html>")
+    }
+}
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    /// Start recording the package at `path` for the report.
+    #[inline]
+    pub fn report_package(&mut self, path: &Path) {
+        self.report.push(PackageReport {
+            path: path.to_path_buf(),
+            success: false,
+            duration_ms: 0,
+            ops: Vec::new(),
+            start: Some(Instant::now()),
+        });
+    }
+
+    /// Finish recording the package being processed.
+    #[inline]
+    pub fn report_package_done(&mut self, success: bool) {
+        if let Some(package) = self.report.last_mut() {
+            package.success = success;
+            package.duration_ms = package.start.take().map(millis).unwrap_or(0);
+        }
+    }
+
+    /// Record an op of the package being processed.
+    #[inline]
+    pub fn report_op(&mut self, op: &Op<'_>, status: OpStatus, transaction: Option<u64>) {
+        if let Some(package) = self.report.last_mut() {
+            package.ops.push(OpReport {
+                op: op_name(op),
+                path: changed_path(op).map(Path::to_path_buf),
+                status,
+                transaction,
+            });
+        }
+    }
+}
+
+#[inline]
+fn millis(start: Instant) -> u64 {
+    duration_millis(start.elapsed())
+}
+
+/// Return `duration` in milliseconds, or 0 if `SOURCE_DATE_EPOCH` is set, so that reports of
+/// runs from the same inputs are identical.
+#[inline]
+pub fn duration_millis(duration: Duration) -> u64 {
+    if source_date_epoch().is_some() {
+        return 0;
+    }
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+/// Return the time at which a run is taken to happen: that of `SOURCE_DATE_EPOCH` if set, or
+/// the current local time.
+#[inline]
+pub fn run_time() -> DateTime<FixedOffset> {
+    match source_date_epoch() {
+        Some(time) => time.with_timezone(&FixedOffset::east(0)),
+        None => {
+            let now = Local::now();
+            now.with_timezone(now.offset())
+        }
+    }
+}
+
+/// Return the current time, or that of `SOURCE_DATE_EPOCH` if set, like [`run_time`]. Recorded
+/// and displayed times use this, so that the output of runs from the same inputs is identical.
+#[inline]
+pub fn now() -> SystemTime {
+    match source_date_epoch() {
+        Some(time) => time.into(),
+        None => SystemTime::now(),
+    }
+}
+
+/// Return the time given by `SOURCE_DATE_EPOCH`, in seconds since the Unix epoch, if it is set
+/// and valid.
+#[inline]
+fn source_date_epoch() -> Option<DateTime<Utc>> {
+    let secs = env::var("SOURCE_DATE_EPOCH").ok()?.trim().parse().ok()?;
+    Utc.timestamp_opt(secs, 0).single()
+}
+
+/// Return the name of the kind of `op`.
+#[inline]
+fn op_name(op: &Op<'_>) -> &'static str {
+    match op {
+        Op::Link(_) => "link",
+        Op::LinkUndo(_) => "link_undo",
+        Op::Copy(_) => "copy",
+        Op::CopyUndo(_) => "copy_undo",
+        Op::Hardlink(_) => "hardlink",
+        Op::HardlinkUndo(_) => "hardlink_undo",
+        Op::CopyDir(_) => "copy_dir",
+        Op::CopyDirUndo(_) => "copy_dir_undo",
+        Op::Create(_) => "create",
+        Op::CreateUndo(_) => "create_undo",
+        Op::Write(_) => "write",
+        Op::WriteUndo(_) => "write_undo",
+        Op::Mkdir(_) => "mkdir",
+        Op::MkdirUndo(_) => "mkdir_undo",
+        Op::Rm(_) => "rm",
+        Op::RmUndo(_) => "rm_undo",
+        Op::Systemctl(_) => "systemctl",
+        Op::SystemctlUndo(_) => "systemctl_undo",
+        Op::Chmod(_) => "chmod",
+        Op::ChmodUndo(_) => "chmod_undo",
+        #[cfg(unix)]
+        Op::Chown(_) => "chown",
+        #[cfg(unix)]
+        Op::ChownUndo(_) => "chown_undo",
+        Op::SourceLine(_) => "source_line",
+        Op::SourceLineUndo(_) => "source_line_undo",
+        Op::Defaults(_) => "defaults",
+        Op::DefaultsUndo(_) => "defaults_undo",
+        #[cfg(all(windows, feature = "registry"))]
+        Op::Registry(_) => "registry",
+        #[cfg(all(windows, feature = "registry"))]
+        Op::RegistryUndo(_) => "registry_undo",
+        Op::Command(_) => "command",
+        Op::Function(_) => "function",
+        Op::Script(_) => "script",
+    }
+}
+
+#[inline]
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
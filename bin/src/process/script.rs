@@ -0,0 +1,116 @@
+use shelflib::{
+    action::{
+        script::{self, Error, Res},
+        Resolve, ScriptAction,
+    },
+    op::Op,
+};
+
+use super::GraphProcessor;
+use crate::ctxpath::CtxPath;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    #[inline]
+    pub fn resolve_script(
+        &self,
+        action: &ScriptAction,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        output::processing_script(action, path, self.opts.paths.home());
+
+        let res = match action.resolve() {
+            Ok(res) => res,
+            Err(err) => {
+                match err {
+                    Error::ScriptMissing => {
+                        output::script_missing(action, path, self.opts.paths.home())
+                    }
+                    Error::ScriptNotFile => {
+                        output::script_not_file(action, path, self.opts.paths.home())
+                    }
+                    Error::StartMissing => {
+                        output::start_missing(action, path, self.opts.paths.home())
+                    }
+                }
+
+                return Err(());
+            }
+        };
+
+        match res {
+            Res::Normal(ops) => Ok(map_ops(ops)),
+        }
+    }
+}
+
+#[inline]
+fn map_ops(ops: Vec<script::Op>) -> Vec<Op<'static>> {
+    ops.into_iter()
+        .map(|op| match op {
+            script::Op::Chmod(op) => Op::Chmod(op),
+            script::Op::Script(op) => Op::Script(op),
+        })
+        .collect()
+}
+
+mod output {
+    use std::path::Path;
+
+    use shelflib::action::ScriptAction;
+
+    use super::super::{describe, Describe, DescribeMode};
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        Pretty, Step,
+    };
+
+    impl Describe for ScriptAction {
+        #[inline]
+        fn describe(&self, path: &CtxPath, _dest: &Path, mode: DescribeMode) -> Pretty {
+            let script = describe::path_relative(&self.path, path);
+            if self.args.is_empty() {
+                sjoin2("running script", describe::mode_spath(script, mode))
+            } else {
+                sjoin4(
+                    "running script",
+                    describe::mode_spath(script, mode),
+                    "with arguments",
+                    self.args.join(" "),
+                )
+            }
+        }
+    }
+
+    #[inline]
+    pub fn processing_script(action: &ScriptAction, path: &CtxPath, dest: &Path) {
+        Step::message(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn script_missing(action: &ScriptAction, path: &CtxPath, dest: &Path) {
+        Step::error().message(sjoin2(
+            "missing script",
+            describe::spath_relative(&action.path, path),
+        ));
+        Step::error().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn script_not_file(action: &ScriptAction, path: &CtxPath, dest: &Path) {
+        Step::error().message(sjoin2(
+            "script is not a file",
+            describe::spath_relative(&action.path, path),
+        ));
+        Step::error().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn start_missing(action: &ScriptAction, path: &CtxPath, dest: &Path) {
+        Step::error().message(sjoin2(
+            "missing start directory",
+            describe::spath_relative(&action.start, path),
+        ));
+        Step::error().context(action.describe_info(path, dest));
+    }
+}
@@ -0,0 +1,69 @@
+use shelflib::{
+    action::{
+        sourceline::{self, Res},
+        Resolve, SourceLineAction,
+    },
+    op::Op,
+};
+
+use super::GraphProcessor;
+use crate::ctxpath::CtxPath;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    #[inline]
+    pub fn resolve_source_line(
+        &self,
+        action: &SourceLineAction,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        match action.resolve() {
+            Ok(Res::Normal(op)) => Ok(vec![Op::SourceLine(op)]),
+            Ok(Res::Skip(_skip)) => {
+                // TODO: Output
+                Ok(vec![])
+            }
+            Err(sourceline::Error::Read(err)) => {
+                output::read_error(err, action, path, self.opts.paths.home());
+                Err(())
+            }
+        }
+    }
+}
+
+mod output {
+    use std::io;
+    use std::path::Path;
+
+    use shelflib::action::SourceLineAction;
+
+    use super::super::{describe, Describe, DescribeMode};
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        Pretty, Step,
+    };
+
+    impl Describe for SourceLineAction {
+        #[inline]
+        fn describe(&self, _path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+            let path = describe::dest_relative(&self.path, dest);
+            sjoin4(
+                "ensuring line",
+                format!("'{}'", self.line),
+                "in",
+                describe::mode_spath(path, mode),
+            )
+        }
+    }
+
+    #[inline]
+    pub fn read_error(err: io::Error, action: &SourceLineAction, path: &CtxPath, dest: &Path) {
+        Step::error()
+            .message(sjoin2(
+                "couldn't read",
+                describe::sdest_relative(&action.path, dest),
+            ))
+            .reason(err)
+            .context(action.describe_info(path, dest));
+    }
+}
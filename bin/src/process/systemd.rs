@@ -0,0 +1,132 @@
+use shelflib::{
+    action::{
+        systemd::{self, Error, Res},
+        Resolve, SystemdUnitAction,
+    },
+    op::Op,
+};
+
+use super::GraphProcessor;
+use crate::ctxpath::CtxPath;
+
+impl<'p, 'g> GraphProcessor<'p, 'g> {
+    #[inline]
+    pub fn resolve_systemd_unit(
+        &self,
+        action: &SystemdUnitAction,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        output::processing_systemd_unit(action, path, self.opts.paths.home());
+
+        let res = match action.resolve() {
+            Ok(res) => res,
+            Err(err) => {
+                match err {
+                    Error::SrcMissing => output::src_missing(action, path, self.opts.paths.home()),
+                    Error::InvalidName => {
+                        output::invalid_name(action, path, self.opts.paths.home())
+                    }
+                }
+
+                return Err(());
+            }
+        };
+
+        match res {
+            Res::Normal(ops) => Ok(map_ops(ops)),
+            Res::Overwrite(ops) => {
+                self.drifted(path, &action.dest);
+                output::overwriting(action, path, self.opts.paths.home());
+                Ok(map_ops(ops))
+            }
+            Res::Skip(skip) => {
+                output::skipping(&skip, action, path, self.opts.paths.home());
+                Ok(vec![])
+            }
+        }
+    }
+}
+
+#[inline]
+fn map_ops(ops: Vec<systemd::Op>) -> Vec<Op<'static>> {
+    ops.into_iter()
+        .map(|op| match op {
+            systemd::Op::Rm(op) => Op::Rm(op),
+            systemd::Op::Link(op) => Op::Link(op),
+            systemd::Op::Mkdir(op) => Op::Mkdir(op),
+            systemd::Op::Systemctl(op) => Op::Systemctl(op),
+        })
+        .collect()
+}
+
+mod output {
+    use std::path::Path;
+
+    use shelflib::action::{systemd::Skip, SystemdUnitAction};
+
+    use super::super::{describe, Describe, DescribeMode};
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        Pretty, Step,
+    };
+
+    impl Describe for SystemdUnitAction {
+        #[inline]
+        fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+            let src = describe::path_relative(&self.src, path);
+            let dest = describe::dest_relative(&self.dest, dest);
+            sjoin4(
+                "installing systemd unit",
+                describe::mode_spath(src, mode),
+                "to",
+                describe::mode_spath(dest, mode),
+            )
+        }
+    }
+
+    #[inline]
+    pub fn processing_systemd_unit(action: &SystemdUnitAction, path: &CtxPath, dest: &Path) {
+        Step::message(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn src_missing(action: &SystemdUnitAction, path: &CtxPath, dest: &Path) {
+        Step::error().message(sjoin2(
+            "missing source",
+            describe::spath_relative(&action.src, path),
+        ));
+        Step::error().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn invalid_name(action: &SystemdUnitAction, path: &CtxPath, dest: &Path) {
+        Step::error().message(sjoin2(
+            "invalid unit name",
+            describe::sdest_relative(&action.dest, dest),
+        ));
+        Step::error().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn overwriting(action: &SystemdUnitAction, path: &CtxPath, dest: &Path) {
+        Step::warning().message(sjoin2(
+            "overwriting existing",
+            describe::sdest_relative(&action.dest, dest),
+        ));
+        Step::warning().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn skipping(skip: &Skip, action: &SystemdUnitAction, path: &CtxPath, dest: &Path) {
+        let message = match skip {
+            Skip::DestExists => sjoin2(
+                "unit already installed",
+                describe::sdest_relative(&action.dest, dest),
+            ),
+        };
+
+        Step::skipping().message(message);
+        Step::skipping().context(action.describe_info(path, dest));
+    }
+}
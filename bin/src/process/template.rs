@@ -1,7 +1,9 @@
+use std::path::Path;
+
 use shelflib::{
     action::{
         template::{self, Res},
-        HandlebarsAction, LiquidAction, Resolve,
+        GotmplAction, HandlebarsAction, LiquidAction, PipeAction, Resolve,
     },
     op::Op,
 };
@@ -13,8 +15,8 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
     #[inline]
     pub fn resolve_handlebars(
         &self,
-        action: HandlebarsAction,
-        _path: &CtxPath,
+        action: &HandlebarsAction<'_>,
+        path: &CtxPath,
     ) -> Result<Vec<Op<'static>>, ()> {
         let res = match action.resolve() {
             Ok(res) => res,
@@ -24,14 +26,14 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
             }
         };
 
-        handle_res(res)
+        self.handle_template_res(res, path, &action.dest)
     }
 
     #[inline]
     pub fn resolve_liquid(
         &self,
-        action: LiquidAction,
-        _path: &CtxPath,
+        action: &LiquidAction,
+        path: &CtxPath,
     ) -> Result<Vec<Op<'static>>, ()> {
         let res = match action.resolve() {
             Ok(res) => res,
@@ -41,28 +43,83 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
             }
         };
 
-        handle_res(res)
+        self.handle_template_res(res, path, &action.dest)
     }
-}
 
-#[inline]
-fn handle_res(res: Res) -> Result<Vec<Op<'static>>, ()> {
-    match res {
-        Res::Normal(ops) => {
-            // TODO: Output
-            Ok(map_ops(ops))
-        }
-        Res::OverwriteContents(ops) => {
-            // TODO: Output
-            Ok(map_ops(ops))
-        }
-        Res::OverwriteFile(ops) => {
-            // TODO: Output
-            Ok(map_ops(ops))
-        }
-        Res::Skip(_skip) => {
-            // TODO: Output
-            Ok(vec![])
+    #[inline]
+    pub fn resolve_gotmpl(
+        &self,
+        action: &GotmplAction,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        let res = match action.resolve() {
+            Ok(res) => res,
+            Err(_err) => {
+                // TODO: Output
+                return Err(());
+            }
+        };
+
+        self.handle_template_res(res, path, &action.dest)
+    }
+
+    #[inline]
+    pub fn resolve_pipe(
+        &self,
+        action: &PipeAction<'_>,
+        path: &CtxPath,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        let res = match action.resolve() {
+            Ok(res) => res,
+            Err(_err) => {
+                // TODO: Output
+                return Err(());
+            }
+        };
+
+        self.handle_template_res(res, path, &action.dest)
+    }
+
+    #[inline]
+    fn handle_template_res(
+        &self,
+        res: Res,
+        path: &CtxPath,
+        dest: &Path,
+    ) -> Result<Vec<Op<'static>>, ()> {
+        match res {
+            Res::Normal(ops) => {
+                // TODO: Output
+                Ok(map_ops(ops))
+            }
+            Res::OverwriteContents(ops) | Res::OverwriteFile(ops) => {
+                self.drifted(path, dest);
+                if let Some(backup) = super::write::backup(&ops, dest) {
+                    super::write::backing_up(dest, backup, self.opts.paths.home());
+                }
+                // TODO: Output
+                Ok(map_ops(ops))
+            }
+            Res::Skip(template::Skip::Conflict) => {
+                super::write::skipping_conflict(dest, self.opts.paths.home());
+                Ok(vec![])
+            }
+            Res::Skip(template::Skip::ParentMissing) => {
+                super::write::skipping_parent_missing(dest, self.opts.paths.home());
+                Ok(vec![])
+            }
+            Res::Skip(_skip) => {
+                // TODO: Output
+                Ok(vec![])
+            }
+            Res::Conflict => {
+                super::write::conflict(dest, self.opts.paths.home());
+                Err(())
+            }
+            Res::ParentMissing => {
+                super::write::parent_missing(dest, self.opts.paths.home());
+                Err(())
+            }
         }
     }
 }
@@ -72,9 +129,13 @@ fn map_ops(ops: Vec<template::Op>) -> Vec<Op<'static>> {
     ops.into_iter()
         .map(|op| match op {
             template::Op::Rm(op) => Op::Rm(op),
+            template::Op::Copy(op) => Op::Copy(op),
             template::Op::Create(op) => Op::Create(op),
             template::Op::Write(op) => Op::Write(op),
             template::Op::Mkdir(op) => Op::Mkdir(op),
+            template::Op::Chmod(op) => Op::Chmod(op),
+            #[cfg(unix)]
+            template::Op::Chown(op) => Op::Chown(op),
         })
         .collect()
 }
@@ -82,13 +143,13 @@ fn map_ops(ops: Vec<template::Op>) -> Vec<Op<'static>> {
 mod output {
     use std::path::Path;
 
-    use shelflib::action::{HandlebarsAction, LiquidAction};
+    use shelflib::action::{GotmplAction, HandlebarsAction, LiquidAction, PipeAction};
 
     use super::super::{describe, Describe, DescribeMode};
     use crate::ctxpath::CtxPath;
     use crate::output::{comb::sjoin4, Pretty};
 
-    impl Describe for HandlebarsAction {
+    impl Describe for HandlebarsAction<'_> {
         #[inline]
         fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
             let src = describe::path_relative(&self.src, path);
@@ -108,7 +169,35 @@ mod output {
             let src = describe::path_relative(&self.src, path);
             let dest = describe::dest_relative(&self.dest, dest);
             sjoin4(
-                "templating (handlebars)",
+                "templating (liquid)",
+                describe::mode_spath(src, mode),
+                "to",
+                describe::mode_spath(dest, mode),
+            )
+        }
+    }
+
+    impl Describe for GotmplAction {
+        #[inline]
+        fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+            let src = describe::path_relative(&self.src, path);
+            let dest = describe::dest_relative(&self.dest, dest);
+            sjoin4(
+                "templating (gotmpl)",
+                describe::mode_spath(src, mode),
+                "to",
+                describe::mode_spath(dest, mode),
+            )
+        }
+    }
+
+    impl<'lua> Describe for PipeAction<'lua> {
+        #[inline]
+        fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
+            let src = describe::path_relative(&self.src, path);
+            let dest = describe::dest_relative(&self.dest, dest);
+            sjoin4(
+                "piping",
                 describe::mode_spath(src, mode),
                 "to",
                 describe::mode_spath(dest, mode),
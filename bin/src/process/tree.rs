@@ -1,6 +1,13 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
 use shelflib::{
-    action::{link, tree::Res, Resolve, TreeAction},
-    op::Op,
+    action::{
+        link,
+        tree::{self, Error, Res},
+        Resolve, TreeAction,
+    },
+    op::{layout, Op},
 };
 
 use super::GraphProcessor;
@@ -10,13 +17,32 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
     #[inline]
     pub fn resolve_tree(
         &self,
-        action: TreeAction,
-        _path: &CtxPath,
+        action: &TreeAction,
+        path: &CtxPath,
     ) -> Result<Vec<Op<'static>>, ()> {
+        let copied;
+        let action =
+            if !action.copy && !action.hardlink && self.degrade(&action.dest, &action.dest, path) {
+                copied = TreeAction {
+                    copy: true,
+                    ..action.clone()
+                };
+                &copied
+            } else {
+                action
+            };
+
+        // Links to directories from earlier runs are converted into links to each file.
+        let links = self.journal.links_in_place();
+        let replaced = layout::dir_links(&action.dest, &links);
+        if !replaced.is_empty() {
+            return self.resolve_tree_converting(action, path, &replaced);
+        }
+
         let res = match action.resolve() {
             Ok(res) => res,
-            Err(_err) => {
-                // TODO: Output
+            Err(err) => {
+                output::error(&err, action, path, self.opts.paths.home());
                 return Err(());
             }
         };
@@ -26,14 +52,7 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
                 // TODO: Output
                 let ops = res
                     .into_iter()
-                    .flat_map(|res| match res {
-                        link::Res::Normal(ops) => super::link::map_ops(ops),
-                        link::Res::Overwrite(ops) => super::link::map_ops(ops),
-                        link::Res::Skip(_skip) => {
-                            // TODO: Output
-                            vec![]
-                        }
-                    })
+                    .flat_map(|res| self.map_link_res(res, action, path))
                     .collect();
                 Ok(ops)
             }
@@ -43,28 +62,172 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
             }
         }
     }
+
+    /// Resolve `action` after removing the `replaced` links to directories under its
+    /// destination. Files under them are linked as if nothing were there yet.
+    #[inline]
+    fn resolve_tree_converting(
+        &self,
+        action: &TreeAction,
+        path: &CtxPath,
+        replaced: &[&(PathBuf, PathBuf)],
+    ) -> Result<Vec<Op<'static>>, ()> {
+        let links = match action.links() {
+            Ok(Some(links)) => links,
+            Ok(None) => return Ok(vec![]),
+            Err(err) => {
+                output::error(&err, action, path, self.opts.paths.home());
+                return Err(());
+            }
+        };
+
+        for (link, _) in replaced {
+            output::converting(link, path);
+        }
+
+        let mut ops = layout::unlink_dirs(replaced);
+        let mut made = HashSet::new();
+        for file in links {
+            match replaced
+                .iter()
+                .find(|(root, _)| file.dest.starts_with(root))
+            {
+                Some((root, _)) => {
+                    let mkdirs = layout::mkdir_under(root, &file.dest, &mut made);
+                    ops.extend(mkdirs.into_iter().map(Op::Mkdir));
+                    ops.extend(super::link::map_ops(vec![file.create_op()]));
+                }
+                None => {
+                    let mut res = match file.resolve() {
+                        Err(link::Error::Conflict) => {
+                            let err = Error::Conflict(file.dest);
+                            output::error(&err, action, path, self.opts.paths.home());
+                            return Err(());
+                        }
+                        // SAFETY: Should be fine since all these files should exist?
+                        res => res.unwrap(),
+                    };
+                    tree::dedup_mkdirs(&mut res, &mut made);
+                    ops.extend(self.map_link_res(res, action, path));
+                }
+            }
+        }
+        Ok(ops)
+    }
+
+    #[inline]
+    fn map_link_res(
+        &self,
+        res: link::Res,
+        action: &TreeAction,
+        path: &CtxPath,
+    ) -> Vec<Op<'static>> {
+        match res {
+            link::Res::Normal(ops) => super::link::map_ops(ops),
+            link::Res::Overwrite(ops) => {
+                // Backups come first, so look from the end.
+                let dest = ops.iter().rev().find_map(|op| match op {
+                    link::Op::Link(op) => Some(&op.dest),
+                    link::Op::Copy(op) => Some(&op.dest),
+                    link::Op::Hardlink(op) => Some(&op.dest),
+                    link::Op::Rm(_) | link::Op::Mkdir(_) | link::Op::Chmod(_) => None,
+                    #[cfg(unix)]
+                    link::Op::Chown(_) => None,
+                });
+                let dest = dest.unwrap_or(&action.dest);
+                self.drifted(path, dest);
+                if let Some(backup) = super::link::backup(&ops, dest) {
+                    super::write::backing_up(dest, backup, self.opts.paths.home());
+                }
+                super::link::map_ops(ops)
+            }
+            link::Res::Skip(_skip) => {
+                // TODO: Output
+                vec![]
+            }
+        }
+    }
 }
 
 mod output {
     use std::path::Path;
 
-    use shelflib::action::TreeAction;
+    use shelflib::action::{
+        tree::{self, Error},
+        TreeAction,
+    };
 
     use super::super::{describe, Describe, DescribeMode};
     use crate::ctxpath::CtxPath;
-    use crate::output::{comb::sjoin4, Pretty};
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        Pretty, Step,
+    };
 
     impl Describe for TreeAction {
         #[inline]
         fn describe(&self, path: &CtxPath, dest: &Path, mode: DescribeMode) -> Pretty {
             let src = describe::path_relative(&self.src, path);
             let dest = describe::dest_relative(&self.dest, dest);
+            let verb = if self.copy {
+                "copying tree"
+            } else if self.hardlink {
+                "hardlinking tree"
+            } else {
+                "linking tree"
+            };
             sjoin4(
-                "linking tree",
+                verb,
                 describe::mode_spath(src, mode),
                 "to",
                 describe::mode_spath(dest, mode),
             )
         }
     }
+
+    #[inline]
+    pub fn error(err: &Error, action: &TreeAction, path: &CtxPath, dest: &Path) {
+        match err {
+            Error::SrcMissing => Step::error().message(sjoin2(
+                "missing source",
+                describe::spath_relative(&action.src, path),
+            )),
+            Error::LayerMissing(layer) => Step::error().message(sjoin2(
+                "missing layer",
+                describe::spath_relative(layer, path),
+            )),
+            Error::EscapingPattern { pattern, .. } => Step::error()
+                .message(sjoin2(
+                    "pattern could match files outside of the tree:",
+                    pattern,
+                ))
+                .reason("patterns must be relative, without '..' components"),
+            Error::Glob(err) => Step::error().message("couldn't glob files").reason(err),
+            Error::Pattern(err) => Step::error().message("invalid pattern").reason(err),
+            Error::IgnoreFile(err) => Step::error()
+                .message(sjoin2("invalid", tree::IGNORE_FILE))
+                .reason(err),
+            Error::Conflict(existing) => Step::error()
+                .message(sjoin2(
+                    "differing destination",
+                    describe::sdest_relative(existing, dest),
+                ))
+                .reason("conflicts are set to fail; move it aside, or pass --force to replace it"),
+            Error::ParentMissing(missing) => Step::error()
+                .message(sjoin2(
+                    "missing parent of",
+                    describe::sdest_relative(missing, dest),
+                ))
+                .reason("missing parents are set to error; create it, or set if_missing"),
+        };
+        Step::error().context(action.describe_info(path, dest));
+    }
+
+    #[inline]
+    pub fn converting(link: &Path, path: &CtxPath) {
+        Step::message(sjoin2(
+            "replacing link from an earlier run at",
+            describe::spath_relative(link, path),
+        ));
+    }
 }
@@ -1,6 +1,8 @@
+use std::path::Path;
+
 use shelflib::{
     action::{
-        write::{self, Res},
+        write::{self, Res, Skip},
         Resolve, WriteAction,
     },
     op::Op,
@@ -9,12 +11,16 @@ use shelflib::{
 use super::GraphProcessor;
 use crate::ctxpath::CtxPath;
 
+pub use self::output::{
+    backing_up, conflict, parent_missing, skipping_conflict, skipping_parent_missing,
+};
+
 impl<'p, 'g> GraphProcessor<'p, 'g> {
     #[inline]
     pub fn resolve_write(
         &self,
-        action: WriteAction,
-        _path: &CtxPath,
+        action: &WriteAction,
+        path: &CtxPath,
     ) -> Result<Vec<Op<'static>>, ()> {
         let res = action.resolve();
         match res {
@@ -22,30 +28,51 @@ impl<'p, 'g> GraphProcessor<'p, 'g> {
                 // TODO: Output
                 Ok(map_ops(ops))
             }
-            Res::OverwriteContents(ops) => {
+            Res::OverwriteContents(ops) | Res::OverwriteFile(ops) => {
+                self.drifted(path, &action.dest);
+                if let Some(backup) = backup(&ops, &action.dest) {
+                    output::backing_up(&action.dest, backup, self.opts.paths.home());
+                }
                 // TODO: Output
                 Ok(map_ops(ops))
             }
-            Res::OverwriteFile(ops) => {
-                // TODO: Output
-                Ok(map_ops(ops))
+            Res::Skip(Skip::Conflict) => {
+                output::skipping_conflict(&action.dest, self.opts.paths.home());
+                Ok(vec![])
             }
             Res::Skip(_skip) => {
                 // TODO: Output
                 Ok(vec![])
             }
+            Res::Conflict => {
+                output::conflict(&action.dest, self.opts.paths.home());
+                Err(())
+            }
         }
     }
 }
 
+/// Return the path that `ops`, which overwrite `dest`, first back it up to, if any.
 #[inline]
-fn map_ops(ops: Vec<write::Op>) -> Vec<Op<'static>> {
+pub fn backup<'a>(ops: &'a [write::Op], dest: &Path) -> Option<&'a Path> {
+    match ops.first() {
+        Some(write::Op::Copy(op)) if op.src == dest => Some(&op.dest),
+        _ => None,
+    }
+}
+
+#[inline]
+pub fn map_ops(ops: Vec<write::Op>) -> Vec<Op<'static>> {
     ops.into_iter()
         .map(|op| match op {
             write::Op::Rm(op) => Op::Rm(op),
+            write::Op::Copy(op) => Op::Copy(op),
             write::Op::Create(op) => Op::Create(op),
             write::Op::Write(op) => Op::Write(op),
             write::Op::Mkdir(op) => Op::Mkdir(op),
+            write::Op::Chmod(op) => Op::Chmod(op),
+            #[cfg(unix)]
+            write::Op::Chown(op) => Op::Chown(op),
         })
         .collect()
 }
@@ -57,7 +84,10 @@ mod output {
 
     use super::super::{describe, Describe, DescribeMode};
     use crate::ctxpath::CtxPath;
-    use crate::output::{comb::sjoin2, Pretty};
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        Pretty, Step,
+    };
 
     impl Describe for WriteAction {
         #[inline]
@@ -66,4 +96,54 @@ mod output {
             sjoin2("writing to", describe::mode_spath(dest, mode))
         }
     }
+
+    #[inline]
+    pub fn backing_up(existing: &Path, backup: &Path, dest: &Path) {
+        Step::message(sjoin4(
+            "backing up existing",
+            describe::sdest_relative(existing, dest),
+            "to",
+            describe::sdest_relative(backup, dest),
+        ));
+    }
+
+    #[inline]
+    pub fn skipping_conflict(existing: &Path, dest: &Path) {
+        Step::skipping()
+            .message(sjoin2(
+                "differing destination",
+                describe::sdest_relative(existing, dest),
+            ))
+            .reason("conflicts are set to be skipped");
+    }
+
+    #[inline]
+    pub fn skipping_parent_missing(missing: &Path, dest: &Path) {
+        Step::skipping()
+            .message(sjoin2(
+                "missing parent of",
+                describe::sdest_relative(missing, dest),
+            ))
+            .reason("missing parents are set to be skipped");
+    }
+
+    #[inline]
+    pub fn conflict(existing: &Path, dest: &Path) {
+        Step::error()
+            .message(sjoin2(
+                "differing destination",
+                describe::sdest_relative(existing, dest),
+            ))
+            .reason("conflicts are set to fail; move it aside, or pass --force to replace it");
+    }
+
+    #[inline]
+    pub fn parent_missing(missing: &Path, dest: &Path) {
+        Step::error()
+            .message(sjoin2(
+                "missing parent of",
+                describe::sdest_relative(missing, dest),
+            ))
+            .reason("missing parents are set to error; create it, or set if_missing");
+    }
 }
@@ -0,0 +1,65 @@
+//! Addressing of packages by name, as subdirectories of a root directory of packages (e.g.
+//! `~/.dotfiles`), as well as by path.
+
+use std::path::{Component, Path, PathBuf};
+
+/// Root directory of packages, if any, by which packages can be named.
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    root: Option<PathBuf>,
+}
+
+impl Registry {
+    #[inline]
+    pub fn new(root: Option<PathBuf>) -> Self {
+        Self { root }
+    }
+
+    /// Resolve `package`, a name or a path, to the path of the package. A single normal component
+    /// is taken as the name of a subdirectory of the root, if there is one; anything else, such as
+    /// `./zsh`, is a path.
+    #[inline]
+    pub fn resolve<P>(&self, package: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let package = package.as_ref();
+        let mut components = package.components();
+        let named = match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => self
+                .root
+                .as_ref()
+                .map(|root| root.join(package))
+                .filter(|path| path.is_dir()),
+            _ => None,
+        };
+
+        named.unwrap_or_else(|| package.to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use tempfile::TempDir;
+
+    use super::Registry;
+
+    #[test]
+    fn test_resolve() -> std::io::Result<()> {
+        let dir = TempDir::new()?;
+        fs::create_dir(dir.path().join("zsh"))?;
+        let registry = Registry::new(Some(dir.path().to_path_buf()));
+
+        assert_eq!(registry.resolve("zsh"), dir.path().join("zsh"));
+        // Paths, and names that aren't in the root, are left alone.
+        assert_eq!(registry.resolve("./zsh"), Path::new("./zsh"));
+        assert_eq!(registry.resolve("a/zsh"), Path::new("a/zsh"));
+        assert_eq!(registry.resolve("nvim"), Path::new("nvim"));
+        assert_eq!(Registry::default().resolve("zsh"), Path::new("zsh"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use shelflib::{
+    action::{link, tree, Action, Resolve},
+    fse,
+    graph::PathResolver,
+    op::{ctx::FinishCtx, journal::OpJournal, LinkOp, RmOp},
+};
+
+use crate::load::Loaded;
+
+/// Broken symlink found under the destination.
+#[derive(Debug)]
+struct Broken {
+    link: PathBuf,
+    target: PathBuf,
+}
+
+/// Scan the destination roots for broken symlinks that point into the sources of the loaded
+/// packages. Those that are still managed by a symlinking directive are re-pointed to their
+/// current source; the others are removed. If `noop` is set, the changes are only reported.
+#[inline]
+pub fn repair(
+    loaded: &Loaded,
+    paths: &PathResolver,
+    max_depth: usize,
+    noop: bool,
+    ctx: &FinishCtx,
+) -> Result<(), ()> {
+    let order = match loaded.graph.order() {
+        Ok(order) => order,
+        Err(err) => {
+            output::error_circular(err);
+            return Err(());
+        }
+    };
+
+    let mut sources = Vec::new();
+    let mut expected = HashMap::new();
+    for pd in order {
+        sources.push(pd.path.clone());
+        for action in pd.action_iter(paths) {
+            expected_links(action, &mut expected);
+        }
+    }
+
+    let dest = paths.home();
+    output::repairing(dest);
+    let mut broken = Vec::new();
+    for root in paths.roots() {
+        scan(&root, &sources, max_depth, &mut broken);
+    }
+    if broken.is_empty() {
+        output::none_broken();
+        return Ok(());
+    }
+
+    let mut journal = OpJournal::new();
+    let mut success = true;
+    for Broken { link, target } in broken {
+        // Re-point only to sources that still exist, otherwise the link stays broken.
+        let src = expected
+            .get(&link)
+            .filter(|src| fs::symlink_metadata(src).is_ok());
+
+        if noop {
+            match src {
+                Some(src) => output::would_repoint(dest, &link, src),
+                None => output::would_remove(dest, &link, &target),
+            }
+            continue;
+        }
+
+        // Each repair is committed in its own transaction.
+        let mut t = journal.lock();
+        let rm = RmOp {
+            path: link.clone(),
+            dir: false,
+        };
+        let res = match t.append_finish(rm, ctx) {
+            Ok(_) => match src {
+                Some(src) => {
+                    let op = LinkOp {
+                        src: src.clone(),
+                        dest: link.clone(),
+                        fallback: false,
+                    };
+                    t.append_finish(op, ctx)
+                        .map(|_| output::repointed(dest, &link, src))
+                        .map_err(|err| err.inner.to_string())
+                }
+                None => {
+                    output::removed(dest, &link, &target);
+                    Ok(())
+                }
+            },
+            Err(err) => Err(err.inner.to_string()),
+        };
+
+        if let Err(err) = res {
+            output::repair_error(dest, &link, err);
+            success = false;
+        }
+    }
+
+    if success {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+/// Record the symlinks that `action` creates, keyed by destination. Copies and hard links are
+/// never broken symlinks.
+#[inline]
+fn expected_links(action: Action<'_>, expected: &mut HashMap<PathBuf, PathBuf>) {
+    match action {
+        Action::Link(action) if !action.copy && !action.hardlink => {
+            expected.insert(action.dest, action.src);
+        }
+        // Trees are resolved to find the files under them.
+        Action::Tree(action) if !action.copy && !action.hardlink => {
+            if let Ok(tree::Res::Normal(reses)) = action.resolve() {
+                let ops = reses.into_iter().flat_map(|res| match res {
+                    link::Res::Normal(ops) | link::Res::Overwrite(ops) => ops,
+                    link::Res::Skip(_) => Vec::new(),
+                });
+                for op in ops {
+                    if let link::Op::Link(op) = op {
+                        expected.insert(op.dest, op.src);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Collect the broken symlinks under `dir` that point into one of `sources`. Symlinked
+/// directories and the sources themselves are not descended into.
+#[inline]
+fn scan(dir: &Path, sources: &[PathBuf], depth: usize, broken: &mut Vec<Broken>) {
+    // Unreadable directories can't contain links shelf created.
+    let dirents = match fs::read_dir(dir) {
+        Ok(dirents) => dirents,
+        Err(_) => return,
+    };
+
+    for dirent in dirents.flatten() {
+        let path = dirent.path();
+        let file_type = match dirent.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+
+        if file_type.is_symlink() {
+            let target = match fs::read_link(&path) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+            // SAFETY: `path` was read from a directory, so it has a parent.
+            let target = fse::clean(path.parent().unwrap().join(target));
+            let dangling = fs::metadata(&path).is_err();
+            if dangling && sources.iter().any(|src| target.starts_with(src)) {
+                broken.push(Broken { link: path, target });
+            }
+        } else if file_type.is_dir()
+            && depth > 0
+            && !sources.iter().any(|src| path.starts_with(src))
+        {
+            scan(&path, sources, depth - 1, broken);
+        }
+    }
+}
+
+mod output {
+    use std::fmt::Display;
+    use std::path::Path;
+
+    use shelflib::graph::CircularDependencyError;
+
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        spath, Pretty, Section, Step,
+    };
+
+    #[inline]
+    fn sdest_relative(target: &Path, dest: &Path) -> Pretty {
+        // SAFETY: `dest` is absolute.
+        spath(CtxPath::new(target, dest).unwrap().rel())
+    }
+
+    #[inline]
+    pub fn repairing(dest: &Path) {
+        Section::message("repairing", spath(dest));
+    }
+
+    #[inline]
+    pub fn none_broken() {
+        Step::message("no broken links found");
+    }
+
+    #[inline]
+    pub fn repointed(dest: &Path, link: &Path, src: &Path) {
+        Step::message(sjoin4(
+            "re-pointed",
+            sdest_relative(link, dest),
+            "to",
+            spath(src),
+        ));
+    }
+
+    #[inline]
+    pub fn would_repoint(dest: &Path, link: &Path, src: &Path) {
+        Step::message(sjoin4(
+            "would re-point",
+            sdest_relative(link, dest),
+            "to",
+            spath(src),
+        ));
+    }
+
+    #[inline]
+    pub fn removed(dest: &Path, link: &Path, target: &Path) {
+        Step::message(sjoin4(
+            "removed",
+            sdest_relative(link, dest),
+            "which pointed to",
+            spath(target),
+        ));
+    }
+
+    #[inline]
+    pub fn would_remove(dest: &Path, link: &Path, target: &Path) {
+        Step::message(sjoin4(
+            "would remove",
+            sdest_relative(link, dest),
+            "which points to",
+            spath(target),
+        ));
+    }
+
+    #[inline]
+    pub fn repair_error(dest: &Path, link: &Path, err: impl Display) {
+        Section::error()
+            .message(sjoin2("couldn't repair", sdest_relative(link, dest)))
+            .reason(err);
+    }
+
+    #[inline]
+    pub fn error_circular(err: CircularDependencyError) {
+        Section::error().message("circular dependency detected");
+        Section::error().context(err.path().display());
+    }
+}
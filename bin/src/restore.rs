@@ -0,0 +1,156 @@
+use std::fs;
+use std::path::Path;
+
+use shelflib::{
+    action::mkdir,
+    op::{ctx::FinishCtx, journal::OpJournal, CopyOp, LinkOp, RmOp},
+    originals::{Original, OriginalStore},
+};
+
+/// Restore what was at `dest` before shelf first took it over, replacing whatever is there now;
+/// if nothing was there, `dest` is removed. The replaced file is backed up like any other that
+/// shelf removes. If `noop` is set, the restoration is only reported.
+#[inline]
+pub fn restore_original(
+    store: &OriginalStore,
+    dest: &Path,
+    home: &Path,
+    noop: bool,
+    ctx: &FinishCtx,
+) -> Result<(), ()> {
+    let original = match store.get(dest) {
+        Ok(Some(Original { path, .. })) => path,
+        Ok(None) => {
+            output::never_taken_over(dest, home);
+            return Err(());
+        }
+        Err(err) => {
+            output::read_error(dest, home, err);
+            return Err(());
+        }
+    };
+
+    let exists = fs::symlink_metadata(dest).ok();
+    if original.is_none() && exists.is_none() {
+        output::nothing_original(dest, home);
+        return Ok(());
+    }
+
+    if noop {
+        output::would_restore(dest, home);
+        return Ok(());
+    }
+
+    let mut journal = OpJournal::new();
+    let mut t = journal.lock();
+    let res: Result<(), String> = (|| {
+        if let Some(metadata) = &exists {
+            let rm = RmOp {
+                path: dest.to_path_buf(),
+                dir: metadata.is_dir(),
+            };
+            t.append_finish(rm, ctx)
+                .map_err(|err| err.inner.to_string())?;
+        } else if original.is_some() {
+            for op in mkdir::mkdir_parents_ops(dest) {
+                t.append_finish(op, ctx)
+                    .map_err(|err| err.inner.to_string())?;
+            }
+        }
+
+        let original = match &original {
+            Some(original) => original,
+            None => return Ok(()),
+        };
+        let metadata = fs::symlink_metadata(original).map_err(|err| err.to_string())?;
+        if metadata.file_type().is_symlink() {
+            let op = LinkOp {
+                src: fs::read_link(original).map_err(|err| err.to_string())?,
+                dest: dest.to_path_buf(),
+                fallback: false,
+            };
+            t.append_finish(op, ctx)
+                .map_err(|err| err.inner.to_string())?;
+        } else {
+            let op = CopyOp {
+                src: original.clone(),
+                dest: dest.to_path_buf(),
+                dir: metadata.is_dir(),
+            };
+            t.append_finish(op, ctx)
+                .map_err(|err| err.inner.to_string())?;
+        }
+
+        Ok(())
+    })();
+
+    match res {
+        Ok(()) => {
+            output::restored(dest, home);
+            Ok(())
+        }
+        Err(err) => {
+            output::restore_error(dest, home, err);
+            Err(())
+        }
+    }
+}
+
+mod output {
+    use std::fmt::Display;
+    use std::path::Path;
+
+    use crate::ctxpath::CtxPath;
+    use crate::output::{comb::sjoin2, spath, Pretty, Section};
+
+    #[inline]
+    fn sdest_relative(target: &Path, dest: &Path) -> Pretty {
+        // SAFETY: `dest` is absolute.
+        spath(CtxPath::new(target, dest).unwrap().rel())
+    }
+
+    #[inline]
+    pub fn restored(dest: &Path, home: &Path) {
+        Section::message("restored the original of", sdest_relative(dest, home));
+    }
+
+    #[inline]
+    pub fn would_restore(dest: &Path, home: &Path) {
+        Section::message("would restore the original of", sdest_relative(dest, home));
+    }
+
+    #[inline]
+    pub fn nothing_original(dest: &Path, home: &Path) {
+        Section::message(
+            "nothing to restore;",
+            sjoin2(
+                sdest_relative(dest, home),
+                "didn't exist before shelf created it, and doesn't now",
+            ),
+        );
+    }
+
+    #[inline]
+    pub fn never_taken_over(dest: &Path, home: &Path) {
+        Section::error()
+            .message(sjoin2("no original of", sdest_relative(dest, home)))
+            .reason("shelf has never replaced or created it");
+    }
+
+    #[inline]
+    pub fn read_error(dest: &Path, home: &Path, err: impl std::error::Error) {
+        Section::error()
+            .message(sjoin2(
+                "couldn't read the original of",
+                sdest_relative(dest, home),
+            ))
+            .reason(err);
+    }
+
+    #[inline]
+    pub fn restore_error(dest: &Path, home: &Path, err: impl Display) {
+        Section::error()
+            .message(sjoin2("couldn't restore", sdest_relative(dest, home)))
+            .reason(err);
+    }
+}
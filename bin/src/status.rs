@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use shelflib::{
+    graph::PathResolver,
+    op::reconcile::{DestStatus, Reconciliation},
+};
+
+use crate::load::Loaded;
+use crate::process::Summary;
+
+/// Report the state of the destinations of the loaded packages, according to `rec`: those
+/// that are in place, those that have drifted since they were applied, and the atoms that were
+/// never committed. Destinations are attributed to the package with a directive for them, or
+/// whose directory contains their source (e.g. files in trees).
+#[inline]
+pub fn status(loaded: &Loaded, paths: &PathResolver, rec: &Reconciliation) -> Result<Summary, ()> {
+    let order = match loaded.graph.order() {
+        Ok(order) => order,
+        Err(err) => {
+            output::error_circular(err);
+            return Err(());
+        }
+    };
+    let packages: Vec<_> = order.map(|pd| pd.path.clone()).collect();
+
+    let managed: HashMap<_, _> = loaded
+        .graph
+        .managed(paths)
+        .into_iter()
+        .map(|managed| (managed.dest, managed.package))
+        .collect();
+    let package_of = |status: &DestStatus| -> Option<PathBuf> {
+        managed.get(&status.dest).cloned().or_else(|| {
+            let src = status.src.as_ref()?;
+            packages
+                .iter()
+                .filter(|package| src.starts_with(package))
+                .max_by_key(|package| package.components().count())
+                .cloned()
+        })
+    };
+
+    let mut by_package: HashMap<PathBuf, Vec<&DestStatus>> = HashMap::new();
+    for status in &rec.dests {
+        if let Some(package) = package_of(status) {
+            by_package.entry(package).or_default().push(status);
+        }
+    }
+
+    let home = paths.home();
+    let mut summary = Summary::default();
+    for package in &packages {
+        // SAFETY: Path guaranteed to be in it by `load`.
+        let path = loaded.paths.get(package).unwrap();
+        output::package(path);
+
+        let dests = match by_package.get(package) {
+            Some(dests) => dests,
+            None => {
+                output::nothing_applied();
+                continue;
+            }
+        };
+        for status in dests {
+            match &status.drift {
+                None => output::in_place(home, status),
+                Some(drift) => {
+                    summary.drift += 1;
+                    output::drifted(home, status, drift);
+                }
+            }
+        }
+    }
+
+    if let Some(uncommitted) = &rec.uncommitted {
+        output::uncommitted(home, uncommitted.seq, &uncommitted.dests);
+    }
+
+    Ok(summary)
+}
+
+mod output {
+    use std::path::{Path, PathBuf};
+
+    use shelflib::{
+        graph::CircularDependencyError,
+        op::reconcile::{DestStatus, Drift, Expected},
+    };
+
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin3, sjoin4},
+        spath, Pretty, Section, Step,
+    };
+
+    #[inline]
+    fn sdest_relative(target: &Path, dest: &Path) -> Pretty {
+        // SAFETY: `dest` is absolute.
+        spath(CtxPath::new(target, dest).unwrap().rel())
+    }
+
+    #[inline]
+    pub fn package(path: &CtxPath) {
+        Section::message("status of", spath(path.rel()));
+    }
+
+    #[inline]
+    pub fn nothing_applied() {
+        Step::message("nothing recorded as applied");
+    }
+
+    #[inline]
+    pub fn in_place(home: &Path, status: &DestStatus) {
+        let dest = sdest_relative(&status.dest, home);
+        match &status.expected {
+            Expected::Link(src) => Step::message(sjoin4("linked", dest, "to", spath(src))),
+            _ => Step::message(sjoin2(dest, "is in place")),
+        }
+    }
+
+    #[inline]
+    pub fn drifted(home: &Path, status: &DestStatus, drift: &Drift) {
+        let dest = sdest_relative(&status.dest, home);
+        let message = match (drift, &status.expected) {
+            (Drift::Missing, _) => sjoin2(dest, "is missing"),
+            (Drift::Retargeted(target), Expected::Link(src)) => sjoin2(
+                sjoin3(dest, "points to", spath(target)),
+                sjoin2("instead of", spath(src)),
+            ),
+            (Drift::Retargeted(target), _) => sjoin3(dest, "points to", spath(target)),
+            (Drift::Changed, Expected::Link(_)) => sjoin2(dest, "is no longer a symlink"),
+            (Drift::Changed, _) => sjoin2(dest, "has changed since it was applied"),
+        };
+        Step::warning().message(message);
+    }
+
+    #[inline]
+    pub fn uncommitted(home: &Path, seq: u64, dests: &[PathBuf]) {
+        Section::warning().message(format!(
+            "transaction starting at record {} was never committed",
+            seq
+        ));
+        for dest in dests {
+            Step::warning().message(sjoin2("changed", sdest_relative(dest, home)));
+        }
+    }
+
+    #[inline]
+    pub fn error_circular(err: CircularDependencyError) {
+        Section::error().message("circular dependency detected");
+        Section::error().context(err.path().display());
+    }
+}
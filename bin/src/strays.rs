@@ -0,0 +1,342 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use shelflib::{
+    action::{tree::TreeAction, volatile::Volatile, Action},
+    graph::PathResolver,
+    op::{ctx::FinishCtx, journal::OpJournal, CopyOp, HardlinkOp, LinkOp, RmOp},
+};
+
+use crate::load::Loaded;
+
+/// What to do with stray files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handling {
+    /// Only report them.
+    Report,
+    /// Move them into the source of their tree, and place them back as the tree would.
+    Adopt,
+    /// Remove them, keeping them in the file safe.
+    Delete,
+}
+
+/// File under the destination of a tree that no directive produces.
+#[derive(Debug)]
+struct Stray {
+    path: PathBuf,
+    /// Where the file would be adopted to in the source of the tree.
+    adopted: PathBuf,
+    copy: bool,
+    hardlink: bool,
+    fallback: bool,
+}
+
+/// Scan the destinations of the trees of the loaded packages for stray files: those that aren't
+/// produced by any directive, according to the plan, and that shelf hasn't placed, according to
+/// `journal`. Trees narrowed with `only` don't manage their whole destination, and volatile paths
+/// belong to applications, so neither is scanned. Strays are handled according to `handling`; if
+/// `noop` is set, what would be done is only reported.
+#[inline]
+pub fn strays(
+    loaded: &Loaded,
+    paths: &PathResolver,
+    journal: &mut OpJournal,
+    handling: Handling,
+    noop: bool,
+    ctx: &FinishCtx,
+) -> Result<(), ()> {
+    let order = match loaded.graph.order() {
+        Ok(order) => order,
+        Err(err) => {
+            output::error_circular(err);
+            return Err(());
+        }
+    };
+
+    let mut managed: HashSet<_> = loaded
+        .graph
+        .managed(paths)
+        .into_iter()
+        .map(|managed| managed.dest)
+        .collect();
+    managed.extend(journal.reconcile().dests.into_iter().map(|dest| dest.dest));
+
+    let mut trees = Vec::new();
+    for pd in order {
+        for action in pd.action_iter(paths) {
+            if let Action::Tree(action) = action {
+                // Trees whose files can't be listed have nothing to compare against.
+                if let Ok(Some(links)) = action.links() {
+                    managed.extend(links.into_iter().map(|link| link.dest));
+                    if action.only.is_none() {
+                        trees.push(action);
+                    }
+                }
+            }
+        }
+    }
+
+    let home = paths.home();
+    output::scanning(home);
+    let mut seen = HashSet::new();
+    let mut strays = Vec::new();
+    for tree in &trees {
+        let volatile = match Volatile::new(&tree.volatile) {
+            Ok(volatile) => volatile,
+            Err(_) => continue,
+        };
+        let root = tree.paths.remap(&tree.dest);
+        scan(
+            tree,
+            &root,
+            &root,
+            &volatile,
+            &managed,
+            &mut seen,
+            &mut strays,
+        );
+    }
+    if strays.is_empty() {
+        output::none_found();
+        return Ok(());
+    }
+
+    let mut success = true;
+    for stray in strays {
+        let res = match (handling, noop) {
+            (Handling::Report, _) => {
+                output::stray(home, &stray.path);
+                Ok(())
+            }
+            (Handling::Adopt, true) => {
+                output::would_adopt(home, &stray.path, &stray.adopted);
+                Ok(())
+            }
+            (Handling::Delete, true) => {
+                output::would_delete(home, &stray.path);
+                Ok(())
+            }
+            (Handling::Adopt, false) => adopt(&stray, journal, ctx)
+                .map(|_| output::adopted(home, &stray.path, &stray.adopted)),
+            (Handling::Delete, false) => {
+                delete(&stray, journal, ctx).map(|_| output::deleted(home, &stray.path))
+            }
+        };
+
+        if let Err(err) = res {
+            output::stray_error(home, &stray.path, err);
+            success = false;
+        }
+    }
+
+    if success {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+/// Collect the files under `dir`, in the destination `root` of `tree`, that are neither managed
+/// nor volatile. Symlinked directories are not descended into.
+#[inline]
+fn scan(
+    tree: &TreeAction,
+    root: &Path,
+    dir: &Path,
+    volatile: &Volatile,
+    managed: &HashSet<PathBuf>,
+    seen: &mut HashSet<PathBuf>,
+    strays: &mut Vec<Stray>,
+) {
+    // Destinations that don't exist yet have no strays.
+    let dirents = match fs::read_dir(dir) {
+        Ok(dirents) => dirents,
+        Err(_) => return,
+    };
+
+    for dirent in dirents.flatten() {
+        let path = dirent.path();
+        let file_type = match dirent.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+
+        // SAFETY: `path` is under `root`.
+        let rel = path.strip_prefix(root).unwrap();
+        if managed.contains(&path) || volatile.matches(rel) {
+            continue;
+        }
+
+        if file_type.is_dir() {
+            scan(tree, root, &path, volatile, managed, seen, strays);
+        } else if seen.insert(path.clone()) {
+            // Trees may share destinations with other trees.
+            let mut adopted = tree.src.clone();
+            if let Some(prefix) = &tree.strip_prefix {
+                adopted.push(prefix);
+            }
+            adopted.push(rel);
+
+            strays.push(Stray {
+                path,
+                adopted,
+                copy: tree.copy,
+                hardlink: tree.hardlink,
+                fallback: tree.fallback,
+            });
+        }
+    }
+}
+
+/// Copy `stray` into the source of its tree, and replace it with the file placed from there. The
+/// original is kept in the file safe, so that unlinking the package restores it.
+#[inline]
+fn adopt(stray: &Stray, journal: &mut OpJournal, ctx: &FinishCtx) -> Result<(), String> {
+    let Stray {
+        path,
+        adopted,
+        copy,
+        hardlink,
+        fallback,
+    } = stray;
+
+    let meta = fs::symlink_metadata(path).map_err(|err| err.to_string())?;
+    if !meta.is_file() {
+        return Err("only regular files can be adopted".to_string());
+    }
+    if fs::symlink_metadata(adopted).is_ok() {
+        return Err(format!("{} already exists", adopted.display()));
+    }
+
+    // The source is edited directly; it belongs to the package, not to the journal.
+    if let Some(parent) = adopted.parent() {
+        fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+    fs::copy(path, adopted).map_err(|err| err.to_string())?;
+
+    // Each adoption is committed in its own transaction.
+    let mut t = journal.lock();
+    let rm = RmOp {
+        path: path.clone(),
+        dir: false,
+    };
+    t.append_finish(rm, ctx)
+        .map_err(|err| err.inner.to_string())?;
+
+    let (src, dest) = (adopted.clone(), path.clone());
+    if *copy {
+        let op = CopyOp {
+            src,
+            dest,
+            dir: false,
+        };
+        t.append_finish(op, ctx)
+            .map(|_| ())
+            .map_err(|err| err.inner.to_string())
+    } else if *hardlink {
+        let op = HardlinkOp { src, dest };
+        t.append_finish(op, ctx)
+            .map(|_| ())
+            .map_err(|err| err.inner.to_string())
+    } else {
+        let op = LinkOp {
+            src,
+            dest,
+            fallback: *fallback,
+        };
+        t.append_finish(op, ctx)
+            .map(|_| ())
+            .map_err(|err| err.inner.to_string())
+    }
+}
+
+/// Remove `stray`, keeping it in the file safe.
+#[inline]
+fn delete(stray: &Stray, journal: &mut OpJournal, ctx: &FinishCtx) -> Result<(), String> {
+    let mut t = journal.lock();
+    let rm = RmOp {
+        path: stray.path.clone(),
+        dir: false,
+    };
+    t.append_finish(rm, ctx)
+        .map(|_| ())
+        .map_err(|err| err.inner.to_string())
+}
+
+mod output {
+    use std::fmt::Display;
+    use std::path::Path;
+
+    use shelflib::graph::CircularDependencyError;
+
+    use crate::ctxpath::CtxPath;
+    use crate::output::{
+        comb::{sjoin2, sjoin4},
+        spath, Pretty, Section, Step,
+    };
+
+    #[inline]
+    fn sdest_relative(target: &Path, dest: &Path) -> Pretty {
+        // SAFETY: `dest` is absolute.
+        spath(CtxPath::new(target, dest).unwrap().rel())
+    }
+
+    #[inline]
+    pub fn scanning(dest: &Path) {
+        Section::message("scanning for strays in", spath(dest));
+    }
+
+    #[inline]
+    pub fn none_found() {
+        Step::message("no stray files found");
+    }
+
+    #[inline]
+    pub fn stray(dest: &Path, path: &Path) {
+        Step::message(sjoin2("stray", sdest_relative(path, dest)));
+    }
+
+    #[inline]
+    pub fn adopted(dest: &Path, path: &Path, src: &Path) {
+        Step::message(sjoin4(
+            "adopted",
+            sdest_relative(path, dest),
+            "into",
+            spath(src),
+        ));
+    }
+
+    #[inline]
+    pub fn would_adopt(dest: &Path, path: &Path, src: &Path) {
+        Step::message(sjoin4(
+            "would adopt",
+            sdest_relative(path, dest),
+            "into",
+            spath(src),
+        ));
+    }
+
+    #[inline]
+    pub fn deleted(dest: &Path, path: &Path) {
+        Step::message(sjoin2("deleted", sdest_relative(path, dest)));
+    }
+
+    #[inline]
+    pub fn would_delete(dest: &Path, path: &Path) {
+        Step::message(sjoin2("would delete", sdest_relative(path, dest)));
+    }
+
+    #[inline]
+    pub fn stray_error(dest: &Path, path: &Path, err: impl Display) {
+        Section::error()
+            .message(sjoin2("couldn't handle", sdest_relative(path, dest)))
+            .reason(err);
+    }
+
+    #[inline]
+    pub fn error_circular(err: CircularDependencyError) {
+        Section::error().message("circular dependency detected");
+        Section::error().context(err.path().display());
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use shelflib::{
+    graph::PathResolver,
+    journal::Rollback,
+    op::{
+        ctx::FinishCtx,
+        journal::{JournalOpFinish, OpJournal},
+    },
+};
+
+use crate::load::Loaded;
+
+/// Undo what shelf did for the loaded packages, dependents first, by rolling back the ops in
+/// `journal` that are still in effect on their destinations: links and files are removed, the
+/// files that they replaced are restored from the file safe, and directories created for them
+/// are removed if empty. Destinations are attributed to packages as in `status`. If `noop` is
+/// set, what would be undone is only reported.
+#[inline]
+pub fn unlink(
+    loaded: &Loaded,
+    paths: &PathResolver,
+    journal: &mut OpJournal,
+    noop: bool,
+    ctx: &FinishCtx,
+) -> Result<(), ()> {
+    let order: Vec<_> = match loaded.graph.order() {
+        Ok(order) => order.map(|pd| pd.path.clone()).collect(),
+        Err(err) => {
+            output::error_circular(err);
+            return Err(());
+        }
+    };
+
+    let managed: Vec<_> = loaded.graph.managed(paths);
+    let home = paths.home();
+    for package in order.iter().rev() {
+        // SAFETY: Path guaranteed to be in it by `load`.
+        let path = loaded.paths.get(package).unwrap();
+        output::package(path, noop);
+
+        let dests: HashSet<_> = managed
+            .iter()
+            .filter(|managed| &managed.package == package)
+            .map(|managed| managed.dest.as_path())
+            .collect();
+        let ops = journal.in_effect(|dest, src| {
+            dests.contains(dest) || src.is_some_and(|src| src.starts_with(package))
+        });
+        if ops.is_empty() {
+            output::nothing_applied();
+            continue;
+        }
+
+        if noop {
+            for fin in &ops {
+                output::would_undo(home, fin);
+            }
+            continue;
+        }
+
+        let mut t = journal.lock();
+        for fin in ops {
+            if let JournalOpFinish::Mkdir(fin) = &fin {
+                if !is_empty_dir(&fin.path) {
+                    output::not_empty(home, &fin.path);
+                    continue;
+                }
+            }
+
+            if let Err(err) = t.append_finish(fin.rollback(), ctx) {
+                output::undo_error(home, &fin, err.inner);
+                return Err(());
+            }
+            output::undone(home, &fin);
+        }
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
+
+#[inline]
+fn dest_of(fin: &JournalOpFinish) -> &Path {
+    // SAFETY: Only ops with destinations are in effect.
+    fin.dest().unwrap()
+}
+
+mod output {
+    use std::path::Path;
+
+    use shelflib::{graph::CircularDependencyError, op::journal::JournalOpFinish};
+
+    use crate::ctxpath::CtxPath;
+    use crate::output::{comb::sjoin2, spath, Pretty, Section, Step};
+
+    #[inline]
+    fn sdest_relative(target: &Path, dest: &Path) -> Pretty {
+        // SAFETY: `dest` is absolute.
+        spath(CtxPath::new(target, dest).unwrap().rel())
+    }
+
+    /// Return the present and past tense of what undoing `fin` does.
+    #[inline]
+    fn verbs(fin: &JournalOpFinish) -> (&'static str, &'static str) {
+        match fin {
+            JournalOpFinish::Link(_) => ("unlink", "unlinked"),
+            JournalOpFinish::Mkdir(_) => ("remove directory", "removed directory"),
+            JournalOpFinish::Rm(_) => ("restore", "restored"),
+            JournalOpFinish::Copy(_)
+            | JournalOpFinish::Hardlink(_)
+            | JournalOpFinish::CopyDir(_)
+            | JournalOpFinish::Create(_) => ("remove", "removed"),
+            _ => ("revert", "reverted"),
+        }
+    }
+
+    #[inline]
+    pub fn package(path: &CtxPath, noop: bool) {
+        let verb = if noop { "would unlink" } else { "unlinking" };
+        Section::message(verb, spath(path.rel()));
+    }
+
+    #[inline]
+    pub fn nothing_applied() {
+        Step::message("nothing recorded as applied");
+    }
+
+    #[inline]
+    pub fn undone(home: &Path, fin: &JournalOpFinish) {
+        let dest = sdest_relative(super::dest_of(fin), home);
+        Step::message(sjoin2(verbs(fin).1, dest));
+    }
+
+    #[inline]
+    pub fn would_undo(home: &Path, fin: &JournalOpFinish) {
+        let dest = sdest_relative(super::dest_of(fin), home);
+        Step::message(sjoin2(format!("would {}", verbs(fin).0), dest));
+    }
+
+    #[inline]
+    pub fn not_empty(home: &Path, dir: &Path) {
+        Step::skipping().message(sjoin2(
+            sdest_relative(dir, home),
+            "is not empty, so leaving it",
+        ));
+    }
+
+    #[inline]
+    pub fn undo_error(home: &Path, fin: &JournalOpFinish, err: impl std::error::Error) {
+        Step::error()
+            .message(sjoin2(
+                "couldn't undo changes to",
+                sdest_relative(super::dest_of(fin), home),
+            ))
+            .reason(err);
+    }
+
+    #[inline]
+    pub fn error_circular(err: CircularDependencyError) {
+        Section::error().message("circular dependency detected");
+        Section::error().context(err.path().display());
+    }
+}
@@ -0,0 +1,94 @@
+//! Template variables given on the command line with `--set`.
+
+use indexmap::IndexMap;
+use shelflib::spec::{Object, ObjectValue};
+
+/// Parse `assignments` of the form `key=value` into an object of variables, in order; later
+/// assignments take precedence. Keys are dotted paths into nested objects, e.g. `colors.bg=#000`.
+/// Values are parsed as JSON where possible, so that `true`, `1.5`, and `[1, 2]` are typed, and
+/// are otherwise strings; quote a value (`'"1"'`) to keep it a string.
+#[inline]
+pub fn parse(assignments: &[String]) -> Result<Object, String> {
+    let mut vars = IndexMap::new();
+    for assignment in assignments {
+        let (key, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value, got '{}'", assignment))?;
+        let path: Vec<_> = key.split('.').collect();
+        if path.iter().any(|part| part.is_empty()) {
+            return Err(format!("invalid variable name '{}'", key));
+        }
+
+        let value = serde_json::from_str(value).unwrap_or_else(|_| ObjectValue::Str(value.into()));
+        set(&mut vars, &path, value);
+    }
+
+    Ok(Object(vars))
+}
+
+/// Set the variable at `path` in `vars`, replacing whatever is in the way.
+#[inline]
+fn set(vars: &mut IndexMap<String, ObjectValue>, path: &[&str], value: ObjectValue) {
+    match path {
+        [] => {}
+        [key] => {
+            vars.insert(key.to_string(), value);
+        }
+        [key, rest @ ..] => {
+            let entry = vars
+                .entry(key.to_string())
+                .or_insert_with(|| ObjectValue::Object(IndexMap::new()));
+            if !matches!(entry, ObjectValue::Object(_)) {
+                *entry = ObjectValue::Object(IndexMap::new());
+            }
+            if let ObjectValue::Object(inner) = entry {
+                set(inner, rest, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexMap;
+    use shelflib::spec::ObjectValue;
+
+    use super::parse;
+
+    fn parse_strs(assignments: &[&str]) -> Result<IndexMap<String, ObjectValue>, String> {
+        let assignments: Vec<_> = assignments.iter().map(|s| s.to_string()).collect();
+        parse(&assignments).map(|vars| vars.0)
+    }
+
+    #[test]
+    fn test_parse() {
+        let vars = parse_strs(&[
+            "theme=light",
+            "size=12",
+            "dark=false",
+            "quoted=\"1\"",
+            "colors.bg=#000",
+            "colors.fg=#fff",
+        ])
+        .unwrap();
+
+        assert_eq!(vars["theme"], ObjectValue::Str("light".into()));
+        assert_eq!(vars["size"], ObjectValue::Int(12));
+        assert_eq!(vars["dark"], ObjectValue::Bool(false));
+        assert_eq!(vars["quoted"], ObjectValue::Str("1".into()));
+        match &vars["colors"] {
+            ObjectValue::Object(colors) => {
+                assert_eq!(colors["bg"], ObjectValue::Str("#000".into()));
+                assert_eq!(colors["fg"], ObjectValue::Str("#fff".into()));
+            }
+            value => panic!("expected an object, got {:?}", value),
+        }
+
+        // Later assignments take precedence, even over objects.
+        let vars = parse_strs(&["a.b=1", "a=2"]).unwrap();
+        assert_eq!(vars["a"], ObjectValue::Int(2));
+
+        assert!(parse_strs(&["theme"]).is_err());
+        assert!(parse_strs(&["a..b=1"]).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Stderr, Write};
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use stderrlog::{ColorChoice, StdErrLog};
+
+use crate::output::Category;
+
+/// Levels of messages by category, e.g. `debug` or `load=debug,process=info`. Categories that
+/// aren't given use the default level, if given, or that set by the number of `-v`. Messages of
+/// dependencies never go below warnings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verbosity {
+    pub default: Option<LevelFilter>,
+    pub categories: HashMap<&'static str, LevelFilter>,
+}
+
+impl FromStr for Verbosity {
+    type Err = String;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_level = |level: &str| {
+            LevelFilter::from_str(level).map_err(|_| format!("invalid level: {}", level))
+        };
+
+        let mut verbosity = Self::default();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((name, level)) => {
+                    let category = Category::from_name(name.trim()).ok_or_else(|| {
+                        let names: Vec<_> = Category::ALL.iter().map(|c| c.name()).collect();
+                        format!(
+                            "unknown category: {} (expected one of {})",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                    verbosity
+                        .categories
+                        .insert(category.name(), parse_level(level.trim())?);
+                }
+                None => verbosity.default = Some(parse_level(directive)?),
+            }
+        }
+
+        Ok(verbosity)
+    }
+}
+
+/// Logger that writes messages to stderr, filtering them by the levels of their categories.
+///
+/// Writing each message on its own dominates the run time of huge applies over slow terminals,
+/// e.g. over SSH, so messages are buffered and flushed once `interval` has passed since the last
+/// flush. Errors are flushed at once, as is everything before a panic.
+#[derive(Debug)]
+pub struct Logger {
+    inner: StdErrLog,
+    default: LevelFilter,
+    categories: HashMap<&'static str, LevelFilter>,
+    /// Minimum time between flushes; if zero, messages are written as they come.
+    interval: Duration,
+    buffer: Mutex<Buffer>,
+}
+
+/// Messages not yet flushed to stderr.
+#[derive(Debug)]
+struct Buffer {
+    writer: BufWriter<Stderr>,
+    /// When the buffer was last flushed.
+    flushed: Instant,
+}
+
+impl Buffer {
+    #[inline]
+    fn flush(&mut self) {
+        // There's nowhere to report failures to write to stderr.
+        let _ = self.writer.flush();
+        self.flushed = Instant::now();
+    }
+}
+
+impl Logger {
+    /// Create a logger of `verbosity`, falling back to `default` for unlisted categories, that
+    /// flushes messages at most every `interval`.
+    #[inline]
+    pub fn new(
+        verbosity: Verbosity,
+        default: LevelFilter,
+        quiet: bool,
+        interval: Duration,
+    ) -> Self {
+        let mut inner = stderrlog::new();
+        inner
+            .quiet(quiet)
+            .verbosity(4)
+            .show_level(false)
+            .color(ColorChoice::Never);
+
+        Self {
+            inner,
+            default: verbosity.default.unwrap_or(default),
+            categories: verbosity.categories,
+            interval,
+            buffer: Mutex::new(Buffer {
+                writer: BufWriter::new(io::stderr()),
+                flushed: Instant::now(),
+            }),
+        }
+    }
+
+    /// Set this as the global logger. Buffered messages are flushed before panics are reported.
+    #[inline]
+    pub fn init(self) -> Result<(), log::SetLoggerError> {
+        let max = self
+            .categories
+            .values()
+            .copied()
+            .fold(self.default, |a, b| a.max(b));
+        log::set_max_level(max);
+        log::set_boxed_logger(Box::new(self))?;
+
+        let report = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            log::logger().flush();
+            report(info);
+        }));
+        Ok(())
+    }
+
+    #[inline]
+    fn buffer(&self) -> MutexGuard<'_, Buffer> {
+        // A panic while holding the lock leaves the buffer intact.
+        self.buffer.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Return the level of messages with `target`. Categories only apply to messages of shelf
+    /// itself; those of dependencies are shown only if they're warnings or errors.
+    #[inline]
+    fn level(&self, target: &str) -> LevelFilter {
+        if let Some(level) = self.categories.get(target) {
+            return *level;
+        }
+
+        let crate_name = target.split("::").next().unwrap_or(target);
+        if Category::from_name(target).is_some() || matches!(crate_name, "shelf" | "shelflib") {
+            self.default
+        } else {
+            self.default.min(LevelFilter::Warn)
+        }
+    }
+}
+
+impl Log for Logger {
+    #[inline]
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level(metadata.target()) && self.inner.enabled(metadata)
+    }
+
+    #[inline]
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if self.interval.is_zero() {
+            self.inner.log(record);
+            return;
+        }
+
+        let mut buffer = self.buffer();
+        let _ = writeln!(buffer.writer, "{}", record.args());
+        if record.level() <= Level::Error || buffer.flushed.elapsed() >= self.interval {
+            buffer.flush();
+        }
+    }
+
+    #[inline]
+    fn flush(&self) {
+        self.buffer().flush();
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use log::LevelFilter;
+
+    use super::{Logger, Verbosity};
+
+    #[test]
+    fn test_parse() {
+        let verbosity: Verbosity = "load=debug, process=info".parse().unwrap();
+        assert_eq!(verbosity.default, None);
+        assert_eq!(verbosity.categories["load"], LevelFilter::Debug);
+        assert_eq!(verbosity.categories["process"], LevelFilter::Info);
+
+        let verbosity: Verbosity = "warn,load=trace".parse().unwrap();
+        assert_eq!(verbosity.default, Some(LevelFilter::Warn));
+        assert_eq!(verbosity.categories["load"], LevelFilter::Trace);
+        assert!(!verbosity.categories.contains_key("process"));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!("graph=debug".parse::<Verbosity>().is_err());
+        assert!("load=loud".parse::<Verbosity>().is_err());
+        assert!("loud".parse::<Verbosity>().is_err());
+    }
+
+    #[test]
+    fn test_level() {
+        let verbosity: Verbosity = "load=trace".parse().unwrap();
+        let logger = Logger::new(verbosity, LevelFilter::Debug, false, Duration::ZERO);
+        assert_eq!(logger.level("load"), LevelFilter::Trace);
+        assert_eq!(logger.level("process"), LevelFilter::Debug);
+        assert_eq!(logger.level("shelflib::action"), LevelFilter::Debug);
+        // Dependencies don't follow the level of shelf's own messages.
+        assert_eq!(logger.level("handlebars::render"), LevelFilter::Warn);
+        assert_eq!(logger.level("ignore::walk"), LevelFilter::Warn);
+    }
+}
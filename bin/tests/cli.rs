@@ -0,0 +1,182 @@
+//! Integration tests of the CLI, run against copies of the example packages in `examples/`.
+//!
+//! Output is compared with the snapshots in `tests/snapshots`. It is made deterministic with
+//! `--ci`, for plain output, and `SOURCE_DATE_EPOCH`, for times, and the temporary directory of
+//! each test is replaced by `[TMP]`. Missing snapshots fail the tests; to record new ones, or after
+//! an intended change to the output, run the tests with `SHELF_UPDATE_SNAPSHOTS=1` and review the
+//! difference before committing it.
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use assert_cmd::Command;
+use tempfile::TempDir;
+
+/// Temporary directory holding copies of the example packages, a home directory to apply them to,
+/// and a data directory for the journal and state.
+struct Fixture {
+    dir: TempDir,
+}
+
+impl Fixture {
+    fn new() -> io::Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        copy_dir(&examples, &dir.path().join("packages"))?;
+        fs::create_dir(dir.path().join("home"))?;
+        fs::create_dir(dir.path().join("data"))?;
+
+        Ok(Self { dir })
+    }
+
+    fn home(&self) -> PathBuf {
+        self.dir.path().join("home")
+    }
+
+    /// Run shelf with `args` from the directory of the packages.
+    fn shelf(&self, args: &[&str]) -> Output {
+        Command::cargo_bin("shelf")
+            .unwrap()
+            .current_dir(self.dir.path().join("packages"))
+            .env_clear()
+            .env("PATH", env::var_os("PATH").unwrap_or_default())
+            .env("HOME", self.home())
+            .env("XDG_DATA_HOME", self.dir.path().join("data"))
+            .env("SOURCE_DATE_EPOCH", "0")
+            .arg("--ci")
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Compare `output` with the snapshot `name`, or record it if requested.
+    fn snapshot(&self, name: &str, output: &Output) {
+        let actual = self.render(output);
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("snapshots")
+            .join(name)
+            .with_extension("txt");
+
+        if env::var_os("SHELF_UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+
+        match fs::read_to_string(&path) {
+            Ok(expected) => assert_eq!(
+                expected,
+                actual,
+                "output differs from snapshot {}",
+                path.display()
+            ),
+            Err(err) => panic!(
+                "couldn't read snapshot {}: {}; run with SHELF_UPDATE_SNAPSHOTS=1 to record it",
+                path.display(),
+                err
+            ),
+        }
+    }
+
+    /// Render the status and output of a run, with the temporary directory replaced.
+    fn render(&self, output: &Output) -> String {
+        let rendered = format!(
+            "status: {}\n--- stdout\n{}--- stderr\n{}",
+            output.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        );
+        // Symlinked temporary directories, e.g. on macOS, may be printed either way.
+        let canonical = fs::canonicalize(self.dir.path()).unwrap();
+        rendered
+            .replace(&*canonical.to_string_lossy(), "[TMP]")
+            .replace(&*self.dir.path().to_string_lossy(), "[TMP]")
+    }
+}
+
+fn copy_dir(src: &Path, dest: &Path) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let dest = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dest)?;
+        } else {
+            fs::copy(entry.path(), dest)?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_plan() -> io::Result<()> {
+    let fixture = Fixture::new()?;
+
+    let output = fixture.shelf(&["apply", "--noop", "dotfiles"]);
+    assert!(output.status.success());
+    fixture.snapshot("plan", &output);
+
+    // Pretending changes nothing.
+    assert_eq!(fs::read_dir(fixture.home())?.count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_apply() -> io::Result<()> {
+    let fixture = Fixture::new()?;
+
+    let output = fixture.shelf(&["apply", "dotfiles"]);
+    assert!(output.status.success());
+    fixture.snapshot("apply", &output);
+
+    let home = fixture.home();
+    assert!(fs::symlink_metadata(home.join(".bashrc"))?
+        .file_type()
+        .is_symlink());
+    assert!(fs::symlink_metadata(home.join(".profile"))?.is_file());
+    assert!(home.join(".local/state/bash").is_dir());
+    assert!(fs::read_to_string(home.join(".gitconfig"))?.contains("name = Example User"));
+    assert!(home.join(".config/nvim/lua/keys.lua").exists());
+    assert!(!home.join(".config/nvim/.shelfignore").exists());
+    Ok(())
+}
+
+#[test]
+fn test_status() -> io::Result<()> {
+    let fixture = Fixture::new()?;
+
+    assert!(fixture.shelf(&["apply", "dotfiles"]).status.success());
+    fs::remove_file(fixture.home().join(".bashrc"))?;
+
+    let output = fixture.shelf(&["status", "dotfiles"]);
+    fixture.snapshot("status", &output);
+    Ok(())
+}
+
+#[test]
+fn test_strays() -> io::Result<()> {
+    let fixture = Fixture::new()?;
+
+    assert!(fixture.shelf(&["apply", "dotfiles"]).status.success());
+    let nvim = fixture.home().join(".config/nvim");
+    fs::write(nvim.join("lua/stray.lua"), "")?;
+
+    // Reporting changes nothing.
+    let output = fixture.shelf(&["strays", "dotfiles"]);
+    assert!(output.status.success());
+    assert!(fixture.render(&output).contains("lua/stray.lua"));
+    assert!(nvim.join("lua/stray.lua").exists());
+
+    assert!(fixture
+        .shelf(&["strays", "--delete", "dotfiles"])
+        .status
+        .success());
+    assert!(!nvim.join("lua/stray.lua").exists());
+    assert!(nvim.join("lua/keys.lua").exists());
+    Ok(())
+}
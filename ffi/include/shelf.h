@@ -0,0 +1,37 @@
+/* C API for embedding shelf's engine. All strings are NUL-terminated UTF-8. */
+#ifndef SHELF_H
+#define SHELF_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Load the packages in `packages`, a JSON array of package paths, and their dependencies, and
+ * return the plan of ops for applying them to `dest` as JSON. Each package's `effects` gives the
+ * predicted effect of its ops on the current filesystem: "create", "replace-file",
+ * "replace-symlink", "modify", "up-to-date", or "conflict". The result must be freed with
+ * shelf_string_free. Returns NULL on failure; see shelf_last_error.
+ */
+char *shelf_plan(const char *packages, const char *dest);
+
+/*
+ * Apply `plan`, as returned by shelf_plan, backing up overwritten files into the directory
+ * `filesafe`. Returns 0 on success, and -1 on failure; see shelf_last_error.
+ */
+int shelf_apply(const char *plan, const char *filesafe);
+
+/*
+ * Return the message of the last error on this thread, or NULL if there was none. The string is
+ * owned by the library and valid until the next call on the same thread.
+ */
+const char *shelf_last_error(void);
+
+/* Free a string returned by the library. */
+void shelf_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SHELF_H */
@@ -0,0 +1,129 @@
+//! C API for embedding shelf's engine. See `include/shelf.h` for the declarations.
+//!
+//! All strings are NUL-terminated UTF-8. Functions that fail return `NULL` or a nonzero status,
+//! and the error is available from [`shelf_last_error`] until the next call on the same thread.
+
+// Errors are only ever turned into messages once, at the boundary.
+#![allow(clippy::result_large_err)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::path::PathBuf;
+use std::ptr;
+
+use shelflib::api::{self, FileSafe, FinishCtx, Loader, Plan, Processor};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error("null argument")]
+    Null,
+    #[error("argument is not valid UTF-8")]
+    Utf8(#[from] std::str::Utf8Error),
+    #[error("invalid JSON")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Api(#[from] api::Error),
+}
+
+/// Load the packages in `packages`, a JSON array of package paths, and their dependencies, and
+/// return the plan of ops for applying them to `dest` as a JSON string, which must be freed with
+/// [`shelf_string_free`]. Returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `packages` and `dest` must be valid, NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn shelf_plan(packages: *const c_char, dest: *const c_char) -> *mut c_char {
+    let res = (|| {
+        let packages: Vec<PathBuf> = serde_json::from_str(str_arg(packages)?)?;
+        let dest = PathBuf::from(str_arg(dest)?);
+
+        let graph = Loader::new().load(packages)?;
+        let plan = Plan::new(&graph, &dest)?;
+        Ok(serde_json::to_string(&plan)?)
+    })();
+
+    match res {
+        // SAFETY: JSON strings have no interior NUL bytes.
+        Ok(json) => CString::new(json).unwrap().into_raw(),
+        Err(err) => {
+            set_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Apply `plan`, as returned by [`shelf_plan`], backing up overwritten files into the directory
+/// `filesafe`. Returns 0 on success, and -1 on failure.
+///
+/// # Safety
+///
+/// `plan` and `filesafe` must be valid, NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn shelf_apply(plan: *const c_char, filesafe: *const c_char) -> c_int {
+    let res = (|| {
+        let plan: Plan = serde_json::from_str(str_arg(plan)?)?;
+        let ctx = FinishCtx::new(FileSafe::new(str_arg(filesafe)?));
+        Processor::new(ctx).apply(&plan)?;
+        Ok(())
+    })();
+
+    match res {
+        Ok(()) => 0,
+        Err(err) => {
+            set_error(err);
+            -1
+        }
+    }
+}
+
+/// Return the message of the last error on this thread, or `NULL` if there was none. The string
+/// is owned by the library.
+#[no_mangle]
+pub extern "C" fn shelf_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Free a string returned by the library.
+///
+/// # Safety
+///
+/// `s` must have been returned by the library and not freed before, or be `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn shelf_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[inline]
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err(Error::Null);
+    }
+
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+#[inline]
+fn set_error(err: Error) {
+    // Include the chain of causes, since there is no other way to get at them.
+    let mut message = err.to_string();
+    let mut source = std::error::Error::source(&err);
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+
+    let message = CString::new(message.replace('\0', "")).unwrap();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::fse;
 use crate::op::CommandOp;
@@ -17,6 +18,12 @@ pub struct CommandAction {
 
     pub clean_env: bool,
     pub env: EnvMap,
+
+    /// Only run if a prior op of the package changed the filesystem in this run. This is decided
+    /// by the processor, since resolution doesn't know which ops were finished.
+    pub only_if_changed: bool,
+    /// If present, the command is killed and fails after running this long.
+    pub timeout: Option<Duration>,
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +54,8 @@ impl Resolve for CommandAction {
             shell,
             clean_env,
             env,
+            only_if_changed: _,
+            timeout,
         } = self;
 
         if fse::symlink_exists(start) {
@@ -56,6 +65,7 @@ impl Resolve for CommandAction {
                 shell: shell.clone(),
                 clean_env: *clean_env,
                 env: env.clone(),
+                timeout: *timeout,
             })];
             Ok(Res::Normal(ops))
         } else {
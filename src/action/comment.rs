@@ -0,0 +1,151 @@
+use std::path::Path;
+
+/// Comment syntax of a file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentSyntax {
+    /// Comments are lines starting with the prefix.
+    Line(&'static str),
+    /// Comments are enclosed by the start and end delimiters.
+    Block(&'static str, &'static str),
+}
+
+impl CommentSyntax {
+    /// Guess the comment syntax of a file from its extension (or name, for extensionless files).
+    /// Returns `None` if the format is unknown or does not support comments (e.g. JSON).
+    #[inline]
+    pub fn from_path<P>(path: P) -> Option<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .or_else(|| path.file_name())
+            .and_then(|ext| ext.to_str())?;
+
+        let syntax = match ext.trim_start_matches('.') {
+            "yaml" | "yml" | "toml" | "sh" | "bash" | "zsh" | "fish" | "py" | "rb" | "pl"
+            | "conf" | "cfg" | "desktop" | "service" | "timer" | "bashrc" | "zshrc" | "profile"
+            | "gitconfig" | "gitignore" => Self::Line("#"),
+            "lua" | "sql" | "hs" => Self::Line("--"),
+            "vim" | "vimrc" => Self::Line("\""),
+            "ini" => Self::Line(";"),
+            "el" | "lisp" | "scm" => Self::Line(";;"),
+            "tex" | "erl" => Self::Line("%"),
+            "js" | "ts" | "jsonc" | "c" | "h" | "cpp" | "hpp" | "rs" | "go" | "java" | "kt"
+            | "swift" | "scss" | "kdl" => Self::Line("//"),
+            "css" => Self::Block("/*", "*/"),
+            "html" | "xml" | "svg" | "md" => Self::Block("<!--", "-->"),
+            _ => return None,
+        };
+
+        Some(syntax)
+    }
+
+    /// Comment out each line of `text`.
+    #[inline]
+    pub fn comment(&self, text: &str) -> String {
+        match self {
+            Self::Line(prefix) => text
+                .lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        prefix.to_string()
+                    } else {
+                        format!("{} {}", prefix, line)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::Block(start, end) => format!("{} {} {}", start, text, end),
+        }
+    }
+}
+
+/// Insert `header` as its own line at the start of `contents`, or after its first line if that's a
+/// shebang, which must stay first for the file to remain executable.
+#[inline]
+pub fn insert_header(contents: &mut String, header: &str) {
+    let at = if contents.starts_with("#!") {
+        match contents.find('\n') {
+            Some(i) => i + 1,
+            None => {
+                contents.push('\n');
+                contents.len()
+            }
+        }
+    } else {
+        0
+    };
+
+    contents.insert(at, '\n');
+    contents.insert_str(at, header);
+}
+
+/// Return the standard banner for files managed by the package `name`.
+#[inline]
+pub fn managed_banner(name: &str) -> String {
+    format!(
+        "This file is managed by shelf (package {}); do not edit it directly.",
+        name
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{insert_header, CommentSyntax};
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            CommentSyntax::from_path("config.toml"),
+            Some(CommentSyntax::Line("#"))
+        );
+        assert_eq!(
+            CommentSyntax::from_path(".zshrc"),
+            Some(CommentSyntax::Line("#"))
+        );
+        assert_eq!(
+            CommentSyntax::from_path("init.lua"),
+            Some(CommentSyntax::Line("--"))
+        );
+        assert_eq!(
+            CommentSyntax::from_path("style.css"),
+            Some(CommentSyntax::Block("/*", "*/"))
+        );
+        assert_eq!(CommentSyntax::from_path("settings.json"), None);
+    }
+
+    #[test]
+    fn test_comment() {
+        let text = "managed\n\ndo not edit";
+        assert_eq!(
+            CommentSyntax::Line("#").comment(text),
+            "# managed\n#\n# do not edit"
+        );
+        assert_eq!(
+            CommentSyntax::Line("--").comment(text),
+            "-- managed\n--\n-- do not edit"
+        );
+        assert_eq!(
+            CommentSyntax::Block("<!--", "-->").comment("managed"),
+            "<!-- managed -->"
+        );
+    }
+
+    #[test]
+    fn test_insert_header() {
+        let mut contents = String::from("set -e\n");
+        insert_header(&mut contents, "# managed");
+        assert_eq!(contents, "# managed\nset -e\n");
+
+        // Shebangs stay first, whether or not anything follows them.
+        let mut contents = String::from("#!/bin/sh\nset -e\n");
+        insert_header(&mut contents, "# managed");
+        assert_eq!(contents, "#!/bin/sh\n# managed\nset -e\n");
+
+        let mut contents = String::from("#!/bin/sh");
+        insert_header(&mut contents, "# managed");
+        assert_eq!(contents, "#!/bin/sh\n# managed\n");
+    }
+}
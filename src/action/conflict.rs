@@ -0,0 +1,104 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::op::CopyOp;
+
+/// Suffix of the paths that existing destinations are backed up to. See [`backup_path`].
+pub const BACKUP_SUFFIX: &str = ".shelf-backup";
+
+/// What to do when a destination already exists and differs from what would be placed there,
+/// e.g. a file written by hand before the package was applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Replace the existing destination.
+    Replace,
+    /// Leave the existing destination alone, and skip the directive.
+    Skip,
+    /// Copy the existing destination aside (see [`backup_path`]), and then replace it.
+    Backup,
+    /// Leave the existing destination alone, and fail the directive.
+    Fail,
+}
+
+impl Default for ConflictPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self::Replace
+    }
+}
+
+/// Return the op that backs up the existing destination `dest` before it is replaced, if it is a
+/// file or directory. Symlinks aren't backed up, since nothing but the link is lost by replacing
+/// them.
+#[inline]
+pub fn backup_op(dest: &Path) -> Option<CopyOp> {
+    let dir = match fs::symlink_metadata(dest) {
+        Ok(meta) if meta.is_dir() => true,
+        Ok(meta) if meta.is_file() => false,
+        Ok(_) | Err(_) => return None,
+    };
+
+    Some(CopyOp {
+        src: dest.to_path_buf(),
+        dest: backup_path(dest),
+        dir,
+    })
+}
+
+/// Return the path to back up `dest` to: `dest` with [`BACKUP_SUFFIX`] appended, or, if that is
+/// taken by an earlier backup, the first free one of those numbered `.1`, `.2`, and so on.
+#[inline]
+pub fn backup_path(dest: &Path) -> PathBuf {
+    let mut base = dest.as_os_str().to_owned();
+    base.push(BACKUP_SUFFIX);
+
+    let mut path = PathBuf::from(&base);
+    let mut n = 1;
+    while fs::symlink_metadata(&path).is_ok() {
+        let mut numbered = base.clone();
+        numbered.push(format!(".{}", n));
+        path = PathBuf::from(numbered);
+        n += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::{backup_op, backup_path};
+
+    /// Test that backups don't take the paths of earlier ones, and that symlinks aren't backed up.
+    #[test]
+    fn test_backup_path() -> std::io::Result<()> {
+        let dir = TempDir::new()?;
+        let dest = dir.path().join("a");
+
+        assert_eq!(backup_path(&dest), dir.path().join("a.shelf-backup"));
+        fs::write(dir.path().join("a.shelf-backup"), "")?;
+        assert_eq!(backup_path(&dest), dir.path().join("a.shelf-backup.1"));
+        fs::write(dir.path().join("a.shelf-backup.1"), "")?;
+        assert_eq!(backup_path(&dest), dir.path().join("a.shelf-backup.2"));
+
+        assert!(backup_op(&dest).is_none());
+        fs::write(&dest, "a")?;
+        let op = backup_op(&dest).unwrap();
+        assert_eq!(op.dest, dir.path().join("a.shelf-backup.2"));
+        assert!(!op.dir);
+
+        #[cfg(unix)]
+        {
+            let link = dir.path().join("b");
+            std::os::unix::fs::symlink(&dest, &link)?;
+            assert!(backup_op(&link).is_none());
+        }
+
+        Ok(())
+    }
+}
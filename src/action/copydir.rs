@@ -0,0 +1,266 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use glob::PatternError;
+
+use crate::fse;
+use crate::op::{CopyDirOp, MkdirOp, RmOp};
+
+use super::tree::Patterns;
+use super::volatile::Volatile;
+use super::{mkdir, Resolve};
+
+/// Action to recursively copy the directory `src` to `dest` as a single unit.
+///
+/// Unlike [`super::TreeAction`], which links or copies each file individually, the directory is
+/// copied wholesale. This suits applications that rewrite their configuration directory and break
+/// symlinks inside it.
+#[derive(Debug, Clone)]
+pub struct CopyDirAction {
+    /// Path of directory to copy.
+    pub src: PathBuf,
+    /// Path of destination of copy.
+    pub dest: PathBuf,
+    /// Destination subpaths that are exempt from drift checks and never removed. See
+    /// [`Volatile`].
+    pub volatile: Patterns,
+
+    /// If the `src` does not exist, emit no operations.
+    pub optional: bool,
+}
+
+/// Error that occurs when resolving [`CopyDirAction`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// `src` was not found, and `optional` was false.
+    #[error("src missing")]
+    SrcMissing,
+    /// `src` is not a directory.
+    #[error("src not a directory")]
+    SrcNotDir,
+    #[error("pattern error")]
+    Pattern(#[from] PatternError),
+}
+
+// Resolution of [`CopyDirAction`].
+#[derive(Debug, Clone)]
+pub enum Res {
+    /// Normal procedure.
+    Normal(Vec<Op>),
+    /// The destination file or directory will be overwritten.
+    Overwrite(Vec<Op>),
+    /// The action is skipped.
+    Skip(Skip),
+}
+
+/// Operation created by resolution.
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Remove operation.
+    Rm(RmOp),
+    /// Copy directory operation.
+    CopyDir(CopyDirOp),
+    /// Mkdir operation.
+    Mkdir(MkdirOp),
+}
+
+/// Reason for skipping [`CopyDirAction`].
+#[derive(Debug, Clone)]
+pub enum Skip {
+    /// `src` and `dest` are the same path.
+    SameSrcDest,
+    /// Optional `src` does not exist.
+    OptMissing,
+    /// Destination directory already exists with the same contents.
+    DestExists,
+}
+
+impl Resolve for CopyDirAction {
+    type Output = Result<Res, Error>;
+
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        let Self {
+            src,
+            dest,
+            volatile,
+            optional,
+        } = self;
+
+        // If src and dest are the same, skip.
+        if src == dest {
+            return Ok(Res::Skip(Skip::SameSrcDest));
+        }
+
+        // If file does not exist and optional flag enabled, skip.
+        // If optional flag disabled, error.
+        match (optional, fse::symlink_exists(src)) {
+            (true, false) => {
+                return Ok(Res::Skip(Skip::OptMissing));
+            }
+            (false, false) => {
+                return Err(Error::SrcMissing);
+            }
+            _ => {}
+        };
+
+        if !src.is_dir() {
+            return Err(Error::SrcNotDir);
+        }
+
+        let volatile = Volatile::new(volatile)?;
+
+        let (overwrite, dest_is_dir) = match fs::symlink_metadata(dest) {
+            // For directories, compare the contents, ignoring volatile paths. If they match, we
+            // should do nothing.
+            Ok(meta) if meta.is_dir() => {
+                if dir_same(src, dest, Path::new(""), &volatile) {
+                    return Ok(Res::Skip(Skip::DestExists));
+                }
+
+                // Volatile paths that exist must survive the overwrite; remove everything else.
+                let mut keep = Vec::new();
+                find_volatile(dest, Path::new(""), &volatile, &mut keep);
+                if !keep.is_empty() {
+                    let mut ops = Vec::new();
+                    prune_ops(dest, Path::new(""), &keep, &mut ops);
+                    ops.push(Op::CopyDir(CopyDirOp {
+                        src: src.clone(),
+                        dest: dest.clone(),
+                        keep,
+                    }));
+
+                    return Ok(Res::Overwrite(ops));
+                }
+
+                (true, true)
+            }
+            // For files and symlinks, warn about an overwrite.
+            Ok(_) => (true, false),
+            // File doesn't exist, or insufficient permissions; treat as nonexistent.
+            Err(_) => (false, false),
+        };
+
+        let copy_op = Op::CopyDir(CopyDirOp {
+            src: src.clone(),
+            dest: dest.clone(),
+            keep: vec![],
+        });
+        if overwrite {
+            // Add op to remove existing file or directory.
+            let rm_op = Op::Rm(RmOp {
+                path: dest.clone(),
+                dir: dest_is_dir,
+            });
+
+            Ok(Res::Overwrite(vec![rm_op, copy_op]))
+        } else {
+            // Check for existence of parent directories and add op to make parent directories if
+            // they don't exist.
+            let mut ops: Vec<_> = mkdir::mkdir_parents_ops(dest).map(Op::Mkdir).collect();
+
+            ops.push(copy_op);
+            Ok(Res::Normal(ops))
+        }
+    }
+}
+
+/// Return the sorted entry names of the directory at `path`.
+#[inline]
+fn read_names(path: &Path) -> Option<Vec<OsString>> {
+    let mut names = fs::read_dir(path)
+        .ok()?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    names.sort();
+    Some(names)
+}
+
+/// Return true if the directories `a/rel` and `b/rel` have the same entries with the same
+/// contents, ignoring volatile paths.
+#[inline]
+fn dir_same(a: &Path, b: &Path, rel: &Path, volatile: &Volatile) -> bool {
+    let names = |path: &Path| {
+        read_names(&path.join(rel)).map(|names| {
+            names
+                .into_iter()
+                .filter(|name| !volatile.matches(rel.join(name)))
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let (a_names, b_names) = match (names(a), names(b)) {
+        (Some(a_names), Some(b_names)) => (a_names, b_names),
+        _ => return false,
+    };
+    if a_names != b_names {
+        return false;
+    }
+
+    a_names.iter().all(|name| {
+        let entry_rel = rel.join(name);
+        let (a_entry, b_entry) = (a.join(&entry_rel), b.join(&entry_rel));
+        // Symlinks are compared by their targets, rather than followed.
+        let file_type = |path: &Path| fs::symlink_metadata(path).map(|meta| meta.file_type());
+        match (file_type(&a_entry), file_type(&b_entry)) {
+            (Ok(a_ft), Ok(b_ft)) if a_ft.is_symlink() && b_ft.is_symlink() => {
+                match (fs::read_link(&a_entry), fs::read_link(&b_entry)) {
+                    (Ok(a_target), Ok(b_target)) => a_target == b_target,
+                    _ => false,
+                }
+            }
+            (Ok(a_ft), Ok(b_ft)) if a_ft.is_symlink() || b_ft.is_symlink() => false,
+            (Ok(a_ft), Ok(b_ft)) if a_ft.is_dir() && b_ft.is_dir() => {
+                dir_same(a, b, &entry_rel, volatile)
+            }
+            (Ok(a_ft), Ok(b_ft)) if !a_ft.is_dir() && !b_ft.is_dir() => {
+                match (fs::read(&a_entry), fs::read(&b_entry)) {
+                    (Ok(a_contents), Ok(b_contents)) => a_contents == b_contents,
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Collect the topmost existing paths under `dest/rel` that are volatile.
+#[inline]
+fn find_volatile(dest: &Path, rel: &Path, volatile: &Volatile, found: &mut Vec<PathBuf>) {
+    if volatile.is_empty() {
+        return;
+    }
+
+    for name in read_names(&dest.join(rel)).unwrap_or_default() {
+        let entry_rel = rel.join(name);
+        if volatile.matches(&entry_rel) {
+            found.push(entry_rel);
+        } else if fs::symlink_metadata(dest.join(&entry_rel)).is_ok_and(|meta| meta.is_dir()) {
+            find_volatile(dest, &entry_rel, volatile, found);
+        }
+    }
+}
+
+/// Create ops to remove everything under `dest/rel`, except for the paths in `keep` and their
+/// ancestor directories.
+#[inline]
+fn prune_ops(dest: &Path, rel: &Path, keep: &[PathBuf], ops: &mut Vec<Op>) {
+    for name in read_names(&dest.join(rel)).unwrap_or_default() {
+        let entry_rel = rel.join(name);
+        if keep.contains(&entry_rel) {
+            continue;
+        }
+
+        let entry = dest.join(&entry_rel);
+        if keep.iter().any(|path| path.starts_with(&entry_rel)) {
+            prune_ops(dest, &entry_rel, keep, ops);
+        } else {
+            let dir = fs::symlink_metadata(&entry)
+                .map(|meta| meta.is_dir())
+                .unwrap_or(false);
+            ops.push(Op::Rm(RmOp { path: entry, dir }));
+        }
+    }
+}
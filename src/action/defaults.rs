@@ -0,0 +1,64 @@
+use crate::op::defaults::{self, DefaultsValue};
+use crate::op::error::DefaultsError;
+use crate::op::DefaultsOp;
+
+use super::Resolve;
+
+/// Action to set a macOS user default, as with `defaults write`. On other platforms, the action
+/// is skipped.
+#[derive(Debug, Clone)]
+pub struct DefaultsAction {
+    /// Domain of the default, e.g. `com.apple.dock`.
+    pub domain: String,
+    /// Key of the default.
+    pub key: String,
+    /// Value to set.
+    pub value: DefaultsValue,
+}
+
+/// Error that occurs when resolving [`DefaultsAction`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The current value couldn't be read.
+    #[error("couldn't read current value")]
+    Read(#[from] DefaultsError),
+}
+
+#[derive(Debug, Clone)]
+pub enum Res {
+    Normal(DefaultsOp),
+    /// The action is skipped.
+    Skip(Skip),
+}
+
+/// Reason for skipping [`DefaultsAction`].
+#[derive(Debug, Clone)]
+pub enum Skip {
+    /// User defaults only exist on macOS.
+    Unsupported,
+    /// The default is already set to the value.
+    UpToDate,
+}
+
+impl Resolve for DefaultsAction {
+    type Output = Result<Res, Error>;
+
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        let Self { domain, key, value } = self;
+
+        if !cfg!(target_os = "macos") {
+            return Ok(Res::Skip(Skip::Unsupported));
+        }
+
+        if defaults::read(domain, key)?.as_ref() == Some(value) {
+            Ok(Res::Skip(Skip::UpToDate))
+        } else {
+            Ok(Res::Normal(DefaultsOp {
+                domain: domain.clone(),
+                key: key.clone(),
+                value: value.clone(),
+            }))
+        }
+    }
+}
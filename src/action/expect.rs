@@ -0,0 +1,95 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fse;
+
+use super::Resolve;
+
+/// What a destination is expected to be.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Expectation {
+    /// A symlink to the given target.
+    Symlink(PathBuf),
+    /// A regular file, or a symlink to one, that contains the given string, if any.
+    File { contains: Option<String> },
+}
+
+/// Action that checks an [`Expectation`] of a destination, after the actions before it are
+/// applied. Nothing is changed.
+#[derive(Debug, Clone)]
+pub struct ExpectAction {
+    pub dest: PathBuf,
+    pub expectation: Expectation,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("nothing is there")]
+    Missing,
+    #[error("it is not a symlink")]
+    NotSymlink,
+    #[error("it points to {0} instead")]
+    Retargeted(PathBuf),
+    #[error("it is not a file")]
+    NotFile,
+    #[error("it doesn't contain '{0}'")]
+    NotContained(String),
+    #[error("couldn't read it")]
+    Read(#[from] io::Error),
+}
+
+impl Resolve for ExpectAction {
+    type Output = Result<(), Error>;
+
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        let Self { dest, expectation } = self;
+
+        let metadata = match fs::symlink_metadata(dest) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(Error::Missing),
+            Err(err) => return Err(err.into()),
+        };
+
+        match expectation {
+            Expectation::Symlink(target) => {
+                if !metadata.file_type().is_symlink() {
+                    return Err(Error::NotSymlink);
+                }
+
+                // Relative targets are relative to the directory of the symlink.
+                let actual = fs::read_link(dest)?;
+                let resolved = match dest.parent() {
+                    Some(parent) => fse::clean(parent.join(&actual)),
+                    None => actual.clone(),
+                };
+                if resolved == fse::clean(target) {
+                    Ok(())
+                } else {
+                    Err(Error::Retargeted(actual))
+                }
+            }
+            Expectation::File { contains } => {
+                if !dest.is_file() {
+                    return Err(Error::NotFile);
+                }
+
+                match contains {
+                    Some(contains) => {
+                        let contents = fs::read(dest)?;
+                        let contents = String::from_utf8_lossy(&contents);
+                        if contents.contains(contains.as_str()) {
+                            Ok(())
+                        } else {
+                            Err(Error::NotContained(contains.clone()))
+                        }
+                    }
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
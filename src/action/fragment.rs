@@ -0,0 +1,94 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::op::RmOp;
+
+use super::write::Res as WriteRes;
+use super::{Resolve, WriteAction};
+
+/// Action to write a fragment file into a conf.d-style directory (e.g. `/etc/profile.d`), and to
+/// remove fragments that the package previously wrote there but no longer declares.
+///
+/// Fragments of a package are recognized by a file name prefix that is unique to it.
+#[derive(Debug, Clone)]
+pub struct FragmentAction {
+    /// Directory of fragments.
+    pub dir: PathBuf,
+    /// Path of the fragment, in `dir`.
+    pub dest: PathBuf,
+    /// Contents of the fragment.
+    pub contents: Vec<u8>,
+
+    /// File name prefix of the fragments of the package.
+    pub prefix: String,
+    /// File names of all the fragments that the package declares in `dir`. Other files with
+    /// `prefix` are stale.
+    pub keep: Vec<String>,
+}
+
+/// Error that occurs when resolving [`FragmentAction`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// `dir` couldn't be listed to find stale fragments.
+    #[error("couldn't read fragment directory")]
+    ReadDir(#[from] io::Error),
+}
+
+/// Resolution of [`FragmentAction`].
+#[derive(Debug, Clone)]
+pub struct Res {
+    /// Resolution of writing the fragment.
+    pub write: WriteRes,
+    /// Ops to remove stale fragments.
+    pub prune: Vec<RmOp>,
+}
+
+impl Resolve for FragmentAction {
+    type Output = Result<Res, Error>;
+
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        let Self {
+            dir,
+            dest,
+            contents,
+            prefix,
+            keep,
+        } = self;
+
+        let prune = match fs::read_dir(dir) {
+            Ok(entries) => {
+                let mut prune = Vec::new();
+                for entry in entries {
+                    let entry = entry?;
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if name.starts_with(prefix.as_str()) && !keep.contains(&name) {
+                        prune.push(RmOp {
+                            path: entry.path(),
+                            dir: entry.file_type()?.is_dir(),
+                        });
+                    }
+                }
+
+                // Sort to make the ops deterministic.
+                prune.sort();
+                prune
+            }
+            // Nothing to prune if the directory doesn't exist yet.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err.into()),
+        };
+
+        let write = WriteAction {
+            dest: dest.clone(),
+            contents: contents.clone(),
+            mode: None,
+            owner: Default::default(),
+            on_conflict: Default::default(),
+        }
+        .resolve();
+
+        Ok(Res { write, prune })
+    }
+}
@@ -1,19 +1,34 @@
 use std::path::PathBuf;
 
-use mlua::Function;
+use mlua::Thread;
 
 use crate::fse;
+use crate::graph::PathResolver;
+use crate::op::command::EnvMap;
+use crate::op::function::FunctionYield;
 use crate::op::FunctionOp;
 
-use super::Resolve;
+use super::{Action, CommandAction, LinkAction, Resolve, WriteAction};
 
 #[derive(Debug, Clone)]
 pub struct FunctionAction<'lua> {
-    pub function: Function<'lua>,
+    /// Coroutine of the function to call. See [`FunctionOp`].
+    pub thread: Thread<'lua>,
 
     pub start: PathBuf,
-}
+    /// Environment variables set for the duration of the call.
+    pub env: EnvMap,
+
+    /// See [`super::CommandAction::only_if_changed`].
+    pub only_if_changed: bool,
 
+    /// Root of the package, against which the sources of requested links are resolved.
+    pub root: PathBuf,
+    /// Resolver through which the destinations of requests are joined.
+    pub paths: PathResolver,
+    /// Shell in which requested commands are run.
+    pub shell: String,
+}
 #[derive(Debug, Clone)]
 pub enum Res<'lua> {
     Normal(Vec<Op<'lua>>),
@@ -36,13 +51,23 @@ impl<'lua> Resolve for FunctionAction<'lua> {
 
     #[inline]
     fn resolve(&self) -> Self::Output {
-        let Self { function, start } = self;
+        let Self {
+            thread,
+            start,
+            env,
+            only_if_changed: _,
+            root: _,
+            paths: _,
+            shell: _,
+        } = self;
 
         // If the start directory doesn't exist, we should error.
         if fse::symlink_exists(start) {
             let ops = vec![Op::Function(FunctionOp {
-                function: function.clone(),
+                thread: thread.clone(),
                 start: start.clone(),
+                env: env.clone(),
+                changed: Vec::new(),
             })];
 
             Ok(Res::Normal(ops))
@@ -51,3 +76,40 @@ impl<'lua> Resolve for FunctionAction<'lua> {
         }
     }
 }
+
+impl<'lua> FunctionAction<'lua> {
+    /// Return the action that makes the change requested by the function. See [`FunctionYield`].
+    #[inline]
+    pub fn request(&self, req: FunctionYield) -> Action<'lua> {
+        match req {
+            FunctionYield::Link { src, dest } => Action::Link(LinkAction {
+                src: fse::clean(self.root.join(src)),
+                dest: self.paths.join(dest),
+                copy: false,
+                hardlink: false,
+                fallback: false,
+                optional: false,
+                mode: None,
+                owner: Default::default(),
+                on_conflict: Default::default(),
+                if_missing: Default::default(),
+            }),
+            FunctionYield::Write { dest, contents } => Action::Write(WriteAction {
+                dest: self.paths.join(dest),
+                contents,
+                mode: None,
+                owner: Default::default(),
+                on_conflict: Default::default(),
+            }),
+            FunctionYield::Cmd { command } => Action::Command(CommandAction {
+                command,
+                start: self.start.clone(),
+                shell: self.shell.clone(),
+                clean_env: false,
+                env: self.env.clone(),
+                only_if_changed: false,
+                timeout: None,
+            }),
+        }
+    }
+}
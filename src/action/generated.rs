@@ -1,5 +1,7 @@
 use std::path::Path;
 
+use super::conflict::ConflictPolicy;
+use super::mode::Owner;
 use super::write::WriteAction;
 use super::Resolve;
 
@@ -13,7 +15,7 @@ pub use super::object::Object;
 pub mod yaml {
     use std::path::PathBuf;
 
-    use super::{Object, Res, Resolve};
+    use super::{schema, ConflictPolicy, Object, Owner, Res, Resolve};
 
     #[derive(Debug, Clone)]
     pub struct YamlAction {
@@ -21,12 +23,22 @@ pub mod yaml {
         pub values: Object,
 
         pub header: Option<String>,
+        /// Path to a JSON Schema that the values must satisfy.
+        pub schema: Option<PathBuf>,
+        /// Permission bits to set on the file, if any.
+        pub mode: Option<u32>,
+        /// Owner to set on the file.
+        pub owner: Owner,
+        /// What to do if `dest` already exists and would be overwritten.
+        pub on_conflict: ConflictPolicy,
     }
 
     #[derive(Debug, thiserror::Error)]
     pub enum Error {
         #[error("serde error")]
         Serde(#[from] serde_yaml::Error),
+        #[error("schema error")]
+        Schema(#[from] schema::Error),
     }
 
     impl Resolve for YamlAction {
@@ -38,11 +50,27 @@ pub mod yaml {
                 dest,
                 values,
                 header,
+                schema,
+                mode,
+                owner,
+                on_conflict,
             } = self;
 
             // Render contents.
             let contents = serde_yaml::to_string(&values)?;
-            Ok(super::write_resolve(dest, contents, header))
+            // Validate against the schema, if given.
+            if let Some(schema) = schema {
+                schema::validate(schema, values)?;
+            }
+
+            Ok(super::write_resolve(
+                dest,
+                contents,
+                header,
+                *mode,
+                *owner,
+                *on_conflict,
+            ))
         }
     }
 }
@@ -50,7 +78,7 @@ pub mod yaml {
 pub mod toml {
     use std::path::PathBuf;
 
-    use super::{Object, Res, Resolve};
+    use super::{schema, ConflictPolicy, Object, Owner, Res, Resolve};
 
     #[derive(Debug, Clone)]
     pub struct TomlAction {
@@ -58,12 +86,22 @@ pub mod toml {
         pub values: Object,
 
         pub header: Option<String>,
+        /// Path to a JSON Schema that the values must satisfy.
+        pub schema: Option<PathBuf>,
+        /// Permission bits to set on the file, if any.
+        pub mode: Option<u32>,
+        /// Owner to set on the file.
+        pub owner: Owner,
+        /// What to do if `dest` already exists and would be overwritten.
+        pub on_conflict: ConflictPolicy,
     }
 
     #[derive(Debug, thiserror::Error)]
     pub enum Error {
         #[error("serde error")]
         Serde(#[from] toml::ser::Error),
+        #[error("schema error")]
+        Schema(#[from] schema::Error),
     }
 
     impl Resolve for TomlAction {
@@ -75,11 +113,27 @@ pub mod toml {
                 dest,
                 values,
                 header,
+                schema,
+                mode,
+                owner,
+                on_conflict,
             } = self;
 
             // Render contents.
             let contents = toml::to_string_pretty(&values)?;
-            Ok(super::write_resolve(dest, contents, header))
+            // Validate against the schema, if given.
+            if let Some(schema) = schema {
+                schema::validate(schema, values)?;
+            }
+
+            Ok(super::write_resolve(
+                dest,
+                contents,
+                header,
+                *mode,
+                *owner,
+                *on_conflict,
+            ))
         }
     }
 }
@@ -87,18 +141,29 @@ pub mod toml {
 pub mod json {
     use std::path::PathBuf;
 
-    use super::{Object, Res, Resolve};
+    use super::{schema, ConflictPolicy, Object, Owner, Res, Resolve};
 
     #[derive(Debug, Clone)]
     pub struct JsonAction {
         pub dest: PathBuf,
         pub values: Object,
+
+        /// Path to a JSON Schema that the values must satisfy.
+        pub schema: Option<PathBuf>,
+        /// Permission bits to set on the file, if any.
+        pub mode: Option<u32>,
+        /// Owner to set on the file.
+        pub owner: Owner,
+        /// What to do if `dest` already exists and would be overwritten.
+        pub on_conflict: ConflictPolicy,
     }
 
     #[derive(Debug, thiserror::Error)]
     pub enum Error {
         #[error("serde error")]
         Serde(#[from] serde_json::Error),
+        #[error("schema error")]
+        Schema(#[from] schema::Error),
     }
 
     impl Resolve for JsonAction {
@@ -106,26 +171,116 @@ pub mod json {
 
         #[inline]
         fn resolve(&self) -> Self::Output {
-            let Self { dest, values } = self;
+            let Self {
+                dest,
+                values,
+                schema,
+                mode,
+                owner,
+                on_conflict,
+            } = self;
 
             // Render contents.
             let contents = serde_json::to_string(&values)?;
-            Ok(super::write_resolve(dest, contents, &None))
+            // Validate against the schema, if given.
+            if let Some(schema) = schema {
+                schema::validate(schema, values)?;
+            }
+
+            Ok(super::write_resolve(
+                dest,
+                contents,
+                &None,
+                *mode,
+                *owner,
+                *on_conflict,
+            ))
+        }
+    }
+}
+
+pub mod schema {
+    use std::fs::File;
+    use std::io;
+    use std::path::Path;
+
+    use jsonschema::JSONSchema;
+
+    use super::Object;
+
+    /// A single location in the generated values that failed validation.
+    #[derive(Debug, Clone)]
+    pub struct Failure {
+        /// JSON pointer to the offending value.
+        pub pointer: String,
+        /// Description of the failure.
+        pub message: String,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    pub enum Error {
+        #[error("i/o error")]
+        Io(#[from] io::Error),
+        #[error("schema parse error")]
+        Parse(#[from] serde_yaml::Error),
+        #[error("serde error")]
+        Serde(#[from] serde_json::Error),
+        #[error("invalid schema: {0}")]
+        Compile(String),
+        #[error("schema validation failed")]
+        Invalid(Vec<Failure>),
+    }
+
+    /// Validate `values` against the JSON Schema at `path`. The schema may be written in either
+    /// JSON or YAML.
+    #[inline]
+    pub fn validate<P>(path: P, values: &Object) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(path)?;
+        let schema: serde_json::Value = serde_yaml::from_reader(file)?;
+        let compiled =
+            JSONSchema::compile(&schema).map_err(|err| Error::Compile(err.to_string()))?;
+
+        let instance = serde_json::to_value(values)?;
+        let res = compiled.validate(&instance);
+        match res {
+            Ok(()) => Ok(()),
+            Err(errors) => {
+                let failures = errors
+                    .map(|err| Failure {
+                        pointer: err.instance_path.to_string(),
+                        message: err.to_string(),
+                    })
+                    .collect();
+                Err(Error::Invalid(failures))
+            }
         }
     }
 }
 
 #[inline]
-fn write_resolve(dest: &Path, mut contents: String, header: &Option<String>) -> Res {
+fn write_resolve(
+    dest: &Path,
+    mut contents: String,
+    header: &Option<String>,
+    mode: Option<u32>,
+    owner: Owner,
+    on_conflict: ConflictPolicy,
+) -> Res {
     if let Some(header) = header.as_ref() {
-        contents.insert_str(0, header);
         contents.insert(0, '\n');
+        contents.insert_str(0, header);
     }
 
     // Write contents.
     let wa = WriteAction {
         dest: dest.to_path_buf(),
         contents: contents.into_bytes(),
+        mode,
+        owner,
+        on_conflict,
     };
 
     wa.resolve()
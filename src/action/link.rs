@@ -1,12 +1,18 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::fse;
-use crate::op::{CopyOp, LinkOp, MkdirOp, RmOp};
+use crate::op::link::{self, LinkKind};
+#[cfg(unix)]
+use crate::op::ChownOp;
+use crate::op::{ChmodOp, CopyOp, HardlinkOp, LinkOp, MkdirOp, RmOp};
 
+use super::conflict::{self, ConflictPolicy};
+use super::missing::{self, MissingParentPolicy};
+use super::mode::{self, Owner};
 use super::{mkdir, Resolve};
 
-/// Action to symlink or copy from `src` to `dest`.
+/// Action to symlink, hard link, or copy from `src` to `dest`.
 #[derive(Debug, Clone)]
 pub struct LinkAction {
     /// Path of file to symlink/copy.
@@ -16,8 +22,24 @@ pub struct LinkAction {
 
     /// Perform a copy instead of a symlink.
     pub copy: bool,
+    /// Perform a hard link instead of a symlink, falling back to a copy when `src` and `dest` are
+    /// on different filesystems. Ignored if `copy` is set.
+    pub hardlink: bool,
+    /// Copy instead of symlinking if symlinks can't be created, e.g. on Windows without the
+    /// privilege to create them, or make a junction if `src` is a directory on Windows. Ignored if
+    /// `copy` or `hardlink` is set.
+    pub fallback: bool,
     /// If the `src` does not exist, emit no operations.
     pub optional: bool,
+    /// Permission bits to set on `dest`, if any. Ignored unless `copy` is set, since symlinks and
+    /// hard links share the permission bits of `src`.
+    pub mode: Option<u32>,
+    /// Owner to set on `dest`. Ignored unless `copy` is set, like `mode`.
+    pub owner: Owner,
+    /// What to do if `dest` already exists and would be overwritten.
+    pub on_conflict: ConflictPolicy,
+    /// What to do if the parent of `dest` doesn't exist.
+    pub if_missing: MissingParentPolicy,
 }
 
 /// Error that occurs when resolving [`LinkAction`].
@@ -26,6 +48,13 @@ pub enum Error {
     /// `src` was not found, and `optional` was false.
     #[error("src missing")]
     SrcMissing,
+    /// `dest` already exists and would be overwritten, and `on_conflict` is
+    /// [`ConflictPolicy::Fail`].
+    #[error("dest exists")]
+    Conflict,
+    /// The parent of `dest` doesn't exist, and `if_missing` is [`MissingParentPolicy::Error`].
+    #[error("dest parent missing")]
+    ParentMissing,
 }
 
 // Resolution of [`LinkAction`].
@@ -48,8 +77,15 @@ pub enum Op {
     Link(LinkOp),
     /// Copy operation.
     Copy(CopyOp),
+    /// Hard link operation.
+    Hardlink(HardlinkOp),
     /// Mkdir operation.
     Mkdir(MkdirOp),
+    /// Chmod operation.
+    Chmod(ChmodOp),
+    /// Chown operation.
+    #[cfg(unix)]
+    Chown(ChownOp),
 }
 
 /// Reason for skipping [`LinkAction`].
@@ -61,6 +97,12 @@ pub enum Skip {
     OptMissing,
     /// Destination link already exists.
     DestExists,
+    /// Destination differs and would be overwritten, but `on_conflict` is
+    /// [`ConflictPolicy::Skip`].
+    Conflict,
+    /// The parent of the destination doesn't exist, and `if_missing` is
+    /// [`MissingParentPolicy::Skip`].
+    ParentMissing,
 }
 
 impl Resolve for LinkAction {
@@ -72,7 +114,13 @@ impl Resolve for LinkAction {
             src,
             dest,
             copy,
+            hardlink,
+            fallback: _,
             optional,
+            mode: _,
+            owner: _,
+            on_conflict: _,
+            if_missing,
         } = self;
 
         // If src and dest are the same, skip.
@@ -92,23 +140,84 @@ impl Resolve for LinkAction {
             _ => {}
         };
 
-        if *copy {
-            self.resolve_copy()
+        if missing::parent_missing(dest) {
+            match if_missing {
+                MissingParentPolicy::CreateParent => {}
+                MissingParentPolicy::Skip => return Ok(Res::Skip(Skip::ParentMissing)),
+                MissingParentPolicy::Error => return Err(Error::ParentMissing),
+            }
+        }
+
+        let res = if *copy {
+            self.resolve_copy()?
+        } else if *hardlink {
+            self.resolve_hardlink()?
         } else {
-            self.resolve_link()
+            self.resolve_link()?
+        };
+
+        match res {
+            Res::Overwrite(ops) => self.resolve_conflict(ops),
+            res => Ok(res),
         }
     }
 }
 
 // TODO: Reduce code duplication
 impl LinkAction {
+    /// Return the op that creates `dest`, ignoring whatever is there now.
+    #[inline]
+    pub fn create_op(&self) -> Op {
+        let Self {
+            src,
+            dest,
+            copy,
+            hardlink,
+            fallback,
+            optional: _,
+            mode: _,
+            owner: _,
+            on_conflict: _,
+            if_missing: _,
+        } = self;
+
+        if *copy {
+            let src_is_dir = match fs::symlink_metadata(src) {
+                Ok(meta) if meta.is_dir() => true,
+                Ok(_) | Err(_) => false,
+            };
+            Op::Copy(CopyOp {
+                src: src.clone(),
+                dest: dest.clone(),
+                dir: src_is_dir,
+            })
+        } else if *hardlink {
+            Op::Hardlink(HardlinkOp {
+                src: src.clone(),
+                dest: dest.clone(),
+            })
+        } else {
+            Op::Link(LinkOp {
+                src: src.clone(),
+                dest: dest.clone(),
+                fallback: *fallback,
+            })
+        }
+    }
+
     #[inline]
     fn resolve_link(&self) -> Result<Res, Error> {
         let Self {
             src,
             dest,
             copy: _,
+            hardlink: _,
+            fallback,
             optional: _,
+            mode: _,
+            owner: _,
+            on_conflict: _,
+            if_missing: _,
         } = self;
 
         // Check the filetype and determine if overwrite is necessary.
@@ -118,13 +227,24 @@ impl LinkAction {
             Ok(meta) if meta.is_symlink() => {
                 // SAFETY: Already determined it exists and is a symlink.
                 let target = fs::read_link(dest).unwrap();
-                if target == *src {
+                // Junctions made in place of symlinks hold the resolved source.
+                let same = match link::link_kind(dest) {
+                    Some(LinkKind::Junction) => target == link::junction_target(src, dest),
+                    Some(LinkKind::Symlink) | None => target == *src,
+                };
+                if same {
                     return Ok(Res::Skip(Skip::DestExists));
                 } else {
                     (true, false)
                 }
             }
 
+            // A file with the same contents may be a copy made in place of the link, since
+            // symlinks couldn't be created; skip.
+            Ok(meta) if meta.is_file() && *fallback && same_contents(src, dest) => {
+                return Ok(Res::Skip(Skip::DestExists));
+            }
+
             // For existing files and directories, warn about an overwrite.
             // Remove the file, and then link.
             Ok(meta) if meta.is_dir() => (true, true),
@@ -135,10 +255,7 @@ impl LinkAction {
             Ok(_) | Err(_) => (false, false),
         };
 
-        let link_op = Op::Link(LinkOp {
-            src: src.clone(),
-            dest: dest.clone(),
-        });
+        let link_op = self.create_op();
         if overwrite {
             // Add op to remove existing file if exist.
             let rm_op = Op::Rm(RmOp {
@@ -162,11 +279,6 @@ impl LinkAction {
     fn resolve_copy(&self) -> Result<Res, Error> {
         let Self { src, dest, .. } = self;
 
-        let src_is_dir = match fs::symlink_metadata(src) {
-            Ok(meta) if meta.is_dir() => true,
-            Ok(_) | Err(_) => false,
-        };
-
         let (overwrite_dest, dest_is_dir) = match fs::symlink_metadata(dest) {
             // For files, check the contents. If they match, we should do nothing.
             // If not, proceed with overwrite.
@@ -178,7 +290,13 @@ impl LinkAction {
                     })
                     .unwrap_or(false);
                 if content_same {
-                    return Ok(Res::Skip(Skip::DestExists));
+                    // The owner and permission bits may still need to be set.
+                    let ops = self.perms_ops(true);
+                    return Ok(if ops.is_empty() {
+                        Res::Skip(Skip::DestExists)
+                    } else {
+                        Res::Normal(ops)
+                    });
                 }
 
                 (true, false)
@@ -194,11 +312,7 @@ impl LinkAction {
             Ok(_) | Err(_) => (false, false),
         };
 
-        let copy_op = Op::Copy(CopyOp {
-            src: src.clone(),
-            dest: dest.clone(),
-            dir: src_is_dir,
-        });
+        let copy_op = self.create_op();
         if overwrite_dest {
             // Add op to remove existing file if exist.
             let rm_op = Op::Rm(RmOp {
@@ -206,14 +320,115 @@ impl LinkAction {
                 dir: dest_is_dir,
             });
 
-            Ok(Res::Overwrite(vec![rm_op, copy_op]))
+            let mut ops = vec![rm_op, copy_op];
+            ops.extend(self.perms_ops(false));
+            Ok(Res::Overwrite(ops))
         } else {
             // Check for existence of parent directories and add op to make parent directories if
             // they don't exist.
             let mut ops: Vec<_> = mkdir::mkdir_parents_ops(dest).map(Op::Mkdir).collect();
 
             ops.push(copy_op);
+            ops.extend(self.perms_ops(false));
+            Ok(Res::Normal(ops))
+        }
+    }
+
+    /// Apply `on_conflict` to `ops`, which overwrite the existing `dest`.
+    #[inline]
+    fn resolve_conflict(&self, ops: Vec<Op>) -> Result<Res, Error> {
+        match self.on_conflict {
+            ConflictPolicy::Replace => Ok(Res::Overwrite(ops)),
+            ConflictPolicy::Skip => Ok(Res::Skip(Skip::Conflict)),
+            ConflictPolicy::Backup => {
+                let mut backup: Vec<_> = conflict::backup_op(&self.dest)
+                    .map(Op::Copy)
+                    .into_iter()
+                    .collect();
+                backup.extend(ops);
+                Ok(Res::Overwrite(backup))
+            }
+            ConflictPolicy::Fail => Err(Error::Conflict),
+        }
+    }
+
+    /// Return the ops that set the owner and then the permission bits of the copy at `dest`, if
+    /// given. `exists` is whether the current file at `dest` is kept.
+    #[inline]
+    fn perms_ops(&self, exists: bool) -> Vec<Op> {
+        let mut ops = Vec::new();
+        if !self.copy {
+            return ops;
+        }
+
+        #[cfg(unix)]
+        ops.extend(mode::chown_op(&self.dest, self.owner, exists).map(Op::Chown));
+        ops.extend(
+            self.mode
+                .and_then(|mode| mode::chmod_op(&self.dest, mode, exists))
+                .map(Op::Chmod),
+        );
+        ops
+    }
+
+    #[inline]
+    fn resolve_hardlink(&self) -> Result<Res, Error> {
+        let Self { src, dest, .. } = self;
+
+        let (overwrite, is_dir) = match (fs::metadata(src), fs::symlink_metadata(dest)) {
+            // For files, skip if the destination is already a link to src. If the filesystems
+            // differ, it was copied instead, so compare the contents.
+            (Ok(src_meta), Ok(meta)) if meta.is_file() => {
+                let same = if fse::same_file(&src_meta, &meta) {
+                    true
+                } else if !fse::same_device(&src_meta, &meta) {
+                    match (fs::read(src), fs::read(dest)) {
+                        (Ok(src_contents), Ok(dest_contents)) => src_contents == dest_contents,
+                        _ => false,
+                    }
+                } else {
+                    false
+                };
+                if same {
+                    return Ok(Res::Skip(Skip::DestExists));
+                }
+
+                (true, false)
+            }
+
+            // For directories and symlinks, warn about an overwrite.
+            (_, Ok(meta)) if meta.is_dir() => (true, true),
+            (_, Ok(meta)) if meta.is_symlink() => (true, false),
+
+            // File doesn't exist, or insufficient permissions; treat as nonexistent.
+            _ => (false, false),
+        };
+
+        let hardlink_op = self.create_op();
+        if overwrite {
+            // Add op to remove existing file if exist.
+            let rm_op = Op::Rm(RmOp {
+                path: dest.clone(),
+                dir: is_dir,
+            });
+
+            Ok(Res::Overwrite(vec![rm_op, hardlink_op]))
+        } else {
+            // Check for existence of parent directories and add op to make parent directories if
+            // they don't exist.
+            let mut ops: Vec<_> = mkdir::mkdir_parents_ops(dest).map(Op::Mkdir).collect();
+
+            ops.push(hardlink_op);
             Ok(Res::Normal(ops))
         }
     }
 }
+
+/// Return true if `src` and `dest` are both readable files with the same contents.
+#[inline]
+fn same_contents(src: &Path, dest: &Path) -> bool {
+    match (fs::read(src), fs::read(dest)) {
+        (Ok(src), Ok(dest)) => src == dest,
+        _ => false,
+    }
+}
@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fse;
+
+/// What to do when the parent directory of a destination doesn't exist. A missing parent often
+/// means that the application that reads the destination isn't installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingParentPolicy {
+    /// Create the parent, and any of its missing ancestors.
+    CreateParent,
+    /// Skip the directive.
+    Skip,
+    /// Fail the directive.
+    Error,
+}
+
+impl Default for MissingParentPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self::CreateParent
+    }
+}
+
+/// Return true if the parent directory of `dest` doesn't exist.
+#[inline]
+pub fn parent_missing(dest: &Path) -> bool {
+    dest.parent()
+        .is_some_and(|parent| !parent.as_os_str().is_empty() && !fse::symlink_exists(parent))
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+
+    use super::parent_missing;
+
+    #[test]
+    fn test_parent_missing() -> std::io::Result<()> {
+        let dir = TempDir::new()?;
+
+        assert!(!parent_missing(&dir.path().join("a")));
+        assert!(parent_missing(&dir.path().join("a/b")));
+        Ok(())
+    }
+}
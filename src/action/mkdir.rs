@@ -2,8 +2,11 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use crate::fse;
-use crate::op::{MkdirOp, RmOp};
+#[cfg(unix)]
+use crate::op::ChownOp;
+use crate::op::{ChmodOp, MkdirOp, RmOp};
 
+use super::mode::{self, Owner};
 use super::Resolve;
 
 #[derive(Debug, Clone)]
@@ -12,6 +15,10 @@ pub struct MkdirAction {
     pub path: PathBuf,
     /// Missing parrents should also be created.
     pub parents: bool,
+    /// Permission bits to set on the directory, if any.
+    pub mode: Option<u32>,
+    /// Owner to set on the directory.
+    pub owner: Owner,
 }
 
 #[derive(Debug, Clone)]
@@ -29,6 +36,11 @@ pub enum Op {
     Rm(RmOp),
     /// Mkdir operation.
     Mkdir(MkdirOp),
+    /// Chmod operation.
+    Chmod(ChmodOp),
+    /// Chown operation.
+    #[cfg(unix)]
+    Chown(ChownOp),
 }
 
 /// Reason for skipping [`MkdirAction`].
@@ -43,12 +55,18 @@ impl Resolve for MkdirAction {
 
     #[inline]
     fn resolve(&self) -> Self::Output {
-        let Self { path, parents } = self;
+        let Self { path, parents, .. } = self;
 
         let (overwrite, is_dir) = match fs::symlink_metadata(path) {
-            // For directories, we should do nothing, as it already exists.
+            // For directories, we should do nothing, as it already exists, except maybe setting
+            // the owner and permission bits.
             Ok(meta) if meta.is_dir() => {
-                return Res::Skip(Skip::DestExists);
+                let ops = self.perms_ops(true);
+                return if ops.is_empty() {
+                    Res::Skip(Skip::DestExists)
+                } else {
+                    Res::Normal(ops)
+                };
             }
 
             // For files and symlinks, warn about an overwrite, remove the file, and then link.
@@ -71,12 +89,30 @@ impl Resolve for MkdirAction {
             };
 
             ops.push(Op::Mkdir(MkdirOp { path: path.clone() }));
+            ops.extend(self.perms_ops(false));
 
             Res::Normal(ops)
         }
     }
 }
 
+impl MkdirAction {
+    /// Return the ops that set the owner and then the permission bits of the directory, if
+    /// given. `exists` is whether the directory already exists.
+    #[inline]
+    fn perms_ops(&self, exists: bool) -> Vec<Op> {
+        let mut ops = Vec::new();
+        #[cfg(unix)]
+        ops.extend(mode::chown_op(&self.path, self.owner, exists).map(Op::Chown));
+        ops.extend(
+            self.mode
+                .and_then(|mode| mode::chmod_op(&self.path, mode, exists))
+                .map(Op::Chmod),
+        );
+        ops
+    }
+}
+
 #[inline]
 pub fn mkdir_parents_ops<P>(path: P) -> impl Iterator<Item = MkdirOp>
 where
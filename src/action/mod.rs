@@ -1,21 +1,46 @@
+pub mod comment;
+pub mod mode;
 pub mod object;
 
 pub mod command;
+pub mod conflict;
+pub mod copydir;
+pub mod defaults;
+pub mod expect;
+pub mod fragment;
 pub mod function;
 pub mod generated;
 pub mod link;
+pub mod missing;
 pub mod mkdir;
+pub mod perms;
+pub mod plugin;
+pub mod registry;
+pub mod script;
+pub mod sourceline;
+pub mod systemd;
 pub mod template;
 pub mod tree;
+pub mod volatile;
 pub mod write;
 
 // Re-export action types.
 pub use self::command::CommandAction;
+pub use self::copydir::CopyDirAction;
+pub use self::defaults::DefaultsAction;
+pub use self::expect::ExpectAction;
+pub use self::fragment::FragmentAction;
 pub use self::function::FunctionAction;
 pub use self::generated::{JsonAction, TomlAction, YamlAction};
 pub use self::link::LinkAction;
 pub use self::mkdir::MkdirAction;
-pub use self::template::{HandlebarsAction, LiquidAction};
+pub use self::perms::SensitivePermsAction;
+pub use self::plugin::PluginAction;
+pub use self::registry::RegValueAction;
+pub use self::script::ScriptAction;
+pub use self::sourceline::SourceLineAction;
+pub use self::systemd::SystemdUnitAction;
+pub use self::template::{GotmplAction, HandlebarsAction, LiquidAction, PipeAction};
 pub use self::tree::TreeAction;
 pub use self::write::WriteAction;
 
@@ -30,32 +55,105 @@ pub enum Action<'lua> {
     Link(LinkAction),
     Write(WriteAction),
     Tree(TreeAction),
-    Handlebars(HandlebarsAction),
+    CopyDir(CopyDirAction),
+    Handlebars(HandlebarsAction<'lua>),
     Liquid(LiquidAction),
+    Gotmpl(GotmplAction),
+    Pipe(PipeAction<'lua>),
     Yaml(YamlAction),
     Toml(TomlAction),
     Json(JsonAction),
     Mkdir(MkdirAction),
+    SystemdUnit(SystemdUnitAction),
+    Fragment(FragmentAction),
+    SourceLine(SourceLineAction),
+    Defaults(DefaultsAction),
+    RegValue(RegValueAction),
+    Plugin(PluginAction),
+    Expect(ExpectAction),
+    SensitivePerms(SensitivePermsAction),
     Command(CommandAction),
     Function(FunctionAction<'lua>),
+    Script(ScriptAction),
+}
+
+impl<'lua> Action<'lua> {
+    /// Set what to do if the destination already exists and would be overwritten, for actions
+    /// that link or write files. Other actions are left alone.
+    #[inline]
+    pub fn set_on_conflict(&mut self, policy: self::conflict::ConflictPolicy) {
+        match self {
+            Self::Link(action) => action.on_conflict = policy,
+            Self::Write(action) => action.on_conflict = policy,
+            Self::Tree(action) => action.on_conflict = policy,
+            Self::Handlebars(action) => action.on_conflict = policy,
+            Self::Liquid(action) => action.on_conflict = policy,
+            Self::Gotmpl(action) => action.on_conflict = policy,
+            Self::Pipe(action) => action.on_conflict = policy,
+            Self::Yaml(action) => action.on_conflict = policy,
+            Self::Toml(action) => action.on_conflict = policy,
+            Self::Json(action) => action.on_conflict = policy,
+            _ => {}
+        }
+    }
+
+    /// Set what to do if the parent of the destination doesn't exist, for actions that link files
+    /// or render templates. Other actions always create it.
+    #[inline]
+    pub fn set_if_missing(&mut self, policy: self::missing::MissingParentPolicy) {
+        match self {
+            Self::Link(action) => action.if_missing = policy,
+            Self::Tree(action) => action.if_missing = policy,
+            Self::Handlebars(action) => action.if_missing = policy,
+            Self::Liquid(action) => action.if_missing = policy,
+            Self::Gotmpl(action) => action.if_missing = policy,
+            _ => {}
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum ResolutionError {
     #[error("link action resolution error")]
     Link(#[from] self::link::Error),
+    #[error("destination already exists, and conflicts are set to fail")]
+    Conflict,
+    #[error("parent of destination doesn't exist, and missing parents are set to fail")]
+    ParentMissing,
+    #[error("copy dir action resolution error")]
+    CopyDir(#[from] self::copydir::Error),
     #[error("handlebars action resolution error")]
     Handlebars(#[from] self::template::hbs::Error),
     #[error("liquid action resolution error")]
     Liquid(#[from] self::template::liquid::Error),
+    #[error("gotmpl action resolution error")]
+    Gotmpl(#[from] self::template::gotmpl::Error),
+    #[error("pipe action resolution error")]
+    Pipe(#[from] self::template::pipe::Error),
     #[error("yaml action resolution error")]
     Yaml(#[from] self::generated::yaml::Error),
     #[error("toml action resolution error")]
     Toml(#[from] self::generated::toml::Error),
     #[error("json action resolution error")]
     Json(#[from] self::generated::json::Error),
+    #[error("fragment action resolution error")]
+    Fragment(#[from] self::fragment::Error),
+    #[error("source line action resolution error")]
+    SourceLine(#[from] self::sourceline::Error),
+    #[error("defaults action resolution error")]
+    Defaults(#[from] self::defaults::Error),
+    #[error("registry value action resolution error")]
+    RegValue(#[from] self::registry::Error),
+    #[error("plugin action resolution error")]
+    Plugin(#[from] self::plugin::Error),
+    #[error("expectation not met")]
+    Expect(#[from] self::expect::Error),
+    #[error("systemd unit action resolution error")]
+    SystemdUnit(#[from] self::systemd::Error),
     #[error("command action resolution error")]
     Command(#[from] self::command::Error),
     #[error("function action resolution error")]
     Function(#[from] self::function::Error),
+    #[error("script action resolution error")]
+    Script(#[from] self::script::Error),
 }
@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use crate::op::ChmodOp;
+#[cfg(unix)]
+use crate::op::ChownOp;
+
+/// Owning user and group ids to set on a file; either is left unchanged if `None`. Ignored on
+/// Windows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// Return the op that sets the permission bits of the file at `path` to `mode`. If `exists`, the
+/// file at `path` is kept by the other ops of the action, and no op is returned if its bits are
+/// already `mode`.
+#[cfg(unix)]
+#[inline]
+pub fn chmod_op(path: &Path, mode: u32, exists: bool) -> Option<ChmodOp> {
+    let unchanged = exists && crate::op::chmod::get_mode(path).is_ok_and(|cur| cur == mode);
+    if unchanged {
+        None
+    } else {
+        Some(ChmodOp {
+            path: path.to_path_buf(),
+            mode,
+        })
+    }
+}
+
+#[cfg(windows)]
+#[inline]
+pub fn chmod_op(_path: &Path, _mode: u32, _exists: bool) -> Option<ChmodOp> {
+    // Permission bits aren't supported on Windows.
+    None
+}
+
+/// Return the op that sets the owner of the file at `path` to `owner`, unless neither the user
+/// nor the group is given. If `exists`, the file at `path` is kept by the other ops of the action,
+/// and no op is returned if it is already owned by them.
+#[cfg(unix)]
+#[inline]
+pub fn chown_op(path: &Path, owner: Owner, exists: bool) -> Option<ChownOp> {
+    let Owner { uid, gid } = owner;
+    if uid.is_none() && gid.is_none() {
+        return None;
+    }
+
+    let unchanged = exists
+        && crate::op::chown::get_owner(path).is_ok_and(|(cur_uid, cur_gid)| {
+            uid.is_none_or(|uid| uid == cur_uid) && gid.is_none_or(|gid| gid == cur_gid)
+        });
+    if unchanged {
+        None
+    } else {
+        Some(ChownOp {
+            path: path.to_path_buf(),
+            uid,
+            gid,
+        })
+    }
+}
@@ -1,13 +1,13 @@
-use std::collections::HashMap;
-
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+/// Map of values. Keys keep their insertion order, so that serialized output is stable.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(transparent)]
-pub struct Object(pub HashMap<String, Value>);
+pub struct Object(pub IndexMap<String, Value>);
 
 // FIXME Custom serialization/deserialization to handle Nil?
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum Value {
     Nil,
@@ -15,13 +15,21 @@ pub enum Value {
     Int(i64),
     Float(f64),
     Str(String),
-    Object(HashMap<String, Value>),
+    /// Sequence, converted from Lua tables whose keys are exactly `1..=n`.
+    Array(Vec<Value>),
+    Object(IndexMap<String, Value>),
 }
 
 impl Object {
     #[inline]
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self(IndexMap::new())
+    }
+
+    /// Sort the keys of this object and of all nested objects.
+    #[inline]
+    pub fn sort_keys(&mut self) {
+        sort_map(&mut self.0);
     }
 }
 
@@ -30,3 +38,35 @@ impl Default for Object {
         Self::new()
     }
 }
+
+impl Value {
+    /// Return the name of the type of this value, e.g. for error messages.
+    #[inline]
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Bool(_) => "boolean",
+            Self::Int(_) => "integer",
+            Self::Float(_) => "float",
+            Self::Str(_) => "string",
+            Self::Array(_) => "array",
+            Self::Object(_) => "object",
+        }
+    }
+
+    /// Sort the keys of all objects in this value.
+    #[inline]
+    pub fn sort_keys(&mut self) {
+        match self {
+            Self::Array(values) => values.iter_mut().for_each(Self::sort_keys),
+            Self::Object(map) => sort_map(map),
+            Self::Nil | Self::Bool(_) | Self::Int(_) | Self::Float(_) | Self::Str(_) => {}
+        }
+    }
+}
+
+#[inline]
+fn sort_map(map: &mut IndexMap<String, Value>) {
+    map.sort_keys();
+    map.values_mut().for_each(Value::sort_keys);
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::op::{chmod, ChmodOp};
+
+use super::Resolve;
+
+/// Well-known security-sensitive directories, relative to the destination.
+pub const SENSITIVE_DIRS: &[&str] = &[".ssh", ".gnupg"];
+
+/// Action to check the permissions of security-sensitive directories (see [`SENSITIVE_DIRS`])
+/// and the files in them. Directories must not be accessible by group or others (0700), nor
+/// may files (0600), except for public keys, which must merely not be writable by them.
+#[derive(Debug, Clone)]
+pub struct SensitivePermsAction {
+    /// Destination in which the sensitive directories are found.
+    pub dest: PathBuf,
+    /// Emit operations that fix violations.
+    pub fix: bool,
+}
+
+/// A file or directory with overly permissive mode bits.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub path: PathBuf,
+    /// Current permission bits.
+    pub mode: u32,
+    /// Permission bits the file should have.
+    pub expected: u32,
+}
+
+// Resolution of [`SensitivePermsAction`].
+#[derive(Debug, Clone)]
+pub struct Res {
+    pub violations: Vec<Violation>,
+    /// Operations to fix the violations; empty unless `fix` is set.
+    pub ops: Vec<ChmodOp>,
+}
+
+impl Resolve for SensitivePermsAction {
+    type Output = Res;
+
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        let Self { dest, fix } = self;
+
+        let mut violations = Vec::new();
+        for dir in SENSITIVE_DIRS {
+            check_recursive(&dest.join(dir), &mut violations);
+        }
+
+        let ops = if *fix {
+            violations
+                .iter()
+                .map(|v| ChmodOp {
+                    path: v.path.clone(),
+                    mode: v.expected,
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        Res { violations, ops }
+    }
+}
+
+#[inline]
+fn check_recursive(path: &Path, violations: &mut Vec<Violation>) {
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return,
+    };
+    let mode = match chmod::get_mode(path) {
+        Ok(mode) => mode,
+        Err(_) => return,
+    };
+
+    let is_pub = path.extension().is_some_and(|ext| ext == "pub");
+    let forbidden = if meta.is_dir() || !is_pub {
+        0o077
+    } else {
+        0o022
+    };
+    if mode & forbidden != 0 {
+        violations.push(Violation {
+            path: path.to_path_buf(),
+            mode,
+            expected: mode & !forbidden,
+        });
+    }
+
+    if meta.is_dir() {
+        let mut entries: Vec<_> = match fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|e| e.path())
+                .collect(),
+            Err(_) => return,
+        };
+        entries.sort();
+
+        for entry in entries {
+            check_recursive(&entry, violations);
+        }
+    }
+}
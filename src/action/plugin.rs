@@ -0,0 +1,241 @@
+//! Directives of custom kinds, resolved by WebAssembly modules.
+//!
+//! A plugin module is instantiated without any imports, so it can't touch the filesystem, the
+//! network, or anything else outside of its own memory, and it is given a limited amount of fuel
+//! so that it can't run forever. It must export:
+//!
+//! - `memory`, its linear memory;
+//! - `shelf_alloc(len: i32) -> i32`, which returns a pointer to `len` free bytes;
+//! - `shelf_resolve(ptr: i32, len: i32) -> i64`, which is given the JSON input at `ptr` and
+//!   returns the pointer to its JSON output in the upper 32 bits and its length in the lower.
+//!
+//! The input is `{"kind": ..., "args": {...}}`, and the output is either
+//! `{"directives": [...]}` or `{"error": "..."}`, where each directive is one of
+//! `{"link": {"src": ..., "dest": ...}}`, `{"write": {"dest": ..., "contents": ...}}`, and
+//! `{"mkdir": {"path": ...}}`. Sources are relative to the package and destinations to the home
+//! directory; neither may be absolute or contain `..`.
+
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::fse;
+use crate::graph::PathResolver;
+
+use super::object::Object;
+use super::{Action, LinkAction, MkdirAction, Resolve, WriteAction};
+
+/// Amount of fuel that a plugin may consume in one call, roughly the number of instructions.
+#[cfg(feature = "wasm")]
+const FUEL: u64 = 1_000_000_000;
+/// Maximum size in bytes of the memory of a plugin.
+#[cfg(feature = "wasm")]
+const MAX_MEMORY: usize = 64 << 20;
+
+/// Action to run a WebAssembly plugin that resolves a custom directive kind into links,
+/// writes, and directories.
+#[derive(Debug, Clone)]
+pub struct PluginAction {
+    /// Absolute path of the module.
+    pub module: PathBuf,
+    /// Kind of directive, passed to the module.
+    pub kind: String,
+    /// Arguments of the directive, passed to the module.
+    pub args: Object,
+    /// Absolute path of the directory that sources are relative to.
+    pub src_root: PathBuf,
+    /// Resolver through which destinations are joined to the home directory.
+    pub paths: PathResolver,
+}
+
+/// Directive returned by a plugin, with its paths made absolute.
+#[derive(Debug, Clone)]
+pub enum PluginDirective {
+    Link(LinkAction),
+    Write(WriteAction),
+    Mkdir(MkdirAction),
+}
+
+impl<'lua> From<PluginDirective> for Action<'lua> {
+    #[inline]
+    fn from(directive: PluginDirective) -> Self {
+        match directive {
+            PluginDirective::Link(action) => Action::Link(action),
+            PluginDirective::Write(action) => Action::Write(action),
+            PluginDirective::Mkdir(action) => Action::Mkdir(action),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// shelf was built without the `wasm` feature.
+    #[error("wasm plugins are not supported in this build")]
+    Unsupported,
+    #[error("couldn't run module: {0}")]
+    Wasm(String),
+    #[error("invalid module output")]
+    Output(#[from] serde_json::Error),
+    #[error("module reported error: {0}")]
+    Plugin(String),
+    #[error("module returned path that is absolute or contains '..': {0}")]
+    Path(PathBuf),
+}
+
+impl Resolve for PluginAction {
+    type Output = Result<Vec<PluginDirective>, Error>;
+
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        let input = serde_json::to_vec(&Input {
+            kind: &self.kind,
+            args: &self.args,
+        })?;
+        let output = run(&self.module, &input)?;
+
+        let directives = match serde_json::from_slice(&output)? {
+            Output::Directives(directives) => directives,
+            Output::Error(err) => return Err(Error::Plugin(err)),
+        };
+
+        directives
+            .into_iter()
+            .map(|directive| {
+                Ok(match directive {
+                    WireDirective::Link {
+                        src,
+                        dest,
+                        copy,
+                        optional,
+                    } => PluginDirective::Link(LinkAction {
+                        src: fse::clean(self.src_root.join(contained(src)?)),
+                        dest: self.paths.join(contained(dest)?),
+                        copy,
+                        hardlink: false,
+                        fallback: false,
+                        optional,
+                        mode: None,
+                        owner: Default::default(),
+                        on_conflict: Default::default(),
+                        if_missing: Default::default(),
+                    }),
+                    WireDirective::Write { dest, contents } => {
+                        PluginDirective::Write(WriteAction {
+                            dest: self.paths.join(contained(dest)?),
+                            contents: contents.into_bytes(),
+                            mode: None,
+                            owner: Default::default(),
+                            on_conflict: Default::default(),
+                        })
+                    }
+                    WireDirective::Mkdir { path } => PluginDirective::Mkdir(MkdirAction {
+                        path: self.paths.join(contained(path)?),
+                        parents: true,
+                        mode: None,
+                        owner: Default::default(),
+                    }),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct Input<'a> {
+    kind: &'a str,
+    args: &'a Object,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Output {
+    Directives(Vec<WireDirective>),
+    Error(String),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WireDirective {
+    Link {
+        src: PathBuf,
+        dest: PathBuf,
+        #[serde(default)]
+        copy: bool,
+        #[serde(default)]
+        optional: bool,
+    },
+    Write {
+        dest: PathBuf,
+        contents: String,
+    },
+    Mkdir {
+        path: PathBuf,
+    },
+}
+
+/// Check that `path` stays under whatever it is joined to.
+#[inline]
+fn contained(path: PathBuf) -> Result<PathBuf, Error> {
+    let escapes = path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        Err(Error::Path(path))
+    } else {
+        Ok(path)
+    }
+}
+
+/// Instantiate `module` and call its resolver with `input`, returning its output.
+#[cfg(feature = "wasm")]
+#[inline]
+fn run(module: &Path, input: &[u8]) -> Result<Vec<u8>, Error> {
+    use std::convert::TryFrom;
+
+    use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+    let wasm = |err: wasmtime::Error| Error::Wasm(format!("{:#}", err));
+
+    let mut config = Config::new();
+    config.consume_fuel(true).wasm_backtrace(false);
+    let engine = Engine::new(&config).map_err(wasm)?;
+    let module = Module::from_file(&engine, module).map_err(wasm)?;
+
+    let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+    let mut store = Store::new(&engine, limits);
+    store.limiter(|limits: &mut StoreLimits| limits);
+    store.set_fuel(FUEL).map_err(wasm)?;
+
+    // No imports are provided, so the module can only compute.
+    let instance = Instance::new(&mut store, &module, &[]).map_err(wasm)?;
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .ok_or_else(|| Error::Wasm("module doesn't export memory".to_string()))?;
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&mut store, "shelf_alloc")
+        .map_err(wasm)?;
+    let resolve = instance
+        .get_typed_func::<(i32, i32), i64>(&mut store, "shelf_resolve")
+        .map_err(wasm)?;
+
+    let len = i32::try_from(input.len()).map_err(|_| Error::Wasm("input too large".to_string()))?;
+    let ptr = alloc.call(&mut store, len).map_err(wasm)?;
+    memory
+        .write(&mut store, ptr as u32 as usize, input)
+        .map_err(|err| Error::Wasm(err.to_string()))?;
+
+    let packed = resolve.call(&mut store, (ptr, len)).map_err(wasm)?;
+    let (ptr, len) = ((packed >> 32) as u32 as usize, packed as u32 as usize);
+    let mut output = vec![0; len];
+    memory
+        .read(&store, ptr, &mut output)
+        .map_err(|err| Error::Wasm(err.to_string()))?;
+
+    Ok(output)
+}
+
+#[cfg(not(feature = "wasm"))]
+#[inline]
+fn run(_module: &Path, _input: &[u8]) -> Result<Vec<u8>, Error> {
+    Err(Error::Unsupported)
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::op::error::RegistryError;
+#[cfg(all(windows, feature = "registry"))]
+use crate::op::{registry, RegistryOp};
+
+use super::Resolve;
+
+/// Value of a Windows registry entry. Only scalar values are supported.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RegistryValue {
+    Dword(u32),
+    Qword(u64),
+    Sz(String),
+    ExpandSz(String),
+}
+
+impl RegistryValue {
+    /// Return the name of the registry type of this value, e.g. `REG_DWORD`.
+    #[inline]
+    pub fn typ(&self) -> &'static str {
+        match self {
+            Self::Dword(_) => "REG_DWORD",
+            Self::Qword(_) => "REG_QWORD",
+            Self::Sz(_) => "REG_SZ",
+            Self::ExpandSz(_) => "REG_EXPAND_SZ",
+        }
+    }
+
+    /// Return the data of this value as passed to `reg add`.
+    #[inline]
+    pub fn data(&self) -> String {
+        match self {
+            Self::Dword(n) => n.to_string(),
+            Self::Qword(n) => n.to_string(),
+            Self::Sz(s) | Self::ExpandSz(s) => s.clone(),
+        }
+    }
+}
+
+impl fmt::Display for RegistryValue {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dword(n) => write!(f, "{}", n),
+            Self::Qword(n) => write!(f, "{}", n),
+            Self::Sz(s) | Self::ExpandSz(s) => write!(f, "'{}'", s),
+        }
+    }
+}
+
+/// Action to set a Windows registry value, as with `reg add`. The action is skipped unless
+/// shelf is built for Windows with the `registry` feature.
+#[derive(Debug, Clone)]
+pub struct RegValueAction {
+    /// Path of the key, e.g. `HKCU\Console`.
+    pub key: String,
+    /// Name of the value.
+    pub name: String,
+    /// Value to set.
+    pub value: RegistryValue,
+}
+
+/// Error that occurs when resolving [`RegValueAction`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The current value couldn't be read.
+    #[error("couldn't read current value")]
+    Read(#[from] RegistryError),
+}
+
+#[derive(Debug, Clone)]
+pub enum Res {
+    #[cfg(all(windows, feature = "registry"))]
+    Normal(RegistryOp),
+    /// The action is skipped.
+    Skip(Skip),
+}
+
+/// Reason for skipping [`RegValueAction`].
+#[derive(Debug, Clone)]
+pub enum Skip {
+    /// Registry support isn't compiled in.
+    Unsupported,
+    /// The value is already set.
+    UpToDate,
+}
+
+impl Resolve for RegValueAction {
+    type Output = Result<Res, Error>;
+
+    #[cfg(all(windows, feature = "registry"))]
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        let Self { key, name, value } = self;
+
+        if registry::read(key, name)?.as_ref() == Some(value) {
+            Ok(Res::Skip(Skip::UpToDate))
+        } else {
+            Ok(Res::Normal(RegistryOp {
+                key: key.clone(),
+                name: name.clone(),
+                value: value.clone(),
+            }))
+        }
+    }
+
+    #[cfg(not(all(windows, feature = "registry")))]
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        Ok(Res::Skip(Skip::Unsupported))
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::op::command::EnvMap;
+use crate::op::{ChmodOp, ScriptOp};
+
+use super::Resolve;
+
+/// Action to execute a file in the package, making it executable first if needed.
+#[derive(Debug, Clone)]
+pub struct ScriptAction {
+    /// Path of the file to execute.
+    pub path: PathBuf,
+    /// Arguments to pass.
+    pub args: Vec<String>,
+
+    /// Directory in which the file is executed.
+    pub start: PathBuf,
+    /// Environment variables to set.
+    pub env: EnvMap,
+
+    /// See [`super::CommandAction::only_if_changed`].
+    pub only_if_changed: bool,
+    /// See [`super::CommandAction::timeout`].
+    pub timeout: Option<Duration>,
+}
+
+// Resolution of [`ScriptAction`].
+#[derive(Debug, Clone)]
+pub enum Res {
+    /// Normal procedure.
+    Normal(Vec<Op>),
+}
+
+/// Operation created by resolution.
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Chmod operation, to make the file executable.
+    Chmod(ChmodOp),
+    /// Script operation.
+    Script(ScriptOp),
+}
+
+/// Error that occurs when resolving [`ScriptAction`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// `path` was not found.
+    #[error("script missing")]
+    ScriptMissing,
+    /// `path` is not a regular file.
+    #[error("script not a file")]
+    ScriptNotFile,
+    /// `start` was not found.
+    #[error("start directory missing")]
+    StartMissing,
+}
+
+impl Resolve for ScriptAction {
+    type Output = Result<Res, Error>;
+
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        let Self {
+            path,
+            args,
+            start,
+            env,
+            only_if_changed: _,
+            timeout,
+        } = self;
+
+        if !path.exists() {
+            return Err(Error::ScriptMissing);
+        } else if !path.is_file() {
+            return Err(Error::ScriptNotFile);
+        } else if !start.is_dir() {
+            return Err(Error::StartMissing);
+        }
+
+        let mut ops = Vec::new();
+        if let Some(mode) = exec_mode(path) {
+            ops.push(Op::Chmod(ChmodOp {
+                path: path.clone(),
+                mode,
+            }));
+        }
+
+        ops.push(Op::Script(ScriptOp {
+            path: path.clone(),
+            args: args.clone(),
+            start: start.clone(),
+            env: env.clone(),
+            timeout: *timeout,
+        }));
+
+        Ok(Res::Normal(ops))
+    }
+}
+
+/// Return the mode that makes the file at `path` executable by those who can read it, or `None`
+/// if it is already executable by its owner.
+#[cfg(unix)]
+#[inline]
+fn exec_mode(path: &Path) -> Option<u32> {
+    let mode = crate::op::chmod::get_mode(path).ok()?;
+    if mode & 0o100 != 0 {
+        None
+    } else {
+        Some(mode | (mode & 0o444) >> 2)
+    }
+}
+
+#[cfg(windows)]
+#[inline]
+fn exec_mode(_path: &Path) -> Option<u32> {
+    // Executability isn't a permission bit on Windows.
+    None
+}
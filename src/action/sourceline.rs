@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+use std::{fs, io};
+
+use crate::op::SourceLineOp;
+
+use super::Resolve;
+
+/// Action to ensure that a line, e.g. `source ~/.config/aliases.sh`, exists exactly once in a
+/// shell rc file. If it doesn't, it is inserted into a block managed by the package, so that
+/// undoing leaves the rest of the file alone.
+#[derive(Debug, Clone)]
+pub struct SourceLineAction {
+    /// Path of the rc file.
+    pub path: PathBuf,
+    /// Line to ensure.
+    pub line: String,
+    /// Name of the managed block of the package.
+    pub block: String,
+}
+
+/// Error that occurs when resolving [`SourceLineAction`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The rc file couldn't be read.
+    #[error("couldn't read rc file")]
+    Read(#[from] io::Error),
+}
+
+#[derive(Debug, Clone)]
+pub enum Res {
+    Normal(SourceLineOp),
+    /// The action is skipped.
+    Skip(Skip),
+}
+
+/// Reason for skipping [`SourceLineAction`].
+#[derive(Debug, Clone)]
+pub enum Skip {
+    /// The line already exists in the file.
+    LineExists,
+}
+
+impl Resolve for SourceLineAction {
+    type Output = Result<Res, Error>;
+
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        let Self { path, line, block } = self;
+
+        let exists = match fs::read_to_string(path) {
+            Ok(contents) => contents.lines().any(|l| l.trim() == line.trim()),
+            // The file will be created.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        if exists {
+            Ok(Res::Skip(Skip::LineExists))
+        } else {
+            Ok(Res::Normal(SourceLineOp {
+                path: path.clone(),
+                line: line.clone(),
+                block: block.clone(),
+            }))
+        }
+    }
+}
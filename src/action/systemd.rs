@@ -0,0 +1,229 @@
+use std::path::PathBuf;
+
+use crate::op::{systemctl::SystemctlCommand, LinkOp, MkdirOp, RmOp, SystemctlOp};
+
+use super::link::{self, Res as LinkActionRes};
+use super::{LinkAction, Resolve};
+
+/// Action to link a systemd user unit from `src` to `dest` and bring systemd up to date with it.
+///
+/// When the unit changes, the user manager is reloaded (and the unit restarted if
+/// `restart_on_change` is set). The unit is enabled if `enable` is set; whether it already is is
+/// only checked when the ops are applied, so that resolving never runs `systemctl`.
+#[derive(Debug, Clone)]
+pub struct SystemdUnitAction {
+    /// Path of the unit file.
+    pub src: PathBuf,
+    /// Path to which the unit file is linked, usually in `~/.config/systemd/user`.
+    pub dest: PathBuf,
+
+    /// Enable the unit.
+    pub enable: bool,
+    /// Restart the unit when the unit file changes.
+    pub restart_on_change: bool,
+}
+
+/// Error that occurs when resolving [`SystemdUnitAction`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// `src` was not found.
+    #[error("src missing")]
+    SrcMissing,
+    /// The unit name could not be determined from `dest`.
+    #[error("invalid unit name")]
+    InvalidName,
+}
+
+// Resolution of [`SystemdUnitAction`].
+#[derive(Debug, Clone)]
+pub enum Res {
+    /// Normal procedure.
+    Normal(Vec<Op>),
+    /// The destination unit file will be overwritten.
+    Overwrite(Vec<Op>),
+    /// The action is skipped.
+    Skip(Skip),
+}
+
+/// Operation created by resolution.
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Remove operation.
+    Rm(RmOp),
+    /// Link operation.
+    Link(LinkOp),
+    /// Mkdir operation.
+    Mkdir(MkdirOp),
+    /// Systemctl operation.
+    Systemctl(SystemctlOp),
+}
+
+/// Reason for skipping [`SystemdUnitAction`].
+#[derive(Debug, Clone)]
+pub enum Skip {
+    /// The unit is already linked, and enabling it isn't requested.
+    DestExists,
+}
+
+impl Resolve for SystemdUnitAction {
+    type Output = Result<Res, Error>;
+
+    #[inline]
+    fn resolve(&self) -> Self::Output {
+        let Self {
+            src,
+            dest,
+            enable,
+            restart_on_change,
+        } = self;
+
+        let unit = dest
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(Error::InvalidName)?
+            .to_string();
+
+        let link = LinkAction {
+            src: src.clone(),
+            dest: dest.clone(),
+            copy: false,
+            hardlink: false,
+            fallback: false,
+            optional: false,
+            mode: None,
+            owner: Default::default(),
+            on_conflict: Default::default(),
+            if_missing: Default::default(),
+        };
+        let (mut ops, overwrite) = match link.resolve() {
+            Ok(LinkActionRes::Normal(ops)) => (map_link_ops(ops), false),
+            Ok(LinkActionRes::Overwrite(ops)) => (map_link_ops(ops), true),
+            Ok(LinkActionRes::Skip(_)) => (vec![], false),
+            Err(link::Error::SrcMissing) => return Err(Error::SrcMissing),
+            // Existing units are always replaced, and their parents created.
+            Err(link::Error::Conflict | link::Error::ParentMissing) => unreachable!(),
+        };
+
+        let changed = !ops.is_empty();
+        if changed {
+            ops.push(systemctl_op(SystemctlCommand::DaemonReload));
+        }
+        if *enable {
+            ops.push(systemctl_op(SystemctlCommand::Enable(unit.clone())));
+        }
+        if *restart_on_change && changed {
+            ops.push(systemctl_op(SystemctlCommand::Restart(unit)));
+        }
+
+        if ops.is_empty() {
+            Ok(Res::Skip(Skip::DestExists))
+        } else if overwrite {
+            Ok(Res::Overwrite(ops))
+        } else {
+            Ok(Res::Normal(ops))
+        }
+    }
+}
+
+#[inline]
+fn systemctl_op(command: SystemctlCommand) -> Op {
+    Op::Systemctl(SystemctlOp { command })
+}
+
+#[inline]
+fn map_link_ops(ops: Vec<link::Op>) -> Vec<Op> {
+    ops.into_iter()
+        .filter_map(|op| match op {
+            link::Op::Rm(op) => Some(Op::Rm(op)),
+            link::Op::Link(op) => Some(Op::Link(op)),
+            link::Op::Mkdir(op) => Some(Op::Mkdir(op)),
+            // Units are always linked.
+            link::Op::Copy(_) | link::Op::Hardlink(_) | link::Op::Chmod(_) => None,
+            #[cfg(unix)]
+            link::Op::Chown(_) => None,
+        })
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::fs;
+    use std::os::unix;
+
+    use tempfile::TempDir;
+
+    use super::{Op, Res, Resolve, Skip, SystemdUnitAction};
+    use crate::op::systemctl::SystemctlCommand;
+
+    fn commands(ops: &[Op]) -> Vec<SystemctlCommand> {
+        ops.iter()
+            .filter_map(|op| match op {
+                Op::Systemctl(op) => Some(op.command.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Test that new units are linked, reloaded, enabled, and restarted as requested.
+    #[test]
+    fn test_resolve_new() -> std::io::Result<()> {
+        let dir = TempDir::new()?;
+        let src = dir.path().join("a.service");
+        fs::write(&src, "")?;
+        let dest = dir.path().join("user/a.service");
+
+        let action = SystemdUnitAction {
+            src: src.clone(),
+            dest: dest.clone(),
+            enable: true,
+            restart_on_change: true,
+        };
+        match action.resolve() {
+            Ok(Res::Normal(ops)) => {
+                assert!(
+                    matches!(&ops[..], [Op::Mkdir(_), Op::Link(link), ..] if link.dest == dest)
+                );
+                assert_eq!(
+                    commands(&ops),
+                    [
+                        SystemctlCommand::DaemonReload,
+                        SystemctlCommand::Enable("a.service".to_string()),
+                        SystemctlCommand::Restart("a.service".to_string()),
+                    ]
+                );
+            }
+            res => panic!("unexpected resolution {:?}", res),
+        }
+
+        Ok(())
+    }
+
+    /// Test that linked units are left alone, except to enable them, which is checked later.
+    #[test]
+    fn test_resolve_linked() -> std::io::Result<()> {
+        let dir = TempDir::new()?;
+        let src = dir.path().join("a.service");
+        fs::write(&src, "")?;
+        let dest = dir.path().join("b.service");
+        unix::fs::symlink(&src, &dest)?;
+
+        let mut action = SystemdUnitAction {
+            src,
+            dest,
+            enable: false,
+            restart_on_change: true,
+        };
+        assert!(matches!(action.resolve(), Ok(Res::Skip(Skip::DestExists))));
+
+        action.enable = true;
+        match action.resolve() {
+            Ok(Res::Normal(ops)) => assert_eq!(
+                commands(&ops),
+                [SystemctlCommand::Enable("b.service".to_string())]
+            ),
+            res => panic!("unexpected resolution {:?}", res),
+        }
+
+        Ok(())
+    }
+}
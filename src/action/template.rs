@@ -4,11 +4,17 @@ use std::path::Path;
 
 use crate::fse;
 
+use super::comment;
+use super::conflict::ConflictPolicy;
+use super::missing::{self, MissingParentPolicy};
+use super::mode::Owner;
 use super::write::{Res as WriteActionRes, WriteAction};
 use super::Resolve;
 
 // Re-export action types.
-pub use self::{hbs::HandlebarsAction, liquid::LiquidAction};
+pub use self::{
+    gotmpl::GotmplAction, hbs::HandlebarsAction, liquid::LiquidAction, pipe::PipeAction,
+};
 // Re-export Res types.
 pub use super::write::Op;
 // Re-export shared Object type.
@@ -17,12 +23,17 @@ pub use super::object::Object;
 #[derive(Debug, Clone)]
 pub enum Res {
     Normal(Vec<Op>),
-    /// The existing destination file's contents will be overwritten.
+    /// The existing destination file's contents differ, and it will be replaced by a new file. See
+    /// [`WriteActionRes::OverwriteContents`].
     OverwriteContents(Vec<Op>),
     /// The existing destination file will be replaced.
     OverwriteFile(Vec<Op>),
     /// The action is skipped.
     Skip(Skip),
+    /// The existing destination differs and would be overwritten, but conflicts are set to fail.
+    Conflict,
+    /// The parent of the destination doesn't exist, and missing parents are set to error.
+    ParentMissing,
 }
 
 impl Res {
@@ -36,12 +47,55 @@ impl Res {
             WriteActionRes::OverwriteFile(ops) => Self::OverwriteFile(ops),
             WriteActionRes::Skip(skip) => Self::Skip(match skip {
                 WriteActionSkip::DestExists => Skip::DestExists,
+                WriteActionSkip::Conflict => Skip::Conflict,
             }),
+            WriteActionRes::Conflict => Self::Conflict,
         }
     }
 }
 
-/// Reason for skipping [`HandlebarsAction`] or [`LiquidAction`].
+/// A template engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    Handlebars,
+    Liquid,
+    Gotmpl,
+}
+
+/// Registry of template file extensions and the engines that render them, used to select the
+/// engine of templates that don't specify one. Extensions are matched case-insensitively. New
+/// engines should register their extensions here.
+pub static ENGINES: &[(&str, Engine)] = &[
+    ("hbs", Engine::Handlebars),
+    ("handlebars", Engine::Handlebars),
+    ("liquid", Engine::Liquid),
+    ("gotmpl", Engine::Gotmpl),
+];
+
+impl Engine {
+    /// Select the engine for the template at `path` by its extension, or return `None` if no
+    /// engine is registered for it. See [`ENGINES`].
+    #[inline]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_lowercase();
+        ENGINES
+            .iter()
+            .find(|(registered, _)| *registered == ext)
+            .map(|(_, engine)| *engine)
+    }
+
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Handlebars => "hbs",
+            Self::Liquid => "liquid",
+            Self::Gotmpl => "gotmpl",
+        }
+    }
+}
+
+/// Reason for skipping [`HandlebarsAction`], [`LiquidAction`], [`GotmplAction`], or
+/// [`PipeAction`].
 #[derive(Debug, Clone)]
 pub enum Skip {
     /// `src` and `dest` are the same path.
@@ -50,31 +104,58 @@ pub enum Skip {
     OptMissing,
     /// Destination link already exists.
     DestExists,
+    /// Destination differs and would be overwritten, but conflicts are set to be skipped.
+    Conflict,
+    /// The parent of the destination doesn't exist, and missing parents are set to be skipped.
+    ParentMissing,
 }
 
 pub mod hbs {
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
     use std::io;
     use std::path::{Path, PathBuf};
 
-    use handlebars::Handlebars;
+    use handlebars::{
+        Context, Handlebars, Helper, HelperDef, JsonValue, RenderContext, ScopedJson,
+    };
+    use mlua::{
+        FromLua, Function, Lua, LuaSerdeExt, Result as LuaResult, ToLua, Value as LuaValue,
+        Variadic,
+    };
     use serde::Serialize;
 
-    use super::{Object, Res, Resolve};
+    use super::{ConflictPolicy, MissingParentPolicy, Object, Owner, Res, Resolve};
 
     // Re-export handlebars error types.
     pub use handlebars::{RenderError, TemplateError};
 
     pub type HandlebarsPartials = HashMap<String, PathBuf>;
 
+    /// Helpers implemented by Lua functions, by name. See [`HandlebarsAction::helpers`].
+    pub type HandlebarsHelpers<'lua> = BTreeMap<String, Function<'lua>>;
+
     #[derive(Debug, Clone)]
-    pub struct HandlebarsAction {
+    pub struct HandlebarsAction<'lua> {
         pub src: PathBuf,
         pub dest: PathBuf,
         pub vars: Object,
 
         pub optional: bool,
         pub partials: HandlebarsPartials,
+        /// Helpers registered by the package. Each is called with the values of the parameters it
+        /// is given, e.g. `{{upper name}}`, and renders what it returns.
+        pub helpers: HandlebarsHelpers<'lua>,
+
+        /// Header prepended to the rendered contents.
+        pub header: Option<String>,
+        /// Permission bits to set on the rendered file, if any.
+        pub mode: Option<u32>,
+        /// Owner to set on the rendered file.
+        pub owner: Owner,
+        /// What to do if `dest` already exists and would be overwritten.
+        pub on_conflict: ConflictPolicy,
+        /// What to do if the parent of `dest` doesn't exist.
+        pub if_missing: MissingParentPolicy,
     }
 
     #[derive(Debug, thiserror::Error)]
@@ -89,7 +170,7 @@ pub mod hbs {
         Render(#[from] RenderError),
     }
 
-    impl Resolve for HandlebarsAction {
+    impl<'lua> Resolve for HandlebarsAction<'lua> {
         type Output = Result<Res, Error>;
 
         #[inline]
@@ -100,11 +181,26 @@ pub mod hbs {
                 vars,
                 optional,
                 partials,
+                helpers,
+                header,
+                mode,
+                owner,
+                on_conflict,
+                if_missing,
             } = self;
 
-            super::resolve_impl(src, dest, vars, optional, |src, _dest, vars| {
-                render(src, vars, partials)
-            })
+            super::resolve_impl(
+                src,
+                dest,
+                vars,
+                optional,
+                header,
+                mode,
+                owner,
+                on_conflict,
+                if_missing,
+                |src, _dest, vars| render(src, vars, partials, helpers),
+            )
             .and_then(|res| res.ok_or(Error::SrcMissing))
         }
     }
@@ -114,6 +210,7 @@ pub mod hbs {
         template: P,
         ctx: &S,
         partials: &HashMap<String, PathBuf>,
+        helpers: &HandlebarsHelpers<'_>,
     ) -> Result<String, Error> {
         let template_str = super::read_template(template)?;
 
@@ -122,10 +219,101 @@ pub mod hbs {
             .iter()
             .map(|(name, path)| reg.register_template_file(name, &path))
             .collect::<Result<Vec<_>, _>>()?;
+        for (name, function) in helpers {
+            reg.register_helper(name, Box::new(LuaHelper(function.clone())));
+        }
 
         let res = reg.render_template(&template_str, ctx)?;
         Ok(res)
     }
+
+    /// Handlebars helper that calls a Lua function with the values of its parameters.
+    struct LuaHelper<'lua>(Function<'lua>);
+
+    // SAFETY: Helpers must be `Send` and `Sync` to be registered, which Lua functions aren't. The
+    // registry holding them is local to `render`, and is never shared with other threads.
+    unsafe impl Send for LuaHelper<'_> {}
+    unsafe impl Sync for LuaHelper<'_> {}
+
+    impl HelperDef for LuaHelper<'_> {
+        #[inline]
+        fn call_inner<'reg: 'rc, 'rc>(
+            &self,
+            h: &Helper<'reg, 'rc>,
+            _: &'reg Handlebars<'reg>,
+            _: &'rc Context,
+            _: &mut RenderContext<'reg, 'rc>,
+        ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+            let args: Variadic<_> = h
+                .params()
+                .iter()
+                .map(|param| Json(param.value().clone()))
+                .collect();
+            let Json(value) = self.0.call::<_, Json>(args).map_err(|err| {
+                RenderError::new(format!("helper '{}' failed: {}", h.name(), err))
+            })?;
+            Ok(ScopedJson::Derived(value))
+        }
+    }
+
+    /// JSON value passed to and returned from Lua helpers.
+    struct Json(JsonValue);
+
+    impl<'lua> ToLua<'lua> for Json {
+        #[inline]
+        fn to_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+            match self.0 {
+                // Missing variables are null, which should be nil rather than a null sentinel.
+                JsonValue::Null => Ok(LuaValue::Nil),
+                value => lua.to_value(&value),
+            }
+        }
+    }
+
+    impl<'lua> FromLua<'lua> for Json {
+        #[inline]
+        fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+            lua.from_value(value).map(Self)
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::collections::HashMap;
+        use std::fs;
+
+        use mlua::{Function, Lua};
+        use tempfile::TempDir;
+
+        use super::{render, HandlebarsHelpers};
+        use crate::spec::{Object, ObjectValue};
+
+        /// Test that helpers are called with the values of their parameters, including those of
+        /// missing variables.
+        #[test]
+        fn test_helpers() -> Result<(), Box<dyn std::error::Error>> {
+            let dir = TempDir::new()?;
+            let template = dir.path().join("a.hbs");
+            fs::write(&template, "{{upper name}} {{join name missing}}")?;
+
+            let lua = Lua::new();
+            let mut helpers = HandlebarsHelpers::new();
+            let upper: Function = lua.load("function(s) return s:upper() end").eval()?;
+            helpers.insert("upper".to_string(), upper);
+            let join: Function = lua
+                .load("function(a, b) return a .. '+' .. tostring(b) end")
+                .eval()?;
+            helpers.insert("join".to_string(), join);
+
+            let mut vars = Object::new();
+            vars.0
+                .insert("name".to_string(), ObjectValue::Str("shelf".to_string()));
+            let rendered = render(&template, &vars, &HashMap::new(), &helpers)?;
+            assert_eq!(rendered, "SHELF shelf+nil");
+
+            Ok(())
+        }
+    }
 }
 
 pub mod liquid {
@@ -135,7 +323,7 @@ pub mod liquid {
     use liquid::ParserBuilder;
     use serde::Serialize;
 
-    use super::{Object, Res, Resolve};
+    use super::{ConflictPolicy, MissingParentPolicy, Object, Owner, Res, Resolve};
 
     // Re-export liquid error type.
     pub use liquid::Error as LiquidError;
@@ -147,6 +335,17 @@ pub mod liquid {
         pub vars: Object,
 
         pub optional: bool,
+
+        /// Header prepended to the rendered contents.
+        pub header: Option<String>,
+        /// Permission bits to set on the rendered file, if any.
+        pub mode: Option<u32>,
+        /// Owner to set on the rendered file.
+        pub owner: Owner,
+        /// What to do if `dest` already exists and would be overwritten.
+        pub on_conflict: ConflictPolicy,
+        /// What to do if the parent of `dest` doesn't exist.
+        pub if_missing: MissingParentPolicy,
     }
 
     #[derive(Debug, thiserror::Error)]
@@ -169,11 +368,25 @@ pub mod liquid {
                 dest,
                 vars,
                 optional,
+                header,
+                mode,
+                owner,
+                on_conflict,
+                if_missing,
             } = self;
 
-            super::resolve_impl(src, dest, vars, optional, |src, _dest, vars| {
-                render(src, vars)
-            })
+            super::resolve_impl(
+                src,
+                dest,
+                vars,
+                optional,
+                header,
+                mode,
+                owner,
+                on_conflict,
+                if_missing,
+                |src, _dest, vars| render(src, vars),
+            )
             .and_then(|res| res.ok_or(Error::SrcMissing))
         }
     }
@@ -191,12 +404,212 @@ pub mod liquid {
     }
 }
 
+pub mod gotmpl {
+    use std::collections::HashMap;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    use gtmpl::{Context, Template, Value};
+    use serde::Serialize;
+    use serde_json::Value as JsonValue;
+
+    use super::{ConflictPolicy, MissingParentPolicy, Object, Owner, Res, Resolve};
+
+    // Re-export gtmpl error type.
+    pub use gtmpl::TemplateError as GotmplError;
+
+    /// Action to render a Go template (as in `text/template`), for compatibility with templates
+    /// written for v0.1.
+    #[derive(Debug, Clone)]
+    pub struct GotmplAction {
+        pub src: PathBuf,
+        pub dest: PathBuf,
+        pub vars: Object,
+
+        pub optional: bool,
+
+        /// Header prepended to the rendered contents.
+        pub header: Option<String>,
+        /// Permission bits to set on the rendered file, if any.
+        pub mode: Option<u32>,
+        /// Owner to set on the rendered file.
+        pub owner: Owner,
+        /// What to do if `dest` already exists and would be overwritten.
+        pub on_conflict: ConflictPolicy,
+        /// What to do if the parent of `dest` doesn't exist.
+        pub if_missing: MissingParentPolicy,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    pub enum Error {
+        #[error("src missing")]
+        SrcMissing,
+        #[error("i/o error")]
+        Io(#[from] io::Error),
+        #[error("vars error")]
+        Vars(#[from] serde_json::Error),
+        #[error("gotmpl error")]
+        Gotmpl(#[from] Box<GotmplError>),
+    }
+
+    impl Resolve for GotmplAction {
+        type Output = Result<Res, Error>;
+
+        #[inline]
+        fn resolve(&self) -> Self::Output {
+            let Self {
+                src,
+                dest,
+                vars,
+                optional,
+                header,
+                mode,
+                owner,
+                on_conflict,
+                if_missing,
+            } = self;
+
+            super::resolve_impl(
+                src,
+                dest,
+                vars,
+                optional,
+                header,
+                mode,
+                owner,
+                on_conflict,
+                if_missing,
+                |src, _dest, vars| render(src, vars),
+            )
+            .and_then(|res| res.ok_or(Error::SrcMissing))
+        }
+    }
+
+    #[inline]
+    pub fn render<P: AsRef<Path>, S: Serialize>(template: P, ctx: &S) -> Result<String, Error> {
+        let template_str = super::read_template(template)?;
+
+        let mut tmpl = Template::default();
+        tmpl.parse(template_str)
+            .map_err(|err| Box::new(GotmplError::from(err)))?;
+
+        let ctx = Context::from(to_value(serde_json::to_value(ctx)?));
+        let res = tmpl
+            .render(&ctx)
+            .map_err(|err| Box::new(GotmplError::from(err)))?;
+        Ok(res)
+    }
+
+    /// Convert JSON-like template variables to a gtmpl value.
+    #[inline]
+    fn to_value(value: JsonValue) -> Value {
+        match value {
+            JsonValue::Null => Value::Nil,
+            JsonValue::Bool(b) => b.into(),
+            JsonValue::Number(n) => match (n.as_u64(), n.as_i64(), n.as_f64()) {
+                (Some(n), _, _) => n.into(),
+                (_, Some(n), _) => n.into(),
+                (_, _, Some(n)) => n.into(),
+                _ => Value::Nil,
+            },
+            JsonValue::String(s) => s.into(),
+            JsonValue::Array(a) => Value::Array(a.into_iter().map(to_value).collect()),
+            JsonValue::Object(o) => Value::Map(
+                o.into_iter()
+                    .map(|(k, v)| (k, to_value(v)))
+                    .collect::<HashMap<_, _>>(),
+            ),
+        }
+    }
+}
+
+pub mod pipe {
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    use mlua::Function;
+
+    use super::{ConflictPolicy, MissingParentPolicy, Object, Owner, Res, Resolve};
+
+    /// Action to write the contents of `src`, passed through a Lua function, to `dest`.
+    #[derive(Debug, Clone)]
+    pub struct PipeAction<'lua> {
+        pub src: PathBuf,
+        pub dest: PathBuf,
+        /// Function that receives the contents of `src` as a string and returns those to write.
+        pub function: Function<'lua>,
+
+        pub optional: bool,
+
+        /// Permission bits to set on the written file, if any.
+        pub mode: Option<u32>,
+        /// Owner to set on the written file.
+        pub owner: Owner,
+        /// What to do if `dest` already exists and would be overwritten.
+        pub on_conflict: ConflictPolicy,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    pub enum Error {
+        #[error("src missing")]
+        SrcMissing,
+        #[error("i/o error")]
+        Io(#[from] io::Error),
+        #[error("lua error")]
+        Lua(#[from] mlua::Error),
+    }
+
+    impl<'lua> Resolve for PipeAction<'lua> {
+        type Output = Result<Res, Error>;
+
+        #[inline]
+        fn resolve(&self) -> Self::Output {
+            let Self {
+                src,
+                dest,
+                function,
+                optional,
+                mode,
+                owner,
+                on_conflict,
+            } = self;
+
+            super::resolve_impl(
+                src,
+                dest,
+                &Object::new(),
+                optional,
+                &None,
+                mode,
+                owner,
+                on_conflict,
+                &MissingParentPolicy::CreateParent,
+                |src, _dest, _vars| pipe(src, function),
+            )
+            .and_then(|res| res.ok_or(Error::SrcMissing))
+        }
+    }
+
+    #[inline]
+    fn pipe<P: AsRef<Path>>(src: P, function: &Function<'_>) -> Result<String, Error> {
+        let contents = super::read_template(src)?;
+        let res = function.call(contents)?;
+        Ok(res)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 #[inline]
 fn resolve_impl<E, RF>(
     src: &Path,
     dest: &Path,
     vars: &Object,
     optional: &bool,
+    header: &Option<String>,
+    mode: &Option<u32>,
+    owner: &Owner,
+    on_conflict: &ConflictPolicy,
+    if_missing: &MissingParentPolicy,
     render: RF,
 ) -> Result<Option<Res>, E>
 where
@@ -213,13 +626,28 @@ where
         (false, false) => Ok(None),
         // Otherwise, `src` exists.
         _ => {
+            if missing::parent_missing(dest) {
+                match if_missing {
+                    MissingParentPolicy::CreateParent => {}
+                    MissingParentPolicy::Skip => return Ok(Some(Res::Skip(Skip::ParentMissing))),
+                    MissingParentPolicy::Error => return Ok(Some(Res::ParentMissing)),
+                }
+            }
+
             // Render contents.
-            let contents = render(src, dest, vars)?;
+            let mut contents = render(src, dest, vars)?;
+            // Prepend the header after rendering, so that it isn't subject to templating.
+            if let Some(header) = header {
+                comment::insert_header(&mut contents, header);
+            }
 
             // Write the contents.
             let wa = WriteAction {
                 dest: dest.to_path_buf(),
                 contents: contents.into_bytes(),
+                mode: *mode,
+                owner: *owner,
+                on_conflict: *on_conflict,
             };
             let res = wa.resolve();
 
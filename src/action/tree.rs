@@ -1,26 +1,64 @@
-use std::collections::HashSet;
-use std::path::{Path, PathBuf};
+pub mod cache;
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 use std::{env, fs};
 
 use glob::{GlobError, PatternError};
+use ignore::gitignore::GitignoreBuilder;
 
 use crate::fse;
+use crate::graph::{DestFilter, PathResolver};
 
-use super::link::Res as LinkActionRes;
+use super::conflict::ConflictPolicy;
+use super::link::{Error as LinkActionError, Op as LinkActionOp, Res as LinkActionRes};
+use super::missing::{self, MissingParentPolicy};
+use super::volatile::Volatile;
 use super::{LinkAction, Resolve};
 
 pub type Patterns = Vec<Pattern>;
 pub type Pattern = String;
 
+/// Name of the file at the root of a tree that lists files not to link, in gitignore syntax. It
+/// applies in addition to [`TreeAction::ignore`], and is never linked itself.
+pub const IGNORE_FILE: &str = ".shelfignore";
+
 #[derive(Debug, Clone)]
 pub struct TreeAction {
     pub src: PathBuf,
+    /// Further source directories merged over `src` in order, before `overrides`. Files in later
+    /// ones replace those with the same relative path in earlier ones.
+    pub layers: Vec<PathBuf>,
     pub dest: PathBuf,
+    /// Directory of machine-specific files that override those in `src` with the same relative
+    /// path. Files only in it are linked too. Ignored if it doesn't exist.
+    pub overrides: Option<PathBuf>,
     pub globs: Patterns,
     pub ignore: Patterns,
+    /// Destination subpaths that are exempt from drift checks and never removed. See
+    /// [`Volatile`].
+    pub volatile: Patterns,
+    /// If present, this prefix is removed from the paths of files relative to `src` to get their
+    /// paths relative to `dest`, and files not under it are skipped.
+    pub strip_prefix: Option<PathBuf>,
+    /// If present, only files whose destinations match are linked.
+    pub only: Option<DestFilter>,
 
     pub copy: bool,
+    /// Hard link files instead of symlinking them. See [`LinkAction::hardlink`].
+    pub hardlink: bool,
+    /// Copy files if symlinks can't be created. See [`LinkAction::fallback`].
+    pub fallback: bool,
     pub optional: bool,
+    /// What to do if the destination of a file already exists and would be overwritten. See
+    /// [`LinkAction::on_conflict`].
+    pub on_conflict: ConflictPolicy,
+    /// What to do if the parent of `dest` doesn't exist. Subdirectories of `dest` are always
+    /// created.
+    pub if_missing: MissingParentPolicy,
+    /// Resolver through which the destinations of files under `dest` are remapped, e.g. into an
+    /// overriding XDG config directory.
+    pub paths: PathResolver,
 }
 
 #[derive(Debug, Clone)]
@@ -40,16 +78,28 @@ pub enum Skip {
     OptMissing,
     /// Destination link already exists.
     DestExists,
+    /// The parent of `dest` doesn't exist, and `if_missing` is [`MissingParentPolicy::Skip`].
+    ParentMissing,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("src missing")]
     SrcMissing,
+    #[error("layer {} missing", .0.display())]
+    LayerMissing(PathBuf),
+    #[error("destination {} exists", .0.display())]
+    Conflict(PathBuf),
+    #[error("parent of destination {} missing", .0.display())]
+    ParentMissing(PathBuf),
     #[error("glob error")]
     Glob(#[from] GlobError),
     #[error("pattern error")]
     Pattern(#[from] PatternError),
+    #[error("ignore file error")]
+    IgnoreFile(#[from] ignore::Error),
+    #[error("pattern '{pattern}' of tree {} could match files outside of it", .src.display())]
+    EscapingPattern { src: PathBuf, pattern: Pattern },
 }
 
 impl Resolve for TreeAction {
@@ -57,19 +107,79 @@ impl Resolve for TreeAction {
 
     #[inline]
     fn resolve(&self) -> Self::Output {
+        let links = match self.links()? {
+            Some(links) => links,
+            None => return Ok(Res::Skip(Skip::OptMissing)),
+        };
+
+        let dest = self.paths.remap(&self.dest);
+        if missing::parent_missing(&dest) {
+            match self.if_missing {
+                MissingParentPolicy::CreateParent => {}
+                MissingParentPolicy::Skip => return Ok(Res::Skip(Skip::ParentMissing)),
+                MissingParentPolicy::Error => return Err(Error::ParentMissing(dest)),
+            }
+        }
+
+        let mut made = HashSet::new();
+        let resvec = links
+            .into_iter()
+            .map(|action| match action.resolve() {
+                Err(LinkActionError::Conflict) => Err(Error::Conflict(action.dest)),
+                // SAFETY: Should be fine since all these files should exist?
+                res => {
+                    let mut res = res.unwrap();
+                    dedup_mkdirs(&mut res, &mut made);
+                    Ok(res)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Res::Normal(resvec))
+    }
+}
+
+/// Remove the ops of `res` that make directories already in `made`, and add those left to it.
+/// Files of a tree share missing parents, which must only be made once.
+#[inline]
+pub fn dedup_mkdirs(res: &mut LinkActionRes, made: &mut HashSet<PathBuf>) {
+    let ops = match res {
+        LinkActionRes::Normal(ops) | LinkActionRes::Overwrite(ops) => ops,
+        LinkActionRes::Skip(_) => return,
+    };
+    ops.retain(|op| match op {
+        LinkActionOp::Mkdir(op) => made.insert(op.path.clone()),
+        _ => true,
+    });
+}
+
+impl TreeAction {
+    /// Return the actions that link each file of the tree, or `None` if `src` is optional and
+    /// does not exist.
+    #[inline]
+    pub fn links(&self) -> Result<Option<Vec<LinkAction>>, Error> {
         let Self {
             src,
+            layers,
             dest,
+            overrides,
             globs,
             ignore,
+            volatile,
+            strip_prefix,
+            only,
             copy,
+            hardlink,
+            fallback,
             optional,
+            on_conflict,
+            if_missing: _,
+            paths: resolver,
         } = self;
 
         match (optional, fse::symlink_exists(src)) {
             // `src` is optional and does not exist; skip.
             (true, false) => {
-                return Ok(Res::Skip(Skip::OptMissing));
+                return Ok(None);
             }
             // `src` is not optional but does not exist; skip.
             (false, false) => {
@@ -79,36 +189,135 @@ impl Resolve for TreeAction {
             _ => {}
         };
 
-        // Glob to get file paths.
-        let mut paths = glob_tree(&src, globs)?;
-        // Glob to get ignored paths.
-        let ignore_paths = glob_tree(&src, ignore)?;
+        for pattern in globs.iter().chain(ignore).chain(volatile) {
+            check_pattern(src, pattern)?;
+        }
 
-        // Remove all the ignored paths from the globbed paths.
-        for path in ignore_paths {
-            paths.remove(&path);
+        // Missing layers are skipped along with `src` if optional.
+        let mut roots = vec![src];
+        for layer in layers {
+            match (optional, fse::symlink_exists(layer)) {
+                (true, false) => {}
+                (false, false) => return Err(Error::LayerMissing(layer.clone())),
+                _ => roots.push(layer),
+            }
+        }
+        // Machine-specific files override all the others.
+        roots.extend(overrides.as_ref().filter(|overrides| overrides.is_dir()));
+
+        // Glob to get file paths from each root in turn, later ones replacing earlier ones.
+        let mut paths = BTreeMap::new();
+        for root in roots {
+            for path in glob_ignore(root, globs, ignore)? {
+                let fsrc = root.join(&path);
+                paths.insert(path, fsrc);
+            }
+        }
+        ignore_file(src, &mut paths)?;
+
+        // Map paths relative to `src` to paths relative to `dest`.
+        if let Some(prefix) = strip_prefix {
+            paths = paths
+                .into_iter()
+                .filter_map(|(path, fsrc)| {
+                    let path = path.strip_prefix(prefix).ok()?.to_path_buf();
+                    Some((path, fsrc))
+                })
+                .collect();
         }
 
-        // Join these back into full paths for src and dest.
-        let src_paths = paths.iter().map(|path| src.join(path));
-        let dest_paths = paths.iter().map(|path| dest.join(path));
+        // Leave existing volatile paths alone; the application owns them now.
+        let volatile = Volatile::new(volatile)?;
+        let join = |path: &PathBuf| resolver.remap(dest.join(path));
+        paths.retain(|path, _| !(volatile.matches(path) && fse::symlink_exists(join(path))));
+
+        // Narrow to the selected destinations.
+        if let Some(only) = only {
+            paths.retain(|path, _| only.matches(join(path)));
+        }
+
+        // Join these back into full paths for dest.
+        let dest_paths: Vec<_> = paths.keys().map(join).collect();
+        let src_paths = paths.into_values();
 
         // Map paths and dest paths into linking actions.
-        let it = src_paths
+        let links = src_paths
             .zip(dest_paths)
-            .map(move |(fsrc, fdest)| LinkAction {
+            .map(|(fsrc, fdest)| LinkAction {
                 src: fsrc,
                 dest: fdest,
                 copy: *copy,
+                hardlink: *hardlink,
+                fallback: *fallback,
                 optional: false,
-            });
+                mode: None,
+                owner: Default::default(),
+                on_conflict: *on_conflict,
+                if_missing: MissingParentPolicy::CreateParent,
+            })
+            .collect();
+        Ok(Some(links))
+    }
+}
 
-        // SAFETY: Should be fine since all these files should exist?
-        let resvec: Vec<_> = it.map(|action| action.resolve().unwrap()).collect();
-        Ok(Res::Normal(resvec))
+/// Glob the files in `src` that match `globs` but not `ignore`. Results are cached; see
+/// [`cache`].
+#[inline]
+fn glob_ignore(src: &Path, globs: &[String], ignore: &[String]) -> Result<HashSet<PathBuf>, Error> {
+    cache::get_or_glob(src, globs, ignore, || {
+        let mut paths = glob_tree(src, globs)?;
+        // Remove all the ignored paths from the globbed paths.
+        for path in glob_tree(src, ignore)? {
+            paths.remove(&path);
+        }
+
+        Ok(paths)
+    })
+}
+
+/// Fail if `pattern` of the tree at `src` could match files outside of it. Patterns are relative
+/// to the tree, so absolute ones and those with `..` components are rejected rather than globbed
+/// as they are.
+#[inline]
+fn check_pattern(src: &Path, pattern: &str) -> Result<(), Error> {
+    let path = Path::new(pattern);
+    let escapes = path.has_root()
+        || path
+            .components()
+            .any(|c| matches!(c, Component::Prefix(_) | Component::ParentDir));
+
+    if escapes {
+        Err(Error::EscapingPattern {
+            src: src.to_path_buf(),
+            pattern: pattern.to_string(),
+        })
+    } else {
+        Ok(())
     }
 }
 
+/// Remove the paths listed in the [`IGNORE_FILE`] at the root of `src`, if any, from `paths`,
+/// along with the file itself. This isn't cached with the globbed paths, since editing the file
+/// doesn't change the fingerprint of the tree.
+#[inline]
+fn ignore_file(src: &Path, paths: &mut BTreeMap<PathBuf, PathBuf>) -> Result<(), Error> {
+    paths.remove(Path::new(IGNORE_FILE));
+
+    let file = src.join(IGNORE_FILE);
+    if !file.is_file() {
+        return Ok(());
+    }
+
+    let mut builder = GitignoreBuilder::new(src);
+    if let Some(err) = builder.add(&file) {
+        return Err(err.into());
+    }
+    let matcher = builder.build()?;
+
+    paths.retain(|path, _| !matcher.matched_path_or_any_parents(path, false).is_ignore());
+    Ok(())
+}
+
 #[inline]
 fn glob_tree<P>(src: P, pats: &[String]) -> Result<HashSet<PathBuf>, Error>
 where
@@ -148,3 +357,58 @@ where
         Err(_) => false,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::path::{Path, PathBuf};
+
+    use super::{check_pattern, dedup_mkdirs, Error, LinkActionOp, LinkActionRes};
+    use crate::op::MkdirOp;
+
+    #[test]
+    fn test_check_pattern() {
+        let src = Path::new("tree");
+        assert!(check_pattern(src, "**/*.conf").is_ok());
+        assert!(check_pattern(src, "a/..b").is_ok());
+
+        for pattern in ["/etc/*", "../*", "a/../../b", "**/../*"].iter() {
+            match check_pattern(src, pattern) {
+                Err(Error::EscapingPattern { src: s, pattern: p }) => {
+                    assert_eq!(s, src);
+                    assert_eq!(p, *pattern);
+                }
+                res => panic!("expected {} to be rejected, got {:?}", pattern, res),
+            }
+        }
+    }
+
+    #[test]
+    fn test_dedup_mkdirs() {
+        let mkdir = |path: &str| {
+            LinkActionOp::Mkdir(MkdirOp {
+                path: PathBuf::from(path),
+            })
+        };
+        let paths = |res: &LinkActionRes| match res {
+            LinkActionRes::Normal(ops) => ops
+                .iter()
+                .map(|op| match op {
+                    LinkActionOp::Mkdir(op) => op.path.clone(),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+
+        let mut made = HashSet::new();
+        let mut first = LinkActionRes::Normal(vec![mkdir("a"), mkdir("a/b")]);
+        dedup_mkdirs(&mut first, &mut made);
+        assert_eq!(paths(&first), [Path::new("a"), Path::new("a/b")]);
+
+        // Parents shared with earlier files are only made once.
+        let mut second = LinkActionRes::Normal(vec![mkdir("a"), mkdir("a/b"), mkdir("a/b/c")]);
+        dedup_mkdirs(&mut second, &mut made);
+        assert_eq!(paths(&second), [Path::new("a/b/c")]);
+    }
+}
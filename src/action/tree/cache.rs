@@ -0,0 +1,217 @@
+//! Cache of the files globbed for tree directives, so that planning and then applying a tree in
+//! the same run (or in consecutive runs, with [`load`] and [`save`]) only globs it once.
+//!
+//! Entries are validated by a fingerprint of the modification times of the directories under the
+//! tree, which change whenever files are added, removed, or renamed. Changes to the contents of
+//! files don't affect which files are globbed.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// Directories modified more recently than this are not cached, since further changes within the
+/// granularity of their modification times would go unnoticed.
+const RACY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, thiserror::Error)]
+pub enum CacheError {
+    #[error("i/o error")]
+    Io(#[from] io::Error),
+    #[error("serde error")]
+    Serde(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+struct Key {
+    src: PathBuf,
+    globs: Vec<String>,
+    ignore: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Entry {
+    key: Key,
+    fingerprint: u64,
+    paths: Vec<PathBuf>,
+}
+
+static ENTRIES: OnceLock<Mutex<HashMap<Key, Entry>>> = OnceLock::new();
+
+#[inline]
+fn entries() -> MutexGuard<'static, HashMap<Key, Entry>> {
+    let entries = ENTRIES.get_or_init(Default::default);
+    // A panic while holding the lock leaves the map intact.
+    entries.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Return the cached paths globbed in `src` for `globs` and `ignore`, or glob them with `glob` and
+/// cache them if there is no valid entry.
+#[inline]
+pub(super) fn get_or_glob<F, E>(
+    src: &Path,
+    globs: &[String],
+    ignore: &[String],
+    glob: F,
+) -> Result<HashSet<PathBuf>, E>
+where
+    F: FnOnce() -> Result<HashSet<PathBuf>, E>,
+{
+    let key = Key {
+        src: src.to_path_buf(),
+        globs: globs.to_vec(),
+        ignore: ignore.to_vec(),
+    };
+
+    // The tree can't be cached if it can't be fingerprinted.
+    let fingerprint = match fingerprint(src) {
+        Some(fingerprint) => fingerprint,
+        None => return glob(),
+    };
+    if let Some(entry) = entries().get(&key) {
+        if entry.fingerprint == fingerprint {
+            return Ok(entry.paths.iter().cloned().collect());
+        }
+    }
+
+    let paths = glob()?;
+    let entry = Entry {
+        key: key.clone(),
+        fingerprint,
+        paths: paths.iter().cloned().collect(),
+    };
+    entries().insert(key, entry);
+
+    Ok(paths)
+}
+
+/// Load the entries saved at `path` with [`save`], replacing entries for the same trees. Nothing
+/// is loaded if the file doesn't exist.
+#[inline]
+pub fn load<P>(path: P) -> Result<(), CacheError>
+where
+    P: AsRef<Path>,
+{
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let saved: Vec<Entry> = serde_json::from_reader(BufReader::new(file))?;
+    entries().extend(saved.into_iter().map(|entry| (entry.key.clone(), entry)));
+    Ok(())
+}
+
+/// Save the entries to `path`, to be loaded by a later run with [`load`].
+#[inline]
+pub fn save<P>(path: P) -> Result<(), CacheError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let saved: Vec<Entry> = entries().values().cloned().collect();
+    let file = File::create(path)?;
+    serde_json::to_writer(BufWriter::new(file), &saved)?;
+    Ok(())
+}
+
+/// Remove all entries.
+#[inline]
+pub fn clear() {
+    entries().clear();
+}
+
+/// Return a fingerprint of the modification times of `src` and the directories under it,
+/// following symlinks, or `None` if they can't be read or some were modified too recently.
+#[inline]
+fn fingerprint(src: &Path) -> Option<u64> {
+    let mut dirs = Vec::new();
+    let mut visited = HashSet::new();
+    walk_dirs(src, &mut dirs, &mut visited).ok()?;
+    dirs.sort();
+
+    let racy = SystemTime::now().checked_sub(RACY)?;
+    let mut hasher = DefaultHasher::new();
+    for (dir, mtime) in dirs {
+        if mtime > racy {
+            return None;
+        }
+        dir.hash(&mut hasher);
+        mtime.hash(&mut hasher);
+    }
+
+    Some(hasher.finish())
+}
+
+#[inline]
+fn walk_dirs(
+    dir: &Path,
+    dirs: &mut Vec<(PathBuf, SystemTime)>,
+    visited: &mut HashSet<PathBuf>,
+) -> io::Result<()> {
+    // Guard against symlink cycles.
+    if !visited.insert(fs::canonicalize(dir)?) {
+        return Ok(());
+    }
+
+    dirs.push((dir.to_path_buf(), fs::metadata(dir)?.modified()?));
+    for dirent in fs::read_dir(dir)? {
+        let path = dirent?.path();
+        if path.is_dir() {
+            walk_dirs(&path, dirs, visited)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    use tempfile::TempDir;
+
+    use super::{get_or_glob, Key};
+
+    /// Test that entries are reused until a file is added.
+    #[test]
+    fn test_get_or_glob() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new()?;
+        let src = dir.path();
+        fs::write(src.join("a"), "")?;
+        // Backdate the directory, since recently modified ones aren't cached.
+        let old = SystemTime::now() - Duration::from_secs(60);
+        fs::File::open(src)?.set_modified(old)?;
+
+        let globs = vec!["*".to_string()];
+        let glob = |n: usize| move || Ok::<_, ()>((0..n).map(|i| i.to_string().into()).collect());
+        assert_eq!(1, get_or_glob(src, &globs, &[], glob(1)).unwrap().len());
+        // Cached, so the glob isn't run.
+        assert_eq!(1, get_or_glob(src, &globs, &[], glob(2)).unwrap().len());
+
+        fs::write(src.join("b"), "")?;
+        fs::File::open(src)?.set_modified(old + Duration::from_secs(1))?;
+        assert_eq!(2, get_or_glob(src, &globs, &[], glob(2)).unwrap().len());
+
+        let key = Key {
+            src: src.to_path_buf(),
+            globs,
+            ignore: vec![],
+        };
+        assert!(super::entries().contains_key(&key));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use std::path::Path;
+
+use glob::{Pattern, PatternError};
+
+/// Destination subpaths that applications mutate on their own (e.g. plugins written into a
+/// managed directory). Volatile paths are exempt from drift checks and are never removed.
+#[derive(Debug, Clone, Default)]
+pub struct Volatile {
+    patterns: Vec<Pattern>,
+}
+
+impl Volatile {
+    /// Compile the glob `patterns`, which are relative to the destination of a directive.
+    #[inline]
+    pub fn new(patterns: &[String]) -> Result<Self, PatternError> {
+        let patterns = patterns
+            .iter()
+            .map(|pat| Pattern::new(pat))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { patterns })
+    }
+
+    /// Return true if there are no volatile patterns.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Return true if the relative path `rel`, or any of its ancestors, matches a volatile
+    /// pattern.
+    #[inline]
+    pub fn matches<P>(&self, rel: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        rel.as_ref()
+            .ancestors()
+            .filter(|path| !path.as_os_str().is_empty())
+            .any(|path| self.patterns.iter().any(|pat| pat.matches_path(path)))
+    }
+}
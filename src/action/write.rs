@@ -1,8 +1,14 @@
+pub mod cache;
+
 use std::fs;
 use std::path::PathBuf;
 
-use crate::op::{CreateOp, MkdirOp, RmOp, WriteOp};
+#[cfg(unix)]
+use crate::op::ChownOp;
+use crate::op::{ChmodOp, CopyOp, CreateOp, MkdirOp, RmOp, WriteOp};
 
+use super::conflict::{self, ConflictPolicy};
+use super::mode::{self, Owner};
 use super::{mkdir, Resolve};
 
 /// Action to write `contents` to a file at `dest`.
@@ -13,29 +19,46 @@ pub struct WriteAction {
     /// Contents to be written.
     // TODO: AsRef<[u8]> instead?
     pub contents: Vec<u8>,
+    /// Permission bits to set on the file, if any.
+    pub mode: Option<u32>,
+    /// Owner to set on the file.
+    pub owner: Owner,
+    /// What to do if `dest` already exists and would be overwritten.
+    pub on_conflict: ConflictPolicy,
 }
 
 #[derive(Debug, Clone)]
 pub enum Res {
     Normal(Vec<Op>),
-    /// The existing destination file's contents will be overwritten.
+    /// The existing destination file's contents differ, and it will be replaced by a new file. The
+    /// original is moved into the file safe, so that undoing restores it.
     OverwriteContents(Vec<Op>),
     /// The existing destination file will be replaced.
     OverwriteFile(Vec<Op>),
     /// The action is skipped.
     Skip(Skip),
+    /// The existing destination differs and would be overwritten, but `on_conflict` is
+    /// [`ConflictPolicy::Fail`].
+    Conflict,
 }
 
 #[derive(Debug, Clone)]
 pub enum Op {
     /// Remove operation.
     Rm(RmOp),
+    /// Copy operation, which backs up the existing destination.
+    Copy(CopyOp),
     /// Create operation.
     Create(CreateOp),
     /// Write operation.
     Write(WriteOp),
     /// Mkdir operation.
     Mkdir(MkdirOp),
+    /// Chmod operation.
+    Chmod(ChmodOp),
+    /// Chown operation.
+    #[cfg(unix)]
+    Chown(ChownOp),
 }
 
 /// Reason for skipping [`WriteAction`].
@@ -43,6 +66,9 @@ pub enum Op {
 pub enum Skip {
     /// Destination link already exists.
     DestExists,
+    /// Destination differs and would be overwritten, but `on_conflict` is
+    /// [`ConflictPolicy::Skip`].
+    Conflict,
 }
 
 impl Resolve for WriteAction {
@@ -50,27 +76,52 @@ impl Resolve for WriteAction {
 
     #[inline]
     fn resolve(&self) -> Self::Output {
-        let Self { dest, contents } = self;
+        let Self { dest, contents, .. } = self;
 
         // If the destination file already exists, check the filetype.
         match fs::symlink_metadata(dest) {
             // For files, check the contents. If they match, we should do nothing.
-            // Otherwise, warn about an overwrite and write.
-            Ok(meta) if meta.is_file() => match fs::read(dest) {
-                // Check for content same.
-                Ok(dest_contents) if dest_contents == *contents => Res::Skip(Skip::DestExists),
-                // If error, just assume content is different.
-                Ok(_) | Err(_) => {
-                    let ops = vec![self.as_op()];
-                    Res::OverwriteContents(ops)
+            // Otherwise, warn about an overwrite, move the file into the file safe rather than
+            // writing over it, create a new one, and then write.
+            Ok(meta) if meta.is_file() => {
+                // The file needn't be read if it's known to hold the contents; see `cache`.
+                let same = cache::holds(dest, &meta, contents)
+                    || match fs::read(dest) {
+                        Ok(dest_contents) if dest_contents == *contents => {
+                            cache::insert(dest, &meta, contents);
+                            true
+                        }
+                        // If error, just assume content is different.
+                        Ok(_) | Err(_) => false,
+                    };
+
+                if same {
+                    // The owner and permission bits may still need to be set.
+                    let ops = self.perms_ops(true);
+                    if ops.is_empty() {
+                        Res::Skip(Skip::DestExists)
+                    } else {
+                        Res::Normal(ops)
+                    }
+                } else {
+                    let mut ops = vec![
+                        Op::Rm(RmOp {
+                            path: dest.clone(),
+                            dir: false,
+                        }),
+                        Op::Create(CreateOp { path: dest.clone() }),
+                        self.as_op(),
+                    ];
+                    ops.extend(self.perms_ops(false));
+                    self.resolve_conflict(ops, Res::OverwriteContents)
                 }
-            },
+            }
 
             // For other kinds of files, warn about an overwrite, remove the directory, create a
             // file, and then write.
             Ok(meta) if meta.is_dir() | meta.is_symlink() => {
                 let dir = meta.is_dir();
-                let ops = vec![
+                let mut ops = vec![
                     Op::Rm(RmOp {
                         path: dest.clone(),
                         dir,
@@ -78,7 +129,8 @@ impl Resolve for WriteAction {
                     Op::Create(CreateOp { path: dest.clone() }),
                     self.as_op(),
                 ];
-                Res::OverwriteFile(ops)
+                ops.extend(self.perms_ops(false));
+                self.resolve_conflict(ops, Res::OverwriteFile)
             }
 
             // File doesn't exist, or insufficient permissions; treat as nonexistent.
@@ -89,6 +141,7 @@ impl Resolve for WriteAction {
                 ops.push(Op::Create(CreateOp { path: dest.clone() }));
                 // Add write operation.
                 ops.push(self.as_op());
+                ops.extend(self.perms_ops(false));
 
                 Res::Normal(ops)
             }
@@ -97,13 +150,91 @@ impl Resolve for WriteAction {
 }
 
 impl WriteAction {
+    /// Apply `on_conflict` to `ops`, which overwrite the existing `dest`, resolving to `overwrite`
+    /// if they are kept.
+    #[inline]
+    fn resolve_conflict<F>(&self, ops: Vec<Op>, overwrite: F) -> Res
+    where
+        F: FnOnce(Vec<Op>) -> Res,
+    {
+        match self.on_conflict {
+            ConflictPolicy::Replace => overwrite(ops),
+            ConflictPolicy::Skip => Res::Skip(Skip::Conflict),
+            ConflictPolicy::Backup => {
+                let mut backup: Vec<_> = conflict::backup_op(&self.dest)
+                    .map(Op::Copy)
+                    .into_iter()
+                    .collect();
+                backup.extend(ops);
+                overwrite(backup)
+            }
+            ConflictPolicy::Fail => Res::Conflict,
+        }
+    }
+
     #[inline]
     fn as_op(&self) -> Op {
-        let Self { dest, contents } = self;
+        let Self { dest, contents, .. } = self;
 
         Op::Write(WriteOp {
             path: dest.clone(),
             contents: contents.clone(),
         })
     }
+
+    /// Return the ops that set the owner and then the permission bits of `dest`, if given, since
+    /// changing the owner may clear setuid bits. `exists` is whether the current file at `dest` is
+    /// kept.
+    #[inline]
+    fn perms_ops(&self, exists: bool) -> Vec<Op> {
+        let mut ops = Vec::new();
+        #[cfg(unix)]
+        ops.extend(mode::chown_op(&self.dest, self.owner, exists).map(Op::Chown));
+        ops.extend(
+            self.mode
+                .and_then(|mode| mode::chmod_op(&self.dest, mode, exists))
+                .map(Op::Chmod),
+        );
+        ops
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::{Op, Res, Resolve, WriteAction};
+
+    /// Test that a differing destination file is moved aside, rather than written over, so that
+    /// the original can be restored from the file safe.
+    #[test]
+    fn test_resolve_overwrite() -> std::io::Result<()> {
+        let dir = TempDir::new()?;
+        let dest = dir.path().join("a");
+        fs::write(&dest, "original")?;
+
+        let action = WriteAction {
+            dest: dest.clone(),
+            contents: b"new".to_vec(),
+            mode: None,
+            owner: Default::default(),
+            on_conflict: Default::default(),
+        };
+        match action.resolve() {
+            Res::OverwriteContents(ops) => match &ops[..] {
+                [Op::Rm(rm), Op::Create(create), Op::Write(write)] => {
+                    assert_eq!(rm.path, dest);
+                    assert!(!rm.dir);
+                    assert_eq!(create.path, dest);
+                    assert_eq!(write.contents, b"new");
+                }
+                ops => panic!("unexpected ops {:?}", ops),
+            },
+            res => panic!("unexpected resolution {:?}", res),
+        }
+
+        Ok(())
+    }
 }
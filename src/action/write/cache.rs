@@ -0,0 +1,163 @@
+//! Cache of the contents of written destinations, so that later runs needn't read a destination
+//! again to find that it already holds what would be written.
+//!
+//! Entries hold a blake3 hash of the contents of a destination, and are validated by its size and
+//! modification time, which change whenever it is written.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// Files modified more recently than this are not cached, since further writes within the
+/// granularity of their modification times would go unnoticed.
+const RACY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, thiserror::Error)]
+pub enum CacheError {
+    #[error("i/o error")]
+    Io(#[from] io::Error),
+    #[error("serde error")]
+    Serde(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+struct Entry {
+    dest: PathBuf,
+    len: u64,
+    modified: SystemTime,
+    hash: String,
+}
+
+static ENTRIES: OnceLock<Mutex<HashMap<PathBuf, Entry>>> = OnceLock::new();
+
+#[inline]
+fn entries() -> MutexGuard<'static, HashMap<PathBuf, Entry>> {
+    let entries = ENTRIES.get_or_init(Default::default);
+    // A panic while holding the lock leaves the map intact.
+    entries.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Return true if the file at `dest`, with metadata `meta`, is known to hold `contents`.
+#[inline]
+pub(super) fn holds(dest: &Path, meta: &fs::Metadata, contents: &[u8]) -> bool {
+    let modified = match meta.modified() {
+        Ok(modified) => modified,
+        Err(_) => return false,
+    };
+
+    match entries().get(dest) {
+        Some(entry) if entry.len == meta.len() && entry.modified == modified => {
+            entry.hash == hash(contents)
+        }
+        _ => false,
+    }
+}
+
+/// Record that the file at `dest`, with metadata `meta`, holds `contents`, unless it was modified
+/// too recently.
+#[inline]
+pub(super) fn insert(dest: &Path, meta: &fs::Metadata, contents: &[u8]) {
+    let modified = match meta.modified() {
+        Ok(modified) => modified,
+        Err(_) => return,
+    };
+    match SystemTime::now().checked_sub(RACY) {
+        Some(racy) if modified <= racy => {}
+        _ => return,
+    }
+
+    let entry = Entry {
+        dest: dest.to_path_buf(),
+        len: meta.len(),
+        modified,
+        hash: hash(contents),
+    };
+    entries().insert(dest.to_path_buf(), entry);
+}
+
+/// Load the entries saved at `path` with [`save`], replacing entries for the same destinations.
+/// Nothing is loaded if the file doesn't exist.
+#[inline]
+pub fn load<P>(path: P) -> Result<(), CacheError>
+where
+    P: AsRef<Path>,
+{
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let saved: Vec<Entry> = serde_json::from_reader(BufReader::new(file))?;
+    entries().extend(saved.into_iter().map(|entry| (entry.dest.clone(), entry)));
+    Ok(())
+}
+
+/// Save the entries to `path`, to be loaded by a later run with [`load`].
+#[inline]
+pub fn save<P>(path: P) -> Result<(), CacheError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let saved: Vec<Entry> = entries().values().cloned().collect();
+    let file = File::create(path)?;
+    serde_json::to_writer(BufWriter::new(file), &saved)?;
+    Ok(())
+}
+
+/// Remove all entries.
+#[inline]
+pub fn clear() {
+    entries().clear();
+}
+
+#[inline]
+fn hash(contents: &[u8]) -> String {
+    blake3::hash(contents).to_hex().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    use tempfile::TempDir;
+
+    use super::{holds, insert};
+
+    /// Test that entries are valid until the destination is written.
+    #[test]
+    fn test_holds() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new()?;
+        let dest = dir.path().join("a");
+        fs::write(&dest, "a")?;
+
+        // Recently modified files aren't cached.
+        insert(&dest, &fs::metadata(&dest)?, b"a");
+        assert!(!holds(&dest, &fs::metadata(&dest)?, b"a"));
+
+        let old = SystemTime::now() - Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&dest)?
+            .set_modified(old)?;
+        insert(&dest, &fs::metadata(&dest)?, b"a");
+        assert!(holds(&dest, &fs::metadata(&dest)?, b"a"));
+        assert!(!holds(&dest, &fs::metadata(&dest)?, b"b"));
+
+        fs::write(&dest, "bb")?;
+        assert!(!holds(&dest, &fs::metadata(&dest)?, b"a"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use crate::graph::PackageGraph;
+use crate::load::SpecLoader;
+
+use super::Error;
+
+/// Loader of packages and their dependencies.
+///
+/// Packages with base packages are not supported, since fetching bases requires git and a cache
+/// directory, which are up to the caller.
+#[derive(Debug, Clone, Default)]
+pub struct Loader {}
+
+impl Loader {
+    #[inline]
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Load the packages at `paths` and their dependencies.
+    #[inline]
+    pub fn load(&self, paths: Vec<PathBuf>) -> Result<PackageGraph, Error> {
+        let mut graph = PackageGraph::new();
+        let mut queue: Vec<_> = paths.into_iter().map(|path| (path, None)).collect();
+        while let Some((path, parent)) = queue.pop() {
+            if !graph.contains(&path) {
+                let data = SpecLoader::load(&path)
+                    .map_err(|err| Error::Load(path.clone(), Box::new(err)))?;
+                if data.spec.base.is_some() {
+                    return Err(Error::Base(path));
+                }
+
+                queue.extend(data.dep_paths().map(|dpath| (dpath, Some(path.clone()))));
+                let _ = graph.add_package(data);
+            }
+
+            if let Some(parent) = parent {
+                graph.add_dependency(&path, parent);
+            }
+        }
+
+        Ok(graph)
+    }
+}
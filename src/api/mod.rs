@@ -0,0 +1,75 @@
+//! The supported API of shelflib.
+//!
+//! Everything reachable from this module follows semantic versioning: breaking changes to it are
+//! only made in major releases. The other modules of the crate are public for the `shelf` binary,
+//! and may change in any release.
+//!
+//! Applying packages takes three steps:
+//!
+//! 1.  [`Loader`] loads packages and their dependencies into a [`PackageGraph`].
+//! 2.  [`Plan::new`] resolves the directives of the packages into ops, predicting the effect of
+//!     each with a [`SimulatedFs`].
+//! 3.  [`Processor`] runs the ops of a plan, journaling them so that they can be rolled back, and
+//!     reports progress to an [`Observer`]. It can be stopped between ops with a [`CancelToken`].
+
+mod load;
+mod plan;
+mod process;
+
+use std::path::PathBuf;
+
+pub use self::load::Loader;
+pub use self::plan::{PackagePlan, Plan};
+pub use self::process::{CancelToken, Observer, Position, Processor};
+
+pub use crate::graph::{CircularDependencyError, PackageData, PackageGraph, PathResolver};
+pub use crate::load::LoadError;
+pub use crate::op::{
+    ctx::{FileSafe, FinishCtx, RetryPolicy},
+    effect::Effect,
+    journal::{JournalOp, JournalOpError, JournalOpFinish, OpJournal as Journal},
+    simulate::{simulate, Conflict, SimulatedFs},
+};
+pub use crate::spec::{Scope, Spec};
+
+use crate::action::{tree, ResolutionError};
+
+/// Error of the API. Errors of the underlying steps are boxed, since some are large.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("couldn't load package {0}")]
+    Load(PathBuf, #[source] Box<LoadError>),
+    #[error("package {0} has a base package, which is not supported")]
+    Base(PathBuf),
+    #[error("{0}")]
+    Circular(CircularDependencyError),
+    #[error("couldn't resolve a directive")]
+    Resolution(#[source] Box<ResolutionError>),
+    #[error("couldn't resolve a tree directive")]
+    Tree(#[source] Box<tree::Error>),
+    #[error("couldn't apply an op")]
+    Op(#[source] Box<JournalOpError>),
+    #[error("cancelled before op {} of package {}", .0.op, .0.package)]
+    Cancelled(Position),
+}
+
+impl From<ResolutionError> for Error {
+    #[inline]
+    fn from(err: ResolutionError) -> Self {
+        Self::Resolution(Box::new(err))
+    }
+}
+
+impl From<tree::Error> for Error {
+    #[inline]
+    fn from(err: tree::Error) -> Self {
+        Self::Tree(Box::new(err))
+    }
+}
+
+impl From<JournalOpError> for Error {
+    #[inline]
+    fn from(err: JournalOpError) -> Self {
+        Self::Op(Box::new(err))
+    }
+}
@@ -0,0 +1,242 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::action::{self, Action, ResolutionError, Resolve};
+use crate::graph::{PackageGraph, PathResolver};
+use crate::op::{effect::Effect, journal::JournalOp, simulate::SimulatedFs};
+
+use super::{Error, Position};
+
+/// Ops that applying packages would run, in order.
+///
+/// Hooks (commands, functions, and scripts) are not journaled, and are left out.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Plan {
+    pub packages: Vec<PackagePlan>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PackagePlan {
+    /// Absolute path of the package.
+    pub path: PathBuf,
+    pub name: String,
+    pub ops: Vec<JournalOp>,
+    /// Predicted effect of each op in `ops` on the current filesystem, at the same indices.
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+}
+
+impl Plan {
+    /// Resolve the directives of the packages in `graph` against the destination `paths`, which
+    /// may be just the home directory.
+    #[inline]
+    pub fn new<R>(graph: &PackageGraph, paths: R) -> Result<Self, Error>
+    where
+        R: Into<PathResolver>,
+    {
+        let paths = paths.into();
+        // Ops are simulated in order across packages, since later packages see earlier changes.
+        let mut fs = SimulatedFs::new();
+        let packages = graph
+            .order()
+            .map_err(Error::Circular)?
+            .map(|pd| {
+                let ops = pd
+                    .action_iter(&paths)
+                    .map(action_ops)
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let ops: Vec<_> = ops.into_iter().flatten().collect();
+                let effects = ops.iter().map(|op| fs.apply(op)).collect();
+
+                Ok(PackagePlan {
+                    path: pd.path.clone(),
+                    name: pd.spec.name.clone(),
+                    ops,
+                    effects,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Plan { packages })
+    }
+
+    /// Return the rest of the plan from the op at `position` on, e.g. to apply it after
+    /// [`Error::Cancelled`](super::Error::Cancelled).
+    #[inline]
+    pub fn resume_from(&self, position: Position) -> Self {
+        let packages = self
+            .packages
+            .iter()
+            .skip(position.package)
+            .enumerate()
+            .map(|(i, package)| {
+                let skip = if i == 0 { position.op } else { 0 };
+                PackagePlan {
+                    path: package.path.clone(),
+                    name: package.name.clone(),
+                    ops: package.ops.iter().skip(skip).cloned().collect(),
+                    effects: package.effects.iter().skip(skip).copied().collect(),
+                }
+            })
+            .collect();
+
+        Self { packages }
+    }
+}
+
+#[inline]
+fn action_ops(action: Action<'_>) -> Result<Vec<JournalOp>, Error> {
+    let ops = match action {
+        Action::Link(action) => link_ops(action.resolve().map_err(ResolutionError::from)?),
+        Action::Tree(action) => match action.resolve()? {
+            action::tree::Res::Normal(reses) => reses.into_iter().flat_map(link_ops).collect(),
+            action::tree::Res::Skip(_) => vec![],
+        },
+        Action::CopyDir(action) => match action.resolve().map_err(ResolutionError::from)? {
+            action::copydir::Res::Normal(ops) | action::copydir::Res::Overwrite(ops) => ops
+                .into_iter()
+                .map(|op| match op {
+                    action::copydir::Op::Rm(op) => op.into(),
+                    action::copydir::Op::CopyDir(op) => op.into(),
+                    action::copydir::Op::Mkdir(op) => op.into(),
+                })
+                .collect(),
+            action::copydir::Res::Skip(_) => vec![],
+        },
+        Action::Write(action) => write_ops(action.resolve())?,
+        Action::SourceLine(action) => match action.resolve().map_err(ResolutionError::from)? {
+            action::sourceline::Res::Normal(op) => vec![op.into()],
+            action::sourceline::Res::Skip(_) => vec![],
+        },
+        Action::Defaults(action) => match action.resolve().map_err(ResolutionError::from)? {
+            action::defaults::Res::Normal(op) => vec![op.into()],
+            action::defaults::Res::Skip(_) => vec![],
+        },
+        Action::RegValue(action) => match action.resolve().map_err(ResolutionError::from)? {
+            #[cfg(all(windows, feature = "registry"))]
+            action::registry::Res::Normal(op) => vec![op.into()],
+            action::registry::Res::Skip(_) => vec![],
+        },
+        Action::Plugin(action) => action
+            .resolve()
+            .map_err(ResolutionError::from)?
+            .into_iter()
+            .map(|directive| action_ops(directive.into()))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect(),
+        Action::Fragment(action) => {
+            let res = action.resolve().map_err(ResolutionError::from)?;
+            res.prune
+                .into_iter()
+                .map(|op| op.into())
+                .chain(write_ops(res.write)?)
+                .collect()
+        }
+        Action::Handlebars(action) => {
+            template_ops(action.resolve().map_err(ResolutionError::from)?)?
+        }
+        Action::Liquid(action) => template_ops(action.resolve().map_err(ResolutionError::from)?)?,
+        Action::Gotmpl(action) => template_ops(action.resolve().map_err(ResolutionError::from)?)?,
+        Action::Pipe(action) => template_ops(action.resolve().map_err(ResolutionError::from)?)?,
+        Action::Yaml(action) => write_ops(action.resolve().map_err(ResolutionError::from)?)?,
+        Action::Toml(action) => write_ops(action.resolve().map_err(ResolutionError::from)?)?,
+        Action::Json(action) => write_ops(action.resolve().map_err(ResolutionError::from)?)?,
+        Action::Mkdir(action) => match action.resolve() {
+            action::mkdir::Res::Normal(ops) | action::mkdir::Res::Overwrite(ops) => ops
+                .into_iter()
+                .map(|op| match op {
+                    action::mkdir::Op::Rm(op) => op.into(),
+                    action::mkdir::Op::Mkdir(op) => op.into(),
+                    action::mkdir::Op::Chmod(op) => op.into(),
+                    #[cfg(unix)]
+                    action::mkdir::Op::Chown(op) => op.into(),
+                })
+                .collect(),
+            action::mkdir::Res::Skip(_) => vec![],
+        },
+        Action::SystemdUnit(action) => match action.resolve().map_err(ResolutionError::from)? {
+            action::systemd::Res::Normal(ops) | action::systemd::Res::Overwrite(ops) => ops
+                .into_iter()
+                .map(|op| match op {
+                    action::systemd::Op::Rm(op) => op.into(),
+                    action::systemd::Op::Link(op) => op.into(),
+                    action::systemd::Op::Mkdir(op) => op.into(),
+                    action::systemd::Op::Systemctl(op) => op.into(),
+                })
+                .collect(),
+            action::systemd::Res::Skip(_) => vec![],
+        },
+        Action::SensitivePerms(action) => {
+            action.resolve().ops.into_iter().map(Into::into).collect()
+        }
+        // Expectations are checked when applying, since they depend on the actions before them.
+        Action::Expect(_) | Action::Command(_) | Action::Function(_) | Action::Script(_) => {
+            vec![]
+        }
+    };
+
+    Ok(ops)
+}
+
+#[inline]
+fn link_ops(res: action::link::Res) -> Vec<JournalOp> {
+    match res {
+        action::link::Res::Normal(ops) | action::link::Res::Overwrite(ops) => ops
+            .into_iter()
+            .map(|op| match op {
+                action::link::Op::Rm(op) => op.into(),
+                action::link::Op::Link(op) => op.into(),
+                action::link::Op::Copy(op) => op.into(),
+                action::link::Op::Hardlink(op) => op.into(),
+                action::link::Op::Mkdir(op) => op.into(),
+                action::link::Op::Chmod(op) => op.into(),
+                #[cfg(unix)]
+                action::link::Op::Chown(op) => op.into(),
+            })
+            .collect(),
+        action::link::Res::Skip(_) => vec![],
+    }
+}
+
+#[inline]
+fn write_ops(res: action::write::Res) -> Result<Vec<JournalOp>, Error> {
+    match res {
+        action::write::Res::Normal(ops)
+        | action::write::Res::OverwriteContents(ops)
+        | action::write::Res::OverwriteFile(ops) => Ok(map_write_ops(ops)),
+        action::write::Res::Skip(_) => Ok(vec![]),
+        action::write::Res::Conflict => Err(ResolutionError::Conflict.into()),
+    }
+}
+
+#[inline]
+fn template_ops(res: action::template::Res) -> Result<Vec<JournalOp>, Error> {
+    match res {
+        action::template::Res::Normal(ops)
+        | action::template::Res::OverwriteContents(ops)
+        | action::template::Res::OverwriteFile(ops) => Ok(map_write_ops(ops)),
+        action::template::Res::Skip(_) => Ok(vec![]),
+        action::template::Res::Conflict => Err(ResolutionError::Conflict.into()),
+        action::template::Res::ParentMissing => Err(ResolutionError::ParentMissing.into()),
+    }
+}
+
+#[inline]
+fn map_write_ops(ops: Vec<action::write::Op>) -> Vec<JournalOp> {
+    ops.into_iter()
+        .map(|op| match op {
+            action::write::Op::Rm(op) => op.into(),
+            action::write::Op::Copy(op) => op.into(),
+            action::write::Op::Create(op) => op.into(),
+            action::write::Op::Write(op) => op.into(),
+            action::write::Op::Mkdir(op) => op.into(),
+            action::write::Op::Chmod(op) => op.into(),
+            #[cfg(unix)]
+            action::write::Op::Chown(op) => op.into(),
+        })
+        .collect()
+}
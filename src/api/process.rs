@@ -0,0 +1,215 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::op::{
+    ctx::FinishCtx,
+    journal::{JournalOp, JournalOpError, JournalOpFinish, OpJournal},
+};
+
+use super::{Error, PackagePlan, Plan};
+
+/// Receiver of the progress of a [`Processor`]. All methods do nothing by default.
+pub trait Observer {
+    /// Called before the ops of `package` are run.
+    #[inline]
+    fn package_started(&mut self, _package: &PackagePlan) {}
+
+    /// Called after `op` is run, with its result.
+    #[inline]
+    fn op_finished(&mut self, _op: &JournalOp, _res: Result<&JournalOpFinish, &JournalOpError>) {}
+
+    /// Called after all the ops of `package` have run successfully.
+    #[inline]
+    fn package_finished(&mut self, _package: &PackagePlan) {}
+}
+
+/// Token through which applying can be cancelled, e.g. from another thread of an embedding
+/// application. Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask that applying stop before the next op.
+    #[inline]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Position of an op in a [`Plan`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    /// Index of the package in [`Plan::packages`].
+    pub package: usize,
+    /// Index of the op in the [`PackagePlan::ops`] of the package.
+    pub op: usize,
+}
+
+/// Runner of the ops of [`Plan`]s. Ops are journaled, one transaction per package, so that they
+/// can be rolled back with [`Processor::journal_mut`].
+#[derive(Debug)]
+pub struct Processor {
+    ctx: FinishCtx,
+    journal: OpJournal,
+    cancel: Option<CancelToken>,
+}
+
+impl Processor {
+    #[inline]
+    pub fn new(ctx: FinishCtx) -> Self {
+        Self {
+            ctx,
+            journal: OpJournal::new(),
+            cancel: None,
+        }
+    }
+
+    /// Check `cancel` between ops, and stop with [`Error::Cancelled`] once it is cancelled. The
+    /// ops run so far are committed to the journal, and the rest can be applied later with
+    /// [`Plan::resume_from`].
+    #[inline]
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Run the ops of `plan`, stopping at the first failure.
+    #[inline]
+    pub fn apply(&mut self, plan: &Plan) -> Result<(), Error> {
+        self.apply_observed(plan, &mut NoopObserver)
+    }
+
+    /// Run the ops of `plan`, stopping at the first failure, and report progress to `observer`.
+    #[inline]
+    pub fn apply_observed<O>(&mut self, plan: &Plan, observer: &mut O) -> Result<(), Error>
+    where
+        O: Observer + ?Sized,
+    {
+        for (i, package) in plan.packages.iter().enumerate() {
+            check_cancelled(&self.cancel, i, 0)?;
+            observer.package_started(package);
+
+            let mut t = self.journal.lock();
+            for (j, op) in package.ops.iter().enumerate() {
+                if j > 0 {
+                    check_cancelled(&self.cancel, i, j)?;
+                }
+
+                match t.append_finish(op.clone(), &self.ctx) {
+                    Ok(fin) => observer.op_finished(op, Ok(fin)),
+                    Err(err) => {
+                        observer.op_finished(op, Err(&err.inner));
+                        return Err(err.inner.into());
+                    }
+                }
+            }
+            drop(t);
+
+            observer.package_finished(package);
+        }
+
+        Ok(())
+    }
+
+    /// Return the journal of the ops run so far.
+    #[inline]
+    pub fn journal(&self) -> &OpJournal {
+        &self.journal
+    }
+
+    /// Return the journal of the ops run so far, e.g. to roll them back.
+    #[inline]
+    pub fn journal_mut(&mut self) -> &mut OpJournal {
+        &mut self.journal
+    }
+
+    #[inline]
+    pub fn into_journal(self) -> OpJournal {
+        self.journal
+    }
+}
+
+/// Fail with [`Error::Cancelled`] at the op at `package` and `op` if `cancel` is cancelled.
+#[inline]
+fn check_cancelled(cancel: &Option<CancelToken>, package: usize, op: usize) -> Result<(), Error> {
+    match cancel {
+        Some(cancel) if cancel.is_cancelled() => Err(Error::Cancelled(Position { package, op })),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug)]
+struct NoopObserver;
+
+impl Observer for NoopObserver {}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::op::{ctx::FileSafe, MkdirOp};
+
+    use super::{CancelToken, Error, FinishCtx, Observer, PackagePlan, Plan, Position, Processor};
+
+    /// Observer that cancels after the first package.
+    struct Canceller(CancelToken);
+
+    impl Observer for Canceller {
+        fn package_finished(&mut self, _package: &PackagePlan) {
+            self.0.cancel();
+        }
+    }
+
+    fn mkdir_package(dir: &Path, name: &str) -> PackagePlan {
+        PackagePlan {
+            path: dir.join(name),
+            name: name.to_string(),
+            ops: vec![MkdirOp {
+                path: dir.join(name),
+            }
+            .into()],
+            effects: vec![],
+        }
+    }
+
+    #[test]
+    fn test_cancel() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let safedir = tempfile::tempdir()?;
+        let plan = Plan {
+            packages: vec![
+                mkdir_package(dir.path(), "a"),
+                mkdir_package(dir.path(), "b"),
+            ],
+        };
+
+        let cancel = CancelToken::new();
+        let mut processor = Processor::new(FinishCtx::new(FileSafe::new(safedir.path())))
+            .with_cancel(cancel.clone());
+        let position = match processor.apply_observed(&plan, &mut Canceller(cancel)) {
+            Err(Error::Cancelled(position)) => position,
+            res => panic!("expected cancellation, got {:?}", res),
+        };
+        assert_eq!(position, Position { package: 1, op: 0 });
+        assert!(dir.path().join("a").is_dir());
+        assert!(!dir.path().join("b").exists());
+
+        let mut processor = Processor::new(FinishCtx::new(FileSafe::new(safedir.path())));
+        processor.apply(&plan.resume_from(position))?;
+        assert!(dir.path().join("b").is_dir());
+
+        Ok(())
+    }
+}
@@ -1,3 +1,8 @@
+pub mod expand;
+pub mod snapshot;
+
+pub use self::snapshot::Snapshot;
+
 use std::fs;
 use std::path::{Component, Path, PathBuf};
 
@@ -40,3 +45,50 @@ where
 
     components.into_iter().collect()
 }
+
+/// Return true if the metadata `a` and `b` belong to the same file, i.e. hard links of each other.
+#[cfg(unix)]
+#[inline]
+pub fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+#[inline]
+pub fn same_file(_a: &fs::Metadata, _b: &fs::Metadata) -> bool {
+    false
+}
+
+/// Return true if the metadata `a` and `b` belong to files on the same filesystem.
+#[cfg(unix)]
+#[inline]
+pub fn same_device(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    a.dev() == b.dev()
+}
+
+#[cfg(not(unix))]
+#[inline]
+pub fn same_device(_a: &fs::Metadata, _b: &fs::Metadata) -> bool {
+    true
+}
+
+/// Return the host name of the machine, or `None` if it can't be determined.
+#[inline]
+pub fn hostname() -> Option<String> {
+    let from_file = |path| {
+        fs::read_to_string(path)
+            .ok()
+            .map(|name| name.trim().to_string())
+    };
+    let from_env = |var| std::env::var(var).ok().map(|name| name.trim().to_string());
+
+    from_file("/proc/sys/kernel/hostname")
+        .or_else(|| from_file("/etc/hostname"))
+        .or_else(|| from_env("HOSTNAME"))
+        .or_else(|| from_env("COMPUTERNAME"))
+        .filter(|name| !name.is_empty())
+}
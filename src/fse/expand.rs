@@ -0,0 +1,165 @@
+//! Expansion of `~` and environment variables in paths.
+
+use std::ffi::OsString;
+use std::path::{self, Path, PathBuf};
+
+/// What to do with variables that aren't set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownVars {
+    /// Fail to expand the path.
+    Error,
+    /// Expand them to nothing.
+    Empty,
+}
+
+impl Default for UnknownVars {
+    #[inline]
+    fn default() -> Self {
+        Self::Error
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExpandError {
+    #[error("environment variable '{0}' isn't set")]
+    Unknown(String),
+    #[error("invalid variable reference in '{0}'")]
+    Invalid(String),
+}
+
+/// Expand `path`: a leading `~` is replaced by the value of `HOME`, and `$NAME` and `${NAME}` by
+/// the value of the variable `NAME`, as given by `lookup`. `$$` is a literal `$`, as is a `$` not
+/// followed by a name. Variables that `lookup` doesn't know are handled according to `unknown`.
+/// Paths that aren't valid Unicode are left alone.
+#[inline]
+pub fn expand<F>(path: &Path, lookup: F, unknown: UnknownVars) -> Result<PathBuf, ExpandError>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    let s = match path.to_str() {
+        Some(s) if s.contains(['~', '$']) => s,
+        _ => return Ok(path.to_path_buf()),
+    };
+
+    let value = |name: &str| match (lookup(name), unknown) {
+        (Some(value), _) => Ok(value),
+        (None, UnknownVars::Empty) => Ok(OsString::new()),
+        (None, UnknownVars::Error) => Err(ExpandError::Unknown(name.to_string())),
+    };
+
+    let mut expanded = OsString::new();
+    let mut rest = s;
+    // Only `~` on its own is the home directory; `~user` isn't supported.
+    if let Some(after) = rest.strip_prefix('~') {
+        if after.chars().next().is_none_or(path::is_separator) {
+            expanded.push(value("HOME")?);
+            rest = after;
+        }
+    }
+
+    while let Some(i) = rest.find('$') {
+        expanded.push(&rest[..i]);
+        let after = &rest[i + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            expanded.push("$");
+            rest = after;
+        } else if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| ExpandError::Invalid(s.to_string()))?;
+            let name = &braced[..end];
+            if !is_name(name) {
+                return Err(ExpandError::Invalid(s.to_string()));
+            }
+            expanded.push(value(name)?);
+            rest = &braced[end + 1..];
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            let name = &after[..end];
+            if is_name(name) {
+                expanded.push(value(name)?);
+                rest = &after[end..];
+            } else {
+                expanded.push("$");
+                rest = after;
+            }
+        }
+    }
+    expanded.push(rest);
+
+    Ok(expanded.into())
+}
+
+/// Return true if `name` is a valid variable name: ASCII letters, digits, and underscores, not
+/// starting with a digit.
+#[inline]
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+
+    use super::{expand, ExpandError, UnknownVars};
+
+    fn lookup(name: &str) -> Option<OsString> {
+        match name {
+            "HOME" => Some("/home/user".into()),
+            "XDG_CONFIG_HOME" => Some("/home/user/.config".into()),
+            _ => None,
+        }
+    }
+
+    fn expand_str(path: &str, unknown: UnknownVars) -> Result<PathBuf, ExpandError> {
+        expand(Path::new(path), lookup, unknown)
+    }
+
+    #[test]
+    fn test_expand() {
+        let expanded = |path| expand_str(path, UnknownVars::Error).unwrap();
+        assert_eq!(expanded("~"), Path::new("/home/user"));
+        assert_eq!(expanded("~/.zshrc"), Path::new("/home/user/.zshrc"));
+        assert_eq!(expanded("$HOME/.zshrc"), Path::new("/home/user/.zshrc"));
+        assert_eq!(
+            expanded("${XDG_CONFIG_HOME}/nvim"),
+            Path::new("/home/user/.config/nvim")
+        );
+        assert_eq!(
+            expanded("${HOME}_backup/a"),
+            Path::new("/home/user_backup/a")
+        );
+        // Only whole leading `~`s and references to names are expanded.
+        assert_eq!(expanded("a/~/b~"), Path::new("a/~/b~"));
+        assert_eq!(expanded("~user/a"), Path::new("~user/a"));
+        assert_eq!(expanded("a$/$1/$$HOME"), Path::new("a$/$1/$HOME"));
+        assert_eq!(expanded(".zshrc"), Path::new(".zshrc"));
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        assert!(matches!(
+            expand_str("$UNSET/a", UnknownVars::Error),
+            Err(ExpandError::Unknown(name)) if name == "UNSET"
+        ));
+        assert_eq!(
+            expand_str("a/${UNSET}b", UnknownVars::Empty).unwrap(),
+            Path::new("a/b")
+        );
+        assert!(matches!(
+            expand_str("${HOME", UnknownVars::Empty),
+            Err(ExpandError::Invalid(_))
+        ));
+        assert!(matches!(
+            expand_str("${1A}", UnknownVars::Empty),
+            Err(ExpandError::Invalid(_))
+        ));
+    }
+}
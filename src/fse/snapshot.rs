@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A recorded entry of a [`Snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    /// A regular file and its contents.
+    File(Vec<u8>),
+    /// A directory. Its contents are recorded as separate entries.
+    Dir,
+    /// A symlink and its (unresolved) target.
+    Symlink(PathBuf),
+}
+
+/// A recursive record of the files, directories, and symlinks under a root directory, for testing
+/// that filesystem changes leave things as they were. Paths are relative to the root, and
+/// symlinks are never followed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+/// A difference between two [`Snapshot`]s at a single relative path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The path exists only in the new snapshot.
+    Added(PathBuf),
+    /// The path exists only in the old snapshot.
+    Removed(PathBuf),
+    /// The path exists in both, but the entries differ.
+    Modified(PathBuf),
+}
+
+impl Snapshot {
+    /// Record everything under the directory `root`.
+    #[inline]
+    pub fn take<P>(root: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut entries = BTreeMap::new();
+        walk(root.as_ref(), Path::new(""), &mut entries)?;
+        Ok(Self { entries })
+    }
+
+    /// Return the recorded entry at the path `rel`, relative to the root.
+    #[inline]
+    pub fn get<P>(&self, rel: P) -> Option<&Entry>
+    where
+        P: AsRef<Path>,
+    {
+        self.entries.get(rel.as_ref())
+    }
+
+    /// Return an iterator over the recorded paths and their entries, in order.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &Entry)> {
+        self.entries
+            .iter()
+            .map(|(path, entry)| (path.as_path(), entry))
+    }
+
+    /// Return the number of recorded entries, not including the root.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Return true if the root was empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Return the changes from this snapshot to `other`, in path order.
+    #[inline]
+    pub fn diff(&self, other: &Self) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        for (path, entry) in &self.entries {
+            match other.entries.get(path) {
+                None => changes.push(Change::Removed(path.clone())),
+                Some(other) if other != entry => changes.push(Change::Modified(path.clone())),
+                Some(_) => {}
+            }
+        }
+        for path in other.entries.keys() {
+            if !self.entries.contains_key(path) {
+                changes.push(Change::Added(path.clone()));
+            }
+        }
+
+        changes.sort_by(|a, b| a.path().cmp(b.path()));
+        changes
+    }
+}
+
+impl Change {
+    /// Return the relative path that changed.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Added(path) | Self::Removed(path) | Self::Modified(path) => path,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added(path) => write!(f, "added {}", path.display()),
+            Self::Removed(path) => write!(f, "removed {}", path.display()),
+            Self::Modified(path) => write!(f, "modified {}", path.display()),
+        }
+    }
+}
+
+#[inline]
+fn walk(root: &Path, rel: &Path, entries: &mut BTreeMap<PathBuf, Entry>) -> io::Result<()> {
+    for dirent in fs::read_dir(root.join(rel))? {
+        let dirent = dirent?;
+        let rel = rel.join(dirent.file_name());
+        let path = root.join(&rel);
+
+        let ft = dirent.file_type()?;
+        if ft.is_symlink() {
+            entries.insert(rel, Entry::Symlink(fs::read_link(&path)?));
+        } else if ft.is_dir() {
+            entries.insert(rel.clone(), Entry::Dir);
+            walk(root, &rel, entries)?;
+        } else {
+            entries.insert(rel, Entry::File(fs::read(&path)?));
+        }
+    }
+
+    Ok(())
+}
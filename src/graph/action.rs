@@ -1,50 +1,170 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::iter::Enumerate;
 use std::path::{Path, PathBuf};
 use std::slice;
+use std::time::Duration;
 
 use mlua::{Function, Lua};
 
+use crate::action::comment::{self, CommentSyntax};
+use crate::action::conflict::ConflictPolicy;
+use crate::action::missing::MissingParentPolicy;
+use crate::action::mode::Owner;
+use crate::action::template::Engine;
 use crate::action::{
-    Action, CommandAction, FunctionAction, HandlebarsAction, JsonAction, LinkAction, LiquidAction,
-    MkdirAction, TomlAction, TreeAction, WriteAction, YamlAction,
+    Action, CommandAction, CopyDirAction, DefaultsAction, ExpectAction, FragmentAction,
+    FunctionAction, GotmplAction, HandlebarsAction, JsonAction, LinkAction, LiquidAction,
+    MkdirAction, PipeAction, PluginAction, RegValueAction, ScriptAction, SourceLineAction,
+    SystemdUnitAction, TomlAction, TreeAction, WriteAction, YamlAction,
 };
-use crate::fse;
-use crate::graph::PackageData;
+use crate::fse::{self, expand::ExpandError};
+use crate::graph::{DestFilter, PackageData, PathResolver, Selector};
+use crate::op::command;
 use crate::spec::{
-    CmdHook, DirFile, Directive, File, FunHook, GeneratedFile, GeneratedFileTyp, Hook, LinkType,
-    RegularFile, TemplatedFile, TemplatedFileType, TreeFile,
+    CmdHook, Condition, CopyDirFile, DefaultsFile, DirFile, Directive, EnvMap, ExpectFile,
+    Expectation, File, FragmentFile, FunHook, GeneratedFile, GeneratedFileTyp, Gid, Hook, LinkType,
+    Mode, Object, PipedFile, PluginFile, RegValueFile, RegularFile, ScriptHook, SourceLineFile,
+    SystemdUnitFile, TemplatedFile, TemplatedFileType, TreeFile, Uid,
 };
 
 impl PackageData {
     #[inline]
-    pub fn action_iter<P>(&self, dest: P) -> ActionIter<'_>
+    pub fn action_iter<R>(&self, paths: R) -> ActionIter<'_>
     where
-        P: AsRef<Path>,
+        R: Into<PathResolver>,
     {
         ActionIter {
-            dest: dest.as_ref().to_path_buf(),
+            paths: paths.into(),
             path: &self.path,
+            name: &self.spec.name,
             lua: &self.lua,
-            directives: self.spec.directives.iter(),
+            env: &self.spec.env,
+            shell: command::default_shell().to_string(),
+            host: fse::hostname(),
+            all: &self.spec.directives,
+            conditions: &self.spec.conditions,
+            policies: &self.spec.on_conflict,
+            conflict: ConflictPolicy::default(),
+            force: false,
+            missing: &self.spec.if_missing,
+            tags: &self.spec.profiles,
+            hbs_helpers: &self.spec.hbs_helpers,
+            profiles: Vec::new(),
+            directives: self.spec.directives.iter().enumerate(),
+            selectors: Vec::new(),
+            only: None,
         }
     }
+
+    /// Check that `~` and environment variables in the destinations of the directives expand, as
+    /// they would when resolving them with `paths`. See [`PathResolver::expand`].
+    #[inline]
+    pub fn check_dests(&self, paths: &PathResolver) -> Result<(), ExpandError> {
+        self.spec
+            .directives
+            .iter()
+            .filter_map(directive_dest)
+            .try_for_each(|dest| paths.expand(dest).map(|_| ()))
+    }
+}
+
+/// Return the destination of a file directive as written, which may contain variables, or `None`
+/// if it has none or a fixed one.
+#[inline]
+fn directive_dest(drct: &Directive) -> Option<&Path> {
+    let dest = match drct {
+        Directive::File(f) => match f {
+            File::Regular(rf) => rf.dest.as_ref().unwrap_or(&rf.src),
+            File::CopyDir(cf) => cf.dest.as_ref().unwrap_or(&cf.src),
+            File::Tree(tf) => tf.dest.as_ref()?,
+            File::Templated(tf) => &tf.dest,
+            File::Piped(pf) => &pf.dest,
+            File::Generated(gf) => &gf.dest,
+            File::Dir(df) => &df.dest,
+            File::Fragment(ff) => &ff.dir,
+            File::SourceLine(sf) => &sf.rc,
+            File::Expect(ef) => &ef.dest,
+            File::SystemdUnit(_) | File::Defaults(_) | File::RegValue(_) | File::Plugin(_) => {
+                return None
+            }
+        },
+        Directive::Hook(_) => return None,
+    };
+
+    Some(dest)
 }
 
+/// Name of the package subdirectory that contains machine-specific overrides, in
+/// `host/<hostname>/`.
+pub const HOSTS_DIR: &str = "host";
+
 pub struct ActionIter<'g> {
-    dest: PathBuf,
+    paths: PathResolver,
     path: &'g Path,
+    name: &'g str,
     lua: &'g Lua,
-
-    directives: slice::Iter<'g, Directive>,
+    env: &'g EnvMap,
+    shell: String,
+    /// Host name of the machine, used to find machine-specific overrides.
+    host: Option<String>,
+
+    /// All directives of the package, including those that aren't selected.
+    all: &'g [Directive],
+    /// Conditions of the directives, by index. See [`Spec::conditions`].
+    ///
+    /// [`Spec::conditions`]: crate::spec::Spec::conditions
+    conditions: &'g [Vec<Condition>],
+    /// Conflict policies of the directives, by index. See [`Spec::on_conflict`].
+    ///
+    /// [`Spec::on_conflict`]: crate::spec::Spec::on_conflict
+    policies: &'g [Option<ConflictPolicy>],
+    /// Conflict policy of directives that don't specify one.
+    conflict: ConflictPolicy,
+    /// Whether to replace existing destinations regardless of conflict policies.
+    force: bool,
+    /// Missing parent policies of the directives, by index. See [`Spec::if_missing`].
+    ///
+    /// [`Spec::if_missing`]: crate::spec::Spec::if_missing
+    missing: &'g [Option<MissingParentPolicy>],
+    /// Profiles of the directives, by index. See [`Spec::profiles`].
+    ///
+    /// [`Spec::profiles`]: crate::spec::Spec::profiles
+    tags: &'g [Vec<String>],
+    /// Selected profiles.
+    profiles: Vec<String>,
+    /// Handlebars helpers of the package. See [`Spec::hbs_helpers`].
+    ///
+    /// [`Spec::hbs_helpers`]: crate::spec::Spec::hbs_helpers
+    hbs_helpers: &'g BTreeMap<String, String>,
+    directives: Enumerate<slice::Iter<'g, Directive>>,
+    selectors: Vec<Selector>,
+    only: Option<DestFilter>,
 }
 
 impl<'p> fmt::Debug for ActionIter<'p> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ActionIter")
-            .field("dest", &self.dest)
+            .field("paths", &self.paths)
             .field("path", &self.path)
+            .field("name", &self.name)
             .field("lua", &"<lua>")
+            .field("env", &self.env)
+            .field("shell", &self.shell)
+            .field("host", &self.host)
+            .field("all", &self.all)
+            .field("conditions", &self.conditions)
+            .field("policies", &self.policies)
+            .field("conflict", &self.conflict)
+            .field("force", &self.force)
+            .field("missing", &self.missing)
+            .field("tags", &self.tags)
+            .field("profiles", &self.profiles)
+            .field("hbs_helpers", &self.hbs_helpers)
             .field("directives", &self.directives)
+            .field("selectors", &self.selectors)
+            .field("only", &self.only)
             .finish()
     }
 }
@@ -54,12 +174,137 @@ impl<'g> Iterator for ActionIter<'g> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        let drct = self.directives.next()?;
-        Some(self.get_directive(drct))
+        loop {
+            let (action, met) = self.next_checked()?;
+            if met {
+                return Some(action);
+            }
+        }
+    }
+}
+
+/// Iterator over the actions of a package along with whether their directives are to be applied:
+/// whether their conditions hold and their profiles are selected. See [`ActionIter::checked`].
+#[derive(Debug)]
+pub struct CheckedActionIter<'g>(ActionIter<'g>);
+
+impl<'g> Iterator for CheckedActionIter<'g> {
+    type Item = (Action<'g>, bool);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_checked()
     }
 }
 
 impl<'g> ActionIter<'g> {
+    /// Only yield actions for directives matched by any of `selectors`. If `selectors` is empty,
+    /// all directives are matched.
+    #[inline]
+    pub fn select(mut self, selectors: Vec<Selector>) -> Self {
+        self.selectors = selectors;
+        self
+    }
+
+    /// Only yield actions restricted to the destinations matched by `only`. See
+    /// [`DestFilter::filter`].
+    #[inline]
+    pub fn only(mut self, only: DestFilter) -> Self {
+        self.only = Some(only);
+        self
+    }
+
+    /// Use `shell` for command hooks that don't specify one, instead of the platform default. See
+    /// [`command::default_shell`].
+    #[inline]
+    pub fn default_shell(mut self, shell: String) -> Self {
+        self.shell = shell;
+        self
+    }
+
+    /// Use `policy` for existing destinations of directives that don't specify their own conflict
+    /// policy, instead of replacing them.
+    #[inline]
+    pub fn on_conflict(mut self, policy: ConflictPolicy) -> Self {
+        self.conflict = policy;
+        self
+    }
+
+    /// If `force` is true, replace existing destinations regardless of the conflict policies of
+    /// directives.
+    #[inline]
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Select `profiles`, so that directives belonging to them are applied too. Directives that
+    /// belong to no profile are always applied; by default, no profile is selected.
+    #[inline]
+    pub fn profiles(mut self, profiles: Vec<String>) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    /// Also yield the actions of directives whose conditions don't hold or whose profiles aren't
+    /// selected, along with whether they are to be applied, rather than leaving them out.
+    #[inline]
+    pub fn checked(self) -> CheckedActionIter<'g> {
+        CheckedActionIter(self)
+    }
+
+    #[inline]
+    fn next_checked(&mut self) -> Option<(Action<'g>, bool)> {
+        loop {
+            let (i, drct) = self.directives.next()?;
+            let selected =
+                self.selectors.is_empty() || self.selectors.iter().any(|sel| sel.matches(i, drct));
+            if !selected {
+                continue;
+            }
+
+            let mut action = self.get_directive(drct);
+            action.set_on_conflict(self.policy(i));
+            action.set_if_missing(self.missing.get(i).copied().flatten().unwrap_or_default());
+            let action = match &self.only {
+                Some(only) => only.filter(action),
+                None => Some(action),
+            };
+            if let Some(action) = action {
+                // Conditions are checked now, rather than when loading, so that cached specs
+                // still reflect the machine they are applied on.
+                let host = self.host.as_deref();
+                let met = self
+                    .conditions
+                    .get(i)
+                    .into_iter()
+                    .flatten()
+                    .all(|condition| condition.holds(host));
+                let profiled = self
+                    .tags
+                    .get(i)
+                    .into_iter()
+                    .flatten()
+                    .all(|profile| self.profiles.contains(profile));
+                return Some((action, met && profiled));
+            }
+        }
+    }
+
+    /// Return the conflict policy of the directive at index `i`.
+    #[inline]
+    fn policy(&self, i: usize) -> ConflictPolicy {
+        if self.force {
+            return ConflictPolicy::Replace;
+        }
+
+        self.policies
+            .get(i)
+            .copied()
+            .flatten()
+            .unwrap_or(self.conflict)
+    }
+
     #[inline]
     fn get_directive(&self, drct: &Directive) -> Action<'g> {
         match drct {
@@ -73,9 +318,18 @@ impl<'g> ActionIter<'g> {
         match f {
             File::Regular(rf) => self.get_file_regular(rf),
             File::Templated(tf) => self.get_file_template(tf),
+            File::Piped(pf) => self.get_file_piped(pf),
             File::Tree(tf) => self.get_file_tree(tf),
+            File::CopyDir(cf) => self.get_file_copy_dir(cf),
             File::Generated(gf) => self.get_file_generated(gf),
             File::Dir(df) => self.get_file_dir(df),
+            File::SystemdUnit(sf) => self.get_file_systemd_unit(sf),
+            File::Fragment(ff) => self.get_file_fragment(ff),
+            File::SourceLine(sf) => self.get_file_source_line(sf),
+            File::Defaults(df) => self.get_file_defaults(df),
+            File::RegValue(rf) => self.get_file_reg_value(rf),
+            File::Plugin(pf) => self.get_file_plugin(pf),
+            File::Expect(ef) => self.get_file_expect(ef),
         }
     }
 
@@ -86,6 +340,9 @@ impl<'g> ActionIter<'g> {
             dest,
             link_type,
             optional,
+            mode,
+            owner,
+            group,
         } = rf;
 
         // Normalize src.
@@ -93,17 +350,25 @@ impl<'g> ActionIter<'g> {
         // Normalize dest (or use src if absent).
         let dest_w = self.join_dest(dest.as_ref().unwrap_or(src));
 
-        // Determine copy flag.
-        let copy = match link_type {
-            LinkType::Link => false,
-            LinkType::Copy => true,
+        // Determine copy, hardlink, and fallback flags.
+        let (copy, hardlink, fallback) = match link_type {
+            LinkType::Link => (false, false, false),
+            LinkType::Copy => (true, false, false),
+            LinkType::Hardlink => (false, true, false),
+            LinkType::Auto => (false, false, true),
         };
 
         Action::Link(LinkAction {
             src: src_w,
             dest: dest_w,
             copy,
+            hardlink,
+            fallback,
             optional: *optional,
+            mode: mode.map(|Mode(mode)| mode),
+            owner: to_owner(owner, group),
+            on_conflict: Default::default(),
+            if_missing: Default::default(),
         })
     }
 
@@ -115,6 +380,10 @@ impl<'g> ActionIter<'g> {
             vars,
             typ,
             optional,
+            auto_header,
+            mode,
+            owner,
+            group,
         } = tf;
 
         // Normalize src.
@@ -122,104 +391,403 @@ impl<'g> ActionIter<'g> {
         // Normalize dest.
         let dest_w = self.join_dest(dest);
 
-        match typ {
-            TemplatedFileType::Handlebars(hbs) => Action::Handlebars(HandlebarsAction {
+        // Comment the banner according to the destination file type; skip it if the type is
+        // unknown. It goes after any shebang of the rendered file.
+        let header = if *auto_header {
+            CommentSyntax::from_path(&dest_w).map(|syntax| syntax.comment(&self.banner()))
+        } else {
+            None
+        };
+
+        let mode = mode.map(|Mode(mode)| mode);
+        let owner = to_owner(owner, group);
+        let (engine, partials) = match typ {
+            TemplatedFileType::Handlebars(hbs) => (Engine::Handlebars, hbs.partials.clone()),
+            TemplatedFileType::Liquid(_) => (Engine::Liquid, Default::default()),
+            TemplatedFileType::Gotmpl(_) => (Engine::Gotmpl, Default::default()),
+            // SAFETY: The extension is checked when loading.
+            TemplatedFileType::Auto(_) => (Engine::from_path(src).unwrap(), Default::default()),
+        };
+
+        match engine {
+            Engine::Handlebars => Action::Handlebars(HandlebarsAction {
+                src: src_w,
+                dest: dest_w,
+                vars: vars.clone(),
+                optional: *optional,
+                partials,
+                // Load helper functions from Lua registry.
+                helpers: self
+                    .hbs_helpers
+                    .iter()
+                    .map(|(name, key)| (name.clone(), self.lua.named_registry_value(key).unwrap()))
+                    .collect(),
+                header,
+                mode,
+                owner,
+                on_conflict: Default::default(),
+                if_missing: Default::default(),
+            }),
+            Engine::Liquid => Action::Liquid(LiquidAction {
                 src: src_w,
                 dest: dest_w,
                 vars: vars.clone(),
                 optional: *optional,
-                partials: hbs.partials.clone(),
+                header,
+                mode,
+                owner,
+                on_conflict: Default::default(),
+                if_missing: Default::default(),
             }),
-            TemplatedFileType::Liquid(_) => Action::Liquid(LiquidAction {
+            Engine::Gotmpl => Action::Gotmpl(GotmplAction {
                 src: src_w,
                 dest: dest_w,
                 vars: vars.clone(),
                 optional: *optional,
+                header,
+                mode,
+                owner,
+                on_conflict: Default::default(),
+                if_missing: Default::default(),
             }),
         }
     }
 
+    #[inline]
+    fn get_file_piped(&self, pf: &PipedFile) -> Action<'g> {
+        let PipedFile {
+            src,
+            dest,
+            name,
+            optional,
+            mode,
+            owner,
+            group,
+        } = pf;
+
+        // Normalize src and dest.
+        let src_w = self.join_package(src);
+        let dest_w = self.join_dest(dest);
+
+        // Load function from Lua registry.
+        let function: Function = self.lua.named_registry_value(name).unwrap();
+
+        Action::Pipe(PipeAction {
+            src: src_w,
+            dest: dest_w,
+            function,
+            optional: *optional,
+            mode: mode.map(|Mode(mode)| mode),
+            owner: to_owner(owner, group),
+            on_conflict: Default::default(),
+        })
+    }
+
     #[inline]
     fn get_file_tree(&self, tf: &TreeFile) -> Action<'g> {
         let TreeFile {
             src,
+            layers,
             dest,
             globs,
             ignore,
+            volatile,
+            strip_prefix,
             link_type,
             optional,
         } = tf;
 
         // Normalize src.
         let src_w = self.join_package(src);
+        let layers = layers
+            .iter()
+            .map(|layer| self.join_package(layer))
+            .collect();
         // Normalize dest.
         let dest_w = dest
             .as_ref()
             .map(|dest| self.join_dest(dest))
-            .unwrap_or_else(|| self.dest.clone());
+            .unwrap_or_else(|| self.paths.home().to_path_buf());
 
         // FIXME no clone
         let globs = globs.clone().unwrap_or_else(|| vec!["**/*".to_string()]);
-        let ignore = ignore.clone().unwrap_or_default();
+        let mut ignore = ignore.clone().unwrap_or_default();
+
+        // Never link the machine-specific overrides themselves.
+        let hosts = self.path.join(HOSTS_DIR);
+        if let Ok(rel) = hosts.strip_prefix(&src_w) {
+            ignore.push(format!("{}/**/*", rel.display()));
+        }
 
-        // Determine copy flag.
-        let copy = match link_type {
-            LinkType::Link => false,
-            LinkType::Copy => true,
+        // Files in `host/<hostname>/<src>` override those in `src`.
+        let overrides = match (&self.host, src_w.strip_prefix(self.path)) {
+            (Some(host), Ok(rel)) => Some(hosts.join(host).join(rel)),
+            _ => None,
+        };
+        let volatile = volatile.clone().unwrap_or_default();
+
+        // Determine copy, hardlink, and fallback flags.
+        let (copy, hardlink, fallback) = match link_type {
+            LinkType::Link => (false, false, false),
+            LinkType::Copy => (true, false, false),
+            LinkType::Hardlink => (false, true, false),
+            LinkType::Auto => (false, false, true),
         };
 
         Action::Tree(TreeAction {
             src: src_w,
+            layers,
             dest: dest_w,
+            overrides,
             globs,
             ignore,
+            volatile,
+            strip_prefix: strip_prefix.clone(),
+            only: None,
             copy,
+            hardlink,
+            fallback,
+            optional: *optional,
+            on_conflict: Default::default(),
+            if_missing: Default::default(),
+            paths: self.paths.clone(),
+        })
+    }
+
+    #[inline]
+    fn get_file_copy_dir(&self, cf: &CopyDirFile) -> Action<'g> {
+        let CopyDirFile {
+            src,
+            dest,
+            volatile,
+            optional,
+        } = cf;
+
+        // Normalize src.
+        let src_w = self.join_package(src);
+        // Normalize dest (or use src if absent).
+        let dest_w = self.join_dest(dest.as_ref().unwrap_or(src));
+
+        Action::CopyDir(CopyDirAction {
+            src: src_w,
+            dest: dest_w,
+            volatile: volatile.clone().unwrap_or_default(),
             optional: *optional,
         })
     }
 
     #[inline]
     fn get_file_generated(&self, gf: &GeneratedFile) -> Action<'g> {
-        let GeneratedFile { dest, typ } = gf;
+        let GeneratedFile {
+            dest,
+            typ,
+            mode,
+            owner,
+            group,
+        } = gf;
 
         // Normalize dest.
         let dest_w = self.join_dest(dest);
+        let mode = mode.map(|Mode(mode)| mode);
+        let owner = to_owner(owner, group);
 
         match typ {
             GeneratedFileTyp::Empty(_) => Action::Write(WriteAction {
                 dest: dest_w,
                 contents: "".to_string().into_bytes(),
+                mode,
+                owner,
+                on_conflict: Default::default(),
             }),
             GeneratedFileTyp::String(s) => Action::Write(WriteAction {
                 dest: dest_w,
                 contents: s.contents.clone().into_bytes(),
+                mode,
+                owner,
+                on_conflict: Default::default(),
             }),
             // FIXME error context
             GeneratedFileTyp::Yaml(y) => Action::Yaml(YamlAction {
                 dest: dest_w,
-                values: y.values.clone(),
-                header: y.header.clone(),
+                values: sorted(&y.values, y.sort_keys),
+                header: self.generated_header(&y.header, y.auto_header),
+                schema: y.schema.as_ref().map(|schema| self.join_package(schema)),
+                mode,
+                owner,
+                on_conflict: Default::default(),
             }),
             GeneratedFileTyp::Toml(t) => Action::Toml(TomlAction {
                 dest: dest_w,
-                values: t.values.clone(),
-                header: t.header.clone(),
+                values: sorted(&t.values, t.sort_keys),
+                header: self.generated_header(&t.header, t.auto_header),
+                schema: t.schema.as_ref().map(|schema| self.join_package(schema)),
+                mode,
+                owner,
+                on_conflict: Default::default(),
             }),
             GeneratedFileTyp::Json(j) => Action::Json(JsonAction {
                 dest: dest_w,
-                values: j.values.clone(),
+                values: sorted(&j.values, j.sort_keys),
+                schema: j.schema.as_ref().map(|schema| self.join_package(schema)),
+                mode,
+                owner,
+                on_conflict: Default::default(),
             }),
         }
     }
 
+    /// Determine the header of a YAML or TOML file, placing the banner before any user header.
+    #[inline]
+    fn generated_header(&self, header: &Option<String>, auto_header: bool) -> Option<String> {
+        if !auto_header {
+            return header.clone();
+        }
+
+        let banner = CommentSyntax::Line("#").comment(&self.banner());
+        match header {
+            Some(header) => Some(format!("{}\n{}", banner, header)),
+            None => Some(banner),
+        }
+    }
+
     #[inline]
     fn get_file_dir(&self, df: &DirFile) -> Action<'g> {
-        let DirFile { dest, parents } = df;
+        let DirFile {
+            dest,
+            parents,
+            mode,
+            owner,
+            group,
+        } = df;
 
         let path = self.join_dest(dest);
         Action::Mkdir(MkdirAction {
             path,
             parents: *parents,
+            mode: mode.map(|Mode(mode)| mode),
+            owner: to_owner(owner, group),
+        })
+    }
+
+    #[inline]
+    fn get_file_systemd_unit(&self, sf: &SystemdUnitFile) -> Action<'g> {
+        let SystemdUnitFile {
+            src,
+            enable,
+            restart_on_change,
+        } = sf;
+
+        // Normalize src.
+        let src_w = self.join_package(src);
+        // Units are linked by file name into the user unit directory.
+        let name = src.file_name().unwrap_or_else(|| src.as_os_str());
+        let dest_w = self.join_dest(Path::new(".config/systemd/user").join(name));
+
+        Action::SystemdUnit(SystemdUnitAction {
+            src: src_w,
+            dest: dest_w,
+            enable: *enable,
+            restart_on_change: *restart_on_change,
+        })
+    }
+
+    #[inline]
+    fn get_file_fragment(&self, ff: &FragmentFile) -> Action<'g> {
+        let FragmentFile {
+            dir,
+            name,
+            contents,
+        } = ff;
+
+        let dir_w = self.join_dest(dir);
+        let prefix = self.fragment_prefix();
+
+        // Every fragment of the package in the same directory is kept, whether or not it is
+        // selected.
+        let keep = self
+            .all
+            .iter()
+            .filter_map(|drct| match drct {
+                Directive::File(File::Fragment(ff)) if self.join_dest(&ff.dir) == dir_w => {
+                    Some(format!("{}{}", prefix, ff.name))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Action::Fragment(FragmentAction {
+            dest: dir_w.join(format!("{}{}", prefix, name)),
+            dir: dir_w,
+            contents: contents.clone().into_bytes(),
+            prefix,
+            keep,
+        })
+    }
+
+    #[inline]
+    fn get_file_source_line(&self, sf: &SourceLineFile) -> Action<'g> {
+        let SourceLineFile { rc, line } = sf;
+
+        Action::SourceLine(SourceLineAction {
+            path: self.join_dest(rc),
+            line: line.clone(),
+            block: format!("shelf {}", self.package_name()),
+        })
+    }
+
+    #[inline]
+    fn get_file_defaults(&self, df: &DefaultsFile) -> Action<'g> {
+        let DefaultsFile { domain, key, value } = df;
+
+        Action::Defaults(DefaultsAction {
+            domain: domain.clone(),
+            key: key.clone(),
+            value: value.clone(),
+        })
+    }
+
+    #[inline]
+    fn get_file_plugin(&self, pf: &PluginFile) -> Action<'g> {
+        let PluginFile {
+            module,
+            kind,
+            args,
+            root,
+        } = pf;
+
+        Action::Plugin(PluginAction {
+            module: self.join_package(module),
+            kind: kind.clone(),
+            args: args.clone(),
+            src_root: self.join_package(root),
+            paths: self.paths.clone(),
+        })
+    }
+
+    #[inline]
+    fn get_file_expect(&self, ef: &ExpectFile) -> Action<'g> {
+        let ExpectFile { dest, expectation } = ef;
+
+        let expectation = match expectation {
+            Expectation::Symlink(target) => Expectation::Symlink(self.join_package(target)),
+            Expectation::File { contains } => Expectation::File {
+                contains: contains.clone(),
+            },
+        };
+        Action::Expect(ExpectAction {
+            dest: self.join_dest(dest),
+            expectation,
+        })
+    }
+
+    #[inline]
+    fn get_file_reg_value(&self, rf: &RegValueFile) -> Action<'g> {
+        let RegValueFile { key, name, value } = rf;
+
+        Action::RegValue(RegValueAction {
+            key: key.clone(),
+            name: name.clone(),
+            value: value.clone(),
         })
     }
 
@@ -228,6 +796,7 @@ impl<'g> ActionIter<'g> {
         match h {
             Hook::Cmd(cmd) => self.get_hook_cmd(cmd),
             Hook::Fun(fun) => self.get_hook_fun(fun),
+            Hook::Script(script) => self.get_hook_script(script),
         }
     }
 
@@ -244,6 +813,8 @@ impl<'g> ActionIter<'g> {
             stdout: _,
             stderr: _,
             nonzero_exit: _,
+            only_if_changed,
+            timeout,
         } = cmd;
 
         // Normalize start path.
@@ -253,17 +824,21 @@ impl<'g> ActionIter<'g> {
             .unwrap_or_else(|| self.path.to_path_buf());
 
         let command = command.clone();
-        // Use sh as default shell.
-        let shell = shell.clone().unwrap_or_else(|| "sh".to_string());
+        // Use the default shell if none was given.
+        let shell = shell.clone().unwrap_or_else(|| self.shell.clone());
         let clean_env = *clean_env.as_ref().unwrap_or(&false);
-        let env = env.clone().unwrap_or_default();
+        // Hook variables take precedence over package variables.
+        let mut env_w = self.env.clone();
+        env_w.extend(env.iter().flatten().map(|(k, v)| (k.clone(), v.clone())));
 
         Action::Command(CommandAction {
             command,
             start,
             shell,
             clean_env,
-            env,
+            env: env_w,
+            only_if_changed: *only_if_changed,
+            timeout: timeout.map(Duration::from_secs),
         })
     }
 
@@ -273,16 +848,92 @@ impl<'g> ActionIter<'g> {
             name,
             start,
             nonzero_exit: _,
+            only_if_changed,
         } = fun;
 
-        // Load function from Lua registry.
+        // Load function from Lua registry, and run it as a coroutine so that it can yield requests.
         let function: Function = self.lua.named_registry_value(name).unwrap();
+        let thread = self.lua.create_thread(function).unwrap();
+        let start = start
+            .as_ref()
+            .map(|start| self.join_package(start))
+            .unwrap_or_else(|| self.path.to_path_buf());
+
+        Action::Function(FunctionAction {
+            thread,
+            start,
+            env: self.env.clone(),
+            only_if_changed: *only_if_changed,
+            root: self.path.to_path_buf(),
+            paths: self.paths.clone(),
+            shell: self.shell.clone(),
+        })
+    }
+
+    #[inline]
+    fn get_hook_script(&self, script: &ScriptHook) -> Action<'g> {
+        let ScriptHook {
+            path,
+            args,
+            start,
+            only_if_changed,
+            timeout,
+        } = script;
+
+        // Normalize script and start paths.
+        let path = self.join_package(path);
         let start = start
             .as_ref()
             .map(|start| self.join_package(start))
             .unwrap_or_else(|| self.path.to_path_buf());
 
-        Action::Function(FunctionAction { function, start })
+        Action::Script(ScriptAction {
+            path,
+            args: args.clone(),
+            start,
+            env: self.env.clone(),
+            only_if_changed: *only_if_changed,
+            timeout: timeout.map(Duration::from_secs),
+        })
+    }
+
+    /// Return the managed-file banner for this package. Falls back to the package directory name
+    /// if the spec is unnamed.
+    #[inline]
+    fn banner(&self) -> String {
+        comment::managed_banner(&self.package_name())
+    }
+
+    /// Return the file name prefix of the fragments of this package, e.g. `shelf-name-`.
+    /// Characters of the package name that may be unsafe in file names are replaced.
+    #[inline]
+    fn fragment_prefix(&self) -> String {
+        let name: String = self
+            .package_name()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        format!("shelf-{}-", name)
+    }
+
+    /// Return the name of the package, falling back to the package directory name if the spec is
+    /// unnamed.
+    #[inline]
+    fn package_name(&self) -> Cow<'g, str> {
+        if self.name.is_empty() {
+            self.path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default()
+        } else {
+            self.name.into()
+        }
     }
 
     #[inline]
@@ -293,12 +944,18 @@ impl<'g> ActionIter<'g> {
         self.normalize_path(path, &self.path)
     }
 
+    /// Resolve the destination `path`, expanding `~` and environment variables in it. Paths that
+    /// don't expand are left as they are; see [`PackageData::check_dests`].
     #[inline]
     fn join_dest<P>(&self, path: P) -> PathBuf
     where
         P: AsRef<Path>,
     {
-        self.normalize_path(path, &self.dest)
+        let path = path.as_ref();
+        match self.paths.expand(path) {
+            Ok(expanded) => self.paths.join(expanded),
+            Err(_) => self.paths.join(path),
+        }
     }
 
     #[inline]
@@ -310,3 +967,22 @@ impl<'g> ActionIter<'g> {
         fse::clean(start.as_ref().join(path))
     }
 }
+
+/// Clone `values`, sorting keys if `sort_keys` is set.
+#[inline]
+fn sorted(values: &Object, sort_keys: bool) -> Object {
+    let mut values = values.clone();
+    if sort_keys {
+        values.sort_keys();
+    }
+    values
+}
+
+/// Collect the owning user and group of a file.
+#[inline]
+fn to_owner(owner: &Option<Uid>, group: &Option<Gid>) -> Owner {
+    Owner {
+        uid: owner.map(|Uid(uid)| uid),
+        gid: group.map(|Gid(gid)| gid),
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::action::Action;
+
+use super::{PackageGraph, PathResolver};
+
+/// How a package manages a destination path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimKind {
+    /// A single file.
+    File,
+    /// A directory symlinked as a whole.
+    DirLink,
+    /// A directory copied as a whole.
+    DirCopy,
+}
+
+/// A destination path managed by a package.
+#[derive(Debug, Clone)]
+pub struct Claim {
+    /// Absolute path of the package.
+    pub package: PathBuf,
+    /// Destination path.
+    pub dest: PathBuf,
+    pub kind: ClaimKind,
+}
+
+/// A conflict between destination paths managed by different packages.
+#[derive(Debug, Clone)]
+pub enum Conflict {
+    /// Both packages manage the same destination path.
+    SameDest { first: Claim, second: Claim },
+    /// The destination paths differ only in case, and collide on case-insensitive filesystems.
+    Case { first: Claim, second: Claim },
+    /// `dir` manages a directory as a whole (symlink or copy), and `inner` manages a path inside
+    /// it.
+    Nested { dir: Claim, inner: Claim },
+}
+
+impl PackageGraph {
+    /// Return the destination paths managed by all packages, in processing order. Hooks,
+    /// directory creation and trees (whose files are only known after resolution) are not
+    /// included.
+    #[inline]
+    pub fn claims<R>(&self, paths: R) -> Vec<Claim>
+    where
+        R: Into<PathResolver>,
+    {
+        let paths = paths.into();
+        let order = match self.order() {
+            Ok(order) => order,
+            Err(_) => return vec![],
+        };
+
+        order
+            .flat_map(|pd| {
+                pd.action_iter(&paths)
+                    .filter_map(|action| claim_dest(&action))
+                    .map(|(dest, kind)| Claim {
+                        package: pd.path.clone(),
+                        dest,
+                        kind,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Detect conflicts between destination paths managed by different packages.
+    #[inline]
+    pub fn conflicts<R>(&self, paths: R) -> Vec<Conflict>
+    where
+        R: Into<PathResolver>,
+    {
+        let claims = self.claims(paths);
+        let mut conflicts = Vec::new();
+
+        let mut by_dest: HashMap<&Path, &Claim> = HashMap::new();
+        let mut by_lower: HashMap<String, &Claim> = HashMap::new();
+        for claim in &claims {
+            match by_dest.get(claim.dest.as_path()) {
+                Some(first) if first.package != claim.package => {
+                    conflicts.push(Conflict::SameDest {
+                        first: (*first).clone(),
+                        second: claim.clone(),
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    by_dest.insert(&claim.dest, claim);
+                }
+            }
+
+            let lower = claim.dest.to_string_lossy().to_lowercase();
+            match by_lower.get(&lower) {
+                Some(first) if first.package != claim.package && first.dest != claim.dest => {
+                    conflicts.push(Conflict::Case {
+                        first: (*first).clone(),
+                        second: claim.clone(),
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    by_lower.insert(lower, claim);
+                }
+            }
+        }
+
+        let dirs = claims.iter().filter(|claim| claim.kind != ClaimKind::File);
+        for dir in dirs {
+            let nested = claims.iter().filter(|inner| {
+                inner.package != dir.package
+                    && inner.dest != dir.dest
+                    && inner.dest.starts_with(&dir.dest)
+            });
+            for inner in nested {
+                conflicts.push(Conflict::Nested {
+                    dir: dir.clone(),
+                    inner: inner.clone(),
+                });
+            }
+        }
+
+        conflicts
+    }
+}
+
+#[inline]
+fn claim_dest(action: &Action<'_>) -> Option<(PathBuf, ClaimKind)> {
+    let claim = match action {
+        Action::Link(action) => {
+            let kind = match (action.src.is_dir(), action.copy || action.hardlink) {
+                (true, false) => ClaimKind::DirLink,
+                (true, true) => ClaimKind::DirCopy,
+                (false, _) => ClaimKind::File,
+            };
+            (action.dest.clone(), kind)
+        }
+        Action::CopyDir(action) => (action.dest.clone(), ClaimKind::DirCopy),
+        Action::Write(action) => (action.dest.clone(), ClaimKind::File),
+        Action::Fragment(action) => (action.dest.clone(), ClaimKind::File),
+        Action::Handlebars(action) => (action.dest.clone(), ClaimKind::File),
+        Action::Liquid(action) => (action.dest.clone(), ClaimKind::File),
+        Action::Gotmpl(action) => (action.dest.clone(), ClaimKind::File),
+        Action::Pipe(action) => (action.dest.clone(), ClaimKind::File),
+        Action::Yaml(action) => (action.dest.clone(), ClaimKind::File),
+        Action::Toml(action) => (action.dest.clone(), ClaimKind::File),
+        Action::Json(action) => (action.dest.clone(), ClaimKind::File),
+        Action::SystemdUnit(action) => (action.dest.clone(), ClaimKind::File),
+        Action::Tree(_)
+        | Action::Mkdir(_)
+        // Lines may be added to the same rc file by several packages.
+        | Action::SourceLine(_)
+        | Action::SensitivePerms(_)
+        | Action::Defaults(_)
+        | Action::RegValue(_)
+        // Destinations of plugins are only known once they are run.
+        | Action::Plugin(_)
+        // Expectations only read their destinations.
+        | Action::Expect(_)
+        | Action::Command(_)
+        | Action::Function(_)
+        | Action::Script(_) => return None,
+    };
+
+    Some(claim)
+}
@@ -0,0 +1,95 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::action::Action;
+use crate::fse;
+
+use super::select::action_dest;
+use super::{PackageGraph, PathResolver};
+
+/// A destination path that lies inside a destination root, but whose real location does not,
+/// because one of its parent directories is a symlink that leads out of the root.
+#[derive(Debug, Clone)]
+pub struct Escape {
+    /// Absolute path of the package.
+    pub package: PathBuf,
+    /// Destination path.
+    pub dest: PathBuf,
+    /// Real path of the nearest existing parent directory of `dest`.
+    pub real: PathBuf,
+}
+
+impl PackageGraph {
+    /// Detect destination paths that lie inside the roots of `paths` or one of the `allowed`
+    /// roots, but whose nearest existing parent directory resolves to a real path outside of all
+    /// of them. Hooks are not included.
+    #[inline]
+    pub fn escapes<R>(&self, paths: R, allowed: &[PathBuf]) -> Vec<Escape>
+    where
+        R: Into<PathResolver>,
+    {
+        let paths = paths.into();
+        let order = match self.order() {
+            Ok(order) => order,
+            Err(_) => return vec![],
+        };
+
+        let roots: Vec<_> = paths
+            .roots()
+            .into_iter()
+            .chain(allowed.iter().map(fse::clean))
+            .collect();
+        let real_roots: Vec<_> = roots.iter().map(|root| real_path(root)).collect();
+
+        order
+            .flat_map(|pd| {
+                pd.action_iter(&paths)
+                    .filter_map(|action| escape_dest(&action).map(fse::clean))
+                    // Destinations outside of the roots were asked for explicitly.
+                    .filter(|dest| roots.iter().any(|root| dest.starts_with(root)))
+                    .filter_map(|dest| {
+                        let real = real_path(dest.parent()?);
+                        if real_roots.iter().any(|root| real.starts_with(root)) {
+                            None
+                        } else {
+                            Some(Escape {
+                                package: pd.path.clone(),
+                                dest,
+                                real,
+                            })
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+/// Return the destination of `action`, including the root directory of trees, or `None` for
+/// hooks.
+#[inline]
+fn escape_dest<'a>(action: &'a Action<'_>) -> Option<&'a Path> {
+    match action {
+        Action::Tree(action) => Some(&action.dest),
+        action => action_dest(action),
+    }
+}
+
+/// Resolve the symlinks in `path`. Since `path` may not exist yet, the nearest existing ancestor
+/// is resolved, and the rest is appended.
+#[inline]
+pub(super) fn real_path(path: &Path) -> PathBuf {
+    let mut existing = path;
+    loop {
+        if let Ok(real) = fs::canonicalize(existing) {
+            // SAFETY: `existing` is an ancestor of `path`.
+            let rest = path.strip_prefix(existing).unwrap();
+            return real.join(rest);
+        }
+
+        existing = match existing.parent() {
+            Some(parent) => parent,
+            None => return path.to_path_buf(),
+        };
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+
+use crate::action::Action;
+
+use super::escape::real_path;
+use super::{PackageGraph, PathResolver};
+
+/// A directive that can't be applied hermetically, because it runs something outside of shelf
+/// or reads files outside of its package and the destination roots.
+#[derive(Debug, Clone)]
+pub struct Unhermetic {
+    /// Absolute path of the package.
+    pub package: PathBuf,
+    /// What the directive does.
+    pub kind: UnhermeticKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum UnhermeticKind {
+    /// A hook is run.
+    Hook,
+    /// An external program is run, e.g. `systemctl`.
+    Program(&'static str),
+    /// A file outside of the package and the destination roots is read.
+    Read(PathBuf),
+}
+
+impl PackageGraph {
+    /// Detect directives that would run hooks or external programs, or read files from outside
+    /// their package and the roots of `paths`, so that untrusted packages can be audited before
+    /// anything is changed. Symlinks in source paths are resolved before they are checked.
+    #[inline]
+    pub fn unhermetic<R>(&self, paths: R) -> Vec<Unhermetic>
+    where
+        R: Into<PathResolver>,
+    {
+        let paths = paths.into();
+        let order = match self.order() {
+            Ok(order) => order,
+            Err(_) => return vec![],
+        };
+
+        let real_roots: Vec<_> = paths.roots().iter().map(|root| real_path(root)).collect();
+
+        order
+            .flat_map(|pd| {
+                let package = real_path(&pd.path);
+                let contained = |path: &Path| {
+                    let real = real_path(path);
+                    real.starts_with(&package)
+                        || real_roots.iter().any(|root| real.starts_with(root))
+                };
+
+                pd.action_iter(&paths)
+                    .flat_map(|action| unhermetic_kinds(&action))
+                    .filter(|kind| match kind {
+                        UnhermeticKind::Read(src) => !contained(src),
+                        _ => true,
+                    })
+                    .map(|kind| Unhermetic {
+                        package: pd.path.clone(),
+                        kind,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+/// Return what `action` does that may break hermeticity; reads are returned for every source,
+/// whether or not it is contained.
+#[inline]
+fn unhermetic_kinds(action: &Action<'_>) -> Vec<UnhermeticKind> {
+    let read = |src: &Path| UnhermeticKind::Read(src.to_path_buf());
+    match action {
+        Action::Command(_) | Action::Function(_) | Action::Script(_) => vec![UnhermeticKind::Hook],
+        Action::SystemdUnit(action) => {
+            vec![UnhermeticKind::Program("systemctl"), read(&action.src)]
+        }
+        Action::Defaults(_) => vec![UnhermeticKind::Program("defaults")],
+        Action::RegValue(_) => vec![UnhermeticKind::Program("reg")],
+        Action::Plugin(action) => vec![read(&action.module)],
+        Action::Link(action) => vec![read(&action.src)],
+        Action::Tree(action) => std::iter::once(&action.src)
+            .chain(&action.layers)
+            .map(|src| read(src))
+            .collect(),
+        Action::CopyDir(action) => vec![read(&action.src)],
+        Action::Handlebars(action) => std::iter::once(&action.src)
+            .chain(action.partials.values())
+            .map(|src| read(src))
+            .collect(),
+        Action::Liquid(action) => vec![read(&action.src)],
+        Action::Gotmpl(action) => vec![read(&action.src)],
+        Action::Pipe(action) => vec![read(&action.src)],
+        Action::Write(_)
+        | Action::Yaml(_)
+        | Action::Toml(_)
+        | Action::Json(_)
+        | Action::Mkdir(_)
+        | Action::Fragment(_)
+        | Action::SourceLine(_)
+        | Action::SensitivePerms(_)
+        | Action::Expect(_) => vec![],
+    }
+}
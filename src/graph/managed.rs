@@ -0,0 +1,42 @@
+use std::path::{Path, PathBuf};
+
+use super::select::action_dest;
+use super::{PackageGraph, PathResolver};
+
+/// A destination path managed by a package.
+#[derive(Debug, Clone)]
+pub struct Managed {
+    /// Absolute path of the package.
+    pub package: PathBuf,
+    /// Destination path.
+    pub dest: PathBuf,
+}
+
+impl PackageGraph {
+    /// Return the destinations of the directives of each package, in package order. The
+    /// destinations of the files in trees are only known once they are globbed, and are not
+    /// included.
+    #[inline]
+    pub fn managed<R>(&self, paths: R) -> Vec<Managed>
+    where
+        R: Into<PathResolver>,
+    {
+        let paths = paths.into();
+        let order = match self.order() {
+            Ok(order) => order,
+            Err(_) => return vec![],
+        };
+
+        order
+            .flat_map(|pd| {
+                pd.action_iter(&paths)
+                    .filter_map(|action| action_dest(&action).map(Path::to_path_buf))
+                    .map(|dest| Managed {
+                        package: pd.path.clone(),
+                        dest,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
@@ -1,8 +1,15 @@
 mod action;
+pub mod conflict;
+pub mod escape;
+pub mod hermetic;
+pub mod managed;
+mod paths;
+pub mod readonly;
+pub mod select;
 
 use std::collections::{
     hash_map::{self, DefaultHasher},
-    HashMap,
+    HashMap, HashSet,
 };
 use std::fmt;
 use std::hash::{Hash, Hasher};
@@ -13,12 +20,20 @@ use mlua::Lua;
 use petgraph::{
     algo,
     graphmap::{DiGraphMap, Nodes},
+    Direction,
 };
 
 use crate::fse;
 use crate::spec::{Dep, Spec};
 
-pub use self::action::ActionIter;
+pub use self::action::{ActionIter, CheckedActionIter};
+pub use self::conflict::{Claim, ClaimKind, Conflict};
+pub use self::escape::Escape;
+pub use self::hermetic::{Unhermetic, UnhermeticKind};
+pub use self::managed::Managed;
+pub use self::paths::PathResolver;
+pub use self::readonly::ReadOnly;
+pub use self::select::{DestFilter, Selector};
 
 pub struct PackageData {
     /// Absolute path of the package.
@@ -180,6 +195,86 @@ impl PackageGraph {
             && self.graph.contains_edge(pid, id)
     }
 
+    /// Returns the paths of the packages that directly depend on the package.
+    #[inline]
+    pub fn dependents<P>(&self, path: P) -> Vec<&Path>
+    where
+        P: AsRef<Path>,
+    {
+        let id = self.keyid(&path);
+        if !self.graph.contains_node(id) {
+            return Vec::new();
+        }
+
+        self.graph
+            .neighbors_directed(id, Direction::Incoming)
+            .filter_map(|pid| self.datamap.get(&pid))
+            .map(|data| data.path.as_path())
+            .collect()
+    }
+
+    /// Returns the paths of the given packages and every package they transitively depend on.
+    /// Paths not in the graph are ignored.
+    #[inline]
+    pub fn reachable<I, P>(&self, roots: I) -> HashSet<PathBuf>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut stack: Vec<_> = roots
+            .into_iter()
+            .map(|path| self.keyid(&path))
+            .filter(|id| self.graph.contains_node(*id))
+            .collect();
+
+        let mut seen = HashSet::new();
+        while let Some(id) = stack.pop() {
+            if seen.insert(id) {
+                stack.extend(self.graph.neighbors_directed(id, Direction::Outgoing));
+            }
+        }
+
+        seen.iter()
+            .filter_map(|id| self.datamap.get(id))
+            .map(|data| data.path.clone())
+            .collect()
+    }
+
+    /// Moves the packages for which `pred` returns true into a new graph, keeping the dependency
+    /// relations between them. Relations between moved and remaining packages are dropped.
+    #[inline]
+    pub fn split_off<F>(&mut self, mut pred: F) -> Self
+    where
+        F: FnMut(&PackageData) -> bool,
+    {
+        let ids: HashSet<_> = self
+            .datamap
+            .iter()
+            .filter(|(_, data)| pred(data))
+            .map(|(id, _)| *id)
+            .collect();
+        let edges: Vec<_> = self
+            .graph
+            .all_edges()
+            .filter(|(a, b, _)| ids.contains(a) && ids.contains(b))
+            .map(|(a, b, _)| (a, b))
+            .collect();
+
+        let mut other = Self::new();
+        for id in &ids {
+            // SAFETY: The ids were taken from the map.
+            let data = self.datamap.remove(id).unwrap();
+            self.graph.remove_node(*id);
+            other.graph.add_node(*id);
+            other.datamap.insert(*id, data);
+        }
+        for (a, b) in edges {
+            other.graph.add_edge(a, b, ());
+        }
+
+        other
+    }
+
     /// Clears all packages and dependency relations from the graph.
     #[inline]
     pub fn clear(&mut self) {
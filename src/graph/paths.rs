@@ -0,0 +1,183 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::fse::{
+    self,
+    expand::{self, ExpandError, UnknownVars},
+};
+
+/// Subdirectory of the home directory that is the default XDG config directory.
+const CONFIG_DIR: &str = ".config";
+/// Subdirectory of the home directory that is the default XDG data directory.
+const DATA_DIR: &str = ".local/share";
+
+/// Resolver of destination paths. Relative destinations are joined to the home directory,
+/// except that those in the default XDG config and data directories (`.config` and
+/// `.local/share`) are moved to the overriding directories, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathResolver {
+    home: PathBuf,
+    config_home: Option<PathBuf>,
+    data_home: Option<PathBuf>,
+    /// What to do with unset environment variables in destinations. See [`PathResolver::expand`].
+    unknown_vars: UnknownVars,
+}
+
+impl PathResolver {
+    /// Create a resolver of destinations relative to the absolute `home`.
+    #[inline]
+    pub fn new<P>(home: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            home: fse::clean(home),
+            config_home: None,
+            data_home: None,
+            unknown_vars: UnknownVars::default(),
+        }
+    }
+
+    /// Override the XDG config directory, which is `home/.config` by default.
+    #[inline]
+    pub fn with_config_home<P>(mut self, config_home: Option<P>) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.config_home = config_home.map(fse::clean);
+        self
+    }
+
+    /// Override the XDG data directory, which is `home/.local/share` by default.
+    #[inline]
+    pub fn with_data_home<P>(mut self, data_home: Option<P>) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.data_home = data_home.map(fse::clean);
+        self
+    }
+
+    /// Set what to do with unset environment variables in destinations, which is to error by
+    /// default.
+    #[inline]
+    pub fn with_unknown_vars(mut self, unknown_vars: UnknownVars) -> Self {
+        self.unknown_vars = unknown_vars;
+        self
+    }
+
+    #[inline]
+    pub fn unknown_vars(&self) -> UnknownVars {
+        self.unknown_vars
+    }
+
+    #[inline]
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    #[inline]
+    pub fn config_home(&self) -> PathBuf {
+        self.config_home
+            .clone()
+            .unwrap_or_else(|| self.home.join(CONFIG_DIR))
+    }
+
+    #[inline]
+    pub fn data_home(&self) -> PathBuf {
+        self.data_home
+            .clone()
+            .unwrap_or_else(|| self.home.join(DATA_DIR))
+    }
+
+    /// Return the roots of the destinations: the home directory, and the overriding XDG
+    /// directories that aren't in it.
+    #[inline]
+    pub fn roots(&self) -> Vec<PathBuf> {
+        let overrides = self.config_home.iter().chain(self.data_home.iter());
+        std::iter::once(&self.home)
+            .chain(overrides.filter(|dir| !dir.starts_with(&self.home)))
+            .cloned()
+            .collect()
+    }
+
+    /// Expand `~` and environment variables in the destination `path`. See [`expand::expand`].
+    /// `HOME`, `XDG_CONFIG_HOME`, and `XDG_DATA_HOME` are the destination directories, which need
+    /// not be those of the environment.
+    #[inline]
+    pub fn expand<P>(&self, path: P) -> Result<PathBuf, ExpandError>
+    where
+        P: AsRef<Path>,
+    {
+        let lookup = |name: &str| match name {
+            "HOME" => Some(self.home.clone().into_os_string()),
+            "XDG_CONFIG_HOME" => Some(self.config_home().into_os_string()),
+            "XDG_DATA_HOME" => Some(self.data_home().into_os_string()),
+            _ => env::var_os(name),
+        };
+        expand::expand(path.as_ref(), lookup, self.unknown_vars)
+    }
+
+    /// Resolve the destination `path`. Absolute paths are only normalized.
+    #[inline]
+    pub fn join<P>(&self, path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let joined = fse::clean(self.home.join(path));
+        if path.is_absolute() {
+            joined
+        } else {
+            self.remap(joined)
+        }
+    }
+
+    /// Move the absolute `path` into the overriding XDG directories if it lies in one of the
+    /// default ones.
+    #[inline]
+    pub fn remap<P>(&self, path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let overrides = [(CONFIG_DIR, &self.config_home), (DATA_DIR, &self.data_home)];
+        for (dir, over) in overrides {
+            if let Some(over) = over {
+                if let Ok(rest) = path.strip_prefix(self.home.join(dir)) {
+                    return fse::clean(over.join(rest));
+                }
+            }
+        }
+
+        path.to_path_buf()
+    }
+}
+
+impl From<&Path> for PathResolver {
+    #[inline]
+    fn from(home: &Path) -> Self {
+        Self::new(home)
+    }
+}
+
+impl From<&PathBuf> for PathResolver {
+    #[inline]
+    fn from(home: &PathBuf) -> Self {
+        Self::new(home)
+    }
+}
+
+impl From<PathBuf> for PathResolver {
+    #[inline]
+    fn from(home: PathBuf) -> Self {
+        Self::new(home)
+    }
+}
+
+impl From<&PathResolver> for PathResolver {
+    #[inline]
+    fn from(paths: &PathResolver) -> Self {
+        paths.clone()
+    }
+}
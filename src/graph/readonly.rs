@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::escape::real_path;
+use super::select::action_dest;
+use super::{PackageGraph, PathResolver};
+
+/// A destination path that can't be written because it lies on a read-only mount.
+#[derive(Debug, Clone)]
+pub struct ReadOnly {
+    /// Absolute path of the package.
+    pub package: PathBuf,
+    /// Destination path.
+    pub dest: PathBuf,
+    /// Mount point of the read-only filesystem.
+    pub mount: PathBuf,
+}
+
+impl PackageGraph {
+    /// Detect destination paths that lie on read-only mounts, so that processing can fail before
+    /// anything is changed. Hooks are not included.
+    ///
+    /// Mounts are only known on Linux; elsewhere, nothing is detected.
+    #[inline]
+    pub fn read_only<R>(&self, paths: R) -> Vec<ReadOnly>
+    where
+        R: Into<PathResolver>,
+    {
+        let paths = paths.into();
+        let order = match self.order() {
+            Ok(order) => order,
+            Err(_) => return vec![],
+        };
+
+        let mounts = mounts();
+        if !mounts.iter().any(|mount| mount.read_only) {
+            return vec![];
+        }
+
+        order
+            .flat_map(|pd| {
+                pd.action_iter(&paths)
+                    .filter_map(|action| action_dest(&action).map(Path::to_path_buf))
+                    .filter_map(|dest| {
+                        let real = real_path(&dest);
+                        // The mount with the longest mount point contains the path.
+                        let mount = mounts
+                            .iter()
+                            .filter(|mount| real.starts_with(&mount.path))
+                            .max_by_key(|mount| mount.path.components().count())?;
+
+                        if mount.read_only {
+                            Some(ReadOnly {
+                                package: pd.path.clone(),
+                                dest,
+                                mount: mount.path.clone(),
+                            })
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Mount {
+    path: PathBuf,
+    read_only: bool,
+}
+
+/// Return the mounted filesystems, as listed in `/proc/self/mounts`.
+#[inline]
+fn mounts() -> Vec<Mount> {
+    let table = match fs::read_to_string("/proc/self/mounts") {
+        Ok(table) => table,
+        Err(_) => return vec![],
+    };
+
+    table
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let path = unescape(fields.nth(1)?);
+            let read_only = fields.nth(1)?.split(',').any(|opt| opt == "ro");
+
+            Some(Mount {
+                path: path.into(),
+                read_only,
+            })
+        })
+        .collect()
+}
+
+/// Decode the octal escapes (e.g. `\040` for a space) of a mount table field.
+#[inline]
+fn unescape(field: &str) -> String {
+    let mut res = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let code: String = chars.by_ref().take(3).collect();
+            match u8::from_str_radix(&code, 8) {
+                Ok(byte) => res.push(byte as char),
+                Err(_) => {
+                    res.push(c);
+                    res.push_str(&code);
+                }
+            }
+        } else {
+            res.push(c);
+        }
+    }
+
+    res
+}
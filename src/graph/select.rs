@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+
+use glob::{Pattern, PatternError};
+
+use crate::action::template::Engine;
+use crate::action::Action;
+use crate::graph::PathResolver;
+use crate::spec::{
+    Directive, File, GeneratedFileTyp, Hook, LinkType, RegularFile, TemplatedFileType,
+};
+
+/// Selection of a subset of the directives of a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    /// Directives of a kind, named after the spec method that declares them (e.g. `tree`, `hbs`)
+    /// or its category (e.g. `template`, `generated`, `hook`).
+    Kind(String),
+    /// The directive at a 1-based index, in order of declaration.
+    Index(usize),
+}
+
+impl Selector {
+    /// Return true if the directive `drct`, declared at 0-based `index`, is selected.
+    #[inline]
+    pub fn matches(&self, index: usize, drct: &Directive) -> bool {
+        match self {
+            Self::Kind(kind) => directive_kinds(drct).contains(&kind.as_str()),
+            Self::Index(n) => index + 1 == *n,
+        }
+    }
+}
+
+/// Names by which a directive can be selected.
+#[inline]
+pub fn directive_kinds(drct: &Directive) -> &'static [&'static str] {
+    match drct {
+        Directive::File(f) => match f {
+            File::Regular(RegularFile { link_type, .. }) => match link_type {
+                LinkType::Link => &["file", "link"],
+                LinkType::Copy => &["file", "copy"],
+                LinkType::Hardlink => &["file", "hardlink"],
+                LinkType::Auto => &["file", "link"],
+            },
+            File::Templated(tf) => match tf.typ {
+                TemplatedFileType::Handlebars(_) => &["template", "hbs"],
+                TemplatedFileType::Liquid(_) => &["template", "liquid"],
+                TemplatedFileType::Gotmpl(_) => &["template", "gotmpl"],
+                TemplatedFileType::Auto(_) => match Engine::from_path(&tf.src) {
+                    Some(Engine::Handlebars) => &["template", "hbs"],
+                    Some(Engine::Liquid) => &["template", "liquid"],
+                    Some(Engine::Gotmpl) => &["template", "gotmpl"],
+                    None => &["template"],
+                },
+            },
+            File::Piped(_) => &["pipe"],
+            File::Tree(_) => &["tree"],
+            File::CopyDir(_) => &["copy_dir"],
+            File::Generated(gf) => match gf.typ {
+                GeneratedFileTyp::Empty(_) => &["generated", "empty"],
+                GeneratedFileTyp::String(_) => &["generated", "str"],
+                GeneratedFileTyp::Yaml(_) => &["generated", "yaml"],
+                GeneratedFileTyp::Toml(_) => &["generated", "toml"],
+                GeneratedFileTyp::Json(_) => &["generated", "json"],
+            },
+            File::Dir(_) => &["mkdir"],
+            File::SystemdUnit(_) => &["systemd_user_unit"],
+            File::Fragment(_) => &["fragment"],
+            File::SourceLine(_) => &["source_line"],
+            File::Defaults(_) => &["defaults"],
+            File::RegValue(_) => &["regvalue"],
+            File::Plugin(_) => &["plugin"],
+            File::Expect(_) => &["expect"],
+        },
+        Directive::Hook(h) => match h {
+            Hook::Cmd(_) => &["hook", "cmd"],
+            Hook::Fun(_) => &["hook", "fn"],
+            Hook::Script(_) => &["hook", "script"],
+        },
+    }
+}
+
+/// Split a package target of the form `path`, `path:kind` or `path#index` into the package path
+/// and its directive selector.
+///
+/// A `:kind` suffix is only recognized if `kind` is a known directive kind, and a `#index` suffix
+/// only if `index` is a positive integer; otherwise, the whole target is taken as the path.
+#[inline]
+pub fn parse_target(target: &str) -> (PathBuf, Option<Selector>) {
+    if let Some((path, kind)) = target.rsplit_once(':') {
+        if !path.is_empty() && KINDS.contains(&kind) {
+            return (path.into(), Some(Selector::Kind(kind.to_string())));
+        }
+    }
+
+    if let Some((path, index)) = target.rsplit_once('#') {
+        match index.parse() {
+            Ok(index) if !path.is_empty() && index > 0 => {
+                return (path.into(), Some(Selector::Index(index)));
+            }
+            _ => {}
+        }
+    }
+
+    (target.into(), None)
+}
+
+/// All directive kind names. See [`directive_kinds`].
+static KINDS: &[&str] = &[
+    "file",
+    "link",
+    "copy",
+    "template",
+    "hbs",
+    "liquid",
+    "gotmpl",
+    "pipe",
+    "tree",
+    "copy_dir",
+    "generated",
+    "empty",
+    "str",
+    "yaml",
+    "toml",
+    "json",
+    "mkdir",
+    "systemd_user_unit",
+    "fragment",
+    "source_line",
+    "defaults",
+    "regvalue",
+    "plugin",
+    "expect",
+    "hook",
+    "cmd",
+    "fn",
+    "script",
+];
+
+/// Filter of actions by destination glob patterns.
+#[derive(Debug, Clone)]
+pub struct DestFilter {
+    patterns: Vec<Pattern>,
+}
+
+impl DestFilter {
+    /// Compile the glob `patterns`, which are destinations resolved by `paths`.
+    #[inline]
+    pub fn new<R>(paths: R, patterns: &[String]) -> Result<Self, PatternError>
+    where
+        R: Into<PathResolver>,
+    {
+        let paths = paths.into();
+        let patterns = patterns
+            .iter()
+            .map(|pat| Pattern::new(&paths.join(pat).to_string_lossy()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { patterns })
+    }
+
+    /// Create a filter that matches only the absolute `path`.
+    #[inline]
+    pub fn exact<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let pattern = Pattern::escape(&path.as_ref().to_string_lossy());
+        // SAFETY: Escaped patterns are valid.
+        let pattern = Pattern::new(&pattern).unwrap();
+        Self {
+            patterns: vec![pattern],
+        }
+    }
+
+    /// Return true if the absolute `path` matches any of the patterns.
+    #[inline]
+    pub fn matches<P>(&self, path: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.patterns.iter().any(|pat| pat.matches_path(path))
+    }
+
+    /// Restrict `action` to the matching destinations. Returns `None` if nothing in it matches.
+    ///
+    /// Trees are narrowed to their matching files during resolution; every other action is kept
+    /// or dropped as a whole. Hooks have no destination, and are always dropped.
+    #[inline]
+    pub fn filter<'lua>(&self, action: Action<'lua>) -> Option<Action<'lua>> {
+        if let Action::Tree(mut tree) = action {
+            tree.only = Some(self.clone());
+            return Some(Action::Tree(tree));
+        }
+
+        match action_dest(&action) {
+            Some(dest) if self.matches(dest) => Some(action),
+            _ => None,
+        }
+    }
+}
+
+/// Return the destination of `action`, or `None` for trees and hooks.
+#[inline]
+pub(super) fn action_dest<'a>(action: &'a Action<'_>) -> Option<&'a Path> {
+    let dest = match action {
+        Action::Link(action) => &action.dest,
+        Action::Write(action) => &action.dest,
+        Action::CopyDir(action) => &action.dest,
+        Action::Handlebars(action) => &action.dest,
+        Action::Liquid(action) => &action.dest,
+        Action::Gotmpl(action) => &action.dest,
+        Action::Pipe(action) => &action.dest,
+        Action::Yaml(action) => &action.dest,
+        Action::Toml(action) => &action.dest,
+        Action::Json(action) => &action.dest,
+        Action::Mkdir(action) => &action.path,
+        Action::SystemdUnit(action) => &action.dest,
+        Action::Fragment(action) => &action.dest,
+        Action::SourceLine(action) => &action.path,
+        Action::SensitivePerms(action) => &action.dest,
+        Action::Tree(_)
+        | Action::Defaults(_)
+        | Action::RegValue(_)
+        | Action::Plugin(_)
+        | Action::Expect(_)
+        | Action::Command(_)
+        | Action::Function(_)
+        | Action::Script(_) => return None,
+    };
+
+    Some(dest)
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::stamp::Line;
+use super::writer::{write_record, ReadError, WriteError};
+use super::{Journal, Record, RotatingFile};
+
+/// Datum that is indexed by a path in a [`JournalIndex`].
+pub trait Indexed {
+    /// Return the path under which the datum is indexed, if any.
+    fn index_key(&self) -> Option<&Path>;
+}
+
+/// Compact index of a journal written to disk, mapping paths to the byte offset of the latest
+/// record indexed under them, so that the record can be looked up without scanning the journal.
+///
+/// On disk, the index is a log of entries, one per line, that is appended to whenever records are
+/// written with [`Journal::write_indexed`]. Later entries override earlier ones; see
+/// [`JournalIndex::compact`] to rewrite it with only the latest entries.
+///
+/// The index of a [`RotatingFile`] is kept at [`RotatingFile::index_path`], and its offsets count
+/// from the start of the oldest segment, as if the segments were a single file. It is appended to
+/// by [`Journal::write_rotated_indexed`], and must be rebuilt with [`JournalIndex::rebuild`] when
+/// the file is rotated.
+#[derive(Debug, Clone, Default)]
+pub struct JournalIndex {
+    offsets: HashMap<PathBuf, u64>,
+}
+
+impl JournalIndex {
+    /// Create a new, empty index.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the byte offset of the latest record indexed under `path`.
+    #[inline]
+    pub fn get<P>(&self, path: P) -> Option<u64>
+    where
+        P: AsRef<Path>,
+    {
+        self.offsets.get(path.as_ref()).copied()
+    }
+
+    /// Return the number of indexed paths.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Return true if no paths are indexed.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Iterate over the indexed paths with the offsets of their latest records, in no particular
+    /// order.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, u64)> {
+        self.offsets
+            .iter()
+            .map(|(path, offset)| (path.as_path(), *offset))
+    }
+
+    /// Load an index previously written by [`Journal::write_indexed`] or
+    /// [`JournalIndex::compact`].
+    #[inline]
+    pub fn load<R>(r: R) -> Result<Self, ReadError>
+    where
+        R: Read,
+    {
+        let mut index = Self::new();
+        for line in BufReader::new(r).lines() {
+            let (path, offset): (PathBuf, u64) = serde_json::from_str(&line?)?;
+            index.offsets.insert(path, offset);
+        }
+
+        Ok(index)
+    }
+
+    /// Write the index with a single entry per path.
+    #[inline]
+    pub fn compact<W>(&self, mut w: W) -> Result<(), WriteError>
+    where
+        W: Write,
+    {
+        // Sort the entries to make the output deterministic.
+        let mut entries: Vec<_> = self.offsets.iter().collect();
+        entries.sort();
+        for (path, offset) in entries {
+            write_entry(path, *offset, &mut w)?;
+        }
+
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Load the index of the rotating journal `file`, returning `None` if it hasn't been written.
+    #[inline]
+    pub fn load_rotated(file: &RotatingFile) -> Result<Option<Self>, ReadError> {
+        match File::open(file.index_path()) {
+            Ok(r) => Self::load(r).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Build the index of the rotating journal `file` by reading all of its segments.
+    #[inline]
+    pub fn rebuild<T>(file: &RotatingFile) -> Result<Self, ReadError>
+    where
+        T: DeserializeOwned + Indexed,
+    {
+        let mut index = Self::new();
+        let mut offset = 0;
+        for segment in file.segments() {
+            let mut r = BufReader::new(File::open(segment)?);
+            let mut line = String::new();
+            loop {
+                line.clear();
+                let len = r.read_line(&mut line)?;
+                if len == 0 {
+                    break;
+                }
+
+                let line: Line<T> = serde_json::from_str(&line)?;
+                if let Record::Atom(datum) = line.into_record() {
+                    if let Some(path) = datum.index_key() {
+                        index.offsets.insert(path.to_path_buf(), offset);
+                    }
+                }
+                offset += len as u64;
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Replace the index of the rotating journal `file` with this one, written with a single
+    /// entry per path.
+    #[inline]
+    pub fn write_rotated(&self, file: &RotatingFile) -> Result<(), WriteError> {
+        // Write to a temporary file first, so that readers never see a partial index.
+        let path = file.index_path();
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        self.compact(BufWriter::new(File::create(&tmp_path)?))?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+}
+
+impl<T> Journal<T>
+where
+    T: Serialize + Indexed,
+{
+    /// Write the records from index `start` onwards to `w`, which should be positioned at byte
+    /// `offset` of the journal file, and append an entry to `iw` for each indexed record. `index`
+    /// is updated with the new entries. Returns the byte offset after the written records.
+    #[inline]
+    pub fn write_indexed<W, I>(
+        &self,
+        mut w: W,
+        start: usize,
+        offset: u64,
+        index: &mut JournalIndex,
+        mut iw: I,
+    ) -> Result<u64, WriteError>
+    where
+        W: Write,
+        I: Write,
+    {
+        let mut offset = offset;
+        for idx in start..self.size() {
+            let stamped = self.line(idx).unwrap();
+            let record = stamped.record;
+            let mut line = serde_json::to_vec(&stamped)?;
+            line.push(b'\n');
+            w.write_all(&line)?;
+
+            if let Record::Atom(datum) = record {
+                if let Some(path) = datum.index_key() {
+                    write_entry(path, offset, &mut iw)?;
+                    index.offsets.insert(path.to_path_buf(), offset);
+                }
+            }
+
+            offset += line.len() as u64;
+        }
+
+        // Flush the journal before the index, so that the index never points past the journal.
+        w.flush()?;
+        iw.flush()?;
+        Ok(offset)
+    }
+
+    /// Append the records from index `start` onwards to the rotating journal `file` like
+    /// [`Journal::write_rotated`], and append an entry to its index for each indexed record.
+    /// `index` is updated with the new entries.
+    ///
+    /// Returns true if the file was rotated, in which case no entries are written, since rotating
+    /// moves and removes segments; the index must be rebuilt with [`JournalIndex::rebuild`].
+    #[inline]
+    pub fn write_rotated_indexed(
+        &self,
+        file: &RotatingFile,
+        start: usize,
+        index: &mut JournalIndex,
+    ) -> Result<bool, WriteError> {
+        if start >= self.size() {
+            return Ok(false);
+        }
+
+        if self.rotate_before(file, start)? {
+            let mut w = BufWriter::new(file.append()?);
+            for idx in start..self.size() {
+                let line = self.line(idx).unwrap();
+                write_record(&line, &mut w)?;
+            }
+            w.flush()?;
+            return Ok(true);
+        }
+
+        let offset = file.total_size()?;
+        let w = BufWriter::new(file.append()?);
+        let iw = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file.index_path())?;
+        self.write_indexed(w, start, offset, index, BufWriter::new(iw))?;
+        Ok(false)
+    }
+}
+
+impl<T> Journal<T>
+where
+    T: DeserializeOwned,
+{
+    /// Read the single record at byte `offset` of a written journal, e.g. one looked up in a
+    /// [`JournalIndex`].
+    #[inline]
+    pub fn read_at<R>(mut r: R, offset: u64) -> Result<Record<T>, ReadError>
+    where
+        R: BufRead + Seek,
+    {
+        r.seek(SeekFrom::Start(offset))?;
+
+        let mut line = String::new();
+        r.read_line(&mut line)?;
+        let line: Line<T> = serde_json::from_str(&line)?;
+        Ok(line.into_record())
+    }
+
+    /// Read the single record at byte `offset` of the rotating journal `file`, counting from the
+    /// start of its oldest segment, e.g. one looked up in its [`JournalIndex`].
+    #[inline]
+    pub fn read_rotated_at(file: &RotatingFile, offset: u64) -> Result<Record<T>, ReadError> {
+        let mut offset = offset;
+        for segment in file.segments() {
+            let size = fs::metadata(&segment)?.len();
+            if offset < size {
+                return Self::read_at(BufReader::new(File::open(segment)?), offset);
+            }
+            offset -= size;
+        }
+
+        let err = io::Error::new(io::ErrorKind::UnexpectedEof, "offset past end of journal");
+        Err(err.into())
+    }
+}
+
+#[inline]
+fn write_entry<W>(path: &Path, offset: u64, mut w: W) -> Result<(), WriteError>
+where
+    W: Write,
+{
+    serde_json::to_writer(&mut w, &(path, offset))?;
+    w.write_all(b"\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{Indexed, Journal, JournalIndex, Record};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Datum(Option<PathBuf>);
+
+    impl Indexed for Datum {
+        fn index_key(&self) -> Option<&Path> {
+            self.0.as_deref()
+        }
+    }
+
+    #[test]
+    fn test_write_indexed() -> Result<(), Box<dyn std::error::Error>> {
+        let a = || Datum(Some("a".into()));
+        let b = || Datum(Some("b".into()));
+
+        let mut journal = Journal::new();
+        {
+            let mut t = journal.lock();
+            t.append(a());
+            t.append(Datum(None));
+            t.append(b());
+        }
+
+        let (mut w, mut iw) = (Vec::new(), Vec::new());
+        let mut index = JournalIndex::new();
+        let offset = journal.write_indexed(&mut w, 0, 0, &mut index, &mut iw)?;
+        assert_eq!(w.len() as u64, offset);
+        assert_eq!(2, index.len());
+
+        // Append incrementally; the latest record for `a` wins.
+        let start = journal.size();
+        journal.lock().append(a());
+        let end = journal.write_indexed(&mut w, start, offset, &mut index, &mut iw)?;
+        assert_eq!(Some(offset), index.get("a"));
+        assert_eq!(w.len() as u64, end);
+
+        let loaded = JournalIndex::load(&iw[..])?;
+        assert_eq!(index.get("a"), loaded.get("a"));
+        assert_eq!(index.get("b"), loaded.get("b"));
+        assert_eq!(None, loaded.get("c"));
+
+        let mut compacted = Vec::new();
+        loaded.compact(&mut compacted)?;
+        assert_eq!(2, String::from_utf8(compacted)?.lines().count());
+
+        for (path, datum) in [("a", a()), ("b", b())] {
+            let offset = loaded.get(path).unwrap();
+            let record: Record<Datum> = Journal::read_at(Cursor::new(&w), offset)?;
+            assert_eq!(Record::Atom(datum), record);
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use std::slice;
 
-use super::{Journal, Record};
+use super::{Journal, Record, Stamp, Stamped};
 
 /// Iterator on a [`Journal`] that emits records from oldest to newest.
 #[derive(Debug)]
@@ -45,6 +45,46 @@ impl<'j, T> DoubleEndedIterator for Iter<'j, T> {
     }
 }
 
+/// Iterator on a [`Journal`] that emits records with their stamps from oldest to newest.
+#[derive(Debug)]
+pub struct StampedIter<'j, T> {
+    inner: std::iter::Zip<slice::Iter<'j, Record<T>>, slice::Iter<'j, Stamp>>,
+}
+
+impl<T> Journal<T> {
+    /// Create an iterator on this journal that includes stamps. See [`StampedIter`].
+    #[inline]
+    pub fn iter_stamped(&self) -> StampedIter<'_, T> {
+        StampedIter {
+            inner: self.records.iter().zip(self.stamps.iter()),
+        }
+    }
+}
+
+impl<'j, T> Iterator for StampedIter<'j, T> {
+    type Item = Stamped<&'j Record<T>>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(stamped)
+    }
+}
+
+impl<'j, T> DoubleEndedIterator for StampedIter<'j, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(stamped)
+    }
+}
+
+#[inline]
+fn stamped<'j, T>((record, stamp): (&'j Record<T>, &'j Stamp)) -> Stamped<&'j Record<T>> {
+    Stamped {
+        stamp: *stamp,
+        record,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::super::test::{Datum, BACKWARD, COMMIT, FORWARD};
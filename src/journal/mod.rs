@@ -1,11 +1,19 @@
+pub mod index;
 pub mod iter;
 pub mod rollback;
+pub mod rotate;
+pub mod stamp;
 pub mod transaction;
 pub mod writer;
 
+pub use self::index::{Indexed, JournalIndex};
 pub use self::rollback::{Rollback, RollbackIter};
+pub use self::rotate::{RotatePolicy, RotatingFile};
+pub use self::stamp::{Stamp, Stamped};
 pub use self::transaction::Transaction;
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 /// Record type to be recorded in a journal.
@@ -19,6 +27,12 @@ pub enum Record<T> {
 #[derive(Debug)]
 pub struct Journal<T> {
     records: Vec<Record<T>>,
+    /// Stamps of the records, at the same indices.
+    stamps: Vec<Stamp>,
+    /// Messages of commit records, by sequence number. See [`Journal::set_message`].
+    messages: BTreeMap<u64, String>,
+    /// Message of the commit records appended from now on, if any.
+    message: Option<String>,
 }
 
 impl<T> Journal<T> {
@@ -28,9 +42,26 @@ impl<T> Journal<T> {
     pub fn new() -> Self {
         Self {
             records: Vec::new(),
+            stamps: Vec::new(),
+            messages: BTreeMap::new(),
+            message: None,
         }
     }
 
+    /// Set the message of the commit records appended from now on, e.g. to describe why a run
+    /// made its changes, or stop attaching one if `None`.
+    #[inline]
+    pub fn set_message(&mut self, message: Option<String>) {
+        self.message = message;
+    }
+
+    /// Return the message of the commit record at the given index, if it has one.
+    #[inline]
+    pub fn message(&self, idx: usize) -> Option<&str> {
+        let stamp = self.stamps.get(idx)?;
+        self.messages.get(&stamp.seq).map(String::as_str)
+    }
+
     /// Return the number of records in the journal.
     #[inline]
     pub fn size(&self) -> usize {
@@ -78,11 +109,23 @@ impl<T> Journal<T> {
         &self.records
     }
 
-    /// Append a new record to the journal.
+    /// Append a new record to the journal, stamped with the next sequence number and the current
+    /// time.
     #[inline]
     pub(self) fn append(&mut self, record: Record<T>) {
+        let stamp = self.next_stamp();
+        if let (Record::Commit, Some(message)) = (&record, &self.message) {
+            self.messages.insert(stamp.seq, message.clone());
+        }
+        self.append_stamped(record, stamp);
+    }
+
+    /// Append a new record to the journal with the given stamp.
+    #[inline]
+    pub(self) fn append_stamped(&mut self, record: Record<T>, stamp: Stamp) {
         // Push the record.
         self.records.push(record);
+        self.stamps.push(stamp);
     }
 }
 
@@ -0,0 +1,313 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Serialize,
+};
+
+use super::stamp::Line;
+use super::writer::{read_into, write_record, ReadError, WriteError};
+use super::{Journal, Record};
+
+/// Limits on the segments of a [`RotatingFile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RotatePolicy {
+    /// Size in bytes past which the current segment is rotated.
+    pub max_size: u64,
+    /// Maximum number of segments, including the current one; the oldest are removed first.
+    pub max_files: usize,
+}
+
+impl Default for RotatePolicy {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_size: 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+/// Journal file that is split into segments: records are appended to `path` (e.g.
+/// `journal.jsonl`), which is rotated to `path.1` when it grows too large, pushing older
+/// segments to `path.2`, `path.3`, and so on.
+///
+/// Segments are only rotated between transactions, so that each transaction is contained in a
+/// single segment, and the segment being written to is never removed; the latest transactions
+/// can therefore always be rolled back.
+#[derive(Debug, Clone)]
+pub struct RotatingFile {
+    path: PathBuf,
+    policy: RotatePolicy,
+}
+
+impl RotatingFile {
+    #[inline]
+    pub fn new<P>(path: P, policy: RotatePolicy) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            policy,
+        }
+    }
+
+    /// Return the path of the current segment.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the path of the `n`th segment, where the current segment is 0.
+    #[inline]
+    pub fn segment(&self, n: usize) -> PathBuf {
+        if n == 0 {
+            self.path.clone()
+        } else {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            name.into()
+        }
+    }
+
+    /// Return the path of the index of the journal. See [`super::JournalIndex`].
+    #[inline]
+    pub fn index_path(&self) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(".index");
+        name.into()
+    }
+
+    /// Return the paths of the existing segments, oldest first.
+    #[inline]
+    pub fn segments(&self) -> Vec<PathBuf> {
+        let mut segments: Vec<_> = (1..=self.rotated())
+            .rev()
+            .map(|n| self.segment(n))
+            .collect();
+        if self.path.exists() {
+            segments.push(self.path.clone());
+        }
+        segments
+    }
+
+    /// Return the total size of the existing segments in bytes.
+    #[inline]
+    pub(super) fn total_size(&self) -> io::Result<u64> {
+        self.segments()
+            .iter()
+            .map(|segment| fs::metadata(segment).map(|metadata| metadata.len()))
+            .sum()
+    }
+
+    /// Return true if the journal is empty or its last record is a commit, i.e. it doesn't end
+    /// with uncommitted atoms. Only the end of the newest segment is read.
+    #[inline]
+    pub fn ends_committed(&self) -> Result<bool, ReadError> {
+        let line = match self.segments().pop() {
+            Some(segment) => last_line(&segment)?,
+            None => None,
+        };
+        // Segments are only rotated after commits, so an empty segment follows one.
+        let line = match line {
+            Some(line) => line,
+            None => return Ok(true),
+        };
+
+        let line: Line<IgnoredAny> = serde_json::from_str(&line)?;
+        Ok(matches!(line.into_record(), Record::Commit))
+    }
+
+    /// Return the number of rotated segments. The current segment may not exist, e.g. right
+    /// after rotating.
+    #[inline]
+    fn rotated(&self) -> usize {
+        (1..).take_while(|&n| self.segment(n).exists()).count()
+    }
+
+    /// Open the current segment for appending, creating it if it doesn't exist.
+    #[inline]
+    pub(super) fn append(&self) -> io::Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+    }
+
+    /// Shift every segment back by one, leaving no current segment, and remove the segments past
+    /// the limit.
+    #[inline]
+    fn rotate(&self) -> io::Result<()> {
+        let max_files = self.policy.max_files.max(1);
+        let rotated = self.rotated();
+
+        // Segments that would be pushed past the limit are removed.
+        for n in (max_files - 1).max(1)..=rotated {
+            fs::remove_file(self.segment(n))?;
+        }
+        for n in (1..(max_files - 1).min(rotated + 1)).rev() {
+            fs::rename(self.segment(n), self.segment(n + 1))?;
+        }
+        if max_files > 1 {
+            fs::rename(&self.path, self.segment(1))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Return the last line of the file at `path`, reading backwards from its end.
+#[inline]
+fn last_line(path: &Path) -> io::Result<Option<String>> {
+    const CHUNK_SIZE: u64 = 4096;
+
+    let mut file = File::open(path)?;
+    // Leave out the trailing newline.
+    let mut end = file.metadata()?.len().saturating_sub(1);
+    let mut line = Vec::new();
+    loop {
+        let start = end.saturating_sub(CHUNK_SIZE);
+        let mut chunk = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+
+        let newline = chunk.iter().rposition(|&b| b == b'\n');
+        chunk.append(&mut line);
+        line = chunk;
+        match newline {
+            Some(idx) => {
+                line.drain(..=idx);
+                break;
+            }
+            None if start == 0 => break,
+            None => end = start,
+        }
+    }
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+impl<T> Journal<T>
+where
+    T: Serialize,
+{
+    /// Append the records from index `start` onwards to the current segment of `file`, rotating
+    /// it first if it has grown past the size limit and `start` begins a new transaction.
+    /// Returns true if the file was rotated, in which case byte offsets into the previous
+    /// segment (e.g. in a [`super::JournalIndex`]) no longer refer to it.
+    #[inline]
+    pub fn write_rotated(&self, file: &RotatingFile, start: usize) -> Result<bool, WriteError> {
+        if start >= self.size() {
+            return Ok(false);
+        }
+
+        let rotated = self.rotate_before(file, start)?;
+
+        let mut w = BufWriter::new(file.append()?);
+        for idx in start..self.size() {
+            let line = self.line(idx).unwrap();
+            write_record(&line, &mut w)?;
+        }
+        w.flush()?;
+
+        Ok(rotated)
+    }
+
+    /// Rotate `file` if it has grown past the size limit and `start` begins a new transaction,
+    /// returning true if it was rotated.
+    #[inline]
+    pub(super) fn rotate_before(&self, file: &RotatingFile, start: usize) -> io::Result<bool> {
+        let size = match fs::metadata(file.path()) {
+            Ok(metadata) => metadata.len(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        let boundary = start == 0 || matches!(self.get(start - 1), Some(Record::Commit));
+        let rotated = size > file.policy.max_size && boundary;
+        if rotated {
+            file.rotate()?;
+        }
+
+        Ok(rotated)
+    }
+}
+
+impl<T> Journal<T>
+where
+    T: DeserializeOwned,
+{
+    /// Load the records of all the segments of `file`, oldest first, as if they were a single
+    /// journal.
+    #[inline]
+    pub fn load_rotated(file: &RotatingFile) -> Result<Self, ReadError> {
+        let mut journal = Journal::new();
+        for segment in file.segments() {
+            read_into(&mut journal, File::open(segment)?)?;
+        }
+
+        Ok(journal)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::super::test::{Datum, BACKWARD, COMMIT, FORWARD};
+    use super::{Journal, RotatePolicy, RotatingFile};
+
+    #[test]
+    fn test_rotate() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new()?;
+        let policy = RotatePolicy {
+            max_size: 1,
+            max_files: 3,
+        };
+        let file = RotatingFile::new(dir.path().join("journal.jsonl"), policy);
+
+        let mut journal = Journal::new();
+        for i in 0..4 {
+            // Each transaction after the first starts a new segment.
+            let start = journal.size();
+            journal.append(FORWARD);
+            assert_eq!(i > 0, journal.write_rotated(&file, start)?);
+
+            // A transaction is never split across segments.
+            let start = journal.size();
+            journal.append(BACKWARD);
+            journal.append(COMMIT);
+            assert!(!journal.write_rotated(&file, start)?);
+        }
+
+        // Only the newest segments are kept.
+        assert_eq!(
+            vec![file.segment(2), file.segment(1), file.segment(0)],
+            file.segments()
+        );
+        assert!(!file.segment(3).exists());
+
+        // Segments are read back in order, keeping the stamps of the records.
+        let loaded: Journal<Datum> = Journal::load_rotated(&file)?;
+        assert_eq!(&journal.records()[3..], loaded.records());
+        assert_eq!(journal.stamp(3), loaded.stamp(0));
+
+        // Rotated segments are still read without a current segment.
+        fs::remove_file(file.segment(0))?;
+        let loaded: Journal<Datum> = Journal::load_rotated(&file)?;
+        assert_eq!(&journal.records()[3..9], loaded.records());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Journal, Record};
+
+/// When a record was appended to a [`Journal`].
+///
+/// # Ordering
+///
+/// `seq` strictly increases in the order that records are appended, including across journals
+/// that are written and loaded again, so it is the authoritative order of records. `time` is the
+/// wall-clock time of appending and is only informational: the clock may be adjusted between
+/// appends, so times are not guaranteed to be monotonic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct Stamp {
+    /// Sequence number of the record.
+    pub seq: u64,
+    /// Time at which the record was appended, or `None` if the record was loaded from a journal
+    /// written before records were timestamped.
+    pub time: Option<SystemTime>,
+}
+
+/// A record together with its [`Stamp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct Stamped<R> {
+    #[serde(flatten)]
+    pub stamp: Stamp,
+    pub record: R,
+}
+
+impl<R> Stamped<R> {
+    /// Map the wrapped record with `f`, keeping the stamp.
+    #[inline]
+    pub fn map<S, F>(self, f: F) -> Stamped<S>
+    where
+        F: FnOnce(R) -> S,
+    {
+        Stamped {
+            stamp: self.stamp,
+            record: f(self.record),
+        }
+    }
+}
+
+/// A line of a written journal. Lines written before records were timestamped are bare records.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(super) enum Line<T> {
+    Stamped(StampedLine<T>),
+    Bare(Record<T>),
+}
+
+/// A stamped line of a written journal, with the message of the record if it has one.
+#[derive(Debug, Deserialize)]
+pub(super) struct StampedLine<T> {
+    #[serde(flatten)]
+    stamp: Stamp,
+    record: Record<T>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+/// A record to be written as a [`Line`].
+#[derive(Debug, Serialize)]
+pub(super) struct LineRef<'a, T> {
+    #[serde(flatten)]
+    pub stamp: Stamp,
+    pub record: &'a Record<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<&'a str>,
+}
+
+impl<T> Journal<T> {
+    /// Return the stamp of the record at the given index, where the oldest record has an index of
+    /// 0.
+    #[inline]
+    pub fn stamp(&self, idx: usize) -> Option<&Stamp> {
+        self.stamps.get(idx)
+    }
+
+    /// Retrieve the record at the given index together with its stamp.
+    #[inline]
+    pub fn get_stamped(&self, idx: usize) -> Option<Stamped<&Record<T>>> {
+        let record = self.records.get(idx)?;
+        Some(Stamped {
+            stamp: self.stamps[idx],
+            record,
+        })
+    }
+
+    /// Return the record at the given index as a line to be written.
+    #[inline]
+    pub(super) fn line(&self, idx: usize) -> Option<LineRef<'_, T>> {
+        let record = self.records.get(idx)?;
+        Some(LineRef {
+            stamp: self.stamps[idx],
+            record,
+            message: self.message(idx),
+        })
+    }
+
+    /// Return the stamp for the next appended record.
+    #[inline]
+    pub(super) fn next_stamp(&self) -> Stamp {
+        Stamp {
+            seq: self.next_seq(),
+            time: Some(SystemTime::now()),
+        }
+    }
+
+    #[inline]
+    fn next_seq(&self) -> u64 {
+        self.stamps.last().map(|stamp| stamp.seq + 1).unwrap_or(0)
+    }
+
+    /// Append a record read from a written journal line, keeping its stamp if it has one.
+    #[inline]
+    pub(super) fn append_line(&mut self, line: Line<T>) {
+        match line {
+            Line::Stamped(StampedLine {
+                stamp,
+                record,
+                message,
+            }) => {
+                // Never let a corrupted or reordered journal break sequence monotonicity.
+                let stamp = Stamp {
+                    seq: stamp.seq.max(self.next_seq()),
+                    time: stamp.time,
+                };
+                if let Some(message) = message {
+                    self.messages.insert(stamp.seq, message);
+                }
+                self.append_stamped(record, stamp);
+            }
+            Line::Bare(record) => {
+                let stamp = Stamp {
+                    seq: self.next_seq(),
+                    time: None,
+                };
+                self.append_stamped(record, stamp);
+            }
+        }
+    }
+}
+
+impl<T> Line<T> {
+    /// Return the record of the line, discarding any stamp.
+    #[inline]
+    pub(super) fn into_record(self) -> Record<T> {
+        match self {
+            Self::Stamped(line) => line.record,
+            Self::Bare(record) => record,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{Datum, BACKWARD, COMMIT, FORWARD};
+    use super::Journal;
+
+    #[test]
+    fn test_stamps() -> Result<(), Box<dyn std::error::Error>> {
+        let mut journal = Journal::new();
+        journal.append(FORWARD);
+        journal.append(BACKWARD);
+        journal.append(COMMIT);
+
+        let seqs: Vec<_> = journal.iter_stamped().map(|s| s.stamp.seq).collect();
+        assert_eq!(vec![0, 1, 2], seqs);
+        assert!(journal.stamp(0).unwrap().time.is_some());
+
+        // Stamps survive writing and loading, and new records continue the sequence.
+        let mut w = Vec::new();
+        journal.write(&mut w, 0)?;
+        let mut loaded: Journal<Datum> = Journal::load(&w[..])?;
+        assert_eq!(journal.stamp(1), loaded.stamp(1));
+        loaded.append(FORWARD);
+        assert_eq!(3, loaded.stamp(3).unwrap().seq);
+
+        // Records written before stamps are still loaded.
+        let loaded: Journal<Datum> = Journal::load(&b"{\"Atom\":\"Forward\"}\n\"Commit\"\n"[..])?;
+        assert_eq!(Some(&FORWARD), loaded.get(0));
+        assert_eq!(1, loaded.stamp(1).unwrap().seq);
+        assert_eq!(None, loaded.stamp(1).unwrap().time);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_messages() -> Result<(), Box<dyn std::error::Error>> {
+        let mut journal = Journal::new();
+        journal.append(FORWARD);
+        journal.append(COMMIT);
+        journal.set_message(Some("switch themes".to_string()));
+        journal.append(FORWARD);
+        journal.append(COMMIT);
+
+        // Only commits appended after the message is set carry it.
+        assert_eq!(None, journal.message(1));
+        assert_eq!(None, journal.message(2));
+        assert_eq!(Some("switch themes"), journal.message(3));
+
+        let mut w = Vec::new();
+        journal.write(&mut w, 0)?;
+        let loaded: Journal<Datum> = Journal::load(&w[..])?;
+        assert_eq!(None, loaded.message(1));
+        assert_eq!(Some("switch themes"), loaded.message(3));
+
+        Ok(())
+    }
+}
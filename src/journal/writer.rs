@@ -2,7 +2,8 @@ use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
 
 use serde::{de::DeserializeOwned, Serialize};
 
-use super::{Journal, Record};
+use super::stamp::{Line, LineRef};
+use super::Journal;
 
 #[derive(Debug, thiserror::Error)]
 pub enum WriteError {
@@ -30,8 +31,9 @@ where
         let mut w = BufWriter::new(w);
 
         let mut i = 0;
-        for record in self.records().iter().skip(start) {
-            write_record(record, &mut w)?;
+        for idx in start..self.size() {
+            let line = self.line(idx).unwrap();
+            write_record(&line, &mut w)?;
             i += 1;
             if i % BATCH_FLUSH_SIZE == 0 {
                 w.flush()?;
@@ -59,34 +61,43 @@ where
     where
         R: Read,
     {
-        let r = BufReader::new(r);
         let mut journal = Journal::new();
-        for line in r.lines() {
-            let line = line?;
-            let record = read_record(&line)?;
-            journal.append(record);
-        }
-
+        read_into(&mut journal, r)?;
         Ok(journal)
     }
 }
 
+/// Append the records of the written journal `r` to `journal`.
+#[inline]
+pub(super) fn read_into<T, R>(journal: &mut Journal<T>, r: R) -> Result<(), ReadError>
+where
+    T: DeserializeOwned,
+    R: Read,
+{
+    for line in BufReader::new(r).lines() {
+        let line = read_line(&line?)?;
+        journal.append_line(line);
+    }
+
+    Ok(())
+}
+
 #[inline]
-fn write_record<T, W>(record: &Record<T>, mut w: W) -> Result<(), WriteError>
+pub(super) fn write_record<T, W>(line: &LineRef<'_, T>, mut w: W) -> Result<(), WriteError>
 where
     T: Serialize,
     W: Write,
 {
-    let _ = serde_json::to_writer(&mut w, record)?;
+    serde_json::to_writer(&mut w, line)?;
     w.write_all(b"\n")?;
     Ok(())
 }
 
 #[inline]
-fn read_record<T>(line: &str) -> Result<Record<T>, ReadError>
+fn read_line<T>(line: &str) -> Result<Line<T>, ReadError>
 where
     T: DeserializeOwned,
 {
-    let record = serde_json::from_str(line)?;
-    Ok(record)
+    let line = serde_json::from_str(line)?;
+    Ok(line)
 }
@@ -1,9 +1,26 @@
+//! Library of shelf, the dotfiles package manager.
+//!
+//! [`api`] is the supported API, and follows semantic versioning. The other modules are hidden
+//! from the documentation: they are public for the `shelf` binary, but may change in any release.
+
+pub mod api;
+pub mod spec;
+
+#[doc(hidden)]
 pub mod action;
+#[doc(hidden)]
 pub mod graph;
+#[doc(hidden)]
 pub mod load;
-pub mod spec;
+#[doc(hidden)]
+pub mod originals;
+#[doc(hidden)]
+pub mod state;
 
+#[doc(hidden)]
 pub mod journal;
+#[doc(hidden)]
 pub mod op;
 
+#[doc(hidden)]
 pub mod fse;
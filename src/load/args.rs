@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+
+use mlua::{Error as LuaError, FromLua, FromLuaMulti, Lua, MultiValue, Table, Value as LuaValue};
+
+/// Convert the arguments `args` of the spec method `method`, which are given either positionally
+/// (at most `max` of them) or by name in a single table. Named arguments are read by `named`.
+#[inline]
+pub fn convert<'lua, T, F>(
+    method: &'static str,
+    args: MultiValue<'lua>,
+    max: usize,
+    lua: &'lua Lua,
+    named: F,
+) -> mlua::Result<T>
+where
+    T: FromLuaMulti<'lua>,
+    F: FnOnce(&mut NamedArgs<'lua>) -> mlua::Result<T>,
+{
+    match named_table(&args) {
+        Some(table) => convert_named(method, table, lua, named),
+        None => positional(method, args, max, lua),
+    }
+}
+
+/// Convert the arguments of the spec method `method` that are given by name in `table`. Named
+/// arguments are read by `named`, and unknown ones are rejected.
+#[inline]
+pub fn convert_named<'lua, T, F>(
+    method: &'static str,
+    table: Table<'lua>,
+    lua: &'lua Lua,
+    named: F,
+) -> mlua::Result<T>
+where
+    F: FnOnce(&mut NamedArgs<'lua>) -> mlua::Result<T>,
+{
+    let mut args = NamedArgs::new(method, table, lua);
+    let converted = named(&mut args)?;
+    args.finish()?;
+    Ok(converted)
+}
+
+/// Convert the positional arguments `args` of the spec method `method`, which takes at most `max`
+/// arguments. Extra arguments are rejected rather than silently dropped.
+#[inline]
+fn positional<'lua, T>(
+    method: &str,
+    args: MultiValue<'lua>,
+    max: usize,
+    lua: &'lua Lua,
+) -> mlua::Result<T>
+where
+    T: FromLuaMulti<'lua>,
+{
+    if args.len() > max {
+        return Err(LuaError::RuntimeError(format!(
+            "pkg:{}: expected at most {} arguments, but got {}",
+            method,
+            max,
+            args.len()
+        )));
+    }
+
+    T::from_lua_multi(args, lua)
+}
+
+/// Return the table of named arguments, if `args` is a single table.
+#[inline]
+fn named_table<'lua>(args: &MultiValue<'lua>) -> Option<Table<'lua>> {
+    match args.iter().next() {
+        Some(LuaValue::Table(table)) if args.len() == 1 => Some(table.clone()),
+        _ => None,
+    }
+}
+
+/// Named arguments of a spec method, given as a table. Keys are tracked as they are read, so that
+/// unknown keys (e.g. typos) can be rejected by [`NamedArgs::finish`].
+pub struct NamedArgs<'lua> {
+    method: &'static str,
+    table: Table<'lua>,
+    lua: &'lua Lua,
+    read: HashSet<&'static str>,
+}
+
+impl<'lua> NamedArgs<'lua> {
+    #[inline]
+    fn new(method: &'static str, table: Table<'lua>, lua: &'lua Lua) -> Self {
+        Self {
+            method,
+            table,
+            lua,
+            read: HashSet::new(),
+        }
+    }
+
+    /// Convert the argument named `key`. Missing arguments are nil.
+    #[inline]
+    pub fn get<T>(&mut self, key: &'static str) -> mlua::Result<T>
+    where
+        T: FromLua<'lua>,
+    {
+        self.read.insert(key);
+
+        let value: LuaValue = self.table.raw_get(key)?;
+        T::from_lua(value, self.lua).map_err(|err| {
+            LuaError::RuntimeError(format!(
+                "pkg:{}: invalid argument '{}': {}",
+                self.method, key, err
+            ))
+        })
+    }
+
+    /// Check that no arguments other than those read were given.
+    #[inline]
+    fn finish(self) -> mlua::Result<()> {
+        let mut unknown = Vec::new();
+        for pair in self.table.pairs::<LuaValue, LuaValue>() {
+            let (key, _) = pair?;
+            let key = match key {
+                LuaValue::String(key) => key.to_string_lossy().into_owned(),
+                LuaValue::Integer(i) => i.to_string(),
+                key => key.type_name().to_string(),
+            };
+
+            if !self.read.contains(key.as_str()) {
+                unknown.push(key);
+            }
+        }
+
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            // Table iteration order is unspecified.
+            unknown.sort();
+            Err(LuaError::RuntimeError(format!(
+                "pkg:{}: unknown argument(s) '{}'",
+                self.method,
+                unknown.join("', '")
+            )))
+        }
+    }
+}
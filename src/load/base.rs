@@ -0,0 +1,407 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+use indexmap::IndexMap;
+
+use crate::fse;
+use crate::spec::{
+    Base, Dep, Directive, Expectation, File, GeneratedFileTyp, Hook, Object, ObjectValue, Spec,
+    TemplatedFileType,
+};
+
+use super::options::{self, OptionError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BaseError {
+    #[error("i/o error")]
+    Io(#[from] io::Error),
+    #[error("{program} exited with {status}")]
+    Command { program: String, status: ExitStatus },
+    #[error("base layers can't have a base layer themselves")]
+    Nested,
+    #[error("function hooks are not supported in base layers")]
+    FunHook,
+    #[error("pipes are not supported in base layers")]
+    Pipe,
+    #[error("handlebars helpers are not supported in base layers")]
+    HbsHelper,
+    #[error("base layer must be fetched, but fetching is forbidden")]
+    Hermetic,
+    #[error(transparent)]
+    Option(#[from] OptionError),
+}
+
+/// Fetcher of base layers into a local directory, keyed by their source and revision.
+#[derive(Debug, Clone)]
+pub struct BaseFetcher {
+    path: PathBuf,
+    refresh: bool,
+    hermetic: bool,
+}
+
+impl BaseFetcher {
+    #[inline]
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            refresh: false,
+            hermetic: false,
+        }
+    }
+
+    /// Re-fetch bases even if they have been fetched before.
+    #[inline]
+    pub fn refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Forbid fetching, so that only local and previously fetched bases can be used.
+    #[inline]
+    pub fn hermetic(mut self, hermetic: bool) -> Self {
+        self.hermetic = hermetic;
+        self
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Fetch `base`, returning the path of the base package.
+    ///
+    /// Local directories are used in place. Tarballs (`.tar`, `.tar.gz`, `.tgz`, `.tar.xz`) are
+    /// downloaded with `curl` if the source is a URL, and extracted with `tar`, stripping the
+    /// top-level directory. Any other source is cloned with `git`.
+    #[inline]
+    pub fn fetch(&self, base: &Base) -> Result<PathBuf, BaseError> {
+        let source = Path::new(&base.source);
+        if source.is_dir() {
+            return Ok(source.canonicalize()?);
+        }
+
+        let target = self.entry_path(base);
+        if target.exists() && (!self.refresh || self.hermetic) {
+            return Ok(target);
+        } else if self.hermetic {
+            return Err(BaseError::Hermetic);
+        } else if target.exists() {
+            fs::remove_dir_all(&target)?;
+        }
+
+        // Fetch into a temporary directory first, so that failures don't leave a partial base.
+        let tmp = target.with_extension("tmp");
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        fs::create_dir_all(&self.path)?;
+
+        let res = if is_tarball(&base.source) {
+            self.fetch_tarball(&base.source, &tmp)
+        } else {
+            fetch_git(&base.source, base.rev.as_deref(), &tmp)
+        };
+        if let Err(err) = res {
+            let _ = fs::remove_dir_all(&tmp);
+            return Err(err);
+        }
+
+        fs::rename(&tmp, &target)?;
+        Ok(target)
+    }
+
+    #[inline]
+    fn fetch_tarball(&self, source: &str, target: &Path) -> Result<(), BaseError> {
+        let archive = if source.starts_with("http://") || source.starts_with("https://") {
+            let archive = target.with_extension("archive");
+            run(Command::new("curl")
+                .args(["-fsSL", "-o"])
+                .arg(&archive)
+                .arg(source))?;
+            archive
+        } else {
+            PathBuf::from(source)
+        };
+
+        fs::create_dir_all(target)?;
+        let res = run(Command::new("tar")
+            .arg("-xf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(target)
+            .arg("--strip-components=1"));
+
+        if archive != Path::new(source) {
+            let _ = fs::remove_file(&archive);
+        }
+        res
+    }
+
+    #[inline]
+    fn entry_path(&self, base: &Base) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        base.source.hash(&mut hasher);
+        base.rev.hash(&mut hasher);
+        self.path.join(format!("{:016x}", hasher.finish()))
+    }
+}
+
+#[inline]
+fn is_tarball(source: &str) -> bool {
+    [".tar", ".tar.gz", ".tgz", ".tar.xz"]
+        .iter()
+        .any(|ext| source.ends_with(ext))
+}
+
+#[inline]
+fn fetch_git(source: &str, rev: Option<&str>, target: &Path) -> Result<(), BaseError> {
+    let mut command = Command::new("git");
+    command.args(["clone", "--quiet", "--depth", "1"]);
+    if let Some(rev) = rev {
+        command.args(["--branch", rev]);
+    }
+    command.arg(source).arg(target);
+
+    run(&mut command)
+}
+
+#[inline]
+fn run(command: &mut Command) -> Result<(), BaseError> {
+    let status = command.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(BaseError::Command {
+            program: command.get_program().to_string_lossy().into_owned(),
+            status,
+        })
+    }
+}
+
+/// Layer the package `spec` over the spec `base` of the base package at `base_path`.
+///
+/// The directives and dependencies of the base come first, with their paths made absolute so
+/// that they still refer to the base package. Base directives are dropped if a local directive
+/// manages the same destination, and the variables of the local [`Base`] override those of base
+/// templates, after being checked against the options of the base. Local environment variables
+/// and options likewise override those of the base.
+#[inline]
+pub fn layer(spec: &mut Spec, base_path: &Path, base: Spec) -> Result<(), BaseError> {
+    if base.base.is_some() {
+        return Err(BaseError::Nested);
+    }
+    // Like function hooks, the helpers live in the base's Lua state.
+    if !base.hbs_helpers.is_empty() {
+        return Err(BaseError::HbsHelper);
+    }
+
+    let vars = spec
+        .base
+        .as_ref()
+        .map(|base| base.vars.clone())
+        .unwrap_or_default();
+    for (name, option) in &base.options {
+        if let Some(value) = vars.0.get(name) {
+            options::check(name, option, value)?;
+        }
+    }
+
+    let local_dests: HashSet<_> = spec.directives.iter().filter_map(directive_dest).collect();
+    let mut directives = Vec::new();
+    let mut conditions = Vec::new();
+    let mut on_conflict = Vec::new();
+    let mut if_missing = Vec::new();
+    let mut profiles = Vec::new();
+    for (i, drct) in base.directives.into_iter().enumerate() {
+        let overridden = directive_dest(&drct).is_some_and(|dest| local_dests.contains(&dest));
+        if !overridden {
+            directives.push(rebase(drct, base_path, &vars)?);
+            conditions.push(base.conditions.get(i).cloned().unwrap_or_default());
+            on_conflict.push(base.on_conflict.get(i).copied().flatten());
+            if_missing.push(base.if_missing.get(i).copied().flatten());
+            profiles.push(base.profiles.get(i).cloned().unwrap_or_default());
+        }
+    }
+    // Conditions, policies, and profiles are kept alongside their directives.
+    conditions.extend((0..spec.directives.len()).map(|i| spec.directive_conditions(i).to_vec()));
+    on_conflict.extend((0..spec.directives.len()).map(|i| spec.directive_on_conflict(i)));
+    if_missing.extend((0..spec.directives.len()).map(|i| spec.directive_if_missing(i)));
+    profiles.extend((0..spec.directives.len()).map(|i| spec.directive_profiles(i).to_vec()));
+    directives.append(&mut spec.directives);
+    spec.directives = directives;
+    spec.conditions = conditions;
+    spec.on_conflict = on_conflict;
+    spec.if_missing = if_missing;
+    spec.profiles = profiles;
+
+    let mut deps: Vec<_> = base
+        .deps
+        .into_iter()
+        .map(|dep| Dep {
+            path: base_path.join(dep.path),
+        })
+        .collect();
+    deps.append(&mut spec.deps);
+    spec.deps = deps;
+
+    let mut env = base.env;
+    env.extend(spec.env.drain());
+    spec.env = env;
+
+    let mut options = base.options;
+    options.append(&mut spec.options);
+    spec.options = options;
+
+    Ok(())
+}
+
+/// Return the destination of a file directive, relative to the destination root.
+#[inline]
+fn directive_dest(drct: &Directive) -> Option<PathBuf> {
+    let dest = match drct {
+        Directive::File(f) => match f {
+            File::Regular(rf) => rf.dest.as_ref().unwrap_or(&rf.src).clone(),
+            File::CopyDir(cf) => cf.dest.as_ref().unwrap_or(&cf.src).clone(),
+            File::Templated(tf) => tf.dest.clone(),
+            File::Piped(pf) => pf.dest.clone(),
+            File::Generated(gf) => gf.dest.clone(),
+            File::Dir(df) => df.dest.clone(),
+            File::SystemdUnit(sf) => {
+                let name = sf.src.file_name()?;
+                Path::new(".config/systemd/user").join(name)
+            }
+            File::Fragment(ff) => ff.dir.join(&ff.name),
+            // Other lines may be added to the same rc file.
+            File::Tree(_) | File::SourceLine(_) => return None,
+            // User defaults and registry values have no destination.
+            File::Defaults(_) | File::RegValue(_) => return None,
+            // Destinations of plugins are only known once they are run.
+            File::Plugin(_) => return None,
+            // Expectations only read their destinations.
+            File::Expect(_) => return None,
+        },
+        Directive::Hook(_) => return None,
+    };
+
+    Some(fse::clean(dest))
+}
+
+/// Make the package-relative paths of the base directive `drct` absolute, and apply variable
+/// overrides.
+#[inline]
+fn rebase(drct: Directive, base_path: &Path, vars: &Object) -> Result<Directive, BaseError> {
+    let drct = match drct {
+        Directive::File(f) => Directive::File(match f {
+            File::Regular(mut rf) => {
+                // The destination defaults to the relative source, so pin it first.
+                rf.dest = Some(rf.dest.take().unwrap_or_else(|| rf.src.clone()));
+                rf.src = base_path.join(rf.src);
+                File::Regular(rf)
+            }
+            File::CopyDir(mut cf) => {
+                cf.dest = Some(cf.dest.take().unwrap_or_else(|| cf.src.clone()));
+                cf.src = base_path.join(cf.src);
+                File::CopyDir(cf)
+            }
+            File::Tree(mut tf) => {
+                tf.src = base_path.join(tf.src);
+                for layer in &mut tf.layers {
+                    *layer = base_path.join(&layer);
+                }
+                File::Tree(tf)
+            }
+            File::Templated(mut tf) => {
+                tf.src = base_path.join(tf.src);
+                if let TemplatedFileType::Handlebars(hbs) = &mut tf.typ {
+                    for partial in hbs.partials.values_mut() {
+                        *partial = base_path.join(&partial);
+                    }
+                }
+                merge_object(&mut tf.vars.0, &vars.0);
+                File::Templated(tf)
+            }
+            // Like function hooks, the functions of pipes can't be moved.
+            File::Piped(_) => return Err(BaseError::Pipe),
+            File::Generated(mut gf) => {
+                let schema = match &mut gf.typ {
+                    GeneratedFileTyp::Yaml(y) => y.schema.as_mut(),
+                    GeneratedFileTyp::Toml(t) => t.schema.as_mut(),
+                    GeneratedFileTyp::Json(j) => j.schema.as_mut(),
+                    GeneratedFileTyp::Empty(_) | GeneratedFileTyp::String(_) => None,
+                };
+                if let Some(schema) = schema {
+                    *schema = base_path.join(&schema);
+                }
+                File::Generated(gf)
+            }
+            File::Dir(df) => File::Dir(df),
+            File::Fragment(ff) => File::Fragment(ff),
+            File::SourceLine(sf) => File::SourceLine(sf),
+            File::Defaults(df) => File::Defaults(df),
+            File::RegValue(rf) => File::RegValue(rf),
+            File::Plugin(mut pf) => {
+                pf.module = base_path.join(pf.module);
+                pf.root = base_path.join(pf.root);
+                File::Plugin(pf)
+            }
+            File::Expect(mut ef) => {
+                if let Expectation::Symlink(target) = &mut ef.expectation {
+                    *target = base_path.join(&target);
+                }
+                File::Expect(ef)
+            }
+            File::SystemdUnit(mut sf) => {
+                sf.src = base_path.join(sf.src);
+                File::SystemdUnit(sf)
+            }
+        }),
+        Directive::Hook(h) => Directive::Hook(match h {
+            Hook::Cmd(mut cmd) => {
+                // Commands start in the package root by default, so pin it to the base's.
+                cmd.start = Some(match cmd.start {
+                    Some(start) => base_path.join(start),
+                    None => base_path.to_path_buf(),
+                });
+                Hook::Cmd(cmd)
+            }
+            Hook::Script(mut script) => {
+                script.path = base_path.join(script.path);
+                script.start = Some(match script.start {
+                    Some(start) => base_path.join(start),
+                    None => base_path.to_path_buf(),
+                });
+                Hook::Script(script)
+            }
+            // Lua functions live in the base's Lua state, and can't be moved.
+            Hook::Fun(_) => return Err(BaseError::FunHook),
+        }),
+    };
+
+    Ok(drct)
+}
+
+/// Recursively merge `overrides` into `target`, with `overrides` taking precedence.
+#[inline]
+pub(crate) fn merge_object(
+    target: &mut IndexMap<String, ObjectValue>,
+    overrides: &IndexMap<String, ObjectValue>,
+) {
+    for (key, value) in overrides {
+        match (target.get_mut(key), value) {
+            (Some(ObjectValue::Object(target)), ObjectValue::Object(value)) => {
+                merge_object(target, value)
+            }
+            _ => {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
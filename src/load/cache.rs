@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::fse;
+use crate::spec::{Directive, File as FileDirective, Hook, Object, Spec};
+
+/// Version of the loader; included in cache keys so that specs evaluated by another version of
+/// shelf are never reused.
+static LOADER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, thiserror::Error)]
+pub enum CacheError {
+    #[error("i/o error")]
+    Io(#[from] io::Error),
+    #[error("serde error")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// On-disk cache of evaluated specs, keyed by the contents of the package's config file, the
+/// template variables of its last application and of this run, the host name it was evaluated on,
+/// and the loader version. Each entry also records the environment variables the package read
+/// while being evaluated, and is stale once any of them changes.
+///
+/// There is at most one entry per package path; storing a new entry replaces the previous one.
+#[derive(Debug, Clone)]
+pub struct SpecCache {
+    path: PathBuf,
+}
+
+/// Key of a cache entry. See [`SpecCache::key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// Hash of the package path, naming the entry.
+    entry: blake3::Hash,
+    /// Hash of everything else the evaluated spec depends on.
+    hash: blake3::Hash,
+}
+
+/// Environment variables read by a package through `os.getenv` while it was evaluated, with the
+/// values they had.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EnvReads(BTreeMap<String, Option<String>>);
+
+impl EnvReads {
+    /// Record that `name` was read with `value`.
+    #[inline]
+    pub fn insert(&mut self, name: String, value: Option<String>) {
+        self.0.insert(name, value);
+    }
+
+    /// Return true if any of the variables has a different value in the current environment.
+    #[inline]
+    pub fn changed(&self) -> bool {
+        self.0.iter().any(|(name, value)| {
+            env::var_os(name).map(|v| v.to_string_lossy().into_owned()) != *value
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheEntry<S> {
+    /// Hex of [`CacheKey::hash`] the entry was stored with.
+    key: String,
+    env: EnvReads,
+    spec: CachedSpec<S>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+enum CachedSpec<S> {
+    /// The evaluated spec.
+    Spec(S),
+    /// The spec cannot be restored without evaluating Lua (e.g. it contains function hooks), and
+    /// must always be re-evaluated.
+    Reeval,
+}
+
+impl SpecCache {
+    #[inline]
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Compute the cache key for the package at `path` with config file `contents`, evaluated
+    /// with `previous_vars` (see [`SpecLoader::previous_vars`](super::SpecLoader::previous_vars))
+    /// and the variables `vars` given for this run. The options a package declares are part of
+    /// its `contents`. The environment is not part of the key; see [`EnvReads`].
+    #[inline]
+    pub fn key<P>(&self, path: P, contents: &str, previous_vars: &Object, vars: &Object) -> CacheKey
+    where
+        P: AsRef<Path>,
+    {
+        let entry = blake3::hash(path.as_ref().to_string_lossy().as_bytes());
+
+        let mut hasher = blake3::Hasher::new();
+        // Prefix each field with its length, so that fields can't run into each other.
+        let mut field = |bytes: &[u8]| {
+            hasher.update(&(bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        };
+
+        field(LOADER_VERSION.as_bytes());
+        field(contents.as_bytes());
+        // SAFETY: Objects always serialize.
+        field(&serde_json::to_vec(previous_vars).unwrap());
+        field(&serde_json::to_vec(vars).unwrap());
+        field(fse::hostname().unwrap_or_default().as_bytes());
+
+        CacheKey {
+            entry,
+            hash: hasher.finalize(),
+        }
+    }
+
+    /// Retrieve the cached spec for `key`, returning `None` if there is no entry, if the entry was
+    /// stored with another key or environment, or if the entry is marked for re-evaluation.
+    #[inline]
+    pub fn get(&self, key: CacheKey) -> Result<Option<Spec>, CacheError> {
+        let entry_path = self.entry_path(key);
+        let file = match File::open(&entry_path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let entry: CacheEntry<Spec> = serde_json::from_reader(BufReader::new(file))?;
+        if entry.key != key.hash.to_hex().as_str() || entry.env.changed() {
+            return Ok(None);
+        }
+
+        match entry.spec {
+            CachedSpec::Spec(spec) => Ok(Some(spec)),
+            CachedSpec::Reeval => Ok(None),
+        }
+    }
+
+    /// Store the evaluated `spec` for `key`, along with the environment variables `env` it read,
+    /// replacing any previous entry for the package. Specs containing function hooks, pipes, or
+    /// Handlebars helpers are stored as re-evaluation markers, since Lua functions cannot be
+    /// serialized.
+    #[inline]
+    pub fn insert(&self, key: CacheKey, spec: &Spec, env: &EnvReads) -> Result<(), CacheError> {
+        fs::create_dir_all(&self.path)?;
+
+        let has_fun = !spec.hbs_helpers.is_empty()
+            || spec.directives.iter().any(|drct| {
+                matches!(
+                    drct,
+                    Directive::Hook(Hook::Fun(_)) | Directive::File(FileDirective::Piped(_))
+                )
+            });
+        let entry = CacheEntry {
+            key: key.hash.to_hex().to_string(),
+            env: env.clone(),
+            spec: if has_fun {
+                CachedSpec::Reeval
+            } else {
+                CachedSpec::Spec(spec)
+            },
+        };
+
+        let file = File::create(self.entry_path(key))?;
+        serde_json::to_writer(BufWriter::new(file), &entry)?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn entry_path(&self, key: CacheKey) -> PathBuf {
+        self.path.join(format!("{}.json", key.entry.to_hex()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    use crate::load::SpecLoader;
+    use crate::spec::{Object, Spec};
+
+    use super::{EnvReads, SpecCache};
+
+    type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+    fn eval(dir: &Path, contents: &str) -> Result<(Spec, EnvReads)> {
+        fs::write(dir.join("package.lua"), contents)?;
+        let loader = SpecLoader::new(dir)?.read()?.eval()?;
+        let env = loader.env_reads();
+        Ok((loader.finish()?.spec, env))
+    }
+
+    /// Test that entries are reused until the environment variables the package read change.
+    #[test]
+    fn test_env_reads() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = SpecCache::new(dir.path().join("cache"));
+        let vars = Object::new();
+
+        env::set_var("SHELF_TEST_CACHE_ENV", "a");
+        let contents = "name(os.getenv('SHELF_TEST_CACHE_ENV'))";
+        let (spec, env) = eval(dir.path(), contents)?;
+        assert_eq!(spec.name, "a");
+
+        let key = cache.key(dir.path(), contents, &vars, &vars);
+        cache.insert(key, &spec, &env)?;
+        assert_eq!(cache.get(key)?.map(|spec| spec.name), Some("a".into()));
+
+        env::set_var("SHELF_TEST_CACHE_ENV", "b");
+        assert!(cache.get(key)?.is_none());
+
+        env::remove_var("SHELF_TEST_CACHE_ENV");
+        assert!(cache.get(key)?.is_none());
+
+        Ok(())
+    }
+
+    /// Test that storing an entry replaces the previous entry for the package.
+    #[test]
+    fn test_replace() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = SpecCache::new(dir.path().join("cache"));
+        let vars = Object::new();
+
+        let old = "name('old')";
+        let (spec, env) = eval(dir.path(), old)?;
+        let old_key = cache.key(dir.path(), old, &vars, &vars);
+        cache.insert(old_key, &spec, &env)?;
+
+        let new = "name('new')";
+        let (spec, env) = eval(dir.path(), new)?;
+        let new_key = cache.key(dir.path(), new, &vars, &vars);
+        cache.insert(new_key, &spec, &env)?;
+
+        assert!(cache.get(old_key)?.is_none());
+        assert_eq!(
+            cache.get(new_key)?.map(|spec| spec.name),
+            Some("new".into())
+        );
+        assert_eq!(fs::read_dir(cache.path())?.count(), 1);
+
+        Ok(())
+    }
+}
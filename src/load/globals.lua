@@ -1,3 +1,24 @@
+-- Raise an error if the table `arg` of `directive` has keys other than its `n` positional
+-- arguments and the named arguments in `keys`, to catch typos like `destt`.
+local function check_keys(directive, arg, n, keys)
+    for key, _ in pairs(arg) do
+        local known = type(key) == 'number' and key >= 1 and key <= n and key % 1 == 0
+        for _, k in ipairs(keys) do
+            known = known or k == key
+        end
+
+        if not known then
+            error(directive .. ": unknown argument '" .. tostring(key) .. "'", 3)
+        end
+    end
+end
+
+-- Values provided by shelf.
+--
+-- shelf.previous_vars: template variables of the package as of its last successful application,
+-- or an empty table if it has never been applied.
+shelf = { previous_vars = {} }
+
 -- name 'test'
 
 -- selene: allow(unused_variable)
@@ -16,31 +37,150 @@ function dep(...)
     return dep
 end
 
+-- requires '0.4'
+
+-- selene: allow(unused_variable)
+function requires(version)
+    pkg:requires(version)
+end
+
+-- scope 'system'
+
+-- selene: allow(unused_variable)
+function scope(value)
+    pkg:scope(value)
+end
+
+-- base 'https://example.com/org/dotfiles.git'
+-- base {'https://example.com/org/dotfiles.git', rev = 'v1'}
+-- base {'https://example.com/org/dotfiles.tar.gz', vars = { editor = 'nvim' }}
+-- base {'../org-dotfiles'}
+
+-- selene: allow(unused_variable)
+function base(arg)
+    local source, rev, vars
+    if type(arg) == 'string' then
+        source = arg
+        rev = nil
+        vars = nil
+    elseif type(arg) == 'table' then
+        check_keys('base', arg, 1, { 'rev', 'vars' })
+        source = arg[1] or error 'base source was not provided'
+        rev = arg.rev
+        vars = arg.vars
+    else
+        error 'base arg must be a string or table'
+    end
+
+    pkg:base(source, rev, vars)
+end
+
+-- env { EDITOR = 'nvim' }
+-- env { EDITOR = 'nvim', PAGER = 'less' }
+
+-- selene: allow(unused_variable)
+function env(arg)
+    if type(arg) ~= 'table' then
+        error 'env arg must be a table'
+    end
+
+    pkg:env(arg)
+end
+
+-- only_if {os = 'linux'}
+-- only_if {hostname = 'work-*'}
+-- only_if {os = 'unix', env = {WAYLAND_DISPLAY = '*'}}
+-- only_if(nil)
+-- only_if({os = 'macos'}, function() file '.yabairc' end)
+-- Directives that follow are only applied if all of the given conditions hold, until only_if is
+-- called again; nil removes the condition. Given a function, the condition only applies to the
+-- directives within it, in addition to any other. Each directive can also be given a condition of
+-- its own as `when`, e.g. file {'a.txt', when = {os = 'linux'}}. The os is a name like 'linux',
+-- 'macos', or 'windows', or a family, 'unix' or 'windows'; hostname and env values are globs.
+-- Conditions are checked when applying, and directives whose conditions don't hold are skipped.
+
+-- selene: allow(unused_variable)
+function only_if(condition, block)
+    if block == nil then
+        pkg:only_if(condition)
+    elseif type(block) == 'function' then
+        pkg:push_when(condition)
+        block()
+        pkg:pop_when()
+    else
+        error 'only_if block must be a function'
+    end
+end
+
+-- profile('work', function() file '.gitconfig-work' end)
+-- Directives within the function belong to the profile, and are only applied if it is selected
+-- with --profile; directives within nested profiles need all of them selected. Directives outside
+-- of any profile are always applied.
+
+-- selene: allow(unused_variable)
+function profile(name, block)
+    if type(block) ~= 'function' then
+        error 'profile block must be a function'
+    end
+
+    pkg:profile(name, block)
+end
+
+-- option('font_size', {default = 12, type = 'number'})
+-- option('theme', {type = 'string'})
+-- Declare an option of the package, which can be set when applying with --set, e.g.
+-- --set font_size=14. Options are given to every template as variables: the value set, or else the
+-- template's own variable of the same name, or else the default. Values set are checked against the
+-- type, one of 'string', 'number', 'integer', 'boolean', 'array', or 'object'.
+
+-- selene: allow(unused_variable)
+function option(name, decl)
+    pkg:option(name, decl)
+end
+
 -- file 'a.txt'
 -- file {'b.txt'}
 -- file {'c.txt', 'd.txt'}
 -- file {'e.txt', 'f.txt', type = 'copy'}
 -- file {'g.txt', type = 'copy'}
 -- file {'h.txt', optional = true}
+-- file {'i.txt', type = 'auto'}
+-- file {'j.txt', type = 'copy', mode = '0600'}
+-- file {'k.txt', '/etc/k.txt', type = 'copy', owner = 'root', group = 'wheel'}
+-- file {'l.conf', '${XDG_CONFIG_HOME}/l/l.conf'}
+-- Destinations, here and in other directives, may start with `~` and refer to environment
+-- variables as $NAME or ${NAME}; `~`, $HOME, and the XDG directories are those being linked into.
+-- Unset variables are an error unless --unknown-env=empty is passed.
+-- Files of type 'auto' are copied where symlinks can't be created, e.g. on Windows without the
+-- privilege to create them; directories are linked with junctions there instead. The mode and
+-- owner of symlinks and hard links are those of the source, so `mode`, `owner`, and `group` only
+-- apply to copies. Owners are names or ids, and are ignored on Windows.
 
 -- selene: allow(unused_variable)
 function file(arg)
-    local src, dest, link_type, optional
+    local src, dest, link_type, optional, mode, owner, group
     if type(arg) == 'string' then
         src = arg
         dest = nil
         link_type = nil
         optional = nil
+        mode = nil
+        owner = nil
+        group = nil
     elseif type(arg) == 'table' then
+        check_keys('file', arg, 2, { 'type', 'optional', 'mode', 'owner', 'group' })
         src = arg[1] or error 'file src path was not provided'
         dest = arg[2]
         link_type = arg.type
         optional = arg.optional
+        mode = arg.mode
+        owner = arg.owner
+        group = arg.group
     else
         error 'invalid file directive'
     end
 
-    pkg:file(src, dest, link_type, optional)
+    pkg:file(src, dest, link_type, optional, mode, owner, group)
 end
 
 -- selene: allow(unused_variable)
@@ -70,24 +210,37 @@ end
 -- tree {'tree', '.config', ignore = '**/*.log'}
 -- tree {'tree', '.config', type = 'copy', ignore = '**/*.log'}
 -- tree {'tree', optional = true}
+-- tree {'tree', '.config/app', volatile = 'plugins'}
+-- tree {'tree', '.config', type = 'hardlink'}
+-- tree {'.', strip_prefix = 'config'}
+-- tree {{'base', 'theme'}, '.config/app'}
+-- Files in 'host/<hostname>/tree' override those in 'tree' on that machine.
+-- Files listed in 'tree/.shelfignore', in gitignore syntax, are ignored too.
+-- Given a list of sources, they are merged in order; files in later ones replace those with the
+-- same path in earlier ones. Machine-specific files are those of the first, and override all.
 
 -- selene: allow(unused_variable)
 function tree(arg)
-    local src, dest, link_type, globs, ignore, optional
+    local src, dest, link_type, globs, ignore, volatile, optional, strip_prefix, layers
     if type(arg) == 'string' then
         src = arg
         dest = nil
         link_type = nil
         globs = nil
         ignore = nil
+        volatile = nil
         optional = nil
+        strip_prefix = nil
     elseif type(arg) == 'table' then
+        check_keys('tree', arg, 2, { 'type', 'globs', 'ignore', 'volatile', 'optional', 'strip_prefix' })
         src = arg[1] or error 'tree src path was not provided'
         dest = arg[2]
         link_type = arg.type
         globs = arg.globs
         ignore = arg.ignore
+        volatile = arg.volatile
         optional = arg.optional
+        strip_prefix = arg.strip_prefix
 
         if type(globs) == 'string' then
             globs = { globs }
@@ -95,27 +248,221 @@ function tree(arg)
         if type(ignore) == 'string' then
             ignore = { ignore }
         end
+        if type(volatile) == 'string' then
+            volatile = { volatile }
+        end
+        if type(src) == 'table' then
+            layers = {}
+            for i = 2, #src do
+                layers[#layers + 1] = src[i]
+            end
+            src = src[1] or error 'tree src path was not provided'
+        end
     else
         error 'tree arg must be a string or table'
     end
 
-    pkg:tree(src, dest, link_type, globs, ignore, optional)
+    pkg:tree(src, dest, link_type, globs, ignore, volatile, optional, strip_prefix, layers)
+end
+
+-- copy_dir 'dir'
+-- copy_dir {'dir'}
+-- copy_dir {'dir', '.config/app'}
+-- copy_dir {'dir', optional = true}
+-- copy_dir {'dir', '.config/app', volatile = {'plugins', '*.log'}}
+
+-- selene: allow(unused_variable)
+function copy_dir(arg)
+    local src, dest, volatile, optional
+    if type(arg) == 'string' then
+        src = arg
+        dest = nil
+        volatile = nil
+        optional = nil
+    elseif type(arg) == 'table' then
+        check_keys('copy_dir', arg, 2, { 'volatile', 'optional' })
+        src = arg[1] or error 'copy_dir src path was not provided'
+        dest = arg[2]
+        volatile = arg.volatile
+        optional = arg.optional
+
+        if type(volatile) == 'string' then
+            volatile = { volatile }
+        end
+    else
+        error 'copy_dir arg must be a string or table'
+    end
+
+    pkg:copy_dir(src, dest, volatile, optional)
+end
+
+-- systemd_user_unit 'app.service'
+-- systemd_user_unit {'app.service'}
+-- systemd_user_unit {'app.service', enable = true, restart_on_change = true}
+
+-- selene: allow(unused_variable)
+function systemd_user_unit(arg)
+    local src, enable, restart_on_change
+    if type(arg) == 'string' then
+        src = arg
+        enable = nil
+        restart_on_change = nil
+    elseif type(arg) == 'table' then
+        check_keys('systemd_user_unit', arg, 1, { 'enable', 'restart_on_change' })
+        src = arg[1] or error 'systemd_user_unit src path was not provided'
+        enable = arg.enable
+        restart_on_change = arg.restart_on_change
+    else
+        error 'systemd_user_unit arg must be a string or table'
+    end
+
+    pkg:systemd_user_unit(src, enable, restart_on_change)
+end
+
+-- fragment {'.config/fish/conf.d', 'path.fish', 'fish_add_path ~/.local/bin'}
+-- fragment {'.profile.d', 'path.sh', 'export PATH="$HOME/.local/bin:$PATH"'}
+
+-- selene: allow(unused_variable)
+function fragment(arg)
+    if type(arg) == 'table' then
+        check_keys('fragment', arg, 3, {})
+        local dir = arg[1] or error 'fragment dest dir was not provided'
+        local name = arg[2] or error 'fragment name was not provided'
+        local contents = arg[3] or error 'fragment contents were not provided'
+        pkg:fragment(dir, name, contents)
+    else
+        error 'fragment arg must be a table'
+    end
+end
+
+-- source_line {'.bashrc', 'source ~/.config/aliases.sh'}
+-- source_line {'.zshrc', 'eval "$(direnv hook zsh)"'}
+-- source_line {'.config/fish/config.fish', 'zoxide init fish | source'}
+
+-- selene: allow(unused_variable)
+function source_line(arg)
+    if type(arg) == 'table' then
+        check_keys('source_line', arg, 2, {})
+        local rc = arg[1] or error 'source_line rc path was not provided'
+        local line = arg[2] or error 'source_line line was not provided'
+        pkg:source_line(rc, line)
+    else
+        error 'source_line arg must be a table'
+    end
+end
+
+-- defaults {'com.apple.dock', 'autohide', true}
+-- defaults {'com.apple.dock', 'tilesize', 48}
+-- defaults {'NSGlobalDomain', 'AppleInterfaceStyle', 'Dark'}
+
+-- selene: allow(unused_variable)
+function defaults(arg)
+    if type(arg) == 'table' then
+        check_keys('defaults', arg, 3, {})
+        local domain = arg[1] or error 'defaults domain was not provided'
+        local key = arg[2] or error 'defaults key was not provided'
+        -- The value may be false.
+        if arg[3] == nil then
+            error 'defaults value was not provided'
+        end
+        pkg:defaults(domain, key, arg[3])
+    else
+        error 'defaults arg must be a table'
+    end
+end
+
+-- regvalue {[[HKCU\Console]], 'QuickEdit', 1}
+-- regvalue {[[HKCU\Console]], 'FaceName', 'Cascadia Mono'}
+
+-- selene: allow(unused_variable)
+function regvalue(arg)
+    if type(arg) == 'table' then
+        check_keys('regvalue', arg, 3, {})
+        local key = arg[1] or error 'regvalue key was not provided'
+        local name = arg[2] or error 'regvalue name was not provided'
+        -- The value may be false.
+        if arg[3] == nil then
+            error 'regvalue value was not provided'
+        end
+        pkg:regvalue(key, name, arg[3])
+    else
+        error 'regvalue arg must be a table'
+    end
+end
+
+-- plugin {'plugins/dirs.wasm', 'xdg_dirs'}
+-- plugin {'plugins/dirs.wasm', 'xdg_dirs', args = { music = 'Music' }}
+
+-- selene: allow(unused_variable)
+function plugin(arg)
+    if type(arg) == 'table' then
+        check_keys('plugin', arg, 2, { 'args' })
+        local module = arg[1] or error 'plugin module was not provided'
+        local kind = arg[2] or error 'plugin kind was not provided'
+
+        pkg:plugin(module, kind, arg.args)
+    else
+        error 'plugin arg must be a table'
+    end
+end
+
+-- expect_symlink {'.vimrc', 'vimrc'}
+
+-- selene: allow(unused_variable)
+function expect_symlink(arg)
+    if type(arg) == 'table' then
+        check_keys('expect_symlink', arg, 2, {})
+        local dest = arg[1] or error 'expect_symlink dest was not provided'
+        local target = arg[2] or error 'expect_symlink target was not provided'
+        pkg:expect_symlink(dest, target)
+    else
+        error 'expect_symlink arg must be a table'
+    end
+end
+
+-- expect_file '.bashrc'
+-- expect_file {'.bashrc', contains = 'source ~/.aliases'}
+
+-- selene: allow(unused_variable)
+function expect_file(arg)
+    if type(arg) == 'string' then
+        pkg:expect_file(arg, {})
+    elseif type(arg) == 'table' then
+        check_keys('expect_file', arg, 1, { 'contains' })
+        local dest = arg[1] or error 'expect_file dest was not provided'
+        pkg:expect_file(dest, { contains = arg.contains })
+    else
+        error 'expect_file arg must be a string or table'
+    end
 end
 
+-- template {'d.hbs', 'j.txt', vars = {}}
 -- template {'d.hbs', 'j.txt', engine = 'handlebars', vars = {}}
 -- template {'d.tmpl', 'k.txt', engine = 'liquid', vars = {}}
+-- template {'d.tmpl', 'k.txt', engine = 'gotmpl', vars = {}}
 -- template {'d.hbs', 'j.txt', engine = 'hbs', vars = {}, optional = true}
+-- template {'d.hbs', 'j.sh', vars = {}, mode = '0755'}
+-- template {'d.hbs', '/etc/j.conf', vars = {}, owner = 'root', group = 'root'}
 
 -- selene: allow(unused_variable)
 function template(arg)
     if type(arg) == 'table' then
-        local engine = arg.engine or error 'template engine was not provided'
-        if engine == 'hbs' then
+        local engine = arg.engine or 'auto'
+        if engine == 'auto' then
+            check_keys('template', arg, 2, { 'vars', 'optional', 'auto_header', 'mode', 'owner', 'group', 'engine' })
+            local src = arg[1] or error 'template src was not provided'
+            local dest = arg[2] or error 'template dest was not provided'
+            local vars = arg.vars or error 'template vars was not provided'
+
+            pkg:template(src, dest, vars, arg.optional, arg.auto_header, arg.mode, arg.owner, arg.group)
+        elseif engine == 'hbs' then
             hbs(arg)
         elseif engine == 'liquid' then
             liquid(arg)
+        elseif engine == 'gotmpl' then
+            gotmpl(arg)
         else
-            error 'template engine must be hbs or liquid'
+            error 'template engine must be auto, hbs, liquid, or gotmpl'
         end
     else
         error 'template arg must be a table'
@@ -124,54 +471,123 @@ end
 
 -- hbs {'b.hbs', 'h.txt', vars = {}}
 -- hbs {'b.hbs', 'h.txt', vars = {}, optional = true}
+-- hbs {'b.hbs', 'h.sh', vars = {}, auto_header = true}
+-- hbs {'b.hbs', 'h.sh', vars = {}, mode = '0755'}
 
 -- selene: allow(unused_variable)
 function hbs(arg)
+    check_keys('hbs', arg, 2, { 'vars', 'partials', 'optional', 'auto_header', 'mode', 'owner', 'group', 'engine' })
     local src = arg[1] or error 'template src was not provided'
     local dest = arg[2] or error 'template dest was not provided'
     local vars = arg.vars or error 'template vars was not provided'
     local partials = arg.partials or {}
     local optional = arg.optional
+    local auto_header = arg.auto_header
+    local mode = arg.mode
+    local owner = arg.owner
+    local group = arg.group
 
-    pkg:hbs(src, dest, vars, partials, optional)
+    pkg:hbs(src, dest, vars, partials, optional, auto_header, mode, owner, group)
+end
+
+-- hbs_helper('upper', function(s) return s:upper() end)
+-- Register a helper for all Handlebars templates of the package, e.g. {{upper name}}. It is called
+-- with the values of its parameters, missing variables being nil, and renders what it returns.
+
+-- selene: allow(unused_variable)
+function hbs_helper(name, fun)
+    if type(fun) ~= 'function' then
+        error 'hbs_helper fun must be a function'
+    end
+
+    pkg:hbs_helper(name, fun)
 end
 
 -- liquid {'b.tmpl', 'i.txt', vars = {}}
 -- liquid {'b.tmpl', 'i.txt', vars = {}, optional = true}
+-- liquid {'b.tmpl', 'i.sh', vars = {}, auto_header = true}
+-- liquid {'b.tmpl', 'i.sh', vars = {}, mode = '0755'}
 
 -- selene: allow(unused_variable)
 function liquid(arg)
+    check_keys('liquid', arg, 2, { 'vars', 'optional', 'auto_header', 'mode', 'owner', 'group', 'engine' })
+    local src = arg[1] or error 'template src was not provided'
+    local dest = arg[2] or error 'template dest was not provided'
+    local vars = arg.vars or error 'template vars was not provided'
+    local optional = arg.optional
+    local auto_header = arg.auto_header
+    local mode = arg.mode
+    local owner = arg.owner
+    local group = arg.group
+
+    pkg:liquid(src, dest, vars, optional, auto_header, mode, owner, group)
+end
+
+-- gotmpl {'b.tmpl', 'i.txt', vars = {}}
+-- gotmpl {'b.tmpl', 'i.txt', vars = {}, optional = true}
+-- gotmpl {'b.tmpl', 'i.sh', vars = {}, auto_header = true}
+-- gotmpl {'b.tmpl', 'i.sh', vars = {}, mode = '0755'}
+
+-- selene: allow(unused_variable)
+function gotmpl(arg)
+    check_keys('gotmpl', arg, 2, { 'vars', 'optional', 'auto_header', 'mode', 'owner', 'group', 'engine' })
     local src = arg[1] or error 'template src was not provided'
     local dest = arg[2] or error 'template dest was not provided'
     local vars = arg.vars or error 'template vars was not provided'
     local optional = arg.optional
+    local auto_header = arg.auto_header
+    local mode = arg.mode
+    local owner = arg.owner
+    local group = arg.group
 
-    pkg:liquid(src, dest, vars, optional)
+    pkg:gotmpl(src, dest, vars, optional, auto_header, mode, owner, group)
+end
+
+-- pipe {'a.txt', '.a.txt', function(contents) return contents:upper() end}
+-- pipe {'b.conf', '.b.conf', function(contents) return (contents:gsub('%$HOST', 'laptop')) end, optional = true}
+-- pipe {'c.sh', '.c.sh', function(contents) return contents end, mode = '0755'}
+
+-- selene: allow(unused_variable)
+function pipe(arg)
+    if type(arg) == 'table' then
+        check_keys('pipe', arg, 3, { 'optional', 'mode', 'owner', 'group' })
+        local src = arg[1] or error 'pipe src was not provided'
+        local dest = arg[2] or error 'pipe dest was not provided'
+        local fun = arg[3] or error 'pipe function was not provided'
+
+        pkg:pipe(src, dest, fun, arg.optional, arg.mode, arg.owner, arg.group)
+    else
+        error 'pipe arg must be a table'
+    end
 end
 
 -- empty 'l.txt'
 -- empty {'m.txt'}
+-- empty {'m.txt', mode = '0600'}
 
 -- selene: allow(unused_variable)
 function empty(arg)
     if type(arg) == 'string' then
         pkg:empty(arg)
     elseif type(arg) == 'table' then
+        check_keys('empty', arg, 1, { 'mode', 'owner', 'group' })
         local path = arg[1] or error 'empty dest was not provided'
-        pkg:empty(path)
+        pkg:empty(path, arg.mode, arg.owner, arg.group)
     else
         error 'empty dest must be a string or table'
     end
 end
 
 -- string {'n.txt', 'contents'}
+-- string {'n.txt', 'contents', mode = '0600'}
 
 -- selene: allow(unused_variable)
 function str(arg)
     if type(arg) == 'table' then
+        check_keys('str', arg, 2, { 'mode', 'owner', 'group' })
         local dest = arg[1] or error 'str dest was not provided'
         local contents = arg[2] or error 'str contents was not provided'
-        pkg:str(dest, contents)
+        pkg:str(dest, contents, arg.mode, arg.owner, arg.group)
     else
         error 'str arg must be a table'
     end
@@ -179,14 +595,22 @@ end
 
 -- yaml {'o.txt', {}}
 -- yaml {'p.txt', {}, header = '# header'}
+-- yaml {'p.txt', {}, schema = 'schema.json'}
+-- yaml {'p.txt', {}, auto_header = true}
+-- yaml {'p.txt', {}, sort_keys = true}
+-- yaml {'p.txt', {}, mode = '0600'}
 
 -- selene: allow(unused_variable)
 function yaml(arg)
     if type(arg) == 'table' then
+        check_keys('yaml', arg, 2, { 'header', 'auto_header', 'schema', 'sort_keys', 'mode', 'owner', 'group' })
         local dest = arg[1] or error 'yaml dest was not provided'
         local values = arg[2] or error 'yaml values were not provided'
         local header = arg.header
-        pkg:yaml(dest, values, header)
+        local auto_header = arg.auto_header
+        local schema = arg.schema
+        local sort_keys = arg.sort_keys
+        pkg:yaml(dest, values, header, auto_header, schema, sort_keys, arg.mode, arg.owner, arg.group)
     else
         error 'yaml arg must be a table'
     end
@@ -194,27 +618,41 @@ end
 
 -- toml {'q.txt', {}}
 -- toml {'r.txt', {}, header = '# header'}
+-- toml {'r.txt', {}, schema = 'schema.json'}
+-- toml {'r.txt', {}, auto_header = true}
+-- toml {'r.txt', {}, sort_keys = true}
+-- toml {'r.txt', {}, mode = '0600'}
 
 -- selene: allow(unused_variable)
 function toml(arg)
     if type(arg) == 'table' then
+        check_keys('toml', arg, 2, { 'header', 'auto_header', 'schema', 'sort_keys', 'mode', 'owner', 'group' })
         local dest = arg[1] or error 'toml dest was not provided'
         local values = arg[2] or error 'toml values were not provided'
         local header = arg.header
-        pkg:toml(dest, values, header)
+        local auto_header = arg.auto_header
+        local schema = arg.schema
+        local sort_keys = arg.sort_keys
+        pkg:toml(dest, values, header, auto_header, schema, sort_keys, arg.mode, arg.owner, arg.group)
     else
         error 'toml arg must be a table'
     end
 end
 
 -- json {'s.txt', {}}
+-- json {'s.txt', {}, schema = 'schema.json'}
+-- json {'s.txt', {}, sort_keys = true}
+-- json {'s.txt', {}, mode = '0600'}
 
 -- selene: allow(unused_variable)
 function json(arg)
     if type(arg) == 'table' then
+        check_keys('json', arg, 2, { 'schema', 'sort_keys', 'mode', 'owner', 'group' })
         local dest = arg[1] or error 'toml dest was not provided'
         local values = arg[2] or error 'toml values were not provided'
-        pkg:json(dest, values)
+        local schema = arg.schema
+        local sort_keys = arg.sort_keys
+        pkg:json(dest, values, schema, sort_keys, arg.mode, arg.owner, arg.group)
     else
         error 'json arg must be a table'
     end
@@ -222,13 +660,16 @@ end
 
 -- mkdir 'd'
 -- mkdir {'d'}
+-- mkdir {'d', parents = true, mode = '0700'}
+-- mkdir {'/srv/d', parents = true, owner = 'www-data', group = 'www-data'}
 
 -- selene: allow(unused_variable)
 function mkdir(arg)
     if type(arg) == 'table' then
+        check_keys('mkdir', arg, 1, { 'parents', 'mode', 'owner', 'group' })
         local dest = arg[1] or error 'mkdir dest was not provided'
         local parents = arg.parents or error 'mkdir parents was not provided'
-        pkg:mkdir(dest, parents)
+        pkg:mkdir(dest, parents, arg.mode, arg.owner, arg.group)
     else
         pkg:mkdir(arg, true)
     end
@@ -236,17 +677,20 @@ end
 
 -- cmd [[echo "a"]]
 -- cmd {[[echo "a"]]}
--- cmd {[[echo "a"]], quiet = true}
+-- cmd {[[echo "a"]], stdout = false}
 -- cmd {[[echo "a"]], start = "tree"}
 -- cmd {[[echo "a"]], shell = "zsh"}
--- cmd {[[echo "a"]], quiet = true, start = "tree"}
--- cmd {[[echo "a"]], quiet = true, shell = "zsh"}
+-- cmd {[[echo "a"]], stdout = false, start = "tree"}
+-- cmd {[[echo "a"]], stdout = false, shell = "zsh"}
 -- cmd {[[echo "a"]], start = "tree", shell = "zsh"}
--- cmd {[[echo "a"]], quiet = true, start = "tree", shell = "zsh"}
+-- cmd {[[echo "a"]], stdout = false, start = "tree", shell = "zsh"}
+-- cmd {[[echo "a"]], only_if_changed = true}
+-- cmd {[[make install]], timeout = 300}
+-- cmd {[[Write-Output "a"]], shell = "powershell"}
 
 -- selene: allow(unused_variable)
 function cmd(arg)
-    local command, start, shell, stdout, stderr, clean_env, env, nonzero_exit
+    local command, start, shell, stdout, stderr, clean_env, env, nonzero_exit, only_if_changed, timeout
     if type(arg) == 'string' then
         command = arg
         start = nil
@@ -256,7 +700,20 @@ function cmd(arg)
         clean_env = nil
         env = nil
         nonzero_exit = nil
+        only_if_changed = nil
+        timeout = nil
     elseif type(arg) == 'table' then
+        check_keys('cmd', arg, 1, {
+            'start',
+            'shell',
+            'stdout',
+            'stderr',
+            'clean_env',
+            'env',
+            'nonzero_exit',
+            'only_if_changed',
+            'timeout',
+        })
         command = arg[1] or error 'cmd command was not provided'
         start = arg.start
         shell = arg.shell
@@ -265,31 +722,174 @@ function cmd(arg)
         clean_env = arg.clean_env
         env = arg.env
         nonzero_exit = arg.nonzero_exit
+        only_if_changed = arg.only_if_changed
+        timeout = arg.timeout
     else
         error 'cmd arg must be a string or table'
     end
 
-    pkg:cmd(command, start, shell, stdout, stderr, clean_env, env, nonzero_exit)
+    pkg:cmd(command, start, shell, stdout, stderr, clean_env, env, nonzero_exit, only_if_changed, timeout)
 end
 
 -- fn(function() print("a") end)
 -- fn {function() print("a") end}
 -- fn {function() print("a") end, error_exit = "error"}
+-- fn {function() print("a") end, only_if_changed = true}
+-- fn(function(ctx) for _, path in ipairs(ctx.changed_paths()) do print(path) end end)
+-- fn(function() hook.link('a.txt', '.a.txt') end)
+-- fn(function() if not hook.write('.b.txt', 'b') then print('failed') end end)
+-- fn(function() hook.cmd('echo c') end)
+-- Changes requested through hook.* are made by shelf as ops of their own, which are journaled and
+-- can be rolled back, rather than by the function itself. Each returns whether it succeeded.
 
 -- selene: allow(unused_variable)
 function fn(arg)
-    local fun, start, error_exit
+    local fun, start, error_exit, only_if_changed
     if type(arg) == 'function' then
         fun = arg
         start = nil
         error_exit = nil
+        only_if_changed = nil
     elseif type(arg) == 'table' then
+        check_keys('fn', arg, 1, { 'start', 'error_exit', 'only_if_changed' })
         fun = arg[1] or error 'fn function was not provided'
         start = arg.start
         error_exit = arg.error_exit
+        only_if_changed = arg.only_if_changed
     else
         error 'fn arg must be a function or table'
     end
 
-    pkg:fn(fun, start, error_exit)
+    pkg:fn(fun, start, error_exit, only_if_changed)
+end
+
+-- Requests yielded by fn hooks. See FunctionYield.
+hook = {
+    link = function(src, dest)
+        return coroutine.yield { kind = 'link', src = src, dest = dest }
+    end,
+    write = function(dest, contents)
+        return coroutine.yield { kind = 'write', dest = dest, contents = contents }
+    end,
+    cmd = function(command)
+        return coroutine.yield { kind = 'cmd', command = command }
+    end,
+}
+
+-- script 'scripts/setup.sh'
+-- script {'scripts/setup.sh'}
+-- script {'scripts/setup.sh', args = {'--force'}}
+-- script {'scripts/setup.sh', start = 'tree'}
+-- script {'scripts/setup.sh', only_if_changed = true}
+-- script {'scripts/setup.sh', timeout = 60}
+
+-- selene: allow(unused_variable)
+function script(arg)
+    local path, args, start, only_if_changed, timeout
+    if type(arg) == 'string' then
+        path = arg
+        args = nil
+        start = nil
+        only_if_changed = nil
+        timeout = nil
+    elseif type(arg) == 'table' then
+        check_keys('script', arg, 1, { 'args', 'start', 'only_if_changed', 'timeout' })
+        path = arg[1] or error 'script path was not provided'
+        args = arg.args
+        start = arg.start
+        only_if_changed = arg.only_if_changed
+        timeout = arg.timeout
+    else
+        error 'script arg must be a string or table'
+    end
+
+    pkg:script(path, args, start, only_if_changed, timeout)
+end
+
+-- Accept a `when` condition in the table argument of every directive. See only_if.
+--
+-- Likewise accept `on_conflict`, which says what to do if the destination of a link, copy, tree,
+-- template, or generated file already exists and differs: 'replace' it, 'skip' the directive,
+-- 'backup' the existing file to <dest>.shelf-backup and then replace it, or 'fail'. Directives
+-- without it follow --on-conflict, which defaults to 'replace'; --force replaces regardless.
+--
+-- File, tree, and template directives also accept `if_missing`, which says what to do if the
+-- parent directory of the destination doesn't exist, as when the application it configures isn't
+-- installed: 'create_parent' (the default), 'skip' the directive, or 'error'.
+local takes_if_missing = {
+    file = true,
+    link = true,
+    copy = true,
+    tree = true,
+    template = true,
+    hbs = true,
+    liquid = true,
+    gotmpl = true,
+}
+for _, name in ipairs {
+    'file',
+    'link',
+    'copy',
+    'tree',
+    'copy_dir',
+    'systemd_user_unit',
+    'fragment',
+    'source_line',
+    'defaults',
+    'regvalue',
+    'plugin',
+    'expect_symlink',
+    'expect_file',
+    'template',
+    'hbs',
+    'liquid',
+    'gotmpl',
+    'pipe',
+    'empty',
+    'str',
+    'yaml',
+    'toml',
+    'json',
+    'mkdir',
+    'cmd',
+    'fn',
+    'script',
+} do
+    local directive = _G[name]
+    _G[name] = function(arg)
+        if type(arg) ~= 'table' then
+            return directive(arg)
+        end
+        -- Other directives are left to reject it as an unknown key.
+        local if_missing = takes_if_missing[name] and arg.if_missing or nil
+        if arg.when == nil and arg.on_conflict == nil and if_missing == nil then
+            return directive(arg)
+        end
+
+        local rest = {}
+        for k, v in pairs(arg) do
+            if k ~= 'when' and k ~= 'on_conflict' and (k ~= 'if_missing' or if_missing == nil) then
+                rest[k] = v
+            end
+        end
+        if arg.when ~= nil then
+            pkg:push_when(arg.when)
+        end
+        if arg.on_conflict ~= nil then
+            pkg:push_on_conflict(arg.on_conflict)
+        end
+        if if_missing ~= nil then
+            pkg:push_if_missing(if_missing)
+        end
+        directive(rest)
+        if if_missing ~= nil then
+            pkg:pop_if_missing()
+        end
+        if arg.on_conflict ~= nil then
+            pkg:pop_on_conflict()
+        end
+        if arg.when ~= nil then
+            pkg:pop_when()
+        end
+    end
 end
@@ -1,4 +1,9 @@
+mod args;
+pub mod base;
+pub mod cache;
+pub mod options;
 mod specobject;
+pub mod version;
 
 use std::env;
 use std::fs::File;
@@ -6,12 +11,18 @@ use std::io::{self, Read};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
 
-use mlua::Lua;
+use mlua::{Lua, LuaSerdeExt};
 
 use crate::graph::PackageData;
+use crate::spec::{Object, Spec};
 
 use self::specobject::SpecObject;
 
+pub use self::base::{BaseError, BaseFetcher};
+pub use self::cache::{CacheKey, EnvReads, SpecCache};
+pub use self::options::OptionError;
+pub use self::version::VersionError;
+
 static CONFIG_FILE: &str = "package.lua";
 
 #[derive(Debug, thiserror::Error)]
@@ -20,6 +31,12 @@ pub enum LoadError {
     Read(#[from] io::Error),
     #[error("couldn't execute Lua")]
     Lua(#[from] mlua::Error),
+    #[error("couldn't layer the base package")]
+    Base(#[from] BaseError),
+    #[error("unsupported package")]
+    Version(#[from] VersionError),
+    #[error("invalid option value")]
+    Option(#[from] OptionError),
 }
 
 /// Loader for a package.
@@ -83,9 +100,35 @@ impl SpecLoaderEmpty {
         lua.globals().set("pkg", SpecObject::new())?;
         lua.load(std::include_str!("globals.lua")).exec()?;
 
+        // Record the environment variables the package reads, so that cached specs go stale when
+        // they change.
+        {
+            lua.set_app_data(EnvReads::default());
+            let getenv = lua.create_function(|lua, name: String| {
+                let value = env::var_os(&name).map(|value| value.to_string_lossy().into_owned());
+                if let Some(mut reads) = lua.app_data_mut::<EnvReads>() {
+                    reads.insert(name, value.clone());
+                }
+                Ok(value)
+            })?;
+            let os: mlua::Table = lua.globals().get("os")?;
+            os.set("getenv", getenv)?;
+        }
+
         Ok(lua)
     }
 
+    /// Expose `vars`, the template variables of the last successful application of the package,
+    /// to Lua as `shelf.previous_vars`. It is an empty table otherwise.
+    #[inline]
+    pub fn previous_vars(self, vars: &Object) -> Result<Self, LoadError> {
+        {
+            let shelf: mlua::Table = self.lua.globals().get("shelf")?;
+            shelf.set("previous_vars", self.lua.to_value(vars)?)?;
+        }
+        Ok(self)
+    }
+
     /// Load the package, returning a [`PackageData`].
     #[inline]
     pub fn load<P>(path: P) -> Result<PackageData, LoadError>
@@ -119,7 +162,34 @@ impl SpecLoaderEmpty {
     }
 }
 
+impl<S> SpecLoader<S>
+where
+    S: SpecLoaderState,
+{
+    /// Return the path of the package.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the configuration contents. This is empty if the configuration has not been read.
+    #[inline]
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+}
+
 impl SpecLoaderRead {
+    /// Skip evaluation and finish with a previously evaluated spec (e.g. from a [`SpecCache`]).
+    #[inline]
+    pub fn with_spec(self, spec: Spec) -> PackageData {
+        PackageData {
+            path: self.path,
+            spec,
+            lua: self.lua,
+        }
+    }
+
     #[inline]
     pub fn eval(self) -> Result<SpecLoaderEvaled, mlua::Error> {
         // FIXME propogate error
@@ -152,6 +222,15 @@ impl SpecLoaderRead {
 }
 
 impl SpecLoaderEvaled {
+    /// Return the environment variables read by the package during evaluation.
+    #[inline]
+    pub fn env_reads(&self) -> EnvReads {
+        self.lua
+            .app_data_ref::<EnvReads>()
+            .map(|reads| reads.clone())
+            .unwrap_or_default()
+    }
+
     #[inline]
     pub fn to_package_data(self) -> Result<PackageData, mlua::Error> {
         let package: SpecObject = self.lua.globals().get("pkg")?;
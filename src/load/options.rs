@@ -0,0 +1,164 @@
+use crate::spec::{Directive, File, Object, ObjectValue, OptionType, PackageOption, Spec};
+
+/// Error encountered when checking the value of a package option against its declaration.
+#[derive(Debug, thiserror::Error)]
+pub enum OptionError {
+    /// The value isn't of the declared type of the option.
+    #[error("option '{name}' expects a value of type {}, but got {found}", .expected.name())]
+    Type {
+        name: String,
+        expected: OptionType,
+        found: &'static str,
+    },
+}
+
+/// Check `value`, given for the option `name`, against `option`.
+#[inline]
+pub fn check(name: &str, option: &PackageOption, value: &ObjectValue) -> Result<(), OptionError> {
+    match option.typ {
+        Some(typ) if !typ.matches(value) => Err(OptionError::Type {
+            name: name.to_string(),
+            expected: typ,
+            found: value.type_name(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Check the values in `overrides` of the options declared by `spec` against their declarations,
+/// and give the defaults of its options to every template that doesn't set them itself. Variables
+/// that aren't options are left alone, and `overrides` still need to be applied with
+/// [`Spec::override_vars`].
+#[inline]
+pub fn apply(spec: &mut Spec, overrides: &Object) -> Result<(), OptionError> {
+    for (name, option) in &spec.options {
+        if let Some(value) = overrides.0.get(name) {
+            check(name, option, value)?;
+        }
+    }
+
+    let defaults: Vec<_> = spec
+        .options
+        .iter()
+        .filter_map(|(name, option)| Some((name, option.default.as_ref()?)))
+        .collect();
+    for drct in &mut spec.directives {
+        if let Directive::File(File::Templated(tf)) = drct {
+            for (name, default) in &defaults {
+                tf.vars
+                    .0
+                    .entry(name.to_string())
+                    .or_insert_with(|| (*default).clone());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use indexmap::IndexMap;
+
+    use crate::spec::{
+        Directive, EnvMap, File, LiquidTemplatedFile, Object, ObjectValue, OptionType,
+        PackageOption, Scope, Spec, TemplatedFile, TemplatedFileType,
+    };
+
+    use super::{apply, OptionError};
+
+    fn spec(vars: Object) -> Spec {
+        let mut options = BTreeMap::new();
+        options.insert(
+            "font_size".to_string(),
+            PackageOption {
+                default: Some(ObjectValue::Int(12)),
+                typ: Some(OptionType::Number),
+            },
+        );
+        options.insert(
+            "theme".to_string(),
+            PackageOption {
+                default: None,
+                typ: Some(OptionType::String),
+            },
+        );
+
+        Spec {
+            name: String::new(),
+            deps: Vec::new(),
+            requires: None,
+            base: None,
+            env: EnvMap::new(),
+            scope: Scope::default(),
+            directives: vec![Directive::File(File::Templated(TemplatedFile {
+                src: "a.hbs".into(),
+                dest: "a".into(),
+                vars,
+                typ: TemplatedFileType::Liquid(LiquidTemplatedFile {}),
+                optional: false,
+                auto_header: false,
+                mode: None,
+                owner: None,
+                group: None,
+            }))],
+            conditions: Vec::new(),
+            on_conflict: Vec::new(),
+            if_missing: Vec::new(),
+            profiles: Vec::new(),
+            hbs_helpers: BTreeMap::new(),
+            options,
+        }
+    }
+
+    fn vars(spec: &Spec) -> &IndexMap<String, ObjectValue> {
+        match &spec.directives[0] {
+            Directive::File(File::Templated(tf)) => &tf.vars.0,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        // Defaults are given to templates, but don't replace their own variables.
+        let mut s = spec(Object::new());
+        apply(&mut s, &Object::new()).unwrap();
+        assert_eq!(vars(&s)["font_size"], ObjectValue::Int(12));
+        assert!(!vars(&s).contains_key("theme"));
+
+        let mut own = Object::new();
+        own.0
+            .insert("font_size".to_string(), ObjectValue::Float(10.5));
+        let mut s = spec(own);
+        apply(&mut s, &Object::new()).unwrap();
+        assert_eq!(vars(&s)["font_size"], ObjectValue::Float(10.5));
+
+        // Overrides are checked against the types of the options, but others are left alone.
+        let mut overrides = Object::new();
+        overrides
+            .0
+            .insert("theme".to_string(), ObjectValue::Str("dark".into()));
+        overrides
+            .0
+            .insert("other".to_string(), ObjectValue::Bool(true));
+        assert!(apply(&mut spec(Object::new()), &overrides).is_ok());
+
+        overrides
+            .0
+            .insert("font_size".to_string(), ObjectValue::Str("big".into()));
+        match apply(&mut spec(Object::new()), &overrides) {
+            Err(OptionError::Type {
+                name,
+                expected,
+                found,
+            }) => {
+                assert_eq!(name, "font_size");
+                assert_eq!(expected, OptionType::Number);
+                assert_eq!(found, "string");
+            }
+            res => panic!("expected a type error, got {:?}", res),
+        }
+    }
+}
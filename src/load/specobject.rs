@@ -1,16 +1,25 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 
 use mlua::{
-    Error as LuaError, FromLua, Function, UserData, UserDataMethods, Value as LuaValue, Variadic,
+    AnyUserData, Error as LuaError, FromLua, Function, LuaSerdeExt, MultiValue, Table, UserData,
+    UserDataMethods, Value as LuaValue, Variadic,
 };
 use uuid::Uuid;
 
+use super::args;
+
+use crate::action::template::{Engine, ENGINES};
+
 use crate::spec::{
-    CmdHook, Dep, DirFile, Directive, EmptyGeneratedFile, File, FunHook, GeneratedFile,
-    GeneratedFileTyp, HandlebarsTemplatedFile, Hook, JsonGeneratedFile, LinkType,
-    LiquidTemplatedFile, NonZeroExitBehavior, Object, ObjectValue, Patterns, RegularFile, Spec,
-    StringGeneratedFile, TemplatedFile, TemplatedFileType, TomlGeneratedFile, TreeFile,
-    YamlGeneratedFile,
+    AutoTemplatedFile, Base, CmdHook, Condition, ConflictPolicy, CopyDirFile, DefaultsFile,
+    DefaultsValue, Dep, DirFile, Directive, EmptyGeneratedFile, EnvMap, ExpectFile, Expectation,
+    File, FragmentFile, FunHook, GeneratedFile, GeneratedFileTyp, Gid, GotmplTemplatedFile,
+    HandlebarsTemplatedFile, Hook, JsonGeneratedFile, LinkType, LiquidTemplatedFile,
+    MissingParentPolicy, Mode, NonZeroExitBehavior, Object, ObjectValue, OptionType, PackageOption,
+    Patterns, PipedFile, PluginFile, RegValueFile, RegistryValue, RegularFile, Scope, ScriptHook,
+    SourceLineFile, Spec, StringGeneratedFile, SystemdUnitFile, TemplatedFile, TemplatedFileType,
+    TomlGeneratedFile, TreeFile, Uid, YamlGeneratedFile,
 };
 
 pub trait SpecLoaderState {}
@@ -18,6 +27,19 @@ pub trait SpecLoaderState {}
 #[derive(Debug, Clone)]
 pub(super) struct SpecObject {
     pub(super) spec: Spec,
+
+    /// Condition set by `pkg:only_if`, which applies to the directives that follow.
+    only_if: Option<Condition>,
+    /// Conditions of the directives being added, e.g. from their `when` arguments.
+    when: Vec<Condition>,
+    /// Conflict policies of the directives being added, from their `on_conflict` arguments. The
+    /// innermost applies.
+    on_conflict: Vec<ConflictPolicy>,
+    /// Missing parent policies of the directives being added, from their `if_missing` arguments.
+    /// The innermost applies.
+    if_missing: Vec<MissingParentPolicy>,
+    /// Profiles of the directives being added, from the enclosing `pkg:profile` blocks.
+    profiles: Vec<String>,
 }
 
 impl SpecObject {
@@ -27,10 +49,37 @@ impl SpecObject {
             spec: Spec {
                 name: String::new(),
                 deps: Vec::new(),
+                requires: None,
+                base: None,
+                env: EnvMap::new(),
+                scope: Scope::default(),
                 directives: Vec::new(),
+                conditions: Vec::new(),
+                on_conflict: Vec::new(),
+                if_missing: Vec::new(),
+                profiles: Vec::new(),
+                hbs_helpers: BTreeMap::new(),
+                options: BTreeMap::new(),
             },
+            only_if: None,
+            when: Vec::new(),
+            on_conflict: Vec::new(),
+            if_missing: Vec::new(),
+            profiles: Vec::new(),
         }
     }
+
+    /// Add `drct`, under the conditions, conflict and missing parent policies, and profiles
+    /// currently in effect.
+    #[inline]
+    fn push(&mut self, drct: Directive) {
+        let conditions = self.only_if.iter().chain(&self.when).cloned().collect();
+        self.spec.directives.push(drct);
+        self.spec.conditions.push(conditions);
+        self.spec.on_conflict.push(self.on_conflict.last().copied());
+        self.spec.if_missing.push(self.if_missing.last().copied());
+        self.spec.profiles.push(self.profiles.clone());
+    }
 }
 
 impl UserData for SpecObject {
@@ -39,12 +88,18 @@ impl UserData for SpecObject {
 
     #[inline]
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        // Directive methods take their arguments either positionally, or by name in a single
+        // table, e.g. `pkg:file('a', 'b')` or `pkg:file{ src = 'a', dest = 'b' }`.
         macro_rules! method {
             ($name:expr; ($($arg:ident; $ty:ty),*); $drct:expr) => {
                 #[allow(unused_parens)]
-                methods.add_method_mut($name, |_, this, arg: ($($ty),*)| {
-                    let ($($arg),*) = arg;
-                    this.spec.directives.push($drct);
+                methods.add_method_mut($name, |lua, this, args: MultiValue| {
+                    let max = [$(stringify!($arg)),*].len();
+                    let ($($arg),*): ($($ty),*) = args::convert($name, args, max, lua, |named| {
+                        let args = ($(named.get(stringify!($arg))?),*);
+                        Ok(args)
+                    })?;
+                    this.push($drct);
                     Ok(())
                 });
             };
@@ -64,6 +119,16 @@ impl UserData for SpecObject {
             Ok(())
         });
 
+        methods.add_method_mut("requires", |_, this, version: String| {
+            this.spec.requires = Some(version);
+            Ok(())
+        });
+
+        methods.add_method_mut("scope", |_, this, scope: Scope| {
+            this.spec.scope = scope;
+            Ok(())
+        });
+
         methods.add_method_mut("dep", |_, this, paths: Variadic<String>| {
             this.spec
                 .deps
@@ -71,26 +136,193 @@ impl UserData for SpecObject {
             Ok(())
         });
 
-        method!("file"; (src; String, dest; Option<String>, link_type; Option<LinkType>, optional; Option<bool>);
+        methods.add_method_mut("base", |lua, this, args: MultiValue| {
+            let (source, rev, vars): (String, Option<String>, Option<Object>) =
+                args::convert("base", args, 3, lua, |named| {
+                    Ok((named.get("source")?, named.get("rev")?, named.get("vars")?))
+                })?;
+
+            this.spec.base = Some(Base {
+                source,
+                rev,
+                vars: vars.unwrap_or_default(),
+            });
+            Ok(())
+        });
+
+        methods.add_method_mut("only_if", |_, this, condition: Option<Condition>| {
+            this.only_if = condition;
+            Ok(())
+        });
+
+        methods.add_method_mut("push_when", |_, this, condition: Condition| {
+            this.when.push(condition);
+            Ok(())
+        });
+
+        methods.add_method_mut("pop_when", |_, this, ()| {
+            this.when.pop();
+            Ok(())
+        });
+
+        methods.add_method_mut("push_on_conflict", |_, this, policy: ConflictPolicy| {
+            this.on_conflict.push(policy);
+            Ok(())
+        });
+
+        methods.add_method_mut("pop_on_conflict", |_, this, ()| {
+            this.on_conflict.pop();
+            Ok(())
+        });
+
+        methods.add_method_mut("push_if_missing", |_, this, policy: MissingParentPolicy| {
+            this.if_missing.push(policy);
+            Ok(())
+        });
+
+        methods.add_method_mut("pop_if_missing", |_, this, ()| {
+            this.if_missing.pop();
+            Ok(())
+        });
+
+        // The spec object isn't borrowed while the block runs, since the block adds directives to
+        // it.
+        methods.add_function(
+            "profile",
+            |_, (this, name, block): (AnyUserData, String, Function)| {
+                if name.is_empty() || name.contains(',') {
+                    return Err(LuaError::RuntimeError(format!(
+                        "pkg:profile: invalid profile name '{}'",
+                        name
+                    )));
+                }
+
+                this.borrow_mut::<SpecObject>()?.profiles.push(name);
+                let res = block.call::<_, ()>(());
+                this.borrow_mut::<SpecObject>()?.profiles.pop();
+                res
+            },
+        );
+
+        methods.add_method_mut(
+            "hbs_helper",
+            |lua, this, (name, fun): (String, Function)| {
+                let key = Uuid::new_v4().to_string();
+                lua.set_named_registry_value(&key, fun)?;
+                this.spec.hbs_helpers.insert(name, key);
+                Ok(())
+            },
+        );
+
+        methods.add_method_mut(
+            "option",
+            |lua, this, (name, decl): (String, Option<Table>)| {
+                let (default, typ): (Option<ObjectValue>, Option<OptionType>) = match decl {
+                    Some(decl) => args::convert_named("option", decl, lua, |named| {
+                        Ok((named.get("default")?, named.get("type")?))
+                    })?,
+                    None => (None, None),
+                };
+
+                let option = PackageOption { default, typ };
+                if let Some(default) = &option.default {
+                    super::options::check(&name, &option, default).map_err(|err| {
+                        LuaError::RuntimeError(format!("pkg:option: invalid default: {}", err))
+                    })?;
+                }
+                this.spec.options.insert(name, option);
+                Ok(())
+            },
+        );
+
+        methods.add_method_mut("env", |_, this, env: HashMap<String, String>| {
+            this.spec.env.extend(env);
+            Ok(())
+        });
+
+        method!("file"; (src; String, dest; Option<String>, link_type; Option<LinkType>,
+                         optional; Option<bool>, mode; Option<Mode>, owner; Option<Uid>,
+                         group; Option<Gid>);
         File; File::Regular(RegularFile {
             src: src.into(),
             dest: dest.map(Into::into),
             link_type: link_type.unwrap_or(LinkType::Link),
-            optional: optional.unwrap_or(false)
+            optional: optional.unwrap_or(false),
+            mode,
+            owner,
+            group,
         }));
 
         method!("tree"; (src; String, dest; Option<String>, link_type; Option<LinkType>,
-                         globs; Option<Patterns>, ignore; Option<Patterns>, optional; Option<bool>);
+                         globs; Option<Patterns>, ignore; Option<Patterns>,
+                         volatile; Option<Patterns>, optional; Option<bool>,
+                         strip_prefix; Option<String>, layers; Option<Vec<String>>);
         File; File::Tree(TreeFile {
             src: src.into(),
+            layers: layers.unwrap_or_default().into_iter().map(Into::into).collect(),
             dest: dest.map(Into::into),
             globs,
             ignore,
+            volatile,
+            strip_prefix: strip_prefix.map(Into::into),
             link_type: link_type.unwrap_or(LinkType::Link),
             optional: optional.unwrap_or(false)
         }));
 
-        method!("hbs"; (src; String, dest; String, vars; Object, partials; HashMap<String, String>, optional; Option<bool>);
+        method!("copy_dir"; (src; String, dest; Option<String>, volatile; Option<Patterns>,
+                             optional; Option<bool>);
+        File; File::CopyDir(CopyDirFile {
+            src: src.into(),
+            dest: dest.map(Into::into),
+            volatile,
+            optional: optional.unwrap_or(false)
+        }));
+
+        method!("systemd_user_unit"; (src; String, enable; Option<bool>,
+                                      restart_on_change; Option<bool>);
+        File; File::SystemdUnit(SystemdUnitFile {
+            src: src.into(),
+            enable: enable.unwrap_or(false),
+            restart_on_change: restart_on_change.unwrap_or(false)
+        }));
+
+        method!("fragment"; (dir; String, name; String, contents; String);
+        File; File::Fragment(FragmentFile { dir: dir.into(), name, contents }));
+
+        method!("source_line"; (rc; String, line; String);
+        File; File::SourceLine(SourceLineFile { rc: rc.into(), line }));
+
+        method!("defaults"; (domain; String, key; String, value; DefaultsValue);
+        File; File::Defaults(DefaultsFile { domain, key, value }));
+
+        method!("regvalue"; (key; String, name; String, value; RegistryValue);
+        File; File::RegValue(RegValueFile { key, name, value }));
+
+        method!("plugin"; (module; String, kind; String, args; Option<Object>);
+        File; File::Plugin(PluginFile {
+            module: module.into(),
+            kind,
+            args: args.unwrap_or_default(),
+            root: PathBuf::new(),
+        }));
+
+        method!("expect_symlink"; (dest; String, target; String);
+        File; File::Expect(ExpectFile {
+            dest: dest.into(),
+            expectation: Expectation::Symlink(target.into()),
+        }));
+
+        method!("expect_file"; (dest; String, opts; Option<ExpectFileOpts>);
+        File; File::Expect(ExpectFile {
+            dest: dest.into(),
+            expectation: Expectation::File {
+                contains: opts.and_then(|opts| opts.contains),
+            },
+        }));
+
+        method!("hbs"; (src; String, dest; String, vars; Object, partials; HashMap<String, String>,
+                        optional; Option<bool>, auto_header; Option<bool>, mode; Option<Mode>,
+                        owner; Option<Uid>, group; Option<Gid>);
         File; {
             let partials = partials.into_iter().map(|(k, v)| (k, v.into())).collect();
             File::Templated(TemplatedFile {
@@ -98,50 +330,185 @@ impl UserData for SpecObject {
                 dest: dest.into(),
                 vars,
                 typ: TemplatedFileType::Handlebars(HandlebarsTemplatedFile { partials }),
-                optional: optional.unwrap_or(false)
+                optional: optional.unwrap_or(false),
+                auto_header: auto_header.unwrap_or(false),
+                mode,
+                owner,
+                group,
             })
         });
 
-        method!("liquid"; (src; String, dest; String, vars; Object, optional; Option<bool>);
+        method!("liquid"; (src; String, dest; String, vars; Object, optional; Option<bool>,
+                           auto_header; Option<bool>, mode; Option<Mode>, owner; Option<Uid>,
+                           group; Option<Gid>);
         File; File::Templated(TemplatedFile {
             src: src.into(),
             dest: dest.into(),
             vars,
             typ: TemplatedFileType::Liquid(LiquidTemplatedFile {}),
-            optional: optional.unwrap_or(false)
+            optional: optional.unwrap_or(false),
+            auto_header: auto_header.unwrap_or(false),
+            mode,
+            owner,
+            group,
         }));
 
-        method!("empty"; (dest; String);
+        method!("gotmpl"; (src; String, dest; String, vars; Object, optional; Option<bool>,
+                           auto_header; Option<bool>, mode; Option<Mode>, owner; Option<Uid>,
+                           group; Option<Gid>);
+        File; File::Templated(TemplatedFile {
+            src: src.into(),
+            dest: dest.into(),
+            vars,
+            typ: TemplatedFileType::Gotmpl(GotmplTemplatedFile {}),
+            optional: optional.unwrap_or(false),
+            auto_header: auto_header.unwrap_or(false),
+            mode,
+            owner,
+            group,
+        }));
+
+        method!("template"; (src; String, dest; String, vars; Object, optional; Option<bool>,
+                             auto_header; Option<bool>, mode; Option<Mode>, owner; Option<Uid>,
+                             group; Option<Gid>);
+        File; {
+            if Engine::from_path(&src).is_none() {
+                let exts: Vec<_> = ENGINES.iter().map(|(ext, _)| format!(".{}", ext)).collect();
+                return Err(LuaError::RuntimeError(format!(
+                    "no template engine for '{}'; extension must be one of {}",
+                    src,
+                    exts.join(", ")
+                )));
+            }
+            File::Templated(TemplatedFile {
+                src: src.into(),
+                dest: dest.into(),
+                vars,
+                typ: TemplatedFileType::Auto(AutoTemplatedFile {}),
+                optional: optional.unwrap_or(false),
+                auto_header: auto_header.unwrap_or(false),
+                mode,
+                owner,
+                group,
+            })
+        });
+
+        methods.add_method_mut("pipe", |lua, this, args: MultiValue| {
+            type Args<'lua> = (
+                String,
+                String,
+                Function<'lua>,
+                Option<bool>,
+                Option<Mode>,
+                Option<Uid>,
+                Option<Gid>,
+            );
+            let (src, dest, fun, optional, mode, owner, group): Args =
+                args::convert("pipe", args, 7, lua, |named| {
+                    Ok((
+                        named.get("src")?,
+                        named.get("dest")?,
+                        named.get("fun")?,
+                        named.get("optional")?,
+                        named.get("mode")?,
+                        named.get("owner")?,
+                        named.get("group")?,
+                    ))
+                })?;
+
+            let name = Uuid::new_v4().to_string();
+            lua.set_named_registry_value(&name, fun)?;
+
+            let drct = Directive::File(File::Piped(PipedFile {
+                src: src.into(),
+                dest: dest.into(),
+                name,
+                optional: optional.unwrap_or(false),
+                mode,
+                owner,
+                group,
+            }));
+            this.push(drct);
+            Ok(())
+        });
+
+        method!("empty"; (dest; String, mode; Option<Mode>, owner; Option<Uid>, group; Option<Gid>);
         Gen; GeneratedFile {
-            dest: dest.into(), typ: GeneratedFileTyp::Empty(EmptyGeneratedFile)
+            dest: dest.into(), typ: GeneratedFileTyp::Empty(EmptyGeneratedFile), mode, owner, group
         });
-        method!("str"; (dest; String, contents; String);
+        method!("str"; (dest; String, contents; String, mode; Option<Mode>, owner; Option<Uid>,
+                        group; Option<Gid>);
         Gen; GeneratedFile {
-            dest: dest.into(), typ: GeneratedFileTyp::String(StringGeneratedFile { contents })
+            dest: dest.into(),
+            typ: GeneratedFileTyp::String(StringGeneratedFile { contents }),
+            mode,
+            owner,
+            group,
         });
-        method!("yaml"; (dest; String, values; Object, header; Option<String>);
+        method!("yaml"; (dest; String, values; Object, header; Option<String>,
+                         auto_header; Option<bool>, schema; Option<String>,
+                         sort_keys; Option<bool>, mode; Option<Mode>,
+                         owner; Option<Uid>, group; Option<Gid>);
         Gen; GeneratedFile {
-            dest: dest.into(), typ: GeneratedFileTyp::Yaml(YamlGeneratedFile { values, header })
+            dest: dest.into(),
+            typ: GeneratedFileTyp::Yaml(YamlGeneratedFile {
+                values,
+                header,
+                auto_header: auto_header.unwrap_or(false),
+                schema: schema.map(Into::into),
+                sort_keys: sort_keys.unwrap_or(false)
+            }),
+            mode,
+            owner,
+            group,
         });
-        method!("toml"; (dest; String, values; Object, header; Option<String>);
+        method!("toml"; (dest; String, values; Object, header; Option<String>,
+                         auto_header; Option<bool>, schema; Option<String>,
+                         sort_keys; Option<bool>, mode; Option<Mode>,
+                         owner; Option<Uid>, group; Option<Gid>);
         Gen; GeneratedFile {
-            dest: dest.into(), typ: GeneratedFileTyp::Toml(TomlGeneratedFile { values, header })
+            dest: dest.into(),
+            typ: GeneratedFileTyp::Toml(TomlGeneratedFile {
+                values,
+                header,
+                auto_header: auto_header.unwrap_or(false),
+                schema: schema.map(Into::into),
+                sort_keys: sort_keys.unwrap_or(false)
+            }),
+            mode,
+            owner,
+            group,
         });
-        method!("json"; (dest; String, values; Object);
+        method!("json"; (dest; String, values; Object, schema; Option<String>,
+                         sort_keys; Option<bool>, mode; Option<Mode>, owner; Option<Uid>,
+                         group; Option<Gid>);
         Gen; GeneratedFile {
-            dest: dest.into(), typ: GeneratedFileTyp::Json(JsonGeneratedFile { values })
+            dest: dest.into(),
+            typ: GeneratedFileTyp::Json(JsonGeneratedFile {
+                values,
+                schema: schema.map(Into::into),
+                sort_keys: sort_keys.unwrap_or(false)
+            }),
+            mode,
+            owner,
+            group,
         });
 
-        method!("mkdir"; (dest; String, parents; bool);
+        method!("mkdir"; (dest; String, parents; bool, mode; Option<Mode>, owner; Option<Uid>,
+                          group; Option<Gid>);
         File; File::Dir(DirFile {
             dest: dest.into(),
             parents,
+            mode,
+            owner,
+            group,
         }));
 
         method!("cmd"; (command; String, start; Option<String>, shell; Option<String>,
                         stdout; Option<bool>, stderr; Option<bool>,
                         clean_env; Option<bool>, env; Option<HashMap<String, String>>,
-                        nonzero_exit; Option<NonZeroExitBehavior>);
+                        nonzero_exit; Option<NonZeroExitBehavior>,
+                        only_if_changed; Option<bool>, timeout; Option<u64>);
         Hook; Hook::Cmd(CmdHook {
             command,
             start: start.map(Into::into),
@@ -150,28 +517,52 @@ impl UserData for SpecObject {
             stderr,
             clean_env,
             env,
-            nonzero_exit
+            nonzero_exit,
+            only_if_changed: only_if_changed.unwrap_or(false),
+            timeout
         }));
 
-        methods.add_method_mut(
-            "fn",
-            |lua, this, arg: (Function, Option<String>, Option<NonZeroExitBehavior>)| {
-                let (fun, start, nonzero_exit) = arg;
+        method!("script"; (path; String, args; Option<Vec<String>>, start; Option<String>,
+                           only_if_changed; Option<bool>, timeout; Option<u64>);
+        Hook; Hook::Script(ScriptHook {
+            path: path.into(),
+            args: args.unwrap_or_default(),
+            start: start.map(Into::into),
+            only_if_changed: only_if_changed.unwrap_or(false),
+            timeout
+        }));
 
-                let name = Uuid::new_v4().to_string();
-                lua.set_named_registry_value(&name, fun)?;
+        methods.add_method_mut("fn", |lua, this, args: MultiValue| {
+            type Args<'lua> = (
+                Function<'lua>,
+                Option<String>,
+                Option<NonZeroExitBehavior>,
+                Option<bool>,
+            );
+            let (fun, start, nonzero_exit, only_if_changed): Args =
+                args::convert("fn", args, 4, lua, |named| {
+                    Ok((
+                        named.get("fun")?,
+                        named.get("start")?,
+                        named.get("nonzero_exit")?,
+                        named.get("only_if_changed")?,
+                    ))
+                })?;
 
-                let start = start.map(Into::into);
+            let name = Uuid::new_v4().to_string();
+            lua.set_named_registry_value(&name, fun)?;
 
-                let drct = Directive::Hook(Hook::Fun(FunHook {
-                    name,
-                    start,
-                    nonzero_exit,
-                }));
-                this.spec.directives.push(drct);
-                Ok(())
-            },
-        );
+            let start = start.map(Into::into);
+
+            let drct = Directive::Hook(Hook::Fun(FunHook {
+                name,
+                start,
+                nonzero_exit,
+                only_if_changed: only_if_changed.unwrap_or(false),
+            }));
+            this.push(drct);
+            Ok(())
+        });
     }
 }
 
@@ -182,9 +573,11 @@ impl<'lua> FromLua<'lua> for ObjectValue {
             LuaValue::Nil => ObjectValue::Nil,
             LuaValue::Boolean(b) => ObjectValue::Bool(b),
             LuaValue::Integer(i) => ObjectValue::Int(i),
-            LuaValue::Number(n) => ObjectValue::Float(n),
+            LuaValue::Number(n) => number_value(n),
             LuaValue::String(s) => ObjectValue::Str(s.to_str()?.to_string()),
-            LuaValue::Table(t) => ObjectValue::Object(FromLua::from_lua(LuaValue::Table(t), lua)?),
+            LuaValue::Table(t) => table_value(t, lua)?,
+            // Serialized nulls (e.g. from `json.null`-style sentinels) are light userdata.
+            LuaValue::LightUserData(ud) if ud.0.is_null() => ObjectValue::Nil,
             LuaValue::Function(_)
             | LuaValue::Thread(_)
             | LuaValue::LightUserData(_)
@@ -204,16 +597,697 @@ impl<'lua> FromLua<'lua> for ObjectValue {
     }
 }
 
-impl<'lua> FromLua<'lua> for Object {
+/// Convert a Lua number. Lua versions without an integer subtype represent all numbers as floats,
+/// so integral values are converted to integers there.
+#[inline]
+fn number_value(n: f64) -> ObjectValue {
+    #[cfg(any(feature = "luajit", feature = "lua51", feature = "lua52"))]
+    if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 {
+        return ObjectValue::Int(n as i64);
+    }
+
+    ObjectValue::Float(n)
+}
+
+/// Convert a Lua table. Tables whose keys are exactly `1..=n` are sequences, and become arrays;
+/// all other tables become objects. Empty tables are objects, unless they have the serde array
+/// metatable.
+#[inline]
+fn table_value<'lua>(t: Table<'lua>, lua: &'lua mlua::Lua) -> mlua::Result<ObjectValue> {
+    let pairs = t
+        .clone()
+        .pairs::<LuaValue, LuaValue>()
+        .collect::<mlua::Result<Vec<_>>>()?;
+
+    // Keys are unique, so `len` keys in `1..=len` are exactly `1..=len`.
+    let len = pairs.len();
+    let is_array = if len == 0 {
+        t.get_metatable() == Some(lua.array_metatable())
+    } else {
+        pairs
+            .iter()
+            .all(|(key, _)| sequence_index(key).is_some_and(|i| i <= len))
+    };
+
+    if is_array {
+        let mut values = vec![ObjectValue::Nil; len];
+        for (key, value) in pairs {
+            // SAFETY: All keys were checked to be sequence indices.
+            let i = sequence_index(&key).unwrap();
+            values[i - 1] = FromLua::from_lua(value, lua)?;
+        }
+        Ok(ObjectValue::Array(values))
+    } else {
+        let map = pairs
+            .into_iter()
+            .map(|(key, value)| Ok((FromLua::from_lua(key, lua)?, FromLua::from_lua(value, lua)?)))
+            .collect::<mlua::Result<_>>()?;
+        Ok(ObjectValue::Object(map))
+    }
+}
+
+/// Return the key as a sequence index, if it is a positive integer.
+#[inline]
+fn sequence_index(key: &LuaValue<'_>) -> Option<usize> {
+    match *key {
+        LuaValue::Integer(i) if i > 0 => Some(i as usize),
+        LuaValue::Number(n) if n > 0.0 && n.fract() == 0.0 => Some(n as usize),
+        _ => None,
+    }
+}
+
+/// Options of `pkg:expect_file`, given as a table whose unknown keys are rejected.
+#[derive(Debug, Clone, Default)]
+struct ExpectFileOpts {
+    contains: Option<String>,
+}
+
+impl<'lua> FromLua<'lua> for ExpectFileOpts {
     #[inline]
     fn from_lua(lua_value: LuaValue<'lua>, lua: &'lua mlua::Lua) -> mlua::Result<Self> {
         match lua_value {
-            LuaValue::Table(t) => Ok(Object(FromLua::from_lua(LuaValue::Table(t), lua)?)),
+            LuaValue::Table(t) => args::convert_named("expect_file", t, lua, |named| {
+                Ok(Self {
+                    contains: named.get("contains")?,
+                })
+            }),
             _ => Err(LuaError::FromLuaConversionError {
                 from: lua_value.type_name(),
-                to: "Object",
+                to: "ExpectFileOpts",
                 message: Some("Only table values are valid".to_string()),
             }),
         }
     }
 }
+
+impl<'lua> FromLua<'lua> for Object {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        let type_name = lua_value.type_name();
+        let map = match lua_value {
+            LuaValue::Table(t) => match table_value(t, lua)? {
+                ObjectValue::Object(map) => Some(map),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        map.map(Object)
+            .ok_or_else(|| LuaError::FromLuaConversionError {
+                from: type_name,
+                to: "Object",
+                message: Some("Only non-sequence table values are valid".to_string()),
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexMap;
+    use mlua::{AnyUserData, FromLua, Function, Lua, LuaSerdeExt};
+
+    use crate::action::template::Engine;
+    use crate::spec::{
+        Condition, ConflictPolicy, Directive, Expectation, File, MissingParentPolicy, Mode,
+        ObjectValue, OptionType, PackageOption, TemplatedFileType,
+    };
+
+    use super::SpecObject;
+
+    fn eval(lua: &Lua, chunk: &str) -> mlua::Result<ObjectValue> {
+        let value = lua.load(chunk).eval()?;
+        FromLua::from_lua(value, lua)
+    }
+
+    /// Test that sequences become arrays, and other tables become objects.
+    #[test]
+    fn test_sequence_detection() -> mlua::Result<()> {
+        let lua = Lua::new();
+
+        let value = eval(&lua, "return { 'a', 'b', 'c' }")?;
+        let expected = ObjectValue::Array(vec![
+            ObjectValue::Str("a".into()),
+            ObjectValue::Str("b".into()),
+            ObjectValue::Str("c".into()),
+        ]);
+        assert_eq!(value, expected);
+
+        let value = eval(&lua, "return { [1] = 'a', [3] = 'c' }")?;
+        assert!(matches!(value, ObjectValue::Object(_)));
+
+        let value = eval(&lua, "return { 'a', key = 'b' }")?;
+        assert!(matches!(value, ObjectValue::Object(_)));
+
+        let value = eval(&lua, "return {}")?;
+        assert_eq!(value, ObjectValue::Object(IndexMap::new()));
+
+        Ok(())
+    }
+
+    /// Test named arguments, and that unknown ones are rejected.
+    #[test]
+    fn test_named_args() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+
+        lua.load("pkg:file{ src = 'a', dest = 'b', optional = true }")
+            .exec()?;
+        let err = lua
+            .load("pkg:file{ src = 'a', destt = 'b' }")
+            .exec()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("pkg:file: unknown argument(s) 'destt'"));
+        let err = lua
+            .load("pkg:file('a', nil, nil, nil, nil, nil, nil, 'extra')")
+            .exec()
+            .unwrap_err();
+        assert!(err.to_string().contains("expected at most 7 arguments"));
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        assert_eq!(pkg.spec.directives.len(), 1);
+        match &pkg.spec.directives[0] {
+            Directive::File(File::Regular(rf)) => {
+                assert_eq!(rf.src.to_str(), Some("a"));
+                assert_eq!(rf.dest.as_ref().and_then(|dest| dest.to_str()), Some("b"));
+                assert!(rf.optional);
+            }
+            drct => panic!("unexpected directive {:?}", drct),
+        }
+
+        Ok(())
+    }
+
+    /// Test that the options of `expect_file` are checked like named arguments.
+    #[test]
+    fn test_expect_file() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+
+        lua.load("pkg:expect_file('a', { contains = 'b' })")
+            .exec()?;
+        let err = lua
+            .load("pkg:expect_file('a', { contans = 'b' })")
+            .exec()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("pkg:expect_file: unknown argument(s) 'contans'"));
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        match &pkg.spec.directives[..] {
+            [Directive::File(File::Expect(ef))] => assert!(matches!(
+                &ef.expectation,
+                Expectation::File { contains: Some(contains) } if contains == "b"
+            )),
+            drcts => panic!("unexpected directives {:?}", drcts),
+        }
+
+        Ok(())
+    }
+
+    /// Test that the template engine is selected by extension, and that unknown extensions are
+    /// rejected when loading.
+    #[test]
+    fn test_template_auto() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+
+        lua.load("pkg:template('a.LIQUID', 'b', {})").exec()?;
+        let err = lua
+            .load("pkg:template('a.txt', 'b', {})")
+            .exec()
+            .unwrap_err();
+        assert!(err.to_string().contains("no template engine for 'a.txt'"));
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        match &pkg.spec.directives[..] {
+            [Directive::File(File::Templated(tf))] => {
+                assert!(matches!(tf.typ, TemplatedFileType::Auto(_)));
+                assert_eq!(Engine::from_path(&tf.src), Some(Engine::Liquid));
+            }
+            drcts => panic!("unexpected directives {:?}", drcts),
+        }
+
+        Ok(())
+    }
+
+    /// Test that the function of a pipe is stored in the registry under the name in its directive.
+    #[test]
+    fn test_pipe() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+
+        lua.load("pkg:pipe{ src = 'a', dest = 'b', fun = function(s) return s:upper() end }")
+            .exec()?;
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        match &pkg.spec.directives[..] {
+            [Directive::File(File::Piped(pf))] => {
+                assert_eq!(pf.src.to_str(), Some("a"));
+                assert_eq!(pf.dest.to_str(), Some("b"));
+                let fun: Function = lua.named_registry_value(&pf.name)?;
+                assert_eq!(fun.call::<_, String>("abc")?, "ABC");
+            }
+            drcts => panic!("unexpected directives {:?}", drcts),
+        }
+
+        Ok(())
+    }
+
+    /// Test that conditions from `only_if` and `when` apply to the right directives, and that
+    /// unknown conditions are rejected.
+    #[test]
+    fn test_conditions() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+        lua.load(include_str!("globals.lua")).exec()?;
+
+        lua.load(
+            r#"
+            only_if { os = 'linux' }
+            file 'a'
+            only_if(nil)
+            file { 'b', when = { hostname = 'work-*' } }
+            only_if({ env = { A = '1' } }, function()
+                file { 'c', when = { os = 'unix' } }
+            end)
+            file 'd'
+            "#,
+        )
+        .exec()?;
+        let err = lua
+            .load("file { 'e', when = { host = 'work' } }")
+            .exec()
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown condition 'host'"));
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        let os = |os: &str| Condition {
+            os: Some(os.to_string()),
+            ..Default::default()
+        };
+        let hostname = Condition {
+            hostname: Some("work-*".to_string()),
+            ..Default::default()
+        };
+        let env = Condition {
+            env: std::iter::once(("A".to_string(), "1".to_string())).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(pkg.spec.directives.len(), 4);
+        assert_eq!(pkg.spec.directive_conditions(0), &[os("linux")]);
+        assert_eq!(pkg.spec.directive_conditions(1), &[hostname]);
+        assert_eq!(pkg.spec.directive_conditions(2), &[env, os("unix")]);
+        assert!(pkg.spec.directive_conditions(3).is_empty());
+
+        Ok(())
+    }
+
+    /// Test that `on_conflict` applies only to its directive, and that unknown policies are
+    /// rejected.
+    #[test]
+    fn test_on_conflict() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+        lua.load(include_str!("globals.lua")).exec()?;
+
+        lua.load(
+            r#"
+            file { 'a', on_conflict = 'backup' }
+            file 'b'
+            hbs { 'c.hbs', 'c', vars = {}, when = { os = 'unix' }, on_conflict = 'fail' }
+            "#,
+        )
+        .exec()?;
+        let err = lua
+            .load("file { 'd', on_conflict = 'merge' }")
+            .exec()
+            .unwrap_err();
+        assert!(err.to_string().contains("ConflictPolicy"));
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        assert_eq!(pkg.spec.directives.len(), 3);
+        assert_eq!(
+            pkg.spec.directive_on_conflict(0),
+            Some(ConflictPolicy::Backup)
+        );
+        assert_eq!(pkg.spec.directive_on_conflict(1), None);
+        assert_eq!(
+            pkg.spec.directive_on_conflict(2),
+            Some(ConflictPolicy::Fail)
+        );
+        assert_eq!(pkg.spec.directive_conditions(2).len(), 1);
+
+        Ok(())
+    }
+
+    /// Test that `if_missing` applies only to its directive, and only to those that place files.
+    #[test]
+    fn test_if_missing() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+        lua.load(include_str!("globals.lua")).exec()?;
+
+        lua.load(
+            r#"
+            file { 'a', if_missing = 'skip' }
+            file 'b'
+            tree { 'c', if_missing = 'error', on_conflict = 'fail' }
+            "#,
+        )
+        .exec()?;
+        let err = lua
+            .load("file { 'd', if_missing = 'mkdir' }")
+            .exec()
+            .unwrap_err();
+        assert!(err.to_string().contains("MissingParentPolicy"));
+        assert!(lua
+            .load("mkdir { 'e', if_missing = 'skip' }")
+            .exec()
+            .is_err());
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        assert_eq!(pkg.spec.directives.len(), 3);
+        assert_eq!(
+            pkg.spec.directive_if_missing(0),
+            Some(MissingParentPolicy::Skip)
+        );
+        assert_eq!(pkg.spec.directive_if_missing(1), None);
+        assert_eq!(
+            pkg.spec.directive_if_missing(2),
+            Some(MissingParentPolicy::Error)
+        );
+        assert_eq!(
+            pkg.spec.directive_on_conflict(2),
+            Some(ConflictPolicy::Fail)
+        );
+
+        Ok(())
+    }
+
+    /// Test that `pkg:option` declares options, and that invalid declarations are rejected.
+    #[test]
+    fn test_option() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+        lua.load(include_str!("globals.lua")).exec()?;
+
+        lua.load(
+            r#"
+            pkg:option('font_size', { default = 12, type = 'number' })
+            option('theme', { type = 'string' })
+            option 'extra'
+            "#,
+        )
+        .exec()?;
+        assert!(lua
+            .load("option('a', { default = 'big', type = 'number' })")
+            .exec()
+            .is_err());
+        assert!(lua.load("option('b', { type = 'float' })").exec().is_err());
+        assert!(lua.load("option('c', { defualt = 1 })").exec().is_err());
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        assert_eq!(pkg.spec.options.len(), 3);
+        assert_eq!(
+            pkg.spec.options["font_size"],
+            PackageOption {
+                default: Some(ObjectValue::Int(12)),
+                typ: Some(OptionType::Number),
+            }
+        );
+        assert_eq!(pkg.spec.options["theme"].typ, Some(OptionType::String));
+        assert_eq!(
+            pkg.spec.options["extra"],
+            PackageOption {
+                default: None,
+                typ: None,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Test that `pkg:profile` tags the directives within its block, including nested ones, and
+    /// that invalid names are rejected.
+    #[test]
+    fn test_profile() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+        lua.load(include_str!("globals.lua")).exec()?;
+
+        lua.load(
+            r#"
+            file 'a'
+            pkg:profile('work', function()
+                file 'b'
+                profile('laptop', function() file 'c' end)
+            end)
+            file 'd'
+            "#,
+        )
+        .exec()?;
+        let err = lua
+            .load("pkg:profile('work,laptop', function() end)")
+            .exec()
+            .unwrap_err();
+        assert!(err.to_string().contains("invalid profile name"));
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        assert_eq!(pkg.spec.directives.len(), 4);
+        assert!(pkg.spec.directive_profiles(0).is_empty());
+        assert_eq!(pkg.spec.directive_profiles(1), &["work"]);
+        assert_eq!(pkg.spec.directive_profiles(2), &["work", "laptop"]);
+        assert!(pkg.spec.directive_profiles(3).is_empty());
+
+        Ok(())
+    }
+
+    /// Test that Handlebars helpers are stored in the registry under the names in the spec.
+    #[test]
+    fn test_hbs_helper() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+        lua.load(include_str!("globals.lua")).exec()?;
+
+        lua.load("hbs_helper('upper', function(s) return s:upper() end)")
+            .exec()?;
+        assert!(lua.load("hbs_helper('lower', 'a')").exec().is_err());
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        assert_eq!(pkg.spec.hbs_helpers.len(), 1);
+        let fun: Function = lua.named_registry_value(&pkg.spec.hbs_helpers["upper"])?;
+        assert_eq!(fun.call::<_, String>("a")?, "A");
+
+        Ok(())
+    }
+
+    /// Evaluate `chunk` against a fresh spec, and return the directives as JSON.
+    fn directives(chunk: &str) -> mlua::Result<serde_json::Value> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+        lua.load(chunk).exec()?;
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        Ok(serde_json::to_value(&pkg.spec.directives).unwrap())
+    }
+
+    /// Test that named arguments are equivalent to positional ones for each kind of directive.
+    #[test]
+    fn test_named_positional() -> mlua::Result<()> {
+        let cases = [
+            (
+                "pkg:tree('t', '.config', 'copy', {'*.conf'}, nil, nil, true, 'config', {'u'})",
+                "pkg:tree{ src = 't', dest = '.config', link_type = 'copy', globs = {'*.conf'}, \
+                 optional = true, strip_prefix = 'config', layers = {'u'} }",
+            ),
+            (
+                "pkg:copy_dir('d', nil, {'plugins'})",
+                "pkg:copy_dir{ src = 'd', volatile = {'plugins'} }",
+            ),
+            (
+                "pkg:hbs('a.hbs', 'a', { x = 1 }, {}, nil, true)",
+                "pkg:hbs{ src = 'a.hbs', dest = 'a', vars = { x = 1 }, partials = {}, \
+                 auto_header = true }",
+            ),
+            (
+                "pkg:yaml('a.yaml', { x = 1 }, '# header', nil, nil, true)",
+                "pkg:yaml{ dest = 'a.yaml', values = { x = 1 }, header = '# header', \
+                 sort_keys = true }",
+            ),
+            ("pkg:str('a', 'b')", "pkg:str{ dest = 'a', contents = 'b' }"),
+            (
+                "pkg:mkdir('d', true, '0700')",
+                "pkg:mkdir{ dest = 'd', parents = true, mode = '0700' }",
+            ),
+            (
+                "pkg:cmd('true', nil, 'bash', nil, nil, nil, { A = 'b' }, nil, true)",
+                "pkg:cmd{ command = 'true', shell = 'bash', env = { A = 'b' }, \
+                 only_if_changed = true }",
+            ),
+            (
+                "pkg:script('s.sh', {'--force'})",
+                "pkg:script{ path = 's.sh', args = {'--force'} }",
+            ),
+        ];
+
+        for (positional, named) in cases.iter() {
+            assert_eq!(directives(positional)?, directives(named)?, "{}", named);
+        }
+
+        let err = directives("pkg:mkdir{ dest = {} }").unwrap_err();
+        assert!(err.to_string().contains("invalid argument 'dest'"));
+
+        Ok(())
+    }
+
+    /// Test that modes are read as octal strings, and that numbers and invalid modes are rejected.
+    #[test]
+    fn test_mode() -> mlua::Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+
+        lua.load("pkg:file{ src = 'a', link_type = 'copy', mode = '0600' }")
+            .exec()?;
+        lua.load("pkg:mkdir{ dest = 'd', parents = true, mode = '0o750' }")
+            .exec()?;
+        for mode in ["644", "'0999'", "'17777'"] {
+            let chunk = format!("pkg:file{{ src = 'a', mode = {} }}", mode);
+            assert!(lua.load(&chunk).exec().is_err(), "{}", mode);
+        }
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        match &pkg.spec.directives[..] {
+            [Directive::File(File::Regular(rf)), Directive::File(File::Dir(df))] => {
+                assert_eq!(rf.mode, Some(Mode(0o600)));
+                assert_eq!(df.mode, Some(Mode(0o750)));
+            }
+            drcts => panic!("unexpected directives {:?}", drcts),
+        }
+
+        Ok(())
+    }
+
+    /// Test that owners are read as names or ids, and that unknown names are rejected.
+    #[cfg(unix)]
+    #[test]
+    fn test_owner() -> mlua::Result<()> {
+        use crate::spec::{Gid, Uid};
+
+        let lua = Lua::new();
+        lua.globals().set("pkg", SpecObject::new())?;
+
+        lua.load("pkg:file{ src = 'a', link_type = 'copy', owner = 'root', group = 0 }")
+            .exec()?;
+        lua.load("pkg:mkdir{ dest = 'd', parents = true, owner = '1000' }")
+            .exec()?;
+        for owner in ["'no-such-user-shelf'", "-1", "true"] {
+            let chunk = format!("pkg:file{{ src = 'a', owner = {} }}", owner);
+            assert!(lua.load(&chunk).exec().is_err(), "{}", owner);
+        }
+
+        let pkg: AnyUserData = lua.globals().get("pkg")?;
+        let pkg = pkg.borrow::<SpecObject>()?;
+        match &pkg.spec.directives[..] {
+            [Directive::File(File::Regular(rf)), Directive::File(File::Dir(df))] => {
+                assert_eq!((rf.owner, rf.group), (Some(Uid(0)), Some(Gid(0))));
+                assert_eq!((df.owner, df.group), (Some(Uid(1000)), None));
+            }
+            drcts => panic!("unexpected directives {:?}", drcts),
+        }
+
+        Ok(())
+    }
+
+    /// Test that nested tables serialize as expected.
+    #[test]
+    fn test_nested_serde() -> mlua::Result<()> {
+        let lua = Lua::new();
+
+        let value = eval(
+            &lua,
+            "return { servers = { { host = 'a', port = 80 }, { host = 'b', port = 443 } } }",
+        )?;
+        let json = serde_json::to_value(&value).unwrap();
+        let expected = serde_json::json!({
+            "servers": [{ "host": "a", "port": 80 }, { "host": "b", "port": 443 }],
+        });
+        assert_eq!(json, expected);
+
+        Ok(())
+    }
+
+    /// Property test: arbitrary values survive the conversion to Lua and back, and serialize
+    /// identically.
+    #[test]
+    fn test_round_trip() -> mlua::Result<()> {
+        let lua = Lua::new();
+
+        let mut rng = Lcg(0x5eed);
+        for _ in 0..256 {
+            let value = arbitrary(&mut rng, 3);
+
+            let lua_value = lua.to_value(&value)?;
+            let converted: ObjectValue = FromLua::from_lua(lua_value, &lua)?;
+            assert_eq!(converted, value);
+            assert_eq!(
+                serde_json::to_value(&converted).unwrap(),
+                serde_json::to_value(&value).unwrap()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Minimal deterministic pseudo-random number generator.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: u64) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 33) % bound
+        }
+    }
+
+    /// Generate an arbitrary value. Nils are excluded, since they can't be stored in tables, and
+    /// floats are never integral, since some Lua versions can't tell them apart from integers.
+    fn arbitrary(rng: &mut Lcg, depth: usize) -> ObjectValue {
+        let kinds = if depth == 0 { 4 } else { 6 };
+        match rng.next(kinds) {
+            0 => ObjectValue::Bool(rng.next(2) == 0),
+            1 => ObjectValue::Int(rng.next(1 << 31) as i64 - (1 << 30)),
+            2 => ObjectValue::Float(rng.next(1 << 20) as f64 + 0.5),
+            3 => ObjectValue::Str(format!("s{}", rng.next(1000))),
+            4 => {
+                let len = rng.next(4) as usize;
+                ObjectValue::Array((0..len).map(|_| arbitrary(rng, depth - 1)).collect())
+            }
+            _ => {
+                let len = rng.next(4);
+                ObjectValue::Object(
+                    (0..len)
+                        .map(|i| (format!("k{}", i), arbitrary(rng, depth - 1)))
+                        .collect(),
+                )
+            }
+        }
+    }
+}
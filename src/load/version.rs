@@ -0,0 +1,93 @@
+use std::cmp::Ordering;
+
+/// Version of shelf that packages are checked against.
+pub static VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Error encountered when checking the version required by a package.
+#[derive(Debug, thiserror::Error)]
+pub enum VersionError {
+    /// The required version isn't of the form `MAJOR[.MINOR[.PATCH]]`.
+    #[error("invalid version requirement '{0}'")]
+    Invalid(String),
+    /// The required version is newer than this version of shelf.
+    #[error("requires shelf {required}, but this is shelf {current}")]
+    Unsupported { required: String, current: String },
+}
+
+/// Check that this version of shelf is at least `required`, which is of the form
+/// `MAJOR[.MINOR[.PATCH]]`; omitted components are taken to be zero.
+#[inline]
+pub fn check(required: &str) -> Result<(), VersionError> {
+    let required_parts =
+        parse(required).ok_or_else(|| VersionError::Invalid(required.to_string()))?;
+    // SAFETY: The crate version is always valid.
+    let current_parts = parse(VERSION).unwrap();
+
+    match compare(&current_parts, &required_parts) {
+        Ordering::Less => Err(VersionError::Unsupported {
+            required: required.to_string(),
+            current: VERSION.to_string(),
+        }),
+        Ordering::Equal | Ordering::Greater => Ok(()),
+    }
+}
+
+/// Parse the numeric components of `version`, ignoring any pre-release or build suffix.
+#[inline]
+fn parse(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim();
+    let version = version.split(['-', '+']).next()?;
+
+    let parts = version
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<_>>>()?;
+    if parts.len() > 3 {
+        None
+    } else {
+        Some(parts)
+    }
+}
+
+/// Compare version components, padding the shorter with zeros.
+#[inline]
+fn compare(a: &[u64], b: &[u64]) -> Ordering {
+    let len = a.len().max(b.len());
+    let component = |parts: &[u64], i: usize| parts.get(i).copied().unwrap_or(0);
+    (0..len)
+        .map(|i| component(a, i).cmp(&component(b, i)))
+        .find(|ord| *ord != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+
+    use super::{check, compare, parse};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("0.4"), Some(vec![0, 4]));
+        assert_eq!(parse(" 1.2.3 "), Some(vec![1, 2, 3]));
+        assert_eq!(parse("1.2.3-alpha.1"), Some(vec![1, 2, 3]));
+        assert_eq!(parse("1.2.3.4"), None);
+        assert_eq!(parse("1.x"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare(&[0, 4], &[0, 4, 0]), Ordering::Equal);
+        assert_eq!(compare(&[0, 4, 1], &[0, 4]), Ordering::Greater);
+        assert_eq!(compare(&[0, 10], &[0, 9, 9]), Ordering::Greater);
+        assert_eq!(compare(&[1], &[1, 0, 1]), Ordering::Less);
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check("0").is_ok());
+        assert!(check("999").is_err());
+        assert!(check("nope").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use static_assertions as sa;
+
+use super::ctx::FinishCtx;
+use super::error::{ChmodError, MetadataError};
+use super::{Finish, Rollback};
+
+sa::assert_impl_all!(ChmodOp: Finish<Output = ChmodFinish, Error = ChmodOpError>);
+sa::assert_impl_all!(ChmodFinish: Rollback<Output = ChmodUndoOp>);
+sa::assert_impl_all!(ChmodUndoOp: Finish<Output = ChmodUndoFinish, Error = ChmodOpError>);
+sa::assert_impl_all!(ChmodUndoFinish: Rollback<Output = ChmodOp>);
+
+/// Error encountered when finishing [`ChmodOp`] or [`ChmodUndoOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum ChmodOpError {
+    #[error("metadata error")]
+    Metadata(#[from] MetadataError),
+    #[error("chmod error")]
+    Chmod(#[from] ChmodError),
+}
+
+/// Operation to set the permission bits of the file at `path` to `mode`. Symlinks are followed.
+///
+/// # Errors
+///
+/// The operation will error if there is no file at `path` or there are insufficient permissions.
+///
+/// # Undo
+///
+/// Undoing will restore the previous permission bits. This set of operations functions in the
+/// following cycle:
+///
+/// [`ChmodOp`] --> [`ChmodFinish`] --> [`ChmodUndoOp`] --> [`ChmodUndoFinish`] --> [`ChmodOp`] -->
+/// ...
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChmodOp {
+    /// Path of the file.
+    pub path: PathBuf,
+    /// Permission bits to set.
+    pub mode: u32,
+}
+
+/// The output of [`ChmodOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChmodFinish {
+    /// See [`ChmodOp`].
+    pub path: PathBuf,
+    /// See [`ChmodOp`].
+    pub mode: u32,
+
+    /// Permission bits before the change.
+    pub prev_mode: u32,
+}
+
+impl Finish for ChmodOp {
+    type Output = ChmodFinish;
+    type Error = ChmodOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self { path, mode } = self;
+
+        let prev_mode = get_mode(path)?;
+        set_mode(path, *mode)?;
+
+        Ok(Self::Output {
+            path: path.clone(),
+            mode: *mode,
+            prev_mode,
+        })
+    }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        // Setting the mode is a single call, and the previous mode is unchanged if it fails.
+        true
+    }
+}
+
+impl Rollback for ChmodFinish {
+    type Output = ChmodUndoOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self {
+            path,
+            mode,
+            prev_mode,
+        } = self;
+
+        Self::Output {
+            path: path.clone(),
+            mode: *mode,
+            prev_mode: *prev_mode,
+        }
+    }
+}
+
+/// The undo of [`ChmodOp`] (see its documentation), created by rolling back [`ChmodFinish`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChmodUndoOp {
+    /// See [`ChmodOp`].
+    pub path: PathBuf,
+    /// See [`ChmodOp`].
+    pub mode: u32,
+
+    /// See [`ChmodFinish`].
+    pub prev_mode: u32,
+}
+
+/// The output of [`ChmodUndoOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChmodUndoFinish {
+    /// See [`ChmodOp`].
+    pub path: PathBuf,
+    /// See [`ChmodOp`].
+    pub mode: u32,
+}
+
+impl Finish for ChmodUndoOp {
+    type Output = ChmodUndoFinish;
+    type Error = ChmodOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self {
+            path,
+            mode,
+            prev_mode,
+        } = self;
+
+        set_mode(path, *prev_mode)?;
+
+        Ok(Self::Output {
+            path: path.clone(),
+            mode: *mode,
+        })
+    }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
+}
+
+impl Rollback for ChmodUndoFinish {
+    type Output = ChmodOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self { path, mode } = self;
+
+        Self::Output {
+            path: path.clone(),
+            mode: *mode,
+        }
+    }
+}
+
+/// Return the permission bits of the file at `path`, following symlinks.
+#[cfg(unix)]
+#[inline]
+pub fn get_mode(path: &Path) -> Result<u32, MetadataError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .map(|meta| meta.permissions().mode() & 0o7777)
+        .map_err(|inner| MetadataError {
+            path: path.to_path_buf(),
+            inner,
+        })
+}
+
+#[cfg(windows)]
+#[inline]
+pub fn get_mode(path: &Path) -> Result<u32, MetadataError> {
+    // FIXME: Look into Windows ACLs
+    Err(MetadataError {
+        path: path.to_path_buf(),
+        inner: unsupported(),
+    })
+}
+
+#[cfg(unix)]
+#[inline]
+fn set_mode(path: &Path, mode: u32) -> Result<(), ChmodError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(|inner| ChmodError {
+        path: path.to_path_buf(),
+        inner,
+    })
+}
+
+#[cfg(windows)]
+#[inline]
+fn set_mode(path: &Path, _mode: u32) -> Result<(), ChmodError> {
+    // FIXME: Look into Windows ACLs
+    Err(ChmodError {
+        path: path.to_path_buf(),
+        inner: unsupported(),
+    })
+}
+
+#[cfg(windows)]
+#[inline]
+fn unsupported() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "file modes aren't supported on this platform",
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test;
+    use super::{get_mode, ChmodOp, Finish, Rollback};
+
+    #[test]
+    fn test_chmod() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let (_, path) = test::new_file(dir, "a")?;
+            let prev_mode = get_mode(&path)?;
+
+            let op = ChmodOp {
+                path: path.clone(),
+                mode: 0o600,
+            };
+
+            let opf = op.finish(ctx)?;
+            assert_eq!(get_mode(&path)?, 0o600);
+            assert_eq!(opf.prev_mode, prev_mode);
+
+            let undo = opf.rollback();
+            let undof = undo.finish(ctx)?;
+            assert_eq!(get_mode(&path)?, prev_mode);
+
+            let op2 = undof.rollback();
+            assert_eq!(op, op2);
+
+            Ok(())
+        })
+    }
+}
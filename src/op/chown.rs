@@ -0,0 +1,311 @@
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use static_assertions as sa;
+
+use super::ctx::FinishCtx;
+use super::error::{ChownError, MetadataError};
+use super::{Finish, Rollback};
+
+sa::assert_impl_all!(ChownOp: Finish<Output = ChownFinish, Error = ChownOpError>);
+sa::assert_impl_all!(ChownFinish: Rollback<Output = ChownUndoOp>);
+sa::assert_impl_all!(ChownUndoOp: Finish<Output = ChownUndoFinish, Error = ChownOpError>);
+sa::assert_impl_all!(ChownUndoFinish: Rollback<Output = ChownOp>);
+
+/// Error encountered when finishing [`ChownOp`] or [`ChownUndoOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum ChownOpError {
+    #[error("metadata error")]
+    Metadata(#[from] MetadataError),
+    #[error("chown error")]
+    Chown(#[from] ChownError),
+}
+
+/// Operation to set the owning user and group of the file at `path` to `uid` and `gid`; either is
+/// left unchanged if `None`. Symlinks are followed.
+///
+/// # Errors
+///
+/// The operation will error if there is no file at `path` or there are insufficient permissions,
+/// which is usually the case unless running as root.
+///
+/// # Undo
+///
+/// Undoing will restore the previous owner and group. This set of operations functions in the
+/// following cycle:
+///
+/// [`ChownOp`] --> [`ChownFinish`] --> [`ChownUndoOp`] --> [`ChownUndoFinish`] --> [`ChownOp`] -->
+/// ...
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChownOp {
+    /// Path of the file.
+    pub path: PathBuf,
+    /// Id of the user to set as the owner.
+    pub uid: Option<u32>,
+    /// Id of the group to set.
+    pub gid: Option<u32>,
+}
+
+/// The output of [`ChownOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChownFinish {
+    /// See [`ChownOp`].
+    pub path: PathBuf,
+    /// See [`ChownOp`].
+    pub uid: Option<u32>,
+    /// See [`ChownOp`].
+    pub gid: Option<u32>,
+
+    /// Id of the owner before the change.
+    pub prev_uid: u32,
+    /// Id of the group before the change.
+    pub prev_gid: u32,
+}
+
+impl Finish for ChownOp {
+    type Output = ChownFinish;
+    type Error = ChownOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self { path, uid, gid } = self;
+
+        let (prev_uid, prev_gid) = get_owner(path)?;
+        set_owner(path, *uid, *gid)?;
+
+        Ok(Self::Output {
+            path: path.clone(),
+            uid: *uid,
+            gid: *gid,
+            prev_uid,
+            prev_gid,
+        })
+    }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        // Setting the owner is a single call, and the previous owner is unchanged if it fails.
+        true
+    }
+}
+
+impl Rollback for ChownFinish {
+    type Output = ChownUndoOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self {
+            path,
+            uid,
+            gid,
+            prev_uid,
+            prev_gid,
+        } = self;
+
+        Self::Output {
+            path: path.clone(),
+            uid: *uid,
+            gid: *gid,
+            prev_uid: *prev_uid,
+            prev_gid: *prev_gid,
+        }
+    }
+}
+
+/// The undo of [`ChownOp`] (see its documentation), created by rolling back [`ChownFinish`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChownUndoOp {
+    /// See [`ChownOp`].
+    pub path: PathBuf,
+    /// See [`ChownOp`].
+    pub uid: Option<u32>,
+    /// See [`ChownOp`].
+    pub gid: Option<u32>,
+
+    /// See [`ChownFinish`].
+    pub prev_uid: u32,
+    /// See [`ChownFinish`].
+    pub prev_gid: u32,
+}
+
+/// The output of [`ChownUndoOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChownUndoFinish {
+    /// See [`ChownOp`].
+    pub path: PathBuf,
+    /// See [`ChownOp`].
+    pub uid: Option<u32>,
+    /// See [`ChownOp`].
+    pub gid: Option<u32>,
+}
+
+impl Finish for ChownUndoOp {
+    type Output = ChownUndoFinish;
+    type Error = ChownOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self {
+            path,
+            uid,
+            gid,
+            prev_uid,
+            prev_gid,
+        } = self;
+
+        // Only restore what was changed.
+        set_owner(path, uid.map(|_| *prev_uid), gid.map(|_| *prev_gid))?;
+
+        Ok(Self::Output {
+            path: path.clone(),
+            uid: *uid,
+            gid: *gid,
+        })
+    }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
+}
+
+impl Rollback for ChownUndoFinish {
+    type Output = ChownOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self { path, uid, gid } = self;
+
+        Self::Output {
+            path: path.clone(),
+            uid: *uid,
+            gid: *gid,
+        }
+    }
+}
+
+/// Return the ids of the owning user and group of the file at `path`, following symlinks.
+#[inline]
+pub fn get_owner(path: &Path) -> Result<(u32, u32), MetadataError> {
+    fs::metadata(path)
+        .map(|meta| (meta.uid(), meta.gid()))
+        .map_err(|inner| MetadataError {
+            path: path.to_path_buf(),
+            inner,
+        })
+}
+
+#[inline]
+fn set_owner(path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<(), ChownError> {
+    std::os::unix::fs::chown(path, uid, gid).map_err(|inner| ChownError {
+        path: path.to_path_buf(),
+        inner,
+    })
+}
+
+/// Return the id of the user named `name`, if there is one.
+#[inline]
+pub fn user_id(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    lookup(|buf, res| {
+        // SAFETY: `passwd` is plain data, and is only read if the lookup succeeds.
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let ret = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut pwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut found,
+            )
+        };
+        *res = (!found.is_null()).then_some(pwd.pw_uid);
+        ret
+    })
+}
+
+/// Return the id of the group named `name`, if there is one.
+#[inline]
+pub fn group_id(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    lookup(|buf, res| {
+        // SAFETY: `group` is plain data, and is only read if the lookup succeeds.
+        let mut grp: libc::group = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let ret = unsafe {
+            libc::getgrnam_r(
+                name.as_ptr(),
+                &mut grp,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut found,
+            )
+        };
+        *res = (!found.is_null()).then_some(grp.gr_gid);
+        ret
+    })
+}
+
+/// Call the reentrant lookup `f` with buffers for the strings of the entry, growing them as long
+/// as they're too small.
+#[inline]
+fn lookup<F>(mut f: F) -> Option<u32>
+where
+    F: FnMut(&mut [libc::c_char], &mut Option<u32>) -> libc::c_int,
+{
+    let mut buf = vec![0; 1024];
+    loop {
+        let mut res = None;
+        match f(&mut buf, &mut res) {
+            libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            0 => return res,
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test;
+    use super::{get_owner, group_id, user_id, ChownOp, Finish, Rollback};
+
+    #[test]
+    fn test_chown() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let (_, path) = test::new_file(dir, "a")?;
+            let (uid, gid) = get_owner(&path)?;
+
+            // Without privileges, files can only be given to their current owner.
+            let op = ChownOp {
+                path: path.clone(),
+                uid: Some(uid),
+                gid: Some(gid),
+            };
+
+            let opf = op.finish(ctx)?;
+            assert_eq!(get_owner(&path)?, (uid, gid));
+            assert_eq!((opf.prev_uid, opf.prev_gid), (uid, gid));
+
+            let undo = opf.rollback();
+            let undof = undo.finish(ctx)?;
+            assert_eq!(get_owner(&path)?, (uid, gid));
+
+            let op2 = undof.rollback();
+            assert_eq!(op, op2);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(user_id("root"), Some(0));
+        assert_eq!(group_id("root").or_else(|| group_id("wheel")), Some(0));
+        assert_eq!(user_id("no-such-user-shelf"), None);
+        assert_eq!(group_id("no-such-group-shelf"), None);
+    }
+}
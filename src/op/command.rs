@@ -1,7 +1,9 @@
 use std::collections::HashMap;
-use std::io;
-use std::path::PathBuf;
-use std::process::{Command, Output, Stdio};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 use static_assertions as sa;
@@ -22,6 +24,45 @@ pub enum CommandOpError {
 /// Map of environment variables and values.
 pub type EnvMap = HashMap<String, String>;
 
+/// Return the default shell of the current platform: `cmd.exe` on Windows, and `sh` elsewhere.
+#[inline]
+pub fn default_shell() -> &'static str {
+    if cfg!(windows) {
+        "cmd.exe"
+    } else {
+        "sh"
+    }
+}
+
+/// Family of a shell, which determines how commands are passed to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    /// POSIX-like shells (e.g. sh, bash, zsh), which take `-c <command>`.
+    Posix,
+    /// The Windows command interpreter, which takes `/C <command>`.
+    Cmd,
+    /// Windows PowerShell or PowerShell Core, which take an encoded command.
+    PowerShell,
+}
+
+impl ShellKind {
+    /// Detect the family of `shell` from its program name (e.g. `bash`, `cmd.exe` or
+    /// `pwsh.exe`). Unknown shells are assumed to be POSIX-like.
+    #[inline]
+    pub fn detect(shell: &str) -> Self {
+        let name = Path::new(shell)
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match name.as_str() {
+            "cmd" => Self::Cmd,
+            "powershell" | "pwsh" => Self::PowerShell,
+            _ => Self::Posix,
+        }
+    }
+}
+
 /// Operation to run a shell command.
 ///
 /// # Errors
@@ -44,6 +85,9 @@ pub struct CommandOp {
     pub clean_env: bool,
     /// Map of extra environment variables to set.
     pub env: EnvMap,
+    /// If present, the command is killed after running this long, and the op fails.
+    #[serde(default)]
+    pub timeout: Option<Duration>,
 }
 
 /// The output of [`CommandOp`]. See its documentation for information.
@@ -78,11 +122,11 @@ impl Finish for CommandOp {
             shell,
             clean_env,
             env,
+            timeout,
         } = self;
 
         let mut cmd = Command::new(shell);
-        // TODO: Don't hardcode this
-        cmd.args(&["-c", command]);
+        shell_args(&mut cmd, shell, command);
         cmd.current_dir(start);
 
         cmd.stdout(Stdio::piped());
@@ -98,7 +142,7 @@ impl Finish for CommandOp {
             }
         }
 
-        let output = self.spawn_output(cmd).map_err(|inner| SpawnError {
+        let output = spawn_output(cmd, *timeout).map_err(|inner| SpawnError {
             command: command.clone(),
             shell: shell.clone(),
             start: start.clone(),
@@ -116,11 +160,170 @@ impl Finish for CommandOp {
     }
 }
 
-impl CommandOp {
-    /// Execute a command and wait for it to finish.
-    #[inline]
-    fn spawn_output(&self, mut cmd: Command) -> Result<Output, io::Error> {
-        let child = cmd.spawn()?;
-        child.wait_with_output()
+/// Execute a command and wait for it to finish. If `timeout` elapses first, the command is killed
+/// and an error of kind [`io::ErrorKind::TimedOut`] is returned.
+#[inline]
+pub(super) fn spawn_output(
+    mut cmd: Command,
+    timeout: Option<Duration>,
+) -> Result<Output, io::Error> {
+    let mut child = cmd.spawn()?;
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return child.wait_with_output(),
+    };
+
+    // Drain the pipes while waiting, so that the command doesn't block on a full pipe.
+    let stdout = child.stdout.take().map(drain);
+    let stderr = child.stderr.take().map(drain);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if Instant::now() >= deadline {
+            kill(&mut child)?;
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("timed out after {}s", timeout.as_secs()),
+            ));
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let join = |handle: Option<thread::JoinHandle<io::Result<Vec<u8>>>>| match handle {
+        // SAFETY: The reader threads don't panic.
+        Some(handle) => handle.join().unwrap(),
+        None => Ok(Vec::new()),
+    };
+    Ok(Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    })
+}
+
+/// Read all of `r` in a new thread.
+#[inline]
+fn drain<R>(mut r: R) -> thread::JoinHandle<io::Result<Vec<u8>>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        Ok(buf)
+    })
+}
+
+/// Kill `child` and reap it.
+#[inline]
+fn kill(child: &mut Child) -> io::Result<()> {
+    match child.kill() {
+        Ok(()) => {}
+        // The command exited in the meantime.
+        Err(err) if err.kind() == io::ErrorKind::InvalidInput => {}
+        Err(err) => return Err(err),
+    }
+    child.wait().map(|_| ())
+}
+
+/// Add the arguments to `cmd` to run `command` with `shell`.
+#[inline]
+fn shell_args(cmd: &mut Command, shell: &str, command: &str) {
+    match ShellKind::detect(shell) {
+        ShellKind::Posix => {
+            cmd.args(["-c", command]);
+        }
+        ShellKind::Cmd => {
+            cmd.arg("/C");
+            // cmd.exe doesn't follow the usual quoting rules, so pass the command verbatim.
+            #[cfg(windows)]
+            {
+                use std::os::windows::process::CommandExt;
+                cmd.raw_arg(command);
+            }
+            #[cfg(not(windows))]
+            cmd.arg(command);
+        }
+        ShellKind::PowerShell => {
+            // Encoding the command sidesteps quoting altogether.
+            cmd.args(["-NoProfile", "-NonInteractive", "-EncodedCommand"]);
+            cmd.arg(encode_powershell(command));
+        }
+    }
+}
+
+/// Encode `command` for `powershell -EncodedCommand`, i.e. as base64 of its UTF-16LE bytes.
+#[inline]
+fn encode_powershell(command: &str) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let bytes: Vec<u8> = command.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (u32::from(*b) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    use super::{encode_powershell, spawn_output, ShellKind};
+
+    /// Test shell family detection.
+    #[test]
+    fn test_detect() {
+        assert_eq!(ShellKind::detect("sh"), ShellKind::Posix);
+        assert_eq!(ShellKind::detect("/usr/bin/zsh"), ShellKind::Posix);
+        assert_eq!(ShellKind::detect("cmd.exe"), ShellKind::Cmd);
+        assert_eq!(ShellKind::detect("PowerShell"), ShellKind::PowerShell);
+        assert_eq!(ShellKind::detect("pwsh.exe"), ShellKind::PowerShell);
+    }
+
+    /// Test encoding of PowerShell commands.
+    #[test]
+    fn test_encode_powershell() {
+        assert_eq!(encode_powershell(""), "");
+        assert_eq!(encode_powershell("dir"), "ZABpAHIA");
+        assert_eq!(
+            encode_powershell(r#"echo "a b""#),
+            "ZQBjAGgAbwAgACIAYQAgAGIAIgA="
+        );
+    }
+
+    /// Test that commands are killed once the timeout elapses, and that output is still collected
+    /// otherwise.
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "sleep 5"]);
+        let err = spawn_output(cmd, Some(Duration::from_millis(50))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo a"]).stdout(Stdio::piped());
+        let output = spawn_output(cmd, Some(Duration::from_secs(5))).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"a\n");
     }
 }
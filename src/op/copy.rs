@@ -92,6 +92,13 @@ impl Finish for CopyOp {
             dir: *dir,
         })
     }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        // Copying a file overwrites whatever a failed attempt left, but copying a directory
+        // doesn't.
+        !self.dir
+    }
 }
 
 impl Rollback for CopyFinish {
@@ -163,6 +170,11 @@ impl Finish for CopyUndoOp {
             dir: *dir,
         })
     }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
 }
 
 impl Rollback for CopyUndoFinish {
@@ -0,0 +1,483 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use static_assertions as sa;
+
+use super::ctx::FinishCtx;
+use super::error::{
+    CopyError, MetadataError, MkdirError, ReadError, ReadLinkError, RemoveError, SymlinkError,
+};
+use super::{Finish, Rollback};
+
+sa::assert_impl_all!(CopyDirOp: Finish<Output = CopyDirFinish, Error = CopyDirOpError>);
+sa::assert_impl_all!(CopyDirFinish: Rollback<Output = CopyDirUndoOp>);
+sa::assert_impl_all!(
+    CopyDirUndoOp: Finish<Output = CopyDirUndoFinish, Error = CopyDirUndoOpError>
+);
+sa::assert_impl_all!(CopyDirUndoFinish: Rollback<Output = CopyDirOp>);
+
+/// Error encountered when finishing [`CopyDirOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum CopyDirOpError {
+    #[error("copy error")]
+    Copy(#[from] CopyError),
+    #[error("mkdir error")]
+    Mkdir(#[from] MkdirError),
+    #[error("read error")]
+    Read(#[from] ReadError),
+    #[error("metadata error")]
+    Metadata(#[from] MetadataError),
+    #[error("symlink read error")]
+    ReadLink(#[from] ReadLinkError),
+    #[error("symlink error")]
+    Symlink(#[from] SymlinkError),
+}
+
+/// Operation to recursively copy the directory `src` to `dest` as a single unit.
+///
+/// Unlike copying each file individually, this produces a single journal record; the finish holds
+/// a manifest of every file and directory created, which is used to undo the copy. Symlinks are
+/// recreated as symlinks, rather than followed.
+///
+/// # Errors
+///
+/// It is assumed that `src` points to a readable directory, and that no file exists at `dest`
+/// (whose parent must be writable), except for the paths in `keep` and their ancestor directories.
+/// These premises are not checked, and the operation will error if they are not met.
+///
+/// # Undo
+///
+/// Undoing will delete the files and directories in the manifest, and then `dest` itself if it was
+/// created. Directories that have since gained other files are left in place. This set of
+/// operations functions in the following cycle:
+///
+/// [`CopyDirOp`] --> [`CopyDirFinish`] --> [`CopyDirUndoOp`] --> [`CopyDirUndoFinish`] -->
+/// [`CopyDirOp`] --> ...
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CopyDirOp {
+    /// Path to directory to copy.
+    pub src: PathBuf,
+    /// Path to destination of copy.
+    pub dest: PathBuf,
+    /// Existing paths, relative to `dest`, that are left untouched and not copied over.
+    pub keep: Vec<PathBuf>,
+}
+
+/// The output of [`CopyDirOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CopyDirFinish {
+    /// See [`CopyDirOp`].
+    pub src: PathBuf,
+    /// See [`CopyDirOp`].
+    pub dest: PathBuf,
+    /// See [`CopyDirOp`].
+    pub keep: Vec<PathBuf>,
+
+    /// Paths, relative to `dest`, of the files and directories created, in order of creation.
+    pub manifest: Vec<PathBuf>,
+    /// Whether `dest` itself was created.
+    pub dest_created: bool,
+}
+
+impl Finish for CopyDirOp {
+    type Output = CopyDirFinish;
+    type Error = CopyDirOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self { src, dest, keep } = self;
+
+        // `dest` may already exist if it holds kept paths.
+        let dest_created = !dest.is_dir();
+        if dest_created {
+            fs::create_dir(dest).map_err(|inner| MkdirError {
+                path: dest.clone(),
+                inner,
+            })?;
+        }
+
+        let mut manifest = Vec::new();
+        copy_recursive(src, dest, Path::new(""), keep, &mut manifest)?;
+
+        Ok(Self::Output {
+            src: src.clone(),
+            dest: dest.clone(),
+            keep: keep.clone(),
+            manifest,
+            dest_created,
+        })
+    }
+}
+
+/// Copy the contents of `src/rel` into `dest/rel`, skipping paths in `keep`, and recording each
+/// created path in `manifest`.
+#[inline]
+fn copy_recursive(
+    src: &Path,
+    dest: &Path,
+    rel: &Path,
+    keep: &[PathBuf],
+    manifest: &mut Vec<PathBuf>,
+) -> Result<(), CopyDirOpError> {
+    let dir = src.join(rel);
+    let entries = fs::read_dir(&dir).map_err(|inner| ReadError {
+        path: dir.clone(),
+        inner,
+    })?;
+
+    // Sort the entries to make the manifest deterministic.
+    let mut names = entries
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|inner| ReadError { path: dir, inner })?;
+    names.sort();
+
+    for name in names {
+        let entry_rel = rel.join(name);
+        if keep.contains(&entry_rel) {
+            continue;
+        }
+
+        let entry_src = src.join(&entry_rel);
+        let entry_dest = dest.join(&entry_rel);
+
+        // Don't follow symlinks, which may dangle or point back up the tree.
+        let ft = fs::symlink_metadata(&entry_src)
+            .map_err(|inner| MetadataError {
+                path: entry_src.clone(),
+                inner,
+            })?
+            .file_type();
+        if ft.is_symlink() {
+            let target = fs::read_link(&entry_src).map_err(|inner| ReadLinkError {
+                path: entry_src.clone(),
+                inner,
+            })?;
+            symlink(&target, &entry_src, &entry_dest)?;
+            manifest.push(entry_rel);
+        } else if ft.is_dir() {
+            // Directories holding kept paths already exist.
+            if !entry_dest.is_dir() {
+                fs::create_dir(&entry_dest).map_err(|inner| MkdirError {
+                    path: entry_dest.clone(),
+                    inner,
+                })?;
+                manifest.push(entry_rel.clone());
+            }
+
+            copy_recursive(src, dest, &entry_rel, keep, manifest)?;
+        } else {
+            fs::copy(&entry_src, &entry_dest).map_err(|inner| CopyError {
+                src: entry_src.clone(),
+                dest: entry_dest.clone(),
+                inner,
+            })?;
+            manifest.push(entry_rel);
+        }
+    }
+
+    Ok(())
+}
+
+/// Make a symlink at `dest` to `target`, the target of the symlink `src`.
+#[cfg(unix)]
+#[inline]
+fn symlink(target: &Path, _src: &Path, dest: &Path) -> Result<(), SymlinkError> {
+    use std::os::unix;
+
+    unix::fs::symlink(target, dest).map_err(|inner| SymlinkError {
+        src: target.to_path_buf(),
+        dest: dest.to_path_buf(),
+        inner,
+    })
+}
+
+#[cfg(windows)]
+#[inline]
+fn symlink(target: &Path, src: &Path, dest: &Path) -> Result<(), SymlinkError> {
+    use std::os::windows;
+
+    // Windows distinguishes between symlinks to files and to directories; dangling symlinks are
+    // linked as files.
+    let res = if src.is_dir() {
+        windows::fs::symlink_dir(target, dest)
+    } else {
+        windows::fs::symlink_file(target, dest)
+    };
+
+    res.map_err(|inner| SymlinkError {
+        src: target.to_path_buf(),
+        dest: dest.to_path_buf(),
+        inner,
+    })
+}
+
+impl Rollback for CopyDirFinish {
+    type Output = CopyDirUndoOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self {
+            src,
+            dest,
+            keep,
+            manifest,
+            dest_created,
+        } = self;
+
+        Self::Output {
+            src: src.clone(),
+            dest: dest.clone(),
+            keep: keep.clone(),
+            manifest: manifest.clone(),
+            dest_created: *dest_created,
+        }
+    }
+}
+
+/// Error encountered when finishing [`CopyDirUndoOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum CopyDirUndoOpError {
+    #[error("remove error")]
+    Remove(#[from] RemoveError),
+}
+
+/// The undo of [`CopyDirOp`] (see its documentation), created by rolling back [`CopyDirFinish`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CopyDirUndoOp {
+    /// See [`CopyDirOp`].
+    pub src: PathBuf,
+    /// See [`CopyDirOp`].
+    pub dest: PathBuf,
+    /// See [`CopyDirOp`].
+    pub keep: Vec<PathBuf>,
+
+    /// See [`CopyDirFinish`].
+    pub manifest: Vec<PathBuf>,
+    /// See [`CopyDirFinish`].
+    pub dest_created: bool,
+}
+
+/// The output of [`CopyDirUndoOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CopyDirUndoFinish {
+    /// See [`CopyDirOp`].
+    pub src: PathBuf,
+    /// See [`CopyDirOp`].
+    pub dest: PathBuf,
+    /// See [`CopyDirOp`].
+    pub keep: Vec<PathBuf>,
+}
+
+impl Finish for CopyDirUndoOp {
+    type Output = CopyDirUndoFinish;
+    type Error = CopyDirUndoOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self {
+            src,
+            dest,
+            keep,
+            manifest,
+            dest_created,
+        } = self;
+
+        // Remove in reverse order of creation, so that directories are emptied before removal.
+        for rel in manifest.iter().rev() {
+            let path = dest.join(rel);
+            match fs::symlink_metadata(&path) {
+                Ok(meta) if meta.is_dir() => remove_dir_if_empty(&path)?,
+                _ => fs::remove_file(&path).map_err(|inner| RemoveError { path, inner })?,
+            }
+        }
+
+        if *dest_created {
+            remove_dir_if_empty(dest)?;
+        }
+
+        Ok(Self::Output {
+            src: src.clone(),
+            dest: dest.clone(),
+            keep: keep.clone(),
+        })
+    }
+}
+
+/// Remove the directory at `path`, unless files not created by the copy were put in it since.
+#[inline]
+fn remove_dir_if_empty(path: &Path) -> Result<(), RemoveError> {
+    let empty = fs::read_dir(path)
+        .map(|mut entries| entries.next().is_none())
+        .map_err(|inner| RemoveError {
+            path: path.to_path_buf(),
+            inner,
+        })?;
+    if !empty {
+        return Ok(());
+    }
+
+    fs::remove_dir(path).map_err(|inner| RemoveError {
+        path: path.to_path_buf(),
+        inner,
+    })
+}
+
+impl Rollback for CopyDirUndoFinish {
+    type Output = CopyDirOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self { src, dest, keep } = self;
+
+        Self::Output {
+            src: src.clone(),
+            dest: dest.clone(),
+            keep: keep.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::fse;
+
+    use super::super::test;
+    use super::{CopyDirOp, Finish, Rollback};
+
+    /// Test nested directory.
+    #[test]
+    fn test_nested_dir() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let src = dir.join("src");
+            fs::create_dir_all(src.join("nested"))?;
+            test::new_file(&src, "a")?;
+            test::new_file(&src, "nested/b")?;
+
+            let dest = dir.join("dest");
+            let op = CopyDirOp {
+                src: src.clone(),
+                dest: dest.clone(),
+                keep: vec![],
+            };
+
+            let opf = op.finish(ctx)?;
+            let expected: Vec<PathBuf> = vec!["a".into(), "nested".into(), "nested/b".into()];
+            assert_eq!(opf.manifest, expected);
+            assert!(fse::symlink_exists(dest.join("a")));
+            assert!(fse::symlink_exists(dest.join("nested/b")));
+
+            let undo = opf.rollback();
+            let undof = undo.finish(ctx)?;
+            assert!(!fse::symlink_exists(&dest));
+            assert!(fse::symlink_exists(src.join("nested/b")));
+
+            let op2 = undof.rollback();
+            assert_eq!(op, op2);
+
+            Ok(())
+        })
+    }
+
+    /// Test that kept paths in an existing destination are left untouched.
+    #[test]
+    fn test_keep() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let src = dir.join("src");
+            fs::create_dir_all(src.join("plugins"))?;
+            test::new_file(&src, "a")?;
+            test::new_file(&src, "plugins/b")?;
+
+            let dest = dir.join("dest");
+            fs::create_dir_all(dest.join("plugins"))?;
+            fs::write(dest.join("plugins/b"), "mutated")?;
+
+            let op = CopyDirOp {
+                src: src.clone(),
+                dest: dest.clone(),
+                keep: vec!["plugins/b".into()],
+            };
+
+            let opf = op.finish(ctx)?;
+            let expected: Vec<PathBuf> = vec!["a".into()];
+            assert_eq!(opf.manifest, expected);
+            assert_eq!(fs::read_to_string(dest.join("plugins/b"))?, "mutated");
+
+            let undo = opf.rollback();
+            undo.finish(ctx)?;
+            assert!(!fse::symlink_exists(dest.join("a")));
+            assert!(fse::symlink_exists(dest.join("plugins/b")));
+
+            Ok(())
+        })
+    }
+
+    /// Test that symlinks are copied as symlinks, even if they dangle or point up the tree.
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks() -> test::Result<()> {
+        use std::os::unix;
+
+        test::with_tempdir(|dir, ctx| {
+            let src = dir.join("src");
+            fs::create_dir_all(&src)?;
+            test::new_file(&src, "a")?;
+            unix::fs::symlink("a", src.join("b"))?;
+            unix::fs::symlink("..", src.join("parent"))?;
+            unix::fs::symlink("missing", src.join("dangling"))?;
+
+            let dest = dir.join("dest");
+            let op = CopyDirOp {
+                src,
+                dest: dest.clone(),
+                keep: vec![],
+            };
+
+            let opf = op.finish(ctx)?;
+            let expected: Vec<PathBuf> =
+                vec!["a".into(), "b".into(), "dangling".into(), "parent".into()];
+            assert_eq!(opf.manifest, expected);
+            assert_eq!(fs::read_link(dest.join("b"))?, PathBuf::from("a"));
+            assert_eq!(fs::read_link(dest.join("parent"))?, PathBuf::from(".."));
+            assert_eq!(
+                fs::read_link(dest.join("dangling"))?,
+                PathBuf::from("missing")
+            );
+
+            opf.rollback().finish(ctx)?;
+            assert!(!fse::symlink_exists(&dest));
+
+            Ok(())
+        })
+    }
+
+    /// Test that undoing only removes what was copied.
+    #[test]
+    fn test_undo_foreign() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let src = dir.join("src");
+            fs::create_dir_all(src.join("nested"))?;
+            test::new_file(&src, "nested/a")?;
+
+            let dest = dir.join("dest");
+            let op = CopyDirOp {
+                src,
+                dest: dest.clone(),
+                keep: vec![],
+            };
+
+            let opf = op.finish(ctx)?;
+            fs::write(dest.join("nested/b"), "foreign")?;
+
+            opf.rollback().finish(ctx)?;
+            assert!(!fse::symlink_exists(dest.join("nested/a")));
+            assert_eq!(fs::read_to_string(dest.join("nested/b"))?, "foreign");
+
+            Ok(())
+        })
+    }
+}
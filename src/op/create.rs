@@ -61,6 +61,11 @@ impl Finish for CreateOp {
 
         Ok(Self::Output { path: path.clone() })
     }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
 }
 
 impl Rollback for CreateFinish {
@@ -111,6 +116,11 @@ impl Finish for CreateUndoOp {
 
         Ok(Self::Output { path: path.clone() })
     }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
 }
 
 impl Rollback for CreateUndoFinish {
@@ -1,27 +1,141 @@
 use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fs;
 use std::hash::{Hash, Hasher};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+use crate::fse;
+
+use super::Finish;
+
 /// Context object passed into [`super::Finish::finish`].
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FinishCtx {
     pub filesafe: FileSafe,
+    #[serde(default)]
+    pub retry: RetryPolicy,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FileSafe {
     path: PathBuf,
+    /// Size in bytes at or above which backed-up regular files are compressed, if any.
+    #[serde(default)]
+    compress: Option<u64>,
 }
 
 impl FinishCtx {
     #[inline]
     pub fn new(filesafe: FileSafe) -> Self {
-        Self { filesafe }
+        Self {
+            filesafe,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    #[inline]
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
     }
 }
 
+/// Policy for retrying ops that fail with transient filesystem errors (e.g. `EBUSY` or `EINTR`
+/// on network filesystems). Only ops that leave nothing behind when they fail are retried (see
+/// [`Finish::retryable`]).
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt.
+    pub retries: u32,
+    /// Delay between attempts.
+    pub delay: Duration,
+}
+
+/// Error of an op, with the number of attempts made.
+#[derive(Debug, thiserror::Error)]
+#[error("failed after {attempts} attempt(s)")]
+pub struct Retried<E>
+where
+    E: Error + 'static,
+{
+    #[source]
+    pub inner: E,
+    pub attempts: u32,
+}
+
+impl RetryPolicy {
+    #[inline]
+    pub fn new(retries: u32, delay: Duration) -> Self {
+        Self { retries, delay }
+    }
+
+    /// Finish `op`, retrying it if it's retryable.
+    #[inline]
+    pub fn finish<O>(&self, op: &O, ctx: &FinishCtx) -> Result<O::Output, Retried<O::Error>>
+    where
+        O: Finish,
+        O::Error: Error + 'static,
+    {
+        if op.retryable() {
+            self.run(|| op.finish(ctx))
+        } else {
+            op.finish(ctx)
+                .map_err(|inner| Retried { inner, attempts: 1 })
+        }
+    }
+
+    /// Call `f` until it succeeds, fails with a non-transient error, or the retries are exhausted.
+    #[inline]
+    pub fn run<T, E, F>(&self, mut f: F) -> Result<T, Retried<E>>
+    where
+        E: Error + 'static,
+        F: FnMut() -> Result<T, E>,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match f() {
+                Ok(v) => return Ok(v),
+                Err(err) if attempts <= self.retries && is_transient(&err) => {
+                    thread::sleep(self.delay);
+                }
+                Err(err) => {
+                    return Err(Retried {
+                        inner: err,
+                        attempts,
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// Return true if `err` is caused by an i/o error that may succeed if retried.
+#[inline]
+fn is_transient(err: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            return matches!(
+                err.kind(),
+                io::ErrorKind::Interrupted
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::ResourceBusy
+                    | io::ErrorKind::StaleNetworkFileHandle
+            );
+        }
+        source = err.source();
+    }
+
+    false
+}
+
 impl FileSafe {
     #[inline]
     pub fn new<P>(path: P) -> Self
@@ -30,16 +144,53 @@ impl FileSafe {
     {
         Self {
             path: path.as_ref().to_path_buf(),
+            compress: None,
         }
     }
 
+    /// Compress backed-up regular files that are at least `threshold` bytes in size.
+    #[inline]
+    pub fn with_compression(mut self, threshold: u64) -> Self {
+        self.compress = Some(threshold);
+        self
+    }
+
     #[inline]
     pub fn path(&self) -> &Path {
         &self.path
     }
 
+    /// Return true if a file with metadata `metadata` should be compressed when backed up.
+    #[inline]
+    pub fn should_compress(&self, metadata: &fs::Metadata) -> bool {
+        match self.compress {
+            Some(threshold) => metadata.file_type().is_file() && metadata.len() >= threshold,
+            None => false,
+        }
+    }
+
+    /// Return a path in the safe at which to back up `path`. If something is already backed up
+    /// there (e.g. the same path was removed twice), a suffix is added so that it isn't
+    /// overwritten.
     #[inline]
     pub fn resolve<P>(&self, path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        self.resolve_ext(path, "")
+    }
+
+    /// Like [`FileSafe::resolve`], but for a compressed backup.
+    #[inline]
+    pub fn resolve_compressed<P>(&self, path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        self.resolve_ext(path, ".zst")
+    }
+
+    #[inline]
+    fn resolve_ext<P>(&self, path: P, ext: &str) -> PathBuf
     where
         P: AsRef<Path>,
     {
@@ -47,6 +198,108 @@ impl FileSafe {
         path.as_ref().hash(&mut hasher);
         let hash = hasher.finish();
 
-        self.path.join(hash.to_string())
+        let mut safepath = self.path.join(format!("{}{}", hash, ext));
+        let mut n = 1;
+        while fse::symlink_exists(&safepath) {
+            safepath = self.path.join(format!("{}-{}{}", hash, n, ext));
+            n += 1;
+        }
+
+        safepath
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::io;
+    use std::time::Duration;
+
+    use super::{FileSafe, Finish, FinishCtx, RetryPolicy};
+
+    /// Op that fails with errors of `kind` the first `failures` times it's finished.
+    struct FlakyOp {
+        kind: io::ErrorKind,
+        failures: u32,
+        retryable: bool,
+        attempts: Cell<u32>,
+    }
+
+    impl FlakyOp {
+        fn new(kind: io::ErrorKind, failures: u32) -> Self {
+            Self {
+                kind,
+                failures,
+                retryable: true,
+                attempts: Cell::new(0),
+            }
+        }
+    }
+
+    impl Finish for FlakyOp {
+        type Output = u32;
+        type Error = io::Error;
+
+        fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+            let attempts = self.attempts.get() + 1;
+            self.attempts.set(attempts);
+            if attempts <= self.failures {
+                Err(io::Error::new(self.kind, "flaky"))
+            } else {
+                Ok(attempts)
+            }
+        }
+
+        fn retryable(&self) -> bool {
+            self.retryable
+        }
+    }
+
+    fn policy(retries: u32) -> (RetryPolicy, FinishCtx) {
+        let ctx = FinishCtx::new(FileSafe::new("safe"));
+        (RetryPolicy::new(retries, Duration::ZERO), ctx)
+    }
+
+    /// Test that transient errors are retried up to the number of retries.
+    #[test]
+    fn test_retry_transient() {
+        let (retry, ctx) = policy(2);
+        for kind in [
+            io::ErrorKind::Interrupted,
+            io::ErrorKind::WouldBlock,
+            io::ErrorKind::TimedOut,
+            io::ErrorKind::ResourceBusy,
+            io::ErrorKind::StaleNetworkFileHandle,
+        ] {
+            let op = FlakyOp::new(kind, 2);
+            assert_eq!(retry.finish(&op, &ctx).unwrap(), 3, "{:?}", kind);
+
+            let op = FlakyOp::new(kind, 3);
+            let err = retry.finish(&op, &ctx).unwrap_err();
+            assert_eq!(err.attempts, 3, "{:?}", kind);
+            assert_eq!(err.inner.kind(), kind);
+            assert_eq!(op.attempts.get(), 3);
+        }
+    }
+
+    /// Test that other errors fail on the first attempt.
+    #[test]
+    fn test_retry_permanent() {
+        let (retry, ctx) = policy(2);
+        let op = FlakyOp::new(io::ErrorKind::PermissionDenied, 1);
+        let err = retry.finish(&op, &ctx).unwrap_err();
+        assert_eq!(err.attempts, 1);
+        assert_eq!(op.attempts.get(), 1);
+    }
+
+    /// Test that ops that aren't retryable are only attempted once, even for transient errors.
+    #[test]
+    fn test_retry_unretryable() {
+        let (retry, ctx) = policy(2);
+        let mut op = FlakyOp::new(io::ErrorKind::ResourceBusy, 1);
+        op.retryable = false;
+        let err = retry.finish(&op, &ctx).unwrap_err();
+        assert_eq!(err.attempts, 1);
+        assert_eq!(op.attempts.get(), 1);
     }
 }
@@ -0,0 +1,303 @@
+use std::fmt;
+use std::io;
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+use static_assertions as sa;
+
+use super::ctx::FinishCtx;
+use super::error::DefaultsError;
+use super::{Finish, Rollback};
+
+sa::assert_impl_all!(DefaultsOp: Finish<Output = DefaultsFinish, Error = DefaultsOpError>);
+sa::assert_impl_all!(DefaultsFinish: Rollback<Output = DefaultsUndoOp>);
+sa::assert_impl_all!(DefaultsUndoOp: Finish<Output = DefaultsUndoFinish, Error = DefaultsOpError>);
+sa::assert_impl_all!(DefaultsUndoFinish: Rollback<Output = DefaultsOp>);
+
+/// Error encountered when finishing [`DefaultsOp`] or [`DefaultsUndoOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum DefaultsOpError {
+    #[error("defaults error")]
+    Defaults(#[from] DefaultsError),
+}
+
+/// Value of a macOS user default. Only scalar values are supported.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
+pub enum DefaultsValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl DefaultsValue {
+    /// Return the arguments passed to `defaults write` to write this value.
+    #[inline]
+    pub fn args(&self) -> [String; 2] {
+        match self {
+            Self::Bool(b) => ["-bool".to_string(), b.to_string()],
+            Self::Int(i) => ["-int".to_string(), i.to_string()],
+            Self::Float(f) => ["-float".to_string(), f.to_string()],
+            Self::Str(s) => ["-string".to_string(), s.clone()],
+        }
+    }
+
+    /// Parse the output of `defaults read` for a value of the type named by `defaults read-type`.
+    /// Returns `None` for unsupported types.
+    #[inline]
+    fn parse(typ: &str, value: &str) -> Option<Self> {
+        let value = value.strip_suffix('\n').unwrap_or(value);
+        match typ {
+            "boolean" => Some(Self::Bool(value == "1")),
+            "integer" => value.parse().ok().map(Self::Int),
+            "float" => value.parse().ok().map(Self::Float),
+            "string" => Some(Self::Str(value.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DefaultsValue {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Float(x) => write!(f, "{}", x),
+            Self::Str(s) => write!(f, "'{}'", s),
+        }
+    }
+}
+
+/// Return the current value of `key` in `domain`, or `None` if it isn't set.
+///
+/// # Errors
+///
+/// Errors if `defaults` can't be run, or the value has an unsupported type (e.g. an array), since
+/// it couldn't be restored.
+#[inline]
+pub fn read(domain: &str, key: &str) -> Result<Option<DefaultsValue>, DefaultsError> {
+    let typ = match run(&["read-type", domain, key]) {
+        Ok(typ) => typ,
+        // Reading fails if the key doesn't exist.
+        Err(err) if err.inner.kind() == io::ErrorKind::Other => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let typ = typ.trim().trim_start_matches("Type is ");
+
+    let args = ["read", domain, key];
+    let value = run(&args)?;
+    match DefaultsValue::parse(typ, &value) {
+        Some(value) => Ok(Some(value)),
+        None => Err(DefaultsError {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            inner: io::Error::other(format!("unsupported value type '{}'", typ)),
+        }),
+    }
+}
+
+#[inline]
+fn write(domain: &str, key: &str, value: &DefaultsValue) -> Result<(), DefaultsError> {
+    let [typ, value] = value.args();
+    run(&["write", domain, key, &typ, &value]).map(|_| ())
+}
+
+#[inline]
+fn delete(domain: &str, key: &str) -> Result<(), DefaultsError> {
+    run(&["delete", domain, key]).map(|_| ())
+}
+
+/// Run `defaults` with `args`, returning its stdout.
+#[inline]
+fn run(args: &[&str]) -> Result<String, DefaultsError> {
+    let output = Command::new("defaults")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .and_then(|output| {
+            if output.status.success() {
+                Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(io::Error::other(stderr.trim().to_string()))
+            }
+        });
+
+    output.map_err(|inner| DefaultsError {
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        inner,
+    })
+}
+
+/// Operation to write a macOS user default with `defaults write`.
+///
+/// # Errors
+///
+/// The operation will error if `defaults` cannot be spawned or exits with a non-zero status, or
+/// if the previous value has an unsupported type.
+///
+/// # Undo
+///
+/// Undoing will write the previous value back, or delete the key if it wasn't set. This set of
+/// operations functions in the following cycle:
+///
+/// [`DefaultsOp`] --> [`DefaultsFinish`] --> [`DefaultsUndoOp`] --> [`DefaultsUndoFinish`] -->
+/// [`DefaultsOp`] --> ...
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
+pub struct DefaultsOp {
+    /// Domain of the default, e.g. `com.apple.dock`.
+    pub domain: String,
+    /// Key of the default.
+    pub key: String,
+    /// Value to write.
+    pub value: DefaultsValue,
+}
+
+/// The output of [`DefaultsOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
+pub struct DefaultsFinish {
+    /// See [`DefaultsOp`].
+    pub domain: String,
+    /// See [`DefaultsOp`].
+    pub key: String,
+    /// See [`DefaultsOp`].
+    pub value: DefaultsValue,
+
+    /// Value before writing, or `None` if the key wasn't set.
+    pub previous: Option<DefaultsValue>,
+}
+
+impl Finish for DefaultsOp {
+    type Output = DefaultsFinish;
+    type Error = DefaultsOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self { domain, key, value } = self;
+
+        let previous = read(domain, key)?;
+        write(domain, key, value)?;
+
+        Ok(Self::Output {
+            domain: domain.clone(),
+            key: key.clone(),
+            value: value.clone(),
+            previous,
+        })
+    }
+}
+
+impl Rollback for DefaultsFinish {
+    type Output = DefaultsUndoOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self {
+            domain,
+            key,
+            value,
+            previous,
+        } = self;
+
+        Self::Output {
+            domain: domain.clone(),
+            key: key.clone(),
+            value: value.clone(),
+            previous: previous.clone(),
+        }
+    }
+}
+
+/// The undo of [`DefaultsOp`] (see its documentation), created by rolling back
+/// [`DefaultsFinish`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
+pub struct DefaultsUndoOp {
+    /// See [`DefaultsOp`].
+    pub domain: String,
+    /// See [`DefaultsOp`].
+    pub key: String,
+    /// See [`DefaultsOp`].
+    pub value: DefaultsValue,
+
+    /// See [`DefaultsFinish`].
+    pub previous: Option<DefaultsValue>,
+}
+
+/// The output of [`DefaultsUndoOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
+pub struct DefaultsUndoFinish {
+    /// See [`DefaultsOp`].
+    pub domain: String,
+    /// See [`DefaultsOp`].
+    pub key: String,
+    /// See [`DefaultsOp`].
+    pub value: DefaultsValue,
+}
+
+impl Finish for DefaultsUndoOp {
+    type Output = DefaultsUndoFinish;
+    type Error = DefaultsOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self {
+            domain,
+            key,
+            value,
+            previous,
+        } = self;
+
+        match previous {
+            Some(previous) => write(domain, key, previous)?,
+            None => delete(domain, key)?,
+        }
+
+        Ok(Self::Output {
+            domain: domain.clone(),
+            key: key.clone(),
+            value: value.clone(),
+        })
+    }
+}
+
+impl Rollback for DefaultsUndoFinish {
+    type Output = DefaultsOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self { domain, key, value } = self;
+
+        Self::Output {
+            domain: domain.clone(),
+            key: key.clone(),
+            value: value.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DefaultsValue;
+
+    /// Test parsing the output of `defaults read` for each supported type.
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Some(DefaultsValue::Bool(true)),
+            DefaultsValue::parse("boolean", "1\n")
+        );
+        assert_eq!(
+            Some(DefaultsValue::Int(-3)),
+            DefaultsValue::parse("integer", "-3\n")
+        );
+        assert_eq!(
+            Some(DefaultsValue::Float(0.5)),
+            DefaultsValue::parse("float", "0.5\n")
+        );
+        assert_eq!(
+            Some(DefaultsValue::Str("a b\n".to_string())),
+            DefaultsValue::parse("string", "a b\n\n")
+        );
+        assert_eq!(None, DefaultsValue::parse("array", "(\n)\n"));
+    }
+}
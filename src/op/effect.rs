@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// Predicted effect of an op on the filesystem, so that destructive changes in a plan can be
+/// spotted before applying it. See [`SimulatedFs::apply`].
+///
+/// [`SimulatedFs::apply`]: super::simulate::SimulatedFs::apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Effect {
+    /// Something new is created where nothing exists.
+    Create,
+    /// An existing file or directory is removed, or a file's contents are overwritten.
+    ReplaceFile,
+    /// An existing symlink is removed.
+    ReplaceSymlink,
+    /// Something existing is changed in place without being replaced, e.g. permissions or a
+    /// managed line in a file, or a service is started.
+    Modify,
+    /// The op would change nothing.
+    UpToDate,
+    /// The op expects a state that doesn't hold, and will fail, e.g. creating something where a
+    /// file already exists.
+    Conflict,
+}
+
+impl Effect {
+    /// Return true if the effect loses existing data.
+    #[inline]
+    pub fn is_destructive(&self) -> bool {
+        matches!(self, Self::ReplaceFile | Self::ReplaceSymlink)
+    }
+}
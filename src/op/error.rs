@@ -38,6 +38,16 @@ pub struct SymlinkError {
     pub inner: io::Error,
 }
 
+/// Error encountered when hard linking a file.
+#[derive(Debug, thiserror::Error)]
+#[error("i/o hardlink error")]
+pub struct HardlinkError {
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    #[source]
+    pub inner: io::Error,
+}
+
 /// Error encountered when copying a file.
 #[derive(Debug, thiserror::Error)]
 #[error("i/o copy error")]
@@ -48,6 +58,26 @@ pub struct CopyError {
     pub inner: io::Error,
 }
 
+/// Error encountered when compressing a file.
+#[derive(Debug, thiserror::Error)]
+#[error("i/o compress error")]
+pub struct CompressError {
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    #[source]
+    pub inner: io::Error,
+}
+
+/// Error encountered when decompressing a file.
+#[derive(Debug, thiserror::Error)]
+#[error("i/o decompress error")]
+pub struct DecompressError {
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    #[source]
+    pub inner: io::Error,
+}
+
 /// Error encountered when renaming a file.
 #[derive(Debug, thiserror::Error)]
 #[error("i/o rename error")]
@@ -124,3 +154,58 @@ pub struct SpawnError {
     #[source]
     pub inner: io::Error,
 }
+
+/// Error encountered when executing a file.
+#[derive(Debug, thiserror::Error)]
+#[error("i/o exec error")]
+pub struct ExecError {
+    pub path: PathBuf,
+    pub args: Vec<String>,
+    pub start: PathBuf,
+
+    #[source]
+    pub inner: io::Error,
+}
+
+/// Error encountered when running `systemctl`.
+#[derive(Debug, thiserror::Error)]
+#[error("systemctl error")]
+pub struct SystemctlError {
+    pub args: Vec<String>,
+    #[source]
+    pub inner: io::Error,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("defaults error")]
+pub struct DefaultsError {
+    pub args: Vec<String>,
+    #[source]
+    pub inner: io::Error,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("reg error")]
+pub struct RegistryError {
+    pub args: Vec<String>,
+    #[source]
+    pub inner: io::Error,
+}
+
+/// Error encountered when changing the permissions of a file.
+#[derive(Debug, thiserror::Error)]
+#[error("i/o chmod error")]
+pub struct ChmodError {
+    pub path: PathBuf,
+    #[source]
+    pub inner: io::Error,
+}
+
+/// Error encountered when changing the owner of a file.
+#[derive(Debug, thiserror::Error)]
+#[error("i/o chown error")]
+pub struct ChownError {
+    pub path: PathBuf,
+    #[source]
+    pub inner: io::Error,
+}
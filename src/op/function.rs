@@ -3,9 +3,10 @@ pub use crate::spec::NonZeroExitBehavior;
 use std::env;
 use std::path::PathBuf;
 
-use mlua::Function;
+use mlua::{Thread, ThreadStatus, ToLuaMulti, UserData, UserDataFields};
 use static_assertions as sa;
 
+use super::command::EnvMap;
 use super::ctx::FinishCtx;
 use super::Finish;
 
@@ -16,74 +17,282 @@ sa::assert_impl_all!(FunctionOp<'static>: Finish<Output = FunctionFinish<'static
 pub enum FunctionOpError {
     #[error("lua error")]
     Lua(#[from] mlua::Error),
+    #[error("invalid request yielded: {0}")]
+    Yield(String),
 }
 
 /// Operation to run a Lua function.
 ///
+/// The function is run as a coroutine, so that it can yield requests for changes (see
+/// [`FunctionYield`]) to be made by shelf as ops of their own, rather than making them itself. Use
+/// [`FunctionOp::start`] and [`FunctionOp::resume`] to handle them; [`Finish::finish`] refuses
+/// every request.
+///
 /// # Errors
 ///
-/// See [`Function`].
+/// See [`Thread`].
 ///
 /// # Undo
 ///
-/// This operation is not undo-able.
+/// This operation is not undo-able, though the ops made for its requests are.
 #[derive(Debug, Clone)]
 pub struct FunctionOp<'lua> {
-    /// Handle to the Lua function to call.
-    pub function: Function<'lua>,
+    /// Handle to the coroutine of the Lua function to call. It can only be run once.
+    pub thread: Thread<'lua>,
     /// Initial directory in which the function will be called.
     pub start: PathBuf,
+    /// Environment variables set for the duration of the call.
+    pub env: EnvMap,
+    /// Destinations changed so far in the package, exposed to the function through its
+    /// [`FunctionCtx`] argument.
+    pub changed: Vec<PathBuf>,
 }
 
 /// The output of [`FunctionOp`]. See its documentation for information.
 #[derive(Debug, Clone)]
 pub struct FunctionFinish<'lua> {
     /// See [`FunctionOp`].
-    pub function: Function<'lua>,
+    pub thread: Thread<'lua>,
     /// See [`FunctionOp`].
     pub start: PathBuf,
+    /// See [`FunctionOp`].
+    pub env: EnvMap,
+    /// See [`FunctionOp`].
+    pub changed: Vec<PathBuf>,
 
     /// The return value from the function call.
     pub ret: Option<mlua::Value<'lua>>,
 }
 
+/// Change requested by the Lua function of [`FunctionOp`] by yielding it, e.g. with
+/// `hook.link(src, dest)`. Paths are as given by the function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FunctionYield {
+    /// Symlink `src`, relative to the package, to `dest`.
+    Link { src: PathBuf, dest: PathBuf },
+    /// Write `contents` to `dest`.
+    Write { dest: PathBuf, contents: Vec<u8> },
+    /// Run `command` in a shell.
+    Cmd { command: String },
+}
+
+/// Progress of [`FunctionOp`] after starting or resuming it.
+#[derive(Debug, Clone)]
+pub enum FunctionStep<'lua> {
+    /// The function yielded a request, and is suspended until it is resumed.
+    Yield(FunctionYield),
+    /// The function returned.
+    Return(Option<mlua::Value<'lua>>),
+}
+
 impl<'lua> Finish for FunctionOp<'lua> {
     type Output = FunctionFinish<'lua>;
     type Error = FunctionOpError;
 
     #[inline]
     fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
-        let Self { function, start } = self;
+        let Self {
+            thread,
+            start,
+            env,
+            changed,
+        } = self;
+
+        // Refuse every request, so that the function continues.
+        let mut step = self.start()?;
+        let ret = loop {
+            match step {
+                FunctionStep::Yield(_) => step = self.resume(false)?,
+                FunctionStep::Return(ret) => break ret,
+            }
+        };
+
+        Ok(Self::Output {
+            thread: thread.clone(),
+            start: start.clone(),
+            env: env.clone(),
+            changed: changed.clone(),
+            ret,
+        })
+    }
+}
 
+impl<'lua> FunctionOp<'lua> {
+    /// Start the function, passing it its [`FunctionCtx`], and run it until it yields or returns.
+    #[inline]
+    pub fn start(&self) -> Result<FunctionStep<'lua>, FunctionOpError> {
+        let ctx = FunctionCtx {
+            changed: self.changed.clone(),
+        };
+        self.step(ctx)
+    }
+
+    /// Resume the function after it yielded, passing it `ok` as the result of its request, and
+    /// run it until it yields or returns again.
+    #[inline]
+    pub fn resume(&self, ok: bool) -> Result<FunctionStep<'lua>, FunctionOpError> {
+        self.step(ok)
+    }
+
+    /// Resume the coroutine with `args` in the start directory and with the environment
+    /// variables set.
+    #[inline]
+    fn step<A>(&self, args: A) -> Result<FunctionStep<'lua>, FunctionOpError>
+    where
+        A: ToLuaMulti<'lua>,
+    {
         // Change to the start directory.
         let cwd = env::current_dir().unwrap();
-        env::set_current_dir(start).unwrap();
+        env::set_current_dir(&self.start).unwrap();
 
-        // Call the function.
-        let ret = self.call();
+        // Set the environment variables, saving previous values.
+        let prev: Vec<_> = self
+            .env
+            .iter()
+            .map(|(k, v)| {
+                let prev = env::var_os(k);
+                env::set_var(k, v);
+                (k, prev)
+            })
+            .collect();
 
-        // Restore cwd regardless of error or not.
+        // Run the coroutine.
+        let ret = self.thread.resume::<_, mlua::Value>(args);
+
+        // Restore cwd and environment regardless of error or not.
         env::set_current_dir(&cwd).unwrap();
+        for (k, prev) in prev {
+            match prev {
+                Some(prev) => env::set_var(k, prev),
+                None => env::remove_var(k),
+            }
+        }
 
         let ret = ret?;
-        Ok(Self::Output {
-            function: function.clone(),
-            start: start.clone(),
-            ret,
-        })
+        if self.thread.status() == ThreadStatus::Resumable {
+            Ok(FunctionStep::Yield(FunctionYield::from_value(ret)?))
+        } else {
+            let ret = match ret {
+                mlua::Value::Nil => None,
+                v => Some(v),
+            };
+            Ok(FunctionStep::Return(ret))
+        }
     }
 }
 
-impl<'lua> FunctionOp<'lua> {
-    /// Call the Lua function and return the return value.
+impl FunctionYield {
+    /// Parse a request yielded as a table, e.g. `{ kind = 'link', src = 'a', dest = '.a' }`.
+    #[inline]
+    fn from_value(value: mlua::Value<'_>) -> Result<Self, FunctionOpError> {
+        let table = match value {
+            mlua::Value::Table(table) => table,
+            _ => return Err(FunctionOpError::Yield("expected table".to_string())),
+        };
+
+        let kind: String = table.get("kind")?;
+        let req = match kind.as_str() {
+            "link" => Self::Link {
+                src: table.get::<_, String>("src")?.into(),
+                dest: table.get::<_, String>("dest")?.into(),
+            },
+            "write" => Self::Write {
+                dest: table.get::<_, String>("dest")?.into(),
+                contents: table
+                    .get::<_, mlua::String>("contents")?
+                    .as_bytes()
+                    .to_vec(),
+            },
+            "cmd" => Self::Cmd {
+                command: table.get("command")?,
+            },
+            _ => return Err(FunctionOpError::Yield(format!("unknown kind '{}'", kind))),
+        };
+        Ok(req)
+    }
+}
+
+/// Context passed to the Lua function of [`FunctionOp`] as its argument.
+///
+/// `ctx.changed_paths()` returns the list of destinations changed so far in the package.
+#[derive(Debug, Clone)]
+pub struct FunctionCtx {
+    /// See [`FunctionOp`].
+    pub changed: Vec<PathBuf>,
+}
+
+impl UserData for FunctionCtx {
     #[inline]
-    fn call(&self) -> Result<Option<mlua::Value<'lua>>, FunctionOpError> {
-        let ret: mlua::Value = self.function.call(())?;
-        let ret = match ret {
-            mlua::Value::Nil => None,
-            v => Some(v),
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        // Exposed as a field holding a function, so that it can be called as `ctx.changed_paths()`
+        // as well as `ctx:changed_paths()`.
+        fields.add_field_method_get("changed_paths", |lua, this| {
+            let paths: Vec<String> = this
+                .changed
+                .iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect();
+            lua.create_function(move |_, ()| Ok(paths.clone()))
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use mlua::{Function, Lua};
+
+    use super::{FunctionOp, FunctionStep, FunctionYield};
+
+    /// Test that requests yielded by the function are returned in turn, and that it is passed the
+    /// result of each.
+    #[test]
+    fn test_yield() -> mlua::Result<()> {
+        let lua = Lua::new();
+        let function: Function = lua
+            .load(
+                r#"
+                function()
+                    local ok = coroutine.yield { kind = 'link', src = 'a', dest = '.a' }
+                    coroutine.yield { kind = 'cmd', command = tostring(ok) }
+                    return 1
+                end
+                "#,
+            )
+            .eval()?;
+
+        let op = FunctionOp {
+            thread: lua.create_thread(function)?,
+            start: std::env::current_dir().unwrap(),
+            env: Default::default(),
+            changed: Vec::new(),
         };
 
-        Ok(ret)
+        match op.start().unwrap() {
+            FunctionStep::Yield(req) => assert_eq!(
+                FunctionYield::Link {
+                    src: PathBuf::from("a"),
+                    dest: PathBuf::from(".a"),
+                },
+                req
+            ),
+            FunctionStep::Return(_) => panic!("function returned before yielding"),
+        }
+        match op.resume(false).unwrap() {
+            FunctionStep::Yield(req) => assert_eq!(
+                FunctionYield::Cmd {
+                    command: "false".to_string()
+                },
+                req
+            ),
+            FunctionStep::Return(_) => panic!("function returned before yielding"),
+        }
+        match op.resume(true).unwrap() {
+            FunctionStep::Return(ret) => assert!(ret.is_some()),
+            FunctionStep::Yield(_) => panic!("function yielded after its last request"),
+        }
+
+        Ok(())
     }
 }
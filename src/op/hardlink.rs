@@ -0,0 +1,225 @@
+use std::path::PathBuf;
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+use static_assertions as sa;
+
+use super::ctx::FinishCtx;
+use super::error::{CopyError, HardlinkError, RemoveError};
+use super::{Finish, Rollback};
+
+sa::assert_impl_all!(HardlinkOp: Finish<Output = HardlinkFinish, Error = HardlinkOpError>);
+sa::assert_impl_all!(HardlinkFinish: Rollback<Output = HardlinkUndoOp>);
+sa::assert_impl_all!(
+    HardlinkUndoOp: Finish<Output = HardlinkUndoFinish, Error = HardlinkUndoOpError>
+);
+sa::assert_impl_all!(HardlinkUndoFinish: Rollback<Output = HardlinkOp>);
+
+/// Error encountered when finishing [`HardlinkOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum HardlinkOpError {
+    #[error("hardlink error")]
+    Hardlink(#[from] HardlinkError),
+    #[error("copy error")]
+    Copy(#[from] CopyError),
+}
+
+/// Operation to hard link a file from `src` to `dest`. If `src` and `dest` are on different
+/// filesystems, the file is copied instead (see [`fs::copy`]).
+///
+/// # Errors
+///
+/// It is assumed that `src` points to an readable regular file, and that no file exists at `dest`
+/// (which must be writable). These premises are not checked, and the operation will error if they
+/// are not met.
+///
+/// # Undo
+///
+/// Undoing will delete the link or copy. This set of operations functions in the following cycle:
+///
+/// [`HardlinkOp`] --> [`HardlinkFinish`] --> [`HardlinkUndoOp`] --> [`HardlinkUndoFinish`] -->
+/// [`HardlinkOp`] --> ...
+///
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HardlinkOp {
+    /// Path to file to link.
+    pub src: PathBuf,
+    /// Path to destination of link.
+    pub dest: PathBuf,
+}
+
+/// The output of [`HardlinkOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HardlinkFinish {
+    /// See [`HardlinkOp`].
+    pub src: PathBuf,
+    /// See [`HardlinkOp`].
+    pub dest: PathBuf,
+    /// True if the file was copied because `src` and `dest` are on different filesystems.
+    pub copied: bool,
+}
+
+impl Finish for HardlinkOp {
+    type Output = HardlinkFinish;
+    type Error = HardlinkOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self { src, dest } = self;
+
+        // Perform hard link, and fall back to copying across filesystems.
+        let copied = match fs::hard_link(src, dest) {
+            Ok(()) => false,
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                fs::copy(src, dest).map_err(|inner| CopyError {
+                    src: src.clone(),
+                    dest: dest.clone(),
+                    inner,
+                })?;
+                true
+            }
+            Err(inner) => {
+                return Err(HardlinkError {
+                    src: src.clone(),
+                    dest: dest.clone(),
+                    inner,
+                }
+                .into())
+            }
+        };
+
+        Ok(Self::Output {
+            src: src.clone(),
+            dest: dest.clone(),
+            copied,
+        })
+    }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
+}
+
+impl Rollback for HardlinkFinish {
+    type Output = HardlinkUndoOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self { src, dest, copied } = self;
+
+        Self::Output {
+            src: src.clone(),
+            dest: dest.clone(),
+            copied: *copied,
+        }
+    }
+}
+
+/// Error encountered when finishing [`HardlinkUndoOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum HardlinkUndoOpError {
+    #[error("remove error")]
+    Remove(#[from] RemoveError),
+}
+
+/// The undo of [`HardlinkOp`] (see its documentation), created by rolling back
+/// [`HardlinkFinish`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HardlinkUndoOp {
+    /// See [`HardlinkOp`].
+    pub src: PathBuf,
+    /// See [`HardlinkOp`].
+    pub dest: PathBuf,
+    /// See [`HardlinkFinish`].
+    pub copied: bool,
+}
+
+/// The output of [`HardlinkUndoOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HardlinkUndoFinish {
+    /// See [`HardlinkOp`].
+    pub src: PathBuf,
+    /// See [`HardlinkOp`].
+    pub dest: PathBuf,
+}
+
+impl Finish for HardlinkUndoOp {
+    type Output = HardlinkUndoFinish;
+    type Error = HardlinkUndoOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self { src, dest, .. } = self;
+
+        // Remove link or copy; either way, `src` is left alone.
+        fs::remove_file(dest).map_err(|inner| RemoveError {
+            path: dest.clone(),
+            inner,
+        })?;
+
+        Ok(Self::Output {
+            src: src.clone(),
+            dest: dest.clone(),
+        })
+    }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
+}
+
+impl Rollback for HardlinkUndoFinish {
+    type Output = HardlinkOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self { src, dest } = self;
+
+        Self::Output {
+            src: src.clone(),
+            dest: dest.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::io::Write;
+
+    use crate::fse;
+
+    use super::super::test;
+    use super::{Finish, HardlinkOp, Rollback};
+
+    /// Test linking within a single filesystem.
+    #[test]
+    fn test_same_filesystem() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let (mut file, src) = test::new_file(dir, "src")?;
+            file.write_all(b"contents")?;
+
+            let dest = dir.join("dest");
+            let op = HardlinkOp {
+                src: src.clone(),
+                dest: dest.clone(),
+            };
+
+            let opf = op.finish(ctx)?;
+            assert!(!opf.copied);
+            assert_eq!(fs::read(&src)?, fs::read(&dest)?);
+
+            let undo = opf.rollback();
+            let undof = undo.finish(ctx)?;
+            assert!(!fse::symlink_exists(&dest));
+            assert!(fse::symlink_exists(&src));
+
+            let op2 = undof.rollback();
+            assert_eq!(op, op2);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,187 @@
+//! Property tests for the rollback invariant of ops: applying any sequence of ops with journaling
+//! and then rolling them back leaves the filesystem exactly as it was.
+
+use std::fs;
+use std::path::Path;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::fse::Snapshot;
+
+use super::ctx::FinishCtx;
+use super::journal::{JournalOp, OpJournal};
+use super::test;
+use super::{CopyOp, CreateOp, HardlinkOp, LinkOp, MkdirOp, RmOp, SourceLineOp, WriteOp};
+
+/// Names of the files that ops are generated over. A small set makes ops more likely to interact.
+const NAMES: &[&str] = &["a", "b", "c", "d", "e"];
+
+/// What to do to the filesystem, independent of its current state. Each intent is turned into an
+/// op that is valid for the state at the time (or skipped) by [`Intent::op`].
+#[derive(Debug, Clone)]
+enum Intent {
+    Create(usize),
+    Write(usize, String),
+    Link(usize, usize),
+    Copy(usize, usize),
+    Hardlink(usize, usize),
+    Mkdir(usize),
+    Rm(usize),
+    SourceLine(usize, String),
+}
+
+/// What is at a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Missing,
+    File,
+    Dir,
+    Symlink,
+}
+
+impl Intent {
+    /// Return an op that carries out this intent in `root`, or `None` if the intent doesn't apply
+    /// to what's currently there.
+    fn op(&self, root: &Path) -> Option<JournalOp> {
+        let path = |i: &usize| root.join(NAMES[*i]);
+        let kind = |i: &usize| kind(&path(i));
+
+        let op = match self {
+            Self::Create(i) if kind(i) == Kind::Missing => CreateOp { path: path(i) }.into(),
+            Self::Write(i, contents) if kind(i) == Kind::File => WriteOp {
+                path: path(i),
+                contents: contents.clone().into_bytes(),
+            }
+            .into(),
+            Self::Link(src, dest) if kind(src) != Kind::Missing && kind(dest) == Kind::Missing => {
+                LinkOp {
+                    src: path(src),
+                    dest: path(dest),
+                    fallback: false,
+                }
+                .into()
+            }
+            Self::Copy(src, dest) if kind(src) == Kind::File && kind(dest) == Kind::Missing => {
+                CopyOp {
+                    src: path(src),
+                    dest: path(dest),
+                    dir: false,
+                }
+                .into()
+            }
+            Self::Hardlink(src, dest) if kind(src) == Kind::File && kind(dest) == Kind::Missing => {
+                HardlinkOp {
+                    src: path(src),
+                    dest: path(dest),
+                }
+                .into()
+            }
+            Self::Mkdir(i) if kind(i) == Kind::Missing => MkdirOp { path: path(i) }.into(),
+            Self::Rm(i) if kind(i) != Kind::Missing => RmOp {
+                path: path(i),
+                dir: kind(i) == Kind::Dir,
+            }
+            .into(),
+            Self::SourceLine(i, line) if matches!(kind(i), Kind::Missing | Kind::File) => {
+                SourceLineOp {
+                    path: path(i),
+                    line: line.clone(),
+                    block: "shelf test".to_string(),
+                }
+                .into()
+            }
+            _ => return None,
+        };
+
+        Some(op)
+    }
+}
+
+fn kind(path: &Path) -> Kind {
+    match fs::symlink_metadata(path) {
+        Err(_) => Kind::Missing,
+        Ok(meta) if meta.file_type().is_symlink() => Kind::Symlink,
+        Ok(meta) if meta.is_dir() => Kind::Dir,
+        Ok(_) => Kind::File,
+    }
+}
+
+fn name() -> impl Strategy<Value = usize> {
+    0..NAMES.len()
+}
+
+/// Newline-terminated text, since source_line ops normalize line endings.
+fn text() -> impl Strategy<Value = String> {
+    vec("[a-z ]{0,8}", 0..4).prop_map(|lines| lines.iter().map(|l| format!("{}\n", l)).collect())
+}
+
+fn intent() -> impl Strategy<Value = Intent> {
+    prop_oneof![
+        name().prop_map(Intent::Create),
+        (name(), text()).prop_map(|(i, contents)| Intent::Write(i, contents)),
+        (name(), name()).prop_map(|(src, dest)| Intent::Link(src, dest)),
+        (name(), name()).prop_map(|(src, dest)| Intent::Copy(src, dest)),
+        (name(), name()).prop_map(|(src, dest)| Intent::Hardlink(src, dest)),
+        name().prop_map(Intent::Mkdir),
+        name().prop_map(Intent::Rm),
+        (name(), "[a-z ]{1,8}").prop_map(|(i, line)| Intent::SourceLine(i, line)),
+    ]
+}
+
+/// Set up `root` with `initial`, apply `intents` in a single transaction, roll back, and check
+/// that `root` is as it was.
+fn check(initial: &[(usize, String)], intents: &[Intent]) -> test::Result<()> {
+    test::with_tempdir(|root, ctx| {
+        for (i, contents) in initial {
+            fs::write(root.join(NAMES[*i]), contents)?;
+        }
+        let before = Snapshot::take(root)?;
+
+        let mut journal = OpJournal::new();
+        apply(&mut journal, root, intents, ctx)?;
+
+        let mut rollback = journal.rollback_last().ok_or("nothing to roll back")?;
+        while let Some(res) = rollback.next() {
+            res?;
+        }
+
+        let after = Snapshot::take(root)?;
+        let changes: Vec<_> = before
+            .diff(&after)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert!(changes.is_empty(), "rollback left changes: {:?}", changes);
+
+        Ok(())
+    })
+}
+
+fn apply(
+    journal: &mut OpJournal,
+    root: &Path,
+    intents: &[Intent],
+    ctx: &FinishCtx,
+) -> test::Result<()> {
+    let mut t = journal.lock();
+    for intent in intents {
+        if let Some(op) = intent.op(root) {
+            t.append_finish(op, ctx)?;
+        }
+    }
+
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_rollback(
+        initial in vec((name(), text()), 0..3),
+        intents in vec(intent(), 1..16),
+    ) {
+        check(&initial, &intents).unwrap();
+    }
+}
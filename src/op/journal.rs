@@ -1,11 +1,25 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
 use serde::{Deserialize, Serialize};
 
-use crate::journal::{self, Journal, Record, Rollback};
+use crate::journal::writer::{ReadError, WriteError};
+use crate::journal::{
+    self, Indexed, Journal, JournalIndex, Record, Rollback, RotatingFile, Stamp, Stamped,
+};
 
-use super::ctx::FinishCtx;
+use super::ctx::{FinishCtx, Retried};
+use super::reconcile;
+#[cfg(unix)]
+use super::ChownOp;
+#[cfg(all(windows, feature = "registry"))]
+use super::RegistryOp;
 use super::{
-    CopyOp, CopyUndoOp, CreateOp, CreateUndoOp, Finish, Finished, FinishedError, LinkOp,
-    LinkUndoOp, MkdirOp, MkdirUndoOp, RmOp, RmUndoOp, Undo, UndoFinished, WriteOp, WriteUndoOp,
+    ChmodOp, CopyDirOp, CopyDirUndoOp, CopyOp, CopyUndoOp, CreateOp, CreateUndoOp, DefaultsOp,
+    Finish, Finished, FinishedError, HardlinkOp, HardlinkUndoOp, LinkOp, LinkUndoOp, MkdirOp,
+    MkdirUndoOp, RmOp, RmUndoOp, SourceLineOp, SourceLineUndoOp, SystemctlOp, Undo, UndoFinished,
+    WriteOp, WriteUndoOp,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -18,6 +32,14 @@ pub enum JournalOpError {
     Copy(#[from] FinishedError<CopyOp>),
     #[error("copy undo op error")]
     CopyUndo(#[from] FinishedError<CopyUndoOp>),
+    #[error("hardlink op error")]
+    Hardlink(#[from] FinishedError<HardlinkOp>),
+    #[error("hardlink undo op error")]
+    HardlinkUndo(#[from] FinishedError<HardlinkUndoOp>),
+    #[error("copy dir op error")]
+    CopyDir(#[from] FinishedError<CopyDirOp>),
+    #[error("copy dir undo op error")]
+    CopyDirUndo(#[from] FinishedError<CopyDirUndoOp>),
     #[error("create op error")]
     Create(#[from] FinishedError<CreateOp>),
     #[error("create undo op error")]
@@ -34,6 +56,22 @@ pub enum JournalOpError {
     Rm(#[from] FinishedError<RmOp>),
     #[error("rm undo op error")]
     RmUndo(#[from] FinishedError<RmUndoOp>),
+    #[error("systemctl op error")]
+    Systemctl(#[from] FinishedError<SystemctlOp>),
+    #[error("chmod op error")]
+    Chmod(#[from] FinishedError<ChmodOp>),
+    #[cfg(unix)]
+    #[error("chown op error")]
+    Chown(#[from] FinishedError<ChownOp>),
+    #[error("source line op error")]
+    SourceLine(#[from] FinishedError<SourceLineOp>),
+    #[error("source line undo op error")]
+    SourceLineUndo(#[from] FinishedError<SourceLineUndoOp>),
+    #[error("defaults op error")]
+    Defaults(#[from] FinishedError<DefaultsOp>),
+    #[cfg(all(windows, feature = "registry"))]
+    #[error("registry op error")]
+    Registry(#[from] FinishedError<RegistryOp>),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -42,6 +80,10 @@ pub enum JournalOp {
     LinkUndo(Undo<LinkOp>),
     Copy(CopyOp),
     CopyUndo(Undo<CopyOp>),
+    Hardlink(HardlinkOp),
+    HardlinkUndo(Undo<HardlinkOp>),
+    CopyDir(CopyDirOp),
+    CopyDirUndo(Undo<CopyDirOp>),
     Create(CreateOp),
     CreateUndo(Undo<CreateOp>),
     Write(WriteOp),
@@ -50,12 +92,29 @@ pub enum JournalOp {
     MkdirUndo(Undo<MkdirOp>),
     Rm(RmOp),
     RmUndo(Undo<RmOp>),
+    Systemctl(SystemctlOp),
+    SystemctlUndo(Undo<SystemctlOp>),
+    Chmod(ChmodOp),
+    ChmodUndo(Undo<ChmodOp>),
+    #[cfg(unix)]
+    Chown(ChownOp),
+    #[cfg(unix)]
+    ChownUndo(Undo<ChownOp>),
+    SourceLine(SourceLineOp),
+    SourceLineUndo(Undo<SourceLineOp>),
+    Defaults(DefaultsOp),
+    DefaultsUndo(Undo<DefaultsOp>),
+    #[cfg(all(windows, feature = "registry"))]
+    Registry(RegistryOp),
+    #[cfg(all(windows, feature = "registry"))]
+    RegistryUndo(Undo<RegistryOp>),
 }
 
 /// Generate [`From`], [`Finish`] implementations for [`Op`].
 macro_rules! Op_impls {
-    ($($Variant:ident => $SubOp:ty),*) => {
+    ($($(#[$attr:meta])* $Variant:ident => $SubOp:ty),*) => {
         $(
+            $(#[$attr])*
             impl From<$SubOp> for JournalOp {
                 #[inline]
                 fn from(op: $SubOp) -> Self {
@@ -73,12 +132,23 @@ macro_rules! Op_impls {
             fn finish(&self, ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
                 let res = match self {
                     $(
+                        $(#[$attr])*
                         Self::$Variant(op) => op.finish(ctx)?.into(),
                     )*
                 };
 
                 Ok(res)
             }
+
+            #[inline]
+            fn retryable(&self) -> bool {
+                match self {
+                    $(
+                        $(#[$attr])*
+                        Self::$Variant(op) => op.retryable(),
+                    )*
+                }
+            }
         }
     };
 }
@@ -88,6 +158,10 @@ Op_impls!(
     LinkUndo => Undo<LinkOp>,
     Copy => CopyOp,
     CopyUndo => Undo<CopyOp>,
+    Hardlink => HardlinkOp,
+    HardlinkUndo => Undo<HardlinkOp>,
+    CopyDir => CopyDirOp,
+    CopyDirUndo => Undo<CopyDirOp>,
     Create => CreateOp,
     CreateUndo => Undo<CreateOp>,
     Write => WriteOp,
@@ -95,7 +169,23 @@ Op_impls!(
     Mkdir => MkdirOp,
     MkdirUndo => Undo<MkdirOp>,
     Rm => RmOp,
-    RmUndo => Undo<RmOp>
+    RmUndo => Undo<RmOp>,
+    Systemctl => SystemctlOp,
+    SystemctlUndo => Undo<SystemctlOp>,
+    Chmod => ChmodOp,
+    ChmodUndo => Undo<ChmodOp>,
+    #[cfg(unix)]
+    Chown => ChownOp,
+    #[cfg(unix)]
+    ChownUndo => Undo<ChownOp>,
+    SourceLine => SourceLineOp,
+    SourceLineUndo => Undo<SourceLineOp>,
+    Defaults => DefaultsOp,
+    DefaultsUndo => Undo<DefaultsOp>,
+    #[cfg(all(windows, feature = "registry"))]
+    Registry => RegistryOp,
+    #[cfg(all(windows, feature = "registry"))]
+    RegistryUndo => Undo<RegistryOp>
 );
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -104,6 +194,10 @@ pub enum JournalOpFinish {
     LinkUndo(UndoFinished<LinkOp>),
     Copy(Finished<CopyOp>),
     CopyUndo(UndoFinished<CopyOp>),
+    Hardlink(Finished<HardlinkOp>),
+    HardlinkUndo(UndoFinished<HardlinkOp>),
+    CopyDir(Finished<CopyDirOp>),
+    CopyDirUndo(UndoFinished<CopyDirOp>),
     Create(Finished<CreateOp>),
     CreateUndo(UndoFinished<CreateOp>),
     Write(Finished<WriteOp>),
@@ -112,11 +206,28 @@ pub enum JournalOpFinish {
     MkdirUndo(UndoFinished<MkdirOp>),
     Rm(Finished<RmOp>),
     RmUndo(UndoFinished<RmOp>),
+    Systemctl(Finished<SystemctlOp>),
+    SystemctlUndo(UndoFinished<SystemctlOp>),
+    Chmod(Finished<ChmodOp>),
+    ChmodUndo(UndoFinished<ChmodOp>),
+    #[cfg(unix)]
+    Chown(Finished<ChownOp>),
+    #[cfg(unix)]
+    ChownUndo(UndoFinished<ChownOp>),
+    SourceLine(Finished<SourceLineOp>),
+    SourceLineUndo(UndoFinished<SourceLineOp>),
+    Defaults(Finished<DefaultsOp>),
+    DefaultsUndo(UndoFinished<DefaultsOp>),
+    #[cfg(all(windows, feature = "registry"))]
+    Registry(Finished<RegistryOp>),
+    #[cfg(all(windows, feature = "registry"))]
+    RegistryUndo(UndoFinished<RegistryOp>),
 }
 
 macro_rules! JournalOpFinish_impls {
-    ($($Variant:ident => $SubOp:ty),*) => {
+    ($($(#[$attr:meta])* $Variant:ident => $SubOp:ty),*) => {
         $(
+            $(#[$attr])*
             impl From<$SubOp> for JournalOpFinish {
                 #[inline]
                 fn from(v: $SubOp) -> Self {
@@ -132,6 +243,7 @@ macro_rules! JournalOpFinish_impls {
             fn rollback(&self) -> Self::Output {
                 match self {
                     $(
+                        $(#[$attr])*
                         Self::$Variant(op) => op.rollback().into(),
                     )*
                 }
@@ -145,6 +257,10 @@ JournalOpFinish_impls!(
     LinkUndo => UndoFinished<LinkOp>,
     Copy => Finished<CopyOp>,
     CopyUndo => UndoFinished<CopyOp>,
+    Hardlink => Finished<HardlinkOp>,
+    HardlinkUndo => UndoFinished<HardlinkOp>,
+    CopyDir => Finished<CopyDirOp>,
+    CopyDirUndo => UndoFinished<CopyDirOp>,
     Create => Finished<CreateOp>,
     CreateUndo => UndoFinished<CreateOp>,
     Write => Finished<WriteOp>,
@@ -152,9 +268,113 @@ JournalOpFinish_impls!(
     Mkdir => Finished<MkdirOp>,
     MkdirUndo => UndoFinished<MkdirOp>,
     Rm => Finished<RmOp>,
-    RmUndo => UndoFinished<RmOp>
+    RmUndo => UndoFinished<RmOp>,
+    Systemctl => Finished<SystemctlOp>,
+    SystemctlUndo => UndoFinished<SystemctlOp>,
+    Chmod => Finished<ChmodOp>,
+    ChmodUndo => UndoFinished<ChmodOp>,
+    #[cfg(unix)]
+    Chown => Finished<ChownOp>,
+    #[cfg(unix)]
+    ChownUndo => UndoFinished<ChownOp>,
+    SourceLine => Finished<SourceLineOp>,
+    SourceLineUndo => UndoFinished<SourceLineOp>,
+    Defaults => Finished<DefaultsOp>,
+    DefaultsUndo => UndoFinished<DefaultsOp>,
+    #[cfg(all(windows, feature = "registry"))]
+    Registry => Finished<RegistryOp>,
+    #[cfg(all(windows, feature = "registry"))]
+    RegistryUndo => UndoFinished<RegistryOp>
 );
 
+impl JournalOpFinish {
+    /// Return the destination path affected by the op, if any.
+    #[inline]
+    pub fn dest(&self) -> Option<&Path> {
+        match self {
+            Self::Link(fin) => Some(&fin.dest),
+            Self::LinkUndo(fin) => Some(&fin.dest),
+            Self::Copy(fin) => Some(&fin.dest),
+            Self::CopyUndo(fin) => Some(&fin.dest),
+            Self::Hardlink(fin) => Some(&fin.dest),
+            Self::HardlinkUndo(fin) => Some(&fin.dest),
+            Self::CopyDir(fin) => Some(&fin.dest),
+            Self::CopyDirUndo(fin) => Some(&fin.dest),
+            Self::Create(fin) => Some(&fin.path),
+            Self::CreateUndo(fin) => Some(&fin.path),
+            Self::Write(fin) => Some(&fin.path),
+            Self::WriteUndo(fin) => Some(&fin.path),
+            Self::Mkdir(fin) => Some(&fin.path),
+            Self::MkdirUndo(fin) => Some(&fin.path),
+            Self::Rm(fin) => Some(&fin.path),
+            Self::RmUndo(fin) => Some(&fin.path),
+            Self::Systemctl(_) | Self::SystemctlUndo(_) => None,
+            Self::Chmod(fin) => Some(&fin.path),
+            Self::ChmodUndo(fin) => Some(&fin.path),
+            #[cfg(unix)]
+            Self::Chown(fin) => Some(&fin.path),
+            #[cfg(unix)]
+            Self::ChownUndo(fin) => Some(&fin.path),
+            Self::SourceLine(fin) => Some(&fin.path),
+            Self::SourceLineUndo(fin) => Some(&fin.path),
+            Self::Defaults(_) | Self::DefaultsUndo(_) => None,
+            #[cfg(all(windows, feature = "registry"))]
+            Self::Registry(_) | Self::RegistryUndo(_) => None,
+        }
+    }
+
+    /// Return the source path of the op, if any.
+    #[inline]
+    pub fn src(&self) -> Option<&Path> {
+        match self {
+            Self::Link(fin) => Some(&fin.src),
+            Self::Copy(fin) => Some(&fin.src),
+            Self::Hardlink(fin) => Some(&fin.src),
+            Self::CopyDir(fin) => Some(&fin.src),
+            _ => None,
+        }
+    }
+
+    /// Return the name of the kind of op, e.g. `link` or `link_undo`.
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Link(_) => "link",
+            Self::LinkUndo(_) => "link_undo",
+            Self::Copy(_) => "copy",
+            Self::CopyUndo(_) => "copy_undo",
+            Self::Hardlink(_) => "hardlink",
+            Self::HardlinkUndo(_) => "hardlink_undo",
+            Self::CopyDir(_) => "copy_dir",
+            Self::CopyDirUndo(_) => "copy_dir_undo",
+            Self::Create(_) => "create",
+            Self::CreateUndo(_) => "create_undo",
+            Self::Write(_) => "write",
+            Self::WriteUndo(_) => "write_undo",
+            Self::Mkdir(_) => "mkdir",
+            Self::MkdirUndo(_) => "mkdir_undo",
+            Self::Rm(_) => "rm",
+            Self::RmUndo(_) => "rm_undo",
+            Self::Systemctl(_) => "systemctl",
+            Self::SystemctlUndo(_) => "systemctl_undo",
+            Self::Chmod(_) => "chmod",
+            Self::ChmodUndo(_) => "chmod_undo",
+            #[cfg(unix)]
+            Self::Chown(_) => "chown",
+            #[cfg(unix)]
+            Self::ChownUndo(_) => "chown_undo",
+            Self::SourceLine(_) => "source_line",
+            Self::SourceLineUndo(_) => "source_line_undo",
+            Self::Defaults(_) => "defaults",
+            Self::DefaultsUndo(_) => "defaults_undo",
+            #[cfg(all(windows, feature = "registry"))]
+            Self::Registry(_) => "registry",
+            #[cfg(all(windows, feature = "registry"))]
+            Self::RegistryUndo(_) => "registry_undo",
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct JournalOpAtom {
     op: JournalOpFinish,
@@ -167,7 +387,11 @@ impl Rollback for JournalOpAtom {
     #[inline]
     fn rollback(&self) -> Self::Output {
         let undo = self.op.rollback();
-        let undof = undo.finish(&self.ctx)?;
+        let undof = self
+            .ctx
+            .retry
+            .finish(&undo, &self.ctx)
+            .map_err(|err| err.inner)?;
 
         Ok(Self {
             op: undof,
@@ -176,11 +400,47 @@ impl Rollback for JournalOpAtom {
     }
 }
 
+/// Atoms are indexed under the destination whose expected state they determine (see
+/// [`OpJournal::reconcile`]).
+impl Indexed for JournalOpAtom {
+    #[inline]
+    fn index_key(&self) -> Option<&Path> {
+        reconcile::determined_dest(&self.op)
+    }
+}
+
+/// Error encountered when opening or syncing a persisted [`OpJournal`].
+#[derive(Debug, thiserror::Error)]
+pub enum JournalFileError {
+    #[error("journal is in use by another process")]
+    Locked,
+    #[error("i/o error")]
+    Io(#[from] io::Error),
+    #[error("read error")]
+    Read(#[from] ReadError),
+    #[error("write error")]
+    Write(#[from] WriteError),
+}
+
 /// Write-ahead logging for [`JournalOp`] that permits rollback.
 #[derive(Debug)]
 pub struct OpJournal {
-    /// This struct is just a wrapper on [`Journal`].
+    /// This struct is mostly a wrapper on [`Journal`].
     inner: Journal<JournalOpAtom>,
+    /// File that the journal is persisted to, if any.
+    file: Option<JournalFile>,
+}
+
+/// Backing file of a persisted [`OpJournal`].
+#[derive(Debug)]
+struct JournalFile {
+    file: RotatingFile,
+    /// Index of the file, kept up to date as records are written.
+    index: JournalIndex,
+    /// Lock on the journal, held until the journal is dropped.
+    _lock: File,
+    /// Number of records that have been written to the file.
+    written: usize,
 }
 
 impl OpJournal {
@@ -193,7 +453,68 @@ impl OpJournal {
 
     #[inline]
     fn new_parts(inner: Journal<JournalOpAtom>) -> Self {
-        Self { inner }
+        Self { inner, file: None }
+    }
+
+    /// Open the journal persisted to `file`, loading the records written by previous runs. The
+    /// journal is locked until it is dropped, so that concurrent runs can't interleave their
+    /// records; records appended from now on are written by [`OpJournal::sync`].
+    ///
+    /// # Errors
+    ///
+    /// Errors with [`JournalFileError::Locked`] if another process has the journal open.
+    #[inline]
+    pub fn open(file: RotatingFile) -> Result<Self, JournalFileError> {
+        if let Some(parent) = file.path().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // The lock must be held before loading, so that no records are missed.
+        let mut lock_path = file.path().as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock = lock(Path::new(&lock_path))?;
+
+        let inner = Journal::load_rotated(&file)?;
+        // The index is rebuilt if it is missing, e.g. for journals written before it existed, or
+        // unreadable, e.g. if a run was interrupted while appending to it.
+        let index = match JournalIndex::load_rotated(&file) {
+            Ok(Some(index)) => index,
+            _ => {
+                let index = JournalIndex::rebuild::<JournalOpAtom>(&file)?;
+                index.write_rotated(&file)?;
+                index
+            }
+        };
+
+        Ok(Self {
+            file: Some(JournalFile {
+                written: inner.size(),
+                file,
+                index,
+                _lock: lock,
+            }),
+            inner,
+        })
+    }
+
+    /// Write the records appended since the last sync to the file that the journal is persisted
+    /// to, along with their entries in its index. Nothing is done if the journal isn't persisted.
+    #[inline]
+    pub fn sync(&mut self) -> Result<(), JournalFileError> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let rotated =
+            self.inner
+                .write_rotated_indexed(&file.file, file.written, &mut file.index)?;
+        file.written = self.inner.size();
+        if rotated {
+            file.index = JournalIndex::rebuild::<JournalOpAtom>(&file.file)?;
+            file.index.write_rotated(&file.file)?;
+        }
+        Ok(())
     }
 
     /// Return the number of records in the journal.
@@ -231,6 +552,61 @@ impl OpJournal {
     pub fn get_back(&self, idx: usize) -> Option<Record<&JournalOpFinish>> {
         self.inner.get_back(idx).map(map_record)
     }
+
+    /// Set the message of the commits of transactions from now on. See [`Journal::set_message`].
+    #[inline]
+    pub fn set_message(&mut self, message: Option<String>) {
+        self.inner.set_message(message)
+    }
+
+    /// Return the message of the commit record at the given index, if it has one.
+    #[inline]
+    pub fn message(&self, idx: usize) -> Option<&str> {
+        self.inner.message(idx)
+    }
+
+    /// Return the stamp of the record at the given index, where the oldest record has an index of
+    /// 0. See [`Stamp`].
+    #[inline]
+    pub fn stamp(&self, idx: usize) -> Option<&Stamp> {
+        self.inner.stamp(idx)
+    }
+
+    /// Retrieve the record at the given index together with its stamp.
+    #[inline]
+    pub fn get_stamped(&self, idx: usize) -> Option<Stamped<Record<&JournalOpFinish>>> {
+        self.inner.get_stamped(idx).map(|s| s.map(map_record))
+    }
+}
+
+impl OpJournal {
+    /// Append the records from index `start` onwards to the rotating journal file `file`. See
+    /// [`Journal::write_rotated`].
+    #[inline]
+    pub fn write_rotated(&self, file: &RotatingFile, start: usize) -> Result<bool, WriteError> {
+        self.inner.write_rotated(file, start)
+    }
+
+    /// Load the records of all the segments of the rotating journal file `file`.
+    #[inline]
+    pub fn load_rotated(file: &RotatingFile) -> Result<Self, ReadError> {
+        Journal::load_rotated(file).map(Self::new_parts)
+    }
+
+    /// Read the single record at byte `offset` of the rotating journal file `file`, e.g. the
+    /// latest record for a destination looked up in its [`JournalIndex`]. See
+    /// [`Journal::read_rotated_at`].
+    #[inline]
+    pub fn read_rotated_at(
+        file: &RotatingFile,
+        offset: u64,
+    ) -> Result<Record<JournalOpFinish>, ReadError> {
+        let record = match Journal::<JournalOpAtom>::read_rotated_at(file, offset)? {
+            Record::Atom(atom) => Record::Atom(atom.op),
+            Record::Commit => Record::Commit,
+        };
+        Ok(record)
+    }
 }
 
 /// Iterator on a journal.
@@ -273,6 +649,38 @@ impl<'j> DoubleEndedIterator for Iter<'j> {
     }
 }
 
+/// Iterator on a journal that includes the stamps of records.
+#[derive(Debug)]
+pub struct StampedIter<'j> {
+    inner: journal::iter::StampedIter<'j, JournalOpAtom>,
+}
+
+impl OpJournal {
+    /// Return an iterator on the journal that includes the stamps of records.
+    #[inline]
+    pub fn iter_stamped(&self) -> StampedIter<'_> {
+        StampedIter {
+            inner: self.inner.iter_stamped(),
+        }
+    }
+}
+
+impl<'j> Iterator for StampedIter<'j> {
+    type Item = Stamped<Record<&'j JournalOpFinish>>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|s| s.map(map_record))
+    }
+}
+
+impl<'j> DoubleEndedIterator for StampedIter<'j> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|s| s.map(map_record))
+    }
+}
+
 /// An iterator that performs rollback on a [`OpJournal`]. See [`OpJournal::rollback`] and
 /// [`OpJournal::rollback_last`].
 #[derive(Debug)]
@@ -333,18 +741,20 @@ impl OpJournal {
 
 impl<'j> Transaction<'j> {
     /// Append a new action record to the journal by finishing the op, and return the result of
-    /// finishing.
+    /// finishing. Transient failures of retryable ops are retried according to the retry policy of
+    /// `ctx`.
     #[inline]
     pub fn append_finish<O>(
         &mut self,
         op: O,
         ctx: &FinishCtx,
-    ) -> Result<&JournalOpFinish, <O as Finish>::Error>
+    ) -> Result<&JournalOpFinish, Retried<<O as Finish>::Error>>
     where
         O: Finish,
         <O as Finish>::Output: Into<JournalOpFinish>,
+        <O as Finish>::Error: std::error::Error + 'static,
     {
-        let fin = op.finish(ctx)?;
+        let fin = ctx.retry.finish(&op, ctx)?;
         let atom = JournalOpAtom {
             op: fin.into(),
             ctx: ctx.clone(),
@@ -358,10 +768,180 @@ impl<'j> Transaction<'j> {
     }
 }
 
+/// Open and exclusively lock the file at `path`, creating it if needed. The lock is released
+/// when the file is closed, including when the process exits abnormally.
+#[cfg(unix)]
+#[inline]
+fn lock(path: &Path) -> Result<File, JournalFileError> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?;
+    // SAFETY: The descriptor is valid for as long as `file` is open.
+    let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if res == 0 {
+        Ok(file)
+    } else {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::WouldBlock {
+            Err(JournalFileError::Locked)
+        } else {
+            Err(err.into())
+        }
+    }
+}
+
+#[cfg(windows)]
+#[inline]
+fn lock(path: &Path) -> Result<File, JournalFileError> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    /// Error code when another process has the file open.
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    // Opening without sharing denies every other open until the file is closed.
+    let res = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .share_mode(0)
+        .open(path);
+    match res {
+        Ok(file) => Ok(file),
+        Err(err) if err.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => {
+            Err(JournalFileError::Locked)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
 #[inline]
 fn map_record(record: &Record<JournalOpAtom>) -> Record<&JournalOpFinish> {
     match record {
         Record::Atom(datum) => Record::Atom(&datum.op),
-        Record::Commit => todo!(),
+        Record::Commit => Record::Commit,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use crate::journal::{JournalIndex, Record, RotatePolicy, RotatingFile};
+
+    use super::super::test;
+    use super::super::CreateOp;
+    use super::{JournalFileError, OpJournal};
+
+    /// Test that persisted records are loaded again, and that the journal is locked while open.
+    #[test]
+    fn test_open() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let file = RotatingFile::new(dir.join("journal.jsonl"), RotatePolicy::default());
+
+            let mut journal = OpJournal::open(file.clone())?;
+            {
+                let mut t = journal.lock();
+                t.append_finish(
+                    CreateOp {
+                        path: dir.join("a"),
+                    },
+                    ctx,
+                )?;
+            }
+            journal.sync()?;
+            assert!(matches!(
+                OpJournal::open(file.clone()),
+                Err(JournalFileError::Locked)
+            ));
+            drop(journal);
+
+            let mut journal = OpJournal::open(file.clone())?;
+            assert_eq!(2, journal.size());
+            assert!(matches!(journal.latest(), Some(Record::Commit)));
+
+            // Sequence numbers continue from the loaded records.
+            {
+                let mut t = journal.lock();
+                t.append_finish(
+                    CreateOp {
+                        path: dir.join("b"),
+                    },
+                    ctx,
+                )?;
+            }
+            journal.sync()?;
+            assert_eq!(Some(2), journal.stamp(2).map(|stamp| stamp.seq));
+            drop(journal);
+            assert_eq!(4, OpJournal::open(file)?.size());
+
+            Ok(())
+        })
+    }
+
+    /// Test that destinations are looked up in the index as records are synced, and after the
+    /// journal is rotated.
+    #[test]
+    fn test_index() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let policy = RotatePolicy {
+                max_size: 1,
+                max_files: 2,
+            };
+            let file = RotatingFile::new(dir.join("journal.jsonl"), policy);
+            let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+
+            let lookup = |dest: &Path| -> test::Result<Option<PathBuf>> {
+                let index = JournalIndex::load_rotated(&file)?.unwrap_or_default();
+                let record = match index.get(dest) {
+                    Some(offset) => OpJournal::read_rotated_at(&file, offset)?,
+                    None => return Ok(None),
+                };
+                match record {
+                    Record::Atom(fin) => Ok(fin.dest().map(Path::to_path_buf)),
+                    Record::Commit => Ok(None),
+                }
+            };
+            let create = |journal: &mut OpJournal, path: &Path| -> test::Result<()> {
+                journal.lock().append_finish(
+                    CreateOp {
+                        path: path.to_path_buf(),
+                    },
+                    ctx,
+                )?;
+                journal.sync()?;
+                Ok(())
+            };
+
+            let mut journal = OpJournal::open(file.clone())?;
+            create(&mut journal, &a)?;
+            assert_eq!(Some(a.clone()), lookup(&a)?);
+            assert_eq!(None, lookup(&b)?);
+
+            // Each transaction after the first rotates the journal, and the index is rebuilt.
+            create(&mut journal, &b)?;
+            assert_eq!(vec![file.segment(1), file.segment(0)], file.segments());
+            assert_eq!(Some(a.clone()), lookup(&a)?);
+            assert_eq!(Some(b.clone()), lookup(&b)?);
+
+            // Records in removed segments are no longer indexed.
+            create(&mut journal, &c)?;
+            assert_eq!(None, lookup(&a)?);
+            assert_eq!(Some(b.clone()), lookup(&b)?);
+            assert_eq!(Some(c.clone()), lookup(&c)?);
+
+            // Reconciling through the index agrees with reading the whole journal.
+            let dests = |rec: crate::op::reconcile::Reconciliation| -> Vec<PathBuf> {
+                rec.dests.into_iter().map(|status| status.dest).collect()
+            };
+            let indexed = dests(OpJournal::reconcile_rotated(&file)?);
+            assert_eq!(vec![b, c], indexed);
+            assert_eq!(indexed, dests(OpJournal::load_rotated(&file)?.reconcile()));
+
+            Ok(())
+        })
     }
 }
@@ -0,0 +1,215 @@
+//! Conversion of destinations between the two layouts that linking a directory can leave behind: a
+//! directory of links to each file (e.g. from `tree`), and a single link to the directory (e.g.
+//! from `file`). Only links that the journal says shelf made are removed; anything else is left for
+//! the usual overwrite handling.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{LinkUndoOp, MkdirOp, MkdirUndoOp, Op};
+
+/// Plan the removal of the directory `dir`, so that it can be replaced by a single link. Each of
+/// the `links` (see [`OpJournal::links_in_place`]) in it is undone, and then each directory is
+/// removed, deepest first. Returns `None` if `dir` isn't a directory, holds none of `links`, or
+/// holds anything that is neither a directory nor one of `links`.
+///
+/// [`OpJournal::links_in_place`]: super::journal::OpJournal::links_in_place
+#[inline]
+pub fn unlink_dir(dir: &Path, links: &[(PathBuf, PathBuf)]) -> Option<Vec<Op<'static>>> {
+    if fs::symlink_metadata(dir).ok()?.file_type().is_symlink() {
+        return None;
+    }
+
+    let mut undo = Vec::new();
+    let mut dirs = Vec::new();
+    walk(dir, links, &mut undo, &mut dirs)?;
+    if undo.is_empty() {
+        return None;
+    }
+
+    let ops = undo
+        .into_iter()
+        .map(Op::LinkUndo)
+        .chain(
+            dirs.into_iter()
+                .map(|path| Op::MkdirUndo(MkdirUndoOp { path })),
+        )
+        .collect();
+    Some(ops)
+}
+
+/// Collect the undos of the links in `dir` into `undo`, and its directories (including `dir`) into
+/// `dirs` in post-order.
+#[inline]
+fn walk(
+    dir: &Path,
+    links: &[(PathBuf, PathBuf)],
+    undo: &mut Vec<LinkUndoOp>,
+    dirs: &mut Vec<PathBuf>,
+) -> Option<()> {
+    if !fs::symlink_metadata(dir).ok()?.is_dir() {
+        return None;
+    }
+
+    let mut entries = fs::read_dir(dir)
+        .ok()?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    entries.sort();
+
+    for path in entries {
+        let meta = fs::symlink_metadata(&path).ok()?;
+        if meta.is_dir() {
+            walk(&path, links, undo, dirs)?;
+        } else {
+            let (dest, src) = links.iter().find(|(dest, _)| *dest == path)?;
+            undo.push(LinkUndoOp {
+                src: src.clone(),
+                dest: dest.clone(),
+                fallback: false,
+                copied: false,
+            });
+        }
+    }
+
+    dirs.push(dir.to_path_buf());
+    Some(())
+}
+
+/// Return those of the `links` (see [`OpJournal::links_in_place`]) at or under `dest` that point
+/// to directories. These must be removed before the files under `dest` can be linked individually,
+/// since their paths currently lead into the sources.
+///
+/// [`OpJournal::links_in_place`]: super::journal::OpJournal::links_in_place
+#[inline]
+pub fn dir_links<'a>(dest: &Path, links: &'a [(PathBuf, PathBuf)]) -> Vec<&'a (PathBuf, PathBuf)> {
+    links
+        .iter()
+        .filter(|(link, _)| link.starts_with(dest))
+        .filter(|(link, _)| fs::metadata(link).is_ok_and(|meta| meta.is_dir()))
+        .collect()
+}
+
+/// Return the ops that undo each of the directory `links` (see [`dir_links`]).
+#[inline]
+pub fn unlink_dirs(links: &[&(PathBuf, PathBuf)]) -> Vec<Op<'static>> {
+    links
+        .iter()
+        .map(|(dest, src)| {
+            Op::LinkUndo(LinkUndoOp {
+                src: src.clone(),
+                dest: dest.clone(),
+                fallback: false,
+                copied: false,
+            })
+        })
+        .collect()
+}
+
+/// Return the ops that make the directories between `root`, a directory link that is removed (see
+/// [`unlink_dirs`]), and `path`, which is under it. Directories already in `made` are skipped, and
+/// those returned are added to it.
+#[inline]
+pub fn mkdir_under(root: &Path, path: &Path, made: &mut HashSet<PathBuf>) -> Vec<MkdirOp> {
+    let mut ops: Vec<_> = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(root))
+        .filter(|dir| !made.contains(*dir))
+        .map(|dir| MkdirOp {
+            path: dir.to_path_buf(),
+        })
+        .collect();
+    ops.reverse();
+
+    made.extend(ops.iter().map(|op| op.path.clone()));
+    ops
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::collections::HashSet;
+    use std::fs;
+    use std::os::unix;
+    use std::path::PathBuf;
+
+    use super::super::test;
+    use super::{dir_links, mkdir_under, unlink_dir};
+    use crate::op::Op;
+
+    /// Test that a directory holding only known links is planned for removal, and that one holding
+    /// anything else isn't.
+    #[test]
+    fn test_unlink_dir() -> test::Result<()> {
+        test::with_tempdir(|dir, _ctx| {
+            let pkg = dir.join("pkg");
+            fs::create_dir_all(pkg.join("sub"))?;
+            fs::write(pkg.join("a"), "a")?;
+            fs::write(pkg.join("sub/b"), "b")?;
+
+            let dest = dir.join("dest");
+            fs::create_dir_all(dest.join("sub"))?;
+            unix::fs::symlink(pkg.join("a"), dest.join("a"))?;
+            unix::fs::symlink(pkg.join("sub/b"), dest.join("sub/b"))?;
+
+            let links = vec![
+                (dest.join("a"), pkg.join("a")),
+                (dest.join("sub/b"), pkg.join("sub/b")),
+            ];
+            let ops = unlink_dir(&dest, &links).unwrap();
+            let paths: Vec<PathBuf> = ops
+                .iter()
+                .map(|op| match op {
+                    Op::LinkUndo(op) => op.dest.clone(),
+                    Op::MkdirUndo(op) => op.path.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+            assert_eq!(
+                vec![
+                    dest.join("a"),
+                    dest.join("sub/b"),
+                    dest.join("sub"),
+                    dest.clone()
+                ],
+                paths
+            );
+
+            fs::write(dest.join("sub/c"), "c")?;
+            assert!(unlink_dir(&dest, &links).is_none());
+
+            Ok(())
+        })
+    }
+
+    /// Test finding links to directories, and making the directories under them.
+    #[test]
+    fn test_dir_links() -> test::Result<()> {
+        test::with_tempdir(|dir, _ctx| {
+            let pkg = dir.join("pkg");
+            fs::create_dir_all(pkg.join("sub"))?;
+            fs::write(pkg.join("a"), "a")?;
+
+            let dest = dir.join("dest");
+            unix::fs::symlink(&pkg, &dest)?;
+
+            let links = vec![(dest.clone(), pkg.clone())];
+            assert_eq!(vec![&links[0]], dir_links(&dest, &links));
+            assert_eq!(vec![&links[0]], dir_links(dir, &links));
+            assert!(dir_links(&dest.join("sub"), &links).is_empty());
+
+            let mut made = HashSet::new();
+            let ops = mkdir_under(&dest, &dest.join("sub/x/y"), &mut made);
+            let paths: Vec<_> = ops.into_iter().map(|op| op.path).collect();
+            assert_eq!(
+                vec![dest.clone(), dest.join("sub"), dest.join("sub/x")],
+                paths
+            );
+            assert!(mkdir_under(&dest, &dest.join("sub/z"), &mut made).is_empty());
+
+            Ok(())
+        })
+    }
+}
@@ -1,11 +1,11 @@
-use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
 
 use serde::{Deserialize, Serialize};
 use static_assertions as sa;
 
 use super::ctx::FinishCtx;
-use super::error::{RemoveError, SymlinkError};
+use super::error::{CopyError, RemoveError, SymlinkError};
 use super::{Finish, Rollback};
 
 sa::assert_impl_all!(LinkOp: Finish<Output = LinkFinish, Error = LinkOpError>);
@@ -18,10 +18,19 @@ sa::assert_impl_all!(LinkUndoFinish: Rollback<Output = LinkOp>);
 pub enum LinkOpError {
     #[error("symlink error")]
     Symlink(#[from] SymlinkError),
+    #[error("copy error")]
+    Copy(#[from] CopyError),
 }
 
 /// Operation to link a file from `src` to `dest`. It roughly corresponds to
-/// [`std::os::unix::fs::symlink`] on Unix and (???) on Windows.
+/// [`std::os::unix::fs::symlink`] on Unix, and [`std::os::windows::fs::symlink_file`] or
+/// [`std::os::windows::fs::symlink_dir`] (depending on whether `src` is a directory) on Windows.
+///
+/// If `fallback` is set and symlinks can't be created at `dest` (e.g. on Windows without the
+/// `SeCreateSymbolicLinkPrivilege` privilege, or on a filesystem without symlinks), a file `src` is
+/// copied instead (see [`fs::copy`]). Directories are never copied; on Windows, a directory
+/// junction to `src` is made instead, which needs no privilege. Junctions hold absolute targets, so
+/// a relative `src` is resolved against the parent of `dest` first (see [`junction_target`]).
 ///
 /// # Errors
 ///
@@ -41,6 +50,9 @@ pub struct LinkOp {
     pub src: PathBuf,
     /// Path to destination of link.
     pub dest: PathBuf,
+    /// Copy `src` instead if symlinks can't be created.
+    #[serde(default)]
+    pub fallback: bool,
 }
 
 /// The output of [`LinkOp`]. See its documentation for information.
@@ -50,6 +62,15 @@ pub struct LinkFinish {
     pub src: PathBuf,
     /// See [`LinkOp`].
     pub dest: PathBuf,
+    /// See [`LinkOp`].
+    #[serde(default)]
+    pub fallback: bool,
+    /// True if the file was copied because symlinks couldn't be created.
+    #[serde(default)]
+    pub copied: bool,
+    /// True if a directory junction was made because symlinks couldn't be created.
+    #[serde(default)]
+    pub junction: bool,
 }
 
 impl Finish for LinkOp {
@@ -58,16 +79,45 @@ impl Finish for LinkOp {
 
     #[inline]
     fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
-        let Self { src, dest } = self;
+        let Self {
+            src,
+            dest,
+            fallback,
+        } = self;
 
-        // Perform symlink.
-        self.symlink()?;
+        // Perform symlink, and fall back to copying files, or to junctions for directories on
+        // Windows, if symlinks aren't permitted.
+        let (copied, junction) = match self.symlink() {
+            Ok(()) => (false, false),
+            Err(err) if *fallback && symlink_unsupported(&err.inner) && !src.is_dir() => {
+                fs::copy(src, dest).map_err(|inner| CopyError {
+                    src: src.clone(),
+                    dest: dest.clone(),
+                    inner,
+                })?;
+                (true, false)
+            }
+            #[cfg(windows)]
+            Err(err) if *fallback && symlink_unsupported(&err.inner) => {
+                self.junction()?;
+                (false, true)
+            }
+            Err(err) => return Err(err.into()),
+        };
 
         Ok(Self::Output {
             src: src.clone(),
             dest: dest.clone(),
+            fallback: *fallback,
+            copied,
+            junction,
         })
     }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
 }
 
 impl LinkOp {
@@ -76,7 +126,7 @@ impl LinkOp {
     fn symlink(&self) -> Result<(), SymlinkError> {
         use std::os::unix;
 
-        let Self { src, dest } = self;
+        let Self { src, dest, .. } = self;
 
         unix::fs::symlink(src, dest).map_err(|inner| SymlinkError {
             src: src.clone(),
@@ -88,21 +138,204 @@ impl LinkOp {
     #[cfg(windows)]
     #[inline]
     fn symlink(&self) -> Result<(), SymlinkError> {
-        // FIXME: Look into Windows API behavior
-        unimplemented!()
+        use std::os::windows;
+
+        let Self { src, dest, .. } = self;
+
+        // Windows distinguishes between symlinks to files and to directories; a missing src is
+        // linked as a file.
+        let res = if src.is_dir() {
+            windows::fs::symlink_dir(src, dest)
+        } else {
+            windows::fs::symlink_file(src, dest)
+        };
+
+        res.map_err(|inner| SymlinkError {
+            src: src.clone(),
+            dest: dest.clone(),
+            inner,
+        })
+    }
+
+    /// Make a directory junction at `dest` to `src` with `mklink /J`.
+    #[cfg(windows)]
+    #[inline]
+    fn junction(&self) -> Result<(), SymlinkError> {
+        use std::process::{Command, Stdio};
+
+        let Self { src, dest, .. } = self;
+
+        let res = Command::new("cmd")
+            .args(["/C", "mklink", "/J"])
+            .arg(dest)
+            .arg(junction_target(src, dest))
+            .stdin(Stdio::null())
+            .output()
+            .and_then(|output| {
+                if output.status.success() {
+                    Ok(())
+                } else {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    Err(io::Error::other(stderr.trim().to_string()))
+                }
+            });
+
+        res.map_err(|inner| SymlinkError {
+            src: src.clone(),
+            dest: dest.clone(),
+            inner,
+        })
     }
 }
 
+/// Mechanism by which a link was made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Symlink,
+    /// Directory junction, which only exists on Windows. See [`LinkOp`].
+    Junction,
+}
+
+/// Return the mechanism of the link at `path`, or `None` if it isn't a link (or doesn't exist).
+#[inline]
+pub fn link_kind(path: &Path) -> Option<LinkKind> {
+    let meta = fs::symlink_metadata(path).ok()?;
+    if !meta.file_type().is_symlink() {
+        return None;
+    }
+
+    // The standard library reports junctions as symlinks to directories.
+    #[cfg(windows)]
+    {
+        if is_junction(path) {
+            return Some(LinkKind::Junction);
+        }
+    }
+
+    Some(LinkKind::Symlink)
+}
+
+/// Return the target of a junction to `src` made at `dest`: `src` if it is absolute, and `src`
+/// relative to the parent of `dest` otherwise, as it would be for a symlink.
+#[inline]
+pub fn junction_target(src: &Path, dest: &Path) -> PathBuf {
+    if src.is_absolute() {
+        return src.to_path_buf();
+    }
+
+    match dest.parent() {
+        Some(parent) => parent.join(src),
+        None => src.to_path_buf(),
+    }
+}
+
+/// Return true if `path` is a directory junction, by its reparse tag.
+#[cfg(windows)]
+#[inline]
+fn is_junction(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::Storage::FileSystem::{FindClose, FindFirstFileW, WIN32_FIND_DATAW};
+
+    /// Reparse tag of junctions, which are mount points of directories.
+    const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    // SAFETY: `wide` is nul-terminated, and `data` is only read if it was filled in. For reparse
+    // points, `dwReserved0` holds the reparse tag.
+    unsafe {
+        let mut data: WIN32_FIND_DATAW = std::mem::zeroed();
+        let handle = FindFirstFileW(wide.as_ptr(), &mut data);
+        if handle == INVALID_HANDLE_VALUE {
+            return false;
+        }
+        FindClose(handle);
+        data.dwReserved0 == IO_REPARSE_TAG_MOUNT_POINT
+    }
+}
+
+/// Return whether symlinks can be created in the directory `dir`, by creating one there and
+/// removing it right after. Returns `None` if that couldn't be determined, e.g. because `dir`
+/// isn't writable.
+#[inline]
+pub fn symlinks_supported(dir: &Path) -> Option<bool> {
+    let probe = LinkOp {
+        src: PathBuf::from(".shelf-symlink-probe-target"),
+        dest: dir.join(format!(".shelf-symlink-probe-{}", std::process::id())),
+        fallback: false,
+    };
+
+    match probe.symlink() {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe.dest);
+            Some(true)
+        }
+        Err(err) if symlink_unsupported(&err.inner) => Some(false),
+        Err(_) => None,
+    }
+}
+
+/// Return true if `err` indicates that symlinks can't be created by the current user or on the
+/// filesystem.
+#[cfg(unix)]
+#[inline]
+fn symlink_unsupported(err: &io::Error) -> bool {
+    // Filesystems without symlinks, e.g. FAT, refuse them with EPERM.
+    err.raw_os_error() == Some(libc::EPERM)
+}
+
+/// Return true if `err` indicates that symlinks can't be created by the current user or on the
+/// filesystem.
+#[cfg(windows)]
+#[inline]
+fn symlink_unsupported(err: &io::Error) -> bool {
+    /// Returned when SeCreateSymbolicLinkPrivilege isn't held, i.e. when not elevated and
+    /// developer mode is off.
+    const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+    /// Returned by filesystems without symlinks.
+    const ERROR_NOT_SUPPORTED: i32 = 50;
+
+    matches!(
+        err.raw_os_error(),
+        Some(ERROR_PRIVILEGE_NOT_HELD | ERROR_NOT_SUPPORTED)
+    )
+}
+
+/// Remove the symlink (or copied file, or junction) at `path`.
+#[inline]
+fn remove_link(path: &Path) -> io::Result<()> {
+    // Symlinks to directories, and junctions, must be removed as directories on Windows.
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileTypeExt;
+
+        if fs::symlink_metadata(path)?.file_type().is_symlink_dir() {
+            return fs::remove_dir(path);
+        }
+    }
+
+    fs::remove_file(path)
+}
+
 impl Rollback for LinkFinish {
     type Output = LinkUndoOp;
 
     #[inline]
     fn rollback(&self) -> Self::Output {
-        let Self { src, dest } = self;
+        let Self {
+            src,
+            dest,
+            fallback,
+            copied,
+            junction: _,
+        } = self;
 
         Self::Output {
             src: src.clone(),
             dest: dest.clone(),
+            fallback: *fallback,
+            copied: *copied,
         }
     }
 }
@@ -121,6 +354,12 @@ pub struct LinkUndoOp {
     pub src: PathBuf,
     /// See [`LinkOp`].
     pub dest: PathBuf,
+    /// See [`LinkOp`].
+    #[serde(default)]
+    pub fallback: bool,
+    /// See [`LinkFinish`].
+    #[serde(default)]
+    pub copied: bool,
 }
 
 /// The output of [`LinkUndoOp`]. See its documentation for information.
@@ -130,6 +369,9 @@ pub struct LinkUndoFinish {
     pub src: PathBuf,
     /// See [`LinkOp`].
     pub dest: PathBuf,
+    /// See [`LinkOp`].
+    #[serde(default)]
+    pub fallback: bool,
 }
 
 impl Finish for LinkUndoOp {
@@ -138,10 +380,15 @@ impl Finish for LinkUndoOp {
 
     #[inline]
     fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
-        let Self { src, dest } = self;
+        let Self {
+            src,
+            dest,
+            fallback,
+            ..
+        } = self;
 
-        // Remove symlink.
-        fs::remove_file(dest).map_err(|inner| RemoveError {
+        // Remove symlink or copy.
+        remove_link(dest).map_err(|inner| RemoveError {
             path: dest.clone(),
             inner,
         })?;
@@ -149,8 +396,14 @@ impl Finish for LinkUndoOp {
         Ok(Self::Output {
             src: src.clone(),
             dest: dest.clone(),
+            fallback: *fallback,
         })
     }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
 }
 
 impl Rollback for LinkUndoFinish {
@@ -158,11 +411,65 @@ impl Rollback for LinkUndoFinish {
 
     #[inline]
     fn rollback(&self) -> Self::Output {
-        let Self { src, dest } = self;
+        let Self {
+            src,
+            dest,
+            fallback,
+        } = self;
 
         Self::Output {
             src: src.clone(),
             dest: dest.clone(),
+            fallback: *fallback,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use std::path::Path;
+
+    use super::super::test;
+    use super::{junction_target, link_kind, symlinks_supported, LinkKind};
+
+    /// Test that probing leaves nothing behind.
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_supported() -> test::Result<()> {
+        test::with_tempdir(|dir, _ctx| {
+            assert_eq!(symlinks_supported(dir), Some(true));
+            assert_eq!(fs::read_dir(dir)?.count(), 0);
+
+            assert_eq!(symlinks_supported(&dir.join("missing")), None);
+            Ok(())
+        })
+    }
+
+    /// Test that symlinks are told apart from other files.
+    #[cfg(unix)]
+    #[test]
+    fn test_link_kind() -> test::Result<()> {
+        test::with_tempdir(|dir, _ctx| {
+            let (file, link) = (dir.join("file"), dir.join("link"));
+            fs::write(&file, "")?;
+            std::os::unix::fs::symlink(&file, &link)?;
+
+            assert_eq!(link_kind(&link), Some(LinkKind::Symlink));
+            assert_eq!(link_kind(&file), None);
+            assert_eq!(link_kind(&dir.join("missing")), None);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_junction_target() {
+        let abs = std::env::temp_dir().join("src");
+        assert_eq!(junction_target(&abs, Path::new("a/dest")), abs);
+        assert_eq!(
+            junction_target(Path::new("../src"), Path::new("a/dest")),
+            Path::new("a/../src")
+        );
+    }
+}
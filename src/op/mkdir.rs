@@ -60,6 +60,11 @@ impl Finish for MkdirOp {
         })?;
         Ok(Self::Output { path: path.clone() })
     }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
 }
 
 impl Rollback for MkdirFinish {
@@ -108,6 +113,11 @@ impl Finish for MkdirUndoOp {
         })?;
         Ok(Self::Output { path: path.clone() })
     }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        true
+    }
 }
 
 impl Rollback for MkdirUndoFinish {
@@ -1,27 +1,54 @@
 pub mod ctx;
+pub mod effect;
 pub mod journal;
+pub mod layout;
+pub mod reconcile;
+pub mod simulate;
+pub mod sink;
 
 pub mod error;
 
+pub mod chmod;
+#[cfg(unix)]
+pub mod chown;
 pub mod command;
 pub mod copy;
+pub mod copydir;
 pub mod create;
+pub mod defaults;
 pub mod function;
+pub mod hardlink;
 pub mod link;
 pub mod mkdir;
+#[cfg(all(windows, feature = "registry"))]
+pub mod registry;
 pub mod rm;
+pub mod script;
+pub mod sourceline;
+pub mod systemctl;
 pub mod write;
 
 pub(super) use crate::journal::Rollback;
 
+#[cfg(unix)]
+pub use self::chown::{ChownOp, ChownUndoOp};
+#[cfg(all(windows, feature = "registry"))]
+pub use self::registry::{RegistryOp, RegistryUndoOp};
 pub use self::{
+    chmod::{ChmodOp, ChmodUndoOp},
     command::CommandOp,
     copy::{CopyOp, CopyUndoOp},
+    copydir::{CopyDirOp, CopyDirUndoOp},
     create::{CreateOp, CreateUndoOp},
+    defaults::{DefaultsOp, DefaultsUndoOp},
     function::FunctionOp,
+    hardlink::{HardlinkOp, HardlinkUndoOp},
     link::{LinkOp, LinkUndoOp},
     mkdir::{MkdirOp, MkdirUndoOp},
     rm::{RmOp, RmUndoOp},
+    script::ScriptOp,
+    sourceline::{SourceLineOp, SourceLineUndoOp},
+    systemctl::{SystemctlOp, SystemctlUndoOp},
     write::{WriteOp, WriteUndoOp},
 };
 
@@ -34,6 +61,33 @@ pub trait Finish {
     type Error;
 
     fn finish(&self, ctx: &FinishCtx) -> Result<Self::Output, Self::Error>;
+
+    /// Return true if a failed attempt to finish leaves nothing behind, so that finishing can
+    /// safely be attempted again (see [`RetryPolicy`](ctx::RetryPolicy)). Ops that make several
+    /// changes, or read what they change to undo it later, must not be retried.
+    #[inline]
+    fn retryable(&self) -> bool {
+        false
+    }
+}
+
+/// Ops can be finished by reference, so that they needn't be cloned to be journaled.
+impl<O> Finish for &O
+where
+    O: Finish,
+{
+    type Output = O::Output;
+    type Error = O::Error;
+
+    #[inline]
+    fn finish(&self, ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        O::finish(*self, ctx)
+    }
+
+    #[inline]
+    fn retryable(&self) -> bool {
+        O::retryable(*self)
+    }
 }
 
 /// The finish of an op.
@@ -80,6 +134,10 @@ pub enum OpError {
     Link(#[from] FinishedError<LinkOp>),
     #[error("copy op error")]
     Copy(#[from] FinishedError<CopyOp>),
+    #[error("hardlink op error")]
+    Hardlink(#[from] FinishedError<HardlinkOp>),
+    #[error("copy dir op error")]
+    CopyDir(#[from] FinishedError<CopyDirOp>),
     #[error("create op error")]
     Create(#[from] FinishedError<CreateOp>),
     #[error("write op error")]
@@ -88,10 +146,26 @@ pub enum OpError {
     Mkdir(#[from] FinishedError<MkdirOp>),
     #[error("rm op error")]
     Rm(#[from] FinishedError<RmOp>),
+    #[error("systemctl op error")]
+    Systemctl(#[from] FinishedError<SystemctlOp>),
+    #[error("chmod op error")]
+    Chmod(#[from] FinishedError<ChmodOp>),
+    #[cfg(unix)]
+    #[error("chown op error")]
+    Chown(#[from] FinishedError<ChownOp>),
+    #[error("source line op error")]
+    SourceLine(#[from] FinishedError<SourceLineOp>),
+    #[error("defaults op error")]
+    Defaults(#[from] FinishedError<DefaultsOp>),
+    #[cfg(all(windows, feature = "registry"))]
+    #[error("registry op error")]
+    Registry(#[from] FinishedError<RegistryOp>),
     #[error("command op error")]
     Command(#[from] FinishedError<CommandOp>),
     #[error("function op error")]
     Function(#[from] FinishedError<FunctionOp<'static>>),
+    #[error("script op error")]
+    Script(#[from] FinishedError<ScriptOp>),
 }
 
 #[derive(Debug, Clone)]
@@ -100,6 +174,10 @@ pub enum Op<'lua> {
     LinkUndo(Undo<LinkOp>),
     Copy(CopyOp),
     CopyUndo(Undo<CopyOp>),
+    Hardlink(HardlinkOp),
+    HardlinkUndo(Undo<HardlinkOp>),
+    CopyDir(CopyDirOp),
+    CopyDirUndo(Undo<CopyDirOp>),
     Create(CreateOp),
     CreateUndo(Undo<CreateOp>),
     Write(WriteOp),
@@ -108,8 +186,25 @@ pub enum Op<'lua> {
     MkdirUndo(Undo<MkdirOp>),
     Rm(RmOp),
     RmUndo(Undo<RmOp>),
+    Systemctl(SystemctlOp),
+    SystemctlUndo(Undo<SystemctlOp>),
+    Chmod(ChmodOp),
+    ChmodUndo(Undo<ChmodOp>),
+    #[cfg(unix)]
+    Chown(ChownOp),
+    #[cfg(unix)]
+    ChownUndo(Undo<ChownOp>),
+    SourceLine(SourceLineOp),
+    SourceLineUndo(Undo<SourceLineOp>),
+    Defaults(DefaultsOp),
+    DefaultsUndo(Undo<DefaultsOp>),
+    #[cfg(all(windows, feature = "registry"))]
+    Registry(RegistryOp),
+    #[cfg(all(windows, feature = "registry"))]
+    RegistryUndo(Undo<RegistryOp>),
     Command(CommandOp),
     Function(FunctionOp<'lua>),
+    Script(ScriptOp),
 }
 
 /// Some test utilities.
@@ -148,10 +243,11 @@ mod test {
         let dir = tempfile::tempdir()?;
         let safedir = tempfile::tempdir()?;
 
-        let ctx = FinishCtx {
-            filesafe: FileSafe::new(safedir.path()),
-        };
+        let ctx = FinishCtx::new(FileSafe::new(safedir.path()));
 
         Ok((dir, ctx, safedir))
     }
 }
+
+#[cfg(test)]
+mod invariants;
@@ -0,0 +1,528 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::journal::writer::ReadError;
+use crate::journal::{JournalIndex, Record, RotatingFile};
+
+use super::journal::{JournalOpFinish, OpJournal};
+
+/// State that a destination should be in, according to the latest committed op that changed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected {
+    /// A symlink to the given source.
+    Link(PathBuf),
+    /// A file whose contents have the given hash.
+    Contents(u64),
+    /// A file with the same contents as the given source.
+    Copy(PathBuf),
+    /// Anything, e.g. a copied directory or a file with a managed line.
+    Exists,
+}
+
+/// How a destination differs from its [`Expected`] state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// Nothing is at the destination.
+    Missing,
+    /// The symlink points to the given target instead.
+    Retargeted(PathBuf),
+    /// The contents differ, or the destination has been replaced by something else.
+    Changed,
+}
+
+/// A destination recorded in the journal and compared against the filesystem.
+#[derive(Debug, Clone)]
+pub struct DestStatus {
+    /// Absolute path of the destination.
+    pub dest: PathBuf,
+    /// Source of the destination, if any.
+    pub src: Option<PathBuf>,
+    pub expected: Expected,
+    /// How the destination has drifted, or `None` if it is as expected.
+    pub drift: Option<Drift>,
+}
+
+/// Atoms after the last commit, which were never committed, e.g. because shelf was interrupted.
+#[derive(Debug, Clone)]
+pub struct Uncommitted {
+    /// Sequence number of the first uncommitted record.
+    pub seq: u64,
+    /// Destinations changed by the uncommitted atoms.
+    pub dests: Vec<PathBuf>,
+}
+
+/// Result of comparing a journal against the filesystem. See [`OpJournal::reconcile`].
+#[derive(Debug, Clone)]
+pub struct Reconciliation {
+    /// Destinations that the journal says are in place, ordered by path.
+    pub dests: Vec<DestStatus>,
+    /// Uncommitted atoms at the end of the journal, if any.
+    pub uncommitted: Option<Uncommitted>,
+}
+
+impl OpJournal {
+    /// Walk the committed records to find the state that each destination should be in, and stat
+    /// the destinations to find those that have drifted since. Destinations removed or rolled back
+    /// by a later op are no longer in place, and are left out.
+    #[inline]
+    pub fn reconcile(&self) -> Reconciliation {
+        let (expected, pending) = self.expected();
+        let dests = statuses(expected);
+
+        let uncommitted = pending.first().map(|(seq, _)| Uncommitted {
+            seq: *seq,
+            dests: pending
+                .iter()
+                .filter_map(|(_, fin)| fin.dest().map(Path::to_path_buf))
+                .collect(),
+        });
+
+        Reconciliation { dests, uncommitted }
+    }
+
+    /// Like [`OpJournal::reconcile`], but for the journal persisted to `file`, reading only the
+    /// latest record of each destination, looked up in its index (see [`JournalIndex`]). The
+    /// whole journal is read instead if it ends with uncommitted atoms, or if its index is
+    /// missing or out of date.
+    #[inline]
+    pub fn reconcile_rotated(file: &RotatingFile) -> Result<Reconciliation, ReadError> {
+        match Self::reconcile_indexed(file) {
+            Some(rec) => Ok(rec),
+            None => Ok(Self::load_rotated(file)?.reconcile()),
+        }
+    }
+
+    #[inline]
+    fn reconcile_indexed(file: &RotatingFile) -> Option<Reconciliation> {
+        // The latest record of a destination may be uncommitted, in which case the state is that
+        // of an earlier one.
+        if !file.ends_committed().ok()? {
+            return None;
+        }
+
+        let index = JournalIndex::load_rotated(file).ok()??;
+        let mut expected: ExpectedMap = BTreeMap::new();
+        for (dest, offset) in index.iter() {
+            match Self::read_rotated_at(file, offset).ok()? {
+                // The record may not match if the journal was rotated since the index was read.
+                Record::Atom(fin) if determined_dest(&fin) == Some(dest) => {
+                    apply(&mut expected, &fin)
+                }
+                _ => return None,
+            }
+        }
+
+        Some(Reconciliation {
+            dests: statuses(expected),
+            uncommitted: None,
+        })
+    }
+
+    /// Return the symlinks that shelf made that are still in place and unchanged, as pairs of
+    /// destination and source, ordered by destination. Unlike [`OpJournal::reconcile`], other
+    /// destinations aren't compared against the filesystem.
+    #[inline]
+    pub fn links_in_place(&self) -> Vec<(PathBuf, PathBuf)> {
+        let (expected, _) = self.expected();
+        expected
+            .into_iter()
+            .filter_map(|(dest, (_, expected))| match expected {
+                Expected::Link(src) if drift(&dest, &Expected::Link(src.clone())).is_none() => {
+                    Some((dest, src))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Walk the committed records to find the state that each destination should be in. The
+    /// atoms after the last commit are returned with their sequence numbers.
+    #[inline]
+    fn expected(&self) -> (ExpectedMap, Vec<(u64, &JournalOpFinish)>) {
+        let mut expected: ExpectedMap = BTreeMap::new();
+        let mut pending: Vec<(u64, &JournalOpFinish)> = Vec::new();
+
+        for stamped in self.iter_stamped() {
+            match stamped.record {
+                Record::Atom(fin) => pending.push((stamped.stamp.seq, fin)),
+                Record::Commit => {
+                    for (_, fin) in pending.drain(..) {
+                        apply(&mut expected, fin);
+                    }
+                }
+            }
+        }
+
+        (expected, pending)
+    }
+}
+
+/// Expected state of each destination, with its source if any.
+type ExpectedMap = BTreeMap<PathBuf, (Option<PathBuf>, Expected)>;
+
+impl OpJournal {
+    /// Return the committed ops that are still in effect on the destinations selected by
+    /// `select`, newest first, so that rolling each back in turn undoes them. `select` is given
+    /// each destination with the source of an op on it, if any; a destination is selected if it
+    /// is for any of its ops. Directories that shelf created are selected too if anything selected
+    /// is under them, unless something unselected that is still in effect is also under them.
+    #[inline]
+    pub fn in_effect<F>(&self, select: F) -> Vec<JournalOpFinish>
+    where
+        F: Fn(&Path, Option<&Path>) -> bool,
+    {
+        let mut stacks: BTreeMap<&Path, Vec<(usize, &JournalOpFinish)>> = BTreeMap::new();
+        let mut pending: Vec<(usize, &JournalOpFinish)> = Vec::new();
+        for (idx, stamped) in self.iter_stamped().enumerate() {
+            match stamped.record {
+                Record::Atom(fin) => pending.push((idx, fin)),
+                Record::Commit => {
+                    for (idx, fin) in pending.drain(..) {
+                        let dest = match fin.dest() {
+                            Some(dest) => dest,
+                            None => continue,
+                        };
+                        let stack = stacks.entry(dest).or_default();
+                        // Undo ops roll back the latest op still in effect on the destination.
+                        if is_undo(fin) {
+                            stack.pop();
+                        } else {
+                            stack.push((idx, fin));
+                        }
+                    }
+                }
+            }
+        }
+
+        let is_dir = |ops: &[(usize, &JournalOpFinish)]| {
+            ops.iter()
+                .all(|(_, fin)| matches!(fin, JournalOpFinish::Mkdir(_)))
+        };
+        let (dirs, dests): (Vec<_>, Vec<_>) = stacks
+            .into_iter()
+            .filter(|(_, ops)| !ops.is_empty())
+            .partition(|(_, ops)| is_dir(ops));
+        let (selected, others): (Vec<_>, Vec<_>) = dests
+            .into_iter()
+            .partition(|(dest, ops)| ops.iter().any(|(_, fin)| select(dest, fin.src())));
+        let dirs: Vec<_> = dirs
+            .into_iter()
+            .filter(|(dir, _)| {
+                (select(dir, None) || selected.iter().any(|(dest, _)| dest.starts_with(dir)))
+                    && !others.iter().any(|(dest, _)| dest.starts_with(dir))
+            })
+            .collect();
+
+        let mut ops: Vec<_> = selected
+            .into_iter()
+            .chain(dirs)
+            .flat_map(|(_, ops)| ops)
+            .collect();
+        ops.sort_by_key(|(idx, _)| std::cmp::Reverse(*idx));
+        ops.into_iter().map(|(_, fin)| fin.clone()).collect()
+    }
+}
+
+/// Return whether `fin` is the undo of another op.
+#[inline]
+fn is_undo(fin: &JournalOpFinish) -> bool {
+    match fin {
+        JournalOpFinish::LinkUndo(_)
+        | JournalOpFinish::CopyUndo(_)
+        | JournalOpFinish::HardlinkUndo(_)
+        | JournalOpFinish::CopyDirUndo(_)
+        | JournalOpFinish::CreateUndo(_)
+        | JournalOpFinish::WriteUndo(_)
+        | JournalOpFinish::MkdirUndo(_)
+        | JournalOpFinish::RmUndo(_)
+        | JournalOpFinish::ChmodUndo(_)
+        | JournalOpFinish::SystemctlUndo(_)
+        | JournalOpFinish::SourceLineUndo(_)
+        | JournalOpFinish::DefaultsUndo(_) => true,
+        #[cfg(unix)]
+        JournalOpFinish::ChownUndo(_) => true,
+        #[cfg(all(windows, feature = "registry"))]
+        JournalOpFinish::RegistryUndo(_) => true,
+        _ => false,
+    }
+}
+
+/// Compare each destination in `expected` against the filesystem.
+#[inline]
+fn statuses(expected: ExpectedMap) -> Vec<DestStatus> {
+    expected
+        .into_iter()
+        .map(|(dest, (src, expected))| DestStatus {
+            drift: drift(&dest, &expected),
+            dest,
+            src,
+            expected,
+        })
+        .collect()
+}
+
+/// Record the state that `fin` leaves its destination in.
+#[inline]
+fn apply(expected: &mut ExpectedMap, fin: &JournalOpFinish) {
+    match state(fin) {
+        Some((dest, Some((src, state)))) => {
+            expected.insert(dest.clone(), (src.cloned(), state));
+        }
+        Some((dest, None)) => {
+            expected.remove(dest);
+        }
+        None => {}
+    }
+}
+
+/// Return the destination whose expected state `fin` determines, if any. Atoms are indexed under
+/// it, so that the latest indexed record of a destination gives its state.
+#[inline]
+pub(crate) fn determined_dest(fin: &JournalOpFinish) -> Option<&Path> {
+    state(fin).map(|(dest, _)| dest.as_path())
+}
+
+/// State that an op leaves its destination in, with the source of the destination if any, or
+/// `None` if the destination is no longer in place.
+type State<'a> = Option<(Option<&'a PathBuf>, Expected)>;
+
+/// Return the destination of `fin` with the state it leaves it in, or `None` if `fin` doesn't
+/// determine the state of a destination (e.g. it changes its mode).
+#[inline]
+fn state(fin: &JournalOpFinish) -> Option<(&PathBuf, State<'_>)> {
+    let (dest, state) = match fin {
+        JournalOpFinish::Link(fin) if fin.copied => (
+            &fin.dest,
+            Some((Some(&fin.src), Expected::Copy(fin.src.clone()))),
+        ),
+        JournalOpFinish::Link(fin) => (
+            &fin.dest,
+            Some((Some(&fin.src), Expected::Link(fin.src.clone()))),
+        ),
+        JournalOpFinish::Copy(fin) if fin.dir => {
+            (&fin.dest, Some((Some(&fin.src), Expected::Exists)))
+        }
+        JournalOpFinish::Copy(fin) => (
+            &fin.dest,
+            Some((Some(&fin.src), Expected::Copy(fin.src.clone()))),
+        ),
+        JournalOpFinish::Hardlink(fin) => (
+            &fin.dest,
+            Some((Some(&fin.src), Expected::Copy(fin.src.clone()))),
+        ),
+        JournalOpFinish::CopyDir(fin) => (&fin.dest, Some((Some(&fin.src), Expected::Exists))),
+        JournalOpFinish::Create(fin) => (&fin.path, Some((None, Expected::Contents(hash(&[]))))),
+        JournalOpFinish::Write(fin) => (
+            &fin.path,
+            Some((None, Expected::Contents(hash(&fin.contents)))),
+        ),
+        JournalOpFinish::SourceLine(fin) => (&fin.path, Some((None, Expected::Exists))),
+        // Removed and rolled back destinations are no longer in place.
+        JournalOpFinish::Rm(fin) => (&fin.path, None),
+        JournalOpFinish::LinkUndo(fin) => (&fin.dest, None),
+        JournalOpFinish::CopyUndo(fin) => (&fin.dest, None),
+        JournalOpFinish::HardlinkUndo(fin) => (&fin.dest, None),
+        JournalOpFinish::CopyDirUndo(fin) => (&fin.dest, None),
+        JournalOpFinish::CreateUndo(fin) => (&fin.path, None),
+        JournalOpFinish::WriteUndo(fin) => (&fin.path, None),
+        JournalOpFinish::SourceLineUndo(fin) => (&fin.path, None),
+        // Directories, modes, owners, and anything outside of the filesystem are not compared.
+        JournalOpFinish::Mkdir(_)
+        | JournalOpFinish::MkdirUndo(_)
+        | JournalOpFinish::RmUndo(_)
+        | JournalOpFinish::Chmod(_)
+        | JournalOpFinish::ChmodUndo(_)
+        | JournalOpFinish::Systemctl(_)
+        | JournalOpFinish::SystemctlUndo(_)
+        | JournalOpFinish::Defaults(_)
+        | JournalOpFinish::DefaultsUndo(_) => return None,
+        #[cfg(unix)]
+        JournalOpFinish::Chown(_) | JournalOpFinish::ChownUndo(_) => return None,
+        #[cfg(all(windows, feature = "registry"))]
+        JournalOpFinish::Registry(_) | JournalOpFinish::RegistryUndo(_) => return None,
+    };
+
+    Some((dest, state))
+}
+
+/// Compare `dest` against its expected state.
+#[inline]
+fn drift(dest: &Path, expected: &Expected) -> Option<Drift> {
+    let metadata = match fs::symlink_metadata(dest) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Some(Drift::Missing),
+        Err(_) => return Some(Drift::Changed),
+    };
+
+    match expected {
+        Expected::Link(src) => {
+            if !metadata.file_type().is_symlink() {
+                return Some(Drift::Changed);
+            }
+            match fs::read_link(dest) {
+                Ok(target) if &target == src => None,
+                Ok(target) => Some(Drift::Retargeted(target)),
+                Err(_) => Some(Drift::Changed),
+            }
+        }
+        Expected::Contents(expected) => match fs::read(dest) {
+            Ok(contents) if hash(&contents) == *expected => None,
+            _ => Some(Drift::Changed),
+        },
+        Expected::Copy(src) => match (fs::read(src), fs::read(dest)) {
+            (Ok(src), Ok(contents)) if hash(&src) == hash(&contents) => None,
+            // Without the source, there's nothing to compare against.
+            (Err(_), Ok(_)) => None,
+            _ => Some(Drift::Changed),
+        },
+        Expected::Exists => None,
+    }
+}
+
+#[inline]
+fn hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(contents);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::super::journal::{JournalOp, OpJournal};
+    use super::super::test;
+    use super::super::{CreateOp, LinkOp, MkdirOp, RmOp, WriteOp};
+    use super::{Drift, Expected};
+    use crate::journal::Rollback;
+
+    /// Test that destinations are compared against the state left by the latest committed op.
+    #[cfg(unix)]
+    #[test]
+    fn test_reconcile() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let (src, link, file) = (dir.join("src"), dir.join("link"), dir.join("file"));
+            fs::write(&src, "src")?;
+
+            let mut journal = OpJournal::new();
+            {
+                let mut t = journal.lock();
+                let link = LinkOp {
+                    src: src.clone(),
+                    dest: link.clone(),
+                    fallback: false,
+                };
+                t.append_finish(link, ctx)?;
+            }
+            {
+                let mut t = journal.lock();
+                t.append_finish(CreateOp { path: file.clone() }, ctx)?;
+                let write = WriteOp {
+                    path: file.clone(),
+                    contents: b"a".to_vec(),
+                };
+                t.append_finish(write, ctx)?;
+            }
+
+            let rec = journal.reconcile();
+            assert!(rec.uncommitted.is_none());
+            assert_eq!(2, rec.dests.len());
+            assert!(rec.dests.iter().all(|status| status.drift.is_none()));
+
+            // Drift is detected for each kind of expected state.
+            fs::write(&file, "b")?;
+            fs::remove_file(&link)?;
+            std::os::unix::fs::symlink(&file, &link)?;
+            let rec = journal.reconcile();
+            assert_eq!(Some(Drift::Changed), rec.dests[0].drift);
+            assert_eq!(Expected::Link(src.clone()), rec.dests[1].expected);
+            assert_eq!(Some(Drift::Retargeted(file.clone())), rec.dests[1].drift);
+
+            fs::remove_file(&file)?;
+            assert_eq!(Some(Drift::Missing), journal.reconcile().dests[0].drift);
+
+            // Uncommitted atoms are reported, but don't change what is expected.
+            let mut t = journal.lock();
+            let rm = RmOp {
+                path: link.clone(),
+                dir: false,
+            };
+            t.append_finish(rm, ctx)?;
+            std::mem::forget(t);
+            let rec = journal.reconcile();
+            assert_eq!(vec![link], rec.uncommitted.unwrap().dests);
+            assert_eq!(2, rec.dests.len());
+
+            Ok(())
+        })
+    }
+
+    /// Test that rolling back the ops in effect on selected destinations restores what they
+    /// replaced and removes the directories created for them, but leaves others alone.
+    #[cfg(unix)]
+    #[test]
+    fn test_in_effect() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let (pkg, other) = (dir.join("pkg"), dir.join("other"));
+            let (sub, replaced, nested) =
+                (dir.join("sub"), dir.join("replaced"), dir.join("sub/a"));
+            fs::create_dir(&pkg)?;
+            fs::create_dir(&other)?;
+            fs::write(&replaced, "original")?;
+
+            let mut journal = OpJournal::new();
+            let ops: Vec<JournalOp> = vec![
+                RmOp {
+                    path: replaced.clone(),
+                    dir: false,
+                }
+                .into(),
+                LinkOp {
+                    src: pkg.join("replaced"),
+                    dest: replaced.clone(),
+                    fallback: false,
+                }
+                .into(),
+                MkdirOp { path: sub.clone() }.into(),
+                LinkOp {
+                    src: pkg.join("a"),
+                    dest: nested.clone(),
+                    fallback: false,
+                }
+                .into(),
+                LinkOp {
+                    src: other.join("b"),
+                    dest: dir.join("b"),
+                    fallback: false,
+                }
+                .into(),
+            ];
+            for op in ops {
+                journal.lock().append_finish(op, ctx)?;
+            }
+
+            let ops = journal.in_effect(|_, src| src.is_some_and(|src| src.starts_with(&pkg)));
+            assert_eq!(4, ops.len());
+            let mut t = journal.lock();
+            for op in ops {
+                t.append_finish(op.rollback(), ctx)?;
+            }
+            drop(t);
+
+            assert_eq!("original", fs::read_to_string(&replaced)?);
+            assert!(!sub.exists());
+            assert!(fs::symlink_metadata(dir.join("b")).is_ok());
+
+            // Nothing of the package is in effect anymore.
+            assert!(journal
+                .in_effect(|_, src| src.is_some_and(|src| src.starts_with(&pkg)))
+                .is_empty());
+
+            Ok(())
+        })
+    }
+}
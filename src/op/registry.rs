@@ -0,0 +1,303 @@
+use std::io;
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+use static_assertions as sa;
+
+use super::ctx::FinishCtx;
+use super::error::RegistryError;
+use super::{Finish, Rollback};
+use crate::spec::RegistryValue;
+
+sa::assert_impl_all!(RegistryOp: Finish<Output = RegistryFinish, Error = RegistryOpError>);
+sa::assert_impl_all!(RegistryFinish: Rollback<Output = RegistryUndoOp>);
+sa::assert_impl_all!(RegistryUndoOp: Finish<Output = RegistryUndoFinish, Error = RegistryOpError>);
+sa::assert_impl_all!(RegistryUndoFinish: Rollback<Output = RegistryOp>);
+
+/// Error encountered when finishing [`RegistryOp`] or [`RegistryUndoOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryOpError {
+    #[error("registry error")]
+    Registry(#[from] RegistryError),
+}
+
+/// Return the current value `name` of `key`, or `None` if it isn't set.
+///
+/// # Errors
+///
+/// Errors if `reg` can't be run, or the value has an unsupported type (e.g. `REG_BINARY`), since
+/// it couldn't be restored.
+#[inline]
+pub fn read(key: &str, name: &str) -> Result<Option<RegistryValue>, RegistryError> {
+    let mut args = vec!["query", key];
+    args.extend(name_args(name));
+    let output = match run(&args) {
+        Ok(output) => output,
+        // Querying fails if the value doesn't exist.
+        Err(err) if err.inner.kind() == io::ErrorKind::Other => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let unsupported = |msg: String| RegistryError {
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        inner: io::Error::other(msg),
+    };
+    let (typ, data) =
+        parse_line(&output, name).ok_or_else(|| unsupported("unexpected output".to_string()))?;
+    match parse(typ, data) {
+        Some(value) => Ok(Some(value)),
+        None => Err(unsupported(format!("unsupported value type '{}'", typ))),
+    }
+}
+
+/// Return the arguments that select the value `name`; the empty name is the default value.
+#[inline]
+fn name_args(name: &str) -> Vec<&str> {
+    if name.is_empty() {
+        vec!["/ve"]
+    } else {
+        vec!["/v", name]
+    }
+}
+
+/// Find the type and data columns for `name` in the output of `reg query`, which lists values
+/// as `    <name>    <type>    <data>`.
+#[inline]
+fn parse_line<'a>(output: &'a str, name: &str) -> Option<(&'a str, &'a str)> {
+    let name = if name.is_empty() { "(Default)" } else { name };
+    output.lines().find_map(|line| {
+        let rest = line.trim_start().strip_prefix(name)?.strip_prefix("    ")?;
+        let mut cols = rest.splitn(2, "    ");
+        Some((cols.next()?, cols.next().unwrap_or("")))
+    })
+}
+
+/// Parse the type and data columns of a value in the output of `reg query`. Returns `None` for
+/// unsupported types.
+#[inline]
+fn parse(typ: &str, data: &str) -> Option<RegistryValue> {
+    let hex = |data: &str| data.strip_prefix("0x").map(str::to_string);
+    match typ {
+        "REG_DWORD" => u32::from_str_radix(&hex(data)?, 16)
+            .ok()
+            .map(RegistryValue::Dword),
+        "REG_QWORD" => u64::from_str_radix(&hex(data)?, 16)
+            .ok()
+            .map(RegistryValue::Qword),
+        "REG_SZ" => Some(RegistryValue::Sz(data.to_string())),
+        "REG_EXPAND_SZ" => Some(RegistryValue::ExpandSz(data.to_string())),
+        _ => None,
+    }
+}
+
+#[inline]
+fn write(key: &str, name: &str, value: &RegistryValue) -> Result<(), RegistryError> {
+    let data = value.data();
+    let mut args = vec!["add", key];
+    args.extend(name_args(name));
+    args.extend(["/t", value.typ(), "/d", &data, "/f"]);
+    run(&args).map(|_| ())
+}
+
+#[inline]
+fn delete(key: &str, name: &str) -> Result<(), RegistryError> {
+    let mut args = vec!["delete", key];
+    args.extend(name_args(name));
+    args.push("/f");
+    run(&args).map(|_| ())
+}
+
+/// Run `reg` with `args`, returning its stdout.
+#[inline]
+fn run(args: &[&str]) -> Result<String, RegistryError> {
+    let output = Command::new("reg")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .and_then(|output| {
+            if output.status.success() {
+                Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(io::Error::other(stderr.trim().to_string()))
+            }
+        });
+
+    output.map_err(|inner| RegistryError {
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        inner,
+    })
+}
+
+/// Operation to set a Windows registry value with `reg add`.
+///
+/// # Errors
+///
+/// The operation will error if `reg` cannot be spawned or exits with a non-zero status, or if
+/// the previous value has an unsupported type.
+///
+/// # Undo
+///
+/// Undoing will write the previous value back, or delete the value if it wasn't set. This set
+/// of operations functions in the following cycle:
+///
+/// [`RegistryOp`] --> [`RegistryFinish`] --> [`RegistryUndoOp`] --> [`RegistryUndoFinish`] -->
+/// [`RegistryOp`] --> ...
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RegistryOp {
+    /// Path of the key, e.g. `HKCU\Console`.
+    pub key: String,
+    /// Name of the value.
+    pub name: String,
+    /// Value to write.
+    pub value: RegistryValue,
+}
+
+/// The output of [`RegistryOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RegistryFinish {
+    /// See [`RegistryOp`].
+    pub key: String,
+    /// See [`RegistryOp`].
+    pub name: String,
+    /// See [`RegistryOp`].
+    pub value: RegistryValue,
+
+    /// Value before writing, or `None` if it wasn't set.
+    pub previous: Option<RegistryValue>,
+}
+
+impl Finish for RegistryOp {
+    type Output = RegistryFinish;
+    type Error = RegistryOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self { key, name, value } = self;
+
+        let previous = read(key, name)?;
+        write(key, name, value)?;
+
+        Ok(Self::Output {
+            key: key.clone(),
+            name: name.clone(),
+            value: value.clone(),
+            previous,
+        })
+    }
+}
+
+impl Rollback for RegistryFinish {
+    type Output = RegistryUndoOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self {
+            key,
+            name,
+            value,
+            previous,
+        } = self;
+
+        Self::Output {
+            key: key.clone(),
+            name: name.clone(),
+            value: value.clone(),
+            previous: previous.clone(),
+        }
+    }
+}
+
+/// The undo of [`RegistryOp`] (see its documentation), created by rolling back
+/// [`RegistryFinish`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RegistryUndoOp {
+    /// See [`RegistryOp`].
+    pub key: String,
+    /// See [`RegistryOp`].
+    pub name: String,
+    /// See [`RegistryOp`].
+    pub value: RegistryValue,
+
+    /// See [`RegistryFinish`].
+    pub previous: Option<RegistryValue>,
+}
+
+/// The output of [`RegistryUndoOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RegistryUndoFinish {
+    /// See [`RegistryOp`].
+    pub key: String,
+    /// See [`RegistryOp`].
+    pub name: String,
+    /// See [`RegistryOp`].
+    pub value: RegistryValue,
+}
+
+impl Finish for RegistryUndoOp {
+    type Output = RegistryUndoFinish;
+    type Error = RegistryOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self {
+            key,
+            name,
+            value,
+            previous,
+        } = self;
+
+        match previous {
+            Some(previous) => write(key, name, previous)?,
+            None => delete(key, name)?,
+        }
+
+        Ok(Self::Output {
+            key: key.clone(),
+            name: name.clone(),
+            value: value.clone(),
+        })
+    }
+}
+
+impl Rollback for RegistryUndoFinish {
+    type Output = RegistryOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self { key, name, value } = self;
+
+        Self::Output {
+            key: key.clone(),
+            name: name.clone(),
+            value: value.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, parse_line, RegistryValue};
+
+    /// Test parsing the output of `reg query`.
+    #[test]
+    fn test_parse() {
+        let output = "\r\nHKEY_CURRENT_USER\\Console\r\n    QuickEdit    REG_DWORD    0x1\r\n\r\n";
+        assert_eq!(Some(("REG_DWORD", "0x1")), parse_line(output, "QuickEdit"));
+        assert_eq!(None, parse_line(output, "Quick"));
+
+        let output = "\r\nHKEY_CURRENT_USER\\Environment\r\n    (Default)    REG_SZ    \r\n";
+        assert_eq!(Some(("REG_SZ", "")), parse_line(output, ""));
+
+        assert_eq!(Some(RegistryValue::Dword(255)), parse("REG_DWORD", "0xff"));
+        assert_eq!(
+            Some(RegistryValue::Qword(1 << 40)),
+            parse("REG_QWORD", "0x10000000000")
+        );
+        assert_eq!(
+            Some(RegistryValue::ExpandSz("%USERPROFILE%\\bin".to_string())),
+            parse("REG_EXPAND_SZ", "%USERPROFILE%\\bin")
+        );
+        assert_eq!(None, parse("REG_BINARY", "00ff"));
+    }
+}
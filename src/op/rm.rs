@@ -6,8 +6,8 @@ use static_assertions as sa;
 
 use super::ctx::FinishCtx;
 use super::error::{
-    CopyError, MetadataError, MkdirError, MoveError, ReadLinkError, RemoveError, RenameError,
-    SymlinkError,
+    CompressError, CopyError, DecompressError, MetadataError, MkdirError, MoveError, ReadLinkError,
+    RemoveError, RenameError, SymlinkError,
 };
 use super::{Finish, Rollback};
 
@@ -35,6 +35,8 @@ pub enum RmOpError {
     Copy(#[from] CopyError),
     #[error("mkdir error")]
     Mkdir(#[from] MkdirError),
+    #[error("compress error")]
+    Compress(#[from] CompressError),
 }
 
 /// Operation to remove a file or directory at `path`.
@@ -46,8 +48,11 @@ pub enum RmOpError {
 ///
 /// # Undo
 ///
-/// Undoing will restore the file or directory (and its contents). This set of operations functions
-/// in the following cycle:
+/// Undoing will restore the file or directory (and its contents). If the file safe is configured
+/// with a compression threshold (see
+/// [`with_compression`](super::ctx::FileSafe::with_compression)), large regular files are backed
+/// up compressed and decompressed transparently on undo. This set of operations functions in the
+/// following cycle:
 ///
 /// [`RmOp`] --> [`RmFinish`] --> [`RmUndoOp`] --> [`RmUndoFinish`] --> [`RmOp`] --> ...
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
@@ -69,6 +74,9 @@ pub struct RmFinish {
 
     /// Path at which the file was backed-up.
     pub safepath: PathBuf,
+    /// True if the backup at `safepath` is compressed.
+    #[serde(default)]
+    pub compressed: bool,
 }
 
 impl Finish for RmOp {
@@ -79,13 +87,27 @@ impl Finish for RmOp {
     fn finish(&self, ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
         let Self { path, dir } = self;
 
-        let safepath = ctx.filesafe.resolve(path);
-        rename_with_fallback(path, &safepath)?;
+        let metadata = path.symlink_metadata().map_err(|inner| MetadataError {
+            path: path.clone(),
+            inner,
+        })?;
+
+        let compressed = ctx.filesafe.should_compress(&metadata);
+        let safepath = if compressed {
+            let safepath = ctx.filesafe.resolve_compressed(path);
+            compress(path, &safepath)?;
+            safepath
+        } else {
+            let safepath = ctx.filesafe.resolve(path);
+            rename_with_fallback(path, &safepath)?;
+            safepath
+        };
 
         Ok(Self::Output {
             path: path.clone(),
             dir: *dir,
             safepath,
+            compressed,
         })
     }
 }
@@ -99,12 +121,14 @@ impl Rollback for RmFinish {
             path,
             dir,
             safepath,
+            compressed,
         } = self;
 
         Self::Output {
             path: path.clone(),
             dir: *dir,
             safepath: safepath.clone(),
+            compressed: *compressed,
         }
     }
 }
@@ -128,6 +152,8 @@ pub enum RmUndoOpError {
     Copy(#[from] CopyError),
     #[error("mkdir error")]
     Mkdir(#[from] MkdirError),
+    #[error("decompress error")]
+    Decompress(#[from] DecompressError),
 }
 
 /// The undo of [`RmOp`] (see its documentation), created by rolling back [`RmFinish`].
@@ -140,6 +166,9 @@ pub struct RmUndoOp {
 
     /// See [`RmFinish`].
     pub safepath: PathBuf,
+    /// See [`RmFinish`].
+    #[serde(default)]
+    pub compressed: bool,
 }
 
 /// The output of [`RmUndoOp`]. See its documentation for information.
@@ -161,9 +190,18 @@ impl Finish for RmUndoOp {
             path,
             dir,
             safepath,
+            compressed,
         } = self;
 
-        match rename_with_fallback(path, safepath) {
+        if *compressed {
+            decompress(safepath, path)?;
+            return Ok(Self::Output {
+                path: path.clone(),
+                dir: *dir,
+            });
+        }
+
+        match rename_with_fallback(safepath, path) {
             Ok(()) => {}
             Err(err) => match err {
                 RmOpError::Rename(err) => return Err(err.into()),
@@ -174,6 +212,7 @@ impl Finish for RmUndoOp {
                 RmOpError::Move(err) => return Err(err.into()),
                 RmOpError::Copy(err) => return Err(err.into()),
                 RmOpError::Mkdir(err) => return Err(err.into()),
+                RmOpError::Compress(_) => unreachable!(),
             },
         }
 
@@ -198,6 +237,61 @@ impl Rollback for RmUndoFinish {
     }
 }
 
+/// Write a compressed copy of the regular file at `path` to `safepath` and remove the original.
+#[inline]
+fn compress(path: &Path, safepath: &Path) -> Result<(), RmOpError> {
+    let err = |inner| CompressError {
+        src: path.to_path_buf(),
+        dest: safepath.to_path_buf(),
+        inner,
+    };
+
+    if let Some(parent) = safepath.parent() {
+        fs::create_dir_all(parent).map_err(|err| MkdirError {
+            path: parent.to_path_buf(),
+            inner: err,
+        })?;
+    }
+
+    let src = fs::File::open(path).map_err(err)?;
+    let dest = fs::File::create(safepath).map_err(err)?;
+    zstd::stream::copy_encode(src, &dest, 0).map_err(err)?;
+
+    // Keep the permissions so that they are restored on undo.
+    let perms = path.metadata().map_err(err)?.permissions();
+    dest.set_permissions(perms).map_err(err)?;
+
+    fs::remove_file(path).map_err(|err| RemoveError {
+        path: path.to_path_buf(),
+        inner: err,
+    })?;
+    Ok(())
+}
+
+/// Restore the regular file at `path` from the compressed backup at `safepath`, and remove the
+/// backup.
+#[inline]
+fn decompress(safepath: &Path, path: &Path) -> Result<(), RmUndoOpError> {
+    let err = |inner| DecompressError {
+        src: safepath.to_path_buf(),
+        dest: path.to_path_buf(),
+        inner,
+    };
+
+    let src = fs::File::open(safepath).map_err(err)?;
+    let dest = fs::File::create(path).map_err(err)?;
+    zstd::stream::copy_decode(&src, &dest).map_err(err)?;
+
+    let perms = src.metadata().map_err(err)?.permissions();
+    dest.set_permissions(perms).map_err(err)?;
+
+    fs::remove_file(safepath).map_err(|err| RemoveError {
+        path: safepath.to_path_buf(),
+        inner: err,
+    })?;
+    Ok(())
+}
+
 #[inline]
 fn rename_with_fallback(path: &Path, safepath: &Path) -> Result<(), RmOpError> {
     // TODO: Try to lift detection of filesystem up to action level?
@@ -299,8 +393,11 @@ fn rename_with_fallback(path: &Path, safepath: &Path) -> Result<(), RmOpError> {
 
 #[cfg(test)]
 mod test {
+    use std::fs;
+
     use crate::fse;
 
+    use super::super::ctx::FinishCtx;
     use super::super::test;
     use super::{Finish, RmOp, Rollback};
 
@@ -331,6 +428,37 @@ mod test {
         })
     }
 
+    /// Test that a regular file is compressed in the file safe and restored on undo.
+    #[test]
+    fn test_compressed_file() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let ctx = &FinishCtx {
+                filesafe: ctx.filesafe.clone().with_compression(0),
+                ..ctx.clone()
+            };
+
+            let path = dir.join("a");
+            let contents = "a".repeat(4096);
+            fs::write(&path, &contents)?;
+
+            let op = RmOp {
+                path: path.clone(),
+                dir: false,
+            };
+
+            let opf = op.finish(ctx)?;
+            assert!(opf.compressed);
+            assert!(!fse::symlink_exists(&path));
+            assert!(fs::metadata(&opf.safepath)?.len() < contents.len() as u64);
+
+            opf.rollback().finish(ctx)?;
+            assert_eq!(contents, fs::read_to_string(&path)?);
+            assert!(!fse::symlink_exists(&opf.safepath));
+
+            Ok(())
+        })
+    }
+
     /// Test for nonexistent file.
     #[test]
     fn test_nonexistent_file() -> test::Result<()> {
@@ -0,0 +1,135 @@
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use static_assertions as sa;
+
+use super::command::{self, EnvMap};
+use super::ctx::FinishCtx;
+use super::error::ExecError;
+use super::Finish;
+
+sa::assert_impl_all!(ScriptOp: Finish<Output = ScriptFinish, Error = ScriptOpError>);
+
+/// Error encountered when finishing [`ScriptOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptOpError {
+    #[error("exec error")]
+    Exec(#[from] ExecError),
+}
+
+/// Operation to execute the file at `path` with `args`, without a shell.
+///
+/// # Errors
+///
+/// It is assumed that `path` points to an executable file. This premise is not checked, and the
+/// operation will error if it is not met.
+///
+/// # Undo
+///
+/// This operation is not undo-able.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ScriptOp {
+    /// Path of the file to execute.
+    pub path: PathBuf,
+    /// Arguments to pass.
+    pub args: Vec<String>,
+    /// Initial directory in which the file will be executed.
+    pub start: PathBuf,
+    /// Map of extra environment variables to set.
+    pub env: EnvMap,
+    /// If present, the file is killed after running this long, and the op fails.
+    #[serde(default)]
+    pub timeout: Option<Duration>,
+}
+
+/// The output of [`ScriptOp`]. See its documentation for information.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptFinish {
+    /// See [`ScriptOp`].
+    pub path: PathBuf,
+    /// See [`ScriptOp`].
+    pub args: Vec<String>,
+    /// See [`ScriptOp`].
+    pub start: PathBuf,
+    /// See [`ScriptOp`].
+    pub env: EnvMap,
+
+    /// Output of the script.
+    pub output: Output,
+}
+
+impl Finish for ScriptOp {
+    type Output = ScriptFinish;
+    type Error = ScriptOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self {
+            path,
+            args,
+            start,
+            env,
+            timeout,
+        } = self;
+
+        let mut cmd = Command::new(path);
+        cmd.args(args);
+        cmd.current_dir(start);
+        cmd.envs(env);
+
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        let output = command::spawn_output(cmd, *timeout).map_err(|inner| ExecError {
+            path: path.clone(),
+            args: args.clone(),
+            start: start.clone(),
+            inner,
+        })?;
+
+        Ok(Self::Output {
+            path: path.clone(),
+            args: args.clone(),
+            start: start.clone(),
+            env: env.clone(),
+            output,
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::super::test;
+    use super::{Finish, ScriptOp};
+
+    /// Test that the script is run with its arguments in the start directory.
+    #[test]
+    fn test_args_start() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let path = dir.join("setup.sh");
+            fs::write(&path, "#!/bin/sh\necho \"$1 $(pwd)\"\n")?;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+
+            let op = ScriptOp {
+                path,
+                args: vec!["hello".into()],
+                start: dir.to_path_buf(),
+                env: Default::default(),
+                timeout: None,
+            };
+
+            let opf = op.finish(ctx)?;
+            assert!(opf.output.status.success());
+            let stdout = String::from_utf8(opf.output.stdout)?;
+            let expected = format!("hello {}\n", dir.canonicalize()?.display());
+            assert_eq!(stdout, expected);
+
+            Ok(())
+        })
+    }
+}
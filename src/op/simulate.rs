@@ -0,0 +1,362 @@
+//! Simulation of ops against an in-memory model of the filesystem, so that what a sequence of
+//! ops would do can be predicted without applying it. The model starts out as the current
+//! filesystem, and records only what the simulated ops change.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::fse;
+
+use super::effect::Effect;
+use super::journal::JournalOp;
+#[cfg(unix)]
+use super::ChownOp;
+use super::{
+    ChmodOp, CopyDirOp, CopyOp, CreateOp, HardlinkOp, LinkOp, MkdirOp, RmOp, SourceLineOp, WriteOp,
+};
+
+/// Op that expects a state that wouldn't hold when it is applied. See [`Effect::Conflict`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// Index of the op in the order it was simulated.
+    pub index: usize,
+    /// Path that the op conflicts on.
+    pub path: PathBuf,
+}
+
+/// Model of the filesystem after simulated ops. Ops must be simulated in the order they would be
+/// applied, since the changes made by earlier ops are taken into account (e.g. a link created
+/// where a file was removed by the previous op is an [`Effect::Create`]).
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedFs {
+    /// Paths created by simulated ops that still exist.
+    created: HashSet<PathBuf>,
+    /// Existing paths removed by simulated ops, including their contents.
+    removed: HashSet<PathBuf>,
+    /// Existing paths that are replaced: overwritten, or removed and then created again.
+    replaced: BTreeSet<PathBuf>,
+    conflicts: Vec<Conflict>,
+    /// Number of simulated ops.
+    count: usize,
+}
+
+/// Simulate `ops` in order against the current filesystem.
+#[inline]
+pub fn simulate<'a, I>(ops: I) -> SimulatedFs
+where
+    I: IntoIterator<Item = &'a JournalOp>,
+{
+    let mut fs = SimulatedFs::new();
+    for op in ops {
+        fs.apply(op);
+    }
+    fs
+}
+
+impl SimulatedFs {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply `op` to the model, and return its predicted effect. Conflicting ops change nothing.
+    #[inline]
+    pub fn apply(&mut self, op: &JournalOp) -> Effect {
+        let (effect, path) = self.apply_impl(op);
+        if let (Effect::Conflict, Some(path)) = (effect, path) {
+            self.conflicts.push(Conflict {
+                index: self.count,
+                path: path.clone(),
+            });
+        }
+        self.count += 1;
+        effect
+    }
+
+    /// Return true if something would exist at `path` after the simulated ops.
+    #[inline]
+    pub fn exists(&self, path: &Path) -> bool {
+        self.created.contains(path) || self.on_disk(path)
+    }
+
+    /// Return the paths that don't exist now but would after the simulated ops, in order.
+    #[inline]
+    pub fn created(&self) -> Vec<&Path> {
+        let mut created: Vec<_> = self
+            .created
+            .iter()
+            .filter(|path| !self.replaced.contains(*path))
+            .map(PathBuf::as_path)
+            .collect();
+        created.sort();
+        created
+    }
+
+    /// Return the paths that exist now but wouldn't after the simulated ops, in order. The
+    /// contents of removed directories are not listed.
+    #[inline]
+    pub fn removed(&self) -> Vec<&Path> {
+        let mut removed: Vec<_> = self
+            .removed
+            .iter()
+            .filter(|path| !self.created.contains(*path))
+            .map(PathBuf::as_path)
+            .collect();
+        removed.sort();
+        removed
+    }
+
+    /// Return the paths that exist now and would be replaced by the simulated ops, in order.
+    #[inline]
+    pub fn replaced(&self) -> Vec<&Path> {
+        self.replaced.iter().map(PathBuf::as_path).collect()
+    }
+
+    /// Return the conflicting ops, in order.
+    #[inline]
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Return the predicted effect of `op`, and the path that it changes, if any.
+    #[inline]
+    fn apply_impl<'o>(&mut self, op: &'o JournalOp) -> (Effect, Option<&'o PathBuf>) {
+        match op {
+            JournalOp::Link(LinkOp { dest, .. })
+            | JournalOp::Copy(CopyOp { dest, .. })
+            | JournalOp::Hardlink(HardlinkOp { dest, .. })
+            | JournalOp::Create(CreateOp { path: dest })
+            | JournalOp::Mkdir(MkdirOp { path: dest }) => (self.create(dest), Some(dest)),
+            JournalOp::CopyDir(CopyDirOp { dest, keep, .. }) => {
+                // Paths to keep are left in place, so the directory may already exist.
+                if !keep.is_empty() && self.exists(dest) {
+                    (Effect::Modify, Some(dest))
+                } else {
+                    (self.create(dest), Some(dest))
+                }
+            }
+            JournalOp::Write(WriteOp { path, contents }) => {
+                let effect = if self.created.contains(path) {
+                    Effect::Create
+                } else if !self.exists(path) {
+                    Effect::Conflict
+                } else {
+                    match fs::read(path) {
+                        Ok(existing) if &existing == contents => Effect::UpToDate,
+                        _ => {
+                            self.replaced.insert(path.clone());
+                            Effect::ReplaceFile
+                        }
+                    }
+                };
+                (effect, Some(path))
+            }
+            JournalOp::Rm(RmOp { path, .. }) => (self.remove(path), Some(path)),
+            JournalOp::Chmod(ChmodOp { path, mode }) => {
+                let effect = if !self.exists(path) {
+                    Effect::Conflict
+                } else if self.on_disk(path) && current_mode(path) == Some(*mode) {
+                    Effect::UpToDate
+                } else {
+                    Effect::Modify
+                };
+                (effect, Some(path))
+            }
+            #[cfg(unix)]
+            JournalOp::Chown(ChownOp { path, uid, gid }) => {
+                let effect = if !self.exists(path) {
+                    Effect::Conflict
+                } else if self.on_disk(path) && owned_by(path, *uid, *gid) {
+                    Effect::UpToDate
+                } else {
+                    Effect::Modify
+                };
+                (effect, Some(path))
+            }
+            JournalOp::SourceLine(SourceLineOp { path, .. }) => {
+                if self.exists(path) {
+                    (Effect::Modify, Some(path))
+                } else {
+                    self.created.insert(path.clone());
+                    (Effect::Create, Some(path))
+                }
+            }
+            JournalOp::Systemctl(_) | JournalOp::Defaults(_) => (Effect::Modify, None),
+            #[cfg(all(windows, feature = "registry"))]
+            JournalOp::Registry(_) | JournalOp::RegistryUndo(_) => (Effect::Modify, None),
+            // Undoing reverts earlier changes; these don't appear in plans.
+            JournalOp::LinkUndo(_)
+            | JournalOp::CopyUndo(_)
+            | JournalOp::HardlinkUndo(_)
+            | JournalOp::CopyDirUndo(_)
+            | JournalOp::CreateUndo(_)
+            | JournalOp::WriteUndo(_)
+            | JournalOp::MkdirUndo(_)
+            | JournalOp::RmUndo(_)
+            | JournalOp::SystemctlUndo(_)
+            | JournalOp::ChmodUndo(_)
+            | JournalOp::SourceLineUndo(_)
+            | JournalOp::DefaultsUndo(_) => (Effect::Modify, None),
+            #[cfg(unix)]
+            JournalOp::ChownUndo(_) => (Effect::Modify, None),
+        }
+    }
+
+    #[inline]
+    fn create(&mut self, path: &Path) -> Effect {
+        if self.exists(path) {
+            return Effect::Conflict;
+        }
+
+        // Something existing was removed earlier in the plan to make way for this.
+        if self.removed.contains(path) {
+            self.replaced.insert(path.to_path_buf());
+        }
+        self.created.insert(path.to_path_buf());
+        Effect::Create
+    }
+
+    #[inline]
+    fn remove(&mut self, path: &Path) -> Effect {
+        if !self.exists(path) {
+            return Effect::Conflict;
+        }
+
+        let effect = if !self.on_disk(path) {
+            // Removing something created earlier in the plan loses nothing that exists now.
+            Effect::Modify
+        } else if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            Effect::ReplaceSymlink
+        } else {
+            Effect::ReplaceFile
+        };
+
+        if effect != Effect::Modify {
+            self.removed.insert(path.to_path_buf());
+        }
+        self.created.retain(|created| !created.starts_with(path));
+        self.replaced.retain(|replaced| !replaced.starts_with(path));
+        effect
+    }
+
+    /// Return true if `path` currently exists and hasn't been removed by a simulated op.
+    #[inline]
+    fn on_disk(&self, path: &Path) -> bool {
+        !path.ancestors().any(|p| self.removed.contains(p)) && fse::symlink_exists(path)
+    }
+}
+
+#[cfg(unix)]
+#[inline]
+fn current_mode(path: &Path) -> Option<u32> {
+    super::chmod::get_mode(path).ok()
+}
+
+#[cfg(not(unix))]
+#[inline]
+fn current_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// Return true if the file at `path` is owned by `uid` and `gid`, where given.
+#[cfg(unix)]
+#[inline]
+fn owned_by(path: &Path, uid: Option<u32>, gid: Option<u32>) -> bool {
+    match super::chown::get_owner(path) {
+        Ok((cur_uid, cur_gid)) => {
+            uid.is_none_or(|uid| uid == cur_uid) && gid.is_none_or(|gid| gid == cur_gid)
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::super::journal::JournalOp;
+    use super::super::test;
+    use super::super::{CreateOp, LinkOp, RmOp, WriteOp};
+    use super::{simulate, Conflict, Effect, SimulatedFs};
+
+    /// Test that ops are classified against the changes of earlier ops.
+    #[cfg(unix)]
+    #[test]
+    fn test_apply() -> test::Result<()> {
+        test::with_tempdir(|dir, _ctx| {
+            let (file, link) = (dir.join("file"), dir.join("link"));
+            fs::write(&file, "a")?;
+            std::os::unix::fs::symlink(&file, &link)?;
+
+            let mut fs = SimulatedFs::new();
+            let write = |contents: &str| {
+                WriteOp {
+                    path: file.clone(),
+                    contents: contents.as_bytes().to_vec(),
+                }
+                .into()
+            };
+            assert_eq!(Effect::UpToDate, fs.apply(&write("a")));
+            assert_eq!(Effect::ReplaceFile, fs.apply(&write("b")));
+
+            let relink = LinkOp {
+                src: file.clone(),
+                dest: link.clone(),
+                fallback: false,
+            }
+            .into();
+            assert_eq!(Effect::Conflict, fs.apply(&relink));
+            let rm = RmOp {
+                path: link.clone(),
+                dir: false,
+            }
+            .into();
+            assert_eq!(Effect::ReplaceSymlink, fs.apply(&rm));
+            assert_eq!(Effect::Create, fs.apply(&relink));
+
+            Ok(())
+        })
+    }
+
+    /// Test that created, removed, and replaced paths and conflicts are reported.
+    #[test]
+    fn test_simulate() -> test::Result<()> {
+        test::with_tempdir(|dir, _ctx| {
+            let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+            fs::write(&a, "a")?;
+            fs::write(&b, "b")?;
+
+            let ops: Vec<JournalOp> = vec![
+                RmOp {
+                    path: a.clone(),
+                    dir: false,
+                }
+                .into(),
+                CreateOp { path: a.clone() }.into(),
+                RmOp {
+                    path: b.clone(),
+                    dir: false,
+                }
+                .into(),
+                CreateOp { path: c.clone() }.into(),
+                CreateOp { path: c.clone() }.into(),
+            ];
+            let fs = simulate(&ops);
+
+            assert_eq!(fs.created(), vec![c.as_path()]);
+            assert_eq!(fs.removed(), vec![b.as_path()]);
+            assert_eq!(fs.replaced(), vec![a.as_path()]);
+            assert_eq!(
+                fs.conflicts(),
+                &[Conflict {
+                    index: 4,
+                    path: c.clone()
+                }]
+            );
+            assert!(fs.exists(&a) && !fs.exists(&b) && fs.exists(&c));
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,176 @@
+//! Sinks that are given a record of each op that is finished, so that changes can be audited
+//! outside of shelf, e.g. in syslog or by a webhook.
+
+use std::fmt::Debug;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use super::journal::JournalOpFinish;
+
+/// Structured record of a finished op. File contents are never included.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpRecord<'a> {
+    /// Time at which the op finished, in seconds since the Unix epoch.
+    pub time: u64,
+    /// Sequence number of the op in the journal.
+    pub seq: u64,
+    /// Path of the package that the op was run for.
+    pub package: &'a Path,
+    /// Name of the kind of op, e.g. `link`.
+    pub kind: &'static str,
+    pub dest: Option<&'a Path>,
+    pub src: Option<&'a Path>,
+}
+
+impl<'a> OpRecord<'a> {
+    /// Create the record of `fin`, finished at `time`, for `package`.
+    #[inline]
+    pub fn new(
+        fin: &'a JournalOpFinish,
+        seq: u64,
+        time: Option<SystemTime>,
+        package: &'a Path,
+    ) -> Self {
+        let time = time
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self {
+            time,
+            seq,
+            package,
+            kind: fin.name(),
+            dest: fin.dest(),
+            src: fin.src(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SinkError {
+    #[error("couldn't serialize record")]
+    Serialize(#[from] serde_json::Error),
+    #[error("couldn't run program")]
+    Exec(#[from] io::Error),
+    #[error("program exited unsuccessfully: {0}")]
+    Failed(String),
+    #[error("sink is not supported on this platform")]
+    Unsupported,
+}
+
+/// Receiver of the records of finished ops.
+pub trait OpSink: Debug + Send + Sync {
+    fn record(&self, record: &OpRecord<'_>) -> Result<(), SinkError>;
+}
+
+/// Sink that logs records as JSON lines to the system log, under the `user` facility.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyslogSink;
+
+impl OpSink for SyslogSink {
+    #[cfg(unix)]
+    #[inline]
+    fn record(&self, record: &OpRecord<'_>) -> Result<(), SinkError> {
+        use std::ffi::CString;
+
+        // The identifier must outlive all calls to syslog.
+        static IDENT: &[u8] = b"shelf\0";
+
+        let message = serde_json::to_string(record)?;
+        // JSON never contains nul bytes outside of escapes.
+        let message = CString::new(message).map_err(|err| SinkError::Failed(err.to_string()))?;
+
+        // SAFETY: Both strings are nul-terminated, and the message is passed through a format
+        // string so that it isn't interpreted.
+        unsafe {
+            libc::openlog(IDENT.as_ptr().cast(), libc::LOG_PID, libc::LOG_USER);
+            libc::syslog(libc::LOG_NOTICE, b"%s\0".as_ptr().cast(), message.as_ptr());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    #[inline]
+    fn record(&self, _record: &OpRecord<'_>) -> Result<(), SinkError> {
+        Err(SinkError::Unsupported)
+    }
+}
+
+/// Sink that POSTs records as JSON to a URL, using `curl`.
+#[derive(Debug, Clone)]
+pub struct WebhookSink {
+    pub url: String,
+}
+
+/// Maximum number of seconds to wait for a webhook to respond.
+const WEBHOOK_TIMEOUT: &str = "10";
+
+impl OpSink for WebhookSink {
+    #[inline]
+    fn record(&self, record: &OpRecord<'_>) -> Result<(), SinkError> {
+        let body = serde_json::to_vec(record)?;
+
+        let mut child = Command::new("curl")
+            .args(["-fsS", "--max-time", WEBHOOK_TIMEOUT])
+            .args(["-H", "Content-Type: application/json"])
+            .args(["--data-binary", "@-"])
+            .arg(&self.url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // SAFETY: stdin was piped.
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(&body)?;
+        drop(stdin);
+
+        let output = child.wait_with_output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(SinkError::Failed(stderr.trim().to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::OpRecord;
+    use crate::op::{journal::JournalOpFinish, link::LinkFinish};
+
+    /// Test that records are serialized with the op's kind and paths.
+    #[test]
+    fn test_record_json() {
+        let fin = JournalOpFinish::Link(LinkFinish {
+            src: PathBuf::from("/pkg/a"),
+            dest: PathBuf::from("/home/a"),
+            fallback: false,
+            copied: false,
+            junction: false,
+        });
+        let time = UNIX_EPOCH + Duration::from_secs(42);
+        let record = OpRecord::new(&fin, 3, Some(time), Path::new("/pkg"));
+
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "time": 42,
+                "seq": 3,
+                "package": "/pkg",
+                "kind": "link",
+                "dest": "/home/a",
+                "src": "/pkg/a",
+            }),
+            json
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::path::PathBuf;
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+use static_assertions as sa;
+
+use super::ctx::FinishCtx;
+use super::error::{ReadError, RemoveError, WriteError};
+use super::{Finish, Rollback};
+
+sa::assert_impl_all!(SourceLineOp: Finish<Output = SourceLineFinish, Error = SourceLineOpError>);
+sa::assert_impl_all!(SourceLineFinish: Rollback<Output = SourceLineUndoOp>);
+sa::assert_impl_all!(
+    SourceLineUndoOp: Finish<Output = SourceLineUndoFinish, Error = SourceLineUndoOpError>
+);
+sa::assert_impl_all!(SourceLineUndoFinish: Rollback<Output = SourceLineOp>);
+
+/// Error encountered when finishing [`SourceLineOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum SourceLineOpError {
+    #[error("read error")]
+    Read(#[from] ReadError),
+    #[error("write error")]
+    Write(#[from] WriteError),
+}
+
+/// Operation to insert `line` into the managed block `block` of the shell rc file at `path`. The
+/// block is delimited by comment lines, and is appended to the end of the file if it doesn't
+/// exist yet:
+///
+/// ```sh
+/// # >>> block >>>
+/// line
+/// # <<< block <<<
+/// ```
+///
+/// # Errors
+///
+/// If `path` doesn't exist, it is created. Its parent directory must exist.
+///
+/// # Undo
+///
+/// Undoing will remove `line` from the block, and the block itself if it has become empty. The
+/// rest of the file is left alone. If the file was created, it is removed once empty. This set of
+/// operations functions in the following cycle:
+///
+/// [`SourceLineOp`] --> [`SourceLineFinish`] --> [`SourceLineUndoOp`] -->
+/// [`SourceLineUndoFinish`] --> [`SourceLineOp`] --> ...
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceLineOp {
+    /// Path of the rc file.
+    pub path: PathBuf,
+    /// Line to insert.
+    pub line: String,
+    /// Name of the managed block.
+    pub block: String,
+}
+
+/// The output of [`SourceLineOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceLineFinish {
+    /// See [`SourceLineOp`].
+    pub path: PathBuf,
+    /// See [`SourceLineOp`].
+    pub line: String,
+    /// See [`SourceLineOp`].
+    pub block: String,
+
+    /// True if the file didn't exist and was created.
+    pub created: bool,
+}
+
+impl Finish for SourceLineOp {
+    type Output = SourceLineFinish;
+    type Error = SourceLineOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self { path, line, block } = self;
+
+        let (contents, created) = match fs::read_to_string(path) {
+            Ok(contents) => (contents, false),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (String::new(), true),
+            Err(inner) => {
+                return Err(ReadError {
+                    path: path.clone(),
+                    inner,
+                }
+                .into())
+            }
+        };
+
+        let contents = insert_line(&contents, block, line);
+        fs::write(path, contents).map_err(|inner| WriteError {
+            path: path.clone(),
+            inner,
+        })?;
+
+        Ok(Self::Output {
+            path: path.clone(),
+            line: line.clone(),
+            block: block.clone(),
+            created,
+        })
+    }
+}
+
+impl Rollback for SourceLineFinish {
+    type Output = SourceLineUndoOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self {
+            path,
+            line,
+            block,
+            created,
+        } = self;
+
+        Self::Output {
+            path: path.clone(),
+            line: line.clone(),
+            block: block.clone(),
+            created: *created,
+        }
+    }
+}
+
+/// Error encountered when finishing [`SourceLineUndoOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum SourceLineUndoOpError {
+    #[error("read error")]
+    Read(#[from] ReadError),
+    #[error("write error")]
+    Write(#[from] WriteError),
+    #[error("remove error")]
+    Remove(#[from] RemoveError),
+}
+
+/// The undo of [`SourceLineOp`] (see its documentation), created by rolling back
+/// [`SourceLineFinish`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceLineUndoOp {
+    /// See [`SourceLineOp`].
+    pub path: PathBuf,
+    /// See [`SourceLineOp`].
+    pub line: String,
+    /// See [`SourceLineOp`].
+    pub block: String,
+
+    /// See [`SourceLineFinish`].
+    pub created: bool,
+}
+
+/// The output of [`SourceLineUndoOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceLineUndoFinish {
+    /// See [`SourceLineOp`].
+    pub path: PathBuf,
+    /// See [`SourceLineOp`].
+    pub line: String,
+    /// See [`SourceLineOp`].
+    pub block: String,
+}
+
+impl Finish for SourceLineUndoOp {
+    type Output = SourceLineUndoFinish;
+    type Error = SourceLineUndoOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self {
+            path,
+            line,
+            block,
+            created,
+        } = self;
+
+        let contents = fs::read_to_string(path).map_err(|inner| ReadError {
+            path: path.clone(),
+            inner,
+        })?;
+
+        let contents = remove_line(&contents, block, line);
+        if *created && contents.is_empty() {
+            fs::remove_file(path).map_err(|inner| RemoveError {
+                path: path.clone(),
+                inner,
+            })?;
+        } else {
+            fs::write(path, contents).map_err(|inner| WriteError {
+                path: path.clone(),
+                inner,
+            })?;
+        }
+
+        Ok(Self::Output {
+            path: path.clone(),
+            line: line.clone(),
+            block: block.clone(),
+        })
+    }
+}
+
+impl Rollback for SourceLineUndoFinish {
+    type Output = SourceLineOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self { path, line, block } = self;
+
+        Self::Output {
+            path: path.clone(),
+            line: line.clone(),
+            block: block.clone(),
+        }
+    }
+}
+
+/// Return the line that starts the managed block `block`.
+#[inline]
+fn block_start(block: &str) -> String {
+    format!("# >>> {} >>>", block)
+}
+
+/// Return the line that ends the managed block `block`.
+#[inline]
+fn block_end(block: &str) -> String {
+    format!("# <<< {} <<<", block)
+}
+
+/// Return the indices of the start and end lines of the managed block `block` in `lines`.
+#[inline]
+fn find_block(lines: &[&str], block: &str) -> Option<(usize, usize)> {
+    let (start, end) = (block_start(block), block_end(block));
+
+    let start = lines.iter().position(|l| l.trim_end() == start)?;
+    let end = start + lines[start..].iter().position(|l| l.trim_end() == end)?;
+    Some((start, end))
+}
+
+/// Insert `line` at the end of the managed block `block` in `contents`, appending the block if it
+/// doesn't exist.
+#[inline]
+fn insert_line(contents: &str, block: &str, line: &str) -> String {
+    let mut lines: Vec<_> = contents.lines().collect();
+    let (start, end) = (block_start(block), block_end(block));
+
+    match find_block(&lines, block) {
+        Some((_, end)) => lines.insert(end, line),
+        None => {
+            // Separate the block from the rest of the file. This is always added (even after an
+            // existing blank line) so that removing the block restores the file exactly.
+            if !lines.is_empty() {
+                lines.push("");
+            }
+            lines.extend([start.as_str(), line, end.as_str()]);
+        }
+    }
+
+    join_lines(&lines)
+}
+
+/// Remove `line` from the managed block `block` in `contents`, and the block itself if it is then
+/// empty. Lines outside of the block are never touched.
+#[inline]
+fn remove_line(contents: &str, block: &str, line: &str) -> String {
+    let mut lines: Vec<_> = contents.lines().collect();
+
+    if let Some((start, end)) = find_block(&lines, block) {
+        // Lines are inserted at the end of the block, so remove the last occurrence.
+        if let Some(i) = lines[start + 1..end].iter().rposition(|l| *l == line) {
+            lines.remove(start + 1 + i);
+
+            // Remove the block if it is now empty, along with the blank separator line.
+            if end - 1 == start + 1 {
+                lines.drain(start..end);
+                if start > 0 && start == lines.len() && lines[start - 1].is_empty() {
+                    lines.remove(start - 1);
+                }
+            }
+        }
+    }
+
+    join_lines(&lines)
+}
+
+#[inline]
+fn join_lines(lines: &[&str]) -> String {
+    lines.iter().map(|l| format!("{}\n", l)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::fse;
+
+    use super::super::test;
+    use super::{Finish, Rollback, SourceLineOp};
+
+    /// Test inserting into an existing rc file, and that undoing restores it exactly.
+    #[test]
+    fn test_existing() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let path = dir.join(".bashrc");
+            let original = "export EDITOR=vim\n";
+            fs::write(&path, original)?;
+
+            let op = SourceLineOp {
+                path: path.clone(),
+                line: "source ~/.bash_aliases".to_string(),
+                block: "shelf bash".to_string(),
+            };
+
+            let opf = op.finish(ctx)?;
+            assert!(!opf.created);
+            assert_eq!(
+                fs::read_to_string(&path)?,
+                "export EDITOR=vim\n\n\
+                 # >>> shelf bash >>>\n\
+                 source ~/.bash_aliases\n\
+                 # <<< shelf bash <<<\n"
+            );
+
+            let undo = opf.rollback();
+            let undof = undo.finish(ctx)?;
+            assert_eq!(fs::read_to_string(&path)?, original);
+
+            let op2 = undof.rollback();
+            assert_eq!(op, op2);
+
+            Ok(())
+        })
+    }
+
+    /// Test that a created rc file is removed on undo.
+    #[test]
+    fn test_created() -> test::Result<()> {
+        test::with_tempdir(|dir, ctx| {
+            let path = dir.join(".zshrc");
+
+            let op = SourceLineOp {
+                path: path.clone(),
+                line: "eval \"$(direnv hook zsh)\"".to_string(),
+                block: "shelf zsh".to_string(),
+            };
+
+            let opf = op.finish(ctx)?;
+            assert!(opf.created);
+
+            opf.rollback().finish(ctx)?;
+            assert!(!fse::symlink_exists(&path));
+
+            Ok(())
+        })
+    }
+}
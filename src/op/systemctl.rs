@@ -0,0 +1,211 @@
+use std::io;
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+use static_assertions as sa;
+
+use super::ctx::FinishCtx;
+use super::error::SystemctlError;
+use super::{Finish, Rollback};
+
+sa::assert_impl_all!(SystemctlOp: Finish<Output = SystemctlFinish, Error = SystemctlOpError>);
+sa::assert_impl_all!(SystemctlFinish: Rollback<Output = SystemctlUndoOp>);
+sa::assert_impl_all!(
+    SystemctlUndoOp: Finish<Output = SystemctlUndoFinish, Error = SystemctlOpError>
+);
+sa::assert_impl_all!(SystemctlUndoFinish: Rollback<Output = SystemctlOp>);
+
+/// Error encountered when finishing [`SystemctlOp`] or [`SystemctlUndoOp`].
+#[derive(Debug, thiserror::Error)]
+pub enum SystemctlOpError {
+    #[error("systemctl error")]
+    Systemctl(#[from] SystemctlError),
+}
+
+/// A `systemctl --user` command.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SystemctlCommand {
+    /// Reload unit files.
+    DaemonReload,
+    /// Enable the unit.
+    Enable(String),
+    /// Disable the unit.
+    Disable(String),
+    /// Restart the unit.
+    Restart(String),
+}
+
+impl SystemctlCommand {
+    /// Return the arguments passed to `systemctl`.
+    #[inline]
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec!["--user".to_string()];
+        match self {
+            Self::DaemonReload => args.push("daemon-reload".to_string()),
+            Self::Enable(unit) => args.extend(["enable".to_string(), unit.clone()]),
+            Self::Disable(unit) => args.extend(["disable".to_string(), unit.clone()]),
+            Self::Restart(unit) => args.extend(["restart".to_string(), unit.clone()]),
+        }
+        args
+    }
+
+    /// Return the command that reverses this one. Reloading and restarting are their own
+    /// inverses, since they only pick up the current state of the unit files.
+    #[inline]
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::DaemonReload => Self::DaemonReload,
+            Self::Enable(unit) => Self::Disable(unit.clone()),
+            Self::Disable(unit) => Self::Enable(unit.clone()),
+            Self::Restart(unit) => Self::Restart(unit.clone()),
+        }
+    }
+
+    #[inline]
+    fn run(&self) -> Result<(), SystemctlError> {
+        let args = self.args();
+        let output = Command::new("systemctl")
+            .args(&args)
+            .stdin(Stdio::null())
+            .output()
+            .and_then(|output| {
+                if output.status.success() {
+                    Ok(output)
+                } else {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    Err(io::Error::other(stderr.trim().to_string()))
+                }
+            });
+
+        output
+            .map(|_| ())
+            .map_err(|inner| SystemctlError { args, inner })
+    }
+}
+
+/// Return true if the user unit `unit` is enabled. Failing to run `systemctl` counts as not.
+#[inline]
+pub fn is_enabled(unit: &str) -> bool {
+    Command::new("systemctl")
+        .args(["--user", "--quiet", "is-enabled", unit])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Operation to run a `systemctl --user` command.
+///
+/// # Errors
+///
+/// The operation will error if `systemctl` cannot be spawned or exits with a non-zero status.
+/// Enabling a unit that is already enabled does nothing.
+///
+/// # Undo
+///
+/// Undoing will run the inverse command (see [`SystemctlCommand::inverse`]), unless the operation
+/// did nothing. This set of
+/// operations functions in the following cycle:
+///
+/// [`SystemctlOp`] --> [`SystemctlFinish`] --> [`SystemctlUndoOp`] --> [`SystemctlUndoFinish`] -->
+/// [`SystemctlOp`] --> ...
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SystemctlOp {
+    /// Command to run.
+    pub command: SystemctlCommand,
+}
+
+/// The output of [`SystemctlOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SystemctlFinish {
+    /// See [`SystemctlOp`].
+    pub command: SystemctlCommand,
+    /// The command had nothing to do, e.g. the unit was already enabled.
+    #[serde(default)]
+    pub noop: bool,
+}
+
+impl Finish for SystemctlOp {
+    type Output = SystemctlFinish;
+    type Error = SystemctlOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self { command } = self;
+
+        let noop = matches!(command, SystemctlCommand::Enable(unit) if is_enabled(unit));
+        if !noop {
+            command.run()?;
+        }
+
+        Ok(Self::Output {
+            command: command.clone(),
+            noop,
+        })
+    }
+}
+
+impl Rollback for SystemctlFinish {
+    type Output = SystemctlUndoOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self { command, noop } = self;
+
+        Self::Output {
+            command: command.clone(),
+            noop: *noop,
+        }
+    }
+}
+
+/// The undo of [`SystemctlOp`] (see its documentation), created by rolling back
+/// [`SystemctlFinish`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SystemctlUndoOp {
+    /// See [`SystemctlOp`]. The inverse of this command is run.
+    pub command: SystemctlCommand,
+    /// See [`SystemctlFinish`]. If set, nothing is run.
+    #[serde(default)]
+    pub noop: bool,
+}
+
+/// The output of [`SystemctlUndoOp`]. See its documentation for information.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SystemctlUndoFinish {
+    /// See [`SystemctlOp`].
+    pub command: SystemctlCommand,
+}
+
+impl Finish for SystemctlUndoOp {
+    type Output = SystemctlUndoFinish;
+    type Error = SystemctlOpError;
+
+    #[inline]
+    fn finish(&self, _ctx: &FinishCtx) -> Result<Self::Output, Self::Error> {
+        let Self { command, noop } = self;
+
+        if !noop {
+            command.inverse().run()?;
+        }
+
+        Ok(Self::Output {
+            command: command.clone(),
+        })
+    }
+}
+
+impl Rollback for SystemctlUndoFinish {
+    type Output = SystemctlOp;
+
+    #[inline]
+    fn rollback(&self) -> Self::Output {
+        let Self { command } = self;
+
+        Self::Output {
+            command: command.clone(),
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use std::fs::File;
-use std::io::{Read, Write};
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
@@ -172,10 +172,14 @@ where
     E: From<OpenError> + From<ReadError> + From<WriteError>,
 {
     // Open file.
-    let mut file = File::open(&path).map_err(|inner| OpenError {
-        path: path.as_ref().to_path_buf(),
-        inner,
-    })?;
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .map_err(|inner| OpenError {
+            path: path.as_ref().to_path_buf(),
+            inner,
+        })?;
 
     // Save overwritten contents.
     file.read_to_end(overwritten).map_err(|inner| ReadError {
@@ -183,11 +187,14 @@ where
         inner,
     })?;
 
-    // Ovewrite contents.
-    file.write_all(contents).map_err(|inner| WriteError {
-        path: path.as_ref().to_path_buf(),
-        inner,
-    })?;
+    // Ovewrite contents, truncating anything past the new end.
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.set_len(0))
+        .and_then(|_| file.write_all(contents))
+        .map_err(|inner| WriteError {
+            path: path.as_ref().to_path_buf(),
+            inner,
+        })?;
 
     Ok(())
 }
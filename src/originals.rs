@@ -0,0 +1,217 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum OriginalsError {
+    #[error("i/o error")]
+    Io(#[from] io::Error),
+    #[error("serde error")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// On-disk store of the files that were at destinations before shelf first took them over, kept
+/// so that they can be restored at any time. Unlike the backups of a run, originals are never
+/// replaced or removed.
+#[derive(Debug, Clone)]
+pub struct OriginalStore {
+    path: PathBuf,
+}
+
+/// What was at a destination before shelf first took it over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Original {
+    /// Time at which shelf took over the destination.
+    pub taken_at: SystemTime,
+    /// Path of the kept copy of the original file, or `None` if there was nothing at the
+    /// destination.
+    pub path: Option<PathBuf>,
+}
+
+impl OriginalStore {
+    #[inline]
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Retrieve the original of `dest`, returning `None` if shelf has never taken it over.
+    #[inline]
+    pub fn get<P>(&self, dest: P) -> Result<Option<Original>, OriginalsError>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = self.entry_dir(dest);
+        let file = match File::open(dir.join(ENTRY)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let entry: Entry = serde_json::from_reader(BufReader::new(file))?;
+        Ok(Some(Original {
+            taken_at: entry.taken_at,
+            path: if entry.kept {
+                Some(dir.join(ORIGINAL))
+            } else {
+                None
+            },
+        }))
+    }
+
+    /// Keep a copy of what is at `dest` as its original, since shelf is about to take it over.
+    /// If shelf has taken over `dest` before, nothing is done, so that only the very first
+    /// original is ever kept. Returns true if the original was recorded now.
+    #[inline]
+    pub fn keep<P>(&self, dest: P) -> Result<bool, OriginalsError>
+    where
+        P: AsRef<Path>,
+    {
+        let dest = dest.as_ref();
+        let dir = self.entry_dir(dest);
+        if dir.join(ENTRY).exists() {
+            return Ok(false);
+        }
+
+        // Start over if a previous attempt was interrupted.
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+
+        let kept = match fs::symlink_metadata(dest) {
+            Ok(metadata) => {
+                copy(dest, &dir.join(ORIGINAL), &metadata)?;
+                true
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        // The entry is written last, so that it only exists for complete copies.
+        let entry = Entry {
+            dest: dest.to_path_buf(),
+            taken_at: SystemTime::now(),
+            kept,
+        };
+        let file = File::create(dir.join(ENTRY))?;
+        serde_json::to_writer(BufWriter::new(file), &entry)?;
+
+        Ok(true)
+    }
+
+    #[inline]
+    fn entry_dir<P>(&self, dest: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let mut hasher = DefaultHasher::new();
+        dest.as_ref().hash(&mut hasher);
+        self.path.join(format!("{:016x}", hasher.finish()))
+    }
+}
+
+/// Name of the entry metadata in an entry directory.
+const ENTRY: &str = "entry.json";
+/// Name of the kept original in an entry directory.
+const ORIGINAL: &str = "original";
+
+/// Stored entry; the destination is kept alongside for inspection.
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry {
+    dest: PathBuf,
+    taken_at: SystemTime,
+    kept: bool,
+}
+
+/// Copy the file, directory, or symlink at `src` to `dest`, without following symlinks.
+#[inline]
+fn copy(src: &Path, dest: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    let ft = metadata.file_type();
+    if ft.is_symlink() {
+        let target = fs::read_link(src)?;
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&target, dest)
+        }
+
+        #[cfg(windows)]
+        {
+            use std::os::windows;
+
+            if src.is_dir() {
+                windows::fs::symlink_dir(&target, dest)
+            } else {
+                windows::fs::symlink_file(&target, dest)
+            }
+        }
+    } else if ft.is_dir() {
+        fs::create_dir(dest)?;
+        for dirent in fs::read_dir(src)? {
+            let dirent = dirent?;
+            copy(
+                &dirent.path(),
+                &dest.join(dirent.file_name()),
+                &dirent.metadata()?,
+            )?;
+        }
+        Ok(())
+    } else {
+        fs::copy(src, dest).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::OriginalStore;
+
+    #[test]
+    fn test_keep() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new()?;
+        let store = OriginalStore::new(dir.path().join("originals"));
+
+        let file = dir.path().join("file");
+        fs::write(&file, "original")?;
+        assert!(store.keep(&file)?);
+
+        // Only the first original is kept.
+        fs::write(&file, "shelf")?;
+        assert!(!store.keep(&file)?);
+        let original = store.get(&file)?.unwrap();
+        assert_eq!("original", fs::read_to_string(original.path.unwrap())?);
+
+        // Destinations that shelf created have no original.
+        let created = dir.path().join("created");
+        assert_eq!(None, store.get(&created)?);
+        assert!(store.keep(&created)?);
+        assert_eq!(None, store.get(&created)?.unwrap().path);
+
+        let subdir = dir.path().join("dir");
+        fs::create_dir(&subdir)?;
+        fs::write(subdir.join("a"), "a")?;
+        assert!(store.keep(&subdir)?);
+        let original = store.get(&subdir)?.unwrap().path.unwrap();
+        assert_eq!("a", fs::read_to_string(original.join("a"))?);
+
+        Ok(())
+    }
+}
@@ -1,6 +1,15 @@
+use std::convert::TryFrom;
+
+use glob::Pattern;
 use mlua::{Error as LuaError, FromLua, Value as LuaValue};
 
-use super::{LinkType, NonZeroExitBehavior};
+#[cfg(unix)]
+use crate::op::chown::{group_id, user_id};
+
+use super::{
+    Condition, ConflictPolicy, DefaultsValue, EnvMap, Gid, LinkType, MissingParentPolicy, Mode,
+    NonZeroExitBehavior, OptionType, RegistryValue, Scope, Uid,
+};
 
 impl<'lua> FromLua<'lua> for LinkType {
     #[inline]
@@ -9,13 +18,92 @@ impl<'lua> FromLua<'lua> for LinkType {
             LuaValue::String(s) => match s.to_str()? {
                 "link" => Ok(Self::Link),
                 "copy" => Ok(Self::Copy),
+                "hardlink" => Ok(Self::Hardlink),
+                "auto" => Ok(Self::Auto),
                 _ => conv_err(
                     LuaValue::String(s),
                     "LinkType",
-                    r#"string ("link" or "copy")"#,
+                    r#"string ("link", "copy", "hardlink", or "auto")"#,
+                ),
+            },
+            _ => conv_err(
+                lua_value,
+                "LinkType",
+                r#"string ("link", "copy", "hardlink", or "auto")"#,
+            ),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for ConflictPolicy {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        match lua_value {
+            LuaValue::String(s) => match s.to_str()? {
+                "replace" => Ok(Self::Replace),
+                "skip" => Ok(Self::Skip),
+                "backup" => Ok(Self::Backup),
+                "fail" => Ok(Self::Fail),
+                _ => conv_err(
+                    LuaValue::String(s),
+                    "ConflictPolicy",
+                    r#"string ("replace", "skip", "backup", or "fail")"#,
                 ),
             },
-            _ => conv_err(lua_value, "LinkType", r#"string ("link" or "copy")"#),
+            _ => conv_err(
+                lua_value,
+                "ConflictPolicy",
+                r#"string ("replace", "skip", "backup", or "fail")"#,
+            ),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for OptionType {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        match lua_value {
+            LuaValue::String(s) => match s.to_str()? {
+                "string" => Ok(Self::String),
+                "number" => Ok(Self::Number),
+                "integer" => Ok(Self::Integer),
+                "boolean" => Ok(Self::Boolean),
+                "array" => Ok(Self::Array),
+                "object" => Ok(Self::Object),
+                _ => conv_err(
+                    LuaValue::String(s),
+                    "OptionType",
+                    r#"string ("string", "number", "integer", "boolean", "array", or "object")"#,
+                ),
+            },
+            _ => conv_err(
+                lua_value,
+                "OptionType",
+                r#"string ("string", "number", "integer", "boolean", "array", or "object")"#,
+            ),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for MissingParentPolicy {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        match lua_value {
+            LuaValue::String(s) => match s.to_str()? {
+                "create_parent" => Ok(Self::CreateParent),
+                "skip" => Ok(Self::Skip),
+                "error" => Ok(Self::Error),
+                _ => conv_err(
+                    LuaValue::String(s),
+                    "MissingParentPolicy",
+                    r#"string ("create_parent", "skip", or "error")"#,
+                ),
+            },
+            _ => conv_err(
+                lua_value,
+                "MissingParentPolicy",
+                r#"string ("create_parent", "skip", or "error")"#,
+            ),
         }
     }
 }
@@ -43,6 +131,183 @@ impl<'lua> FromLua<'lua> for NonZeroExitBehavior {
     }
 }
 
+impl<'lua> FromLua<'lua> for Scope {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        match lua_value {
+            LuaValue::String(s) => match s.to_str()? {
+                "user" => Ok(Self::User),
+                "system" => Ok(Self::System),
+                _ => conv_err(
+                    LuaValue::String(s),
+                    "Scope",
+                    r#"string ("user" or "system")"#,
+                ),
+            },
+            _ => conv_err(lua_value, "Scope", r#"string ("user" or "system")"#),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for DefaultsValue {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        match lua_value {
+            LuaValue::Boolean(b) => Ok(Self::Bool(b)),
+            LuaValue::Integer(i) => Ok(Self::Int(i)),
+            LuaValue::Number(n) => Ok(Self::Float(n)),
+            LuaValue::String(s) => Ok(Self::Str(s.to_str()?.to_string())),
+            _ => conv_err(lua_value, "DefaultsValue", "boolean, number, or string"),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for RegistryValue {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        let dword = |n: i64| u32::try_from(n).map(Self::Dword).ok();
+        let qword = |n: i64| u64::try_from(n).map(Self::Qword).ok();
+        let value = match lua_value {
+            LuaValue::Boolean(b) => Some(Self::Dword(b.into())),
+            LuaValue::Integer(n) => dword(n).or_else(|| qword(n)),
+            LuaValue::String(ref s) => Some(Self::Sz(s.to_str()?.to_string())),
+            _ => None,
+        };
+
+        match value {
+            Some(value) => Ok(value),
+            None => conv_err(
+                lua_value,
+                "RegistryValue",
+                "boolean, non-negative integer, or string",
+            ),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for Mode {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        // Numbers aren't accepted, since Lua reads `0644` as decimal.
+        let mode = match lua_value {
+            LuaValue::String(ref s) => {
+                let s = s.to_str()?;
+                let digits = s.strip_prefix("0o").unwrap_or(s);
+                u32::from_str_radix(digits, 8)
+                    .ok()
+                    .filter(|mode| *mode <= 0o7777)
+            }
+            _ => None,
+        };
+
+        match mode {
+            Some(mode) => Ok(Self(mode)),
+            None => conv_err(
+                lua_value,
+                "Mode",
+                r#"string of octal digits (e.g. "0644") no greater than "7777""#,
+            ),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for Uid {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        owner_id(lua_value, "Uid", "user", user_id).map(Self)
+    }
+}
+
+impl<'lua> FromLua<'lua> for Gid {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        owner_id(lua_value, "Gid", "group", group_id).map(Self)
+    }
+}
+
+impl<'lua> FromLua<'lua> for Condition {
+    #[inline]
+    fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua mlua::Lua) -> mlua::Result<Self> {
+        let table = match lua_value {
+            LuaValue::Table(table) => table,
+            _ => return conv_err(lua_value, "Condition", "table of os, hostname, and env"),
+        };
+
+        // Reject unknown keys, to catch typos like `host`.
+        for pair in table.clone().pairs::<LuaValue, LuaValue>() {
+            let (key, _) = pair?;
+            let name = match &key {
+                LuaValue::String(s) => s.to_str()?.to_string(),
+                key => key.type_name().to_string(),
+            };
+            if !matches!(name.as_str(), "os" | "hostname" | "env") {
+                return Err(LuaError::RuntimeError(format!(
+                    "unknown condition '{}'",
+                    name
+                )));
+            }
+        }
+
+        let condition = Self {
+            os: table.get("os")?,
+            hostname: table.get("hostname")?,
+            env: table.get::<_, Option<EnvMap>>("env")?.unwrap_or_default(),
+        };
+
+        // Check patterns now, rather than failing to match when applying.
+        for pattern in condition.hostname.iter().chain(condition.env.values()) {
+            if let Err(err) = Pattern::new(pattern) {
+                return Err(LuaError::RuntimeError(format!(
+                    "invalid pattern '{}': {}",
+                    pattern, err
+                )));
+            }
+        }
+
+        Ok(condition)
+    }
+}
+
+/// Convert an owner given as an id, or as a name looked up by `lookup`.
+#[inline]
+fn owner_id(
+    lua_value: LuaValue<'_>,
+    to: &'static str,
+    kind: &str,
+    lookup: fn(&str) -> Option<u32>,
+) -> mlua::Result<u32> {
+    let id = match &lua_value {
+        LuaValue::Integer(id) => u32::try_from(*id).ok(),
+        LuaValue::String(s) => {
+            let s = s.to_str()?;
+            s.parse().ok().or_else(|| lookup(s))
+        }
+        _ => None,
+    };
+
+    match id {
+        Some(id) => Ok(id),
+        None => conv_err(
+            lua_value,
+            to,
+            &format!("{} id, or the name of an existing {}", kind, kind),
+        ),
+    }
+}
+
+/// Names can't be resolved on Windows, where owners are ignored.
+#[cfg(windows)]
+#[inline]
+fn user_id(_name: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(windows)]
+#[inline]
+fn group_id(_name: &str) -> Option<u32> {
+    None
+}
+
 fn conv_err<R>(value: LuaValue<'_>, to: &'static str, should: &str) -> mlua::Result<R> {
     Err(LuaError::FromLuaConversionError {
         from: value.type_name(),
@@ -1,47 +1,303 @@
 mod lua;
 
+use std::collections::BTreeMap;
+use std::env;
 use std::path::PathBuf;
 
+use glob::Pattern;
 use serde::{Deserialize, Serialize};
 
 pub use crate::action::{
+    conflict::ConflictPolicy,
+    expect::Expectation,
+    missing::MissingParentPolicy,
     object::{Object, Value as ObjectValue},
+    registry::RegistryValue,
     template::hbs::HandlebarsPartials,
     tree::Patterns,
 };
 pub use crate::op::command::EnvMap;
+pub use crate::op::defaults::DefaultsValue;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Spec {
     pub name: String,
     pub deps: Vec<Dep>,
+    /// Minimum version of shelf required to apply the package.
+    #[serde(default)]
+    pub requires: Option<String>,
+    /// Package layered beneath this one, whose directives come first. See [`Base`].
+    #[serde(default)]
+    pub base: Option<Base>,
+    /// Environment variables set for every hook of the package. Variables set by a hook take
+    /// precedence.
+    #[serde(default)]
+    pub env: EnvMap,
+    /// Whether the package configures the user's account or the whole system.
+    #[serde(default)]
+    pub scope: Scope,
     /// List of file link directives; order matters.
     pub directives: Vec<Directive>,
+    /// Conditions of the directives, by index, all of which must hold for a directive to be
+    /// applied. Directives past the end have none.
+    #[serde(default)]
+    pub conditions: Vec<Vec<Condition>>,
+    /// What to do about existing destinations of the directives, by index. Directives with
+    /// `None`, or past the end, use the policy given when applying.
+    #[serde(default)]
+    pub on_conflict: Vec<Option<ConflictPolicy>>,
+    /// What to do about missing parents of the destinations of the directives, by index.
+    /// Directives with `None`, or past the end, create them.
+    #[serde(default)]
+    pub if_missing: Vec<Option<MissingParentPolicy>>,
+    /// Profiles of the directives, by index, all of which must be selected for a directive to be
+    /// applied. Directives with none, or past the end, are always applied.
+    #[serde(default)]
+    pub profiles: Vec<Vec<String>>,
+    /// Handlebars helpers registered by the package, by name, mapped to the names under which
+    /// their functions are stored in the Lua registry. They are available to all of its
+    /// Handlebars templates.
+    #[serde(default)]
+    pub hbs_helpers: BTreeMap<String, String>,
+    /// Options declared by the package, by name. Their values are given to every template of the
+    /// package as variables. See [`PackageOption`].
+    #[serde(default)]
+    pub options: BTreeMap<String, PackageOption>,
 }
 
-#[derive(Debug, Clone)]
+/// Key under which shelf-provided variables are added to template variables.
+static SHELF_VARS_KEY: &str = "shelf";
+
+impl Spec {
+    /// Return the conditions of the directive at `index`.
+    #[inline]
+    pub fn directive_conditions(&self, index: usize) -> &[Condition] {
+        self.conditions.get(index).map_or(&[], Vec::as_slice)
+    }
+
+    /// Return the conflict policy of the directive at `index`, if it specifies one.
+    #[inline]
+    pub fn directive_on_conflict(&self, index: usize) -> Option<ConflictPolicy> {
+        self.on_conflict.get(index).copied().flatten()
+    }
+
+    /// Return the missing parent policy of the directive at `index`, if it specifies one.
+    #[inline]
+    pub fn directive_if_missing(&self, index: usize) -> Option<MissingParentPolicy> {
+        self.if_missing.get(index).copied().flatten()
+    }
+
+    /// Return the profiles of the directive at `index`.
+    #[inline]
+    pub fn directive_profiles(&self, index: usize) -> &[String] {
+        self.profiles.get(index).map_or(&[], Vec::as_slice)
+    }
+
+    /// Return the variables of all template directives, merged in order. The reserved `shelf`
+    /// key is left out.
+    #[inline]
+    pub fn template_vars(&self) -> Object {
+        let mut vars = Object::new();
+        for drct in &self.directives {
+            if let Directive::File(File::Templated(tf)) = drct {
+                crate::load::base::merge_object(&mut vars.0, &tf.vars.0);
+            }
+        }
+
+        vars.0.shift_remove(SHELF_VARS_KEY);
+        vars
+    }
+
+    /// Expose `previous`, the template variables of the last successful application (see
+    /// [`Spec::template_vars`]), to every template as `shelf.previous_vars`. Templates that
+    /// define their own `shelf` variable are left alone.
+    #[inline]
+    pub fn set_previous_vars(&mut self, previous: &Object) {
+        let mut shelf = Object::new();
+        shelf.0.insert(
+            "previous_vars".to_string(),
+            ObjectValue::Object(previous.0.clone()),
+        );
+
+        for drct in &mut self.directives {
+            if let Directive::File(File::Templated(tf)) = drct {
+                tf.vars
+                    .0
+                    .entry(SHELF_VARS_KEY.to_string())
+                    .or_insert_with(|| ObjectValue::Object(shelf.0.clone()));
+            }
+        }
+    }
+
+    /// Merge `overrides` into the variables of every template directive, taking precedence over
+    /// those of the spec. Objects are merged recursively, so `{ a = { b = 1 } }` only replaces
+    /// `a.b`.
+    #[inline]
+    pub fn override_vars(&mut self, overrides: &Object) {
+        for drct in &mut self.directives {
+            if let Directive::File(File::Templated(tf)) = drct {
+                crate::load::base::merge_object(&mut tf.vars.0, &overrides.0);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum Directive {
     File(File),
     Hook(Hook),
 }
 
-#[derive(Debug, Clone)]
+/// Requirements of the machine on which a directive is applied, given by `pkg:only_if` or the
+/// `when` argument of a directive. Fields that aren't given always hold.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Condition {
+    /// Operating system, e.g. `linux` or `macos`, or family, i.e. `unix` or `windows`.
+    pub os: Option<String>,
+    /// Glob pattern of the host name.
+    pub hostname: Option<String>,
+    /// Glob patterns of the values of environment variables. Unset variables never match.
+    pub env: EnvMap,
+}
+
+impl Condition {
+    /// Return whether the condition holds on this machine, whose host name is `host`.
+    #[inline]
+    pub fn holds(&self, host: Option<&str>) -> bool {
+        let matches =
+            |pattern: &str, value: &str| Pattern::new(pattern).is_ok_and(|p| p.matches(value));
+
+        let os = self
+            .os
+            .as_ref()
+            .is_none_or(|os| os == env::consts::OS || os == env::consts::FAMILY);
+        let hostname = self
+            .hostname
+            .as_ref()
+            .is_none_or(|pattern| host.is_some_and(|host| matches(pattern, host)));
+        let vars = self
+            .env
+            .iter()
+            .all(|(var, pattern)| env::var(var).is_ok_and(|value| matches(pattern, &value)));
+
+        os && hostname && vars
+    }
+}
+
+/// What a package configures, which determines where its destinations are rooted and whether
+/// applying it requires elevated privileges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// The user's account; destinations are relative to the home directory.
+    User,
+    /// The whole system; destinations are relative to the system root.
+    System,
+}
+
+impl Default for Scope {
+    #[inline]
+    fn default() -> Self {
+        Self::User
+    }
+}
+
+impl Scope {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::System => "system",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Dep {
     pub path: PathBuf,
 }
 
-#[derive(Debug, Clone)]
+/// Option of a package, which can be set when applying it (e.g. with `--set`).
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PackageOption {
+    /// Value of the option if it isn't set, if any.
+    pub default: Option<ObjectValue>,
+    /// Type that values of the option must have, if any.
+    pub typ: Option<OptionType>,
+}
+
+/// Type of the values of a [`PackageOption`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionType {
+    String,
+    /// Integer or float.
+    Number,
+    Integer,
+    Boolean,
+    Array,
+    Object,
+}
+
+impl OptionType {
+    /// Return true if `value` is of this type.
+    #[inline]
+    pub fn matches(self, value: &ObjectValue) -> bool {
+        matches!(
+            (self, value),
+            (Self::String, ObjectValue::Str(_))
+                | (Self::Number, ObjectValue::Int(_) | ObjectValue::Float(_))
+                | (Self::Integer, ObjectValue::Int(_))
+                | (Self::Boolean, ObjectValue::Bool(_))
+                | (Self::Array, ObjectValue::Array(_))
+                | (Self::Object, ObjectValue::Object(_))
+        )
+    }
+
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Number => "number",
+            Self::Integer => "integer",
+            Self::Boolean => "boolean",
+            Self::Array => "array",
+            Self::Object => "object",
+        }
+    }
+}
+
+/// A shared package, fetched from a remote or local source, that a package is layered over.
+/// Local directives replace base directives with the same destination.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Base {
+    /// Git repository, tarball (URL or path), or local directory of the base package.
+    pub source: String,
+    /// Git branch or tag to fetch.
+    pub rev: Option<String>,
+    /// Template variables overriding those of the base templates.
+    pub vars: Object,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum File {
     Regular(RegularFile),
     Tree(TreeFile),
+    CopyDir(CopyDirFile),
     Templated(TemplatedFile),
+    Piped(PipedFile),
     Generated(GeneratedFile),
     Dir(DirFile),
+    SystemdUnit(SystemdUnitFile),
+    Fragment(FragmentFile),
+    SourceLine(SourceLineFile),
+    Defaults(DefaultsFile),
+    RegValue(RegValueFile),
+    Plugin(PluginFile),
+    Expect(ExpectFile),
 }
 
-// FIXME existing file replacement options
-// FIXME: permission
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RegularFile {
     pub src: PathBuf,
     /// Configuration can optionally specify a destination path relative to HOME.
@@ -51,28 +307,64 @@ pub struct RegularFile {
     /// Files can be symlinked or copied to the destination.
     pub link_type: LinkType,
     pub optional: bool,
+    /// Permission bits of copies. Symlinks and hard links share those of the source.
+    #[serde(default)]
+    pub mode: Option<Mode>,
+    /// Owning user of copies, like `mode`.
+    #[serde(default)]
+    pub owner: Option<Uid>,
+    /// Owning group of copies, like `mode`.
+    #[serde(default)]
+    pub group: Option<Gid>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TreeFile {
     pub src: PathBuf,
+    /// Further source directories merged over `src` in order; files in later ones replace those
+    /// with the same relative path in earlier ones.
+    #[serde(default)]
+    pub layers: Vec<PathBuf>,
     pub dest: Option<PathBuf>,
 
     pub globs: Option<Patterns>,
     pub ignore: Option<Patterns>,
+    /// Destination subpaths that applications mutate; exempt from drift checks and never removed.
+    pub volatile: Option<Patterns>,
+    /// Leading path components of files in `src` to drop when mapping them to `dest`. Files not
+    /// under this prefix are skipped.
+    pub strip_prefix: Option<PathBuf>,
 
     pub link_type: LinkType,
     pub optional: bool,
 }
 
-#[derive(Debug, Clone)]
+/// A directory copied recursively as a unit, rather than per-file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CopyDirFile {
+    pub src: PathBuf,
+    /// Destination path relative to HOME. If none is provided, the relative src path will be used.
+    pub dest: Option<PathBuf>,
+
+    /// Destination subpaths that applications mutate; exempt from drift checks and never removed.
+    pub volatile: Option<Patterns>,
+
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum LinkType {
     Link,
     Copy,
+    /// Symlink, falling back to a copy when symlinks can't be created, e.g. on Windows without the
+    /// privilege to create them.
+    Auto,
+    /// Hard link, falling back to a copy when the source and destination are on different
+    /// filesystems.
+    Hardlink,
 }
 
-// FIXME: permissions
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TemplatedFile {
     pub src: PathBuf,
     pub dest: PathBuf,
@@ -82,33 +374,85 @@ pub struct TemplatedFile {
     pub typ: TemplatedFileType,
 
     pub optional: bool,
+    /// Prepend a banner marking the file as managed by shelf, commented according to the
+    /// destination file type.
+    pub auto_header: bool,
+    /// Permission bits of the rendered file.
+    #[serde(default)]
+    pub mode: Option<Mode>,
+    /// Owning user of the rendered file.
+    #[serde(default)]
+    pub owner: Option<Uid>,
+    /// Owning group of the rendered file.
+    #[serde(default)]
+    pub group: Option<Gid>,
 }
 
 // FIXME more template engine options
-// FIXME pipe src content through function to get result
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum TemplatedFileType {
     Handlebars(HandlebarsTemplatedFile),
     Liquid(LiquidTemplatedFile),
+    Gotmpl(GotmplTemplatedFile),
+    /// Engine selected by the extension of the source. See [`Engine::from_path`].
+    ///
+    /// [`Engine::from_path`]: crate::action::template::Engine::from_path
+    Auto(AutoTemplatedFile),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HandlebarsTemplatedFile {
     pub partials: HandlebarsPartials,
 }
 
 // FIXME partials & filters support
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LiquidTemplatedFile {}
 
-// FIXME: permissions
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GotmplTemplatedFile {}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AutoTemplatedFile {}
+
+/// A file whose contents are those of `src` passed through a Lua function, which receives them as
+/// a string and returns the contents to write.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PipedFile {
+    pub src: PathBuf,
+    pub dest: PathBuf,
+
+    /// Name under which the function is stored in the Lua registry.
+    pub name: String,
+
+    pub optional: bool,
+    /// Permission bits of the written file.
+    #[serde(default)]
+    pub mode: Option<Mode>,
+    /// Owning user of the written file.
+    #[serde(default)]
+    pub owner: Option<Uid>,
+    /// Owning group of the written file.
+    #[serde(default)]
+    pub group: Option<Gid>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GeneratedFile {
     pub dest: PathBuf,
     pub typ: GeneratedFileTyp,
+    /// Permission bits of the file.
+    #[serde(default)]
+    pub mode: Option<Mode>,
+    /// Owning user of the file.
+    #[serde(default)]
+    pub owner: Option<Uid>,
+    /// Owning group of the file.
+    #[serde(default)]
+    pub group: Option<Gid>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum GeneratedFileTyp {
     Empty(EmptyGeneratedFile),
     String(StringGeneratedFile),
@@ -117,46 +461,158 @@ pub enum GeneratedFileTyp {
     Json(JsonGeneratedFile),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EmptyGeneratedFile;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct StringGeneratedFile {
     pub contents: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct YamlGeneratedFile {
     pub values: Object,
     pub header: Option<String>,
+    /// Prepend a banner marking the file as managed by shelf.
+    pub auto_header: bool,
+    /// Path to a JSON Schema, relative to the package, that values are validated against.
+    pub schema: Option<PathBuf>,
+    /// Sort the keys of values, so that output is stable regardless of the order in which Lua
+    /// iterates tables. Otherwise, keys are written in that order.
+    pub sort_keys: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TomlGeneratedFile {
     pub values: Object,
     pub header: Option<String>,
+    /// Prepend a banner marking the file as managed by shelf.
+    pub auto_header: bool,
+    /// Path to a JSON Schema, relative to the package, that values are validated against.
+    pub schema: Option<PathBuf>,
+    /// Sort the keys of values, so that output is stable regardless of the order in which Lua
+    /// iterates tables. Otherwise, keys are written in that order.
+    pub sort_keys: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct JsonGeneratedFile {
     pub values: Object,
+    /// Path to a JSON Schema, relative to the package, that values are validated against.
+    pub schema: Option<PathBuf>,
+    /// Sort the keys of values, so that output is stable regardless of the order in which Lua
+    /// iterates tables. Otherwise, keys are written in that order.
+    pub sort_keys: bool,
 }
 
-// TODO: permissions
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DirFile {
     pub dest: PathBuf,
     pub parents: bool,
+    /// Permission bits of the directory.
+    #[serde(default)]
+    pub mode: Option<Mode>,
+    /// Owning user of the directory.
+    #[serde(default)]
+    pub owner: Option<Uid>,
+    /// Owning group of the directory.
+    #[serde(default)]
+    pub group: Option<Gid>,
+}
+
+/// Permission bits of a file or directory, given in Lua as a string of octal digits, e.g.
+/// `'0644'`. Ignored on Windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Mode(pub u32);
+
+/// Id of the user that owns a file or directory, given in Lua as a user name or id. Names are
+/// resolved when loading, which is only supported on Unix; owners are ignored on Windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Uid(pub u32);
+
+/// Id of the group that owns a file or directory, given like [`Uid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Gid(pub u32);
+
+/// A file in a conf.d-style directory, named uniquely to the package. Fragments that the package
+/// previously wrote to the directory but no longer declares are removed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FragmentFile {
+    /// Directory of fragments, relative to the destination.
+    pub dir: PathBuf,
+    /// Name of the fragment, which is prefixed to make it unique.
+    pub name: String,
+    pub contents: String,
+}
+
+/// A line, e.g. `source ~/.aliases`, that must exist exactly once in a shell rc file. It is
+/// inserted into a block managed by the package if missing.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SourceLineFile {
+    /// Path of the rc file, relative to the destination.
+    pub rc: PathBuf,
+    pub line: String,
 }
 
-#[derive(Debug, Clone)]
+/// A macOS user default, set as with `defaults write`. Ignored on other platforms.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DefaultsFile {
+    /// Domain of the default, e.g. `com.apple.dock`.
+    pub domain: String,
+    pub key: String,
+    pub value: DefaultsValue,
+}
+
+/// A Windows registry value, set as with `reg add`. Ignored on other platforms, and unless
+/// built with the `registry` feature.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RegValueFile {
+    /// Path of the key, e.g. `HKCU\Software\Microsoft\Command Processor`.
+    pub key: String,
+    pub name: String,
+    pub value: RegistryValue,
+}
+
+/// A directive of a custom kind, resolved by a WebAssembly module. Only supported when built
+/// with the `wasm` feature.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginFile {
+    /// Path of the module, relative to the package.
+    pub module: PathBuf,
+    pub kind: String,
+    pub args: Object,
+    /// Directory that the sources returned by the module are relative to, relative to the
+    /// package. This is only changed when the directive comes from a base.
+    pub root: PathBuf,
+}
+
+/// An expectation of a destination, checked once the directives before it are applied, that
+/// fails the package if it isn't met. Nothing is changed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExpectFile {
+    pub dest: PathBuf,
+    /// Symlink targets are relative to the package.
+    pub expectation: Expectation,
+}
+
+/// A systemd user unit, linked into `~/.config/systemd/user`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SystemdUnitFile {
+    pub src: PathBuf,
+
+    pub enable: bool,
+    pub restart_on_change: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum Hook {
     Cmd(CmdHook),
     Fun(FunHook),
+    Script(ScriptHook),
 }
 
 // FIXME optional env variables?
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CmdHook {
     pub command: String,
 
@@ -170,6 +626,12 @@ pub struct CmdHook {
     pub env: Option<EnvMap>,
 
     pub nonzero_exit: Option<NonZeroExitBehavior>,
+    /// Only run if a prior op of the package changed the filesystem in this run.
+    #[serde(default)]
+    pub only_if_changed: bool,
+    /// Seconds after which the command is killed and fails.
+    #[serde(default)]
+    pub timeout: Option<u64>,
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
@@ -179,10 +641,30 @@ pub enum NonZeroExitBehavior {
     Ignore,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FunHook {
     pub name: String,
 
     pub start: Option<PathBuf>,
     pub nonzero_exit: Option<NonZeroExitBehavior>,
+    /// See [`CmdHook::only_if_changed`].
+    #[serde(default)]
+    pub only_if_changed: bool,
+}
+
+/// An executable file in the package, run directly rather than through a shell. It is made
+/// executable first if it isn't already.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScriptHook {
+    pub path: PathBuf,
+    pub args: Vec<String>,
+
+    /// Directory in which the script is run; the package root if none is provided.
+    pub start: Option<PathBuf>,
+    /// See [`CmdHook::only_if_changed`].
+    #[serde(default)]
+    pub only_if_changed: bool,
+    /// See [`CmdHook::timeout`].
+    #[serde(default)]
+    pub timeout: Option<u64>,
 }
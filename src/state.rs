@@ -0,0 +1,179 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::spec::Object;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    #[error("i/o error")]
+    Io(#[from] io::Error),
+    #[error("serde error")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// On-disk store of per-package state that persists across runs, keyed by the package path.
+#[derive(Debug, Clone)]
+pub struct StateStore {
+    path: PathBuf,
+}
+
+/// Metadata of the last application of a package.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PackageState {
+    /// Time at which the package was last applied.
+    pub applied_at: SystemTime,
+    /// Version of shelf that applied the package.
+    pub version: String,
+    /// Outcome of the application.
+    pub result: ApplyResult,
+    /// Whether only a subset of the directives was applied.
+    #[serde(default)]
+    pub partial: bool,
+    /// Template variables of the package as of its last successful, complete application. See
+    /// [`Spec::template_vars`](crate::spec::Spec::template_vars).
+    #[serde(default)]
+    pub vars: Option<Object>,
+    /// Message given to the application, describing why it was made.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Outcome of the application of a package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ApplyResult {
+    /// All directives were applied successfully.
+    Success,
+    /// An error was encountered, and the package was only partially applied.
+    Failure,
+}
+
+/// Progress of an apply that failed, from which it can be resumed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Checkpoint {
+    /// Time at which the apply failed.
+    pub failed_at: SystemTime,
+    /// Command-line arguments of the apply, to run it again.
+    pub args: Vec<String>,
+    /// Paths of the packages in the order that they were applied.
+    pub plan: Vec<PathBuf>,
+    /// Path of the package that failed.
+    pub package: PathBuf,
+    /// Number of actions of `package` that were applied successfully before the failure.
+    pub actions: usize,
+    /// Number of ops of the failed action that were applied successfully before the failure.
+    pub ops: usize,
+}
+
+impl StateStore {
+    #[inline]
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Retrieve the state of the package at `package`, returning `None` if it has never been
+    /// applied.
+    #[inline]
+    pub fn get<P>(&self, package: P) -> Result<Option<PackageState>, StateError>
+    where
+        P: AsRef<Path>,
+    {
+        let file = match File::open(self.entry_path(package)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let entry: Entry = serde_json::from_reader(BufReader::new(file))?;
+        Ok(Some(entry.state))
+    }
+
+    /// Store `state` as the state of the package at `package`, replacing any previous state.
+    #[inline]
+    pub fn insert<P>(&self, package: P, state: &PackageState) -> Result<(), StateError>
+    where
+        P: AsRef<Path>,
+    {
+        fs::create_dir_all(&self.path)?;
+
+        let package = package.as_ref();
+        let entry = Entry {
+            package: package.to_path_buf(),
+            state: state.clone(),
+        };
+
+        let file = File::create(self.entry_path(package))?;
+        serde_json::to_writer(BufWriter::new(file), &entry)?;
+
+        Ok(())
+    }
+
+    /// Retrieve the checkpoint of the last failed apply, returning `None` if there is none.
+    #[inline]
+    pub fn checkpoint(&self) -> Result<Option<Checkpoint>, StateError> {
+        let file = match File::open(self.checkpoint_path()) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Some(serde_json::from_reader(BufReader::new(file))?))
+    }
+
+    /// Store `checkpoint`, replacing any previous checkpoint.
+    #[inline]
+    pub fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(), StateError> {
+        fs::create_dir_all(&self.path)?;
+
+        let file = File::create(self.checkpoint_path())?;
+        serde_json::to_writer(BufWriter::new(file), checkpoint)?;
+
+        Ok(())
+    }
+
+    /// Remove the stored checkpoint, if any.
+    #[inline]
+    pub fn clear_checkpoint(&self) -> Result<(), StateError> {
+        match fs::remove_file(self.checkpoint_path()) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    #[inline]
+    fn checkpoint_path(&self) -> PathBuf {
+        self.path.join("checkpoint.json")
+    }
+
+    #[inline]
+    fn entry_path<P>(&self, package: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let mut hasher = DefaultHasher::new();
+        package.as_ref().hash(&mut hasher);
+        self.path.join(format!("{:016x}.json", hasher.finish()))
+    }
+}
+
+/// Stored entry; the package path is kept alongside the state for inspection.
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry {
+    package: PathBuf,
+    state: PackageState,
+}